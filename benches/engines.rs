@@ -0,0 +1,61 @@
+//! Compares the hand-rolled thread engine with the rayon and work-stealing engines.
+//!
+//! ```text
+//! cargo bench --features rayon --bench engines
+//! ```
+//!
+//! Two figures per engine: the hash rate over a fixed window that holds no solution, and
+//! the mean time to solve a set of puzzles. Both use SHA-256 on every available core.
+
+use std::ops::RangeInclusive;
+use std::time::{Duration, Instant};
+
+use parallell_puzzle_generator_and_solver::{
+    parallel_mine_range, parallel_mine_rayon_with, parallel_mine_steal_with, Difficulty, Puzzle,
+    PuzzleError, Solution,
+};
+use sha2::Sha256;
+
+/// Nonces in the window each engine exhausts for the throughput figure.
+const WINDOW: u64 = 1 << 22;
+/// Leading zero bits of the puzzles each engine solves for the latency figure.
+const SOLVE_BITS: u32 = 18;
+/// How many of those puzzles each engine solves.
+const SOLVES: usize = 20;
+
+/// A miner under test: the puzzle, the window and the thread count.
+type Engine = fn(&Puzzle, RangeInclusive<u64>, usize) -> Result<Solution, PuzzleError>;
+
+fn main() {
+    let threads = num_cpus::get();
+    println!("{} threads, SHA-256", threads);
+    let engines: [(&str, Engine); 3] = [
+        ("threads", parallel_mine_range::<Sha256>),
+        ("rayon", parallel_mine_rayon_with::<Sha256>),
+        ("steal", parallel_mine_steal_with::<Sha256>),
+    ];
+    for (name, engine) in engines {
+        // No digest of this sample will have 255 leading zero bits.
+        let unsolvable = Puzzle::new("engine benchmark", Difficulty::LeadingZeroBits(255));
+        let start = Instant::now();
+        let result = engine(&unsolvable, 0..=WINDOW - 1, threads);
+        assert!(matches!(result, Err(PuzzleError::Exhausted)));
+        let rate = WINDOW as f64 / start.elapsed().as_secs_f64();
+
+        let mut total = Duration::ZERO;
+        for i in 0..SOLVES {
+            let data = format!("puzzle {}", i);
+            let puzzle = Puzzle::new(data, Difficulty::LeadingZeroBits(SOLVE_BITS));
+            let start = Instant::now();
+            engine(&puzzle, 0..=u64::MAX, threads).expect("puzzle has a solution");
+            total += start.elapsed();
+        }
+        println!(
+            "{:>8}: {:>6.2} MH/s, {:>7.1} ms per {}-bit solve",
+            name,
+            rate / 1e6,
+            total.as_secs_f64() * 1e3 / SOLVES as f64,
+            SOLVE_BITS
+        );
+    }
+}
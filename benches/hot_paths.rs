@@ -0,0 +1,100 @@
+//! Criterion micro-benchmarks of the engine's hot paths.
+//!
+//! ```text
+//! cargo bench --bench hot_paths
+//! ```
+//!
+//! - `validate`: checking one nonce with each hash backend.
+//! - `midstate`: hashing one nonce of a long message from scratch and from the midstate a
+//!   `PrehashedPuzzle` caches.
+//! - `search`: the engine's batched inner loop on one thread, exhausting a window that
+//!   holds no solution, with each hash backend. SHA-256 runs on the SIMD lanes or SHA-NI
+//!   where the CPU has them, so its figure covers those paths too.
+//!
+//! Criterion keeps the last run's figures under `target/criterion` and reports the change
+//! from them, so a regression shows up as a slower figure than the baseline.
+
+use std::hint::black_box;
+
+use criterion::measurement::WallTime;
+use criterion::{
+    criterion_group, criterion_main, BenchmarkGroup, BenchmarkId, Criterion, Throughput,
+};
+use parallell_puzzle_generator_and_solver::digest::Digest;
+use parallell_puzzle_generator_and_solver::{
+    hash_with, parallel_mine_range, validate, validate_with, Blake3, Difficulty, DoubleSha256,
+    PrehashedPuzzle, Puzzle, PuzzleError,
+};
+use sha2::Sha256;
+use sha3::Keccak256;
+
+/// Nonces in the window each `search` iteration exhausts.
+const WINDOW: u64 = 1 << 16;
+/// Bytes of data in the `midstate` message: 64 SHA-256 blocks.
+const LONG_DATA: usize = 4096;
+
+/// A puzzle no nonce solves: no digest has 255 leading zero bits.
+fn unsolvable(data: impl Into<Vec<u8>>) -> Puzzle {
+    Puzzle::new(data, Difficulty::LeadingZeroBits(255))
+}
+
+fn bench_validate(c: &mut Criterion) {
+    let puzzle = unsolvable("validate benchmark");
+    let mut group = c.benchmark_group("validate");
+    group.throughput(Throughput::Elements(1));
+    group.bench_function("sha256", |b| b.iter(|| validate(black_box(&puzzle), black_box(7))));
+    validate_backend::<DoubleSha256>(&mut group, "sha256d", &puzzle);
+    validate_backend::<Blake3>(&mut group, "blake3", &puzzle);
+    validate_backend::<Keccak256>(&mut group, "keccak256", &puzzle);
+    group.finish();
+}
+
+/// Benchmarks `validate_with::<D>` as `name`.
+fn validate_backend<D: Digest>(
+    group: &mut BenchmarkGroup<'_, WallTime>,
+    name: &str,
+    puzzle: &Puzzle,
+) {
+    group.bench_function(name, |b| b.iter(|| validate_with::<D>(black_box(puzzle), black_box(7))));
+}
+
+fn bench_midstate(c: &mut Criterion) {
+    let puzzle = unsolvable(vec![0x5a; LONG_DATA]);
+    let prehashed = PrehashedPuzzle::<Sha256>::from_puzzle(&puzzle);
+    let mut group = c.benchmark_group("midstate");
+    group.throughput(Throughput::Elements(1));
+    group.bench_function("from scratch", |b| {
+        b.iter(|| hash_with::<Sha256>(black_box(&puzzle), black_box(7)))
+    });
+    group.bench_function("cached", |b| b.iter(|| prehashed.hash(black_box(7))));
+    group.finish();
+}
+
+fn bench_search(c: &mut Criterion) {
+    let puzzle = unsolvable("search benchmark");
+    let mut group = c.benchmark_group("search");
+    group.throughput(Throughput::Elements(WINDOW));
+    group.sample_size(20);
+    search_backend::<Sha256>(&mut group, "sha256", &puzzle);
+    search_backend::<DoubleSha256>(&mut group, "sha256d", &puzzle);
+    search_backend::<Blake3>(&mut group, "blake3", &puzzle);
+    search_backend::<Keccak256>(&mut group, "keccak256", &puzzle);
+    group.finish();
+}
+
+/// Benchmarks a one-thread search of the window with `D` as `name`.
+fn search_backend<D: Digest + Clone + Send + Sync + 'static>(
+    group: &mut BenchmarkGroup<'_, WallTime>,
+    name: &str,
+    puzzle: &Puzzle,
+) {
+    group.bench_function(BenchmarkId::new(name, WINDOW), |b| {
+        b.iter(|| {
+            let result = parallel_mine_range::<D>(puzzle, 0..=WINDOW - 1, 1);
+            assert!(matches!(result, Err(PuzzleError::Exhausted)));
+        })
+    });
+}
+
+criterion_group!(benches, bench_validate, bench_midstate, bench_search);
+criterion_main!(benches);
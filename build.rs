@@ -0,0 +1,58 @@
+//! Generates the gRPC service of the `grpc` feature; see `src/grpc.rs`.
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    #[cfg(feature = "grpc")]
+    grpc::generate();
+}
+
+/// The service of `proto/puzzle.proto`, described here rather than compiled from the file
+/// so that building needs no `protoc`. Its messages are written out in `src/grpc.rs`.
+#[cfg(feature = "grpc")]
+mod grpc {
+    use tonic_build::manual::{Builder, Method, Service};
+
+    /// Writes the service's server and client into `OUT_DIR`.
+    pub fn generate() {
+        let method = |name: &str, route: &str, input: &str, output: &str| {
+            Method::builder()
+                .name(name)
+                .route_name(route)
+                .input_type(format!("super::{}", input))
+                .output_type(format!("super::{}", output))
+                .codec_path("tonic::codec::ProstCodec")
+        };
+        let service = Service::builder()
+            .name("PuzzleDistribution")
+            .package("puzzle.v1")
+            .method(method("register", "Register", "RegisterRequest", "RegisterReply").build())
+            .method(
+                method("get_work", "GetWork", "GetWorkRequest", "Lease").server_streaming().build(),
+            )
+            .method(
+                method("submit_solution", "SubmitSolution", "SubmitSolutionRequest", "PuzzleState")
+                    .build(),
+            )
+            .method(
+                method("submit_share", "SubmitShare", "SubmitShareRequest", "ShareReply").build(),
+            )
+            .method(
+                method(
+                    "report_exhausted",
+                    "ReportExhausted",
+                    "ReportExhaustedRequest",
+                    "PuzzleState",
+                )
+                .build(),
+            )
+            .method(
+                method("report_progress", "ReportProgress", "ProgressReport", "ProgressSummary")
+                    .client_streaming()
+                    .build(),
+            )
+            .method(method("get_worker", "GetWorker", "GetWorkerRequest", "WorkerState").build())
+            .method(method("get_puzzle", "GetPuzzle", "GetPuzzleRequest", "PuzzleState").build())
+            .build();
+        Builder::new().compile(&[service]);
+    }
+}
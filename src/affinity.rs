@@ -0,0 +1,142 @@
+//! Pinning worker threads to CPU cores.
+//!
+//! The operating system is free to move a thread from core to core, and on a busy
+//! machine it does, so the hash rate of a search wobbles with whatever else runs. A
+//! [`CorePinning`] fixes each worker to one core instead: worker `i` runs on the `i`-th
+//! core the process may use, wrapping round if there are more workers than cores. Cores
+//! can be left out, e.g. to keep core 0 free for the operating system, and on machines
+//! with several NUMA nodes the workers can be spread over the nodes and keep their work
+//! on them; see the [`numa`](crate::numa) module. On hybrid CPUs the workers can be kept
+//! to the performance or the efficiency cores, and take chunks sized by the speed of
+//! their core; see the [`cores`](crate::cores) module. Pass it to
+//! [`MinerBuilder::pin_cores`](crate::MinerBuilder::pin_cores).
+
+use core_affinity::CoreId;
+
+use crate::control::{CHUNK_SIZE, PROGRESS_INTERVAL};
+use crate::{cpu_cores, numa_nodes, CoreKind, CpuCore};
+
+/// Which cores a miner's worker threads are pinned to.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CorePinning {
+    /// The cores no worker is pinned to, by number.
+    excluded: Vec<usize>,
+    /// Whether the workers are spread over the NUMA nodes.
+    numa: bool,
+    /// The only kind of core the workers are pinned to, if any.
+    kind: Option<CoreKind>,
+    /// Whether each worker's chunks are sized by the capacity of its core.
+    weighted: bool,
+}
+
+/// Where one worker thread runs.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Placement {
+    pub(crate) core: CoreId,
+    /// The index of its node among those the workers are spread over, with
+    /// [`CorePinning::numa`].
+    pub(crate) node: Option<usize>,
+    /// How many positions it takes from a search's cursor at a time.
+    pub(crate) chunk_size: u64,
+}
+
+impl CorePinning {
+    /// Pins the workers to every core the process may run on.
+    pub fn all() -> Self {
+        CorePinning::default()
+    }
+
+    /// Pins the workers to every core the process may run on except those numbered in
+    /// `cores`.
+    pub fn excluding(cores: impl IntoIterator<Item = usize>) -> Self {
+        CorePinning { excluded: cores.into_iter().collect(), ..CorePinning::default() }
+    }
+
+    /// Spreads the workers over the NUMA nodes, taking a core from each node in turn, and
+    /// has each one search its own node's share of the window first.
+    pub fn numa(mut self) -> Self {
+        self.numa = true;
+        self
+    }
+
+    /// Pins the workers only to cores of `kind`, e.g. to the efficiency cores to mine
+    /// quietly in the background. A CPU that is not hybrid has no efficiency cores.
+    pub fn only(mut self, kind: CoreKind) -> Self {
+        self.kind = Some(kind);
+        self
+    }
+
+    /// Sizes the chunks each worker takes by the capacity of its core, so a chunk takes
+    /// about as long on a performance core as on an efficiency core.
+    ///
+    /// Fast workers already take more chunks than slow ones, so every core is kept busy
+    /// either way; with weighted chunks the fast ones also claim less often, and the
+    /// last chunks of a search finish closer together.
+    pub fn weighted(mut self) -> Self {
+        self.weighted = true;
+        self
+    }
+
+    /// The cores left out.
+    pub fn excluded(&self) -> &[usize] {
+        &self.excluded
+    }
+
+    /// Whether the workers are spread over the NUMA nodes.
+    pub fn is_numa(&self) -> bool {
+        self.numa
+    }
+
+    /// The only kind of core the workers are pinned to, if any.
+    pub fn kind(&self) -> Option<CoreKind> {
+        self.kind
+    }
+
+    /// Whether each worker's chunks are sized by the capacity of its core.
+    pub fn is_weighted(&self) -> bool {
+        self.weighted
+    }
+
+    /// The numbers of the cores the workers are pinned to, in the order workers take
+    /// them. Empty if none are left, or if the platform cannot tell which cores the
+    /// process may use.
+    pub fn cores(&self) -> Vec<usize> {
+        self.placements().into_iter().map(|placement| placement.core.id).collect()
+    }
+
+    /// Where the workers run, in the order they take the cores.
+    pub(crate) fn placements(&self) -> Vec<Placement> {
+        let cores: Vec<CpuCore> = cpu_cores()
+            .into_iter()
+            .filter(|core| !self.excluded.contains(&core.id))
+            .filter(|core| self.kind.is_none_or(|kind| core.kind == kind))
+            .collect();
+        let slowest = cores.iter().map(|core| core.capacity).min().unwrap_or(1).max(1);
+        let placement = &|core: &CpuCore, node| {
+            // Weighted chunks stay a whole number of progress intervals.
+            let chunk_size = if self.weighted {
+                let intervals = CHUNK_SIZE / PROGRESS_INTERVAL * u64::from(core.capacity);
+                (intervals + u64::from(slowest) / 2) / u64::from(slowest) * PROGRESS_INTERVAL
+            } else {
+                CHUNK_SIZE
+            };
+            Placement { core: CoreId { id: core.id }, node, chunk_size }
+        };
+        if !self.numa {
+            return cores.iter().map(|core| placement(core, None)).collect();
+        }
+        let nodes: Vec<Vec<&CpuCore>> = numa_nodes()
+            .into_iter()
+            .map(|node| cores.iter().filter(|core| node.cores.contains(&core.id)).collect())
+            .filter(|cores: &Vec<&CpuCore>| !cores.is_empty())
+            .collect();
+        let rounds = nodes.iter().map(Vec::len).max().unwrap_or(0);
+        (0..rounds)
+            .flat_map(|round| {
+                nodes.iter().enumerate().filter_map(move |(node, cores)| {
+                    Some(placement(cores.get(round)?, Some(node)))
+                })
+            })
+            .collect()
+    }
+}
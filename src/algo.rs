@@ -0,0 +1,591 @@
+//! Hash algorithms that can be selected at runtime.
+//!
+//! The miner itself is generic over [`Digest`](crate::digest::Digest), which is the right
+//! tool when the hash is known at compile time. Command-line users pick the hash with
+//! `--algo`, so this module
+//! provides the [`Algorithm`] enum that dispatches to the matching monomorphised solver,
+//! plus two `Digest` adapters: [`Blake3`] around the `blake3` crate and
+//! [`DoubleSha256`] for Bitcoin-style `SHA256(SHA256(x))` hashing.
+//!
+//! Algorithms do not have to share an output size: difficulty is checked on the raw
+//! digest bytes by [`meets_difficulty`](crate::meets_difficulty), and [`Algorithm::output_len`] reports how
+//! long each digest is.
+
+use std::fmt;
+use std::io::{self, Read};
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use sha2::Sha256;
+use sha3::Keccak256;
+
+use crate::digest::{
+    consts::U32, Digest, FixedOutput, HashMarker, Output, OutputSizeUser, Reset, Update,
+};
+use crate::batch::{parallel_mine_batch_with, BatchResult};
+use crate::checkpoint::{Checkpoint, CheckpointError};
+use crate::control::{
+    parallel_mine_cancellable_with, parallel_mine_resume_with, parallel_mine_spawn_with,
+    parallel_mine_timeout_with, resume_observed, spawn_observed, CancellationToken, MiningHandle,
+};
+use crate::estimate::{calibrate_with, measure_hash_rate_with, Calibration};
+use crate::generate::{
+    generate_puzzle_with, generate_puzzles_with, generate_series_with, GeneratedPuzzle,
+};
+use crate::observer::Observer;
+use crate::pool::WorkerPool;
+use crate::prehash::{parallel_mine_prehashed, PrehashedPuzzle};
+use crate::solutions::{find_solutions_with, Solutions};
+use crate::verify::{verify_with, Verification};
+use crate::{
+    hash_with, parallel_mine_matching, parallel_mine_range, parallel_mine_with, validate_with,
+    NonceEncoding, Puzzle, PuzzleError, SearchOrder, Solution, Target,
+};
+
+/// A [`Digest`](crate::digest::Digest) implementation backed by BLAKE3 with the standard
+/// 32-byte output.
+///
+/// The `blake3` crate does not implement the pre-release `digest` traits used by this
+/// crate, so this thin wrapper bridges the two and lets BLAKE3 run through the exact
+/// same parallel harness as SHA-256.
+#[derive(Clone, Default)]
+pub struct Blake3 {
+    inner: blake3::Hasher,
+}
+
+impl HashMarker for Blake3 {}
+
+impl OutputSizeUser for Blake3 {
+    type OutputSize = U32;
+}
+
+impl Update for Blake3 {
+    fn update(&mut self, data: &[u8]) {
+        self.inner.update(data);
+    }
+}
+
+impl FixedOutput for Blake3 {
+    fn finalize_into(self, out: &mut Output<Self>) {
+        out.copy_from_slice(self.inner.finalize().as_bytes());
+    }
+}
+
+impl Reset for Blake3 {
+    fn reset(&mut self) {
+        self.inner.reset();
+    }
+}
+
+/// A [`Digest`](crate::digest::Digest) implementation computing `SHA256(SHA256(x))`.
+///
+/// This is the hash Bitcoin applies to block headers. Input is streamed into a single
+/// SHA-256 instance and the 32-byte result is hashed a second time on finalisation.
+///
+/// Note that Bitcoin displays and compares this digest as a little-endian number; the
+/// solver compares raw digest bytes in order, like every other algorithm here.
+#[derive(Clone, Default)]
+pub struct DoubleSha256 {
+    inner: Sha256,
+}
+
+impl HashMarker for DoubleSha256 {}
+
+impl OutputSizeUser for DoubleSha256 {
+    type OutputSize = U32;
+}
+
+impl Update for DoubleSha256 {
+    fn update(&mut self, data: &[u8]) {
+        Update::update(&mut self.inner, data);
+    }
+}
+
+impl FixedOutput for DoubleSha256 {
+    fn finalize_into(self, out: &mut Output<Self>) {
+        let first = self.inner.finalize_fixed();
+        let mut second = Sha256::default();
+        Update::update(&mut second, &first);
+        FixedOutput::finalize_into(second, out);
+    }
+}
+
+impl Reset for DoubleSha256 {
+    fn reset(&mut self) {
+        Reset::reset(&mut self.inner);
+    }
+}
+
+/// The hash algorithms the solver can use, selectable at runtime.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Algorithm {
+    /// SHA-256, the original and default puzzle hash.
+    #[default]
+    Sha256,
+    /// BLAKE3 with a 32-byte output.
+    Blake3,
+    /// The original Keccak-256 (pre-standard SHA-3 padding), as used by Ethereum.
+    Keccak256,
+    /// Bitcoin-style double SHA-256, `SHA256(SHA256(data || nonce))`.
+    DoubleSha256,
+}
+
+impl Algorithm {
+    /// Every supported algorithm, in the order they are listed in help output.
+    pub const ALL: [Algorithm; 4] = [
+        Algorithm::Sha256,
+        Algorithm::Blake3,
+        Algorithm::Keccak256,
+        Algorithm::DoubleSha256,
+    ];
+
+    /// The canonical lower-case name of the algorithm, as accepted by [`FromStr`].
+    pub fn name(self) -> &'static str {
+        match self {
+            Algorithm::Sha256 => "sha256",
+            Algorithm::Blake3 => "blake3",
+            Algorithm::Keccak256 => "keccak256",
+            Algorithm::DoubleSha256 => "sha256d",
+        }
+    }
+
+    /// The length in bytes of the digest produced by this algorithm.
+    pub fn output_len(self) -> usize {
+        match self {
+            Algorithm::Sha256 => <Sha256 as OutputSizeUser>::output_size(),
+            Algorithm::Blake3 => <Blake3 as OutputSizeUser>::output_size(),
+            Algorithm::Keccak256 => <Keccak256 as OutputSizeUser>::output_size(),
+            Algorithm::DoubleSha256 => <DoubleSha256 as OutputSizeUser>::output_size(),
+        }
+    }
+
+    /// Computes the puzzle hash of `puzzle.data || nonce` with this algorithm.
+    ///
+    /// The returned vector is [`output_len`](Algorithm::output_len) bytes long.
+    pub fn hash(self, puzzle: &Puzzle, nonce: u64) -> Vec<u8> {
+        match self {
+            Algorithm::Sha256 => hash_with::<Sha256>(puzzle, nonce).to_vec(),
+            Algorithm::Blake3 => hash_with::<Blake3>(puzzle, nonce).to_vec(),
+            Algorithm::Keccak256 => hash_with::<Keccak256>(puzzle, nonce).to_vec(),
+            Algorithm::DoubleSha256 => hash_with::<DoubleSha256>(puzzle, nonce).to_vec(),
+        }
+    }
+
+    /// Validates `nonce` against `puzzle` using this algorithm.
+    ///
+    /// See [`validate_with`] for the validity rule.
+    pub fn validate(self, puzzle: &Puzzle, nonce: u64) -> bool {
+        match self {
+            Algorithm::Sha256 => validate_with::<Sha256>(puzzle, nonce),
+            Algorithm::Blake3 => validate_with::<Blake3>(puzzle, nonce),
+            Algorithm::Keccak256 => validate_with::<Keccak256>(puzzle, nonce),
+            Algorithm::DoubleSha256 => validate_with::<DoubleSha256>(puzzle, nonce),
+        }
+    }
+
+    /// Re-hashes `nonce` against `puzzle` using this algorithm and reports the hash along
+    /// with whether it is valid.
+    ///
+    /// See [`verify_with`] for details.
+    pub fn verify(self, puzzle: &Puzzle, nonce: u64) -> Verification {
+        match self {
+            Algorithm::Sha256 => verify_with::<Sha256>(puzzle, nonce),
+            Algorithm::Blake3 => verify_with::<Blake3>(puzzle, nonce),
+            Algorithm::Keccak256 => verify_with::<Keccak256>(puzzle, nonce),
+            Algorithm::DoubleSha256 => verify_with::<DoubleSha256>(puzzle, nonce),
+        }
+    }
+
+    /// Streams the payload from `reader` into this algorithm's prefix state, then checks
+    /// `nonce` appended with `nonce_encoding` against `target`.
+    ///
+    /// # Returns
+    ///
+    /// An I/O error if reading fails; otherwise the verification result.
+    pub fn verify_reader(
+        self,
+        reader: impl Read,
+        target: Target,
+        nonce_encoding: NonceEncoding,
+        nonce: u64,
+    ) -> io::Result<Verification> {
+        fn verify<D: Digest + Clone, R: Read>(
+            reader: R,
+            target: Target,
+            nonce_encoding: NonceEncoding,
+            nonce: u64,
+        ) -> io::Result<Verification> {
+            let mut puzzle = PrehashedPuzzle::<D>::from_reader(reader, target)?;
+            puzzle.nonce_encoding = nonce_encoding;
+            let hash = puzzle.hash(nonce);
+            Ok(Verification { valid: target.is_met_by(&hash), hash: hash.to_vec() })
+        }
+
+        let verify = match self {
+            Algorithm::Sha256 => verify::<Sha256, _>,
+            Algorithm::Blake3 => verify::<Blake3, _>,
+            Algorithm::Keccak256 => verify::<Keccak256, _>,
+            Algorithm::DoubleSha256 => verify::<DoubleSha256, _>,
+        };
+        verify(reader, target, nonce_encoding, nonce)
+    }
+
+    /// Solves `puzzle` across `num_threads` threads using this algorithm.
+    ///
+    /// See [`parallel_mine_with`] for details and the return value.
+    pub fn parallel_mine(
+        self,
+        puzzle: &Puzzle,
+        num_threads: usize,
+    ) -> Result<Solution, PuzzleError> {
+        match self {
+            Algorithm::Sha256 => parallel_mine_with::<Sha256>(puzzle, num_threads),
+            Algorithm::Blake3 => parallel_mine_with::<Blake3>(puzzle, num_threads),
+            Algorithm::Keccak256 => parallel_mine_with::<Keccak256>(puzzle, num_threads),
+            Algorithm::DoubleSha256 => parallel_mine_with::<DoubleSha256>(puzzle, num_threads),
+        }
+    }
+
+    /// Solves `puzzle` across `num_threads` threads using this algorithm, searching only
+    /// the nonces in `nonces`.
+    ///
+    /// See [`parallel_mine_range`] for details and the return value.
+    pub fn parallel_mine_range(
+        self,
+        puzzle: &Puzzle,
+        nonces: RangeInclusive<u64>,
+        num_threads: usize,
+    ) -> Result<Solution, PuzzleError> {
+        match self {
+            Algorithm::Sha256 => parallel_mine_range::<Sha256>(puzzle, nonces, num_threads),
+            Algorithm::Blake3 => parallel_mine_range::<Blake3>(puzzle, nonces, num_threads),
+            Algorithm::Keccak256 => parallel_mine_range::<Keccak256>(puzzle, nonces, num_threads),
+            Algorithm::DoubleSha256 => {
+                parallel_mine_range::<DoubleSha256>(puzzle, nonces, num_threads)
+            }
+        }
+    }
+
+    /// Starts mining `puzzle` with this algorithm in the background.
+    ///
+    /// See [`parallel_mine_spawn_with`] for details.
+    pub fn parallel_mine_spawn(
+        self,
+        puzzle: &Puzzle,
+        nonces: RangeInclusive<u64>,
+        num_threads: usize,
+    ) -> MiningHandle {
+        let spawn = match self {
+            Algorithm::Sha256 => parallel_mine_spawn_with::<Sha256>,
+            Algorithm::Blake3 => parallel_mine_spawn_with::<Blake3>,
+            Algorithm::Keccak256 => parallel_mine_spawn_with::<Keccak256>,
+            Algorithm::DoubleSha256 => parallel_mine_spawn_with::<DoubleSha256>,
+        };
+        spawn(puzzle, nonces, num_threads)
+    }
+
+    /// Mines `puzzle` with this algorithm, giving up after `max_duration`.
+    ///
+    /// See [`parallel_mine_timeout_with`] for details and the return value.
+    pub fn parallel_mine_timeout(
+        self,
+        puzzle: &Puzzle,
+        nonces: RangeInclusive<u64>,
+        num_threads: usize,
+        max_duration: Duration,
+    ) -> Result<Solution, PuzzleError> {
+        let mine = match self {
+            Algorithm::Sha256 => parallel_mine_timeout_with::<Sha256>,
+            Algorithm::Blake3 => parallel_mine_timeout_with::<Blake3>,
+            Algorithm::Keccak256 => parallel_mine_timeout_with::<Keccak256>,
+            Algorithm::DoubleSha256 => parallel_mine_timeout_with::<DoubleSha256>,
+        };
+        mine(puzzle, nonces, num_threads, max_duration)
+    }
+
+    /// Mines `puzzle` with this algorithm until a solution is found or `token` is
+    /// cancelled.
+    ///
+    /// See [`parallel_mine_cancellable_with`] for details and the return value.
+    pub fn parallel_mine_cancellable(
+        self,
+        puzzle: &Puzzle,
+        nonces: RangeInclusive<u64>,
+        num_threads: usize,
+        token: &CancellationToken,
+    ) -> Result<Solution, PuzzleError> {
+        let mine = match self {
+            Algorithm::Sha256 => parallel_mine_cancellable_with::<Sha256>,
+            Algorithm::Blake3 => parallel_mine_cancellable_with::<Blake3>,
+            Algorithm::Keccak256 => parallel_mine_cancellable_with::<Keccak256>,
+            Algorithm::DoubleSha256 => parallel_mine_cancellable_with::<DoubleSha256>,
+        };
+        mine(puzzle, nonces, num_threads, token)
+    }
+
+    /// Measures the hash rate of this algorithm on `puzzle` over `duration`.
+    ///
+    /// See [`measure_hash_rate_with`] for details.
+    pub fn measure_hash_rate(self, puzzle: &Puzzle, num_threads: usize, duration: Duration) -> f64 {
+        let measure = match self {
+            Algorithm::Sha256 => measure_hash_rate_with::<Sha256>,
+            Algorithm::Blake3 => measure_hash_rate_with::<Blake3>,
+            Algorithm::Keccak256 => measure_hash_rate_with::<Keccak256>,
+            Algorithm::DoubleSha256 => measure_hash_rate_with::<DoubleSha256>,
+        };
+        measure(puzzle, num_threads, duration)
+    }
+
+    /// Benchmarks this algorithm on `puzzle` for `benchmark` and picks the target it
+    /// meets in `solve_time` on average.
+    ///
+    /// See [`calibrate_with`] for details and the return value.
+    pub fn calibrate(
+        self,
+        puzzle: &Puzzle,
+        num_threads: usize,
+        solve_time: Duration,
+        benchmark: Duration,
+    ) -> Calibration {
+        let calibrate = match self {
+            Algorithm::Sha256 => calibrate_with::<Sha256>,
+            Algorithm::Blake3 => calibrate_with::<Blake3>,
+            Algorithm::Keccak256 => calibrate_with::<Keccak256>,
+            Algorithm::DoubleSha256 => calibrate_with::<DoubleSha256>,
+        };
+        calibrate(puzzle, num_threads, solve_time, benchmark)
+    }
+
+    /// Generates a puzzle over `data_len` random bytes that takes `target_duration` on
+    /// average to solve with this algorithm on `num_threads` threads.
+    ///
+    /// See [`generate_puzzle_with`] for details.
+    pub fn generate_puzzle(
+        self,
+        data_len: usize,
+        target_duration: Duration,
+        num_threads: usize,
+    ) -> Puzzle {
+        let generate = match self {
+            Algorithm::Sha256 => generate_puzzle_with::<Sha256>,
+            Algorithm::Blake3 => generate_puzzle_with::<Blake3>,
+            Algorithm::Keccak256 => generate_puzzle_with::<Keccak256>,
+            Algorithm::DoubleSha256 => generate_puzzle_with::<DoubleSha256>,
+        };
+        generate(data_len, target_duration, num_threads)
+    }
+
+    /// Generates `count` puzzles from the seeds starting at `first_seed` that each take
+    /// `target_duration` on average to solve with this algorithm on `num_threads` threads.
+    ///
+    /// See [`generate_puzzles_with`] for details.
+    pub fn generate_puzzles(
+        self,
+        first_seed: u64,
+        count: usize,
+        data_len: usize,
+        target_duration: Duration,
+        num_threads: usize,
+    ) -> Vec<GeneratedPuzzle> {
+        let generate = match self {
+            Algorithm::Sha256 => generate_puzzles_with::<Sha256>,
+            Algorithm::Blake3 => generate_puzzles_with::<Blake3>,
+            Algorithm::Keccak256 => generate_puzzles_with::<Keccak256>,
+            Algorithm::DoubleSha256 => generate_puzzles_with::<DoubleSha256>,
+        };
+        generate(first_seed, count, data_len, target_duration, num_threads)
+    }
+
+    /// Generates one puzzle from the seeds starting at `first_seed` for each of
+    /// `solve_times`, each taking its time on average with this algorithm on
+    /// `num_threads` threads.
+    ///
+    /// See [`generate_series_with`] for details.
+    pub fn generate_series(
+        self,
+        first_seed: u64,
+        data_len: usize,
+        solve_times: &[Duration],
+        num_threads: usize,
+    ) -> Vec<GeneratedPuzzle> {
+        let generate = match self {
+            Algorithm::Sha256 => generate_series_with::<Sha256>,
+            Algorithm::Blake3 => generate_series_with::<Blake3>,
+            Algorithm::Keccak256 => generate_series_with::<Keccak256>,
+            Algorithm::DoubleSha256 => generate_series_with::<DoubleSha256>,
+        };
+        generate(first_seed, data_len, solve_times, num_threads)
+    }
+
+    /// Continues mining `puzzle` with this algorithm from `checkpoint`.
+    ///
+    /// See [`parallel_mine_resume_with`] for details and the return value.
+    pub fn parallel_mine_resume(
+        self,
+        puzzle: &Puzzle,
+        checkpoint: &Checkpoint,
+    ) -> Result<MiningHandle, CheckpointError> {
+        let resume = match self {
+            Algorithm::Sha256 => parallel_mine_resume_with::<Sha256>,
+            Algorithm::Blake3 => parallel_mine_resume_with::<Blake3>,
+            Algorithm::Keccak256 => parallel_mine_resume_with::<Keccak256>,
+            Algorithm::DoubleSha256 => parallel_mine_resume_with::<DoubleSha256>,
+        };
+        resume(puzzle, checkpoint)
+    }
+
+    /// Like [`parallel_mine_spawn`](Algorithm::parallel_mine_spawn), with `observer`
+    /// told about the search and the workers run on `pool` if there is one.
+    pub(crate) fn spawn_observed(
+        self,
+        puzzle: &Puzzle,
+        nonces: RangeInclusive<u64>,
+        num_threads: usize,
+        order: SearchOrder,
+        observer: Option<Arc<dyn Observer>>,
+        pool: Option<Arc<WorkerPool>>,
+    ) -> MiningHandle {
+        let spawn = match self {
+            Algorithm::Sha256 => spawn_observed::<Sha256>,
+            Algorithm::Blake3 => spawn_observed::<Blake3>,
+            Algorithm::Keccak256 => spawn_observed::<Keccak256>,
+            Algorithm::DoubleSha256 => spawn_observed::<DoubleSha256>,
+        };
+        spawn(puzzle, nonces, num_threads, order, observer, pool)
+    }
+
+    /// Like [`parallel_mine_resume`](Algorithm::parallel_mine_resume), with `observer`
+    /// told about the search and the workers run on `pool` if there is one.
+    pub(crate) fn resume_observed(
+        self,
+        puzzle: &Puzzle,
+        checkpoint: &Checkpoint,
+        observer: Option<Arc<dyn Observer>>,
+        pool: Option<Arc<WorkerPool>>,
+    ) -> Result<MiningHandle, CheckpointError> {
+        let resume = match self {
+            Algorithm::Sha256 => resume_observed::<Sha256>,
+            Algorithm::Blake3 => resume_observed::<Blake3>,
+            Algorithm::Keccak256 => resume_observed::<Keccak256>,
+            Algorithm::DoubleSha256 => resume_observed::<DoubleSha256>,
+        };
+        resume(puzzle, checkpoint, observer, pool)
+    }
+
+    /// Searches `nonces` for a hash with this algorithm that satisfies `predicate`.
+    ///
+    /// See [`parallel_mine_matching`] for details and the return value.
+    pub fn parallel_mine_matching<P>(
+        self,
+        puzzle: &Puzzle,
+        nonces: RangeInclusive<u64>,
+        num_threads: usize,
+        predicate: P,
+    ) -> Result<Solution, PuzzleError>
+    where
+        P: Fn(&[u8]) -> bool + Send + Sync + 'static,
+    {
+        let mine = match self {
+            Algorithm::Sha256 => parallel_mine_matching::<Sha256, P>,
+            Algorithm::Blake3 => parallel_mine_matching::<Blake3, P>,
+            Algorithm::Keccak256 => parallel_mine_matching::<Keccak256, P>,
+            Algorithm::DoubleSha256 => parallel_mine_matching::<DoubleSha256, P>,
+        };
+        mine(puzzle, nonces, num_threads, predicate)
+    }
+
+    /// Mines every puzzle in `puzzles` with this algorithm over `num_threads` shared
+    /// threads.
+    ///
+    /// See [`parallel_mine_batch_with`] for details.
+    pub fn parallel_mine_batch(self, puzzles: &[Puzzle], num_threads: usize) -> Vec<BatchResult> {
+        let mine = match self {
+            Algorithm::Sha256 => parallel_mine_batch_with::<Sha256>,
+            Algorithm::Blake3 => parallel_mine_batch_with::<Blake3>,
+            Algorithm::Keccak256 => parallel_mine_batch_with::<Keccak256>,
+            Algorithm::DoubleSha256 => parallel_mine_batch_with::<DoubleSha256>,
+        };
+        mine(puzzles, num_threads)
+    }
+
+    /// Streams every solution of `puzzle` in `nonces` using this algorithm, stopping
+    /// after `limit` solutions if given.
+    ///
+    /// See [`find_solutions_with`] for details.
+    pub fn find_solutions(
+        self,
+        puzzle: &Puzzle,
+        nonces: RangeInclusive<u64>,
+        num_threads: usize,
+        limit: Option<usize>,
+    ) -> Solutions {
+        let find = match self {
+            Algorithm::Sha256 => find_solutions_with::<Sha256>,
+            Algorithm::Blake3 => find_solutions_with::<Blake3>,
+            Algorithm::Keccak256 => find_solutions_with::<Keccak256>,
+            Algorithm::DoubleSha256 => find_solutions_with::<DoubleSha256>,
+        };
+        find(puzzle, nonces, num_threads, limit)
+    }
+
+    /// Streams the payload from `reader` into this algorithm's prefix state once, then
+    /// solves it by splitting `nonces` across `num_threads` threads, appending the nonce
+    /// with `nonce_encoding`.
+    ///
+    /// See [`PrehashedPuzzle`] for why this avoids holding the payload in memory.
+    ///
+    /// # Returns
+    ///
+    /// An I/O error if reading fails; otherwise the mining result.
+    pub fn parallel_mine_reader(
+        self,
+        reader: impl Read,
+        target: Target,
+        nonce_encoding: NonceEncoding,
+        nonces: RangeInclusive<u64>,
+        num_threads: usize,
+    ) -> io::Result<Result<Solution, PuzzleError>> {
+        fn mine<D: Digest + Clone + Send + Sync + 'static, R: Read>(
+            reader: R,
+            target: Target,
+            nonce_encoding: NonceEncoding,
+            nonces: RangeInclusive<u64>,
+            num_threads: usize,
+        ) -> io::Result<Result<Solution, PuzzleError>> {
+            let mut puzzle = PrehashedPuzzle::<D>::from_reader(reader, target)?;
+            puzzle.nonce_encoding = nonce_encoding;
+            Ok(parallel_mine_prehashed(&puzzle, nonces, num_threads))
+        }
+
+        let mine = match self {
+            Algorithm::Sha256 => mine::<Sha256, _>,
+            Algorithm::Blake3 => mine::<Blake3, _>,
+            Algorithm::Keccak256 => mine::<Keccak256, _>,
+            Algorithm::DoubleSha256 => mine::<DoubleSha256, _>,
+        };
+        mine(reader, target, nonce_encoding, nonces, num_threads)
+    }
+}
+
+impl fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Algorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let wanted = s.to_ascii_lowercase();
+        Algorithm::ALL
+            .into_iter()
+            .find(|algo| algo.name() == wanted)
+            .ok_or_else(|| {
+                let names: Vec<_> = Algorithm::ALL.iter().map(|algo| algo.name()).collect();
+                format!("unknown algorithm `{}` (expected one of: {})", s, names.join(", "))
+            })
+    }
+}
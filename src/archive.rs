@@ -0,0 +1,620 @@
+//! Keeping an archive of solved puzzles.
+//!
+//! A long campaign solves puzzles for days on end, on machines that come and go. An
+//! archive keeps every solution found, together with the puzzle it solves, where it can
+//! be looked up and checked again later. [`ResultStore`] is the interface to one:
+//! [`save`](ResultStore::save) an [`ArchivedSolution`], and [`get`](ResultStore::get) it
+//! back by the id of its puzzle. Two stores come with the crate:
+//!
+//! - [`FileStore`] keeps each solution in a file of its own in a directory.
+//! - [`S3Store`] keeps each as an object in a bucket of Amazon S3, or of any service that
+//!   speaks its API, such as MinIO or Ceph. `https://` endpoints need the `tls` feature.
+//!
+//! A puzzle's id, from [`puzzle_id`], is a SHA-256 fingerprint of the puzzle and its hash
+//! function, so the same puzzle has the same id whichever machine solved it. Each
+//! solution is kept as one JSON object, in the file or object `<id>.json`:
+//!
+//! ```text
+//! {"record":"solution","puzzle":"3f0c...e1","algorithm":"sha256","data_hex":"...",...}
+//! ```
+//!
+//! An archive is written once: saving a solution of a puzzle that is archived already
+//! keeps the earlier one, so nothing archived is ever replaced. A solution read back can
+//! be hashed again with [`ArchivedSolution::verify`] to check that it still holds.
+
+use std::error::Error;
+use std::fmt::{self, Write as _};
+use std::fs;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use sha2::Sha256;
+
+use crate::digest::Digest;
+use crate::hashcash::civil_from_days;
+use crate::json::{nullable, Request, Response};
+use crate::work_client::Connection;
+#[cfg(feature = "tls")]
+use crate::ClientTls;
+use crate::{hex, Algorithm, NonceFormat, Puzzle, Solution};
+
+/// How long an [`S3Store`] waits for the service to answer a request.
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The reasons a solution could not be archived or looked up.
+#[derive(Debug)]
+pub enum StoreError {
+    /// The archive could not be reached, read or written.
+    Io(io::Error),
+    /// The service refused the request with this HTTP status and message.
+    Refused(u16, String),
+    /// The id is not one [`puzzle_id`] gives.
+    InvalidId(String),
+    /// An archived solution, the address of the archive or its answer could not be
+    /// understood.
+    Malformed(String),
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::Io(e) => write!(f, "archive I/O failed: {}", e),
+            StoreError::Refused(status, message) => {
+                write!(f, "the archive refused the request ({}): {}", status, message)
+            }
+            StoreError::InvalidId(id) => write!(f, "`{}` is not a puzzle id", id),
+            StoreError::Malformed(message) => write!(f, "malformed archive: {}", message),
+        }
+    }
+}
+
+impl Error for StoreError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            StoreError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for StoreError {
+    fn from(e: io::Error) -> Self {
+        StoreError::Io(e)
+    }
+}
+
+impl From<String> for StoreError {
+    /// A record or an answer that could not be read.
+    fn from(message: String) -> Self {
+        StoreError::Malformed(message)
+    }
+}
+
+/// The id of `puzzle` mined with `algorithm` in an archive: the SHA-256, in hex, of a line
+/// naming the algorithm, the nonce format, the extra nonce the search started from and
+/// the target, followed by the data.
+pub fn puzzle_id(puzzle: &Puzzle, algorithm: Algorithm) -> String {
+    let optional = |value: Option<u64>| value.map_or("-".to_string(), |value| value.to_string());
+    let header = format!(
+        "{} {} {} {} {}\n",
+        algorithm.name(),
+        puzzle.nonce_format.encoding,
+        optional(puzzle.nonce_format.offset.map(|offset| offset as u64)),
+        optional(puzzle.extra_nonce),
+        puzzle.target
+    );
+    hex::encode(&Sha256::new().chain_update(header).chain_update(&puzzle.data).finalize())
+}
+
+/// A solution kept in an archive, with the puzzle it solves.
+#[derive(Clone, Debug)]
+pub struct ArchivedSolution {
+    /// The puzzle, with the extra nonce its search started from, if it uses one.
+    pub puzzle: Puzzle,
+    /// The hash the puzzle was mined with.
+    pub algorithm: Algorithm,
+    /// The solution found.
+    pub solution: Solution,
+    /// When the solution was found, in seconds since the Unix epoch.
+    pub solved_at: u64,
+}
+
+impl ArchivedSolution {
+    /// The `solution` of `puzzle` mined with `algorithm`, found just now.
+    pub fn new(puzzle: Puzzle, algorithm: Algorithm, solution: Solution) -> Self {
+        let solved_at = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        ArchivedSolution { puzzle, algorithm, solution, solved_at }
+    }
+
+    /// The id of the puzzle solved; see [`puzzle_id`].
+    pub fn puzzle_id(&self) -> String {
+        puzzle_id(&self.puzzle, self.algorithm)
+    }
+
+    /// Whether the solution holds: its nonce, under its extra nonce, hashes to its hash,
+    /// which meets the puzzle's target.
+    pub fn verify(&self) -> bool {
+        let puzzle = Puzzle { extra_nonce: self.solution.extra_nonce, ..self.puzzle.clone() };
+        let verification = self.algorithm.verify(&puzzle, self.solution.nonce);
+        verification.valid && verification.hash == self.solution.hash
+    }
+}
+
+impl fmt::Display for ArchivedSolution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (puzzle, solution) = (&self.puzzle, &self.solution);
+        let record = Response::record("solution")
+            .string("puzzle", &self.puzzle_id())
+            .string("algorithm", self.algorithm.name())
+            .string("data_hex", &hex::encode(&puzzle.data))
+            .string("target", &puzzle.target.to_string())
+            .string("nonce_encoding", &puzzle.nonce_format.encoding.to_string())
+            .value("nonce_offset", &nullable(puzzle.nonce_format.offset))
+            .value("extra_nonce", &nullable(puzzle.extra_nonce))
+            .value("issued_at", &nullable(puzzle.issued_at))
+            .value("expires_at", &nullable(puzzle.expires_at))
+            .number("nonce", solution.nonce)
+            .string("hash", &hex::encode(&solution.hash))
+            .value("solution_extra_nonce", &nullable(solution.extra_nonce))
+            .number("solved_at", self.solved_at);
+        write!(f, "{}", record)
+    }
+}
+
+impl FromStr for ArchivedSolution {
+    type Err = StoreError;
+
+    fn from_str(s: &str) -> Result<Self, StoreError> {
+        let record = Request::parse(s.trim())?;
+        let field = |key: &str| -> Result<&str, String> {
+            record.string(key)?.ok_or_else(|| format!("missing `{}`", key))
+        };
+        if field("record")? != "solution" {
+            return Err(StoreError::Malformed("not an archived solution".to_string()));
+        }
+        let puzzle = Puzzle {
+            target: field("target")?.parse()?,
+            data: hex::decode(field("data_hex")?).map_err(|e| e.to_string())?,
+            nonce: 0,
+            nonce_format: NonceFormat {
+                encoding: field("nonce_encoding")?.parse()?,
+                offset: record.number("nonce_offset")?,
+            },
+            extra_nonce: record.number("extra_nonce")?,
+            issued_at: record.number("issued_at")?,
+            expires_at: record.number("expires_at")?,
+        };
+        let archived = ArchivedSolution {
+            puzzle,
+            algorithm: field("algorithm")?.parse()?,
+            solution: Solution {
+                nonce: record.required("nonce")?,
+                hash: hex::decode(field("hash")?).map_err(|e| e.to_string())?,
+                extra_nonce: record.number("solution_extra_nonce")?,
+            },
+            solved_at: record.required("solved_at")?,
+        };
+        if field("puzzle")? != archived.puzzle_id() {
+            return Err(StoreError::Malformed("the puzzle id does not match the puzzle".into()));
+        }
+        Ok(archived)
+    }
+}
+
+/// Somewhere solved puzzles are archived; see the [module documentation](self).
+pub trait ResultStore {
+    /// Archives `record` under the id of its puzzle, unless a solution of that puzzle is
+    /// archived already.
+    ///
+    /// # Returns
+    ///
+    /// Whether the solution was archived, `false` if the earlier one was kept, or why the
+    /// archive could not be written.
+    fn save(&self, record: &ArchivedSolution) -> Result<bool, StoreError>;
+
+    /// Looks up the solution of the puzzle whose id is `puzzle_id`.
+    ///
+    /// # Returns
+    ///
+    /// The solution, `None` if the puzzle is not in the archive, or why it could not be
+    /// read.
+    fn get(&self, puzzle_id: &str) -> Result<Option<ArchivedSolution>, StoreError>;
+}
+
+/// An archive in a directory, with each solution in the file `<id>.json`.
+#[derive(Clone, Debug)]
+pub struct FileStore {
+    dir: PathBuf,
+}
+
+impl FileStore {
+    /// Opens the archive in the directory `dir`, creating it if need be.
+    ///
+    /// # Returns
+    ///
+    /// The archive, or why the directory could not be created.
+    pub fn open(dir: impl Into<PathBuf>) -> Result<Self, StoreError> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(FileStore { dir })
+    }
+
+    /// The directory the archive is kept in.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The file the solution of the puzzle `puzzle_id` is kept in.
+    fn path(&self, puzzle_id: &str) -> Result<PathBuf, StoreError> {
+        check_id(puzzle_id)?;
+        Ok(self.dir.join(format!("{}.json", puzzle_id)))
+    }
+}
+
+impl ResultStore for FileStore {
+    fn save(&self, record: &ArchivedSolution) -> Result<bool, StoreError> {
+        let id = record.puzzle_id();
+        let path = self.path(&id)?;
+        // The record is written to a temporary file first and then linked into place,
+        // which fails if the file exists, so a half-written record is never seen and an
+        // archived one never replaced.
+        let temporary = self.dir.join(format!(".{}.{:016x}.tmp", id, rand::random::<u64>()));
+        fs::write(&temporary, format!("{}\n", record))?;
+        let linked = fs::hard_link(&temporary, &path);
+        fs::remove_file(&temporary)?;
+        match linked {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn get(&self, puzzle_id: &str) -> Result<Option<ArchivedSolution>, StoreError> {
+        match fs::read_to_string(self.path(puzzle_id)?) {
+            Ok(text) => read_record(puzzle_id, &text).map(Some),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// The keys requests to S3 are signed with.
+#[derive(Clone)]
+pub struct S3Credentials {
+    /// The access key id.
+    pub access_key: String,
+    /// The secret access key.
+    pub secret_key: String,
+    /// The session token that comes with temporary keys.
+    pub session_token: Option<String>,
+}
+
+impl S3Credentials {
+    /// The keys in the usual environment variables: `AWS_ACCESS_KEY_ID`,
+    /// `AWS_SECRET_ACCESS_KEY` and, for temporary keys, `AWS_SESSION_TOKEN`.
+    ///
+    /// # Returns
+    ///
+    /// The keys, or `None` if either of the first two is not set.
+    pub fn from_env() -> Option<Self> {
+        Some(S3Credentials {
+            access_key: std::env::var("AWS_ACCESS_KEY_ID").ok()?,
+            secret_key: std::env::var("AWS_SECRET_ACCESS_KEY").ok()?,
+            session_token: std::env::var("AWS_SESSION_TOKEN").ok(),
+        })
+    }
+}
+
+impl fmt::Debug for S3Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("S3Credentials");
+        debug.field("access_key", &self.access_key).finish_non_exhaustive()
+    }
+}
+
+/// An archive in an S3 bucket, with each solution in the object `<prefix><id>.json`.
+///
+/// Requests are signed with AWS Signature Version 4 and address the bucket by path, as
+/// `<endpoint>/<bucket>/<key>`, which every S3-compatible service understands. Saving
+/// sends `If-None-Match: *`, so the service keeps the earlier solution of a puzzle, and a
+/// service too old to honour it replaces it instead.
+#[derive(Debug)]
+pub struct S3Store {
+    /// The endpoint's `host[:port]`, as written in its URL.
+    host: String,
+    /// The endpoint's `host:port`.
+    addr: String,
+    bucket: String,
+    prefix: String,
+    region: String,
+    credentials: S3Credentials,
+    /// How to speak TLS to an `https://` endpoint, and its name in its certificate.
+    #[cfg(feature = "tls")]
+    tls: Option<(ClientTls, String)>,
+}
+
+impl S3Store {
+    /// An archive in `bucket` of the service at `endpoint`, written as `http://host:port`
+    /// or, with the `tls` feature, `https://host:port`, such as
+    /// `https://s3.eu-west-1.amazonaws.com`, signing requests for `region` with
+    /// `credentials`. Nothing is sent until the first request.
+    ///
+    /// # Returns
+    ///
+    /// The archive, or why `endpoint` is not usable.
+    pub fn new(
+        endpoint: &str,
+        bucket: &str,
+        region: &str,
+        credentials: S3Credentials,
+    ) -> Result<Self, StoreError> {
+        let (https, host) = match endpoint.split_once("://") {
+            Some(("http", host)) => (false, host),
+            Some(("https", host)) => (true, host),
+            _ => return Err(format!("`{}` is not an http:// or https:// URL", endpoint).into()),
+        };
+        let host = host.trim_end_matches('/');
+        if host.is_empty() || host.contains('/') {
+            return Err(format!("`{}` is not an endpoint address", endpoint).into());
+        }
+        if bucket.is_empty() || bucket.contains('/') {
+            return Err(format!("`{}` is not a bucket name", bucket).into());
+        }
+        let (name, addr) = match host.rsplit_once(':').filter(|(_, port)| !port.contains(']')) {
+            Some((name, _)) => (name, host.to_string()),
+            None => (host, format!("{}:{}", host, if https { 443 } else { 80 })),
+        };
+        #[cfg(feature = "tls")]
+        let tls = match https {
+            true => Some((ClientTls::new(None, None).map_err(|e| e.to_string())?, name.into())),
+            false => None,
+        };
+        #[cfg(not(feature = "tls"))]
+        if https {
+            return Err("this build has no TLS (feature `tls`)".to_string().into());
+        }
+        #[cfg(not(feature = "tls"))]
+        let _ = name;
+        Ok(S3Store {
+            host: host.to_string(),
+            addr,
+            bucket: bucket.to_string(),
+            prefix: String::new(),
+            region: region.to_string(),
+            credentials,
+            #[cfg(feature = "tls")]
+            tls,
+        })
+    }
+
+    /// Returns the archive with its objects kept under `prefix` in the bucket, such as
+    /// `campaigns/2024/`. A `/` is added if `prefix` does not end with one.
+    pub fn with_prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.trim_matches('/').to_string();
+        if !self.prefix.is_empty() {
+            self.prefix.push('/');
+        }
+        self
+    }
+
+    /// The bucket the archive is kept in.
+    pub fn bucket(&self) -> &str {
+        &self.bucket
+    }
+
+    /// The path of the object the solution of the puzzle `puzzle_id` is kept in.
+    fn path(&self, puzzle_id: &str) -> Result<String, StoreError> {
+        check_id(puzzle_id)?;
+        Ok(uri_encode(&format!("/{}/{}{}.json", self.bucket, self.prefix, puzzle_id)))
+    }
+
+    /// Opens a connection to the service, over TLS for an `https://` endpoint.
+    fn open(&self) -> Result<Box<dyn Connection>, StoreError> {
+        let stream = TcpStream::connect(&self.addr)?;
+        stream.set_read_timeout(Some(RESPONSE_TIMEOUT))?;
+        #[cfg(feature = "tls")]
+        if let Some((tls, name)) = &self.tls {
+            return Ok(Box::new(tls.connect(name, stream)?));
+        }
+        Ok(Box::new(stream))
+    }
+
+    /// Sends a signed `method` request for `path` with the extra `headers` and `body`.
+    ///
+    /// # Returns
+    ///
+    /// The status and body of the answer, or why the request failed.
+    fn request(
+        &self,
+        method: &str,
+        path: &str,
+        headers: &[(&str, &str)],
+        body: &[u8],
+    ) -> Result<(u16, Vec<u8>), StoreError> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        let (year, month, day) = civil_from_days((now / 86_400) as i64);
+        let date = format!("{:04}{:02}{:02}", year, month, day);
+        let time = now % 86_400;
+        let timestamp =
+            format!("{}T{:02}{:02}{:02}Z", date, time / 3_600, time / 60 % 60, time % 60);
+        let payload = hex::encode(&Sha256::digest(body));
+
+        let mut signed = vec![
+            ("host".to_string(), self.host.clone()),
+            ("x-amz-content-sha256".to_string(), payload.clone()),
+            ("x-amz-date".to_string(), timestamp.clone()),
+        ];
+        if let Some(token) = &self.credentials.session_token {
+            signed.push(("x-amz-security-token".to_string(), token.clone()));
+        }
+        for (name, value) in headers {
+            signed.push((name.to_ascii_lowercase(), value.to_string()));
+        }
+        signed.sort();
+        let names: Vec<&str> = signed.iter().map(|(name, _)| name.as_str()).collect();
+        let names = names.join(";");
+        let canonical_headers: String =
+            signed.iter().map(|(name, value)| format!("{}:{}\n", name, value.trim())).collect();
+        let canonical_request =
+            format!("{}\n{}\n\n{}\n{}\n{}", method, path, canonical_headers, names, payload);
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            timestamp,
+            scope,
+            hex::encode(&Sha256::digest(canonical_request.as_bytes()))
+        );
+        let mut key = hmac(format!("AWS4{}", self.credentials.secret_key).as_bytes(), &date);
+        for part in [self.region.as_str(), "s3", "aws4_request"] {
+            key = hmac(&key, part);
+        }
+        let signature = hex::encode(&hmac(&key, &string_to_sign));
+
+        let mut head = format!("{} {} HTTP/1.1\r\n", method, path);
+        for (name, value) in &signed {
+            let _ = write!(head, "{}: {}\r\n", name, value);
+        }
+        let _ = write!(
+            head,
+            "authorization: AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, \
+             Signature={}\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+            self.credentials.access_key,
+            scope,
+            names,
+            signature,
+            body.len()
+        );
+        let mut stream = self.open()?;
+        stream.write_all(head.as_bytes())?;
+        stream.write_all(body)?;
+        let mut answer = Vec::new();
+        stream.read_to_end(&mut answer)?;
+
+        let split = answer
+            .windows(4)
+            .position(|window| window == b"\r\n\r\n")
+            .ok_or_else(|| "the answer has no body".to_string())?;
+        let head = String::from_utf8_lossy(&answer[..split]).to_ascii_lowercase();
+        let status: u16 = head
+            .split_whitespace()
+            .nth(1)
+            .and_then(|status| status.parse().ok())
+            .ok_or_else(|| "the answer has no status".to_string())?;
+        let body = &answer[split + 4..];
+        if head.lines().any(|line| line.replace(' ', "") == "transfer-encoding:chunked") {
+            return Ok((status, dechunk(body)?));
+        }
+        Ok((status, body.to_vec()))
+    }
+}
+
+impl ResultStore for S3Store {
+    fn save(&self, record: &ArchivedSolution) -> Result<bool, StoreError> {
+        let path = self.path(&record.puzzle_id())?;
+        let body = format!("{}\n", record);
+        let headers = [("content-type", "application/json"), ("if-none-match", "*")];
+        match self.request("PUT", &path, &headers, body.as_bytes())? {
+            (200, _) => Ok(true),
+            (412, _) => Ok(false),
+            (status, body) => Err(refused(status, &body)),
+        }
+    }
+
+    fn get(&self, puzzle_id: &str) -> Result<Option<ArchivedSolution>, StoreError> {
+        let path = self.path(puzzle_id)?;
+        match self.request("GET", &path, &[], &[])? {
+            (200, body) => read_record(puzzle_id, &String::from_utf8_lossy(&body)).map(Some),
+            (404, _) => Ok(None),
+            (status, body) => Err(refused(status, &body)),
+        }
+    }
+}
+
+/// Checks that `puzzle_id` is one [`puzzle_id`] gives, so it is safe in a path.
+fn check_id(puzzle_id: &str) -> Result<(), StoreError> {
+    if puzzle_id.len() != 64 || !puzzle_id.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(StoreError::InvalidId(puzzle_id.to_string()));
+    }
+    Ok(())
+}
+
+/// Reads the archived solution in `text`, which must be of the puzzle `puzzle_id`.
+fn read_record(puzzle_id: &str, text: &str) -> Result<ArchivedSolution, StoreError> {
+    let record: ArchivedSolution = text.parse()?;
+    if !record.puzzle_id().eq_ignore_ascii_case(puzzle_id) {
+        return Err(format!("the solution kept as {} is of another puzzle", puzzle_id).into());
+    }
+    Ok(record)
+}
+
+/// The error for an answer with the unexpected `status`, with the message of its XML
+/// `body`, if it has one.
+fn refused(status: u16, body: &[u8]) -> StoreError {
+    let body = String::from_utf8_lossy(body);
+    let element = |name: &str| {
+        let start = body.find(&format!("<{}>", name))? + name.len() + 2;
+        let end = body[start..].find('<')?;
+        Some(body[start..start + end].to_string())
+    };
+    let message = match (element("Code"), element("Message")) {
+        (Some(code), Some(message)) => format!("{}: {}", code, message),
+        (code, message) => code.or(message).unwrap_or_else(|| "no reason given".to_string()),
+    };
+    StoreError::Refused(status, message)
+}
+
+/// HMAC-SHA256 of `message` under `key`, as Signature Version 4 chains it.
+fn hmac(key: &[u8], message: &str) -> [u8; 32] {
+    let mut block = [0; 64];
+    if key.len() > block.len() {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let inner = Sha256::new()
+        .chain_update(block.map(|byte| byte ^ 0x36))
+        .chain_update(message)
+        .finalize();
+    Sha256::new().chain_update(block.map(|byte| byte ^ 0x5c)).chain_update(inner).finalize().into()
+}
+
+/// `path` with every byte but the unreserved characters of RFC 3986 and `/`
+/// percent-encoded, as Signature Version 4 expects it.
+fn uri_encode(path: &str) -> String {
+    let mut encoded = String::new();
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                encoded.push(char::from(byte))
+            }
+            _ => {
+                let _ = write!(encoded, "%{:02X}", byte);
+            }
+        }
+    }
+    encoded
+}
+
+/// The body of an answer sent with `Transfer-Encoding: chunked`, without the chunking.
+fn dechunk(mut body: &[u8]) -> Result<Vec<u8>, StoreError> {
+    let mut data = Vec::new();
+    loop {
+        let line = body
+            .windows(2)
+            .position(|window| window == b"\r\n")
+            .ok_or_else(|| "a chunk has no size".to_string())?;
+        let size = String::from_utf8_lossy(&body[..line]);
+        let size = size.split(';').next().unwrap_or("").trim();
+        let size = usize::from_str_radix(size, 16).map_err(|e| e.to_string())?;
+        body = &body[line + 2..];
+        if size == 0 {
+            return Ok(data);
+        }
+        let chunk = body.get(..size).ok_or_else(|| "a chunk is cut short".to_string())?;
+        data.extend_from_slice(chunk);
+        body = body.get(size + 2..).unwrap_or(&[]);
+    }
+}
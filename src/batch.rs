@@ -0,0 +1,191 @@
+//! Mining many puzzles over one shared set of worker threads.
+//!
+//! Each puzzle's nonce window is cut into fixed-size chunks. Workers walk the puzzles
+//! round-robin and claim one chunk at a time, so every unsolved puzzle keeps getting a
+//! share of the threads: easy puzzles finish early while hard ones carry on, and threads
+//! freed by a solved puzzle move straight on to the rest.
+
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use sha2::Sha256;
+
+use crate::digest::Digest;
+use crate::{default_nonce_range, PrehashedPuzzle, Puzzle, PuzzleError, Solution};
+
+/// How many nonces a worker claims from a puzzle before moving on to the next one.
+const CHUNK_SIZE: u64 = 1 << 16;
+
+/// The outcome of one puzzle in a batch.
+#[derive(Debug)]
+pub struct BatchResult {
+    /// The solution, or why none was found.
+    pub result: Result<Solution, PuzzleError>,
+    /// How many nonces were hashed for this puzzle.
+    pub hashes: u64,
+    /// Time from the start of the batch until this puzzle was solved or exhausted.
+    pub elapsed: Duration,
+}
+
+/// Shared state for one puzzle while the batch is running.
+struct Job<D> {
+    puzzle: PrehashedPuzzle<D>,
+    extra_nonce: Option<u64>,
+    /// The last nonce of the window.
+    end: u64,
+    /// Start of the next unclaimed chunk, or `None` once the whole window is handed out.
+    next_start: Mutex<Option<u64>>,
+    /// Chunks that have been claimed but not yet finished.
+    in_flight: AtomicUsize,
+    /// Set by the first worker to solve the puzzle; workers stop once it is.
+    solution: OnceLock<Solution>,
+    hashes: AtomicU64,
+    elapsed: OnceLock<Duration>,
+}
+
+impl<D> Job<D> {
+    /// Hands out the next chunk of this puzzle's window, if it still needs work.
+    fn claim(&self) -> Option<(u64, u64)> {
+        if self.solution.get().is_some() {
+            return None;
+        }
+        let mut next_start = self.next_start.lock().unwrap_or_else(PoisonError::into_inner);
+        let start = (*next_start)?;
+        let end = start.saturating_add(CHUNK_SIZE - 1).min(self.end);
+        *next_start = end.checked_add(1).filter(|&next| next <= self.end);
+        // Counted under the lock so a finished window is never mistaken for an idle one.
+        self.in_flight.fetch_add(1, Ordering::AcqRel);
+        Some((start, end))
+    }
+
+    /// Records when the puzzle finished; only the first call has any effect.
+    fn finish(&self, started: Instant) {
+        self.elapsed.get_or_init(|| started.elapsed());
+    }
+}
+
+/// Mines every puzzle in `puzzles` with SHA-256 on all available cores.
+///
+/// See [`parallel_mine_batch_with`] for details.
+pub fn parallel_mine_batch(puzzles: &[Puzzle]) -> Vec<BatchResult> {
+    parallel_mine_batch_with::<Sha256>(puzzles, num_cpus::get())
+}
+
+/// Mines every puzzle in `puzzles` with the hash function `D`, sharing `num_threads`
+/// worker threads between them.
+///
+/// # Parameters
+///
+/// - `puzzles`: The puzzles to solve. Each one's current extra nonce, if any, is used as
+///   is and is not rolled.
+/// - `num_threads`: How many worker threads to spawn. A value of zero is treated as one.
+///
+/// # Returns
+///
+/// One [`BatchResult`] per puzzle, in the same order as `puzzles`.
+///
+/// # Details
+///
+/// Every puzzle searches the same window as [`parallel_mine_with`](crate::parallel_mine_with)
+/// would with this thread count. The window is cut into chunks of 65 536 nonces; a worker
+/// claims a chunk from one puzzle, searches it, then moves on to the next unsolved
+/// puzzle. A puzzle stops handing out chunks as soon as any worker solves it, and workers
+/// exit once no puzzle has unclaimed chunks left.
+pub fn parallel_mine_batch_with<D>(puzzles: &[Puzzle], num_threads: usize) -> Vec<BatchResult>
+where
+    D: Digest + Clone + Send + Sync + 'static,
+{
+    let num_threads = num_threads.max(1);
+    let nonces = default_nonce_range();
+    let jobs: Arc<Vec<Job<D>>> = Arc::new(
+        puzzles
+            .iter()
+            .map(|puzzle| Job {
+                puzzle: PrehashedPuzzle::from_puzzle(puzzle),
+                extra_nonce: puzzle.extra_nonce,
+                end: *nonces.end(),
+                next_start: Mutex::new((!nonces.is_empty()).then_some(*nonces.start())),
+                in_flight: AtomicUsize::new(0),
+                solution: OnceLock::new(),
+                hashes: AtomicU64::new(0),
+                elapsed: OnceLock::new(),
+            })
+            .collect(),
+    );
+    let started = Instant::now();
+
+    let handles: Vec<_> = (0..num_threads)
+        .map(|worker| {
+            let jobs = Arc::clone(&jobs);
+            thread::spawn(move || {
+                if jobs.is_empty() {
+                    return;
+                }
+                // Start each worker on a different puzzle so they spread out immediately.
+                let mut index = worker % jobs.len();
+                loop {
+                    let claimed = (0..jobs.len())
+                        .map(|step| (index + step) % jobs.len())
+                        .find_map(|i| jobs[i].claim().map(|chunk| (i, chunk)));
+                    let Some((i, (start, end))) = claimed else {
+                        return;
+                    };
+                    index = i + 1;
+
+                    let job = &jobs[i];
+                    let mut tried = 0u64;
+                    for nonce in start..=end {
+                        // Another thread may have solved this puzzle meanwhile.
+                        if job.solution.get().is_some() {
+                            break;
+                        }
+                        tried += 1;
+                        let hash = job.puzzle.hash(nonce);
+                        if job.puzzle.target.is_met_by(&hash) {
+                            let solution = Solution {
+                                nonce,
+                                hash: hash.to_vec(),
+                                extra_nonce: job.extra_nonce,
+                            };
+                            if job.solution.set(solution).is_ok() {
+                                job.finish(started);
+                            }
+                            break;
+                        }
+                    }
+                    job.hashes.fetch_add(tried, Ordering::Relaxed);
+
+                    // The last chunk of an exhausted window marks the puzzle as finished.
+                    if job.in_flight.fetch_sub(1, Ordering::AcqRel) == 1
+                        && job.next_start.lock().unwrap_or_else(PoisonError::into_inner).is_none()
+                    {
+                        job.finish(started);
+                    }
+                }
+            })
+        })
+        .collect();
+
+    // A panicked worker may have left chunks unsearched, so no window counts as exhausted.
+    let mut panicked = false;
+    for handle in handles {
+        panicked |= handle.join().is_err();
+    }
+
+    jobs.iter()
+        .map(|job| {
+            let result = job.solution.get().cloned().ok_or(if panicked {
+                PuzzleError::WorkerPanicked
+            } else {
+                PuzzleError::Exhausted
+            });
+            BatchResult {
+                result,
+                hashes: job.hashes.load(Ordering::Relaxed),
+                elapsed: job.elapsed.get().copied().unwrap_or_default(),
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,235 @@
+//! Benchmarking the hash rate of every algorithm on this machine.
+//!
+//! [`Benchmark::run`] searches with each [`Algorithm`] for a fixed time, first on one
+//! thread and then on all of them, with a target no hash can meet, so the result is the
+//! machine's steady hash rate rather than the luck of a particular puzzle. The ratio of
+//! the two rates shows how well each algorithm scales across the cores.
+//!
+//! A benchmark can be saved and loaded again, so calibration and estimates can use the
+//! measured rates instead of measuring again before every puzzle; see
+//! [`Benchmark::calibrate`] and [`Benchmark::estimate`]. The file is plain text, one
+//! field per line, with each algorithm's single-thread and all-thread rates in hashes
+//! per second:
+//!
+//! ```text
+//! puzzle-bench 1
+//! threads 8
+//! rate sha256 17100000 121000000
+//! rate blake3 ...
+//! ```
+//!
+//! The rates are measured over a short message that fits in one block of each hash;
+//! puzzles over longer data hash more slowly.
+
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::{Algorithm, Calibration, Difficulty, Puzzle, Target, WorkEstimate};
+
+/// The first line of every benchmark file.
+const HEADER: &str = "puzzle-bench 1";
+
+/// The reasons a benchmark cannot be loaded.
+#[derive(Debug)]
+pub enum BenchmarkError {
+    /// The file could not be read or written.
+    Io(io::Error),
+    /// The line with this number (starting at 1) is not a valid benchmark field, or a
+    /// field is missing.
+    Malformed(usize),
+}
+
+impl fmt::Display for BenchmarkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BenchmarkError::Io(e) => write!(f, "benchmark I/O failed: {}", e),
+            BenchmarkError::Malformed(line) => {
+                write!(f, "benchmark is malformed at line {}", line)
+            }
+        }
+    }
+}
+
+impl Error for BenchmarkError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            BenchmarkError::Io(e) => Some(e),
+            BenchmarkError::Malformed(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for BenchmarkError {
+    fn from(e: io::Error) -> Self {
+        BenchmarkError::Io(e)
+    }
+}
+
+/// The measured hash rates of one algorithm, in hashes per second.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HashRates {
+    /// The algorithm measured.
+    pub algorithm: Algorithm,
+    /// The rate on a single thread.
+    pub single_thread: f64,
+    /// The rate on all of the benchmark's threads together.
+    pub all_threads: f64,
+}
+
+impl HashRates {
+    /// How many times faster all threads hash than one.
+    pub fn speedup(&self) -> f64 {
+        self.all_threads / self.single_thread
+    }
+}
+
+/// The hash rates of this machine; see the [module documentation](self).
+#[derive(Clone, Debug, PartialEq)]
+pub struct Benchmark {
+    /// The number of threads the all-thread rates were measured with.
+    pub threads: usize,
+    /// The rates of each algorithm measured.
+    pub rates: Vec<HashRates>,
+}
+
+impl Benchmark {
+    /// Measures every algorithm for `duration` on one thread and again on `num_threads`
+    /// threads, so each algorithm takes twice `duration`. A thread count of zero is
+    /// treated as one.
+    pub fn run(num_threads: usize, duration: Duration) -> Self {
+        Benchmark::run_algorithms(&Algorithm::ALL, num_threads, duration)
+    }
+
+    /// Like [`run`](Benchmark::run), but only measures `algorithms`.
+    pub fn run_algorithms(
+        algorithms: &[Algorithm],
+        num_threads: usize,
+        duration: Duration,
+    ) -> Self {
+        let threads = num_threads.max(1);
+        let puzzle = Puzzle::new("benchmark", Difficulty::Target(Target::MAX));
+        let rates = algorithms
+            .iter()
+            .map(|&algorithm| HashRates {
+                algorithm,
+                single_thread: algorithm.measure_hash_rate(&puzzle, 1, duration),
+                all_threads: algorithm.measure_hash_rate(&puzzle, threads, duration),
+            })
+            .collect();
+        Benchmark { threads, rates }
+    }
+
+    /// The rates measured for `algorithm`, if it was benchmarked.
+    pub fn rates(&self, algorithm: Algorithm) -> Option<&HashRates> {
+        self.rates.iter().find(|rates| rates.algorithm == algorithm)
+    }
+
+    /// The hash rate of `algorithm` on `num_threads` threads, in hashes per second, if
+    /// it was benchmarked.
+    ///
+    /// Thread counts between one and the benchmark's are interpolated between the two
+    /// measured rates; more threads than the benchmark's are taken to hash no faster.
+    pub fn hash_rate(&self, algorithm: Algorithm, num_threads: usize) -> Option<f64> {
+        let rates = self.rates(algorithm)?;
+        let num_threads = num_threads.max(1);
+        if num_threads >= self.threads {
+            return Some(rates.all_threads);
+        }
+        let share = (num_threads - 1) as f64 / (self.threads - 1) as f64;
+        Some(rates.single_thread + (rates.all_threads - rates.single_thread) * share)
+    }
+
+    /// Picks the target `algorithm` meets in `solve_time` on average on `num_threads`
+    /// threads, like [`calibrate_with`](crate::calibrate_with) but from the benchmarked
+    /// rate, or `None` if the algorithm was not benchmarked.
+    pub fn calibrate(
+        &self,
+        algorithm: Algorithm,
+        num_threads: usize,
+        solve_time: Duration,
+    ) -> Option<Calibration> {
+        let hash_rate = self.hash_rate(algorithm, num_threads)?;
+        let target = Target::from_expected_attempts(hash_rate * solve_time.as_secs_f64());
+        Some(Calibration { hash_rate, target })
+    }
+
+    /// The work and solve-time distribution of `target` for `algorithm` on `num_threads`
+    /// threads, or `None` if the algorithm was not benchmarked.
+    pub fn estimate(
+        &self,
+        algorithm: Algorithm,
+        target: Target,
+        num_threads: usize,
+    ) -> Option<WorkEstimate> {
+        Some(WorkEstimate::new(target, self.hash_rate(algorithm, num_threads)?))
+    }
+
+    /// Reads a benchmark from the file at `path`.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, BenchmarkError> {
+        fs::read_to_string(path)?.parse()
+    }
+
+    /// Writes the benchmark to the file at `path`.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), BenchmarkError> {
+        fs::write(path, self.to_string())?;
+        Ok(())
+    }
+}
+
+impl fmt::Display for Benchmark {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", HEADER)?;
+        writeln!(f, "threads {}", self.threads)?;
+        for rates in &self.rates {
+            writeln!(
+                f,
+                "rate {} {:.0} {:.0}",
+                rates.algorithm, rates.single_thread, rates.all_threads
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Benchmark {
+    type Err = BenchmarkError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = s
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty());
+        match lines.next() {
+            Some((_, HEADER)) => {}
+            Some((number, _)) => return Err(BenchmarkError::Malformed(number)),
+            None => return Err(BenchmarkError::Malformed(1)),
+        }
+        let threads = match lines.next() {
+            Some((number, line)) => line
+                .strip_prefix("threads ")
+                .and_then(|threads| threads.parse().ok())
+                .filter(|&threads| threads > 0)
+                .ok_or(BenchmarkError::Malformed(number))?,
+            None => return Err(BenchmarkError::Malformed(2)),
+        };
+        let rates = lines
+            .map(|(number, line)| parse_rates(line).ok_or(BenchmarkError::Malformed(number)))
+            .collect::<Result<_, _>>()?;
+        Ok(Benchmark { threads, rates })
+    }
+}
+
+/// Parses a line written as `rate <algorithm> <single-thread> <all-threads>`.
+fn parse_rates(line: &str) -> Option<HashRates> {
+    let mut fields = line.strip_prefix("rate ")?.split(' ');
+    let algorithm = fields.next()?.parse().ok()?;
+    let mut rate = || fields.next()?.parse::<f64>().ok().filter(|rate| rate.is_finite());
+    let (single_thread, all_threads) = (rate()?, rate()?);
+    fields.next().is_none().then_some(HashRates { algorithm, single_thread, all_threads })
+}
@@ -0,0 +1,144 @@
+//! A toy blockchain: a sequence of puzzles where each one commits to the last.
+//!
+//! Every [`Block`] is a puzzle over a small header holding its height, the previous
+//! block's hash, a timestamp, its target and its data. Because the previous hash is part
+//! of the header, a block can only be mined once the block before it is known, and
+//! changing any block invalidates every block after it. Mining a [`Chain`] therefore runs
+//! the parallel miner on many consecutive jobs, which makes it a handy teaching aid and
+//! a stress test. With [`Chain::next_target`], the difficulty follows the observed block
+//! times; see the [`retarget`](crate::retarget) module.
+
+use crate::{unix_now_millis, Algorithm, MerkleTree, Puzzle, PuzzleError, Retarget, Target};
+
+/// One mined block.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Block {
+    /// Position in the chain, starting at zero for the genesis block.
+    pub height: u64,
+    /// The hash of the previous block, or all zeroes for the genesis block.
+    pub prev_hash: Vec<u8>,
+    /// When mining of the block started, in milliseconds since the Unix epoch.
+    pub timestamp: u64,
+    /// The target the block's hash had to meet.
+    pub target: Target,
+    /// The block's payload.
+    pub data: Vec<u8>,
+    /// The nonce that solved the block.
+    pub nonce: u64,
+    /// The block's hash, which the next block commits to.
+    pub hash: Vec<u8>,
+}
+
+impl Block {
+    /// The bytes the nonce is appended to:
+    /// `height || prev_hash || timestamp || target || data`, with the numbers as 8
+    /// big-endian bytes and the target as 32.
+    pub fn header(&self) -> Vec<u8> {
+        [
+            &self.height.to_be_bytes()[..],
+            &self.prev_hash,
+            &self.timestamp.to_be_bytes(),
+            &self.target.to_be_bytes(),
+            &self.data,
+        ]
+        .concat()
+    }
+
+    /// The puzzle this block solves.
+    pub fn puzzle(&self) -> Puzzle {
+        Puzzle::new(self.header(), self.target)
+    }
+}
+
+/// A chain of blocks mined with one hash algorithm.
+#[derive(Clone, Debug)]
+pub struct Chain {
+    algorithm: Algorithm,
+    blocks: Vec<Block>,
+}
+
+impl Chain {
+    /// Creates an empty chain whose blocks are hashed with `algorithm`.
+    pub fn new(algorithm: Algorithm) -> Self {
+        Chain { algorithm, blocks: Vec::new() }
+    }
+
+    /// The hash algorithm of the chain.
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    /// The blocks mined so far, genesis first.
+    pub fn blocks(&self) -> &[Block] {
+        &self.blocks
+    }
+
+    /// The most recent block, if any.
+    pub fn tip(&self) -> Option<&Block> {
+        self.blocks.last()
+    }
+
+    /// The target for the next block under `retarget`, or `initial` for the genesis
+    /// block. See [`Retarget::next_target`].
+    pub fn next_target(&self, retarget: &Retarget, initial: Target) -> Target {
+        retarget.next_target(&self.blocks, unix_now_millis()).unwrap_or(initial)
+    }
+
+    /// Mines a block carrying `data` on top of the current tip, using `num_threads`
+    /// threads, and appends it.
+    ///
+    /// # Returns
+    ///
+    /// The new block, or a [`PuzzleError`] if no nonce met `target`; the chain is then
+    /// unchanged.
+    pub fn mine_block(
+        &mut self,
+        data: impl Into<Vec<u8>>,
+        target: Target,
+        num_threads: usize,
+    ) -> Result<&Block, PuzzleError> {
+        let (height, prev_hash) = match self.tip() {
+            Some(tip) => (tip.height + 1, tip.hash.clone()),
+            None => (0, vec![0; self.algorithm.output_len()]),
+        };
+        let mut block = Block {
+            height,
+            prev_hash,
+            timestamp: unix_now_millis(),
+            target,
+            data: data.into(),
+            nonce: 0,
+            hash: Vec::new(),
+        };
+        let solution = self.algorithm.parallel_mine(&block.puzzle(), num_threads)?;
+        block.nonce = solution.nonce;
+        block.hash = solution.hash;
+        self.blocks.push(block);
+        Ok(self.blocks.last().expect("a block was just pushed"))
+    }
+
+    /// Mines a block whose data is the [`MerkleTree`] root of `transactions`, so the
+    /// block commits to all of them; see [`mine_block`](Chain::mine_block).
+    pub fn mine_transactions<T: AsRef<[u8]>>(
+        &mut self,
+        transactions: &[T],
+        target: Target,
+        num_threads: usize,
+    ) -> Result<&Block, PuzzleError> {
+        self.mine_block(MerkleTree::new(transactions).root(), target, num_threads)
+    }
+
+    /// Whether every block links to the one before it, carries the right height and
+    /// hash, and solves its puzzle.
+    pub fn verify(&self) -> bool {
+        let genesis_prev = vec![0; self.algorithm.output_len()];
+        self.blocks.iter().enumerate().all(|(i, block)| {
+            let prev_hash = if i == 0 { &genesis_prev } else { &self.blocks[i - 1].hash };
+            let verification = self.algorithm.verify(&block.puzzle(), block.nonce);
+            block.height == i as u64
+                && block.prev_hash == *prev_hash
+                && verification.valid
+                && verification.hash == block.hash
+        })
+    }
+}
@@ -0,0 +1,270 @@
+//! Saving the progress of a search so it can be continued later.
+//!
+//! A [`MiningHandle`](crate::MiningHandle) can take a [`Checkpoint`] at any time: which
+//! puzzle is being mined, the current extra nonce, and the ranges of the window not tried
+//! yet, i.e. the rest of each worker's chunk and everything no worker has taken. Written
+//! to a file every few seconds, it lets a long search survive a crash or a reboot, and
+//! [`parallel_mine_resume_with`](crate::parallel_mine_resume_with) picks up where the
+//! last checkpoint left off.
+//!
+//! The file is plain text, one field per line:
+//!
+//! ```text
+//! puzzle-checkpoint 1
+//! puzzle 3f0c...e1
+//! window 0 18446744073709551615
+//! threads 8
+//! extra-nonce none
+//! range 1048576 2305843009213693950
+//! ...
+//! ```
+//!
+//! `puzzle` is a SHA-256 fingerprint of the puzzle and hash function, so a checkpoint is
+//! never applied to a different search by mistake. A search in a shuffled
+//! [`SearchOrder`] has an `order shuffled <seed>` line after `extra-nonce`, or `order
+//! restarts <seed> <run>` with restarts, and each `range` then counts positions in the
+//! walk of the window rather than nonces.
+
+use std::error::Error;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::str::FromStr;
+
+use sha2::Sha256;
+
+use crate::digest::Digest;
+use crate::{hex, NonceEncoding, Puzzle, SearchOrder};
+
+/// The first line of every checkpoint file.
+const HEADER: &str = "puzzle-checkpoint 1";
+
+/// The reasons a checkpoint cannot be loaded or applied.
+#[derive(Debug)]
+pub enum CheckpointError {
+    /// The file could not be read or written.
+    Io(io::Error),
+    /// The line with this number (starting at 1) is not a valid checkpoint field, or a
+    /// field is missing.
+    Malformed(usize),
+    /// The checkpoint belongs to a different puzzle or hash function.
+    Mismatch,
+}
+
+impl fmt::Display for CheckpointError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckpointError::Io(e) => write!(f, "checkpoint I/O failed: {}", e),
+            CheckpointError::Malformed(line) => {
+                write!(f, "checkpoint is malformed at line {}", line)
+            }
+            CheckpointError::Mismatch => {
+                f.write_str("checkpoint was taken for a different puzzle or hash function")
+            }
+        }
+    }
+}
+
+impl Error for CheckpointError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CheckpointError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for CheckpointError {
+    fn from(e: io::Error) -> Self {
+        CheckpointError::Io(e)
+    }
+}
+
+/// The state of a search at one moment, enough to continue it without repeating or
+/// skipping work.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Checkpoint {
+    /// Fingerprint of the puzzle and hash function; see [`Checkpoint::matches`].
+    pub puzzle: [u8; 32],
+    /// The whole nonce window, searched again for every new extra nonce.
+    pub window: RangeInclusive<u64>,
+    /// How many threads search the window.
+    pub threads: usize,
+    /// The extra nonce being searched, if the puzzle uses one.
+    pub extra_nonce: Option<u64>,
+    /// The order in which the window is searched.
+    pub order: SearchOrder,
+    /// The positions in the walk through the window not yet tried under the current extra
+    /// nonce, lowest first.
+    pub ranges: Vec<RangeInclusive<u64>>,
+}
+
+impl Checkpoint {
+    /// The checkpoint of a search of `puzzle` with the hash function `D` that has not
+    /// started yet: `nonces` searched by `num_threads` threads in `order`.
+    pub(crate) fn new<D: Digest>(
+        puzzle: &Puzzle,
+        nonces: RangeInclusive<u64>,
+        num_threads: usize,
+        order: SearchOrder,
+    ) -> Self {
+        Checkpoint {
+            puzzle: fingerprint::<D>(puzzle),
+            ranges: vec![nonces.clone()],
+            window: nonces,
+            threads: num_threads,
+            extra_nonce: puzzle.extra_nonce,
+            order,
+        }
+    }
+
+    /// Whether the checkpoint was taken while mining `puzzle` with the hash function `D`.
+    ///
+    /// The data, target, nonce format and whether an extra nonce is used must all be the
+    /// same; the value of the extra nonce is taken from the checkpoint.
+    pub fn matches<D: Digest>(&self, puzzle: &Puzzle) -> bool {
+        self.puzzle == fingerprint::<D>(puzzle)
+    }
+
+    /// How many nonces are left under the current extra nonce.
+    pub fn remaining(&self) -> u128 {
+        self.ranges
+            .iter()
+            .map(|range| u128::from(range.end() - range.start()) + 1)
+            .sum()
+    }
+
+    /// Reads a checkpoint from the file at `path`.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, CheckpointError> {
+        fs::read_to_string(path)?.parse()
+    }
+
+    /// Writes the checkpoint to the file at `path`.
+    ///
+    /// The checkpoint is written to a temporary file next to `path` first and then
+    /// renamed over it, so a crash mid-write never leaves a truncated checkpoint behind.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), CheckpointError> {
+        let path = path.as_ref();
+        let mut temporary = OsString::from(path.as_os_str());
+        temporary.push(".tmp");
+        fs::write(&temporary, self.to_string())?;
+        fs::rename(&temporary, path)?;
+        Ok(())
+    }
+}
+
+impl fmt::Display for Checkpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", HEADER)?;
+        writeln!(f, "puzzle {}", hex::encode(&self.puzzle))?;
+        writeln!(f, "window {} {}", self.window.start(), self.window.end())?;
+        writeln!(f, "threads {}", self.threads)?;
+        match self.extra_nonce {
+            Some(extra_nonce) => writeln!(f, "extra-nonce {}", extra_nonce)?,
+            None => writeln!(f, "extra-nonce none")?,
+        }
+        if self.order != SearchOrder::Sequential {
+            writeln!(f, "order {}", self.order)?;
+        }
+        for range in &self.ranges {
+            writeln!(f, "range {} {}", range.start(), range.end())?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Checkpoint {
+    type Err = CheckpointError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lines: Vec<(usize, &str)> = s
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty())
+            .collect();
+        match lines.first() {
+            Some(&(_, HEADER)) => {}
+            Some(&(number, _)) => return Err(CheckpointError::Malformed(number)),
+            None => return Err(CheckpointError::Malformed(1)),
+        }
+
+        let (number, puzzle) = field(&lines, 1, "puzzle")?;
+        let puzzle = hex::decode(puzzle)
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or(CheckpointError::Malformed(number))?;
+        let (number, window) = field(&lines, 2, "window")?;
+        let window = parse_range(window).ok_or(CheckpointError::Malformed(number))?;
+        let (number, threads) = field(&lines, 3, "threads")?;
+        let threads = threads
+            .parse()
+            .ok()
+            .filter(|&threads| threads > 0)
+            .ok_or(CheckpointError::Malformed(number))?;
+        let (number, extra_nonce) = field(&lines, 4, "extra-nonce")?;
+        let extra_nonce = match extra_nonce {
+            "none" => None,
+            value => Some(value.parse().map_err(|_| CheckpointError::Malformed(number))?),
+        };
+        // The order is only written for searches that do not count up.
+        let (order, first_range) = match field(&lines, 5, "order") {
+            Ok((number, order)) => {
+                (order.parse().map_err(|_| CheckpointError::Malformed(number))?, 6)
+            }
+            Err(_) => (SearchOrder::Sequential, 5),
+        };
+        let ranges = (first_range..lines.len())
+            .map(|index| {
+                let (number, range) = field(&lines, index, "range")?;
+                parse_range(range).ok_or(CheckpointError::Malformed(number))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Checkpoint { puzzle, window, threads, extra_nonce, order, ranges })
+    }
+}
+
+/// The value of the `index`-th non-empty line, which must be the field `name`, with the
+/// line's number.
+fn field<'a>(
+    lines: &[(usize, &'a str)],
+    index: usize,
+    name: &str,
+) -> Result<(usize, &'a str), CheckpointError> {
+    match lines.get(index) {
+        Some(&(number, line)) => match line.split_once(' ') {
+            Some((key, value)) if key == name => Ok((number, value)),
+            _ => Err(CheckpointError::Malformed(number)),
+        },
+        None => Err(CheckpointError::Malformed(lines.last().map_or(1, |&(number, _)| number + 1))),
+    }
+}
+
+/// Parses a non-empty range written as `start end`.
+fn parse_range(s: &str) -> Option<RangeInclusive<u64>> {
+    let (start, end) = s.split_once(' ')?;
+    let (start, end) = (start.parse().ok()?, end.parse().ok()?);
+    (start <= end).then_some(start..=end)
+}
+
+/// SHA-256 over the name of `D` and everything about `puzzle` that decides which hash
+/// each nonce produces, except the value of the extra nonce.
+fn fingerprint<D: Digest>(puzzle: &Puzzle) -> [u8; 32] {
+    let encoding: u8 = match puzzle.nonce_format.encoding {
+        NonceEncoding::BigEndian => 0,
+        NonceEncoding::LittleEndian => 1,
+        NonceEncoding::Ascii => 2,
+    };
+    let offset = puzzle.nonce_format.offset.map_or(u64::MAX, |offset| offset as u64);
+    Sha256::new()
+        .chain_update(std::any::type_name::<D>())
+        .chain_update([0, encoding, u8::from(puzzle.extra_nonce.is_some())])
+        .chain_update(offset.to_be_bytes())
+        .chain_update(puzzle.target.to_be_bytes())
+        .chain_update(&puzzle.data)
+        .finalize()
+        .into()
+}
@@ -0,0 +1,438 @@
+//! Stateless client puzzles for protecting a server against denial of service.
+//!
+//! A server hands each client a [`Challenge`] before doing any expensive work for it and
+//! only serves the request once the client returns a nonce that solves it. The server
+//! keeps no per-client state: a challenge carries the client's IP address, the time it
+//! was issued, its difficulty and a random salt, and an HMAC-SHA256 tag over all of them
+//! under a key only the [`ChallengeIssuer`] knows. A returned challenge whose tag still
+//! matches is one the server issued, unchanged.
+//!
+//! Solving a challenge is an ordinary puzzle over its string form with a number of
+//! leading zero bits, so the client runs the parallel miner; checking a solution costs
+//! the server two hashes for the tag and one for the nonce.
+//!
+//! On its own a challenge can be solved once and submitted again until it expires. To
+//! refuse repeats, check solutions with [`ChallengeIssuer::verify_once`], which records
+//! accepted ones in a [`SeenSolutions`] set.
+
+use std::error::Error;
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::time::Duration;
+
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    hex, parallel_mine_with_threads, unix_now, validate, Difficulty, Puzzle, PuzzleError,
+    SeenSolutions,
+};
+
+/// Domain separation for the challenge tag.
+const MAC_DOMAIN: &[u8] = b"client-puzzle-v1";
+
+/// The block size of SHA-256, which sets the HMAC padding length.
+const SHA256_BLOCK_LEN: usize = 64;
+
+/// Length of the random salt that keeps challenges issued in the same second distinct.
+const SALT_LEN: usize = 16;
+
+/// The reasons a challenge cannot be parsed or a solution is rejected.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ChallengeError {
+    /// The challenge string does not have the five `:`-separated fields, or one of them
+    /// does not parse.
+    Malformed,
+    /// The tag does not match: the challenge was not issued with this key, or was altered.
+    Forged,
+    /// The challenge was issued to a different client.
+    WrongClient,
+    /// The challenge is older than the issuer's time to live.
+    Expired,
+    /// The nonce does not solve the challenge.
+    Unsolved,
+    /// The solution was already accepted once.
+    Replayed,
+}
+
+impl fmt::Display for ChallengeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChallengeError::Malformed => f.write_str("challenge is malformed"),
+            ChallengeError::Forged => f.write_str("challenge was not issued by this server"),
+            ChallengeError::WrongClient => f.write_str("challenge was issued to another client"),
+            ChallengeError::Expired => f.write_str("challenge has expired"),
+            ChallengeError::Unsolved => f.write_str("nonce does not solve the challenge"),
+            ChallengeError::Replayed => f.write_str("solution was already used"),
+        }
+    }
+}
+
+impl Error for ChallengeError {}
+
+/// A challenge issued to one client.
+///
+/// It is written as `bits:issued_at:salt:tag:client`, with the salt and tag in hex, so
+/// it can be sent to the client and back as a single string.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Challenge {
+    /// The number of leading zero bits a solution's SHA-256 hash needs.
+    pub bits: u32,
+    /// When the challenge was issued, in seconds since the Unix epoch.
+    pub issued_at: u64,
+    /// Random salt that keeps challenges for the same client and second distinct.
+    pub salt: [u8; SALT_LEN],
+    /// HMAC-SHA256 of the other fields under the issuer's key.
+    pub tag: [u8; 32],
+    /// The address of the client the challenge was issued to.
+    pub client: IpAddr,
+}
+
+impl Challenge {
+    /// The puzzle a solution has to solve: the challenge string with an 8-byte
+    /// big-endian nonce after it.
+    pub fn puzzle(&self) -> Puzzle {
+        Puzzle::new(self.to_string(), Difficulty::LeadingZeroBits(self.bits))
+    }
+
+    /// Finds a nonce that solves the challenge on `num_threads` threads.
+    ///
+    /// # Returns
+    ///
+    /// The nonce, or a [`PuzzleError`] if the nonce range was exhausted first.
+    pub fn solve(&self, num_threads: usize) -> Result<u64, PuzzleError> {
+        parallel_mine_with_threads(&self.puzzle(), num_threads).map(|solution| solution.nonce)
+    }
+
+    /// Whether `nonce` solves the challenge. This does not check the tag.
+    pub fn is_solved_by(&self, nonce: u64) -> bool {
+        validate(&self.puzzle(), nonce)
+    }
+}
+
+impl fmt::Display for Challenge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}:{}:{}",
+            self.bits,
+            self.issued_at,
+            hex::encode(&self.salt),
+            hex::encode(&self.tag),
+            self.client
+        )
+    }
+}
+
+impl FromStr for Challenge {
+    type Err = ChallengeError;
+
+    /// Parses a challenge. The client comes last because IPv6 addresses contain `:`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = s.trim().splitn(5, ':').collect();
+        let [bits, issued_at, salt, tag, client] = fields[..] else {
+            return Err(ChallengeError::Malformed);
+        };
+        Ok(Challenge {
+            bits: bits.parse().map_err(|_| ChallengeError::Malformed)?,
+            issued_at: issued_at.parse().map_err(|_| ChallengeError::Malformed)?,
+            salt: decode_array(salt)?,
+            tag: decode_array(tag)?,
+            client: client.parse().map_err(|_| ChallengeError::Malformed)?,
+        })
+    }
+}
+
+/// Issues challenges and checks their solutions.
+#[derive(Clone)]
+pub struct ChallengeIssuer {
+    key: Vec<u8>,
+    bits: u32,
+    ttl: Duration,
+}
+
+impl fmt::Debug for ChallengeIssuer {
+    /// Formats the issuer without its key.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ChallengeIssuer")
+            .field("bits", &self.bits)
+            .field("ttl", &self.ttl)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ChallengeIssuer {
+    /// Creates an issuer that signs challenges with `key`, asks for `bits` leading zero
+    /// bits and accepts solutions for `ttl` after a challenge is issued.
+    ///
+    /// The key should be at least 32 random bytes and stay on the server.
+    pub fn new(key: impl Into<Vec<u8>>, bits: u32, ttl: Duration) -> Self {
+        ChallengeIssuer { key: key.into(), bits, ttl }
+    }
+
+    /// The number of leading zero bits new challenges ask for.
+    pub fn bits(&self) -> u32 {
+        self.bits
+    }
+
+    /// How long a challenge stays valid after it is issued.
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// Issues a challenge to `client`, dated now.
+    pub fn issue(&self, client: IpAddr) -> Challenge {
+        self.issue_at(client, unix_now())
+    }
+
+    /// Issues a challenge to `client`, dated `issued_at` seconds since the Unix epoch.
+    pub fn issue_at(&self, client: IpAddr, issued_at: u64) -> Challenge {
+        let mut salt = [0u8; SALT_LEN];
+        rand::thread_rng().fill_bytes(&mut salt);
+        let tag = self.tag(self.bits, issued_at, &salt, client);
+        Challenge { bits: self.bits, issued_at, salt, tag, client }
+    }
+
+    /// Checks that `challenge` was issued by this issuer to `client`, has not expired
+    /// and is solved by `nonce`.
+    pub fn verify(
+        &self,
+        client: IpAddr,
+        challenge: &Challenge,
+        nonce: u64,
+    ) -> Result<(), ChallengeError> {
+        self.verify_at(client, challenge, nonce, unix_now())
+    }
+
+    /// Like [`verify`](ChallengeIssuer::verify), with the current time given as `now`
+    /// seconds since the Unix epoch.
+    ///
+    /// # Details
+    ///
+    /// The tag is checked first, so the client and issue time compared afterwards are
+    /// known to be the ones the issuer signed.
+    pub fn verify_at(
+        &self,
+        client: IpAddr,
+        challenge: &Challenge,
+        nonce: u64,
+        now: u64,
+    ) -> Result<(), ChallengeError> {
+        let tag = self.tag(challenge.bits, challenge.issued_at, &challenge.salt, challenge.client);
+        if !constant_time_eq(&tag, &challenge.tag) {
+            return Err(ChallengeError::Forged);
+        }
+        if challenge.client != client {
+            return Err(ChallengeError::WrongClient);
+        }
+        if now.saturating_sub(challenge.issued_at) > self.ttl.as_secs() {
+            return Err(ChallengeError::Expired);
+        }
+        if !challenge.is_solved_by(nonce) {
+            return Err(ChallengeError::Unsolved);
+        }
+        Ok(())
+    }
+
+    /// Like [`verify`](ChallengeIssuer::verify), but also refuses solutions already
+    /// recorded in `seen` and records the ones it accepts.
+    ///
+    /// `seen` should have room for every solution accepted within one time to live;
+    /// see the [`replay`](crate::replay) module.
+    pub fn verify_once(
+        &self,
+        seen: &mut SeenSolutions,
+        client: IpAddr,
+        challenge: &Challenge,
+        nonce: u64,
+    ) -> Result<(), ChallengeError> {
+        self.verify_once_at(seen, client, challenge, nonce, unix_now())
+    }
+
+    /// Like [`verify_once`](ChallengeIssuer::verify_once), with the current time given
+    /// as `now` seconds since the Unix epoch.
+    pub fn verify_once_at(
+        &self,
+        seen: &mut SeenSolutions,
+        client: IpAddr,
+        challenge: &Challenge,
+        nonce: u64,
+        now: u64,
+    ) -> Result<(), ChallengeError> {
+        self.verify_at(client, challenge, nonce, now)?;
+        if !seen.insert(&challenge.puzzle(), nonce) {
+            return Err(ChallengeError::Replayed);
+        }
+        Ok(())
+    }
+
+    /// The HMAC-SHA256 tag over a challenge's fields.
+    fn tag(&self, bits: u32, issued_at: u64, salt: &[u8], client: IpAddr) -> [u8; 32] {
+        let client = match client {
+            IpAddr::V4(v4) => [&[4][..], &v4.octets()].concat(),
+            IpAddr::V6(v6) => [&[6][..], &v6.octets()].concat(),
+        };
+        let message =
+            [MAC_DOMAIN, &bits.to_be_bytes(), &issued_at.to_be_bytes(), salt, &client].concat();
+        hmac_sha256(&self.key, &message)
+    }
+}
+
+/// HMAC-SHA256 as specified in RFC 2104.
+pub(crate) fn hmac_sha256(key: &[u8], message: &[u8]) -> [u8; 32] {
+    let mut block = [0u8; SHA256_BLOCK_LEN];
+    if key.len() > SHA256_BLOCK_LEN {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let inner_pad = block.map(|b| b ^ 0x36);
+    let outer_pad = block.map(|b| b ^ 0x5c);
+    let inner = Sha256::new().chain_update(inner_pad).chain_update(message).finalize();
+    Sha256::new().chain_update(outer_pad).chain_update(inner).finalize().into()
+}
+
+/// Compares two tags without stopping at the first difference, so the time taken does
+/// not reveal how much of a guessed tag was right.
+fn constant_time_eq(a: &[u8; 32], b: &[u8; 32]) -> bool {
+    a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// Decodes a hex field of exactly `N` bytes.
+fn decode_array<const N: usize>(s: &str) -> Result<[u8; N], ChallengeError> {
+    hex::decode(s)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or(ChallengeError::Malformed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &[u8] = b"a key of thirty-two random bytes";
+
+    fn issuer() -> ChallengeIssuer {
+        ChallengeIssuer::new(KEY, 8, Duration::from_secs(60))
+    }
+
+    fn client() -> IpAddr {
+        "192.0.2.7".parse().unwrap()
+    }
+
+    #[test]
+    fn hmac_matches_rfc_4231() {
+        let block_key = [0xaa; 131];
+        let vectors: [(&[u8], &[u8], &str); 6] = [
+            (
+                &[0x0b; 20],
+                b"Hi There",
+                "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7",
+            ),
+            (
+                b"Jefe",
+                b"what do ya want for nothing?",
+                "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+            ),
+            (
+                &[0xaa; 20],
+                &[0xdd; 50],
+                "773ea91e36800e46854db8ebd09181a72959098b3ef8c122d9635514ced565fe",
+            ),
+            (
+                &[
+                    1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22,
+                    23, 24, 25,
+                ],
+                &[0xcd; 50],
+                "82558a389a443c0ea4cc819899f2083a85f0faa3e578f8077a2e3ff46729665b",
+            ),
+            (
+                &block_key,
+                b"Test Using Larger Than Block-Size Key - Hash Key First",
+                "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54",
+            ),
+            (
+                &block_key,
+                b"This is a test using a larger than block-size key and a larger than \
+                  block-size data. The key needs to be hashed before being used by the HMAC \
+                  algorithm.",
+                "9b09ffa71b942fcb27635fbcd5b0e944bfdc63644f0713938a7f51535c3a35e2",
+            ),
+        ];
+        for (key, message, expected) in vectors {
+            assert_eq!(hex::encode(&hmac_sha256(key, message)), expected);
+        }
+        // Test case 5 checks only the first 128 bits.
+        let truncated = hmac_sha256(&[0x0c; 20], b"Test With Truncation");
+        assert_eq!(hex::encode(&truncated[..16]), "a3b6167473100ee06e0c796c2955552b");
+    }
+
+    #[test]
+    fn solved_challenges_verify() {
+        let issuer = issuer();
+        let challenge = issuer.issue_at(client(), 1_000);
+        let nonce = challenge.solve(2).unwrap();
+        assert_eq!(issuer.verify_at(client(), &challenge, nonce, 1_000), Ok(()));
+        assert_eq!(issuer.verify_at(client(), &challenge, nonce, 1_060), Ok(()));
+
+        let parsed: Challenge = challenge.to_string().parse().unwrap();
+        assert_eq!(parsed, challenge);
+        assert_eq!(issuer.verify_at(client(), &parsed, nonce, 1_000), Ok(()));
+
+        let unsolved = (0..).find(|&nonce| !challenge.is_solved_by(nonce)).unwrap();
+        let result = issuer.verify_at(client(), &challenge, unsolved, 1_000);
+        assert_eq!(result, Err(ChallengeError::Unsolved));
+    }
+
+    #[test]
+    fn altered_or_foreign_challenges_are_forged() {
+        let issuer = issuer();
+        let challenge = issuer.issue_at(client(), 1_000);
+        let nonce = challenge.solve(2).unwrap();
+        let altered = [
+            Challenge { bits: 0, ..challenge.clone() },
+            Challenge { issued_at: 2_000, ..challenge.clone() },
+            Challenge { salt: [0; SALT_LEN], ..challenge.clone() },
+            Challenge { tag: [0; 32], ..challenge.clone() },
+            Challenge { client: "192.0.2.8".parse().unwrap(), ..challenge.clone() },
+        ];
+        for forged in &altered {
+            let result = issuer.verify_at(forged.client, forged, nonce, 1_000);
+            assert_eq!(result, Err(ChallengeError::Forged), "{}", forged);
+        }
+        let other = ChallengeIssuer::new(b"another key".to_vec(), 8, Duration::from_secs(60));
+        let result = other.verify_at(client(), &challenge, nonce, 1_000);
+        assert_eq!(result, Err(ChallengeError::Forged));
+    }
+
+    #[test]
+    fn challenges_are_bound_to_their_client_and_lifetime() {
+        let issuer = issuer();
+        let challenge = issuer.issue_at(client(), 1_000);
+        let nonce = challenge.solve(2).unwrap();
+        let elsewhere = "2001:db8::7".parse().unwrap();
+        let result = issuer.verify_at(elsewhere, &challenge, nonce, 1_000);
+        assert_eq!(result, Err(ChallengeError::WrongClient));
+        let result = issuer.verify_at(client(), &challenge, nonce, 1_061);
+        assert_eq!(result, Err(ChallengeError::Expired));
+    }
+
+    #[test]
+    fn solutions_are_accepted_once() {
+        let issuer = issuer();
+        let mut seen = SeenSolutions::new(16);
+        let challenge = issuer.issue_at(client(), 1_000);
+        let nonce = challenge.solve(2).unwrap();
+        let result = issuer.verify_once_at(&mut seen, client(), &challenge, nonce, 1_000);
+        assert_eq!(result, Ok(()));
+        let result = issuer.verify_once_at(&mut seen, client(), &challenge, nonce, 1_001);
+        assert_eq!(result, Err(ChallengeError::Replayed));
+
+        // A fresh challenge to the same client is a different puzzle.
+        let fresh = issuer.issue_at(client(), 1_000);
+        let nonce = fresh.solve(2).unwrap();
+        assert_eq!(issuer.verify_once_at(&mut seen, client(), &fresh, nonce, 1_000), Ok(()));
+    }
+}
@@ -0,0 +1,1198 @@
+//! Mining in the background with a handle to pause, resume or cancel the search.
+//!
+//! [`parallel_mine_spawn`] starts the same search as [`parallel_mine`](crate::parallel_mine)
+//! but returns at once with a [`MiningHandle`]. Pausing parks every worker thread on a
+//! condition variable where it stands, so the machine is free for other work; resuming
+//! wakes them and each carries on from the exact nonce it stopped at, so no part of the
+//! window is searched twice or skipped. Cancelling stops the workers for good and the
+//! search ends with [`PuzzleError::Cancelled`]. A search can also be given a time budget
+//! with [`MiningHandle::cancel_after`] or [`parallel_mine_timeout`], after which it ends
+//! with [`PuzzleError::TimedOut`].
+//!
+//! [`MiningHandle::set_max_cpu`] throttles a search instead of stopping it: each worker
+//! rests between stretches of hashing, so it uses only a share of its core's time.
+//! [`MiningHandle::limit_temperature`] throttles it the same way only while the CPU runs
+//! hot; see the [`thermal`](crate::thermal) module.
+//!
+//! [`MiningHandle::on_progress`] reports the number of hashes tried and the hash rate of
+//! every worker at a fixed interval, for progress bars and dashboards, and the
+//! [`MiningReport`] of a finished search adds the energy it consumed where the
+//! processor can tell; see the [`energy`](crate::energy) module.
+//!
+//! A [`CancellationToken`] lets code that does not hold the handle stop a search, or
+//! several at once: clone it, hand a copy to whatever decides when to give up, and pass
+//! it to [`MiningHandle::cancel_on`] or [`parallel_mine_cancellable`].
+//!
+//! [`MiningHandle::checkpoint`] snapshots the nonces still to be tried, and
+//! [`parallel_mine_resume_with`] continues a search from such a [`Checkpoint`], possibly
+//! in a later process; see the [`checkpoint`](crate::checkpoint) module.
+
+use std::ops::RangeInclusive;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError, RwLock, Weak};
+use std::task::Waker;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use sha2::Sha256;
+
+use crate::digest::Digest;
+use crate::observer::{notify_end, Observer};
+use crate::lanes::{LaneSearch, Sha256Lanes};
+use crate::cores;
+use crate::numa;
+use crate::pool::WorkerPool;
+#[cfg(feature = "profile")]
+use crate::profile::{ProfileSlot, ThreadProfile};
+use crate::search::{search_parallel_controlled, HashSearch};
+use crate::{
+    cpu_temperature, default_nonce_range, Checkpoint, CheckpointError, EnergyMeter,
+    PrehashedPuzzle, Puzzle, PuzzleError, SearchOrder, Solution, Target, ThermalLimit,
+};
+
+/// How many nonces a worker tries between updates of its [`Progress`].
+pub(crate) const PROGRESS_INTERVAL: u64 = 1 << 12;
+/// How many positions a worker takes from the shared cursor at a time; a multiple of
+/// [`PROGRESS_INTERVAL`].
+pub(crate) const CHUNK_SIZE: u64 = 1 << 16;
+/// How long a throttled worker hashes and rests in one cycle together, about; see
+/// [`Control::pace`].
+const DUTY_CYCLE: Duration = Duration::from_millis(100);
+/// How often a [`ThermalLimit`] watchdog reads the CPU temperature.
+const THERMAL_POLL: Duration = Duration::from_secs(1);
+
+/// Pause and cancel requests shared between a handle and the worker threads.
+#[derive(Debug, Default)]
+pub(crate) struct Control {
+    /// Set while paused or once cancelled, so workers only take the lock when needed.
+    interrupted: AtomicBool,
+    /// The number of hashes tried so far, over every round of the search.
+    attempts: AtomicU64,
+    /// The percentage of the time each worker may spend hashing, or 0 without a limit.
+    max_cpu: AtomicU8,
+    /// Like `max_cpu`, as a [`ThermalLimit`] watchdog last set it for a hot CPU.
+    thermal_cpu: AtomicU8,
+    state: Mutex<ControlState>,
+    /// Notified whenever the state changes.
+    resumed: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct ControlState {
+    paused: bool,
+    cancelled: bool,
+    /// Set together with `cancelled` when the cancellation came from a deadline.
+    timed_out: bool,
+    /// Set once the search has ended, which releases a deadline watchdog.
+    finished: bool,
+    /// The index of the worker that found the solution, if any.
+    found_by: Option<usize>,
+    /// Each worker's hashes over the rounds that have ended.
+    thread_attempts: Vec<u64>,
+    /// Each worker's time over the rounds that have ended.
+    #[cfg(feature = "profile")]
+    thread_profiles: Vec<ThreadProfile>,
+    /// Set as the thread running the search exits, so joining it no longer blocks.
+    exited: bool,
+    /// Woken once `exited` is set, for a future awaiting the search.
+    waker: Option<Waker>,
+}
+
+impl Control {
+    /// Locks the state. No update of it can be left half done, so the lock is taken
+    /// even if a thread panicked holding it.
+    fn state(&self) -> MutexGuard<'_, ControlState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn pause(&self) {
+        let mut state = self.state();
+        if !state.cancelled {
+            state.paused = true;
+            self.interrupted.store(true, Ordering::Relaxed);
+        }
+    }
+
+    fn resume(&self) {
+        let mut state = self.state();
+        state.paused = false;
+        self.interrupted.store(state.cancelled, Ordering::Relaxed);
+        self.resumed.notify_all();
+    }
+
+    pub(crate) fn cancel(&self) {
+        let mut state = self.state();
+        self.cancel_locked(&mut state);
+    }
+
+    fn cancel_locked(&self, state: &mut ControlState) {
+        state.cancelled = true;
+        state.paused = false;
+        self.interrupted.store(true, Ordering::Relaxed);
+        self.resumed.notify_all();
+    }
+
+    /// Cancels the search at `deadline` from a watchdog thread, unless it is cancelled
+    /// or finishes first, and marks it as timed out.
+    pub(crate) fn cancel_at(self: &Arc<Self>, deadline: Instant) {
+        let control = Arc::clone(self);
+        thread::spawn(move || {
+            let mut state = control.state();
+            while !state.cancelled && !state.finished {
+                let now = Instant::now();
+                if now >= deadline {
+                    state.timed_out = true;
+                    control.cancel_locked(&mut state);
+                    return;
+                }
+                let waited = control.resumed.wait_timeout(state, deadline - now);
+                state = waited.unwrap_or_else(PoisonError::into_inner).0;
+            }
+        });
+    }
+
+    /// Throttles the workers from a watchdog thread while the CPU is hotter than `limit`
+    /// allows, until the search is cancelled or finishes.
+    pub(crate) fn watch_temperature(self: &Arc<Self>, limit: ThermalLimit) {
+        let control = Arc::clone(self);
+        thread::spawn(move || {
+            let mut share = 100;
+            loop {
+                let state = control.state();
+                let (state, _) = control
+                    .resumed
+                    .wait_timeout_while(state, THERMAL_POLL, |state| {
+                        !state.cancelled && !state.finished
+                    })
+                    .unwrap_or_else(PoisonError::into_inner);
+                if state.cancelled || state.finished {
+                    return;
+                }
+                drop(state);
+                share = limit.next_share(share, cpu_temperature());
+                let stored = if share >= 100 { 0 } else { share };
+                control.thermal_cpu.store(stored, Ordering::Relaxed);
+            }
+        });
+    }
+
+    /// Blocks until `deadline` or until the search ends, whichever comes first.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the search has ended.
+    fn wait_until(&self, deadline: Instant) -> bool {
+        let mut state = self.state();
+        while !state.finished {
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            let waited = self.resumed.wait_timeout(state, deadline - now);
+            state = waited.unwrap_or_else(PoisonError::into_inner).0;
+        }
+        true
+    }
+
+    /// Records that the worker at `index` found the solution.
+    pub(crate) fn record_winner(&self, index: usize) {
+        self.state().found_by = Some(index);
+    }
+
+    /// Adds each worker's hashes in a round that has ended to its running total.
+    pub(crate) fn tally(&self, progress: &[Progress]) {
+        let mut state = self.state();
+        if state.thread_attempts.len() < progress.len() {
+            state.thread_attempts.resize(progress.len(), 0);
+        }
+        for (total, slot) in state.thread_attempts.iter_mut().zip(progress) {
+            *total += slot.attempts.load(Ordering::Relaxed);
+        }
+        #[cfg(feature = "profile")]
+        {
+            let now = Instant::now();
+            if state.thread_profiles.len() < progress.len() {
+                state.thread_profiles.resize(progress.len(), ThreadProfile::default());
+            }
+            for (total, slot) in state.thread_profiles.iter_mut().zip(progress) {
+                *total += slot.profile.profile(now);
+            }
+        }
+    }
+
+    /// The statistics of a search that started at `started`, with `meter` if the energy
+    /// it consumed can be measured, and has just ended.
+    fn report(&self, started: Instant, meter: Option<&EnergyMeter>) -> MiningReport {
+        let state = self.state();
+        MiningReport {
+            attempts: self.attempts(),
+            elapsed: started.elapsed(),
+            thread_attempts: state.thread_attempts.clone(),
+            found_by: state.found_by,
+            energy: meter.and_then(EnergyMeter::microjoules),
+            #[cfg(feature = "profile")]
+            thread_profiles: state.thread_profiles.clone(),
+        }
+    }
+
+    /// Marks the search as ended, so a deadline watchdog stops waiting.
+    pub(crate) fn finish(&self) {
+        self.state().finished = true;
+        self.resumed.notify_all();
+    }
+
+    /// Marks the thread running the search as exiting, and wakes a future awaiting it.
+    fn exit(&self) {
+        let waker = {
+            let mut state = self.state();
+            state.exited = true;
+            state.waker.take()
+        };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+
+    /// Whether the thread running the search is exiting; if not, `waker` is woken once
+    /// it is.
+    fn poll_exit(&self, waker: &Waker) -> bool {
+        let mut state = self.state();
+        if !state.exited {
+            state.waker = Some(waker.clone());
+        }
+        state.exited
+    }
+
+    /// Whether the search is paused or cancelled, checked without taking the lock.
+    /// Searches that cannot be paused use it as a cheap test for cancellation.
+    pub(crate) fn is_interrupted(&self) -> bool {
+        self.interrupted.load(Ordering::Relaxed)
+    }
+
+    fn is_paused(&self) -> bool {
+        self.state().paused
+    }
+
+    /// Whether the search was cancelled.
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.state().cancelled
+    }
+
+    /// Whether the search was cancelled because its deadline passed.
+    pub(crate) fn is_timed_out(&self) -> bool {
+        self.state().timed_out
+    }
+
+    /// Adds `count` hashes to the number tried.
+    pub(crate) fn add_attempts(&self, count: u64) {
+        self.attempts.fetch_add(count, Ordering::Relaxed);
+    }
+
+    /// The number of hashes tried so far.
+    pub(crate) fn attempts(&self) -> u64 {
+        self.attempts.load(Ordering::Relaxed)
+    }
+
+    /// Limits each worker to `percent` of the time, or lifts the limit at 100.
+    fn set_max_cpu(&self, percent: u8) {
+        self.max_cpu.store(if percent >= 100 { 0 } else { percent }, Ordering::Relaxed);
+    }
+
+    /// Called by a worker between batches, with the moment it last started hashing:
+    /// once it has hashed for its share of a [`DUTY_CYCLE`], rests for the remainder and
+    /// restarts `busy_since`.
+    ///
+    /// The rest ends early if the search is cancelled. Time spent paused does not count
+    /// as hashing beyond one cycle, so resuming never starts with a long rest.
+    pub(crate) fn pace(&self, busy_since: &mut Instant) {
+        let limits = [&self.max_cpu, &self.thermal_cpu].map(|limit| limit.load(Ordering::Relaxed));
+        let Some(percent) = limits.into_iter().filter(|&limit| limit > 0).min() else {
+            return;
+        };
+        let percent = u32::from(percent);
+        let busy = busy_since.elapsed();
+        if busy < DUTY_CYCLE * percent / 100 {
+            return;
+        }
+        let rest = busy.min(DUTY_CYCLE) * (100 - percent) / percent;
+        let state = self.state();
+        let _ = self.resumed.wait_timeout_while(state, rest, |state| !state.cancelled);
+        *busy_since = Instant::now();
+    }
+
+    /// Called by a worker before trying the nonce at `position`: blocks while paused.
+    ///
+    /// An interrupted worker first records `position` in its `slot`, so a checkpoint
+    /// taken while paused or after cancelling is exact.
+    ///
+    /// # Returns
+    ///
+    /// `false` if the search was cancelled and the worker should stop.
+    pub(crate) fn checkpoint(&self, slot: &Progress, position: u64) -> bool {
+        if !self.interrupted.load(Ordering::Relaxed) {
+            return true;
+        }
+        slot.record(position);
+        let mut state = self.state();
+        while state.paused {
+            state = self.resumed.wait(state).unwrap_or_else(PoisonError::into_inner);
+        }
+        !state.cancelled
+    }
+}
+
+/// The positions of one round of a search, handed out to the workers in chunks, and how
+/// far each worker got.
+///
+/// Positions count through the window as if it were searched sequentially; the
+/// [`SearchOrder`] maps each position to the nonce tried there. Workers take the next
+/// [`CHUNK_SIZE`] positions from a shared cursor whenever they finish a chunk, so fast
+/// workers simply take more chunks, and the window is searched from the bottom up.
+/// Workers pinned with [`CorePinning::weighted`](crate::CorePinning::weighted) take more
+/// or fewer positions at a time by the capacity of their core, always a multiple of
+/// [`PROGRESS_INTERVAL`].
+///
+/// When the workers are spread over NUMA nodes, the cursor's positions are split into one
+/// share per node, each with a cursor of its own; a worker takes chunks from its own
+/// node's share, bottom up, and from the other shares once it runs dry.
+#[derive(Debug)]
+pub(crate) struct Work {
+    /// The whole window, which fixes the permutation of a shuffled order or of restarts.
+    window: RangeInclusive<u64>,
+    order: SearchOrder,
+    /// Ranges left over from a checkpoint, lowest first, handed out before the cursor's.
+    leftovers: Mutex<Vec<RangeInclusive<u64>>>,
+    /// The positions the cursors hand out, one share per node; none if there are none.
+    shares: Box<[Share]>,
+    /// Read while a worker takes a chunk and written by snapshots, so a snapshot never
+    /// misses a chunk that has left the cursor but not yet reached its worker's slot.
+    claims: RwLock<()>,
+    /// One slot per worker.
+    slots: Box<[Progress]>,
+}
+
+impl Work {
+    /// `nonces` for `num_threads` workers (at least one), searched in `order`.
+    pub(crate) fn new(nonces: RangeInclusive<u64>, num_threads: usize, order: SearchOrder) -> Self {
+        Work::on_nodes(nonces, num_threads, order, 1)
+    }
+
+    /// Like [`new`](Work::new), with the window split into a share for each of `nodes`
+    /// NUMA nodes.
+    pub(crate) fn on_nodes(
+        nonces: RangeInclusive<u64>,
+        num_threads: usize,
+        order: SearchOrder,
+        nodes: usize,
+    ) -> Self {
+        Work::with_remaining(nonces.clone(), vec![nonces], num_threads, order, nodes)
+    }
+
+    /// The positions left in `checkpoint`, for workers on `nodes` NUMA nodes. The highest
+    /// range is handed out by the cursors and the others, which were workers' unfinished
+    /// chunks, before it.
+    fn resume(checkpoint: &Checkpoint, nodes: usize) -> Self {
+        Work::with_remaining(
+            checkpoint.window.clone(),
+            checkpoint.ranges.clone(),
+            checkpoint.threads,
+            checkpoint.order,
+            nodes,
+        )
+    }
+
+    fn with_remaining(
+        window: RangeInclusive<u64>,
+        mut remaining: Vec<RangeInclusive<u64>>,
+        num_threads: usize,
+        order: SearchOrder,
+        nodes: usize,
+    ) -> Self {
+        remaining.retain(|range| !range.is_empty());
+        remaining.sort_by_key(|range| *range.start());
+        let shares = match remaining.pop() {
+            Some(pending) => Share::split(pending, nodes.max(1)),
+            None => Box::default(),
+        };
+        Work {
+            window,
+            order,
+            leftovers: Mutex::new(remaining),
+            shares,
+            claims: RwLock::new(()),
+            slots: (0..num_threads.max(1)).map(|_| Progress::default()).collect(),
+        }
+    }
+
+    /// One slot per worker.
+    pub(crate) fn slots(&self) -> &[Progress] {
+        &self.slots
+    }
+
+    /// The nonce tried at `position`.
+    pub(crate) fn nonce_at(&self, position: u64) -> u64 {
+        self.order.nonce_at(&self.window, position)
+    }
+
+    /// Hands the next chunk to the worker with `slot`, or `None` once every position has
+    /// been handed out.
+    pub(crate) fn claim(&self, slot: &Progress) -> Option<RangeInclusive<u64>> {
+        let _claiming = self.claims.read().unwrap_or_else(PoisonError::into_inner);
+        let chunk = self.take_leftover().or_else(|| self.take_chunk())?;
+        slot.hold(&chunk);
+        Some(chunk)
+    }
+
+    /// The next chunk of the lowest leftover range.
+    fn take_leftover(&self) -> Option<RangeInclusive<u64>> {
+        let mut leftovers = self.leftovers.lock().unwrap_or_else(PoisonError::into_inner);
+        let range = leftovers.first_mut()?;
+        let (start, end) = (*range.start(), *range.end());
+        let last = end.min(start.saturating_add(cores::chunk_size() - 1));
+        if last == end {
+            leftovers.remove(0);
+        } else {
+            *range = last + 1..=end;
+        }
+        Some(start..=last)
+    }
+
+    /// The next chunk from the cursor of the worker's own node, or of the next node with
+    /// positions left.
+    fn take_chunk(&self) -> Option<RangeInclusive<u64>> {
+        let home = numa::current_node().unwrap_or(0);
+        let count = self.shares.len();
+        (0..count).find_map(|i| self.shares[(home + i) % count].take_chunk())
+    }
+
+    /// Whether every position has been handed out and every worker is done with its chunk.
+    pub(crate) fn is_done(&self) -> bool {
+        self.remaining().is_empty()
+    }
+
+    /// The positions that may not have been tried, lowest first: the rest of each
+    /// worker's chunk, the leftovers and whatever the cursor has not handed out yet.
+    pub(crate) fn remaining(&self) -> Vec<RangeInclusive<u64>> {
+        let _snapshot = self.claims.write().unwrap_or_else(PoisonError::into_inner);
+        let mut remaining: Vec<_> = self.slots.iter().filter_map(Progress::remaining).collect();
+        let leftovers = self.leftovers.lock().unwrap_or_else(PoisonError::into_inner);
+        remaining.extend(leftovers.iter().cloned());
+        remaining.extend(self.shares.iter().filter_map(Share::remaining));
+        remaining.sort_by_key(|range| *range.start());
+        remaining
+    }
+}
+
+/// One node's part of the positions the cursors hand out, with its own cursor. Each
+/// share takes a cache line of its own, so the nodes never write to the same one.
+#[derive(Debug)]
+#[repr(align(128))]
+struct Share {
+    positions: RangeInclusive<u64>,
+    /// How many [`PROGRESS_INTERVAL`]s of `positions` have been handed out.
+    cursor: AtomicU64,
+}
+
+impl Share {
+    /// `positions` cut into `count` (at least one) shares of about the same size, lowest
+    /// first.
+    fn split(positions: RangeInclusive<u64>, count: usize) -> Box<[Share]> {
+        let (start, end) = positions.into_inner();
+        let len = u128::from(end - start) + 1;
+        let count = (count as u128).min(len);
+        (0..count)
+            .map(|i| {
+                // Both bounds are at most `len`, so they fit the window.
+                let first = start + (len * i / count) as u64;
+                let last = start + (len * (i + 1) / count - 1) as u64;
+                Share { positions: first..=last, cursor: AtomicU64::new(0) }
+            })
+            .collect()
+    }
+
+    /// The next chunk from the cursor, of the current worker's chunk size.
+    fn take_chunk(&self) -> Option<RangeInclusive<u64>> {
+        let (start, end) = self.positions.clone().into_inner();
+        let size = cores::chunk_size();
+        let intervals = size / PROGRESS_INTERVAL;
+        let first = chunk_start(start, self.cursor.fetch_add(intervals, Ordering::Relaxed))?;
+        (first <= end).then(|| first..=end.min(first.saturating_add(size - 1)))
+    }
+
+    /// The positions the cursor has not handed out yet, if any.
+    fn remaining(&self) -> Option<RangeInclusive<u64>> {
+        let (start, end) = self.positions.clone().into_inner();
+        let first = chunk_start(start, self.cursor.load(Ordering::Relaxed))?;
+        (first <= end).then_some(first..=end)
+    }
+}
+
+/// The first position after `intervals` [`PROGRESS_INTERVAL`]s of a range starting at
+/// `start`, if it exists.
+fn chunk_start(start: u64, intervals: u64) -> Option<u64> {
+    start.checked_add(intervals.checked_mul(PROGRESS_INTERVAL)?)
+}
+
+/// One worker's current chunk and how far it got.
+#[derive(Debug)]
+pub(crate) struct Progress {
+    /// The first position of the chunk the worker may not have reached yet.
+    next: AtomicU64,
+    /// The last position of the chunk.
+    end: AtomicU64,
+    /// Set while the worker holds no chunk: before its first, after finishing one, and
+    /// once it has found a solution.
+    idle: AtomicBool,
+    /// The number of hashes the worker has reported trying.
+    attempts: AtomicU64,
+    /// How the worker has spent its time.
+    #[cfg(feature = "profile")]
+    pub(crate) profile: ProfileSlot,
+}
+
+impl Default for Progress {
+    fn default() -> Self {
+        Progress {
+            next: AtomicU64::new(0),
+            end: AtomicU64::new(0),
+            idle: AtomicBool::new(true),
+            attempts: AtomicU64::new(0),
+            #[cfg(feature = "profile")]
+            profile: ProfileSlot::default(),
+        }
+    }
+}
+
+impl Progress {
+    /// Records that the worker has taken `chunk`.
+    fn hold(&self, chunk: &RangeInclusive<u64>) {
+        self.next.store(*chunk.start(), Ordering::Relaxed);
+        self.end.store(*chunk.end(), Ordering::Relaxed);
+        self.idle.store(false, Ordering::Relaxed);
+    }
+
+    /// Records that every position of the chunk before `position` has been tried.
+    pub(crate) fn record(&self, position: u64) {
+        self.next.store(position, Ordering::Relaxed);
+    }
+
+    /// Adds `count` hashes to the number the worker has tried.
+    pub(crate) fn add_attempts(&self, count: u64) {
+        self.attempts.fetch_add(count, Ordering::Relaxed);
+    }
+
+    /// Records that the worker is done with its chunk.
+    pub(crate) fn finish(&self) {
+        self.idle.store(true, Ordering::Relaxed);
+    }
+
+    /// The positions of the chunk that may not have been tried, or `None` when idle.
+    fn remaining(&self) -> Option<RangeInclusive<u64>> {
+        let idle = self.idle.load(Ordering::Relaxed);
+        (!idle).then(|| self.next.load(Ordering::Relaxed)..=self.end.load(Ordering::Relaxed))
+    }
+}
+
+/// The extra nonce being searched and the work of the window under it, replaced
+/// together whenever the extra nonce rolls over.
+#[derive(Debug)]
+struct Round {
+    extra_nonce: Option<u64>,
+    work: Arc<Work>,
+}
+
+/// A report on a running search, passed to [`MiningHandle::on_progress`] callbacks.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MiningProgress {
+    /// The number of hashes tried since the search started.
+    pub attempts: u64,
+    /// The time since the search started.
+    pub elapsed: Duration,
+    /// Each worker's hash rate over the last interval, in hashes per second.
+    pub thread_rates: Vec<f64>,
+}
+
+impl MiningProgress {
+    /// The combined hash rate of all workers over the last interval, in hashes per
+    /// second.
+    pub fn hash_rate(&self) -> f64 {
+        self.thread_rates.iter().sum()
+    }
+
+    /// The average hash rate since the search started, in hashes per second.
+    pub fn average_rate(&self) -> f64 {
+        if self.elapsed.is_zero() {
+            0.0
+        } else {
+            self.attempts as f64 / self.elapsed.as_secs_f64()
+        }
+    }
+}
+
+/// Statistics of a finished search, from [`MiningHandle::join_report`].
+///
+/// A search that finds a solution ends as soon as the winner reports it, so the counts
+/// may leave out the last few thousand hashes the other workers tried while stopping.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MiningReport {
+    /// The number of hashes tried.
+    pub attempts: u64,
+    /// The wall-clock time from the start of the search to its end.
+    pub elapsed: Duration,
+    /// The number of hashes each worker tried, indexed like
+    /// [`found_by`](MiningReport::found_by).
+    pub thread_attempts: Vec<u64>,
+    /// The index of the worker that found the solution, or `None` if the search ended
+    /// without one.
+    pub found_by: Option<usize>,
+    /// The energy the processor packages consumed during the search, in microjoules, or
+    /// `None` if it could not be measured; see the [`energy`](crate::energy) module.
+    pub energy: Option<u64>,
+    /// How each worker spent its time, indexed like
+    /// [`thread_attempts`](MiningReport::thread_attempts); see the
+    /// [`profile`](crate::profile) module.
+    #[cfg(feature = "profile")]
+    pub thread_profiles: Vec<ThreadProfile>,
+}
+
+impl MiningReport {
+    /// The effective hash rate of the whole search, in hashes per second.
+    pub fn hash_rate(&self) -> f64 {
+        if self.elapsed.is_zero() {
+            0.0
+        } else {
+            self.attempts as f64 / self.elapsed.as_secs_f64()
+        }
+    }
+
+    /// The energy consumed during the search, in joules, if it was measured.
+    pub fn joules(&self) -> Option<f64> {
+        self.energy.map(|microjoules| microjoules as f64 / 1e6)
+    }
+
+    /// The hashes tried per joule consumed, if the energy was measured and is not zero.
+    pub fn hashes_per_joule(&self) -> Option<f64> {
+        self.joules().filter(|&joules| joules > 0.0).map(|joules| self.attempts as f64 / joules)
+    }
+}
+
+/// A search running in the background.
+///
+/// Dropping the handle cancels the search and waits for its threads to stop.
+#[derive(Debug)]
+pub struct MiningHandle {
+    control: Arc<Control>,
+    round: Arc<Mutex<Round>>,
+    started: Instant,
+    /// The fingerprint, window and thread count recorded in checkpoints.
+    checkpoint: Checkpoint,
+    thread: Option<JoinHandle<(Result<Solution, PuzzleError>, MiningReport)>>,
+}
+
+impl MiningHandle {
+    /// Parks every worker thread where it stands. Has no effect once cancelled.
+    ///
+    /// Workers notice the request before their next nonce, so a paused search uses no
+    /// CPU until [`resume`](MiningHandle::resume) is called.
+    pub fn pause(&self) {
+        self.control.pause();
+    }
+
+    /// Wakes the workers, which continue from the nonces they stopped at.
+    pub fn resume(&self) {
+        self.control.resume();
+    }
+
+    /// Whether the search is paused.
+    pub fn is_paused(&self) -> bool {
+        self.control.is_paused()
+    }
+
+    /// Stops the search for good; [`join`](MiningHandle::join) then returns
+    /// [`PuzzleError::Cancelled`] unless a solution was already found.
+    pub fn cancel(&self) {
+        self.control.cancel();
+    }
+
+    /// Limits each worker to about `percent` of its core's time from now on, or lifts the
+    /// limit at 100 or more; see the [module documentation](self).
+    ///
+    /// A worker hashes for up to a tenth of a second at a time and then sleeps, so with
+    /// one worker per core the whole machine stays near `percent` busy. A resting search
+    /// is not [paused](MiningHandle::is_paused), and cancelling ends the rest at once.
+    ///
+    /// # Panics
+    ///
+    /// If `percent` is zero.
+    pub fn set_max_cpu(&self, percent: u8) {
+        assert!(percent > 0, "the CPU limit must be positive");
+        self.control.set_max_cpu(percent);
+    }
+
+    /// Slows the search down while the CPU is hotter than `limit` allows, checking every
+    /// second until the search ends; see the [`thermal`](crate::thermal) module.
+    ///
+    /// The workers then hash for no more of the time than either this or
+    /// [`set_max_cpu`](MiningHandle::set_max_cpu) allows. Without a temperature sensor,
+    /// see [`cpu_temperature`], the search runs as if there were no limit.
+    pub fn limit_temperature(&self, limit: ThermalLimit) {
+        self.control.watch_temperature(limit);
+    }
+
+    /// Cancels the search when `token` is cancelled, or at once if it already is.
+    pub fn cancel_on(&self, token: &CancellationToken) {
+        token.register(&self.control);
+    }
+
+    /// Cancels the search once `max_duration` has passed, counting from now;
+    /// [`join`](MiningHandle::join) then returns [`PuzzleError::TimedOut`] unless a
+    /// solution was found in time. Time spent paused counts towards the limit.
+    pub fn cancel_after(&self, max_duration: Duration) {
+        if let Some(deadline) = Instant::now().checked_add(max_duration) {
+            self.control.cancel_at(deadline);
+        }
+    }
+
+    /// The number of hashes tried so far. Running workers report their count every few
+    /// thousand hashes, so the figure lags slightly until the search ends.
+    pub fn attempts(&self) -> u64 {
+        self.control.attempts()
+    }
+
+    /// Calls `callback` with a [`MiningProgress`] every `interval` until the search ends.
+    ///
+    /// The callback runs on a thread of its own, so a slow callback never holds up the
+    /// workers. Rates are measured from the counts workers report
+    /// every few thousand hashes, so keep the interval well above the time that takes;
+    /// a second is a good choice for fast hashes.
+    ///
+    /// # Panics
+    ///
+    /// If `interval` is zero.
+    pub fn on_progress<F>(&self, interval: Duration, mut callback: F)
+    where
+        F: FnMut(&MiningProgress) + Send + 'static,
+    {
+        assert!(!interval.is_zero(), "the progress interval must be positive");
+        let control = Arc::clone(&self.control);
+        let round = Arc::clone(&self.round);
+        let started = self.started;
+        thread::spawn(move || {
+            let mut previous: Option<(Arc<Work>, Vec<u64>)> = None;
+            let mut last = Instant::now();
+            while !control.wait_until(last + interval) {
+                let now = Instant::now();
+                let work = Arc::clone(&round.lock().unwrap_or_else(PoisonError::into_inner).work);
+                let counts: Vec<u64> =
+                    work.slots.iter().map(|slot| slot.attempts.load(Ordering::Relaxed)).collect();
+                // A new round starts every worker from zero again.
+                let before = match &previous {
+                    Some((last_work, before)) if Arc::ptr_eq(last_work, &work) => before.clone(),
+                    _ => vec![0; counts.len()],
+                };
+                let seconds = (now - last).as_secs_f64();
+                let thread_rates = counts
+                    .iter()
+                    .zip(&before)
+                    .map(|(count, before)| count.saturating_sub(*before) as f64 / seconds)
+                    .collect();
+                callback(&MiningProgress {
+                    attempts: control.attempts(),
+                    elapsed: now - started,
+                    thread_rates,
+                });
+                previous = Some((work, counts));
+                last = now;
+            }
+        });
+    }
+
+    /// A snapshot of the nonces still to be tried, from which
+    /// [`parallel_mine_resume_with`] can continue the search later.
+    ///
+    /// Running workers record their position every few thousand nonces, so resuming
+    /// from a checkpoint of a running search may try a few nonces again but never skips
+    /// one. A checkpoint of a paused search is exact.
+    pub fn checkpoint(&self) -> Checkpoint {
+        let round = self.round.lock().unwrap_or_else(PoisonError::into_inner);
+        Checkpoint {
+            extra_nonce: round.extra_nonce,
+            ranges: round.work.remaining(),
+            ..self.checkpoint.clone()
+        }
+    }
+
+    /// Cancels the search without waiting for it to end. The thread running it still
+    /// stops and joins its workers, or hands them back to their pool, on its own.
+    pub(crate) fn detach(mut self) {
+        self.control.cancel();
+        self.thread.take();
+    }
+
+    /// Whether the search has ended or is about to, so [`join`](MiningHandle::join) would
+    /// block for no more than a moment; if not, `waker` is woken once it has.
+    pub(crate) fn poll_finished(&self, waker: &Waker) -> bool {
+        self.control.poll_exit(waker)
+    }
+
+    /// Whether the search has ended, so [`join`](MiningHandle::join) would not block.
+    pub fn is_finished(&self) -> bool {
+        self.thread.as_ref().is_none_or(JoinHandle::is_finished)
+    }
+
+    /// Waits for the search to end.
+    ///
+    /// # Returns
+    ///
+    /// The [`Solution`], or a [`PuzzleError`] as for
+    /// [`parallel_mine_range`](crate::parallel_mine_range), or [`PuzzleError::Cancelled`].
+    /// A paused search never ends on its own, so resume or cancel it first.
+    pub fn join(self) -> Result<Solution, PuzzleError> {
+        self.join_report().0
+    }
+
+    /// Waits for the search to end, like [`join`](MiningHandle::join), and also returns
+    /// its statistics: how many hashes each worker tried, how long it took, which worker
+    /// found the solution and, where it can be measured, how much energy it consumed.
+    ///
+    /// If the thread running the search panicked, the result is
+    /// [`PuzzleError::WorkerPanicked`] and the statistics cover the hashes counted
+    /// before the panic, without the energy.
+    pub fn join_report(mut self) -> (Result<Solution, PuzzleError>, MiningReport) {
+        let thread = self.thread.take().expect("the thread is only taken here or on drop");
+        thread.join().unwrap_or_else(|_| {
+            self.control.finish();
+            (Err(PuzzleError::WorkerPanicked), self.control.report(self.started, None))
+        })
+    }
+}
+
+impl Drop for MiningHandle {
+    fn drop(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            self.control.cancel();
+            let _ = thread.join();
+        }
+    }
+}
+
+/// A shared flag that stops every search it is attached to.
+///
+/// Clones share the same flag, so any of them can cancel. Once cancelled, a token stays
+/// cancelled, and searches attached to it later are cancelled straight away.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken {
+    inner: Arc<Mutex<TokenState>>,
+}
+
+#[derive(Debug, Default)]
+struct TokenState {
+    cancelled: bool,
+    /// The searches to cancel; entries of searches that have ended are dropped lazily.
+    searches: Vec<Weak<Control>>,
+}
+
+impl CancellationToken {
+    /// Creates a token that is not cancelled.
+    pub fn new() -> Self {
+        CancellationToken::default()
+    }
+
+    /// Cancels every search attached to the token. Their workers stop before their next
+    /// nonce.
+    pub fn cancel(&self) {
+        let mut state = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
+        state.cancelled = true;
+        for control in state.searches.drain(..).filter_map(|search| search.upgrade()) {
+            control.cancel();
+        }
+    }
+
+    /// Whether [`cancel`](CancellationToken::cancel) has been called on this token or a
+    /// clone of it.
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner).cancelled
+    }
+
+    /// Attaches a search, cancelling it at once if the token already is.
+    pub(crate) fn register(&self, control: &Arc<Control>) {
+        let mut state = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
+        if state.cancelled {
+            control.cancel();
+            return;
+        }
+        state.searches.retain(|search| search.strong_count() > 0);
+        state.searches.push(Arc::downgrade(control));
+    }
+}
+
+/// Mines `puzzle` with SHA-256 on every available core until a solution is found or
+/// `token` is cancelled.
+///
+/// See [`parallel_mine_cancellable_with`] for details and the return value.
+pub fn parallel_mine_cancellable(
+    puzzle: &Puzzle,
+    token: &CancellationToken,
+) -> Result<Solution, PuzzleError> {
+    let num_threads = num_cpus::get();
+    parallel_mine_cancellable_with::<Sha256>(puzzle, default_nonce_range(), num_threads, token)
+}
+
+/// Mines `puzzle` with the hash function `D` over `nonces`, split across `num_threads`
+/// threads, stopping early if `token` is cancelled from another thread.
+///
+/// # Returns
+///
+/// The [`Solution`], or [`PuzzleError::Cancelled`] if the token was cancelled first, or
+/// a [`PuzzleError`] as for [`parallel_mine_range`](crate::parallel_mine_range).
+pub fn parallel_mine_cancellable_with<D>(
+    puzzle: &Puzzle,
+    nonces: RangeInclusive<u64>,
+    num_threads: usize,
+    token: &CancellationToken,
+) -> Result<Solution, PuzzleError>
+where
+    D: Digest + Clone + Send + Sync + 'static,
+{
+    let handle = parallel_mine_spawn_with::<D>(puzzle, nonces, num_threads);
+    handle.cancel_on(token);
+    handle.join()
+}
+
+/// Starts mining `puzzle` with SHA-256 on every available core and returns a handle to
+/// the running search.
+///
+/// See [`parallel_mine_spawn_with`] for details.
+pub fn parallel_mine_spawn(puzzle: &Puzzle) -> MiningHandle {
+    let num_threads = num_cpus::get();
+    parallel_mine_spawn_with::<Sha256>(puzzle, default_nonce_range(), num_threads)
+}
+
+/// Mines `puzzle` with SHA-256 on every available core for at most `max_duration`.
+///
+/// See [`parallel_mine_timeout_with`] for details and the return value.
+pub fn parallel_mine_timeout(
+    puzzle: &Puzzle,
+    max_duration: Duration,
+) -> Result<Solution, PuzzleError> {
+    let num_threads = num_cpus::get();
+    parallel_mine_timeout_with::<Sha256>(puzzle, default_nonce_range(), num_threads, max_duration)
+}
+
+/// Mines `puzzle` with the hash function `D` over `nonces`, split across `num_threads`
+/// threads, giving up once `max_duration` of wall-clock time has passed.
+///
+/// This is the search [`parallel_mine_range`](crate::parallel_mine_range) runs, with a
+/// time budget for callers that cannot wait indefinitely, such as request handlers. The
+/// workers notice the deadline before their next nonce, so the call returns promptly.
+///
+/// # Returns
+///
+/// The [`Solution`], or [`PuzzleError::TimedOut`] with the number of hashes tried if the
+/// budget ran out first, or a [`PuzzleError`] as for `parallel_mine_range`.
+pub fn parallel_mine_timeout_with<D>(
+    puzzle: &Puzzle,
+    nonces: RangeInclusive<u64>,
+    num_threads: usize,
+    max_duration: Duration,
+) -> Result<Solution, PuzzleError>
+where
+    D: Digest + Clone + Send + Sync + 'static,
+{
+    let handle = parallel_mine_spawn_with::<D>(puzzle, nonces, num_threads);
+    handle.cancel_after(max_duration);
+    handle.join()
+}
+
+/// Starts mining `puzzle` with the hash function `D` over `nonces`, split across
+/// `num_threads` threads, and returns a handle to the running search.
+///
+/// The search is the one [`parallel_mine_range`](crate::parallel_mine_range) runs,
+/// including extra-nonce rolling; it runs on its own threads and this function does not
+/// block.
+pub fn parallel_mine_spawn_with<D>(
+    puzzle: &Puzzle,
+    nonces: RangeInclusive<u64>,
+    num_threads: usize,
+) -> MiningHandle
+where
+    D: Digest + Clone + Send + Sync + 'static,
+{
+    spawn_observed::<D>(puzzle, nonces, num_threads, SearchOrder::Sequential, None, None)
+}
+
+/// Continues mining `puzzle` with the hash function `D` from `checkpoint`, taken by
+/// [`MiningHandle::checkpoint`] during an earlier search of the same puzzle.
+///
+/// The checkpoint's number of threads take chunks as in a fresh search: first of the
+/// ranges the workers left unfinished, lowest first, then from a shared cursor over the
+/// last range, which no worker had reached. Once they are exhausted, the extra nonce, if
+/// any, rolls over and the search goes on over the original window.
+///
+/// # Returns
+///
+/// A handle to the running search, or [`CheckpointError::Mismatch`] if the checkpoint
+/// was taken for a different puzzle or hash function.
+pub fn parallel_mine_resume_with<D>(
+    puzzle: &Puzzle,
+    checkpoint: &Checkpoint,
+) -> Result<MiningHandle, CheckpointError>
+where
+    D: Digest + Clone + Send + Sync + 'static,
+{
+    resume_observed::<D>(puzzle, checkpoint, None, None)
+}
+
+/// Like [`parallel_mine_spawn_with`], with the window searched in `order`, `observer`
+/// told about the search, and the workers run on `pool` if there is one.
+pub(crate) fn spawn_observed<D>(
+    puzzle: &Puzzle,
+    nonces: RangeInclusive<u64>,
+    num_threads: usize,
+    order: SearchOrder,
+    observer: Option<Arc<dyn Observer>>,
+    pool: Option<Arc<WorkerPool>>,
+) -> MiningHandle
+where
+    D: Digest + Clone + Send + Sync + 'static,
+{
+    let checkpoint = Checkpoint::new::<D>(puzzle, nonces, num_threads, order);
+    spawn::<D>(puzzle.clone(), checkpoint, observer, pool)
+}
+
+/// Like [`parallel_mine_resume_with`], with `observer` told about the search and the
+/// workers run on `pool` if there is one.
+pub(crate) fn resume_observed<D>(
+    puzzle: &Puzzle,
+    checkpoint: &Checkpoint,
+    observer: Option<Arc<dyn Observer>>,
+    pool: Option<Arc<WorkerPool>>,
+) -> Result<MiningHandle, CheckpointError>
+where
+    D: Digest + Clone + Send + Sync + 'static,
+{
+    if !checkpoint.matches::<D>(puzzle) {
+        return Err(CheckpointError::Mismatch);
+    }
+    let puzzle = Puzzle { extra_nonce: checkpoint.extra_nonce, ..puzzle.clone() };
+    Ok(spawn::<D>(puzzle, checkpoint.clone(), observer, pool))
+}
+
+/// Starts the search described by `checkpoint` on its own thread, reporting its events
+/// to `observer` and running its workers on `pool` if there is one.
+fn spawn<D>(
+    mut puzzle: Puzzle,
+    checkpoint: Checkpoint,
+    observer: Option<Arc<dyn Observer>>,
+    pool: Option<Arc<WorkerPool>>,
+) -> MiningHandle
+where
+    D: Digest + Clone + Send + Sync + 'static,
+{
+    let started = Instant::now();
+    let meter = EnergyMeter::start();
+    let control = Arc::new(Control::default());
+    let nodes = pool.as_ref().map_or(1, |pool| pool.nodes());
+    let round = Arc::new(Mutex::new(Round {
+        extra_nonce: puzzle.extra_nonce,
+        work: Arc::new(Work::resume(&checkpoint, nodes)),
+    }));
+    let thread = {
+        let control = Arc::clone(&control);
+        let round = Arc::clone(&round);
+        let observer = observer.clone();
+        let plan = checkpoint.clone();
+        thread::spawn(move || {
+            // Wakes a future awaiting the search on the way out, even after a panic.
+            let _exit = ExitGuard(Arc::clone(&control));
+            if let Some(observer) = &observer {
+                observer.on_start(&plan);
+            }
+            let (window, num_threads, order) = (plan.window, plan.threads, plan.order);
+            let pool = pool.as_deref();
+            let result =
+                mine_rounds::<D>(&mut puzzle, &round, &control, window, num_threads, order, pool);
+            control.finish();
+            let report = control.report(started, meter.as_ref());
+            if let Some(observer) = &observer {
+                notify_end(observer.as_ref(), &result, &report);
+            }
+            (result, report)
+        })
+    };
+    let handle = MiningHandle { control, round, started, checkpoint, thread: Some(thread) };
+    if let Some(observer) = observer {
+        handle.on_progress(observer.progress_interval(), move |progress| {
+            observer.on_progress(progress)
+        });
+    }
+    handle
+}
+
+/// Calls [`Control::exit`] when the thread running a search drops it.
+struct ExitGuard(Arc<Control>);
+
+impl Drop for ExitGuard {
+    fn drop(&mut self) {
+        self.0.exit();
+    }
+}
+
+/// Searches the current round's ranges, then the whole `window` under each following
+/// extra nonce, until the search ends.
+fn mine_rounds<D>(
+    puzzle: &mut Puzzle,
+    round: &Mutex<Round>,
+    control: &Arc<Control>,
+    window: RangeInclusive<u64>,
+    num_threads: usize,
+    order: SearchOrder,
+    pool: Option<&WorkerPool>,
+) -> Result<Solution, PuzzleError>
+where
+    D: Digest + Clone + Send + Sync + 'static,
+{
+    if window.is_empty() {
+        return Err(PuzzleError::EmptyRange);
+    }
+    if puzzle.target == Target::ZERO {
+        return Err(PuzzleError::InvalidDifficulty);
+    }
+    loop {
+        let target = puzzle.target;
+        let work = Arc::clone(&round.lock()?.work);
+        let control = Arc::clone(control);
+        // Hash several nonces at once where the CPU and the puzzle allow it.
+        let result = match Sha256Lanes::new::<D>(puzzle) {
+            Some(lanes) => {
+                let search = LaneSearch::new(lanes, move |hash: &[u8]| target.is_met_by(hash));
+                search_parallel_controlled(work, search, control, pool)
+            }
+            None => {
+                let prehashed = Arc::new(PrehashedPuzzle::<D>::from_puzzle(puzzle));
+                let search = HashSearch::new(
+                    || (),
+                    move |_, nonce| prehashed.hash(nonce),
+                    move |hash| target.is_met_by(hash),
+                );
+                search_parallel_controlled(work, search, control, pool)
+            }
+        };
+        match (result, puzzle.extra_nonce) {
+            (Ok(solution), extra_nonce) => return Ok(Solution { extra_nonce, ..solution }),
+            (Err(PuzzleError::Exhausted), Some(extra_nonce)) => {
+                puzzle.extra_nonce = Some(extra_nonce.wrapping_add(1));
+                *round.lock()? = Round {
+                    extra_nonce: puzzle.extra_nonce,
+                    work: Arc::new(Work::on_nodes(
+                        window.clone(),
+                        num_threads,
+                        order,
+                        pool.map_or(1, WorkerPool::nodes),
+                    )),
+                };
+            }
+            (Err(e), _) => return Err(e),
+        }
+    }
+}
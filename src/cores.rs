@@ -0,0 +1,142 @@
+//! Telling performance cores from efficiency cores on hybrid CPUs.
+//!
+//! Hybrid processors, such as Intel's since Alder Lake and ARM's big.LITTLE designs, mix
+//! fast performance cores with slower, frugal efficiency cores. [`cpu_cores`] lists the
+//! cores the process may run on with the [`CoreKind`] of each and its capacity, its
+//! throughput relative to the fastest core. A [`CorePinning`](crate::CorePinning) can
+//! then keep the workers to one kind of core with
+//! [`only`](crate::CorePinning::only), e.g. to the efficiency cores for quiet background
+//! mining, or [`weight`](crate::CorePinning::weighted) the chunks each worker takes by
+//! the capacity of its core.
+//!
+//! On Linux, Intel's hybrid processors list their efficiency cores in
+//! `/sys/devices/cpu_atom/cpus`, and each core's capacity is read from its
+//! `cpu_capacity`, as ARM systems report it, or else from its highest clock frequency.
+//! Without those, a core counts as an efficiency core if it has at most half the
+//! capacity of the fastest. Anywhere else every core is taken as a performance core of
+//! full capacity.
+
+use std::cell::Cell;
+use std::fmt;
+use std::fs;
+use std::str::FromStr;
+
+use crate::control::CHUNK_SIZE;
+use crate::numa::parse_cpulist;
+
+/// The capacity of the fastest core, as Linux counts it.
+pub const FULL_CAPACITY: u32 = 1024;
+
+/// Where Linux lists the cores of an Intel hybrid processor that are efficiency cores.
+const INTEL_ATOM_CORES: &str = "/sys/devices/cpu_atom/cpus";
+
+thread_local! {
+    /// How many positions the current thread takes from a search's cursor at a time.
+    static CHUNK: Cell<u64> = const { Cell::new(CHUNK_SIZE) };
+}
+
+/// The two kinds of core on a hybrid CPU.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CoreKind {
+    /// A fast core; every core of a CPU that is not hybrid is one.
+    Performance,
+    /// A slower core that draws less power.
+    Efficiency,
+}
+
+/// One core the process may run on; see the [module documentation](self).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CpuCore {
+    /// The core's number, as the operating system counts them.
+    pub id: usize,
+    /// Whether it is a performance or an efficiency core.
+    pub kind: CoreKind,
+    /// Its throughput relative to the fastest core, which has [`FULL_CAPACITY`].
+    pub capacity: u32,
+}
+
+/// The cores the process may run on, lowest first, or nothing if the platform cannot
+/// tell which cores those are.
+pub fn cpu_cores() -> Vec<CpuCore> {
+    let ids: Vec<usize> = core_affinity::get_core_ids()
+        .unwrap_or_default()
+        .into_iter()
+        .map(|core| core.id)
+        .collect();
+    // Without a reading for every core, the cores are taken to be alike.
+    let speeds = ids
+        .iter()
+        .map(|&id| read_speed(id))
+        .collect::<Option<Vec<u64>>>()
+        .unwrap_or_else(|| vec![1; ids.len()]);
+    let fastest = speeds.iter().copied().max().unwrap_or(1);
+    let atom_cores = fs::read_to_string(INTEL_ATOM_CORES).ok();
+    let atom_cores = atom_cores.and_then(|list| parse_cpulist(&list));
+    ids.into_iter()
+        .zip(speeds)
+        .map(|(id, speed)| {
+            let capacity = (speed * u64::from(FULL_CAPACITY) / fastest) as u32;
+            let efficient = match &atom_cores {
+                Some(atom_cores) => atom_cores.contains(&id),
+                None => capacity <= FULL_CAPACITY / 2,
+            };
+            let kind = if efficient { CoreKind::Efficiency } else { CoreKind::Performance };
+            CpuCore { id, kind, capacity }
+        })
+        .collect()
+}
+
+/// How fast core `id` is, in units that only compare between cores: its `cpu_capacity`,
+/// or else its highest frequency.
+fn read_speed(id: usize) -> Option<u64> {
+    let dir = format!("/sys/devices/system/cpu/cpu{}", id);
+    let speed = fs::read_to_string(format!("{}/cpu_capacity", dir))
+        .or_else(|_| fs::read_to_string(format!("{}/cpufreq/cpuinfo_max_freq", dir)))
+        .ok()?;
+    speed.trim().parse().ok().filter(|&speed| speed > 0)
+}
+
+/// Records that the current thread takes `positions` at a time from a search's cursor,
+/// instead of [`CHUNK_SIZE`].
+pub(crate) fn set_chunk_size(positions: u64) {
+    CHUNK.with(|chunk| chunk.set(positions));
+}
+
+/// How many positions the current thread takes from a search's cursor at a time.
+pub(crate) fn chunk_size() -> u64 {
+    CHUNK.with(Cell::get)
+}
+
+impl fmt::Display for CoreKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CoreKind::Performance => "performance",
+            CoreKind::Efficiency => "efficiency",
+        })
+    }
+}
+
+/// The error returned when a string is not a [`CoreKind`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ParseCoreKindError;
+
+impl fmt::Display for ParseCoreKindError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("expected `performance` or `efficiency`")
+    }
+}
+
+impl std::error::Error for ParseCoreKindError {}
+
+impl FromStr for CoreKind {
+    type Err = ParseCoreKindError;
+
+    /// Parses the form written by `Display`: `performance` or `efficiency`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "performance" => Ok(CoreKind::Performance),
+            "efficiency" => Ok(CoreKind::Efficiency),
+            _ => Err(ParseCoreKindError),
+        }
+    }
+}
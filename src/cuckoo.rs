@@ -0,0 +1,425 @@
+//! Cuckoo Cycle, a graph-theoretic proof of work by John Tromp.
+//!
+//! The input (data and nonce) keys siphash-2-4, which maps every edge index `i` below
+//! `2^edge_bits` to an edge between node `siphash(2i) mod 2^edge_bits` on the left of a
+//! bipartite graph and node `siphash(2i + 1) mod 2^edge_bits` on the right. A solution is
+//! a cycle of exactly `proof_size` edges (42 in deployed Cuckoo Cycle), given as its edge
+//! indices in ascending order. Finding one means walking a large random graph, so the
+//! cost is dominated by memory accesses rather than hashing; checking one takes only
+//! `proof_size` hashes.
+//!
+//! The solver is a *lean* miner: it keeps one bit per edge. Each trimming round counts
+//! node degrees on one side and drops edges that end in a leaf, since a leaf cannot lie
+//! on a cycle. Both passes are split across threads over disjoint parts of the edge
+//! bitmap. After trimming only a small fraction of edges remain, and cycles among them
+//! are found sequentially with Tromp's path-reversal method. The memory-hungry *mean*
+//! miner, which sorts edges into buckets instead, is not implemented.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+use std::thread;
+
+use blake2b_simd::Params;
+use rand::RngCore;
+
+use crate::{partition_nonces, PuzzleError};
+
+/// How many times each side of the graph is trimmed before looking for cycles.
+const TRIM_ROUNDS: usize = 40;
+
+/// Paths longer than this are abandoned while looking for cycles.
+const MAX_PATH_LEN: usize = 8192;
+
+/// Cuckoo Cycle parameters.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct CuckooParams {
+    edge_bits: u8,
+    proof_size: usize,
+}
+
+/// Cuckoo Cycle parameters that [`CuckooParams::new`] rejected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InvalidCuckooParams {
+    /// The rejected edge bits.
+    pub edge_bits: u8,
+    /// The rejected cycle length.
+    pub proof_size: usize,
+}
+
+impl fmt::Display for InvalidCuckooParams {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid Cuckoo Cycle parameters ({} edge bits, {}-cycles): need 1 to 31 edge \
+             bits and an even cycle length of at least 4",
+            self.edge_bits, self.proof_size
+        )
+    }
+}
+
+impl Error for InvalidCuckooParams {}
+
+impl CuckooParams {
+    /// Checks and builds parameters for a graph with `2^edge_bits` edges and cycles of
+    /// `proof_size` edges. Every cycle in a bipartite graph has even length.
+    pub fn new(edge_bits: u8, proof_size: usize) -> Result<Self, InvalidCuckooParams> {
+        if (1..=31).contains(&edge_bits) && proof_size >= 4 && proof_size.is_multiple_of(2) {
+            Ok(CuckooParams { edge_bits, proof_size })
+        } else {
+            Err(InvalidCuckooParams { edge_bits, proof_size })
+        }
+    }
+
+    /// Base-2 logarithm of the number of edges.
+    pub fn edge_bits(&self) -> u8 {
+        self.edge_bits
+    }
+
+    /// The cycle length a solution must have.
+    pub fn proof_size(&self) -> usize {
+        self.proof_size
+    }
+
+    /// The number of edges (and of nodes on each side).
+    fn edges(&self) -> u64 {
+        1 << self.edge_bits
+    }
+
+    /// The index of the endpoint of `edge` on `side` (0 for left, 1 for right).
+    fn endpoint(&self, keys: &[u64; 4], edge: u64, side: u64) -> u64 {
+        siphash24(keys, 2 * edge + side) & (self.edges() - 1)
+    }
+
+    /// The endpoint of `edge` on `side` as a graph node: even for the left side and odd
+    /// for the right, so the two sides never collide.
+    fn node(&self, keys: &[u64; 4], edge: u64, side: u64) -> u64 {
+        (self.endpoint(keys, edge, side) << 1) | side
+    }
+}
+
+impl Default for CuckooParams {
+    /// `2^16` edges and 42-cycles: small enough to find a solution in about a second.
+    fn default() -> Self {
+        CuckooParams { edge_bits: 16, proof_size: 42 }
+    }
+}
+
+/// A solved Cuckoo Cycle puzzle.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct CuckooSolution {
+    /// The nonce that was appended to the data.
+    pub nonce: u64,
+    /// The cycle's edge indices in ascending order.
+    pub edges: Vec<u32>,
+}
+
+/// A Cuckoo Cycle puzzle: parameters plus the data that every attempt hashes.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct CuckooPuzzle {
+    /// Graph size and cycle length.
+    pub params: CuckooParams,
+    /// The data hashed in front of the nonce.
+    pub data: Vec<u8>,
+}
+
+impl CuckooPuzzle {
+    /// Creates a puzzle over `data`.
+    pub fn new(params: CuckooParams, data: impl Into<Vec<u8>>) -> Self {
+        CuckooPuzzle { params, data: data.into() }
+    }
+
+    /// Generates a puzzle over 32 random bytes of data.
+    pub fn generate(params: CuckooParams) -> Self {
+        let mut data = vec![0u8; 32];
+        rand::thread_rng().fill_bytes(&mut data);
+        CuckooPuzzle::new(params, data)
+    }
+
+    /// The siphash keys for `nonce`: BLAKE2b-256 of `data || nonce` (little-endian), read
+    /// as four little-endian words.
+    fn keys(&self, nonce: u64) -> [u64; 4] {
+        let hash = Params::new()
+            .hash_length(32)
+            .to_state()
+            .update(&self.data)
+            .update(&nonce.to_le_bytes())
+            .finalize();
+        let mut keys = [0u64; 4];
+        for (key, chunk) in keys.iter_mut().zip(hash.as_bytes().chunks_exact(8)) {
+            *key = u64::from_le_bytes(chunk.try_into().expect("chunks are 8 bytes"));
+        }
+        keys
+    }
+
+    /// Finds every `proof_size`-cycle of the graph for `nonce`, trimming on
+    /// `num_threads` threads.
+    ///
+    /// Most graphs have no cycle of the required length; on average one in `proof_size`
+    /// graphs does.
+    pub fn solve(&self, nonce: u64, num_threads: usize) -> Vec<Vec<u32>> {
+        let params = self.params;
+        let keys = self.keys(nonce);
+        let alive = trim(params, &keys, num_threads);
+
+        // Follow paths through the surviving edges; `links` maps each node to the next
+        // node on its path towards the root of its tree.
+        let mut links: HashMap<u64, u64> = HashMap::new();
+        let (mut us, mut vs) = (Vec::new(), Vec::new());
+        let mut cycles = Vec::new();
+        for &edge in &alive {
+            let u0 = params.node(&keys, edge, 0);
+            let v0 = params.node(&keys, edge, 1);
+            if !path(&links, u0, &mut us) || !path(&links, v0, &mut vs) {
+                continue;
+            }
+            if us.last() == vs.last() {
+                // Both endpoints already share a root, so this edge closes a cycle.
+                let (mut nu, mut nv) = (us.len() - 1, vs.len() - 1);
+                while nu > 0 && nv > 0 && us[nu - 1] == vs[nv - 1] {
+                    nu -= 1;
+                    nv -= 1;
+                }
+                if nu + nv + 1 == params.proof_size {
+                    let mut pairs: Vec<(u64, u64)> = vec![(u0, v0)];
+                    pairs.extend(us[..=nu].windows(2).map(|w| ordered(w[0], w[1])));
+                    pairs.extend(vs[..=nv].windows(2).map(|w| ordered(w[0], w[1])));
+                    cycles.push(recover_edges(params, &keys, &alive, pairs));
+                }
+            } else if us.len() < vs.len() {
+                // Reverse the shorter path so the new edge can point along it.
+                for w in us.windows(2).rev() {
+                    links.insert(w[1], w[0]);
+                }
+                links.insert(u0, v0);
+            } else {
+                for w in vs.windows(2).rev() {
+                    links.insert(w[1], w[0]);
+                }
+                links.insert(v0, u0);
+            }
+        }
+        cycles.sort();
+        cycles.dedup();
+        cycles
+    }
+
+    /// Tries each nonce in `nonces` in turn until its graph has a cycle of the required
+    /// length.
+    ///
+    /// # Returns
+    ///
+    /// The first cycle of the first nonce that has one, or [`PuzzleError::Exhausted`] if
+    /// none of the nonces did.
+    pub fn solve_first(
+        &self,
+        nonces: RangeInclusive<u64>,
+        num_threads: usize,
+    ) -> Result<CuckooSolution, PuzzleError> {
+        for nonce in nonces {
+            if let Some(edges) = self.solve(nonce, num_threads).into_iter().next() {
+                return Ok(CuckooSolution { nonce, edges });
+            }
+        }
+        Err(PuzzleError::Exhausted)
+    }
+
+    /// Checks a solution: `proof_size` ascending, in-range edge indices that form a single
+    /// cycle in the graph for `nonce`.
+    pub fn verify(&self, nonce: u64, edges: &[u32]) -> bool {
+        let params = self.params;
+        if edges.len() != params.proof_size {
+            return false;
+        }
+        if edges.windows(2).any(|pair| pair[0] >= pair[1]) {
+            return false;
+        }
+        if edges.iter().any(|&edge| u64::from(edge) >= params.edges()) {
+            return false;
+        }
+
+        let keys = self.keys(nonce);
+        // Endpoints of edge n are at 2n (left) and 2n + 1 (right).
+        let uvs: Vec<u64> = edges
+            .iter()
+            .flat_map(|&edge| [0, 1].map(|side| params.endpoint(&keys, u64::from(edge), side)))
+            .collect();
+
+        // Walk the cycle: from an endpoint, find the one other edge sharing that node, then
+        // continue from that edge's opposite endpoint until we are back at the start.
+        let mut i = 0;
+        let mut length = 0;
+        loop {
+            let mut next = None;
+            let mut k = i;
+            loop {
+                // Same side only: step by two.
+                k = (k + 2) % uvs.len();
+                if k == i {
+                    break;
+                }
+                if uvs[k] == uvs[i] {
+                    if next.is_some() {
+                        // Three edges meet at one node.
+                        return false;
+                    }
+                    next = Some(k);
+                }
+            }
+            let Some(j) = next else {
+                // A node of degree one: not a cycle.
+                return false;
+            };
+            i = j ^ 1;
+            length += 1;
+            if i == 0 {
+                break;
+            }
+        }
+        length == params.proof_size
+    }
+}
+
+/// Trims edges that end in leaves until only (mostly) cycle candidates survive.
+///
+/// # Returns
+///
+/// The surviving edge indices in ascending order.
+fn trim(params: CuckooParams, keys: &[u64; 4], num_threads: usize) -> Vec<u64> {
+    let edges = params.edges();
+    let words = edges.div_ceil(64);
+    // One bit per edge; each thread owns a run of whole words.
+    let alive: Vec<AtomicU64> = (0..words)
+        .map(|word| {
+            let live = (edges - word * 64).min(64);
+            AtomicU64::new(if live == 64 { u64::MAX } else { (1 << live) - 1 })
+        })
+        .collect();
+    let ranges = partition_nonces(0..=words - 1, num_threads);
+
+    let for_each_alive = |ranges: &[RangeInclusive<u64>], visit: &(dyn Fn(u64, u64) + Sync)| {
+        thread::scope(|scope| {
+            for range in ranges {
+                let alive = &alive;
+                scope.spawn(move || {
+                    for word in range.clone() {
+                        let mut bits = alive[word as usize].load(Ordering::Relaxed);
+                        while bits != 0 {
+                            let bit = u64::from(bits.trailing_zeros());
+                            bits &= bits - 1;
+                            visit(word, bit);
+                        }
+                    }
+                });
+            }
+        });
+    };
+
+    for _ in 0..TRIM_ROUNDS {
+        for side in 0..2 {
+            // Degree of every node on this side, saturating at two.
+            let degree: Vec<AtomicU8> = (0..edges).map(|_| AtomicU8::new(0)).collect();
+            for_each_alive(&ranges, &|word, bit| {
+                let node = params.endpoint(keys, word * 64 + bit, side) as usize;
+                let _ = degree[node].fetch_update(Ordering::Relaxed, Ordering::Relaxed, |d| {
+                    (d < 2).then_some(d + 1)
+                });
+            });
+            for_each_alive(&ranges, &|word, bit| {
+                let node = params.endpoint(keys, word * 64 + bit, side) as usize;
+                if degree[node].load(Ordering::Relaxed) < 2 {
+                    alive[word as usize].fetch_and(!(1 << bit), Ordering::Relaxed);
+                }
+            });
+        }
+    }
+
+    alive
+        .iter()
+        .enumerate()
+        .flat_map(|(word, bits)| {
+            let bits = bits.load(Ordering::Relaxed);
+            (0..64).filter(move |bit| bits >> bit & 1 == 1).map(move |bit| word as u64 * 64 + bit)
+        })
+        .collect()
+}
+
+/// Follows `links` from `node` to the root of its tree, recording the nodes in `path`.
+///
+/// # Returns
+///
+/// `false` if the path grew longer than [`MAX_PATH_LEN`].
+fn path(links: &HashMap<u64, u64>, mut node: u64, path: &mut Vec<u64>) -> bool {
+    path.clear();
+    path.push(node);
+    while let Some(&next) = links.get(&node) {
+        if path.len() >= MAX_PATH_LEN {
+            return false;
+        }
+        node = next;
+        path.push(node);
+    }
+    true
+}
+
+/// Orders a node pair as (left, right); left nodes are even.
+fn ordered(a: u64, b: u64) -> (u64, u64) {
+    if a & 1 == 0 {
+        (a, b)
+    } else {
+        (b, a)
+    }
+}
+
+/// Finds the edge index behind each (left, right) node pair of a cycle.
+fn recover_edges(
+    params: CuckooParams,
+    keys: &[u64; 4],
+    alive: &[u64],
+    mut pairs: Vec<(u64, u64)>,
+) -> Vec<u32> {
+    let mut edges = Vec::with_capacity(pairs.len());
+    for &edge in alive {
+        let pair = (params.node(keys, edge, 0), params.node(keys, edge, 1));
+        if let Some(at) = pairs.iter().position(|&p| p == pair) {
+            pairs.swap_remove(at);
+            edges.push(edge as u32);
+        }
+    }
+    // `alive` is ascending, so the edges already are.
+    edges
+}
+
+/// Siphash-2-4 with the four key words used directly as the initial state, as in
+/// Tromp's reference implementation.
+fn siphash24(keys: &[u64; 4], nonce: u64) -> u64 {
+    let mut v = *keys;
+    v[3] ^= nonce;
+    sip_round(&mut v);
+    sip_round(&mut v);
+    v[0] ^= nonce;
+    v[2] ^= 0xff;
+    for _ in 0..4 {
+        sip_round(&mut v);
+    }
+    v[0] ^ v[1] ^ v[2] ^ v[3]
+}
+
+fn sip_round(v: &mut [u64; 4]) {
+    v[0] = v[0].wrapping_add(v[1]);
+    v[2] = v[2].wrapping_add(v[3]);
+    v[1] = v[1].rotate_left(13);
+    v[3] = v[3].rotate_left(16);
+    v[1] ^= v[0];
+    v[3] ^= v[2];
+    v[0] = v[0].rotate_left(32);
+    v[2] = v[2].wrapping_add(v[1]);
+    v[0] = v[0].wrapping_add(v[3]);
+    v[1] = v[1].rotate_left(17);
+    v[3] = v[3].rotate_left(21);
+    v[1] ^= v[2];
+    v[3] ^= v[0];
+    v[2] = v[2].rotate_left(32);
+}
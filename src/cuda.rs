@@ -0,0 +1,742 @@
+//! An optional CUDA backend for NVIDIA GPUs, behind the `cuda` feature.
+//!
+//! [`CudaMiner`] runs the nonce search as a CUDA kernel on one of the GPUs [`devices`]
+//! lists. The backend talks to the CUDA driver through a small FFI layer over its C API
+//! and compiles the kernel for the GPU at hand with NVRTC, the runtime compiler of the
+//! CUDA toolkit. Both libraries are loaded when a miner is first set up rather than
+//! linked, so the feature needs no CUDA toolkit to build, and a binary built with it
+//! still runs on machines without one and only reports [`CudaError::NoDriver`] or
+//! [`CudaError::NoCompiler`].
+//!
+//! The search works like those of the [`gpu`](crate::gpu) and [`opencl`](crate::opencl)
+//! backends and takes and returns the same types as the CPU engine. The message is hashed
+//! up to the nonce once on the CPU, each launch tests a few million consecutive nonces
+//! from that midstate, one per thread, and the CPU hashes the candidates the kernel
+//! records again before accepting one. Only SHA-256 with a fixed-width (`be` or `le`)
+//! nonce is supported, and the search cannot be paused, cancelled, checkpointed or
+//! observed.
+
+use std::borrow::Cow;
+use std::error::Error;
+use std::ffi::{c_char, c_int, c_uint, c_void, CStr, CString};
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::ptr;
+
+use libloading::Library;
+use sha2::Sha256;
+
+use crate::hybrid::Accelerator;
+use crate::lanes::Sha256Midstate;
+use crate::{
+    hash_with, CancellationToken, NonceEncoding, Puzzle, PuzzleError, Solution, Target,
+};
+
+/// How many nonces one kernel launch tests.
+const LAUNCH_NONCES: u64 = 1 << 22;
+
+/// The threads of one block of a launch.
+const BLOCK_SIZE: u32 = 256;
+
+/// How many candidates the kernel records per launch, as defined in the kernel.
+const MAX_CANDIDATES: usize = 64;
+
+/// The names the CUDA driver library goes by, tried in order.
+#[cfg(windows)]
+const DRIVER_LIBRARIES: &[&str] = &["nvcuda.dll"];
+#[cfg(not(windows))]
+const DRIVER_LIBRARIES: &[&str] = &["libcuda.so.1", "libcuda.so"];
+
+/// The names the NVRTC library goes by, tried in order.
+#[cfg(windows)]
+const NVRTC_LIBRARIES: &[&str] = &["nvrtc64_120_0.dll", "nvrtc64_112_0.dll"];
+#[cfg(not(windows))]
+const NVRTC_LIBRARIES: &[&str] = &["libnvrtc.so", "libnvrtc.so.12", "libnvrtc.so.11.2"];
+
+/// Why the CUDA backend could not list its devices or mine a puzzle.
+#[derive(Debug)]
+pub enum CudaError {
+    /// The CUDA driver library could not be loaded.
+    NoDriver,
+    /// NVRTC, the runtime compiler of the CUDA toolkit, could not be loaded.
+    NoCompiler,
+    /// The driver found no CUDA GPU.
+    NoDevice,
+    /// There is no GPU at `index` in the list [`devices`] returns.
+    UnknownDevice {
+        /// The index asked for.
+        index: usize,
+        /// How many GPUs there are.
+        count: usize,
+    },
+    /// NVRTC could not compile the kernel, with its log.
+    Compile(String),
+    /// The GPU could not be set up or failed while searching, with the driver's message.
+    Device(String),
+    /// The puzzle needs something the kernel does not do: a hash other than SHA-256 or an
+    /// `ascii` nonce.
+    Unsupported,
+    /// The GPU reported `nonce` as a solution, but its hash on the CPU does not meet the
+    /// target.
+    WrongSolution {
+        /// The nonce the GPU reported.
+        nonce: u64,
+    },
+    /// The search ran but found no solution, as a CPU search would report it.
+    Search(PuzzleError),
+}
+
+impl fmt::Display for CudaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CudaError::NoDriver => f.write_str("no CUDA driver is installed"),
+            CudaError::NoCompiler => {
+                f.write_str("NVRTC, the CUDA runtime compiler, is not installed")
+            }
+            CudaError::NoDevice => f.write_str("no CUDA GPU was found"),
+            CudaError::UnknownDevice { index, count } => {
+                write!(f, "there is no CUDA GPU {} (found {})", index, count)
+            }
+            CudaError::Compile(log) => write!(f, "the CUDA kernel does not compile: {}", log),
+            CudaError::Device(message) => write!(f, "the CUDA GPU failed: {}", message),
+            CudaError::Unsupported => {
+                f.write_str("CUDA only mines SHA-256 puzzles with a `be` or `le` nonce")
+            }
+            CudaError::WrongSolution { nonce } => {
+                write!(f, "the CUDA GPU reported nonce {}, which does not solve the puzzle", nonce)
+            }
+            CudaError::Search(e) => e.fmt(f),
+        }
+    }
+}
+
+impl Error for CudaError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CudaError::Search(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<PuzzleError> for CudaError {
+    fn from(e: PuzzleError) -> Self {
+        CudaError::Search(e)
+    }
+}
+
+// The parts of the CUDA driver and NVRTC APIs the backend uses, as declared in `cuda.h`
+// and `nvrtc.h`.
+
+type CuResult = c_int;
+type CuDevice = c_int;
+type CuContext = *mut c_void;
+type CuModule = *mut c_void;
+type CuFunction = *mut c_void;
+type CuDevicePtr = u64;
+type NvrtcResult = c_int;
+type NvrtcProgram = *mut c_void;
+
+const CUDA_SUCCESS: CuResult = 0;
+const CUDA_ERROR_NO_DEVICE: CuResult = 100;
+const CU_DEVICE_ATTRIBUTE_MULTIPROCESSOR_COUNT: c_int = 16;
+const CU_DEVICE_ATTRIBUTE_COMPUTE_CAPABILITY_MAJOR: c_int = 75;
+const CU_DEVICE_ATTRIBUTE_COMPUTE_CAPABILITY_MINOR: c_int = 76;
+const NVRTC_SUCCESS: NvrtcResult = 0;
+
+/// Opens the first of `names` that loads.
+fn open(names: &[&str]) -> Option<Library> {
+    // SAFETY: Loading the CUDA libraries runs no initialisation that could misbehave.
+    names.iter().find_map(|name| unsafe { Library::new(name) }.ok())
+}
+
+/// Looks up the function `name`, NUL-terminated, in `library`.
+///
+/// # Safety
+///
+/// `T` must be the type of the function, and the result must not outlive `library`.
+unsafe fn symbol<T: Copy>(library: &Library, name: &[u8]) -> Result<T, CudaError> {
+    let symbol = library.get::<T>(name).map_err(|e| CudaError::Device(e.to_string()))?;
+    Ok(*symbol)
+}
+
+/// The entry points of the CUDA driver, loaded from its library.
+#[derive(Debug)]
+struct Driver {
+    device_get_count: unsafe extern "C" fn(*mut c_int) -> CuResult,
+    device_get: unsafe extern "C" fn(*mut CuDevice, c_int) -> CuResult,
+    device_get_name: unsafe extern "C" fn(*mut c_char, c_int, CuDevice) -> CuResult,
+    device_get_attribute: unsafe extern "C" fn(*mut c_int, c_int, CuDevice) -> CuResult,
+    primary_ctx_retain: unsafe extern "C" fn(*mut CuContext, CuDevice) -> CuResult,
+    primary_ctx_release: unsafe extern "C" fn(CuDevice) -> CuResult,
+    ctx_push_current: unsafe extern "C" fn(CuContext) -> CuResult,
+    ctx_pop_current: unsafe extern "C" fn(*mut CuContext) -> CuResult,
+    ctx_synchronize: unsafe extern "C" fn() -> CuResult,
+    module_load_data: unsafe extern "C" fn(*mut CuModule, *const c_void) -> CuResult,
+    module_unload: unsafe extern "C" fn(CuModule) -> CuResult,
+    module_get_function: unsafe extern "C" fn(*mut CuFunction, CuModule, *const c_char) -> CuResult,
+    mem_alloc: unsafe extern "C" fn(*mut CuDevicePtr, usize) -> CuResult,
+    mem_free: unsafe extern "C" fn(CuDevicePtr) -> CuResult,
+    memcpy_htod: unsafe extern "C" fn(CuDevicePtr, *const c_void, usize) -> CuResult,
+    memcpy_dtoh: unsafe extern "C" fn(*mut c_void, CuDevicePtr, usize) -> CuResult,
+    launch_kernel: unsafe extern "C" fn(
+        CuFunction,
+        c_uint,
+        c_uint,
+        c_uint,
+        c_uint,
+        c_uint,
+        c_uint,
+        c_uint,
+        *mut c_void,
+        *mut *mut c_void,
+        *mut *mut c_void,
+    ) -> CuResult,
+    get_error_string: unsafe extern "C" fn(CuResult, *mut *const c_char) -> CuResult,
+    /// Keeps the entry points above loaded.
+    _library: Library,
+}
+
+impl Driver {
+    /// Loads the driver and initialises it.
+    fn load() -> Result<Self, CudaError> {
+        let library = open(DRIVER_LIBRARIES).ok_or(CudaError::NoDriver)?;
+        // SAFETY: Each type is that of the function in `cuda.h`; the `_v2` versions are
+        // the ones the unsuffixed names stand for since CUDA 4.0.
+        let driver = unsafe {
+            let init: unsafe extern "C" fn(c_uint) -> CuResult = symbol(&library, b"cuInit\0")?;
+            let driver = Driver {
+                device_get_count: symbol(&library, b"cuDeviceGetCount\0")?,
+                device_get: symbol(&library, b"cuDeviceGet\0")?,
+                device_get_name: symbol(&library, b"cuDeviceGetName\0")?,
+                device_get_attribute: symbol(&library, b"cuDeviceGetAttribute\0")?,
+                primary_ctx_retain: symbol(&library, b"cuDevicePrimaryCtxRetain\0")?,
+                primary_ctx_release: symbol(&library, b"cuDevicePrimaryCtxRelease_v2\0")?,
+                ctx_push_current: symbol(&library, b"cuCtxPushCurrent_v2\0")?,
+                ctx_pop_current: symbol(&library, b"cuCtxPopCurrent_v2\0")?,
+                ctx_synchronize: symbol(&library, b"cuCtxSynchronize\0")?,
+                module_load_data: symbol(&library, b"cuModuleLoadData\0")?,
+                module_unload: symbol(&library, b"cuModuleUnload\0")?,
+                module_get_function: symbol(&library, b"cuModuleGetFunction\0")?,
+                mem_alloc: symbol(&library, b"cuMemAlloc_v2\0")?,
+                mem_free: symbol(&library, b"cuMemFree_v2\0")?,
+                memcpy_htod: symbol(&library, b"cuMemcpyHtoD_v2\0")?,
+                memcpy_dtoh: symbol(&library, b"cuMemcpyDtoH_v2\0")?,
+                launch_kernel: symbol(&library, b"cuLaunchKernel\0")?,
+                get_error_string: symbol(&library, b"cuGetErrorString\0")?,
+                _library: library,
+            };
+            match init(0) {
+                CUDA_ERROR_NO_DEVICE => return Err(CudaError::NoDevice),
+                result => driver.check(result)?,
+            }
+            driver
+        };
+        Ok(driver)
+    }
+
+    /// Turns the result of a driver call into an error with the driver's description.
+    fn check(&self, result: CuResult) -> Result<(), CudaError> {
+        if result == CUDA_SUCCESS {
+            return Ok(());
+        }
+        let mut text = ptr::null();
+        // SAFETY: The driver points `text` at a static string, or leaves it null.
+        let message = unsafe {
+            (self.get_error_string)(result, &mut text);
+            if text.is_null() {
+                format!("error {}", result)
+            } else {
+                CStr::from_ptr(text).to_string_lossy().into_owned()
+            }
+        };
+        Err(CudaError::Device(message))
+    }
+
+    /// How many GPUs the driver found.
+    fn device_count(&self) -> Result<usize, CudaError> {
+        let mut count = 0;
+        // SAFETY: The driver writes one `int`.
+        self.check(unsafe { (self.device_get_count)(&mut count) })?;
+        Ok(count.max(0) as usize)
+    }
+
+    /// The GPU at `index`, as the driver knows it.
+    fn device(&self, index: usize) -> Result<CuDevice, CudaError> {
+        let count = self.device_count()?;
+        if index >= count {
+            return Err(CudaError::UnknownDevice { index, count });
+        }
+        let mut device = 0;
+        // SAFETY: The driver writes one device handle for an ordinal below the count.
+        self.check(unsafe { (self.device_get)(&mut device, index as c_int) })?;
+        Ok(device)
+    }
+
+    /// The attribute `attribute` of `device`.
+    fn attribute(&self, device: CuDevice, attribute: c_int) -> Result<u32, CudaError> {
+        let mut value = 0;
+        // SAFETY: The driver writes one `int`.
+        self.check(unsafe { (self.device_get_attribute)(&mut value, attribute, device) })?;
+        Ok(value.max(0) as u32)
+    }
+
+    /// Describes the GPU at `index`.
+    fn describe(&self, index: usize) -> Result<CudaDevice, CudaError> {
+        let device = self.device(index)?;
+        let mut name = [0 as c_char; 256];
+        // SAFETY: The driver writes a NUL-terminated name of at most `name.len()` bytes.
+        let name = unsafe {
+            self.check((self.device_get_name)(name.as_mut_ptr(), name.len() as c_int, device))?;
+            CStr::from_ptr(name.as_ptr()).to_string_lossy().trim().to_owned()
+        };
+        Ok(CudaDevice {
+            index,
+            name,
+            compute_capability: (
+                self.attribute(device, CU_DEVICE_ATTRIBUTE_COMPUTE_CAPABILITY_MAJOR)?,
+                self.attribute(device, CU_DEVICE_ATTRIBUTE_COMPUTE_CAPABILITY_MINOR)?,
+            ),
+            multiprocessors: self.attribute(device, CU_DEVICE_ATTRIBUTE_MULTIPROCESSOR_COUNT)?,
+        })
+    }
+}
+
+/// The entry points of NVRTC, loaded from its library.
+struct Nvrtc {
+    create_program: unsafe extern "C" fn(
+        *mut NvrtcProgram,
+        *const c_char,
+        *const c_char,
+        c_int,
+        *const *const c_char,
+        *const *const c_char,
+    ) -> NvrtcResult,
+    compile_program: unsafe extern "C" fn(NvrtcProgram, c_int, *const *const c_char) -> NvrtcResult,
+    get_program_log_size: unsafe extern "C" fn(NvrtcProgram, *mut usize) -> NvrtcResult,
+    get_program_log: unsafe extern "C" fn(NvrtcProgram, *mut c_char) -> NvrtcResult,
+    get_ptx_size: unsafe extern "C" fn(NvrtcProgram, *mut usize) -> NvrtcResult,
+    get_ptx: unsafe extern "C" fn(NvrtcProgram, *mut c_char) -> NvrtcResult,
+    destroy_program: unsafe extern "C" fn(*mut NvrtcProgram) -> NvrtcResult,
+    /// Keeps the entry points above loaded.
+    _library: Library,
+}
+
+impl Nvrtc {
+    /// Loads NVRTC.
+    fn load() -> Result<Self, CudaError> {
+        let library = open(NVRTC_LIBRARIES).ok_or(CudaError::NoCompiler)?;
+        // SAFETY: Each type is that of the function in `nvrtc.h`.
+        unsafe {
+            Ok(Nvrtc {
+                create_program: symbol(&library, b"nvrtcCreateProgram\0")?,
+                compile_program: symbol(&library, b"nvrtcCompileProgram\0")?,
+                get_program_log_size: symbol(&library, b"nvrtcGetProgramLogSize\0")?,
+                get_program_log: symbol(&library, b"nvrtcGetProgramLog\0")?,
+                get_ptx_size: symbol(&library, b"nvrtcGetPTXSize\0")?,
+                get_ptx: symbol(&library, b"nvrtcGetPTX\0")?,
+                destroy_program: symbol(&library, b"nvrtcDestroyProgram\0")?,
+                _library: library,
+            })
+        }
+    }
+
+    /// Compiles the kernel to PTX, NUL-terminated as the driver loads it.
+    fn compile(&self) -> Result<Vec<u8>, CudaError> {
+        let source = CString::new(include_str!("cuda.cu")).expect("no NUL in the kernel");
+        let failed = |stage: &str| CudaError::Compile(format!("{} failed", stage));
+        let mut program = ptr::null_mut();
+        // SAFETY: Every pointer passed in is valid for the call, the sizes NVRTC reports
+        // are those of the buffers it then fills, and the program is destroyed last.
+        unsafe {
+            let result = (self.create_program)(
+                &mut program,
+                source.as_ptr(),
+                c"cuda.cu".as_ptr(),
+                0,
+                ptr::null(),
+                ptr::null(),
+            );
+            if result != NVRTC_SUCCESS {
+                return Err(failed("nvrtcCreateProgram"));
+            }
+            let compiled = if (self.compile_program)(program, 0, ptr::null()) == NVRTC_SUCCESS {
+                let mut size = 0;
+                let mut ptx = Vec::new();
+                if (self.get_ptx_size)(program, &mut size) == NVRTC_SUCCESS {
+                    ptx.resize(size, 0);
+                }
+                let copied = !ptx.is_empty()
+                    && (self.get_ptx)(program, ptx.as_mut_ptr().cast()) == NVRTC_SUCCESS;
+                if copied {
+                    Ok(ptx)
+                } else {
+                    Err(failed("nvrtcGetPTX"))
+                }
+            } else {
+                let mut size = 0;
+                let mut log = Vec::new();
+                if (self.get_program_log_size)(program, &mut size) == NVRTC_SUCCESS {
+                    log.resize(size, 0u8);
+                    (self.get_program_log)(program, log.as_mut_ptr().cast());
+                }
+                let log = String::from_utf8_lossy(&log);
+                Err(CudaError::Compile(log.trim_end_matches('\0').trim().to_owned()))
+            };
+            (self.destroy_program)(&mut program);
+            compiled
+        }
+    }
+}
+
+/// One CUDA GPU, as [`devices`] lists it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CudaDevice {
+    /// The position of the GPU in the list, the CUDA device ordinal, which
+    /// [`CudaMiner::with_device`] takes.
+    pub index: usize,
+    /// The name of the GPU, as the driver reports it.
+    pub name: String,
+    /// The major and minor compute capability of the GPU.
+    pub compute_capability: (u32, u32),
+    /// How many streaming multiprocessors the GPU runs threads on.
+    pub multiprocessors: u32,
+}
+
+impl fmt::Display for CudaDevice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (major, minor) = self.compute_capability;
+        write!(
+            f,
+            "{}: {} (compute capability {}.{}, {} multiprocessors)",
+            self.index, self.name, major, minor, self.multiprocessors
+        )
+    }
+}
+
+/// Lists the CUDA GPUs of the machine, in the order of their device ordinals.
+///
+/// # Returns
+///
+/// The GPUs, possibly none, or [`CudaError::NoDriver`] if the driver library cannot be
+/// loaded.
+pub fn devices() -> Result<Vec<CudaDevice>, CudaError> {
+    let driver = match Driver::load() {
+        Err(CudaError::NoDevice) => return Ok(Vec::new()),
+        driver => driver?,
+    };
+    (0..driver.device_count()?).map(|index| driver.describe(index)).collect()
+}
+
+/// Device memory for `len` words, freed when dropped.
+struct DeviceBuffer<'a> {
+    driver: &'a Driver,
+    pointer: CuDevicePtr,
+    len: usize,
+}
+
+impl<'a> DeviceBuffer<'a> {
+    /// Allocates the buffer in the current context.
+    fn new(driver: &'a Driver, len: usize) -> Result<Self, CudaError> {
+        let mut pointer = 0;
+        // SAFETY: The driver writes one device pointer.
+        driver.check(unsafe { (driver.mem_alloc)(&mut pointer, len * 4) })?;
+        Ok(DeviceBuffer { driver, pointer, len })
+    }
+
+    /// Allocates a buffer holding `words`.
+    fn from_words(driver: &'a Driver, words: &[u32]) -> Result<Self, CudaError> {
+        let buffer = DeviceBuffer::new(driver, words.len())?;
+        buffer.write(words)?;
+        Ok(buffer)
+    }
+
+    /// Copies `words` to the start of the buffer.
+    fn write(&self, words: &[u32]) -> Result<(), CudaError> {
+        assert!(words.len() <= self.len);
+        // SAFETY: The copy stays within both the buffer and `words`.
+        let result = unsafe {
+            (self.driver.memcpy_htod)(self.pointer, words.as_ptr().cast(), words.len() * 4)
+        };
+        self.driver.check(result)
+    }
+
+    /// Copies the start of the buffer into `words`, once all launches have finished.
+    fn read(&self, words: &mut [u32]) -> Result<(), CudaError> {
+        assert!(words.len() <= self.len);
+        // SAFETY: The copy stays within both the buffer and `words`.
+        let result = unsafe {
+            (self.driver.memcpy_dtoh)(words.as_mut_ptr().cast(), self.pointer, words.len() * 4)
+        };
+        self.driver.check(result)
+    }
+}
+
+impl Drop for DeviceBuffer<'_> {
+    fn drop(&mut self) {
+        // SAFETY: The pointer came from `mem_alloc` and is freed once.
+        unsafe { (self.driver.mem_free)(self.pointer) };
+    }
+}
+
+/// The miner's context made current on this thread, until dropped.
+struct Current<'a> {
+    driver: &'a Driver,
+}
+
+impl Drop for Current<'_> {
+    fn drop(&mut self) {
+        let mut context = ptr::null_mut();
+        // SAFETY: The context pushed when `self` was made is the current one.
+        unsafe { (self.driver.ctx_pop_current)(&mut context) };
+    }
+}
+
+/// A CUDA GPU set up to mine SHA-256 puzzles; see the [module documentation](self).
+#[derive(Debug)]
+pub struct CudaMiner {
+    device: CudaDevice,
+    handle: CuDevice,
+    /// The primary context of the GPU, made current around each use.
+    context: CuContext,
+    module: CuModule,
+    function: CuFunction,
+    /// Declared last so that the library stays loaded until the fields above are freed.
+    driver: Driver,
+}
+
+// SAFETY: The context is made current on whichever thread uses the miner, and every
+// search allocates its own device memory, so threads never share mutable state.
+unsafe impl Send for CudaMiner {}
+unsafe impl Sync for CudaMiner {}
+
+impl CudaMiner {
+    /// Sets up the first CUDA GPU; see [`with_device`](CudaMiner::with_device).
+    pub fn new() -> Result<Self, CudaError> {
+        CudaMiner::with_device(0)
+    }
+
+    /// Sets up the GPU at `index` in the list [`devices`] returns and compiles the kernel
+    /// for it.
+    ///
+    /// # Returns
+    ///
+    /// The miner, [`CudaError::NoDriver`] or [`CudaError::NoCompiler`] without the CUDA
+    /// libraries, [`CudaError::NoDevice`] or [`CudaError::UnknownDevice`] if there is no
+    /// such GPU, or another [`CudaError`] if it cannot run the kernel.
+    pub fn with_device(index: usize) -> Result<Self, CudaError> {
+        let driver = Driver::load()?;
+        let device = driver.describe(index)?;
+        let handle = driver.device(index)?;
+        let ptx = Nvrtc::load()?.compile()?;
+
+        let mut context = ptr::null_mut();
+        // SAFETY: The driver writes one context handle, retained until `drop`.
+        driver.check(unsafe { (driver.primary_ctx_retain)(&mut context, handle) })?;
+        let mut miner = CudaMiner {
+            device,
+            handle,
+            context,
+            module: ptr::null_mut(),
+            function: ptr::null_mut(),
+            driver,
+        };
+        let (module, function) = {
+            let _current = miner.enter()?;
+            let driver = &miner.driver;
+            let mut module = ptr::null_mut();
+            let mut function = ptr::null_mut();
+            // SAFETY: `ptx` is NUL-terminated PTX and the kernel's name is NUL-terminated.
+            unsafe {
+                driver.check((driver.module_load_data)(&mut module, ptx.as_ptr().cast()))?;
+                let result = (driver.module_get_function)(&mut function, module, c"mine".as_ptr());
+                if let Err(e) = driver.check(result) {
+                    (driver.module_unload)(module);
+                    return Err(e);
+                }
+            }
+            (module, function)
+        };
+        miner.module = module;
+        miner.function = function;
+        Ok(miner)
+    }
+
+    /// The GPU the miner runs on.
+    pub fn device(&self) -> &CudaDevice {
+        &self.device
+    }
+
+    /// Makes the miner's context current on this thread until the result is dropped.
+    fn enter(&self) -> Result<Current<'_>, CudaError> {
+        // SAFETY: The context is retained for as long as `self` lives.
+        self.driver.check(unsafe { (self.driver.ctx_push_current)(self.context) })?;
+        Ok(Current { driver: &self.driver })
+    }
+
+    /// Searches `nonces` for a valid nonce of `puzzle` with SHA-256, like
+    /// [`parallel_mine_range`](crate::parallel_mine_range) but on the GPU.
+    ///
+    /// # Returns
+    ///
+    /// The [`Solution`], [`CudaError::Unsupported`] if the nonce encoding is `ascii`,
+    /// [`CudaError::Search`] with the [`PuzzleError`] a CPU search would give if there is
+    /// no solution, or another [`CudaError`] if the GPU failed.
+    pub fn mine(
+        &self,
+        puzzle: &Puzzle,
+        nonces: RangeInclusive<u64>,
+    ) -> Result<Solution, CudaError> {
+        self.mine_cancellable(puzzle, nonces, &CancellationToken::new())
+    }
+
+    /// Like [`mine`](Self::mine), but stops before the next launch once `token` is
+    /// cancelled, with [`PuzzleError::Cancelled`].
+    pub fn mine_cancellable(
+        &self,
+        puzzle: &Puzzle,
+        nonces: RangeInclusive<u64>,
+        token: &CancellationToken,
+    ) -> Result<Solution, CudaError> {
+        if !matches!(
+            puzzle.nonce_format.encoding,
+            NonceEncoding::BigEndian | NonceEncoding::LittleEndian
+        ) {
+            return Err(CudaError::Unsupported);
+        }
+        if puzzle.target == Target::ZERO {
+            return Err(PuzzleError::InvalidDifficulty.into());
+        }
+        if nonces.is_empty() {
+            return Err(PuzzleError::EmptyRange.into());
+        }
+        let _current = self.enter()?;
+        let mut puzzle = Cow::Borrowed(puzzle);
+        loop {
+            match (self.search(&puzzle, nonces.clone(), token), puzzle.extra_nonce) {
+                (Ok(solution), extra_nonce) => return Ok(Solution { extra_nonce, ..solution }),
+                (Err(CudaError::Search(PuzzleError::Exhausted)), Some(extra_nonce)) => {
+                    puzzle.to_mut().extra_nonce = Some(extra_nonce.wrapping_add(1));
+                }
+                (Err(e), _) => return Err(e),
+            }
+        }
+    }
+
+    /// Searches `nonces` of `puzzle` as it stands with its current extra nonce, with the
+    /// miner's context current.
+    fn search(
+        &self,
+        puzzle: &Puzzle,
+        nonces: RangeInclusive<u64>,
+        token: &CancellationToken,
+    ) -> Result<Solution, CudaError> {
+        let driver = &self.driver;
+        let message = Sha256Midstate::new(puzzle);
+        let words = |bytes: &[u8]| -> Vec<u32> {
+            bytes
+                .chunks_exact(4)
+                .map(|word| u32::from_be_bytes(word.try_into().expect("four bytes")))
+                .collect()
+        };
+        let state = DeviceBuffer::from_words(driver, &message.state)?;
+        let target = DeviceBuffer::from_words(driver, &words(&puzzle.target.to_be_bytes()))?;
+        let tail = DeviceBuffer::from_words(driver, &words(message.tail.as_flattened()))?;
+        let found = DeviceBuffer::new(driver, 1 + MAX_CANDIDATES)?;
+        let nonce_offset = message.nonce_offset as c_uint;
+        let blocks = message.tail.len() as c_uint;
+        let little_endian =
+            c_uint::from(puzzle.nonce_format.encoding == NonceEncoding::LittleEndian);
+
+        let (mut base, end) = nonces.into_inner();
+        loop {
+            if token.is_cancelled() {
+                return Err(CudaError::Search(PuzzleError::Cancelled));
+            }
+            let count = ((end - base).min(LAUNCH_NONCES - 1) + 1) as c_uint;
+            found.write(&[0])?;
+            // The kernel's parameters, each passed by a pointer to its value.
+            let mut parameters = [
+                ptr::addr_of!(state.pointer).cast_mut().cast(),
+                ptr::addr_of!(target.pointer).cast_mut().cast(),
+                ptr::addr_of!(tail.pointer).cast_mut().cast(),
+                ptr::addr_of!(base).cast_mut().cast(),
+                ptr::addr_of!(nonce_offset).cast_mut().cast(),
+                ptr::addr_of!(blocks).cast_mut().cast(),
+                ptr::addr_of!(little_endian).cast_mut().cast(),
+                ptr::addr_of!(count).cast_mut().cast(),
+                ptr::addr_of!(found.pointer).cast_mut().cast(),
+            ];
+            let mut results = [0; 1 + MAX_CANDIDATES];
+            // SAFETY: The parameters match the kernel's in number, order and type, and
+            // the values they point to outlive the launch, which the synchronisation ends.
+            unsafe {
+                driver.check((driver.launch_kernel)(
+                    self.function,
+                    count.div_ceil(BLOCK_SIZE),
+                    1,
+                    1,
+                    BLOCK_SIZE,
+                    1,
+                    1,
+                    0,
+                    ptr::null_mut(),
+                    parameters.as_mut_ptr(),
+                    ptr::null_mut(),
+                ))?;
+                driver.check((driver.ctx_synchronize)())?;
+            }
+            found.read(&mut results)?;
+
+            let found = (results[0] as usize).min(MAX_CANDIDATES);
+            // Accept the lowest candidate, once the CPU has hashed it too.
+            if let Some(&index) = results[1..=found].iter().min() {
+                let nonce = base + u64::from(index);
+                let hash = hash_with::<Sha256>(puzzle, nonce);
+                if !puzzle.target.is_met_by(&hash) {
+                    return Err(CudaError::WrongSolution { nonce });
+                }
+                return Ok(Solution { nonce, hash: hash.to_vec(), extra_nonce: None });
+            }
+            if end - base < LAUNCH_NONCES {
+                return Err(PuzzleError::Exhausted.into());
+            }
+            base += u64::from(count);
+        }
+    }
+}
+
+impl Accelerator for CudaMiner {
+    type Error = CudaError;
+
+    fn mine_cancellable(
+        &self,
+        puzzle: &Puzzle,
+        nonces: RangeInclusive<u64>,
+        token: &CancellationToken,
+    ) -> Result<Solution, CudaError> {
+        CudaMiner::mine_cancellable(self, puzzle, nonces, token)
+    }
+
+    fn into_search_error(error: CudaError) -> Result<PuzzleError, CudaError> {
+        match error {
+            CudaError::Search(e) => Ok(e),
+            e => Err(e),
+        }
+    }
+}
+
+impl Drop for CudaMiner {
+    fn drop(&mut self) {
+        // SAFETY: The module was loaded in the primary context, which is released once,
+        // after the module is unloaded.
+        unsafe {
+            if !self.module.is_null() {
+                if let Ok(_current) = self.enter() {
+                    (self.driver.module_unload)(self.module);
+                }
+            }
+            (self.driver.primary_ctx_release)(self.handle);
+        }
+    }
+}
@@ -0,0 +1,317 @@
+//! A long-running daemon that mines puzzles for other processes on the machine.
+//!
+//! Starting a process per puzzle costs a fresh set of threads every time and leaves the
+//! caller no way to ask how a search is going. A [`Daemon`] instead keeps one
+//! [`Scheduler`] running and listens on a Unix socket, where any local process can
+//! submit puzzles, ask about them, wait for them and cancel them.
+//!
+//! The protocol is line-based JSON: each request is an object on a line of its own, and
+//! each gets one object back on a line. Requests name an `op`:
+//!
+//! - `submit` queues a puzzle over `data` (text) or `data_hex`, with exactly one of
+//!   `zero_bits` and `target` (a hex target), and optionally a `priority` (higher runs
+//!   first, default 0), a `timeout` in seconds after which it is abandoned, and an
+//!   `extra_nonce`. The response carries the job's `id`.
+//! - `status` reports the job with the given `id`: its `state` and `hashes`, and once it
+//!   has finished its `elapsed` seconds and either the `nonce` and `hash` of its solution
+//!   or the `error` that ended it.
+//! - `wait` is `status`, but first blocks until the job has finished.
+//! - `cancel` stops the job and reports its status.
+//! - `shutdown` stops the daemon, cancelling every unfinished job.
+//!
+//! A job's `state` is `running`, `solved`, `exhausted`, `expired` (its timeout passed),
+//! `cancelled` or `failed`. The daemon forgets a job once it has reported it finished.
+//! Every response has `ok`, which is `false` with an `error` message if the request
+//! could not be carried out:
+//!
+//! ```text
+//! > {"op":"submit","data":"hello","zero_bits":20}
+//! < {"ok":true,"id":1}
+//! > {"op":"wait","id":1}
+//! < {"ok":true,"id":1,"state":"solved","nonce":1514533,"hash":"00000f4b...","hashes":...}
+//! ```
+//!
+//! Numbers are read exactly, so nonces up to `u64::MAX` survive the round trip. Requests
+//! are flat objects: nested objects and arrays are rejected.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::Shutdown;
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread::{self, Scope};
+use std::time::{Duration, Instant};
+
+use crate::json::{Request, Response};
+use crate::{hex, BatchResult, Difficulty, Puzzle, PuzzleError, ScheduledJob, Scheduler, Target};
+
+/// A mining daemon listening on a Unix socket; see the [module documentation](self).
+///
+/// Dropping the daemon removes its socket file.
+pub struct Daemon {
+    listener: UnixListener,
+    path: PathBuf,
+    scheduler: Scheduler,
+    /// Jobs that have not yet been reported finished, by id.
+    jobs: Mutex<HashMap<u64, Arc<ScheduledJob>>>,
+    next_job: AtomicU64,
+    /// The open connections, by id, so shutting down can close them.
+    connections: Mutex<HashMap<u64, UnixStream>>,
+    next_connection: AtomicU64,
+    shutdown: AtomicBool,
+}
+
+impl Daemon {
+    /// Listens on a socket at `path` and starts a scheduler with `num_threads` workers. A
+    /// value of zero is treated as one.
+    ///
+    /// A socket file left at `path` by a daemon that did not shut down cleanly is
+    /// replaced, but not one another daemon is still listening on.
+    ///
+    /// # Returns
+    ///
+    /// The daemon, or the error that stopped it from listening.
+    pub fn bind(path: impl AsRef<Path>, num_threads: usize) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let listener = match UnixListener::bind(&path) {
+            Err(e) if e.kind() == io::ErrorKind::AddrInUse && is_stale(&path) => {
+                fs::remove_file(&path)?;
+                UnixListener::bind(&path)?
+            }
+            listener => listener?,
+        };
+        Ok(Daemon {
+            listener,
+            path,
+            scheduler: Scheduler::new(num_threads),
+            jobs: Mutex::new(HashMap::new()),
+            next_job: AtomicU64::new(1),
+            connections: Mutex::new(HashMap::new()),
+            next_connection: AtomicU64::new(0),
+            shutdown: AtomicBool::new(false),
+        })
+    }
+
+    /// The path of the socket the daemon listens on.
+    pub fn socket_path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The number of worker threads mining submitted puzzles.
+    pub fn num_threads(&self) -> usize {
+        self.scheduler.num_threads()
+    }
+
+    /// Serves requests, each connection on a thread of its own, until a client asks the
+    /// daemon to shut down.
+    ///
+    /// Before returning, the daemon cancels every unfinished job, answers the requests
+    /// that were waiting on them and closes every connection.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` after a `shutdown` request, or the error that stopped the daemon from
+    /// accepting connections.
+    pub fn run(&self) -> io::Result<()> {
+        thread::scope(|scope| {
+            let result = self.accept(scope);
+            self.shutdown.store(true, Ordering::Relaxed);
+            for job in self.jobs().values() {
+                job.cancel();
+            }
+            // Only the reading half is closed, so the answers to requests that were
+            // waiting on the cancelled jobs still go out.
+            for connection in self.connections().values() {
+                let _ = connection.shutdown(Shutdown::Read);
+            }
+            result
+        })
+    }
+
+    /// Accepts connections until the daemon shuts down, starting a thread for each.
+    fn accept<'scope>(&'scope self, scope: &'scope Scope<'scope, '_>) -> io::Result<()> {
+        for stream in self.listener.incoming() {
+            if self.shutdown.load(Ordering::Relaxed) {
+                break;
+            }
+            let stream = stream?;
+            let id = self.next_connection.fetch_add(1, Ordering::Relaxed);
+            self.connections().insert(id, stream.try_clone()?);
+            scope.spawn(move || {
+                let _ = self.serve(stream);
+                self.connections().remove(&id);
+            });
+        }
+        Ok(())
+    }
+
+    /// Answers the requests on one connection until the client hangs up.
+    fn serve(&self, stream: UnixStream) -> io::Result<()> {
+        let mut writer = stream.try_clone()?;
+        for line in BufReader::new(stream).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let response = self.handle(&line).unwrap_or_else(|e| Response::error(&e));
+            writeln!(writer, "{}", response)?;
+            if self.shutdown.load(Ordering::Relaxed) {
+                // Wake the accept loop so it notices.
+                let _ = UnixStream::connect(&self.path);
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Carries out one request.
+    ///
+    /// # Returns
+    ///
+    /// The response, or why the request could not be carried out.
+    fn handle(&self, line: &str) -> Result<Response, String> {
+        let request = Request::parse(line)?;
+        match request.string("op")?.ok_or("missing `op`")? {
+            "submit" => self.submit(&request),
+            "status" => self.status(request.id()?, false),
+            "wait" => self.status(request.id()?, true),
+            "cancel" => {
+                let id = request.id()?;
+                self.job(id)?.cancel();
+                self.status(id, false)
+            }
+            "shutdown" => {
+                self.shutdown.store(true, Ordering::Relaxed);
+                Ok(Response::ok())
+            }
+            op => Err(format!("unknown op `{}`", op)),
+        }
+    }
+
+    /// Queues the puzzle described by a `submit` request.
+    fn submit(&self, request: &Request) -> Result<Response, String> {
+        let data = match (request.string("data")?, request.string("data_hex")?) {
+            (Some(text), None) => text.as_bytes().to_vec(),
+            (None, Some(digits)) => {
+                hex::decode(digits).map_err(|e| format!("invalid `data_hex`: {}", e))?
+            }
+            _ => return Err("exactly one of `data` and `data_hex` is required".to_string()),
+        };
+        let difficulty = match (request.number::<u32>("zero_bits")?, request.string("target")?) {
+            (Some(bits), None) => Difficulty::LeadingZeroBits(bits),
+            (None, Some(target)) => Difficulty::Target(target.parse::<Target>()?),
+            _ => return Err("exactly one of `zero_bits` and `target` is required".to_string()),
+        };
+        let mut puzzle = Puzzle::new(data, difficulty);
+        if puzzle.target == Target::ZERO {
+            return Err(PuzzleError::InvalidDifficulty.to_string());
+        }
+        puzzle.extra_nonce = request.number("extra_nonce")?;
+        let priority = request.number("priority")?.unwrap_or(0);
+        let deadline = match request.number::<f64>("timeout")? {
+            Some(seconds) => Some(
+                Duration::try_from_secs_f64(seconds)
+                    .ok()
+                    .and_then(|timeout| Instant::now().checked_add(timeout))
+                    .ok_or("`timeout` must be a non-negative number of seconds")?,
+            ),
+            None => None,
+        };
+
+        let job = self.scheduler.submit(&puzzle, priority, deadline).map_err(|e| e.to_string())?;
+        let id = self.next_job.fetch_add(1, Ordering::Relaxed);
+        self.jobs().insert(id, Arc::new(job));
+        Ok(Response::ok().number("id", id))
+    }
+
+    /// Reports the job `id`, after waiting for it to finish if `wait` is set.
+    fn status(&self, id: u64, wait: bool) -> Result<Response, String> {
+        let job = self.job(id)?;
+        let response = Response::ok().number("id", id);
+        if !wait && !job.is_finished() {
+            return Ok(response.string("state", "running").number("hashes", job.hashes()));
+        }
+        let response = job.wait_with(|outcome| response.outcome(outcome));
+        self.jobs().remove(&id);
+        Ok(response)
+    }
+
+    /// Locks the jobs. A map is never left half updated, so the lock is taken even if a
+    /// connection's thread panicked holding it.
+    fn jobs(&self) -> MutexGuard<'_, HashMap<u64, Arc<ScheduledJob>>> {
+        self.jobs.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Locks the open connections, like [`jobs`](Daemon::jobs).
+    fn connections(&self) -> MutexGuard<'_, HashMap<u64, UnixStream>> {
+        self.connections.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// The job `id`, if the daemon still knows it.
+    fn job(&self, id: u64) -> Result<Arc<ScheduledJob>, String> {
+        self.jobs().get(&id).cloned().ok_or_else(|| format!("no job {}", id))
+    }
+}
+
+impl Drop for Daemon {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+impl fmt::Debug for Daemon {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Daemon")
+            .field("path", &self.path)
+            .field("num_threads", &self.num_threads())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Whether `path` is a socket nothing listens on any more.
+fn is_stale(path: &Path) -> bool {
+    let is_socket = fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_socket());
+    is_socket && UnixStream::connect(path).is_err()
+}
+
+impl Request {
+    /// The job id the request is about.
+    fn id(&self) -> Result<u64, String> {
+        self.required("id")
+    }
+}
+
+impl Response {
+    /// Adds the fields of a finished job.
+    fn outcome(self, outcome: &BatchResult) -> Self {
+        let response = match &outcome.result {
+            Ok(solution) => {
+                let response = self
+                    .string("state", "solved")
+                    .number("nonce", solution.nonce)
+                    .string("hash", &hex::encode(&solution.hash));
+                match solution.extra_nonce {
+                    Some(extra_nonce) => response.number("extra_nonce", extra_nonce),
+                    None => response,
+                }
+            }
+            Err(e) => {
+                let state = match e {
+                    PuzzleError::Exhausted => "exhausted",
+                    PuzzleError::DeadlineExceeded => "expired",
+                    PuzzleError::Cancelled => "cancelled",
+                    _ => "failed",
+                };
+                self.string("state", state).string("error", &e.to_string())
+            }
+        };
+        response
+            .number("hashes", outcome.hashes)
+            .number("elapsed", outcome.elapsed.as_secs_f64())
+    }
+}
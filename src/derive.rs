@@ -0,0 +1,128 @@
+//! Deriving the same puzzles on both sides from a shared seed.
+//!
+//! Two parties that share a secret seed can agree on any number of challenges without
+//! sending puzzle bodies back and forth: each side derives puzzle number `index` from the
+//! seed, so only the index, and later the nonce, has to travel. The data of each puzzle
+//! is HKDF-SHA256 (RFC 5869) output: the seed is extracted into a key once, under a fixed
+//! salt, and the big-endian index is the `info` of the expansion. Different indices give
+//! unrelated puzzles, and without the seed nobody can tell which puzzle an index stands
+//! for before it is used.
+
+use std::fmt;
+
+use crate::client_puzzle::hmac_sha256;
+use crate::{validate, Difficulty, Puzzle};
+
+/// The HKDF salt, which keeps these keys apart from other uses of the same seed.
+const SALT: &[u8] = b"puzzle-derivation-v1";
+
+/// A seed that puzzles are derived from.
+#[derive(Clone)]
+pub struct SharedSeed {
+    /// The HKDF pseudorandom key extracted from the seed.
+    key: [u8; 32],
+}
+
+impl fmt::Debug for SharedSeed {
+    /// Formats the seed without its key.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SharedSeed").finish_non_exhaustive()
+    }
+}
+
+impl SharedSeed {
+    /// Extracts the derivation key from `seed`, which both parties must know and should
+    /// be at least 32 random bytes.
+    pub fn new(seed: impl AsRef<[u8]>) -> Self {
+        SharedSeed { key: extract(SALT, seed.as_ref()) }
+    }
+
+    /// The puzzle with number `index`: 32 bytes of data derived from the seed, with
+    /// `difficulty` and the default nonce format.
+    pub fn derive(&self, index: u64, difficulty: impl Into<Difficulty>) -> Puzzle {
+        Puzzle::new(self.data(index), difficulty)
+    }
+
+    /// Whether `puzzle` carries the data of puzzle number `index`, i.e. is the challenge
+    /// the other party derived for that index.
+    pub fn is_derived(&self, index: u64, puzzle: &Puzzle) -> bool {
+        puzzle.data == self.data(index)
+    }
+
+    /// Checks that `nonce` solves puzzle number `index` at `difficulty`, deriving the
+    /// puzzle afresh so the solver never has to send it.
+    pub fn verify(&self, index: u64, difficulty: impl Into<Difficulty>, nonce: u64) -> bool {
+        validate(&self.derive(index, difficulty), nonce)
+    }
+
+    /// The first block of HKDF-Expand with the index as `info`.
+    fn data(&self, index: u64) -> Vec<u8> {
+        expand(&self.key, &index.to_be_bytes()).to_vec()
+    }
+}
+
+/// HKDF-Extract: the pseudorandom key for input keying material `ikm` under `salt`.
+fn extract(salt: &[u8], ikm: &[u8]) -> [u8; 32] {
+    hmac_sha256(salt, ikm)
+}
+
+/// The first 32 bytes of HKDF-Expand of the pseudorandom key `prk` with `info`.
+fn expand(prk: &[u8; 32], info: &[u8]) -> [u8; 32] {
+    hmac_sha256(prk, &[info, &[1]].concat())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hex;
+
+    /// The bytes from `start` to `end`, as the RFC writes its longer inputs.
+    fn counting(start: u8, end: u8) -> Vec<u8> {
+        (start..=end).collect()
+    }
+
+    #[test]
+    fn hkdf_matches_rfc_5869() {
+        // Test cases 1 to 3, up to the first block of output keying material.
+        let vectors = [
+            (
+                vec![0x0b; 22],
+                counting(0x00, 0x0c),
+                counting(0xf0, 0xf9),
+                "077709362c2e32df0ddc3f0dc47bba6390b6c73bb50f9c3122ec844ad7c2b3e5",
+                "3cb25f25faacd57a90434f64d0362f2a2d2d0a90cf1a5a4c5db02d56ecc4c5bf",
+            ),
+            (
+                counting(0x00, 0x4f),
+                counting(0x60, 0xaf),
+                counting(0xb0, 0xff),
+                "06a6b88c5853361a06104c9ceb35b45cef760014904671014a193f40c15fc244",
+                "b11e398dc80327a1c8e7f78c596a49344f012eda2d4efad8a050cc4c19afa97c",
+            ),
+            (
+                vec![0x0b; 22],
+                Vec::new(),
+                Vec::new(),
+                "19ef24a32c717b167f33a91d6f648bdf96596776afdb6377ac434c1c293ccb04",
+                "8da4e775a563c18f715f802a063c5a31b8a11f5c5ee1879ec3454e5f3c738d2d",
+            ),
+        ];
+        for (ikm, salt, info, prk, okm) in vectors {
+            let key = extract(&salt, &ikm);
+            assert_eq!(hex::encode(&key), prk);
+            assert_eq!(hex::encode(&expand(&key, &info)), okm);
+        }
+    }
+
+    #[test]
+    fn puzzles_are_derived_from_the_seed_and_index() {
+        let seed = SharedSeed::new(b"a shared seed of thirty-two byte");
+        let key = extract(SALT, b"a shared seed of thirty-two byte");
+        let puzzle = seed.derive(7, 1000u64);
+        assert_eq!(puzzle.data, expand(&key, &7u64.to_be_bytes()));
+        assert!(seed.is_derived(7, &puzzle));
+        assert!(!seed.is_derived(8, &puzzle));
+        assert!(!SharedSeed::new(b"another seed").is_derived(7, &puzzle));
+        assert_eq!(seed.derive(7, 1000u64).data, puzzle.data);
+    }
+}
@@ -0,0 +1,159 @@
+//! Human-friendly ways of describing how hard a puzzle is.
+//!
+//! Every puzzle is ultimately checked against a 256-bit [`Target`]; a [`Difficulty`] is
+//! just a convenient way to build one. The original model only looks at the first two
+//! bytes of the hash, which caps the meaningful difficulty at 16 bits.
+//! [`Difficulty::LeadingZeroBits`] counts zero bits across the whole digest, so puzzles
+//! can be made arbitrarily hard, and [`Difficulty::Target`] takes the target directly,
+//! which is how Bitcoin's compact nBits values and sub-power-of-two difficulties are
+//! expressed.
+
+use std::fmt;
+
+use crate::target::{CompactTargetError, Target};
+
+/// How hard a puzzle is. Convert it into the target a digest must meet with
+/// [`Difficulty::to_target`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Difficulty {
+    /// The first two bytes of the hash, read as a big-endian `u16`, must be below this
+    /// threshold. Lower values make it much harder to find a valid nonce.
+    Threshold(u64),
+    /// The hash must start with at least this many zero bits. Each extra bit doubles the
+    /// expected amount of work.
+    LeadingZeroBits(u32),
+    /// The hash, read as a big-endian number, must be less than or equal to this
+    /// 256-bit target.
+    Target(Target),
+}
+
+impl Difficulty {
+    /// Builds a [`Difficulty::Target`] from a Bitcoin compact "nBits" value.
+    ///
+    /// See [`Target::from_compact`] for the encoding and the possible errors.
+    pub fn from_compact(bits: u32) -> Result<Self, CompactTargetError> {
+        Target::from_compact(bits).map(Difficulty::Target)
+    }
+
+    /// Converts this difficulty into the equivalent 256-bit target.
+    ///
+    /// - `Threshold(t)` becomes `t * 2^240 - 1`, so a digest meets it exactly when its
+    ///   first two bytes are below `t`. Thresholds above `u16::MAX` accept every digest,
+    ///   and a zero threshold gives [`Target::ZERO`].
+    /// - `LeadingZeroBits(n)` becomes `2^(256 - n) - 1`.
+    /// - `Target(t)` is returned unchanged.
+    pub fn to_target(&self) -> Target {
+        match *self {
+            Difficulty::Threshold(0) => Target::ZERO,
+            Difficulty::Threshold(threshold) if threshold > u16::MAX as u64 => Target::MAX,
+            Difficulty::Threshold(threshold) => {
+                let mut bytes = [0xffu8; 32];
+                bytes[..2].copy_from_slice(&((threshold - 1) as u16).to_be_bytes());
+                Target::from_be_bytes(bytes)
+            }
+            Difficulty::LeadingZeroBits(bits) => Target::from_leading_zero_bits(bits),
+            Difficulty::Target(target) => target,
+        }
+    }
+
+    /// Checks whether `hash` satisfies this difficulty.
+    ///
+    /// # Parameters
+    ///
+    /// - `hash`: The raw digest bytes, of any length.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the digest meets [`Difficulty::to_target`]; otherwise, `false`.
+    pub fn is_met_by(&self, hash: &[u8]) -> bool {
+        self.to_target().is_met_by(hash)
+    }
+}
+
+impl From<u64> for Difficulty {
+    /// Interprets a bare number as the original two-byte threshold.
+    fn from(threshold: u64) -> Self {
+        Difficulty::Threshold(threshold)
+    }
+}
+
+impl From<Target> for Difficulty {
+    fn from(target: Target) -> Self {
+        Difficulty::Target(target)
+    }
+}
+
+impl fmt::Display for Difficulty {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Difficulty::Threshold(threshold) => write!(f, "threshold < {}", threshold),
+            Difficulty::LeadingZeroBits(bits) => write!(f, "{} leading zero bits", bits),
+            Difficulty::Target(target) => {
+                write!(f, "target <= {} (nBits {:#010x})", target, target.to_compact())
+            }
+        }
+    }
+}
+
+/// Counts the number of leading zero bits in `hash`, treating it as one big-endian number.
+///
+/// An all-zero digest returns `hash.len() * 8`.
+pub fn leading_zero_bits(hash: &[u8]) -> u32 {
+    let mut bits = 0;
+    for &byte in hash {
+        if byte == 0 {
+            bits += 8;
+        } else {
+            bits += byte.leading_zeros();
+            break;
+        }
+    }
+    bits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn difficulties_convert_to_targets() {
+        let threshold = Difficulty::Threshold(0x1234).to_target();
+        assert_eq!(threshold.to_string(), format!("1233{}", "f".repeat(60)));
+        assert_eq!(Difficulty::Threshold(1).to_target(), Target::from_leading_zero_bits(16));
+        assert_eq!(Difficulty::Threshold(0).to_target(), Target::ZERO);
+        assert_eq!(Difficulty::Threshold(0x1_0000).to_target(), Target::MAX);
+        assert_eq!(Difficulty::Threshold(u64::MAX).to_target(), Target::MAX);
+
+        let bits = Difficulty::LeadingZeroBits(20).to_target();
+        assert_eq!(bits.to_string(), format!("00000{}", "f".repeat(59)));
+        assert_eq!(Difficulty::LeadingZeroBits(0).to_target(), Target::MAX);
+        assert_eq!(Difficulty::LeadingZeroBits(256).to_target(), Target::ZERO);
+
+        let genesis = Target::from_compact(0x1d00_ffff).unwrap();
+        assert_eq!(Difficulty::Target(genesis).to_target(), genesis);
+        assert_eq!(Difficulty::from_compact(0x1d00_ffff), Ok(Difficulty::Target(genesis)));
+        assert_eq!(Difficulty::from_compact(0x1d80_ffff), Err(CompactTargetError::Negative));
+
+        assert_eq!(Difficulty::from(0x1234), Difficulty::Threshold(0x1234));
+        assert_eq!(Difficulty::from(genesis), Difficulty::Target(genesis));
+    }
+
+    #[test]
+    fn thresholds_look_only_at_the_first_two_bytes() {
+        let difficulty = Difficulty::Threshold(0x1234);
+        assert!(difficulty.is_met_by(&[0x12, 0x33, 0xff, 0xff]));
+        assert!(difficulty.is_met_by(&[0x12, 0x33]));
+        assert!(!difficulty.is_met_by(&[0x12, 0x34, 0x00, 0x00]));
+        assert!(!Difficulty::Threshold(0).is_met_by(&[0x00, 0x00, 0x00, 0x01]));
+        assert!(Difficulty::Threshold(0x1_0000).is_met_by(&[0xff; 32]));
+    }
+
+    #[test]
+    fn leading_zero_bits_span_bytes() {
+        assert_eq!(leading_zero_bits(&[]), 0);
+        assert_eq!(leading_zero_bits(&[0x80]), 0);
+        assert_eq!(leading_zero_bits(&[0x00, 0x01]), 15);
+        assert_eq!(leading_zero_bits(&[0x00, 0x00, 0x10, 0x00]), 19);
+        assert_eq!(leading_zero_bits(&[0; 20]), 160);
+    }
+}
@@ -0,0 +1,149 @@
+//! Finding a work server on the local network over mDNS, with the `mdns` feature.
+//!
+//! A worker normally needs the URL of its coordinator. On a LAN, such as a classroom
+//! of machines, the coordinator can instead announce itself with multicast DNS: an
+//! [`Advertisement`] registers it as a [`SERVICE_TYPE`] service, with a `scheme` of
+//! `http` or `https` in its TXT record, for as long as it is kept, and [`discover`]
+//! browses for such a service and returns the URL of the first one that answers. Both
+//! ends run the responder of the `mdns-sd` crate on a thread of its own.
+//!
+//! Multicast DNS does not cross routers, and anyone on the network can announce a
+//! coordinator, so a worker that must not be misled should be given the URL, and
+//! a certificate to check it against, instead.
+
+use std::error::Error;
+use std::fmt;
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+use std::time::{Duration, Instant};
+
+use mdns_sd::{ServiceDaemon, ServiceEvent, ServiceInfo};
+
+/// The DNS-SD service type a work server is announced as.
+pub const SERVICE_TYPE: &str = "_puzzle-work._tcp.local.";
+
+/// How long [`discover`] is usually given to find a work server.
+pub const DEFAULT_DISCOVERY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The reasons a work server could not be announced or found.
+#[derive(Debug)]
+pub enum DiscoveryError {
+    /// The mDNS responder could not be started or refused the request.
+    Mdns(String),
+    /// No work server answered in time.
+    NotFound,
+}
+
+impl fmt::Display for DiscoveryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DiscoveryError::Mdns(message) => write!(f, "mDNS failed: {}", message),
+            DiscoveryError::NotFound => write!(f, "no work server found on the local network"),
+        }
+    }
+}
+
+impl Error for DiscoveryError {}
+
+impl From<mdns_sd::Error> for DiscoveryError {
+    fn from(e: mdns_sd::Error) -> Self {
+        DiscoveryError::Mdns(e.to_string())
+    }
+}
+
+/// A work server's announcement on the local network, withdrawn when dropped.
+pub struct Advertisement {
+    daemon: ServiceDaemon,
+    fullname: String,
+}
+
+impl Advertisement {
+    /// Announces the work server listening on `addr`, which speaks `scheme` (`http` or
+    /// `https`). A server listening on every interface is announced with the addresses of
+    /// all of them, and keeps up with them as they change.
+    ///
+    /// # Returns
+    ///
+    /// The announcement, or the error that stopped it.
+    pub fn new(addr: SocketAddr, scheme: &str) -> Result<Self, DiscoveryError> {
+        let daemon = ServiceDaemon::new()?;
+        // The process id tells apart coordinators on the same machine and port.
+        let name = format!("puzzle-solver-{}-{}", std::process::id(), addr.port());
+        let host = format!("{}.local.", name);
+        let properties = [("scheme", scheme)];
+        let service = if addr.ip().is_unspecified() {
+            ServiceInfo::new(SERVICE_TYPE, &name, &host, (), addr.port(), &properties[..])?
+                .enable_addr_auto()
+        } else {
+            ServiceInfo::new(SERVICE_TYPE, &name, &host, addr.ip(), addr.port(), &properties[..])?
+        };
+        let fullname = service.get_fullname().to_string();
+        daemon.register(service)?;
+        Ok(Advertisement { daemon, fullname })
+    }
+
+    /// The full DNS-SD name the server is announced under.
+    pub fn name(&self) -> &str {
+        &self.fullname
+    }
+}
+
+impl fmt::Debug for Advertisement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Advertisement").field("name", &self.fullname).finish_non_exhaustive()
+    }
+}
+
+impl Drop for Advertisement {
+    fn drop(&mut self) {
+        // Waiting for the goodbye to go out spares workers a stale answer.
+        if let Ok(status) = self.daemon.unregister(&self.fullname) {
+            let _ = status.recv_timeout(Duration::from_secs(1));
+        }
+        let _ = self.daemon.shutdown();
+    }
+}
+
+/// Browses the local network for a work server for up to `timeout`.
+///
+/// # Returns
+///
+/// The URL of the first work server to answer, such as `http://192.168.1.20:8080`, or
+/// [`DiscoveryError::NotFound`] if none did in time.
+pub fn discover(timeout: Duration) -> Result<String, DiscoveryError> {
+    let daemon = ServiceDaemon::new()?;
+    let events = daemon.browse(SERVICE_TYPE)?;
+    let deadline = Instant::now() + timeout;
+    let mut found = None;
+    while let Ok(event) = events.recv_deadline(deadline) {
+        if let ServiceEvent::ServiceResolved(service) = event {
+            found = url(&service);
+            if found.is_some() {
+                break;
+            }
+        }
+    }
+    let _ = daemon.shutdown();
+    found.ok_or(DiscoveryError::NotFound)
+}
+
+/// The URL of an announced work server, at its first IPv4 address if it has one, and
+/// otherwise at an IPv6 address that, unlike a link-local one, needs no interface.
+fn url(service: &ServiceInfo) -> Option<String> {
+    let addresses = service.get_addresses();
+    let ip = addresses.iter().find(|ip| ip.is_ipv4()).or_else(|| {
+        addresses.iter().find(|ip| matches!(ip, IpAddr::V6(ip) if !is_link_local(ip)))
+    })?;
+    let scheme = match service.get_property_val_str("scheme") {
+        Some("https") => "https",
+        _ => "http",
+    };
+    Some(match ip {
+        IpAddr::V4(ip) => format!("{}://{}:{}", scheme, ip, service.get_port()),
+        IpAddr::V6(ip) => format!("{}://[{}]:{}", scheme, ip, service.get_port()),
+    })
+}
+
+/// Whether `ip` is in fe80::/10.
+fn is_link_local(ip: &Ipv6Addr) -> bool {
+    ip.segments()[0] & 0xffc0 == 0xfe80
+}
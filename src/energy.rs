@@ -0,0 +1,92 @@
+//! Measuring the energy a search consumes, for comparing the efficiency of algorithms and
+//! thread counts.
+//!
+//! Intel and AMD processors count the energy each package has drawn in their RAPL
+//! (running average power limit) registers, which Linux exposes under
+//! `/sys/class/powercap`. An [`EnergyMeter`] reads every package's counter when it starts
+//! and again when asked, and every search on a [`MiningHandle`](crate::MiningHandle)
+//! records what its [`MiningReport`](crate::MiningReport) consumed this way.
+//!
+//! The counters measure the whole package, so anything else running at the same time
+//! counts too, and recent kernels only let root read them. Without readable counters,
+//! as on other platforms, nothing is measured.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Where Linux exposes the RAPL counters.
+const POWERCAP: &str = "/sys/class/powercap";
+
+/// Measures the energy the processor packages consume from the moment it starts; see
+/// the [module documentation](self).
+#[derive(Clone, Debug)]
+pub struct EnergyMeter {
+    packages: Vec<Package>,
+}
+
+/// One package's counter and its reading when the meter started.
+#[derive(Clone, Debug)]
+struct Package {
+    /// The file holding the counter, in microjoules.
+    counter: PathBuf,
+    /// The counter's value when the meter started.
+    start: u64,
+    /// The value after which the counter wraps round to zero.
+    range: u64,
+}
+
+impl EnergyMeter {
+    /// Starts measuring.
+    ///
+    /// # Returns
+    ///
+    /// The meter, or `None` if no package's counter can be read.
+    pub fn start() -> Option<Self> {
+        let mut packages = Vec::new();
+        for entry in fs::read_dir(POWERCAP).ok()?.flatten() {
+            let dir = entry.path();
+            // Each package is a top-level zone such as `intel-rapl:0`, named `package-0`;
+            // its subzones, such as `intel-rapl:0:0`, are parts of it.
+            let Some(zone) = entry.file_name().to_str().map(str::to_owned) else {
+                continue;
+            };
+            let top_level = zone.split(':').count() == 2;
+            let name = fs::read_to_string(dir.join("name")).unwrap_or_default();
+            if !top_level || !name.starts_with("package") {
+                continue;
+            }
+            let counter = dir.join("energy_uj");
+            let (Some(start), Some(range)) =
+                (read_counter(&counter), read_counter(&dir.join("max_energy_range_uj")))
+            else {
+                continue;
+            };
+            packages.push(Package { counter, start, range });
+        }
+        (!packages.is_empty()).then_some(EnergyMeter { packages })
+    }
+
+    /// The energy consumed since the meter started, in microjoules.
+    ///
+    /// A counter that wraps round more than once between the start and this call, which
+    /// takes hours even on a busy server, is counted as wrapping once.
+    ///
+    /// # Returns
+    ///
+    /// The energy, or `None` if a counter can no longer be read.
+    pub fn microjoules(&self) -> Option<u64> {
+        self.packages.iter().try_fold(0, |total: u64, package| {
+            let now = read_counter(&package.counter)?;
+            let consumed = match now.checked_sub(package.start) {
+                Some(consumed) => consumed,
+                None => package.range.saturating_sub(package.start) + now,
+            };
+            Some(total + consumed)
+        })
+    }
+}
+
+/// Reads a counter file holding a whole number.
+fn read_counter(path: &Path) -> Option<u64> {
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
@@ -0,0 +1,479 @@
+//! Equihash, a memory-bound proof of work based on the generalized birthday problem.
+//!
+//! Unlike the nonce miners, an Equihash solution is not a single lucky hash. For
+//! parameters `(n, k)` the input (data and nonce) defines `2^(n/(k+1)+1)` strings of `n`
+//! bits, and a solution is a set of `2^k` distinct indices whose strings XOR to zero,
+//! arranged so that every subtree of the index list also collides on a growing number
+//! of leading bits. Hashing follows Zcash: personalised BLAKE2b (`"ZcashPoW" || n || k`)
+//! over `data || nonce`, with the nonce as 8 little-endian bytes.
+//!
+//! [`EquihashPuzzle::solve`] runs Wagner's algorithm: generate every string, then for `k`
+//! rounds sort the rows by the next `n/(k+1)` bits and XOR together the pairs that
+//! collide. Generating the strings and combining the collision buckets are split across
+//! threads, the buckets, whose sizes vary, on the work-stealing engine of the
+//! [`steal`](crate::steal) module; sorting each round is the only sequential step.
+//!
+//! The solver keeps every index list in memory, so it is meant for small parameters such
+//! as `(96, 5)` or `(48, 5)`. Zcash's `(200, 9)` needs several gigabytes this way.
+
+use std::error::Error;
+use std::fmt;
+use std::iter;
+use std::ops::{Range, RangeInclusive};
+use std::thread;
+
+use blake2b_simd::{Params, State};
+use rand::RngCore;
+
+use crate::steal::{steal_collect, Spawner, StealSearch};
+use crate::{partition_nonces, PuzzleError};
+
+/// Equihash `(n, k)` parameters.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct EquihashParams {
+    n: u32,
+    k: u32,
+}
+
+/// Why a pair of Equihash parameters was rejected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InvalidEquihashParams {
+    /// The rejected `n`.
+    pub n: u32,
+    /// The rejected `k`.
+    pub k: u32,
+}
+
+impl fmt::Display for InvalidEquihashParams {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid Equihash parameters ({}, {}): need n a multiple of 8 and of k + 1, \
+             n <= 512, k >= 1, 1 <= n / (k + 1) < 31 and k <= n / (k + 1) + 1",
+            self.n, self.k
+        )
+    }
+}
+
+impl Error for InvalidEquihashParams {}
+
+impl EquihashParams {
+    /// Checks and builds `(n, k)`.
+    ///
+    /// The collision bits `n / (k + 1)` must be below 31 so the `2^(n / (k + 1) + 1)`
+    /// strings can be indexed by a `u32`, and a solution's `2^k` indices must fit among
+    /// them; together these also keep `k` below 32.
+    pub fn new(n: u32, k: u32) -> Result<Self, InvalidEquihashParams> {
+        let valid = k >= 1
+            && n.is_multiple_of(8)
+            && n <= 512
+            && n.is_multiple_of(k + 1)
+            && (1..31).contains(&(n / (k + 1)))
+            && k <= n / (k + 1) + 1;
+        if valid {
+            Ok(EquihashParams { n, k })
+        } else {
+            Err(InvalidEquihashParams { n, k })
+        }
+    }
+
+    /// The string length `n` in bits.
+    pub fn n(&self) -> u32 {
+        self.n
+    }
+
+    /// The number of rounds `k`.
+    pub fn k(&self) -> u32 {
+        self.k
+    }
+
+    /// How many bits collide in each round, `n / (k + 1)`.
+    pub fn collision_bits(&self) -> u32 {
+        self.n / (self.k + 1)
+    }
+
+    /// How many strings the input defines, `2^(collision_bits + 1)`.
+    pub fn string_count(&self) -> u32 {
+        1 << (self.collision_bits() + 1)
+    }
+
+    /// How many indices a solution has, `2^k`.
+    pub fn solution_len(&self) -> usize {
+        1 << self.k
+    }
+
+    /// Bytes per string.
+    fn string_bytes(&self) -> usize {
+        self.n as usize / 8
+    }
+
+    /// How many strings one BLAKE2b output holds.
+    fn strings_per_hash(&self) -> u32 {
+        512 / self.n
+    }
+
+    /// The BLAKE2b state after absorbing the personalisation and `input`.
+    fn base_state(&self, input: &[u8]) -> State {
+        let mut personal = [0u8; 16];
+        personal[..8].copy_from_slice(b"ZcashPoW");
+        personal[8..12].copy_from_slice(&self.n.to_le_bytes());
+        personal[12..].copy_from_slice(&self.k.to_le_bytes());
+        let mut state = Params::new()
+            .hash_length(self.strings_per_hash() as usize * self.string_bytes())
+            .personal(&personal)
+            .to_state();
+        state.update(input);
+        state
+    }
+
+    /// The `n`-bit string with index `index`.
+    fn string(&self, base: &State, index: u32) -> Vec<u8> {
+        let per_hash = self.strings_per_hash();
+        let hash = base.clone().update(&(index / per_hash).to_le_bytes()).finalize();
+        let start = (index % per_hash) as usize * self.string_bytes();
+        hash.as_bytes()[start..start + self.string_bytes()].to_vec()
+    }
+}
+
+impl Default for EquihashParams {
+    /// `(96, 5)`, small enough to solve in well under a second.
+    fn default() -> Self {
+        EquihashParams { n: 96, k: 5 }
+    }
+}
+
+/// A solved Equihash puzzle.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct EquihashSolution {
+    /// The nonce that was appended to the data.
+    pub nonce: u64,
+    /// The `2^k` indices, in the order the verifier checks them.
+    pub indices: Vec<u32>,
+}
+
+/// An Equihash puzzle: parameters plus the data that every attempt hashes.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct EquihashPuzzle {
+    /// The `(n, k)` parameters.
+    pub params: EquihashParams,
+    /// The data hashed in front of the nonce.
+    pub data: Vec<u8>,
+}
+
+/// One partial solution: the XOR of its strings and the indices that produced it.
+struct Row {
+    hash: Vec<u8>,
+    indices: Vec<u32>,
+}
+
+impl EquihashPuzzle {
+    /// Creates a puzzle over `data`.
+    pub fn new(params: EquihashParams, data: impl Into<Vec<u8>>) -> Self {
+        EquihashPuzzle { params, data: data.into() }
+    }
+
+    /// Generates a puzzle over 32 random bytes of data.
+    pub fn generate(params: EquihashParams) -> Self {
+        let mut data = vec![0u8; 32];
+        rand::thread_rng().fill_bytes(&mut data);
+        EquihashPuzzle::new(params, data)
+    }
+
+    /// The bytes hashed for `nonce`: `data || nonce` (little-endian).
+    fn input(&self, nonce: u64) -> Vec<u8> {
+        [self.data.as_slice(), &nonce.to_le_bytes()].concat()
+    }
+
+    /// Finds every solution for `nonce` on `num_threads` threads.
+    ///
+    /// Most nonces have one or two solutions, some have none.
+    ///
+    /// # Returns
+    ///
+    /// The solutions, or [`PuzzleError::WorkerPanicked`] if a thread panicked.
+    pub fn solve(&self, nonce: u64, num_threads: usize) -> Result<Vec<Vec<u32>>, PuzzleError> {
+        let params = self.params;
+        let bits = params.collision_bits() as usize;
+        let base = params.base_state(&self.input(nonce));
+
+        // Round 0: every string, generated in parallel.
+        let count = u64::from(params.string_count());
+        let mut rows = thread::scope(|scope| {
+            let handles: Vec<_> = partition_nonces(0..=count - 1, num_threads)
+                .into_iter()
+                .map(|range| {
+                    let base = &base;
+                    scope.spawn(move || {
+                        range
+                            .map(|index| {
+                                let index = index as u32;
+                                Row { hash: params.string(base, index), indices: vec![index] }
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            handles.into_iter().try_fold(Vec::new(), |mut rows, handle| {
+                rows.extend(handle.join().map_err(|_| PuzzleError::WorkerPanicked)?);
+                Ok::<_, PuzzleError>(rows)
+            })
+        })?;
+
+        // The first k - 1 rounds collide on the next `bits` bits, the last on the final
+        // 2 * bits, which leaves only pairs that XOR to zero.
+        for round in 0..params.k as usize {
+            let last = round + 1 == params.k as usize;
+            let width = if last { 2 * bits } else { bits };
+            let start = round * bits;
+            rows.sort_unstable_by_key(|row| read_bits(&row.hash, start, width));
+            let buckets = buckets(&rows, |row| read_bits(&row.hash, start, width));
+            rows = combine_parallel(&rows, &buckets, num_threads, last)?;
+        }
+
+        // The same index set can be reached along several paths; keep one of each.
+        let mut solutions: Vec<(Vec<u32>, Vec<u32>)> = rows
+            .into_iter()
+            .map(|row| {
+                let mut sorted = row.indices.clone();
+                sorted.sort_unstable();
+                (sorted, row.indices)
+            })
+            .collect();
+        solutions.sort_unstable();
+        solutions.dedup_by(|a, b| a.0 == b.0);
+        Ok(solutions.into_iter().map(|(_, indices)| indices).collect())
+    }
+
+    /// Tries each nonce in `nonces` in turn until one has a solution.
+    ///
+    /// # Returns
+    ///
+    /// The first solution of the first nonce that has one,
+    /// [`PuzzleError::Exhausted`] if none of the nonces did, or
+    /// [`PuzzleError::WorkerPanicked`] if a thread panicked.
+    pub fn solve_first(
+        &self,
+        nonces: RangeInclusive<u64>,
+        num_threads: usize,
+    ) -> Result<EquihashSolution, PuzzleError> {
+        for nonce in nonces {
+            if let Some(indices) = self.solve(nonce, num_threads)?.into_iter().next() {
+                return Ok(EquihashSolution { nonce, indices });
+            }
+        }
+        Err(PuzzleError::Exhausted)
+    }
+
+    /// Checks a solution: the right number of distinct, in-range indices, in canonical
+    /// order, whose strings XOR to zero with every subtree colliding on its leading bits.
+    pub fn verify(&self, nonce: u64, indices: &[u32]) -> bool {
+        let params = self.params;
+        if indices.len() != params.solution_len() {
+            return false;
+        }
+        if indices.iter().any(|&index| index >= params.string_count()) {
+            return false;
+        }
+        let mut sorted = indices.to_vec();
+        sorted.sort_unstable();
+        if sorted.windows(2).any(|pair| pair[0] == pair[1]) {
+            return false;
+        }
+
+        let base = params.base_state(&self.input(nonce));
+        let mut rows: Vec<Row> = indices
+            .iter()
+            .map(|&index| Row { hash: params.string(&base, index), indices: vec![index] })
+            .collect();
+        let bits = params.collision_bits() as usize;
+        for level in 0..params.k as usize {
+            let mut next = Vec::with_capacity(rows.len() / 2);
+            for pair in rows.chunks(2) {
+                let (left, right) = (&pair[0], &pair[1]);
+                if left.indices[0] >= right.indices[0] {
+                    return false;
+                }
+                let hash = xor(&left.hash, &right.hash);
+                if read_bits(&hash, 0, (level + 1) * bits) != 0 {
+                    return false;
+                }
+                let indices = [left.indices.as_slice(), &right.indices].concat();
+                next.push(Row { hash, indices });
+            }
+            rows = next;
+        }
+        rows[0].hash.iter().all(|&byte| byte == 0)
+    }
+}
+
+/// Reads `len` bits (at most 64) starting at bit `start` of `bytes`, most significant first.
+fn read_bits(bytes: &[u8], start: usize, len: usize) -> u64 {
+    (start..start + len).fold(0, |acc, bit| {
+        (acc << 1) | u64::from((bytes[bit / 8] >> (7 - bit % 8)) & 1)
+    })
+}
+
+fn xor(a: &[u8], b: &[u8]) -> Vec<u8> {
+    a.iter().zip(b).map(|(x, y)| x ^ y).collect()
+}
+
+/// Splits sorted `rows` into runs with the same `key`, as index ranges.
+fn buckets(rows: &[Row], key: impl Fn(&Row) -> u64) -> Vec<(usize, usize)> {
+    let mut buckets = Vec::new();
+    let mut start = 0;
+    for i in 1..=rows.len() {
+        if i == rows.len() || key(&rows[i]) != key(&rows[start]) {
+            if i - start > 1 {
+                buckets.push((start, i));
+            }
+            start = i;
+        }
+    }
+    buckets
+}
+
+/// XORs every pair inside each bucket, spreading the buckets across `num_threads`.
+///
+/// On the last round only pairs whose XOR is entirely zero are kept. The combined rows
+/// come out in the order of their buckets, whichever thread combined them.
+///
+/// # Returns
+///
+/// The combined rows, or [`PuzzleError::WorkerPanicked`] if a thread panicked.
+fn combine_parallel(
+    rows: &[Row],
+    buckets: &[(usize, usize)],
+    num_threads: usize,
+    last: bool,
+) -> Result<Vec<Row>, PuzzleError> {
+    if buckets.is_empty() {
+        return Ok(Vec::new());
+    }
+    let combine = Combine { rows, buckets, last };
+    let mut pieces = steal_collect(&combine, iter::once(0..buckets.len()), num_threads)?;
+    pieces.sort_unstable_by_key(|&(start, _)| start);
+    Ok(pieces.into_iter().flat_map(|(_, combined)| combined).collect())
+}
+
+/// The most buckets [`Combine`] leaves in one task. A bucket's work grows with the square
+/// of its size, so equal shares of the buckets can take very different times; small
+/// tasks let idle threads steal what is left of a slow one's.
+const COMBINE_GRAIN: usize = 64;
+
+/// Combining the pairs of a range of buckets, as a task of the work-stealing engine.
+struct Combine<'a> {
+    rows: &'a [Row],
+    buckets: &'a [(usize, usize)],
+    last: bool,
+}
+
+impl StealSearch for Combine<'_> {
+    type Task = Range<usize>;
+    type State = ();
+    /// The index of the task's first bucket and the rows it combined.
+    type Output = (usize, Vec<Row>);
+
+    fn init(&self) {}
+
+    fn run(
+        &self,
+        _: &mut (),
+        task: Range<usize>,
+        spawner: &Spawner<'_, Range<usize>>,
+    ) -> Option<(usize, Vec<Row>)> {
+        let Range { start, mut end } = task;
+        while end - start > COMBINE_GRAIN {
+            let middle = start + (end - start) / 2;
+            spawner.spawn(middle..end);
+            end = middle;
+        }
+        let mut combined = Vec::new();
+        for &(first, last) in &self.buckets[start..end] {
+            for i in first..last {
+                for j in i + 1..last {
+                    if let Some(row) = combine(&self.rows[i], &self.rows[j], self.last) {
+                        combined.push(row);
+                    }
+                }
+            }
+        }
+        Some((start, combined))
+    }
+}
+
+/// Merges two colliding rows, or returns `None` if they share an index (or, on the last
+/// round, do not XOR to zero).
+fn combine(a: &Row, b: &Row, last: bool) -> Option<Row> {
+    if a.indices.iter().any(|index| b.indices.contains(index)) {
+        return None;
+    }
+    let hash = xor(&a.hash, &b.hash);
+    if last && hash.iter().any(|&byte| byte != 0) {
+        return None;
+    }
+    // The subtree with the smaller first index goes on the left.
+    let (left, right) = if a.indices[0] < b.indices[0] { (a, b) } else { (b, a) };
+    Some(Row { hash, indices: [left.indices.as_slice(), &right.indices].concat() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parameters_the_solver_cannot_index_are_rejected() {
+        for (n, k) in [(96, 5), (48, 5), (144, 5), (200, 9), (56, 1), (240, 7), (8, 1), (8, 3)] {
+            assert!(EquihashParams::new(n, k).is_ok(), "({}, {})", n, k);
+        }
+        // Too few strings to hold a solution's 2^k distinct indices.
+        for (n, k) in [(8, 7), (24, 7), (256, 255), (64, 31)] {
+            let error = EquihashParams::new(n, k).unwrap_err();
+            assert_eq!(error, InvalidEquihashParams { n, k });
+        }
+        // 2^(collision bits + 1) strings would not fit a u32 index.
+        for (n, k) in [(64, 1), (248, 7), (496, 15)] {
+            assert!(EquihashParams::new(n, k).is_err(), "({}, {})", n, k);
+        }
+        // The old checks on n and k still apply.
+        for (n, k) in [(0, 1), (96, 0), (100, 4), (96, 6), (520, 12)] {
+            assert!(EquihashParams::new(n, k).is_err(), "({}, {})", n, k);
+        }
+    }
+
+    #[test]
+    fn solutions_verify_and_tampered_ones_do_not() {
+        let params = EquihashParams::new(48, 5).unwrap();
+        let puzzle = EquihashPuzzle::new(params, "equihash tests");
+        let solution = puzzle.solve_first(0..=u64::MAX, 4).unwrap();
+        assert_eq!(solution.indices.len(), params.solution_len());
+        assert!(puzzle.verify(solution.nonce, &solution.indices));
+        assert!(!puzzle.verify(solution.nonce + 1, &solution.indices));
+
+        let mut replaced = solution.indices.clone();
+        replaced[3] = (0..params.string_count()).find(|i| !replaced.contains(i)).unwrap();
+        assert!(!puzzle.verify(solution.nonce, &replaced));
+
+        let mut swapped = solution.indices.clone();
+        swapped.swap(0, 1);
+        assert!(!puzzle.verify(solution.nonce, &swapped));
+
+        let mut duplicated = solution.indices.clone();
+        duplicated[1] = duplicated[0];
+        assert!(!puzzle.verify(solution.nonce, &duplicated));
+
+        let mut out_of_range = solution.indices.clone();
+        out_of_range[0] = params.string_count();
+        assert!(!puzzle.verify(solution.nonce, &out_of_range));
+
+        assert!(!puzzle.verify(solution.nonce, &solution.indices[1..]));
+    }
+
+    #[test]
+    fn thread_count_does_not_change_the_solutions() {
+        let puzzle = EquihashPuzzle::new(EquihashParams::new(48, 5).unwrap(), "threads");
+        let expected = puzzle.solve(0, 1).unwrap();
+        for threads in [2, 3, 8] {
+            assert_eq!(puzzle.solve(0, threads).unwrap(), expected, "with {} threads", threads);
+        }
+    }
+}
@@ -0,0 +1,63 @@
+//! Error types returned by the solver.
+
+use std::io;
+use std::sync::PoisonError;
+
+use thiserror::Error;
+
+use crate::CheckpointError;
+
+/// The reasons a call into the solver can fail: the puzzle or search window was unusable,
+/// the search finished without a solution, or the machinery around it broke down.
+#[derive(Debug, Error)]
+pub enum PuzzleError {
+    /// The puzzle's target is zero, which no hash a search can expect to find meets,
+    /// e.g. because the difficulty asked for more leading zero bits than a hash has.
+    #[error("the difficulty gives a zero target, which no search can meet")]
+    InvalidDifficulty,
+    /// The nonce window to search holds no nonces.
+    #[error("the nonce range to search is empty")]
+    EmptyRange,
+    /// Every nonce in the search range was tried and none met the target.
+    #[error("nonce range exhausted without finding a solution")]
+    Exhausted,
+    /// The search was stopped before it found a solution or covered its whole range.
+    #[error("mining was cancelled before a solution was found")]
+    Cancelled,
+    /// The puzzle's deadline passed before a solution was found.
+    #[error("the deadline passed before a solution was found")]
+    DeadlineExceeded,
+    /// The search's time budget ran out before a solution was found.
+    #[error("no solution found within the time limit after {attempts} hashes")]
+    TimedOut {
+        /// How many hashes were tried within the budget.
+        attempts: u64,
+    },
+    /// Reading or writing a file failed.
+    #[error("I/O failed: {0}")]
+    Io(#[from] io::Error),
+    /// A checkpoint could not be loaded or belongs to a different search.
+    #[error("{0}")]
+    Checkpoint(#[from] CheckpointError),
+    /// A lock shared between the workers was poisoned by a thread that panicked while
+    /// holding it.
+    #[error("a lock was poisoned by a panicked thread")]
+    PoisonedLock,
+    /// A worker thread panicked, so part of the range may not have been searched.
+    #[error("a mining thread panicked")]
+    WorkerPanicked,
+    /// A work server's queue in Redis could not be reached, or holds a state that cannot
+    /// be read.
+    #[error("the queue is unavailable: {0}")]
+    Unavailable(String),
+}
+
+/// The name this error had before it covered more than mining runs.
+#[deprecated(note = "renamed to `PuzzleError`")]
+pub type MineError = PuzzleError;
+
+impl<T> From<PoisonError<T>> for PuzzleError {
+    fn from(_: PoisonError<T>) -> Self {
+        PuzzleError::PoisonedLock
+    }
+}
@@ -0,0 +1,150 @@
+//! How much work a puzzle takes, and how long that is likely to last.
+//!
+//! Every hash meets a target independently with the same probability `p`, so the number
+//! of hashes until the first solution follows a geometric distribution with mean `1 / p`.
+//! At a steady hash rate the time to a solution is, for any realistic target, as good as
+//! exponential. Luck matters a great deal: a run finishes within its expected time only
+//! about 63% of the time, and one run in a hundred takes more than 4.6 times as long.
+//! A [`WorkEstimate`] answers both "how many hashes on average" and "how likely is a
+//! solution within this time", and [`measure_hash_rate_with`] supplies the rate.
+//!
+//! [`calibrate_with`] works the other way round: it benchmarks the machine and picks the
+//! target that takes a requested time on average, so a puzzle generator can ask for "a
+//! puzzle of about 30 seconds" rather than guess a difficulty.
+
+use std::time::Duration;
+
+use crate::digest::Digest;
+use crate::{parallel_mine_spawn_with, Puzzle, Target};
+
+/// The expected work for a puzzle and the distribution of its solve time at a given
+/// hash rate.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WorkEstimate {
+    /// The probability that a single hash solves the puzzle.
+    pub probability: f64,
+    /// The hash rate the search runs at, in hashes per second.
+    pub hash_rate: f64,
+}
+
+impl WorkEstimate {
+    /// The estimate for meeting `target` at `hash_rate` hashes per second.
+    pub fn new(target: Target, hash_rate: f64) -> Self {
+        WorkEstimate::from_probability(target.success_probability(), hash_rate)
+    }
+
+    /// The estimate for a puzzle that each hash solves with `probability`, such as the
+    /// inverse of [`HashPrefix::expected_attempts`](crate::HashPrefix::expected_attempts).
+    pub fn from_probability(probability: f64, hash_rate: f64) -> Self {
+        WorkEstimate { probability, hash_rate }
+    }
+
+    /// The average number of hashes until a solution.
+    pub fn expected_attempts(&self) -> f64 {
+        1.0 / self.probability
+    }
+
+    /// The average time until a solution.
+    pub fn expected_time(&self) -> Duration {
+        seconds(self.expected_attempts() / self.hash_rate)
+    }
+
+    /// The probability that `attempts` hashes include a solution.
+    pub fn probability_within_attempts(&self, attempts: f64) -> f64 {
+        // 1 - (1 - p)^n, computed without losing a tiny `p` to rounding.
+        -(attempts * (-self.probability).ln_1p()).exp_m1()
+    }
+
+    /// The probability that a solution is found within `time`.
+    pub fn probability_within(&self, time: Duration) -> f64 {
+        self.probability_within_attempts(time.as_secs_f64() * self.hash_rate)
+    }
+
+    /// The number of hashes after which a solution has been found with `probability`,
+    /// e.g. `0.5` for the median.
+    ///
+    /// # Panics
+    ///
+    /// If `probability` is not in `[0, 1)`.
+    pub fn attempts_for_probability(&self, probability: f64) -> f64 {
+        assert!(
+            (0.0..1.0).contains(&probability),
+            "the probability must be at least 0 and less than 1"
+        );
+        (-probability).ln_1p() / (-self.probability).ln_1p()
+    }
+
+    /// The time after which a solution has been found with `probability`; see
+    /// [`attempts_for_probability`](WorkEstimate::attempts_for_probability).
+    pub fn time_for_probability(&self, probability: f64) -> Duration {
+        seconds(self.attempts_for_probability(probability) / self.hash_rate)
+    }
+}
+
+/// A target sized to a machine's measured hash rate, from [`calibrate_with`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Calibration {
+    /// The measured hash rate, in hashes per second.
+    pub hash_rate: f64,
+    /// The target expected to take the requested time at that rate.
+    pub target: Target,
+}
+
+impl Calibration {
+    /// The work and solve-time distribution of the calibrated target at the measured
+    /// rate.
+    pub fn estimate(&self) -> WorkEstimate {
+        WorkEstimate::new(self.target, self.hash_rate)
+    }
+}
+
+/// Benchmarks hashing `puzzle` with the hash function `D` on `num_threads` threads for
+/// `benchmark`, and picks the target a search on this machine meets in `solve_time` on
+/// average.
+///
+/// Only the data, nonce format and hash function of `puzzle` matter; its target is
+/// ignored. Luck still decides the actual time: about one run in ten takes 2.3 times as
+/// long; see the [module documentation](self).
+///
+/// # Returns
+///
+/// The measured rate and the calibrated target. A machine too slow for a single hash in
+/// `solve_time` gets [`Target::MAX`].
+pub fn calibrate_with<D>(
+    puzzle: &Puzzle,
+    num_threads: usize,
+    solve_time: Duration,
+    benchmark: Duration,
+) -> Calibration
+where
+    D: Digest + Clone + Send + Sync + 'static,
+{
+    let hash_rate = measure_hash_rate_with::<D>(puzzle, num_threads, benchmark);
+    let target = Target::from_expected_attempts(hash_rate * solve_time.as_secs_f64());
+    Calibration { hash_rate, target }
+}
+
+/// Measures how fast `puzzle` can be hashed with the hash function `D` on `num_threads`
+/// threads, by searching for `duration` with a target no hash can meet.
+///
+/// # Returns
+///
+/// The hash rate in hashes per second.
+pub fn measure_hash_rate_with<D>(puzzle: &Puzzle, num_threads: usize, duration: Duration) -> f64
+where
+    D: Digest + Clone + Send + Sync + 'static,
+{
+    let mut puzzle = puzzle.clone();
+    // Only a hash of 0 or 1 meets this target, so the search runs for the whole time.
+    let mut one = [0; 32];
+    one[31] = 1;
+    puzzle.target = Target::from_be_bytes(one);
+    let handle = parallel_mine_spawn_with::<D>(&puzzle, 0..=u64::MAX, num_threads);
+    handle.cancel_after(duration);
+    handle.join_report().1.hash_rate()
+}
+
+/// `seconds` as a duration, saturating at [`Duration::MAX`] for huge or infinite values.
+fn seconds(seconds: f64) -> Duration {
+    Duration::try_from_secs_f64(seconds).unwrap_or(Duration::MAX)
+}
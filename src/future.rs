@@ -0,0 +1,90 @@
+//! Awaiting a search from async code.
+//!
+//! A web service that verifies client puzzles, or mints them on demand, cannot afford to
+//! block its async runtime for the length of a search. A [`MiningHandle`] can therefore
+//! be awaited: it turns into a [`MiningFuture`] that resolves to the search's result,
+//! while the workers run on their own threads, or on a miner's pool, never on the
+//! runtime's. [`parallel_mine_async`] and [`Miner::mine_async`](crate::Miner::mine_async)
+//! start a search and return the future at once.
+//!
+//! Dropping the future before it resolves cancels the search, which is how async
+//! runtimes such as tokio cancel a task, e.g. in `select!` or when a timeout elapses.
+//! The drop does not wait for the workers to notice: the thread running the search
+//! stops and joins them in the background, so the runtime is never blocked, however
+//! slow the hash. A [`CancellationToken`](crate::CancellationToken) attached to the
+//! search cancels it from anywhere else.
+//!
+//! The future only relies on the standard [`Waker`](std::task::Waker), so it works on
+//! any executor, and the crate needs no runtime of its own.
+
+use std::future::{Future, IntoFuture};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use crate::{parallel_mine_spawn, MiningHandle, Puzzle, PuzzleError, Solution};
+
+/// The result of a search, once it ends; see the [module documentation](self).
+///
+/// Created by awaiting a [`MiningHandle`], by [`parallel_mine_async`] or by
+/// [`Miner::mine_async`](crate::Miner::mine_async).
+#[derive(Debug)]
+#[must_use = "a future does nothing unless awaited, and dropping it cancels the search"]
+pub struct MiningFuture {
+    /// The running search, or why it could not start; `None` once resolved.
+    search: Option<Result<MiningHandle, PuzzleError>>,
+}
+
+impl MiningFuture {
+    /// A future that resolves to `error` at once.
+    pub(crate) fn failed(error: PuzzleError) -> Self {
+        MiningFuture { search: Some(Err(error)) }
+    }
+}
+
+impl Future for MiningFuture {
+    type Output = Result<Solution, PuzzleError>;
+
+    /// Resolves to what [`MiningHandle::join`] would return.
+    ///
+    /// # Panics
+    ///
+    /// If polled again after it resolved.
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        match this.search.take().expect("a mining future is not polled after it resolves") {
+            Err(e) => Poll::Ready(Err(e)),
+            Ok(handle) if handle.poll_finished(cx.waker()) => Poll::Ready(handle.join()),
+            Ok(handle) => {
+                this.search = Some(Ok(handle));
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl Drop for MiningFuture {
+    /// Cancels the search if it has not resolved, without waiting for it to stop.
+    fn drop(&mut self) {
+        if let Some(Ok(handle)) = self.search.take() {
+            handle.detach();
+        }
+    }
+}
+
+impl IntoFuture for MiningHandle {
+    type Output = Result<Solution, PuzzleError>;
+    type IntoFuture = MiningFuture;
+
+    /// Awaits the search, which is cancelled if the future is dropped first.
+    fn into_future(self) -> MiningFuture {
+        MiningFuture { search: Some(Ok(self)) }
+    }
+}
+
+/// Starts mining `puzzle` with SHA-256 on every available core and returns a future of
+/// the result, like [`parallel_mine_spawn`] followed by an await.
+///
+/// The search starts at once, whether or not the future is ever polled.
+pub fn parallel_mine_async(puzzle: &Puzzle) -> MiningFuture {
+    parallel_mine_spawn(puzzle).into_future()
+}
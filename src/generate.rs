@@ -0,0 +1,152 @@
+//! Minting new puzzles of a chosen hardness.
+//!
+//! A difficulty on its own says little about how long a puzzle takes: 28 leading zero
+//! bits is a few seconds on a desktop and minutes on a small board. [`generate_puzzle`]
+//! starts from the time instead. It fills the puzzle with random data, benchmarks the
+//! machine on it as [`calibrate_with`](crate::calibrate_with) does, and sets the target
+//! that takes the requested time on average at the measured rate.
+//!
+//! The data of a generated puzzle is derived from a 64-bit seed, so a [`GeneratedPuzzle`]
+//! can be recreated from its seed, length and target alone. [`generate_puzzles_with`]
+//! mints many puzzles at once from consecutive seeds, benchmarking only once, e.g. for a
+//! set of classroom or CTF challenges.
+//!
+//! [`generate_series_with`] gives each puzzle of such a set its own solve time. With the
+//! times from [`difficulty_curve`] the puzzles get geometrically harder, e.g. ten puzzles
+//! from about a second to about ten minutes, for progressive challenges or a benchmarking
+//! suite.
+
+use std::time::Duration;
+
+use sha2::Sha256;
+
+use crate::digest::Digest;
+use crate::estimate::measure_hash_rate_with;
+use crate::{Difficulty, Puzzle, Target};
+
+/// How long the machine is benchmarked for before the target is set.
+const BENCHMARK_TIME: Duration = Duration::from_secs(1);
+
+/// A generated puzzle with the seed its data was derived from.
+#[derive(Clone, Debug)]
+pub struct GeneratedPuzzle {
+    /// The seed the data was derived from.
+    pub seed: u64,
+    /// The puzzle itself.
+    pub puzzle: Puzzle,
+}
+
+impl GeneratedPuzzle {
+    /// The puzzle over `data_len` bytes derived from `seed`, with `target`.
+    ///
+    /// The bytes are SHA-256 of the seed and a block counter, both big-endian, so the
+    /// same seed gives the same data on every machine and in every version of the crate.
+    pub fn from_seed(seed: u64, data_len: usize, target: Target) -> Self {
+        let data: Vec<u8> = (0u64..)
+            .flat_map(|block| {
+                Sha256::new()
+                    .chain_update(seed.to_be_bytes())
+                    .chain_update(block.to_be_bytes())
+                    .finalize()
+            })
+            .take(data_len)
+            .collect();
+        GeneratedPuzzle { seed, puzzle: Puzzle::new(data, Difficulty::Target(target)) }
+    }
+}
+
+/// Generates a SHA-256 puzzle over `data_len` random bytes that takes `target_duration`
+/// on average to solve on every available core of this machine.
+///
+/// See [`generate_puzzle_with`] for details.
+pub fn generate_puzzle(data_len: usize, target_duration: Duration) -> Puzzle {
+    generate_puzzle_with::<Sha256>(data_len, target_duration, num_cpus::get())
+}
+
+/// Generates a puzzle over `data_len` random bytes that takes `target_duration` on
+/// average to solve with the hash function `D` on `num_threads` threads of this
+/// machine.
+///
+/// The machine is benchmarked on the new puzzle for about a second first, so the call
+/// blocks for that long. The solve time is only an average: about one solve in ten takes
+/// more than 2.3 times as long, and a slower or busier solver takes longer still.
+pub fn generate_puzzle_with<D>(
+    data_len: usize,
+    target_duration: Duration,
+    num_threads: usize,
+) -> Puzzle
+where
+    D: Digest + Clone + Send + Sync + 'static,
+{
+    let seed = rand::random();
+    generate_puzzles_with::<D>(seed, 1, data_len, target_duration, num_threads)
+        .pop()
+        .expect("one puzzle was generated")
+        .puzzle
+}
+
+/// Generates `count` puzzles over `data_len` bytes each, derived from the seeds
+/// `first_seed`, `first_seed + 1` and so on, that each take `target_duration` on
+/// average to solve with the hash function `D` on `num_threads` threads.
+///
+/// The machine is benchmarked once, on the first puzzle, and every puzzle gets the same
+/// target; see [`generate_puzzle_with`].
+pub fn generate_puzzles_with<D>(
+    first_seed: u64,
+    count: usize,
+    data_len: usize,
+    target_duration: Duration,
+    num_threads: usize,
+) -> Vec<GeneratedPuzzle>
+where
+    D: Digest + Clone + Send + Sync + 'static,
+{
+    let solve_times = vec![target_duration; count];
+    generate_series_with::<D>(first_seed, data_len, &solve_times, num_threads)
+}
+
+/// Generates one puzzle over `data_len` bytes for each of `solve_times`, derived from the
+/// seeds `first_seed`, `first_seed + 1` and so on, each taking its solve time on average
+/// with the hash function `D` on `num_threads` threads.
+///
+/// The machine is benchmarked once, on the first puzzle, as in
+/// [`generate_puzzle_with`].
+pub fn generate_series_with<D>(
+    first_seed: u64,
+    data_len: usize,
+    solve_times: &[Duration],
+    num_threads: usize,
+) -> Vec<GeneratedPuzzle>
+where
+    D: Digest + Clone + Send + Sync + 'static,
+{
+    let sample = GeneratedPuzzle::from_seed(first_seed, data_len, Target::MAX);
+    let hash_rate = measure_hash_rate_with::<D>(&sample.puzzle, num_threads, BENCHMARK_TIME);
+    (0u64..)
+        .zip(solve_times)
+        .map(|(i, time)| {
+            let target = Target::from_expected_attempts(hash_rate * time.as_secs_f64());
+            GeneratedPuzzle::from_seed(first_seed.wrapping_add(i), data_len, target)
+        })
+        .collect()
+}
+
+/// `count` solve times growing geometrically from `easiest` to `hardest`, each the
+/// same factor longer than the one before.
+///
+/// A single step gives just `easiest`; none gives an empty curve.
+///
+/// # Panics
+///
+/// If `easiest` or `hardest` is zero.
+pub fn difficulty_curve(easiest: Duration, hardest: Duration, count: usize) -> Vec<Duration> {
+    assert!(!easiest.is_zero() && !hardest.is_zero(), "solve times must be positive");
+    let (first, last) = (easiest.as_secs_f64(), hardest.as_secs_f64());
+    let steps = count.saturating_sub(1).max(1) as f64;
+    (0..count)
+        .map(|i| {
+            let seconds = first * (last / first).powf(i as f64 / steps);
+            Duration::try_from_secs_f64(seconds).unwrap_or(Duration::MAX)
+        })
+        .collect()
+}
@@ -0,0 +1,801 @@
+//! Sharing puzzles among peers with no coordinator, over libp2p gossip, with the `gossip`
+//! feature.
+//!
+//! A [`WorkServer`](crate::WorkServer) is a single point that every worker depends on. A
+//! [`GossipNode`] instead is one peer among equals: each node joins a libp2p gossipsub
+//! topic, [`TOPIC`], over TCP with noise encryption, and tells the others what it is
+//! doing, so that together they search each puzzle's window once between them. Any node
+//! can [`publish`](GossipNode::publish) a puzzle, and every node mines whatever is
+//! published, a range at a time:
+//!
+//! - [`claim`](GossipNode::claim) picks the lowest range of a running puzzle that no
+//!   node has claimed or searched, and announces the claim;
+//! - [`mine`](GossipNode::mine) searches the claim, then announces the solution, or that
+//!   the range was searched without one.
+//!
+//! Messages are flat JSON objects, as for the `server`, with an `event` of `puzzle` (the
+//! puzzle as in a lease, with its `puzzle` id and window from `start` to `end`),
+//! `claim` (a `puzzle` id, a range from `start` to `end`, and the claim's `clock`),
+//! `searched` (a `puzzle` id and the range searched) or `solved` (a `puzzle` id and its
+//! `nonce`). Every message is signed by the node that sent it, and nodes check every
+//! solution before believing it. A puzzle's id is derived from the puzzle itself, so
+//! nodes that publish the same puzzle agree on it.
+//!
+//! Two nodes may claim overlapping ranges before hearing of each other's claim. Each
+//! node keeps a Lamport clock, advanced past every claim it hears of, and stamps its own
+//! claims with it, and of two overlapping claims the one with the lower clock, or with
+//! the lower peer id if the clocks are equal, wins. Every node applies the same rule, so
+//! they all agree on the winner; the loser stops mining its claim and claims another
+//! range. A claim that the node which made it has not reported on within the claim
+//! timeout ([`DEFAULT_CLAIM_TIMEOUT`] unless set otherwise) is given up on, so the ranges
+//! of a node that left are still searched.
+//!
+//! Gossip is not a log: a node hears only what is sent after it joins. When a peer
+//! joins the topic, every node sends it the puzzles it knows of, their solutions, the
+//! ranges searched and its own claims, so a late joiner catches up.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+use std::sync::{mpsc as std_mpsc, Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use libp2p::futures::StreamExt;
+use libp2p::gossipsub::{self, IdentTopic, MessageAuthenticity};
+use libp2p::multiaddr::Protocol;
+use libp2p::swarm::SwarmEvent;
+use libp2p::{noise, tcp, yamux, Multiaddr, PeerId, Swarm, SwarmBuilder};
+use sha2::{Digest, Sha256};
+use tokio::sync::mpsc;
+
+use crate::json::{Request, Response};
+use crate::{
+    hex, Algorithm, CancellationToken, NonceFormat, Puzzle, PuzzleError, Solution, Target,
+};
+
+/// The gossipsub topic the nodes share.
+pub const TOPIC: &str = "puzzle-solver/gossip/1";
+
+/// The number of nonces a node claims at a time unless told otherwise.
+pub const DEFAULT_CLAIM_SIZE: u64 = 1 << 26;
+
+/// How long a node's claim is honoured without a report on it unless told otherwise.
+pub const DEFAULT_CLAIM_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// The reasons a node could not join the network.
+#[derive(Debug)]
+pub enum GossipError {
+    /// An address to listen on or dial is not a multiaddr.
+    Address(String),
+    /// The node could not listen, dial a peer or join the topic.
+    Network(String),
+}
+
+impl fmt::Display for GossipError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GossipError::Address(message) => write!(f, "invalid address {}", message),
+            GossipError::Network(message) => write!(f, "cannot join the network: {}", message),
+        }
+    }
+}
+
+impl Error for GossipError {}
+
+/// A range of a puzzle's window claimed by this node, to [`mine`](GossipNode::mine).
+#[derive(Clone, Debug)]
+pub struct GossipClaim {
+    /// The puzzle's id.
+    pub puzzle_id: u64,
+    /// The puzzle.
+    pub puzzle: Puzzle,
+    /// The hash the puzzle is mined with.
+    pub algorithm: Algorithm,
+    /// The claimed nonces.
+    pub nonces: RangeInclusive<u64>,
+    /// Cancelled once the claim is lost to another node's or the puzzle is finished.
+    token: CancellationToken,
+}
+
+/// A node of the gossip network, which mines the puzzles any node publishes.
+pub struct GossipNode {
+    peer_id: PeerId,
+    listen_addr: Multiaddr,
+    shared: Arc<Shared>,
+    /// Messages for the network thread to publish; dropping it stops the thread.
+    outgoing: Option<mpsc::UnboundedSender<String>>,
+    network: Option<JoinHandle<()>>,
+    claim_size: u64,
+    claim_timeout: Duration,
+}
+
+impl GossipNode {
+    /// Listens for peers on the multiaddr `listen`, such as `/ip4/0.0.0.0/tcp/4001`, dials
+    /// the multiaddrs `peers`, and joins the topic. The network runs on a thread of its
+    /// own until the node is dropped.
+    ///
+    /// # Returns
+    ///
+    /// The node, or why it could not join the network.
+    pub fn start(listen: &str, peers: &[&str]) -> Result<Self, GossipError> {
+        let listen = multiaddr(listen)?;
+        let peers = peers.iter().map(|peer| multiaddr(peer)).collect::<Result<Vec<_>, _>>()?;
+        let shared = Arc::new(Shared {
+            ledger: Mutex::new(Ledger::default()),
+            changed: Condvar::new(),
+        });
+        let (outgoing, messages) = mpsc::unbounded_channel();
+        let (started, result) = std_mpsc::channel();
+        let network = {
+            let shared = Arc::clone(&shared);
+            thread::spawn(move || run_network(listen, peers, shared, messages, started))
+        };
+        let (peer_id, listen_addr) = match result.recv() {
+            Ok(Ok(started)) => started,
+            Ok(Err(e)) => return Err(e),
+            Err(_) => return Err(GossipError::Network("the network thread stopped".into())),
+        };
+        Ok(GossipNode {
+            peer_id,
+            listen_addr,
+            shared,
+            outgoing: Some(outgoing),
+            network: Some(network),
+            claim_size: DEFAULT_CLAIM_SIZE,
+            claim_timeout: DEFAULT_CLAIM_TIMEOUT,
+        })
+    }
+
+    /// Claims `claim_size` nonces at a time. A value of zero is treated as one.
+    pub fn with_claim_size(mut self, claim_size: u64) -> Self {
+        self.claim_size = claim_size.max(1);
+        self
+    }
+
+    /// Gives up on another node's claim that it has not reported on within `timeout`,
+    /// rather than after [`DEFAULT_CLAIM_TIMEOUT`]. Nodes should agree on it, and on a
+    /// claim size they search well within it.
+    pub fn with_claim_timeout(mut self, timeout: Duration) -> Self {
+        self.claim_timeout = timeout;
+        self
+    }
+
+    /// The node's peer id, which breaks ties between claims.
+    pub fn peer_id(&self) -> String {
+        self.peer_id.to_string()
+    }
+
+    /// The address the node listens on, with its peer id, for other nodes to dial.
+    pub fn listen_addr(&self) -> String {
+        self.listen_addr.to_string()
+    }
+
+    /// Publishes `puzzle`, hashed with `algorithm`, whose window `nonces` the nodes share
+    /// out. A puzzle's extra nonce, if any, is baked into its data and not rolled.
+    ///
+    /// # Returns
+    ///
+    /// The puzzle's id, for [`outcome`](GossipNode::outcome).
+    pub fn publish(
+        &self,
+        puzzle: Puzzle,
+        algorithm: Algorithm,
+        nonces: RangeInclusive<u64>,
+    ) -> u64 {
+        let puzzle = Puzzle {
+            data: puzzle.message_data().into_owned(),
+            extra_nonce: None,
+            ..puzzle
+        };
+        let entry = Entry::new(puzzle, algorithm, nonces);
+        let (id, message) = (entry.id, entry.message());
+        self.shared.update(|ledger| ledger.add(entry));
+        self.send(message);
+        id
+    }
+
+    /// Claims the lowest range of a running puzzle that no node has claimed or searched,
+    /// waiting up to `timeout` for one to come free, and tells the other nodes.
+    ///
+    /// # Returns
+    ///
+    /// The claim, or `None` if there was nothing to claim in time or the node was shut
+    /// down.
+    pub fn claim(&self, timeout: Duration) -> Option<GossipClaim> {
+        let deadline = Instant::now() + timeout;
+        let mut ledger = self.shared.lock();
+        loop {
+            if ledger.shut_down {
+                return None;
+            }
+            if let Some((claim, message)) =
+                ledger.claim(self.peer_id, self.claim_size, self.claim_timeout)
+            {
+                drop(ledger);
+                self.send(message);
+                return Some(claim);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return None;
+            }
+            let waited = self.shared.changed.wait_timeout(ledger, deadline - now);
+            ledger = waited.unwrap_or_else(PoisonError::into_inner).0;
+        }
+    }
+
+    /// Mines `claim` on `num_threads` threads and tells the other nodes the outcome.
+    ///
+    /// # Returns
+    ///
+    /// The solution if the range held one, `None` if it did not, or
+    /// [`PuzzleError::Cancelled`] if the claim was lost to another node's, the puzzle was
+    /// finished elsewhere or the node was shut down first.
+    pub fn mine(
+        &self,
+        claim: &GossipClaim,
+        num_threads: usize,
+    ) -> Result<Option<Solution>, PuzzleError> {
+        let (id, nonces, token) = (claim.puzzle_id, claim.nonces.clone(), &claim.token);
+        match claim.algorithm.parallel_mine_cancellable(&claim.puzzle, nonces, num_threads, token) {
+            Ok(solution) => {
+                self.shared.update(|ledger| ledger.solved(id, solution.clone()));
+                self.send(solved_message(id, solution.nonce));
+                Ok(Some(solution))
+            }
+            Err(PuzzleError::Exhausted | PuzzleError::InvalidDifficulty) => {
+                let peer_id = self.peer_id;
+                self.shared.update(|ledger| ledger.searched(id, &claim.nonces, peer_id));
+                self.send(searched_message(id, &claim.nonces));
+                Ok(None)
+            }
+            Err(e) => {
+                self.shared.update(|ledger| ledger.release(id, &claim.nonces));
+                Err(e)
+            }
+        }
+    }
+
+    /// The outcome of the puzzle `id`, as far as this node has heard.
+    ///
+    /// # Returns
+    ///
+    /// The solution, [`PuzzleError::Exhausted`] if every range of the window was searched
+    /// without one, or `None` while the puzzle is running or if the node knows of no
+    /// puzzle `id`.
+    pub fn outcome(&self, id: u64) -> Option<Result<Solution, PuzzleError>> {
+        let ledger = self.shared.lock();
+        let entry = ledger.puzzles.get(&id)?;
+        match &entry.solution {
+            Some(solution) => Some(Ok(solution.clone())),
+            None if entry.is_exhausted() => Some(Err(PuzzleError::Exhausted)),
+            None => None,
+        }
+    }
+
+    /// Stops mining: the claim being mined is cancelled, and
+    /// [`claim`](GossipNode::claim) returns `None` from now on. The node stays on the
+    /// network until dropped.
+    pub fn shutdown(&self) {
+        self.shared.update(|ledger| {
+            ledger.shut_down = true;
+            for entry in ledger.puzzles.values_mut() {
+                entry.cancel_own();
+            }
+        });
+    }
+
+    /// Hands `message` to the network thread to publish.
+    fn send(&self, message: impl fmt::Display) {
+        if let Some(outgoing) = &self.outgoing {
+            let _ = outgoing.send(message.to_string());
+        }
+    }
+}
+
+impl fmt::Debug for GossipNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GossipNode")
+            .field("peer_id", &self.peer_id)
+            .field("listen_addr", &self.listen_addr)
+            .field("claim_size", &self.claim_size)
+            .field("claim_timeout", &self.claim_timeout)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for GossipNode {
+    fn drop(&mut self) {
+        self.shutdown();
+        self.outgoing = None;
+        if let Some(network) = self.network.take() {
+            let _ = network.join();
+        }
+    }
+}
+
+/// What the node knows, shared between its network thread and the threads mining.
+struct Shared {
+    ledger: Mutex<Ledger>,
+    changed: Condvar,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, Ledger> {
+        self.ledger.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Changes the ledger with `f` and wakes every thread waiting on it.
+    fn update<T>(&self, f: impl FnOnce(&mut Ledger) -> T) -> T {
+        let result = f(&mut self.lock());
+        self.changed.notify_all();
+        result
+    }
+
+    /// Takes in a message `source` sent; a malformed one is ignored.
+    fn receive(&self, source: PeerId, data: &[u8]) {
+        let Some(request) = std::str::from_utf8(data).ok().and_then(|s| Request::parse(s).ok())
+        else {
+            return;
+        };
+        let _ = self.update(|ledger| ledger.receive(source, &request));
+    }
+}
+
+/// The puzzles a node knows of, and who is searching what.
+#[derive(Default)]
+struct Ledger {
+    puzzles: HashMap<u64, Entry>,
+    /// The Lamport clock that orders claims.
+    clock: u64,
+    shut_down: bool,
+}
+
+impl Ledger {
+    /// Adds `entry`, unless the puzzle is known already.
+    fn add(&mut self, entry: Entry) {
+        self.puzzles.entry(entry.id).or_insert(entry);
+    }
+
+    /// Claims a range of the first running puzzle with one to spare, for `node`.
+    ///
+    /// # Returns
+    ///
+    /// The claim and the message that announces it, or `None` if every range is taken.
+    fn claim(
+        &mut self,
+        node: PeerId,
+        claim_size: u64,
+        claim_timeout: Duration,
+    ) -> Option<(GossipClaim, Response)> {
+        let mut ids: Vec<u64> = self.puzzles.keys().copied().collect();
+        // Puzzles published first, by the order of their ids; every node agrees on it.
+        ids.sort_unstable();
+        for id in ids {
+            let entry = self.puzzles.get_mut(&id).unwrap();
+            if !entry.is_running() {
+                continue;
+            }
+            entry.claims.retain(|claim| {
+                claim.node == node || claim.heard_at.elapsed() < claim_timeout
+            });
+            let Some(nonces) = entry.next_range(claim_size) else {
+                continue;
+            };
+            self.clock += 1;
+            let token = CancellationToken::new();
+            entry.claims.push(Claim {
+                nonces: nonces.clone(),
+                node,
+                clock: self.clock,
+                heard_at: Instant::now(),
+                token: Some(token.clone()),
+            });
+            let message = claim_message(id, &nonces, self.clock);
+            let claim = GossipClaim {
+                puzzle_id: id,
+                puzzle: entry.puzzle.clone(),
+                algorithm: entry.algorithm,
+                nonces,
+                token,
+            };
+            return Some((claim, message));
+        }
+        None
+    }
+
+    /// Records that `node` searched `nonces` of the puzzle `id` without a solution.
+    fn searched(&mut self, id: u64, nonces: &RangeInclusive<u64>, node: PeerId) {
+        if let Some(entry) = self.puzzles.get_mut(&id) {
+            entry.searched_by(node, nonces);
+        }
+    }
+
+    /// Records the solution of the puzzle `id`, and stops mining it.
+    fn solved(&mut self, id: u64, solution: Solution) {
+        if let Some(entry) = self.puzzles.get_mut(&id) {
+            entry.solved(solution);
+        }
+    }
+
+    /// Drops this node's claim on `nonces`, which it will not report on.
+    fn release(&mut self, id: u64, nonces: &RangeInclusive<u64>) {
+        if let Some(entry) = self.puzzles.get_mut(&id) {
+            entry.claims.retain(|claim| claim.token.is_none() || claim.nonces != *nonces);
+        }
+    }
+
+    /// Takes in `request`, sent by `source`.
+    fn receive(&mut self, source: PeerId, request: &Request) -> Result<(), String> {
+        if request.string("event")? == Some("puzzle") {
+            let entry = Entry::decode(request)?;
+            if entry.id != request.required::<u64>("puzzle")? {
+                return Err("the puzzle's id does not match it".into());
+            }
+            self.add(entry);
+            return Ok(());
+        }
+        let id: u64 = request.required("puzzle")?;
+        let Some(entry) = self.puzzles.get_mut(&id) else {
+            return Ok(());
+        };
+        match request.string("event")? {
+            Some("claim") => {
+                let nonces = request.required("start")?..=request.required("end")?;
+                let clock: u64 = request.required("clock")?;
+                self.clock = self.clock.max(clock);
+                entry.claimed(source, nonces, clock);
+            }
+            Some("searched") => {
+                let nonces = request.required("start")?..=request.required("end")?;
+                entry.searched_by(source, &nonces);
+            }
+            Some("solved") => {
+                let nonce = request.required("nonce")?;
+                if entry.algorithm.validate(&entry.puzzle, nonce) {
+                    let hash = entry.algorithm.hash(&entry.puzzle, nonce);
+                    entry.solved(Solution { nonce, hash, extra_nonce: None });
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// The messages that bring a node that just joined up to date with this one, `node`.
+    fn announcements(&self, node: PeerId) -> Vec<Response> {
+        let mut messages = Vec::new();
+        for entry in self.puzzles.values() {
+            messages.push(entry.message());
+            if let Some(solution) = &entry.solution {
+                messages.push(solved_message(entry.id, solution.nonce));
+                continue;
+            }
+            for nonces in &entry.searched {
+                messages.push(searched_message(entry.id, nonces));
+            }
+            for claim in entry.claims.iter().filter(|claim| claim.node == node) {
+                messages.push(claim_message(entry.id, &claim.nonces, claim.clock));
+            }
+        }
+        messages
+    }
+}
+
+/// A puzzle, and the claims and searches of its window.
+struct Entry {
+    id: u64,
+    puzzle: Puzzle,
+    algorithm: Algorithm,
+    nonces: RangeInclusive<u64>,
+    claims: Vec<Claim>,
+    /// The ranges searched without a solution, sorted and merged.
+    searched: Vec<RangeInclusive<u64>>,
+    solution: Option<Solution>,
+}
+
+/// A node's claim on a range of a puzzle's window.
+struct Claim {
+    nonces: RangeInclusive<u64>,
+    node: PeerId,
+    clock: u64,
+    heard_at: Instant,
+    /// Cancels the mining of one of this node's own claims.
+    token: Option<CancellationToken>,
+}
+
+impl Claim {
+    /// Whether this claim wins over one by `node` at `clock`: the lower clock wins, and
+    /// the lower peer id if the clocks are equal.
+    fn beats(&self, node: &PeerId, clock: u64) -> bool {
+        (self.clock, self.node.to_bytes()) < (clock, node.to_bytes())
+    }
+}
+
+impl Entry {
+    fn new(puzzle: Puzzle, algorithm: Algorithm, nonces: RangeInclusive<u64>) -> Self {
+        let mut entry = Entry {
+            id: 0,
+            puzzle,
+            algorithm,
+            nonces,
+            claims: Vec::new(),
+            searched: Vec::new(),
+            solution: None,
+        };
+        // The id is the first eight bytes of the SHA-256 of the message without one.
+        let digest = Sha256::digest(entry.message().to_string().as_bytes());
+        entry.id = u64::from_be_bytes(digest[..8].try_into().unwrap());
+        entry
+    }
+
+    /// Reads a puzzle from its `puzzle` message.
+    fn decode(request: &Request) -> Result<Self, String> {
+        let field = |key: &str| -> Result<&str, String> {
+            request.string(key)?.ok_or_else(|| format!("missing `{}`", key))
+        };
+        let data = hex::decode(field("data_hex")?).map_err(|e| e.to_string())?;
+        let nonce_format = NonceFormat {
+            encoding: parse(field("nonce_encoding")?)?,
+            offset: request.number("nonce_offset")?,
+        };
+        let target: Target = parse(field("target")?)?;
+        let puzzle = Puzzle::new(data, target).with_nonce_format(nonce_format);
+        let nonces = request.required("start")?..=request.required("end")?;
+        Ok(Entry::new(puzzle, parse(field("algorithm")?)?, nonces))
+    }
+
+    /// The `puzzle` message that publishes the puzzle, with its id unless it has none yet.
+    fn message(&self) -> Response {
+        let format = self.puzzle.nonce_format;
+        let mut message = Response::event("puzzle");
+        if self.id != 0 {
+            message = message.number("puzzle", self.id);
+        }
+        message = message
+            .string("algorithm", self.algorithm.name())
+            .string("data_hex", &hex::encode(&self.puzzle.data))
+            .string("target", &self.puzzle.target.to_string())
+            .string("nonce_encoding", &format.encoding.to_string());
+        if let Some(offset) = format.offset {
+            message = message.number("nonce_offset", offset);
+        }
+        message.number("start", self.nonces.start()).number("end", self.nonces.end())
+    }
+
+    fn is_exhausted(&self) -> bool {
+        self.searched.first() == Some(&self.nonces)
+    }
+
+    fn is_running(&self) -> bool {
+        self.solution.is_none() && !self.is_exhausted()
+    }
+
+    /// The lowest range of at most `size` nonces that is neither claimed nor searched.
+    fn next_range(&self, size: u64) -> Option<RangeInclusive<u64>> {
+        let mut taken: Vec<(u64, u64)> = self
+            .searched
+            .iter()
+            .chain(self.claims.iter().map(|claim| &claim.nonces))
+            .map(|nonces| (*nonces.start(), *nonces.end()))
+            .collect();
+        taken.sort_unstable();
+        let mut next = *self.nonces.start();
+        let mut end = *self.nonces.end();
+        for (start, taken_end) in taken {
+            if start > next {
+                end = end.min(start - 1);
+                break;
+            }
+            next = next.max(taken_end.checked_add(1)?);
+        }
+        if next > end {
+            return None;
+        }
+        Some(next..=end.min(next.saturating_add(size - 1)))
+    }
+
+    /// Records `node`'s claim on `nonces` at `clock`, unless a claim it overlaps wins over
+    /// it, and drops the claims it wins over.
+    fn claimed(&mut self, node: PeerId, nonces: RangeInclusive<u64>, clock: u64) {
+        let overlaps = |claim: &Claim| {
+            claim.node != node
+                && claim.nonces.start() <= nonces.end()
+                && nonces.start() <= claim.nonces.end()
+        };
+        if self.claims.iter().any(|claim| overlaps(claim) && claim.beats(&node, clock)) {
+            return;
+        }
+        self.claims.retain(|claim| {
+            if !overlaps(claim) {
+                return true;
+            }
+            if let Some(token) = &claim.token {
+                token.cancel();
+            }
+            false
+        });
+        // A claim heard of again, when a node catches up a newcomer, is renewed.
+        self.claims.retain(|claim| !(claim.node == node && claim.nonces == nonces));
+        self.claims.push(Claim { nonces, node, clock, heard_at: Instant::now(), token: None });
+    }
+
+    /// Records that `node` searched `nonces` without a solution, which ends its claim.
+    fn searched_by(&mut self, node: PeerId, nonces: &RangeInclusive<u64>) {
+        self.claims.retain(|claim| !(claim.node == node && claim.nonces == *nonces));
+        self.add_searched(nonces.clone());
+    }
+
+    /// Records `solution`, unless one was found first, and stops mining the puzzle.
+    fn solved(&mut self, solution: Solution) {
+        self.solution.get_or_insert(solution);
+        self.cancel_own();
+    }
+
+    /// Adds `nonces`, cut to the window, to the ranges searched.
+    fn add_searched(&mut self, nonces: RangeInclusive<u64>) {
+        let start = *nonces.start().max(self.nonces.start());
+        let end = *nonces.end().min(self.nonces.end());
+        if start > end {
+            return;
+        }
+        self.searched.push(start..=end);
+        self.searched.sort_unstable_by_key(|nonces| *nonces.start());
+        let mut merged: Vec<RangeInclusive<u64>> = Vec::with_capacity(self.searched.len());
+        for nonces in self.searched.drain(..) {
+            let touches = |last: &RangeInclusive<u64>| {
+                last.end().checked_add(1).is_none_or(|next| next >= *nonces.start())
+            };
+            match merged.last_mut() {
+                Some(last) if touches(last) => {
+                    *last = *last.start()..=*last.end().max(nonces.end());
+                }
+                _ => merged.push(nonces),
+            }
+        }
+        self.searched = merged;
+    }
+
+    /// Cancels the mining of this node's claims on the puzzle.
+    fn cancel_own(&mut self) {
+        for token in self.claims.iter().filter_map(|claim| claim.token.as_ref()) {
+            token.cancel();
+        }
+    }
+}
+
+/// The `claim` message for `nonces` of the puzzle `id` at `clock`.
+fn claim_message(id: u64, nonces: &RangeInclusive<u64>, clock: u64) -> Response {
+    Response::event("claim")
+        .number("puzzle", id)
+        .number("start", nonces.start())
+        .number("end", nonces.end())
+        .number("clock", clock)
+}
+
+/// The `solved` message for `nonce`, which solves the puzzle `id`.
+fn solved_message(id: u64, nonce: u64) -> Response {
+    Response::event("solved").number("puzzle", id).number("nonce", nonce)
+}
+
+/// The `searched` message for `nonces` of the puzzle `id`.
+fn searched_message(id: u64, nonces: &RangeInclusive<u64>) -> Response {
+    Response::event("searched")
+        .number("puzzle", id)
+        .number("start", nonces.start())
+        .number("end", nonces.end())
+}
+
+/// Parses a field of a message written with the `Display` form of `T`.
+fn parse<T: FromStr>(s: &str) -> Result<T, String>
+where
+    T::Err: fmt::Display,
+{
+    s.parse().map_err(|e: T::Err| e.to_string())
+}
+
+/// Parses `addr` as a multiaddr.
+fn multiaddr(addr: &str) -> Result<Multiaddr, GossipError> {
+    addr.parse().map_err(|e| GossipError::Address(format!("`{}`: {}", addr, e)))
+}
+
+/// Runs the node's side of the network until `outgoing` is closed: publishes what the
+/// node sends, and takes in what its peers do. Sends the node's peer id and address
+/// with `started` once listening, or why it could not.
+fn run_network(
+    listen: Multiaddr,
+    peers: Vec<Multiaddr>,
+    shared: Arc<Shared>,
+    mut outgoing: mpsc::UnboundedReceiver<String>,
+    started: std_mpsc::Sender<Result<(PeerId, Multiaddr), GossipError>>,
+) {
+    let network_error = |e: &dyn fmt::Display| GossipError::Network(e.to_string());
+    let runtime = match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            let _ = started.send(Err(network_error(&e)));
+            return;
+        }
+    };
+    runtime.block_on(async move {
+        let mut swarm = match join(listen, &peers) {
+            Ok(swarm) => swarm,
+            Err(e) => {
+                let _ = started.send(Err(e));
+                return;
+            }
+        };
+        let topic = IdentTopic::new(TOPIC);
+        let mut started = Some(started);
+        loop {
+            tokio::select! {
+                message = outgoing.recv() => match message {
+                    // Publishing fails while no peer is subscribed; the peers that join
+                    // later are caught up then.
+                    Some(message) => {
+                        let _ = swarm.behaviour_mut().publish(topic.clone(), message);
+                    }
+                    None => break,
+                },
+                event = swarm.select_next_some() => match event {
+                    SwarmEvent::NewListenAddr { address, .. } => {
+                        let peer_id = *swarm.local_peer_id();
+                        if let Some(started) = started.take() {
+                            let address = address.with(Protocol::P2p(peer_id));
+                            let _ = started.send(Ok((peer_id, address)));
+                        }
+                    }
+                    SwarmEvent::ListenerError { error, .. } => {
+                        if let Some(started) = started.take() {
+                            let _ = started.send(Err(network_error(&error)));
+                            return;
+                        }
+                    }
+                    SwarmEvent::Behaviour(gossipsub::Event::Message { message, .. }) => {
+                        if let Some(source) = message.source {
+                            shared.receive(source, &message.data);
+                        }
+                    }
+                    SwarmEvent::Behaviour(gossipsub::Event::Subscribed { .. }) => {
+                        let messages = shared.lock().announcements(*swarm.local_peer_id());
+                        for message in messages {
+                            let message = message.to_string();
+                            let _ = swarm.behaviour_mut().publish(topic.clone(), message);
+                        }
+                    }
+                    _ => {}
+                },
+            }
+        }
+    });
+}
+
+/// Builds the node's swarm, listens on `listen`, dials `peers` and joins the topic.
+fn join(
+    listen: Multiaddr,
+    peers: &[Multiaddr],
+) -> Result<Swarm<gossipsub::Behaviour>, GossipError> {
+    let network_error = |e: &dyn fmt::Display| GossipError::Network(e.to_string());
+    let mut swarm = SwarmBuilder::with_new_identity()
+        .with_tokio()
+        .with_tcp(tcp::Config::default(), noise::Config::new, yamux::Config::default)
+        .map_err(|e| network_error(&e))?
+        .with_behaviour(|key| {
+            let config = gossipsub::ConfigBuilder::default().build()?;
+            let authenticity = MessageAuthenticity::Signed(key.clone());
+            Ok::<_, Box<dyn Error + Send + Sync>>(gossipsub::Behaviour::new(authenticity, config)?)
+        })
+        .map_err(|e| network_error(&e))?
+        // Peers stay connected while idle between puzzles.
+        .with_swarm_config(|config| config.with_idle_connection_timeout(Duration::from_secs(3600)))
+        .build();
+    swarm.behaviour_mut().subscribe(&IdentTopic::new(TOPIC)).map_err(|e| network_error(&e))?;
+    swarm.listen_on(listen).map_err(|e| network_error(&e))?;
+    for peer in peers {
+        swarm.dial(peer.clone()).map_err(|e| network_error(&e))?;
+    }
+    Ok(swarm)
+}
@@ -0,0 +1,357 @@
+//! An optional GPU backend, behind the `gpu` feature.
+//!
+//! [`GpuMiner`] runs the nonce search as a compute shader through wgpu, on whichever GPU
+//! API the platform offers (Vulkan, Metal, DirectX 12 or OpenGL). The puzzle's message is
+//! hashed up to the nonce once on the CPU, as for the SIMD lanes, and each dispatch then
+//! tests a few million consecutive nonces from that midstate, one per invocation. The
+//! invocations whose hash meets the target record their nonce, and the CPU hashes every
+//! recorded candidate again before accepting it, so a faulty driver or shader compiler
+//! cannot produce a wrong solution.
+//!
+//! The shader only does SHA-256 with a fixed-width (`be` or `le`) nonce; other puzzles
+//! are [`GpuError::Unsupported`]. The search counts up through the window, so it finds
+//! the same lowest solution as a sequential CPU search unless a dispatch holds more
+//! solutions than it records, and rolls the extra nonce like the CPU engine. It cannot be
+//! paused, cancelled, checkpointed or observed. Software adapters that emulate a GPU on
+//! the CPU are slower than the CPU engine, so [`GpuMiner::new`] ignores them.
+
+use std::borrow::Cow;
+use std::error::Error;
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::sync::mpsc;
+
+use sha2::Sha256;
+use wgpu::util::DeviceExt;
+
+use crate::hybrid::Accelerator;
+use crate::lanes::Sha256Midstate;
+use crate::{
+    hash_with, CancellationToken, NonceEncoding, Puzzle, PuzzleError, Solution, Target,
+};
+
+/// The threads of one workgroup, as declared in the shader.
+const WORKGROUP_SIZE: u64 = 64;
+
+/// Workgroups along the first axis of a dispatch; a dispatch adds rows of this many
+/// until it covers its nonces, since one axis is limited to 65535 workgroups.
+const WORKGROUPS_PER_ROW: u64 = 4096;
+
+/// How many nonces one dispatch tests.
+const DISPATCH_NONCES: u64 = 1 << 22;
+
+/// How many candidates the shader records per dispatch, as declared in the shader.
+const MAX_CANDIDATES: usize = 64;
+
+/// The size of the shader's `Params`: sixteen state and target words and six scalars.
+const PARAMS_SIZE: u64 = 22 * 4;
+
+/// The size of the shader's `Results`: a counter and the candidates.
+const RESULTS_SIZE: u64 = (1 + MAX_CANDIDATES as u64) * 4;
+
+/// Why the GPU backend could not mine a puzzle.
+#[derive(Debug)]
+pub enum GpuError {
+    /// No GPU was found, or only a software adapter that emulates one on the CPU.
+    NoAdapter,
+    /// The GPU could not be set up or failed while searching, with the driver's message.
+    Device(String),
+    /// The puzzle needs something the shader does not do: a hash other than SHA-256 or an
+    /// `ascii` nonce.
+    Unsupported,
+    /// The GPU reported `nonce` as a solution, but its hash on the CPU does not meet the
+    /// target.
+    WrongSolution {
+        /// The nonce the GPU reported.
+        nonce: u64,
+    },
+    /// The search ran but found no solution, as a CPU search would report it.
+    Search(PuzzleError),
+}
+
+impl fmt::Display for GpuError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GpuError::NoAdapter => f.write_str("no GPU adapter was found"),
+            GpuError::Device(message) => write!(f, "the GPU failed: {}", message),
+            GpuError::Unsupported => {
+                f.write_str("the GPU only mines SHA-256 puzzles with a `be` or `le` nonce")
+            }
+            GpuError::WrongSolution { nonce } => {
+                write!(f, "the GPU reported nonce {}, which does not solve the puzzle", nonce)
+            }
+            GpuError::Search(e) => e.fmt(f),
+        }
+    }
+}
+
+impl Error for GpuError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            GpuError::Search(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<PuzzleError> for GpuError {
+    fn from(e: PuzzleError) -> Self {
+        GpuError::Search(e)
+    }
+}
+
+/// A GPU set up to mine SHA-256 puzzles; see the [module documentation](self).
+#[derive(Debug)]
+pub struct GpuMiner {
+    adapter: String,
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    pipeline: wgpu::ComputePipeline,
+    /// The shader's `Params`, rewritten before each dispatch.
+    params: wgpu::Buffer,
+    /// The shader's `Results`, cleared before each dispatch.
+    results: wgpu::Buffer,
+    /// Where `results` is copied for the CPU to read.
+    readback: wgpu::Buffer,
+}
+
+impl GpuMiner {
+    /// Finds the fastest GPU of the machine and compiles the shader for it.
+    ///
+    /// # Returns
+    ///
+    /// The miner, [`GpuError::NoAdapter`] if there is no hardware GPU, or
+    /// [`GpuError::Device`] if it cannot run the shader.
+    pub fn new() -> Result<Self, GpuError> {
+        let instance =
+            wgpu::Instance::new(wgpu::InstanceDescriptor::new_without_display_handle_from_env());
+        let options = wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            force_fallback_adapter: false,
+            compatible_surface: None,
+            apply_limit_buckets: false,
+        };
+        let adapter = pollster::block_on(instance.request_adapter(&options))
+            .map_err(|_| GpuError::NoAdapter)?;
+        let info = adapter.get_info();
+        if info.device_type == wgpu::DeviceType::Cpu {
+            return Err(GpuError::NoAdapter);
+        }
+        let (device, queue) =
+            pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor::default()))
+                .map_err(|e| GpuError::Device(e.to_string()))?;
+
+        // Report a shader the driver rejects as an error rather than a panic.
+        let scope = device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("sha256"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("gpu.wgsl").into()),
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("sha256"),
+            layout: None,
+            module: &module,
+            entry_point: Some("main"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+        if let Some(e) = pollster::block_on(scope.pop()) {
+            return Err(GpuError::Device(e.to_string()));
+        }
+
+        let buffer = |label, size, usage| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                size,
+                usage,
+                mapped_at_creation: false,
+            })
+        };
+        use wgpu::BufferUsages as Usage;
+        let params = buffer("params", PARAMS_SIZE, Usage::STORAGE | Usage::COPY_DST);
+        let results =
+            buffer("results", RESULTS_SIZE, Usage::STORAGE | Usage::COPY_SRC | Usage::COPY_DST);
+        let readback = buffer("readback", RESULTS_SIZE, Usage::MAP_READ | Usage::COPY_DST);
+        Ok(GpuMiner { adapter: info.name, device, queue, pipeline, params, results, readback })
+    }
+
+    /// The name of the GPU, as its driver reports it.
+    pub fn adapter_name(&self) -> &str {
+        &self.adapter
+    }
+
+    /// Searches `nonces` for a valid nonce of `puzzle` with SHA-256, like
+    /// [`parallel_mine_range`](crate::parallel_mine_range) but on the GPU.
+    ///
+    /// # Returns
+    ///
+    /// The [`Solution`], [`GpuError::Unsupported`] if the nonce encoding is `ascii`,
+    /// [`GpuError::Search`] with the [`PuzzleError`] a CPU search would give if there is
+    /// no solution, or another [`GpuError`] if the GPU failed.
+    pub fn mine(&self, puzzle: &Puzzle, nonces: RangeInclusive<u64>) -> Result<Solution, GpuError> {
+        self.mine_cancellable(puzzle, nonces, &CancellationToken::new())
+    }
+
+    /// Like [`mine`](Self::mine), but stops before the next launch once `token` is
+    /// cancelled, with [`PuzzleError::Cancelled`].
+    pub fn mine_cancellable(
+        &self,
+        puzzle: &Puzzle,
+        nonces: RangeInclusive<u64>,
+        token: &CancellationToken,
+    ) -> Result<Solution, GpuError> {
+        if !matches!(
+            puzzle.nonce_format.encoding,
+            NonceEncoding::BigEndian | NonceEncoding::LittleEndian
+        ) {
+            return Err(GpuError::Unsupported);
+        }
+        if puzzle.target == Target::ZERO {
+            return Err(PuzzleError::InvalidDifficulty.into());
+        }
+        if nonces.is_empty() {
+            return Err(PuzzleError::EmptyRange.into());
+        }
+        let mut puzzle = Cow::Borrowed(puzzle);
+        loop {
+            match (self.search(&puzzle, nonces.clone(), token), puzzle.extra_nonce) {
+                (Ok(solution), extra_nonce) => return Ok(Solution { extra_nonce, ..solution }),
+                (Err(GpuError::Search(PuzzleError::Exhausted)), Some(extra_nonce)) => {
+                    puzzle.to_mut().extra_nonce = Some(extra_nonce.wrapping_add(1));
+                }
+                (Err(e), _) => return Err(e),
+            }
+        }
+    }
+
+    /// Searches `nonces` of `puzzle` as it stands with its current extra nonce.
+    fn search(
+        &self,
+        puzzle: &Puzzle,
+        nonces: RangeInclusive<u64>,
+        token: &CancellationToken,
+    ) -> Result<Solution, GpuError> {
+        let message = Sha256Midstate::new(puzzle);
+        let tail: Vec<u8> = message.tail.iter().flat_map(|block| shader_words(block)).collect();
+        let tail = self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("tail"),
+            contents: &tail,
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("sha256"),
+            layout: &self.pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: self.params.as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 1, resource: tail.as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 2, resource: self.results.as_entire_binding() },
+            ],
+        });
+        let target = puzzle.target.to_be_bytes();
+        let little_endian = puzzle.nonce_format.encoding == NonceEncoding::LittleEndian;
+
+        let (mut base, end) = nonces.into_inner();
+        loop {
+            if token.is_cancelled() {
+                return Err(GpuError::Search(PuzzleError::Cancelled));
+            }
+            let count = (end - base).min(DISPATCH_NONCES - 1) + 1;
+            let mut params = Vec::with_capacity(PARAMS_SIZE as usize);
+            params.extend(message.state.iter().flat_map(|word| word.to_le_bytes()));
+            params.extend(shader_words(&target));
+            for scalar in [
+                base as u32,
+                (base >> 32) as u32,
+                message.nonce_offset as u32,
+                message.tail.len() as u32,
+                little_endian as u32,
+                count as u32,
+            ] {
+                params.extend(scalar.to_le_bytes());
+            }
+            self.queue.write_buffer(&self.params, 0, &params);
+            self.queue.write_buffer(&self.results, 0, &0u32.to_le_bytes());
+
+            let mut encoder = self.device.create_command_encoder(&Default::default());
+            {
+                let mut pass = encoder.begin_compute_pass(&Default::default());
+                pass.set_pipeline(&self.pipeline);
+                pass.set_bind_group(0, &bind_group, &[]);
+                let rows = count.div_ceil(WORKGROUP_SIZE * WORKGROUPS_PER_ROW);
+                pass.dispatch_workgroups(WORKGROUPS_PER_ROW as u32, rows as u32, 1);
+            }
+            encoder.copy_buffer_to_buffer(&self.results, 0, &self.readback, 0, RESULTS_SIZE);
+            self.queue.submit([encoder.finish()]);
+
+            let mut candidates = self.read_candidates()?;
+            candidates.sort_unstable();
+            // Accept the lowest candidate, once the CPU has hashed it too.
+            if let Some(&index) = candidates.first() {
+                let nonce = base + u64::from(index);
+                let hash = hash_with::<Sha256>(puzzle, nonce);
+                if !puzzle.target.is_met_by(&hash) {
+                    return Err(GpuError::WrongSolution { nonce });
+                }
+                return Ok(Solution { nonce, hash: hash.to_vec(), extra_nonce: None });
+            }
+            if end - base < DISPATCH_NONCES {
+                return Err(PuzzleError::Exhausted.into());
+            }
+            base += count;
+        }
+    }
+
+    /// Waits for the last dispatch and reads the candidates it recorded.
+    fn read_candidates(&self) -> Result<Vec<u32>, GpuError> {
+        let (sender, receiver) = mpsc::channel();
+        self.readback.map_async(wgpu::MapMode::Read, .., move |mapped| {
+            let _ = sender.send(mapped);
+        });
+        self.device
+            .poll(wgpu::PollType::wait_indefinitely())
+            .map_err(|e| GpuError::Device(e.to_string()))?;
+        receiver
+            .recv()
+            .map_err(|e| GpuError::Device(e.to_string()))?
+            .map_err(|e| GpuError::Device(e.to_string()))?;
+
+        let words: Vec<u32> = {
+            let bytes =
+                self.readback.get_mapped_range(..).map_err(|e| GpuError::Device(e.to_string()))?;
+            bytes
+                .chunks_exact(4)
+                .map(|word| u32::from_le_bytes(word.try_into().expect("four bytes")))
+                .collect()
+        };
+        self.readback.unmap();
+        let found = (words[0] as usize).min(MAX_CANDIDATES);
+        Ok(words[1..=found].to_vec())
+    }
+}
+
+impl Accelerator for GpuMiner {
+    type Error = GpuError;
+
+    fn mine_cancellable(
+        &self,
+        puzzle: &Puzzle,
+        nonces: RangeInclusive<u64>,
+        token: &CancellationToken,
+    ) -> Result<Solution, GpuError> {
+        GpuMiner::mine_cancellable(self, puzzle, nonces, token)
+    }
+
+    fn into_search_error(error: GpuError) -> Result<PuzzleError, GpuError> {
+        match error {
+            GpuError::Search(e) => Ok(e),
+            e => Err(e),
+        }
+    }
+}
+
+/// The big-endian words of `bytes`, laid out as the shader reads `u32`s.
+fn shader_words(bytes: &[u8]) -> impl Iterator<Item = u8> + '_ {
+    bytes.chunks_exact(4).flat_map(|word| {
+        u32::from_be_bytes(word.try_into().expect("four bytes")).to_le_bytes()
+    })
+}
@@ -0,0 +1,142 @@
+// SHA-256 of one nonce per invocation, from the midstate of the puzzle; see `gpu.rs`.
+//
+// Invocation `index` hashes the nonce `base + index` and, if the hash is at most the
+// target, records `index` in `results` for the CPU to verify.
+
+struct Params {
+    // The state after every full block before the nonce.
+    state: array<u32, 8>,
+    // The target as eight big-endian words, most significant first.
+    target_words: array<u32, 8>,
+    // The first nonce of the dispatch, split into 32-bit halves.
+    base_lo: u32,
+    base_hi: u32,
+    // Where the nonce starts in `tail`, in bytes.
+    nonce_offset: u32,
+    // How many blocks `tail` holds.
+    blocks: u32,
+    // 1 for a little-endian nonce, 0 for a big-endian one.
+    little_endian: u32,
+    // How many nonces the dispatch tests.
+    count: u32,
+}
+
+struct Results {
+    // How many invocations found a hash at most the target; only the first
+    // `MAX_CANDIDATES` are recorded.
+    found: atomic<u32>,
+    candidates: array<u32, 64>,
+}
+
+const MAX_CANDIDATES: u32 = 64u;
+const WORKGROUP_SIZE: u32 = 64u;
+
+@group(0) @binding(0) var<storage, read> params: Params;
+// The padded rest of the message as big-endian words, with zeros in place of the nonce.
+@group(0) @binding(1) var<storage, read> tail: array<u32>;
+@group(0) @binding(2) var<storage, read_write> results: Results;
+
+var<private> K: array<u32, 64> = array<u32, 64>(
+    0x428a2f98u, 0x71374491u, 0xb5c0fbcfu, 0xe9b5dba5u, 0x3956c25bu, 0x59f111f1u, 0x923f82a4u, 0xab1c5ed5u,
+    0xd807aa98u, 0x12835b01u, 0x243185beu, 0x550c7dc3u, 0x72be5d74u, 0x80deb1feu, 0x9bdc06a7u, 0xc19bf174u,
+    0xe49b69c1u, 0xefbe4786u, 0x0fc19dc6u, 0x240ca1ccu, 0x2de92c6fu, 0x4a7484aau, 0x5cb0a9dcu, 0x76f988dau,
+    0x983e5152u, 0xa831c66du, 0xb00327c8u, 0xbf597fc7u, 0xc6e00bf3u, 0xd5a79147u, 0x06ca6351u, 0x14292967u,
+    0x27b70a85u, 0x2e1b2138u, 0x4d2c6dfcu, 0x53380d13u, 0x650a7354u, 0x766a0abbu, 0x81c2c92eu, 0x92722c85u,
+    0xa2bfe8a1u, 0xa81a664bu, 0xc24b8b70u, 0xc76c51a3u, 0xd192e819u, 0xd6990624u, 0xf40e3585u, 0x106aa070u,
+    0x19a4c116u, 0x1e376c08u, 0x2748774cu, 0x34b0bcb5u, 0x391c0cb3u, 0x4ed8aa4au, 0x5b9cca4fu, 0x682e6ff3u,
+    0x748f82eeu, 0x78a5636fu, 0x84c87814u, 0x8cc70208u, 0x90befffau, 0xa4506cebu, 0xbef9a3f7u, 0xc67178f2u,
+);
+
+fn rotr(x: u32, n: u32) -> u32 {
+    return (x >> n) | (x << (32u - n));
+}
+
+@compute @workgroup_size(64)
+fn main(@builtin(global_invocation_id) id: vec3<u32>, @builtin(num_workgroups) groups: vec3<u32>) {
+    let index = id.x + id.y * groups.x * WORKGROUP_SIZE;
+    if index >= params.count {
+        return;
+    }
+    let lo = params.base_lo + index;
+    let hi = params.base_hi + select(0u, 1u, lo < index);
+
+    // The bytes of the encoded nonce, in message order.
+    var nonce: array<u32, 8>;
+    for (var i = 0u; i < 4u; i++) {
+        if params.little_endian == 1u {
+            nonce[i] = (lo >> (8u * i)) & 0xffu;
+            nonce[i + 4u] = (hi >> (8u * i)) & 0xffu;
+        } else {
+            nonce[i] = (hi >> (24u - 8u * i)) & 0xffu;
+            nonce[i + 4u] = (lo >> (24u - 8u * i)) & 0xffu;
+        }
+    }
+
+    var h = params.state;
+    var w: array<u32, 64>;
+    for (var block = 0u; block < params.blocks; block++) {
+        for (var t = 0u; t < 16u; t++) {
+            w[t] = tail[16u * block + t];
+        }
+        // Write in the nonce bytes that fall in this block.
+        for (var i = 0u; i < 8u; i++) {
+            let position = params.nonce_offset + i;
+            if position / 64u == block {
+                let byte = position % 64u;
+                w[byte / 4u] |= nonce[i] << (24u - 8u * (byte % 4u));
+            }
+        }
+        for (var t = 16u; t < 64u; t++) {
+            let s0 = rotr(w[t - 15u], 7u) ^ rotr(w[t - 15u], 18u) ^ (w[t - 15u] >> 3u);
+            let s1 = rotr(w[t - 2u], 17u) ^ rotr(w[t - 2u], 19u) ^ (w[t - 2u] >> 10u);
+            w[t] = w[t - 16u] + s0 + w[t - 7u] + s1;
+        }
+
+        var a = h[0];
+        var b = h[1];
+        var c = h[2];
+        var d = h[3];
+        var e = h[4];
+        var f = h[5];
+        var g = h[6];
+        var hh = h[7];
+        for (var t = 0u; t < 64u; t++) {
+            let s1 = rotr(e, 6u) ^ rotr(e, 11u) ^ rotr(e, 25u);
+            let ch = (e & f) ^ (~e & g);
+            let t1 = hh + s1 + ch + K[t] + w[t];
+            let s0 = rotr(a, 2u) ^ rotr(a, 13u) ^ rotr(a, 22u);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0 + maj;
+            hh = g;
+            g = f;
+            f = e;
+            e = d + t1;
+            d = c;
+            c = b;
+            b = a;
+            a = t1 + t2;
+        }
+        h[0] += a;
+        h[1] += b;
+        h[2] += c;
+        h[3] += d;
+        h[4] += e;
+        h[5] += f;
+        h[6] += g;
+        h[7] += hh;
+    }
+
+    // The hash read as a big-endian number must not exceed the target.
+    for (var j = 0u; j < 8u; j++) {
+        if h[j] < params.target_words[j] {
+            break;
+        }
+        if h[j] > params.target_words[j] {
+            return;
+        }
+    }
+    let slot = atomicAdd(&results.found, 1u);
+    if slot < MAX_CANDIDATES {
+        results.candidates[slot] = index;
+    }
+}
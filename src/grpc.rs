@@ -0,0 +1,452 @@
+//! A gRPC interface to the [`server`](crate::server), with the `grpc` feature.
+//!
+//! [`WorkServer::run_grpc`](crate::WorkServer::run_grpc) serves the puzzles, workers and
+//! leases of the HTTP API over gRPC as well, so a worker can use either, for typed
+//! messages and streams instead of JSON over one request per connection. The service,
+//! `puzzle.v1.PuzzleDistribution`, is described in `proto/puzzle.proto`, from which
+//! workers in other languages can be generated:
+//!
+//! - `Register` registers a worker and returns its `worker` id.
+//! - `GetWork` streams leases to a worker: one whenever the worker holds none and there
+//!   is a range to lease. A worker that reports on each lease is sent the next without
+//!   asking, and one with nothing to do waits on the stream rather than polling. The
+//!   stream ends when the server shuts down.
+//! - `SubmitSolution` submits the nonce that solves a lease, which the server checks
+//!   before accepting it, and `ReportExhausted` reports that a lease holds none. Both
+//!   return the puzzle's state.
+//! - `SubmitShare` sends a nonce that meets the lease's `share_target`, which a server
+//!   that counts shares sets, as proof of a worker's effort. It returns whether the
+//!   nonce also solves the puzzle, which is then still to be submitted, and the worker's
+//!   shares so far; `GetWorker` reports those too.
+//! - `ReportProgress` takes a stream of how many nonces of its lease a worker has
+//!   searched, which counts towards the puzzle's `searched`.
+//! - `GetPuzzle` reports a puzzle's state: its outcome, its solution once solved, and
+//!   the nonces searched so far.
+//!
+//! A worker, lease or puzzle that does not exist is refused with `NOT_FOUND`, a nonce
+//! outside its lease or that solves nothing with `INVALID_ARGUMENT`, and a report on a
+//! lease that has expired with `FAILED_PRECONDITION`.
+//!
+//! The generated [`PuzzleDistributionClient`] is the Rust end of the service, and a
+//! [`Lease`] it receives converts into a [`WorkLease`] to mine.
+//!
+//! [`PuzzleDistributionClient`]: puzzle_distribution_client::PuzzleDistributionClient
+
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::{ReceiverStream, TcpListenerStream};
+use tonic::transport::Server;
+use tonic::{Request, Response, Status, Streaming};
+
+use crate::server::{Coordinator, JobStatus, Refusal};
+#[cfg(feature = "tls")]
+use crate::ServerTls;
+use crate::{NonceFormat, Puzzle, Target, WorkError, WorkLease, WorkerShares};
+
+use puzzle_distribution_server::{PuzzleDistribution, PuzzleDistributionServer};
+
+include!(concat!(env!("OUT_DIR"), "/puzzle.v1.PuzzleDistribution.rs"));
+
+/// A request to register a worker.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct RegisterRequest {}
+
+/// A registered worker's id.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct RegisterReply {
+    #[prost(uint64, tag = "1")]
+    pub worker: u64,
+}
+
+/// A request for a stream of leases for `worker`.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct GetWorkRequest {
+    #[prost(uint64, tag = "1")]
+    pub worker: u64,
+}
+
+/// A range of a puzzle's window leased to a worker.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Lease {
+    #[prost(uint64, tag = "1")]
+    pub lease: u64,
+    #[prost(uint64, tag = "2")]
+    pub puzzle: u64,
+    /// The [name](crate::Algorithm::name) of the hash.
+    #[prost(string, tag = "3")]
+    pub algorithm: String,
+    #[prost(bytes = "vec", tag = "4")]
+    pub data: Vec<u8>,
+    /// The target, 32 bytes big-endian.
+    #[prost(bytes = "vec", tag = "5")]
+    pub target: Vec<u8>,
+    /// The [name](crate::NonceEncoding::name) of the nonce encoding.
+    #[prost(string, tag = "6")]
+    pub nonce_encoding: String,
+    #[prost(uint64, optional, tag = "7")]
+    pub nonce_offset: Option<u64>,
+    #[prost(uint64, optional, tag = "8")]
+    pub extra_nonce: Option<u64>,
+    /// The first leased nonce.
+    #[prost(uint64, tag = "9")]
+    pub start: u64,
+    /// The last leased nonce.
+    #[prost(uint64, tag = "10")]
+    pub end: u64,
+    /// The target of the shares the server counts, 32 bytes big-endian, if it counts any.
+    #[prost(bytes = "vec", optional, tag = "11")]
+    pub share_target: Option<Vec<u8>>,
+}
+
+/// The nonce that solves a lease.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct SubmitSolutionRequest {
+    #[prost(uint64, tag = "1")]
+    pub worker: u64,
+    #[prost(uint64, tag = "2")]
+    pub lease: u64,
+    #[prost(uint64, tag = "3")]
+    pub nonce: u64,
+}
+
+/// A nonce of a lease that meets its share target.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct SubmitShareRequest {
+    #[prost(uint64, tag = "1")]
+    pub worker: u64,
+    #[prost(uint64, tag = "2")]
+    pub lease: u64,
+    #[prost(uint64, tag = "3")]
+    pub nonce: u64,
+}
+
+/// A share accepted.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ShareReply {
+    /// Whether the share also solves the puzzle.
+    #[prost(bool, tag = "1")]
+    pub solves: bool,
+    /// The worker's shares, this one included.
+    #[prost(message, optional, tag = "2")]
+    pub worker: Option<WorkerState>,
+}
+
+/// A request for the shares of the worker `worker`.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct GetWorkerRequest {
+    #[prost(uint64, tag = "1")]
+    pub worker: u64,
+}
+
+/// The shares counted for a worker; see [`WorkerShares`].
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct WorkerState {
+    #[prost(uint64, tag = "1")]
+    pub worker: u64,
+    #[prost(uint64, tag = "2")]
+    pub shares: u64,
+    /// The nonces rejected, as shares or solutions.
+    #[prost(uint64, tag = "6")]
+    pub invalid: u64,
+    /// The hashes the shares prove, on average.
+    #[prost(double, tag = "3")]
+    pub work: f64,
+    /// How many seconds ago the last share was accepted, if any was.
+    #[prost(double, optional, tag = "4")]
+    pub since_last_share: Option<f64>,
+    #[prost(bool, tag = "5")]
+    pub idle: bool,
+    /// Whether too many of the worker's nonces were rejected.
+    #[prost(bool, tag = "7")]
+    pub flagged: bool,
+}
+
+/// A report that a lease holds no solution.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ReportExhaustedRequest {
+    #[prost(uint64, tag = "1")]
+    pub worker: u64,
+    #[prost(uint64, tag = "2")]
+    pub lease: u64,
+}
+
+/// How many nonces of its lease a worker has searched so far.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ProgressReport {
+    #[prost(uint64, tag = "1")]
+    pub worker: u64,
+    #[prost(uint64, tag = "2")]
+    pub lease: u64,
+    #[prost(uint64, tag = "3")]
+    pub searched: u64,
+}
+
+/// The end of a stream of progress reports.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ProgressSummary {
+    /// The reports received.
+    #[prost(uint64, tag = "1")]
+    pub reports: u64,
+}
+
+/// A request for where the puzzle `puzzle` stands.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct GetPuzzleRequest {
+    #[prost(uint64, tag = "1")]
+    pub puzzle: u64,
+}
+
+/// How a puzzle has turned out so far.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, prost::Enumeration)]
+#[repr(i32)]
+pub enum Outcome {
+    Running = 0,
+    Solved = 1,
+    /// The whole window was searched without a solution.
+    Exhausted = 2,
+}
+
+/// Where a puzzle stands.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct PuzzleState {
+    #[prost(uint64, tag = "1")]
+    pub puzzle: u64,
+    #[prost(enumeration = "Outcome", tag = "2")]
+    pub outcome: i32,
+    /// The solution's nonce, once solved.
+    #[prost(uint64, optional, tag = "3")]
+    pub nonce: Option<u64>,
+    /// The solution's hash, once solved.
+    #[prost(bytes = "vec", tag = "4")]
+    pub hash: Vec<u8>,
+    /// The solution's extra nonce, if it has one.
+    #[prost(uint64, optional, tag = "5")]
+    pub extra_nonce: Option<u64>,
+    /// The nonces workers have said they searched, over every pass of the window.
+    #[prost(uint64, tag = "6")]
+    pub searched: u64,
+}
+
+impl From<&WorkLease> for Lease {
+    fn from(lease: &WorkLease) -> Self {
+        let format = lease.puzzle.nonce_format;
+        Lease {
+            lease: lease.id,
+            puzzle: lease.puzzle_id,
+            algorithm: lease.algorithm.name().to_string(),
+            data: lease.puzzle.data.clone(),
+            target: lease.puzzle.target.to_be_bytes().to_vec(),
+            nonce_encoding: format.encoding.name().to_string(),
+            nonce_offset: format.offset.map(|offset| offset as u64),
+            extra_nonce: lease.puzzle.extra_nonce,
+            start: *lease.nonces.start(),
+            end: *lease.nonces.end(),
+            share_target: lease.share_target.map(|target| target.to_be_bytes().to_vec()),
+        }
+    }
+}
+
+impl TryFrom<Lease> for WorkLease {
+    type Error = WorkError;
+
+    /// Reads a lease received from the service.
+    fn try_from(lease: Lease) -> Result<Self, WorkError> {
+        let target = |bytes: &[u8]| -> Result<Target, WorkError> {
+            let bytes = bytes.try_into();
+            let bytes = bytes.map_err(|_| "the target is not 32 bytes".to_string())?;
+            Ok(Target::from_be_bytes(bytes))
+        };
+        let offset = lease
+            .nonce_offset
+            .map(usize::try_from)
+            .transpose()
+            .map_err(|_| WorkError::Malformed("the nonce offset is too large".to_string()))?;
+        let nonce_format = NonceFormat { encoding: lease.nonce_encoding.parse()?, offset };
+        let mut puzzle =
+            Puzzle::new(lease.data, target(&lease.target)?).with_nonce_format(nonce_format);
+        puzzle.extra_nonce = lease.extra_nonce;
+        Ok(WorkLease {
+            id: lease.lease,
+            puzzle_id: lease.puzzle,
+            puzzle,
+            algorithm: lease.algorithm.parse()?,
+            nonces: lease.start..=lease.end,
+            share_target: lease.share_target.as_deref().map(target).transpose()?,
+        })
+    }
+}
+
+impl From<JobStatus> for PuzzleState {
+    fn from(status: JobStatus) -> Self {
+        let outcome = match &status.solution {
+            Some(_) => Outcome::Solved,
+            None if status.finished => Outcome::Exhausted,
+            None => Outcome::Running,
+        };
+        let (nonce, hash, extra_nonce) = match status.solution {
+            Some(solution) => (Some(solution.nonce), solution.hash, solution.extra_nonce),
+            None => (None, Vec::new(), None),
+        };
+        PuzzleState {
+            puzzle: status.id,
+            outcome: outcome.into(),
+            nonce,
+            hash,
+            extra_nonce,
+            searched: status.searched,
+        }
+    }
+}
+
+impl From<WorkerShares> for WorkerState {
+    fn from(shares: WorkerShares) -> Self {
+        WorkerState {
+            worker: shares.worker,
+            shares: shares.shares,
+            invalid: shares.invalid,
+            work: shares.work,
+            since_last_share: shares.since_last_share.map(|since| since.as_secs_f64()),
+            idle: shares.idle,
+            flagged: shares.flagged,
+        }
+    }
+}
+
+impl From<Refusal> for Status {
+    fn from(refusal: Refusal) -> Self {
+        match refusal {
+            Refusal::NotFound(message) => Status::not_found(message),
+            Refusal::Rejected(message) => Status::invalid_argument(message),
+            Refusal::Expired(message) => Status::failed_precondition(message),
+            Refusal::Unavailable(message) => Status::unavailable(message),
+        }
+    }
+}
+
+/// The service, on the coordinator of a [`WorkServer`](crate::WorkServer).
+struct Distribution(Arc<Coordinator>);
+
+#[tonic::async_trait]
+impl PuzzleDistribution for Distribution {
+    type GetWorkStream = ReceiverStream<Result<Lease, Status>>;
+
+    async fn register(
+        &self,
+        _: Request<RegisterRequest>,
+    ) -> Result<Response<RegisterReply>, Status> {
+        Ok(Response::new(RegisterReply { worker: self.0.register()? }))
+    }
+
+    async fn get_work(
+        &self,
+        request: Request<GetWorkRequest>,
+    ) -> Result<Response<Self::GetWorkStream>, Status> {
+        let worker = request.into_inner().worker;
+        let coordinator = Arc::clone(&self.0);
+        let (sender, receiver) = mpsc::channel(1);
+        // Waiting for a lease blocks, so each stream waits on a thread of its own.
+        tokio::task::spawn_blocking(move || loop {
+            let lease = match coordinator.wait_for_lease(worker, || sender.is_closed()) {
+                Ok(Some(lease)) => Ok(Lease::from(&lease)),
+                Ok(None) => break,
+                Err(refusal) => Err(Status::from(refusal)),
+            };
+            let refused = lease.is_err();
+            if sender.blocking_send(lease).is_err() || refused {
+                break;
+            }
+        });
+        Ok(Response::new(ReceiverStream::new(receiver)))
+    }
+
+    async fn submit_solution(
+        &self,
+        request: Request<SubmitSolutionRequest>,
+    ) -> Result<Response<PuzzleState>, Status> {
+        let SubmitSolutionRequest { worker, lease, nonce } = request.into_inner();
+        Ok(Response::new(self.0.submit(worker, lease, nonce)?.into()))
+    }
+
+    async fn submit_share(
+        &self,
+        request: Request<SubmitShareRequest>,
+    ) -> Result<Response<ShareReply>, Status> {
+        let SubmitShareRequest { worker, lease, nonce } = request.into_inner();
+        let solves = self.0.share(worker, lease, nonce)?;
+        let worker = Some(self.0.worker(worker)?.into());
+        Ok(Response::new(ShareReply { solves, worker }))
+    }
+
+    async fn report_exhausted(
+        &self,
+        request: Request<ReportExhaustedRequest>,
+    ) -> Result<Response<PuzzleState>, Status> {
+        let ReportExhaustedRequest { worker, lease } = request.into_inner();
+        Ok(Response::new(self.0.exhausted(worker, lease)?.into()))
+    }
+
+    async fn report_progress(
+        &self,
+        request: Request<Streaming<ProgressReport>>,
+    ) -> Result<Response<ProgressSummary>, Status> {
+        let mut reports = request.into_inner();
+        let mut count = 0;
+        while let Some(report) = reports.message().await? {
+            self.0.progress(report.worker, report.lease, report.searched)?;
+            count += 1;
+        }
+        Ok(Response::new(ProgressSummary { reports: count }))
+    }
+
+    async fn get_worker(
+        &self,
+        request: Request<GetWorkerRequest>,
+    ) -> Result<Response<WorkerState>, Status> {
+        Ok(Response::new(self.0.worker(request.into_inner().worker)?.into()))
+    }
+
+    async fn get_puzzle(
+        &self,
+        request: Request<GetPuzzleRequest>,
+    ) -> Result<Response<PuzzleState>, Status> {
+        Ok(Response::new(self.0.status(request.into_inner().puzzle)?.into()))
+    }
+}
+
+/// Serves the service for `coordinator` on `addr`, over TLS if given `tls`, until the
+/// server shuts down.
+///
+/// # Returns
+///
+/// `Ok(())` once shut down, or the error that stopped the service from listening.
+pub(crate) fn serve(
+    coordinator: Arc<Coordinator>,
+    addr: SocketAddr,
+    #[cfg(feature = "tls")] tls: Option<&ServerTls>,
+) -> io::Result<()> {
+    let mut builder = Server::builder();
+    #[cfg(feature = "tls")]
+    if let Some(tls) = tls {
+        builder = builder.tls_config(tls.grpc_config()).map_err(io::Error::other)?;
+    }
+    let runtime = tokio::runtime::Builder::new_multi_thread().enable_all().build()?;
+    let result = runtime.block_on(async {
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+        let waiter = Arc::clone(&coordinator);
+        let shutdown = tokio::task::spawn_blocking(move || waiter.wait_for_shutdown());
+        builder
+            .add_service(PuzzleDistributionServer::new(Distribution(coordinator)))
+            .serve_with_incoming_shutdown(TcpListenerStream::new(listener), async {
+                let _ = shutdown.await;
+            })
+            .await
+            .map_err(io::Error::other)
+    });
+    // The threads blocked on the coordinator return once the server shuts down, which
+    // may be after this does.
+    runtime.shutdown_background();
+    result
+}
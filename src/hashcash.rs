@@ -0,0 +1,307 @@
+//! Hashcash version 1 stamps, as used for anti-spam proof-of-work.
+//!
+//! A stamp looks like `1:20:261014:alice@example.com::McMybZIhxKXu57jd:3187`: version,
+//! claimed bits, date (`YYMMDD`, UTC), resource, extension, random salt and counter. It
+//! is valid when the SHA-1 hash of the whole string starts with at least `bits` zero
+//! bits. Minting a stamp is just a puzzle over everything up to the counter with the
+//! counter written as ASCII digits, so the ordinary parallel miner finds it.
+
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rand::Rng;
+use sha1::Sha1;
+
+use crate::digest::Digest;
+use crate::{
+    leading_zero_bits, parallel_mine_with, Difficulty, NonceEncoding, NonceFormat, Puzzle,
+    PuzzleError,
+};
+
+/// The stamp format version produced and accepted here.
+const VERSION: u32 = 1;
+
+/// Characters used for the random salt (the base64 alphabet).
+const SALT_ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Length of the random salt; 16 base64 characters carry 96 bits of randomness.
+const SALT_LEN: usize = 16;
+
+/// The reasons a string cannot be parsed as a Hashcash stamp.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StampError {
+    /// The stamp does not have the seven `:`-separated fields of a version 1 stamp.
+    Malformed,
+    /// The version field is not `1`.
+    UnsupportedVersion(String),
+    /// The bits field is not a number.
+    InvalidBits(String),
+    /// A field of a stamp being minted contains the `:` that separates the fields.
+    InvalidField {
+        /// The name of the field.
+        field: &'static str,
+        /// Its value.
+        value: String,
+    },
+}
+
+impl fmt::Display for StampError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StampError::Malformed => f.write_str("stamp does not have 7 `:`-separated fields"),
+            StampError::UnsupportedVersion(v) => write!(f, "unsupported stamp version `{}`", v),
+            StampError::InvalidBits(b) => write!(f, "invalid bits field `{}`", b),
+            StampError::InvalidField { field, value } => {
+                write!(f, "{} `{}` contains a `:`", field, value)
+            }
+        }
+    }
+}
+
+impl Error for StampError {}
+
+/// Why a stamp could not be minted.
+#[derive(Debug)]
+pub enum MintError {
+    /// A field would not survive formatting and parsing the stamp.
+    Stamp(StampError),
+    /// The search found no counter.
+    Search(PuzzleError),
+}
+
+impl fmt::Display for MintError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MintError::Stamp(e) => e.fmt(f),
+            MintError::Search(e) => e.fmt(f),
+        }
+    }
+}
+
+impl Error for MintError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            MintError::Stamp(e) => e.source(),
+            MintError::Search(e) => e.source(),
+        }
+    }
+}
+
+impl From<StampError> for MintError {
+    fn from(e: StampError) -> Self {
+        MintError::Stamp(e)
+    }
+}
+
+impl From<PuzzleError> for MintError {
+    fn from(e: PuzzleError) -> Self {
+        MintError::Search(e)
+    }
+}
+
+/// A Hashcash version 1 stamp.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Stamp {
+    /// The number of leading zero bits the stamp claims.
+    pub bits: u32,
+    /// The date the stamp was minted, as `YYMMDD`.
+    pub date: String,
+    /// What the stamp was minted for, typically an e-mail address.
+    pub resource: String,
+    /// Extension field; empty for ordinary stamps.
+    pub ext: String,
+    /// Random salt that keeps stamps for the same resource and date distinct.
+    pub rand: String,
+    /// The counter found by minting.
+    pub counter: String,
+}
+
+impl Stamp {
+    /// Creates an unminted stamp for `resource` dated today with a fresh random salt.
+    ///
+    /// The counter is empty until [`mint`](Stamp::mint) is called.
+    pub fn new(resource: impl Into<String>, bits: u32) -> Self {
+        let mut rng = rand::thread_rng();
+        let rand = (0..SALT_LEN)
+            .map(|_| SALT_ALPHABET[rng.gen_range(0..SALT_ALPHABET.len())] as char)
+            .collect();
+        Stamp {
+            bits,
+            date: today(),
+            resource: resource.into(),
+            ext: String::new(),
+            rand,
+            counter: String::new(),
+        }
+    }
+
+    /// Everything up to and including the `:` before the counter.
+    fn prefix(&self) -> String {
+        format!(
+            "{}:{}:{}:{}:{}:{}:",
+            VERSION, self.bits, self.date, self.resource, self.ext, self.rand
+        )
+    }
+
+    /// Finds a counter for this stamp on `num_threads` threads.
+    ///
+    /// # Returns
+    ///
+    /// The stamp with its counter filled in, a [`MintError::Stamp`] if the date, resource,
+    /// extension or salt contains a `:`, which would split it into extra fields, or a
+    /// [`MintError::Search`] if the nonce range was exhausted first.
+    pub fn mint(mut self, num_threads: usize) -> Result<Self, MintError> {
+        let fields = [
+            ("date", &self.date),
+            ("resource", &self.resource),
+            ("ext", &self.ext),
+            ("rand", &self.rand),
+        ];
+        if let Some((field, value)) = fields.into_iter().find(|(_, value)| value.contains(':')) {
+            return Err(StampError::InvalidField { field, value: value.clone() }.into());
+        }
+        let puzzle = Puzzle::new(self.prefix(), Difficulty::LeadingZeroBits(self.bits))
+            .with_nonce_format(NonceFormat { encoding: NonceEncoding::Ascii, offset: None });
+        let solution = parallel_mine_with::<Sha1>(&puzzle, num_threads)?;
+        self.counter = solution.nonce.to_string();
+        Ok(self)
+    }
+
+    /// The SHA-1 hash of the complete stamp.
+    pub fn hash(&self) -> Vec<u8> {
+        Sha1::digest(self.to_string().as_bytes()).to_vec()
+    }
+
+    /// Whether the stamp's hash has at least the number of zero bits it claims.
+    pub fn is_valid(&self) -> bool {
+        leading_zero_bits(&self.hash()) >= self.bits
+    }
+
+    /// The stamp formatted as a mail header line.
+    pub fn header(&self) -> String {
+        format!("X-Hashcash: {}", self)
+    }
+}
+
+impl fmt::Display for Stamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.prefix(), self.counter)
+    }
+}
+
+impl FromStr for Stamp {
+    type Err = StampError;
+
+    /// Parses a stamp, with or without a leading `X-Hashcash:` header name.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let s = s.strip_prefix("X-Hashcash:").map(str::trim_start).unwrap_or(s);
+        let fields: Vec<&str> = s.split(':').collect();
+        let [version, bits, date, resource, ext, rand, counter] = fields[..] else {
+            return Err(StampError::Malformed);
+        };
+        if version != VERSION.to_string() {
+            return Err(StampError::UnsupportedVersion(version.to_string()));
+        }
+        Ok(Stamp {
+            bits: bits.parse().map_err(|_| StampError::InvalidBits(bits.to_string()))?,
+            date: date.to_string(),
+            resource: resource.to_string(),
+            ext: ext.to_string(),
+            rand: rand.to_string(),
+            counter: counter.to_string(),
+        })
+    }
+}
+
+/// Today's UTC date as `YYMMDD`.
+fn today() -> String {
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+    format!("{:02}{:02}{:02}", year % 100, month, day)
+}
+
+/// Converts days since 1970-01-01 into a proleptic Gregorian `(year, month, day)`.
+///
+/// This is Howard Hinnant's `civil_from_days` algorithm.
+pub(crate) fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn minted_stamps_parse_back_and_are_valid() {
+        let mut stamp = Stamp::new("alice@example.com", 12);
+        stamp.date = "261015".to_string();
+        stamp.ext = "note=hi".to_string();
+        let minted = stamp.mint(4).unwrap();
+        assert!(minted.is_valid());
+        assert!(leading_zero_bits(&minted.hash()) >= 12);
+
+        let parsed: Stamp = minted.to_string().parse().unwrap();
+        assert_eq!(parsed, minted);
+        assert!(parsed.is_valid());
+        let header: Stamp = minted.header().parse().unwrap();
+        assert_eq!(header, minted);
+
+        // Claiming more bits than the hash has makes the same stamp invalid.
+        let greedy = Stamp { bits: 160, ..minted };
+        assert!(!greedy.is_valid());
+    }
+
+    #[test]
+    fn colons_in_minted_fields_are_refused() {
+        for field in ["date", "resource", "ext", "rand"] {
+            let mut stamp = Stamp::new("alice@example.com", 1);
+            let value = match field {
+                "date" => &mut stamp.date,
+                "resource" => &mut stamp.resource,
+                "ext" => &mut stamp.ext,
+                _ => &mut stamp.rand,
+            };
+            value.push_str(":x");
+            let expected = StampError::InvalidField { field, value: value.clone() };
+            match stamp.mint(1) {
+                Err(MintError::Stamp(error)) => assert_eq!(error, expected),
+                other => panic!("expected {:?}, got {:?}", expected, other),
+            }
+        }
+    }
+
+    #[test]
+    fn malformed_stamps_are_rejected() {
+        let parse = |s: &str| s.parse::<Stamp>();
+        assert_eq!(parse("1:20:261015:alice::salt"), Err(StampError::Malformed));
+        assert_eq!(parse("1:20:261015:a:b::salt:3"), Err(StampError::Malformed));
+        assert_eq!(
+            parse("0:20:261015:alice::salt:3"),
+            Err(StampError::UnsupportedVersion("0".to_string()))
+        );
+        assert_eq!(
+            parse("1:many:261015:alice::salt:3"),
+            Err(StampError::InvalidBits("many".to_string()))
+        );
+    }
+
+    #[test]
+    fn days_convert_to_calendar_dates() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+        assert_eq!(civil_from_days(11_016), (2000, 2, 29));
+        assert_eq!(civil_from_days(20_741), (2026, 10, 15));
+    }
+}
@@ -0,0 +1,52 @@
+//! Minimal hex encoding and decoding for puzzle payloads and digests.
+
+use std::error::Error;
+use std::fmt;
+
+/// The reasons a string cannot be decoded as hex.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HexError {
+    /// The input has an odd number of digits, so it does not describe whole bytes.
+    OddLength,
+    /// The character at this byte offset is not a hex digit.
+    InvalidDigit(usize),
+}
+
+impl fmt::Display for HexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HexError::OddLength => f.write_str("hex string has an odd number of digits"),
+            HexError::InvalidDigit(at) => write!(f, "invalid hex digit at offset {}", at),
+        }
+    }
+}
+
+impl Error for HexError {}
+
+/// Formats bytes as lower-case hex.
+pub fn encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Decodes a hex string (either case, optional `0x` prefix) into bytes.
+///
+/// # Returns
+///
+/// The decoded bytes, or a [`HexError`] describing the first problem found.
+pub fn decode(s: &str) -> Result<Vec<u8>, HexError> {
+    let (offset, digits) = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(rest) => (2, rest),
+        None => (0, s),
+    };
+    if let Some(at) = digits.bytes().position(|b| !b.is_ascii_hexdigit()) {
+        return Err(HexError::InvalidDigit(offset + at));
+    }
+    if digits.len() % 2 != 0 {
+        return Err(HexError::OddLength);
+    }
+
+    Ok((0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).expect("digits were checked to be hex"))
+        .collect())
+}
@@ -0,0 +1,245 @@
+//! Mining on the CPU and a GPU at the same time.
+//!
+//! [`parallel_mine_hybrid`] first measures how fast the CPU threads and the device each
+//! hash the puzzle, then splits the nonce window between them in proportion, so both
+//! would reach the end of their share at about the same time. The two searches run
+//! side by side, and as soon as either finds a solution the other is cancelled. Each
+//! side rolls the extra nonce over its own share, if the puzzle has one.
+//!
+//! The device is anything that implements [`Accelerator`]: `GpuMiner`, `OpenClMiner` and
+//! `CudaMiner` do, with the `gpu`, `opencl` and `cuda` features.
+
+use std::error::Error;
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use sha2::Sha256;
+
+use crate::{
+    measure_hash_rate_with, parallel_mine_spawn_with, CancellationToken, Puzzle, PuzzleError,
+    Solution, Target,
+};
+
+/// How long each side's hash rate is measured before the search starts.
+const MEASURE_TIME: Duration = Duration::from_millis(250);
+
+/// The first window the device's hash rate is measured over; it doubles until the
+/// measurement has run for [`MEASURE_TIME`].
+const MEASURE_NONCES: u64 = 1 << 20;
+
+/// A device that can search a nonce window for a SHA-256 solution next to the CPU.
+pub trait Accelerator: Sync {
+    /// Why the device could not search, including how a search ended without a solution.
+    type Error: Error + Send + 'static;
+
+    /// Mines `puzzle` with SHA-256 over `nonces`, rolling the extra nonce if it has one,
+    /// until a solution is found or `token` is cancelled.
+    fn mine_cancellable(
+        &self,
+        puzzle: &Puzzle,
+        nonces: RangeInclusive<u64>,
+        token: &CancellationToken,
+    ) -> Result<Solution, Self::Error>;
+
+    /// The [`PuzzleError`] in `error` if the search ended without a solution, such as
+    /// [`PuzzleError::Exhausted`], or `error` itself if the device failed.
+    fn into_search_error(error: Self::Error) -> Result<PuzzleError, Self::Error>;
+}
+
+/// A solution from [`parallel_mine_hybrid`] and how the search was split.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HybridSolution {
+    /// The solution.
+    pub solution: Solution,
+    /// Whether the device found it rather than the CPU.
+    pub found_by_device: bool,
+    /// The CPU's measured hash rate, in hashes per second.
+    pub cpu_rate: f64,
+    /// The device's measured hash rate, in hashes per second.
+    pub device_rate: f64,
+}
+
+impl HybridSolution {
+    /// The fraction of the nonce window given to the CPU, between 0 and 1.
+    pub fn cpu_share(&self) -> f64 {
+        cpu_share(self.cpu_rate, self.device_rate)
+    }
+}
+
+/// Why [`parallel_mine_hybrid`] found no solution.
+#[derive(Debug)]
+pub enum HybridError<E> {
+    /// Neither side found a solution, as a CPU search would report it.
+    Search(PuzzleError),
+    /// The device failed, and the CPU had not found a solution.
+    Device(E),
+}
+
+impl<E: fmt::Display> fmt::Display for HybridError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HybridError::Search(e) => e.fmt(f),
+            HybridError::Device(e) => write!(f, "the device failed: {}", e),
+        }
+    }
+}
+
+impl<E: Error + 'static> Error for HybridError<E> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            HybridError::Search(e) => e.source(),
+            HybridError::Device(e) => Some(e),
+        }
+    }
+}
+
+impl<E> From<PuzzleError> for HybridError<E> {
+    fn from(e: PuzzleError) -> Self {
+        HybridError::Search(e)
+    }
+}
+
+/// Mines `puzzle` with SHA-256 over `nonces` on `num_threads` CPU threads and on `device`
+/// at once, each searching a share of the window in proportion to its hash rate.
+///
+/// Both rates are measured for a moment first, one after the other. Whichever side
+/// finds a solution first cancels the other; if the device fails, the CPU is cancelled
+/// too.
+///
+/// # Returns
+///
+/// The [`HybridSolution`], a [`HybridError::Search`] with the [`PuzzleError`] a CPU
+/// search would give if neither side found one, or [`HybridError::Device`] if the device
+/// failed.
+pub fn parallel_mine_hybrid<A: Accelerator>(
+    puzzle: &Puzzle,
+    nonces: RangeInclusive<u64>,
+    num_threads: usize,
+    device: &A,
+) -> Result<HybridSolution, HybridError<A::Error>> {
+    if puzzle.target == Target::ZERO {
+        return Err(PuzzleError::InvalidDifficulty.into());
+    }
+    if nonces.is_empty() {
+        return Err(PuzzleError::EmptyRange.into());
+    }
+    let cpu_rate = measure_hash_rate_with::<Sha256>(puzzle, num_threads, MEASURE_TIME);
+    let device_rate = measure_device_rate(puzzle, device)?;
+    let (cpu_nonces, device_nonces) = split(nonces, cpu_share(cpu_rate, device_rate));
+
+    let token = CancellationToken::new();
+    let (cpu, on_device) = thread::scope(|scope| {
+        let on_device = device_nonces.map(|nonces| {
+            scope.spawn(|| {
+                let result = device.mine_cancellable(puzzle, nonces, &token);
+                match result {
+                    Ok(solution) => {
+                        token.cancel();
+                        Ok(solution)
+                    }
+                    Err(e) => match A::into_search_error(e) {
+                        Ok(e) => Err(HybridError::Search(e)),
+                        Err(e) => {
+                            token.cancel();
+                            Err(HybridError::Device(e))
+                        }
+                    },
+                }
+            })
+        });
+        let cpu = cpu_nonces.map(|nonces| {
+            let handle = parallel_mine_spawn_with::<Sha256>(puzzle, nonces, num_threads);
+            handle.cancel_on(&token);
+            let result = handle.join();
+            if result.is_ok() {
+                token.cancel();
+            }
+            result
+        });
+        let on_device = on_device.map(|thread| {
+            thread.join().unwrap_or(Err(HybridError::Search(PuzzleError::WorkerPanicked)))
+        });
+        (cpu, on_device)
+    });
+
+    let found = |solution, found_by_device| HybridSolution {
+        solution,
+        found_by_device,
+        cpu_rate,
+        device_rate,
+    };
+    match (cpu.unwrap_or(Err(PuzzleError::Exhausted)), on_device) {
+        // Both can finish in the same moment; keep the lower nonce.
+        (Ok(cpu), Some(Ok(on_device))) if on_device.nonce < cpu.nonce => {
+            Ok(found(on_device, true))
+        }
+        (Ok(cpu), _) => Ok(found(cpu, false)),
+        (Err(_), Some(Ok(on_device))) => Ok(found(on_device, true)),
+        (Err(_), Some(Err(HybridError::Device(e)))) => Err(HybridError::Device(e)),
+        // Without a solution or a failure, neither side was cancelled by the other.
+        (Err(PuzzleError::Exhausted), Some(Err(e))) => Err(e),
+        (Err(e), _) => Err(e.into()),
+    }
+}
+
+/// The fraction of the window the CPU searches, given both hash rates.
+fn cpu_share(cpu_rate: f64, device_rate: f64) -> f64 {
+    let share = cpu_rate / (cpu_rate + device_rate);
+    if share.is_finite() {
+        share.clamp(0.0, 1.0)
+    } else {
+        0.5
+    }
+}
+
+/// Splits `nonces` into the CPU's first `share` of it and the device's rest, leaving out
+/// a side whose share is empty.
+fn split(
+    nonces: RangeInclusive<u64>,
+    share: f64,
+) -> (Option<RangeInclusive<u64>>, Option<RangeInclusive<u64>>) {
+    let (start, end) = nonces.into_inner();
+    let len = u128::from(end - start) + 1;
+    let cpu_len = ((len as f64 * share).round() as u128).min(len);
+    if cpu_len == 0 {
+        return (None, Some(start..=end));
+    }
+    // `cpu_len` is at most the window's length, so the CPU's last nonce is within it.
+    let cpu_end = start + (cpu_len - 1) as u64;
+    let device = (cpu_len < len).then(|| cpu_end + 1..=end);
+    (Some(start..=cpu_end), device)
+}
+
+/// Measures how fast `device` hashes `puzzle`, by searching ever larger windows with a
+/// target no hash can meet until [`MEASURE_TIME`] has passed.
+fn measure_device_rate<A: Accelerator>(
+    puzzle: &Puzzle,
+    device: &A,
+) -> Result<f64, HybridError<A::Error>> {
+    let mut puzzle = puzzle.clone();
+    // Only a hash of 0 or 1 meets this target, so every window is searched to the end,
+    // and without an extra nonce the search ends there instead of rolling it.
+    let mut one = [0; 32];
+    one[31] = 1;
+    puzzle.target = Target::from_be_bytes(one);
+    puzzle.extra_nonce = None;
+    let token = CancellationToken::new();
+    let started = Instant::now();
+    let mut hashes = 0;
+    let mut window = MEASURE_NONCES;
+    while started.elapsed() < MEASURE_TIME {
+        match device.mine_cancellable(&puzzle, hashes..=hashes + window - 1, &token) {
+            Ok(_) => {}
+            Err(e) => match A::into_search_error(e) {
+                Ok(PuzzleError::Exhausted) => {}
+                Ok(e) => return Err(HybridError::Search(e)),
+                Err(e) => return Err(HybridError::Device(e)),
+            },
+        }
+        hashes += window;
+        window *= 2;
+    }
+    Ok(hashes as f64 / started.elapsed().as_secs_f64())
+}
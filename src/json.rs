@@ -0,0 +1,388 @@
+//! Flat JSON objects, the messages of the daemon, the work server and Stratum.
+//!
+//! The daemon and the work server only ever exchange objects whose values are strings,
+//! numbers, booleans or null, and Stratum adds arrays of those for its parameters, so
+//! this reads and writes just that much of JSON rather than pulling in a general
+//! library. Numbers are read exactly, so nonces up to `u64::MAX` survive the round trip;
+//! nested objects are rejected. Only the work server's `GET /status`, which is for
+//! people rather than workers, writes arrays of objects.
+
+use std::collections::HashMap;
+use std::fmt::{self, Write as _};
+use std::iter::Peekable;
+use std::str::{Chars, FromStr};
+
+/// A value in a request.
+pub(crate) enum Value {
+    String(String),
+    /// A number, kept as written so integers beyond `f64`'s precision stay exact.
+    Number(String),
+    Bool(bool),
+    Null,
+    /// An array, whose values may be arrays in turn but not objects.
+    Array(Vec<Value>),
+}
+
+impl Value {
+    /// The string, if this is one.
+    pub(crate) fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// The number, if this is one and it fits in a `T`.
+    pub(crate) fn as_number<T: FromStr>(&self) -> Option<T> {
+        match self {
+            Value::Number(digits) => digits.parse().ok(),
+            _ => None,
+        }
+    }
+
+    /// The boolean, if this is one.
+    pub(crate) fn as_bool(&self) -> Option<bool> {
+        match *self {
+            Value::Bool(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// The values of the array, if this is one.
+    pub(crate) fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::String(value) => {
+                let mut quoted = String::new();
+                write_string(&mut quoted, value);
+                f.write_str(&quoted)
+            }
+            Value::Number(digits) => f.write_str(digits),
+            Value::Bool(value) => write!(f, "{}", value),
+            Value::Null => f.write_str("null"),
+            Value::Array(values) => {
+                f.write_str("[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                f.write_str("]")
+            }
+        }
+    }
+}
+
+/// A request: a flat JSON object.
+pub(crate) struct Request {
+    fields: HashMap<String, Value>,
+}
+
+impl Request {
+    /// Parses one request.
+    pub(crate) fn parse(line: &str) -> Result<Self, String> {
+        let mut parser = Parser { chars: line.chars().peekable() };
+        let mut fields = HashMap::new();
+        parser.expect('{')?;
+        if !parser.eat('}') {
+            loop {
+                parser.expect('"')?;
+                let key = parser.string()?;
+                parser.expect(':')?;
+                fields.insert(key, parser.value()?);
+                if parser.eat('}') {
+                    break;
+                }
+                parser.expect(',')?;
+            }
+        }
+        match parser.peek() {
+            None => Ok(Request { fields }),
+            Some(c) => Err(format!("unexpected `{}` after the request", c)),
+        }
+    }
+
+    /// The string field `key`, if present and not null.
+    pub(crate) fn string(&self, key: &str) -> Result<Option<&str>, String> {
+        match self.fields.get(key) {
+            Some(Value::String(value)) => Ok(Some(value)),
+            Some(Value::Null) | None => Ok(None),
+            Some(_) => Err(format!("`{}` must be a string", key)),
+        }
+    }
+
+    /// The numeric field `key`, if present and not null.
+    pub(crate) fn number<T: FromStr>(&self, key: &str) -> Result<Option<T>, String> {
+        match self.fields.get(key) {
+            Some(Value::Number(digits)) => match digits.parse() {
+                Ok(value) => Ok(Some(value)),
+                Err(_) => Err(format!("`{}` is out of range", key)),
+            },
+            Some(Value::Null) | None => Ok(None),
+            Some(_) => Err(format!("`{}` must be a number", key)),
+        }
+    }
+
+    /// The numeric field `key`, which must be present.
+    pub(crate) fn required<T: FromStr>(&self, key: &str) -> Result<T, String> {
+        self.number(key)?.ok_or_else(|| format!("missing `{}`", key))
+    }
+
+    /// The field `key` as it was read, if present.
+    pub(crate) fn value(&self, key: &str) -> Option<&Value> {
+        self.fields.get(key)
+    }
+}
+
+/// Reads the JSON of one request, skipping whitespace between tokens.
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+}
+
+impl Parser<'_> {
+    /// The next character that is not whitespace, without consuming it.
+    fn peek(&mut self) -> Option<char> {
+        while self.chars.next_if(|c| c.is_ascii_whitespace()).is_some() {}
+        self.chars.peek().copied()
+    }
+
+    /// Consumes `c` if it comes next.
+    fn eat(&mut self, c: char) -> bool {
+        let next = self.peek() == Some(c);
+        if next {
+            self.chars.next();
+        }
+        next
+    }
+
+    /// Consumes `c`, which must come next.
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        if self.eat(c) {
+            return Ok(());
+        }
+        match self.peek() {
+            Some(found) => Err(format!("expected `{}` but found `{}`", c, found)),
+            None => Err(format!("expected `{}` but the request ended", c)),
+        }
+    }
+
+    /// Reads a value.
+    fn value(&mut self) -> Result<Value, String> {
+        match self.peek() {
+            Some('"') => {
+                self.chars.next();
+                self.string().map(Value::String)
+            }
+            Some('-' | '0'..='9') => {
+                let mut digits = String::new();
+                let is_numeric = |c: &char| c.is_ascii_digit() || "+-.eE".contains(*c);
+                while let Some(c) = self.chars.next_if(is_numeric) {
+                    digits.push(c);
+                }
+                match digits.parse::<f64>() {
+                    Ok(_) => Ok(Value::Number(digits)),
+                    Err(_) => Err(format!("invalid number `{}`", digits)),
+                }
+            }
+            Some('t') => self.literal("true", Value::Bool(true)),
+            Some('f') => self.literal("false", Value::Bool(false)),
+            Some('n') => self.literal("null", Value::Null),
+            Some('[') => {
+                self.chars.next();
+                let mut values = Vec::new();
+                if !self.eat(']') {
+                    loop {
+                        values.push(self.value()?);
+                        if self.eat(']') {
+                            break;
+                        }
+                        self.expect(',')?;
+                    }
+                }
+                Ok(Value::Array(values))
+            }
+            Some('{') => Err("nested objects are not supported".to_string()),
+            Some(c) => Err(format!("unexpected `{}`", c)),
+            None => Err("the request ended before a value".to_string()),
+        }
+    }
+
+    /// Reads the keyword `word`, which stands for `value`.
+    fn literal(&mut self, word: &str, value: Value) -> Result<Value, String> {
+        for expected in word.chars() {
+            if self.chars.next() != Some(expected) {
+                return Err(format!("expected `{}`", word));
+            }
+        }
+        Ok(value)
+    }
+
+    /// Reads the rest of a string whose opening quote has been consumed.
+    fn string(&mut self) -> Result<String, String> {
+        let mut string = String::new();
+        loop {
+            match self.chars.next().ok_or("unterminated string")? {
+                '"' => return Ok(string),
+                '\\' => string.push(match self.chars.next().ok_or("unterminated string")? {
+                    '"' => '"',
+                    '\\' => '\\',
+                    '/' => '/',
+                    'b' => '\u{8}',
+                    'f' => '\u{c}',
+                    'n' => '\n',
+                    'r' => '\r',
+                    't' => '\t',
+                    'u' => self.unicode_escape()?,
+                    c => return Err(format!("invalid escape `\\{}`", c)),
+                }),
+                c => string.push(c),
+            }
+        }
+    }
+
+    /// Reads the digits of a `\u` escape, and the low half of a surrogate pair after it.
+    fn unicode_escape(&mut self) -> Result<char, String> {
+        let high = self.hex4()?;
+        let code = if (0xd800..0xdc00).contains(&high) {
+            if self.chars.next() != Some('\\') || self.chars.next() != Some('u') {
+                return Err("unpaired surrogate in `\\u` escape".to_string());
+            }
+            let low = self.hex4()?;
+            if !(0xdc00..0xe000).contains(&low) {
+                return Err("unpaired surrogate in `\\u` escape".to_string());
+            }
+            0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)
+        } else {
+            high
+        };
+        char::from_u32(code).ok_or_else(|| "unpaired surrogate in `\\u` escape".to_string())
+    }
+
+    /// Reads four hex digits.
+    fn hex4(&mut self) -> Result<u32, String> {
+        (0..4).try_fold(0, |code, _| {
+            let digit = self.chars.next().and_then(|c| c.to_digit(16));
+            digit.map(|digit| code << 4 | digit).ok_or("invalid `\\u` escape".to_string())
+        })
+    }
+}
+
+/// A response being written: a flat JSON object.
+pub(crate) struct Response(String);
+
+impl Response {
+    /// A response to a request that was carried out.
+    pub(crate) fn ok() -> Self {
+        Response(String::from("{\"ok\":true"))
+    }
+
+    /// A response to a request that could not be carried out, and why.
+    pub(crate) fn error(message: &str) -> Self {
+        Response(String::from("{\"ok\":false")).string("error", message)
+    }
+
+    /// A message sent without being asked for, telling of the `event` that happened.
+    pub(crate) fn event(name: &str) -> Self {
+        Response(format!("{{\"event\":\"{}\"", name))
+    }
+
+    /// A record of the `kind` given, such as those a queue in Redis or an archive of
+    /// solved puzzles is kept as.
+    pub(crate) fn record(kind: &str) -> Self {
+        Response(format!("{{\"record\":\"{}\"", kind))
+    }
+
+    /// An object with no fields yet, to be put in an array with
+    /// [`objects`](Response::objects).
+    pub(crate) fn object() -> Self {
+        Response(String::from("{"))
+    }
+
+    /// A JSON-RPC message with the given `id`, or a null one for a notification.
+    pub(crate) fn with_id(id: Option<u64>) -> Self {
+        match id {
+            Some(id) => Response(format!("{{\"id\":{}", id)),
+            None => Response(String::from("{\"id\":null")),
+        }
+    }
+
+    /// Adds a numeric field.
+    pub(crate) fn number(mut self, key: &str, value: impl fmt::Display) -> Self {
+        self.key(key);
+        let _ = write!(self.0, "{}", value);
+        self
+    }
+
+    /// Adds a string field.
+    pub(crate) fn string(mut self, key: &str, value: &str) -> Self {
+        self.key(key);
+        write_string(&mut self.0, value);
+        self
+    }
+
+    /// Adds a field of any kind, such as an array.
+    pub(crate) fn value(mut self, key: &str, value: &Value) -> Self {
+        self.key(key);
+        let _ = write!(self.0, "{}", value);
+        self
+    }
+
+    /// Adds a field holding an array of `objects`.
+    pub(crate) fn objects(mut self, key: &str, objects: impl IntoIterator<Item = Self>) -> Self {
+        self.key(key);
+        self.0.push('[');
+        for (i, object) in objects.into_iter().enumerate() {
+            if i > 0 {
+                self.0.push(',');
+            }
+            let _ = write!(self.0, "{}", object);
+        }
+        self.0.push(']');
+        self
+    }
+
+    /// Starts the field `key`, after a comma unless it is the first.
+    fn key(&mut self, key: &str) {
+        if !self.0.ends_with('{') {
+            self.0.push(',');
+        }
+        let _ = write!(self.0, "\"{}\":", key);
+    }
+}
+
+/// `value` as a JSON number, or null if it is `None`.
+pub(crate) fn nullable(value: Option<impl fmt::Display>) -> Value {
+    value.map_or(Value::Null, |value| Value::Number(value.to_string()))
+}
+
+/// Writes `value` to `out` as a quoted, escaped JSON string.
+fn write_string(out: &mut String, value: &str) {
+    out.push('"');
+    for c in value.chars() {
+        let _ = match c {
+            '"' => write!(out, "\\\""),
+            '\\' => write!(out, "\\\\"),
+            '\n' => write!(out, "\\n"),
+            '\r' => write!(out, "\\r"),
+            '\t' => write!(out, "\\t"),
+            c if c < ' ' => write!(out, "\\u{:04x}", c as u32),
+            c => write!(out, "{}", c),
+        };
+    }
+    out.push('"');
+}
+
+impl fmt::Display for Response {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}}}", self.0)
+    }
+}
@@ -0,0 +1,215 @@
+//! Vanity ed25519 keypairs: keys whose public key or address starts with chosen text.
+//!
+//! The search reuses the nonce engine with a different work function. A random base
+//! seed is drawn once; for each nonce the secret key is `SHA-256(base seed || nonce)`,
+//! and the "hash" the engine checks is the resulting public key. Threads therefore
+//! partition the nonce space and stop each other exactly as they do when mining, and
+//! the winning secret key is re-derived from the nonce at the end.
+
+use std::error::Error;
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+use ed25519_dalek::SigningKey;
+use rand::RngCore;
+use sha2::Sha256;
+
+use crate::digest::Digest;
+use crate::{default_nonce_range, hex, search_parallel, HashPrefix, PuzzleError};
+
+/// The characters of the Bitcoin base58 alphabet used by `bs58`.
+const BASE58_ALPHABET: &str = "123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+/// How a public key is turned into the text that the prefix is matched against.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum KeyEncoding {
+    /// Lower-case hex of the 32 public key bytes.
+    #[default]
+    Hex,
+    /// Base58 of the public key bytes, which is how Solana and similar chains write
+    /// addresses.
+    Base58,
+}
+
+impl KeyEncoding {
+    /// Every supported encoding, in the order they are listed in help output.
+    pub const ALL: [KeyEncoding; 2] = [KeyEncoding::Hex, KeyEncoding::Base58];
+
+    /// The canonical lower-case name of the encoding, as accepted by [`FromStr`].
+    pub fn name(self) -> &'static str {
+        match self {
+            KeyEncoding::Hex => "hex",
+            KeyEncoding::Base58 => "base58",
+        }
+    }
+
+    /// Formats `public_key` in this encoding.
+    pub fn encode(self, public_key: &[u8]) -> String {
+        match self {
+            KeyEncoding::Hex => hex::encode(public_key),
+            KeyEncoding::Base58 => bs58::encode(public_key).into_string(),
+        }
+    }
+}
+
+impl fmt::Display for KeyEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for KeyEncoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let wanted = s.to_ascii_lowercase();
+        KeyEncoding::ALL
+            .into_iter()
+            .find(|encoding| encoding.name() == wanted)
+            .ok_or_else(|| {
+                let names: Vec<_> = KeyEncoding::ALL.iter().map(|e| e.name()).collect();
+                format!("unknown key encoding `{}` (expected one of: {})", s, names.join(", "))
+            })
+    }
+}
+
+/// A prefix contains a character its encoding can never produce.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InvalidKeyPrefix {
+    /// The encoding the prefix was checked against.
+    pub encoding: KeyEncoding,
+    /// Byte offset of the offending character.
+    pub at: usize,
+}
+
+impl fmt::Display for InvalidKeyPrefix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "character at offset {} can never appear in {} keys", self.at, self.encoding)
+    }
+}
+
+impl Error for InvalidKeyPrefix {}
+
+/// The text a vanity public key must start with.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct KeyPrefix {
+    encoding: KeyEncoding,
+    prefix: String,
+    /// The hex form, matched on raw bytes without formatting the key.
+    hex: Option<HashPrefix>,
+}
+
+impl KeyPrefix {
+    /// Checks that `prefix` can occur in keys written with `encoding`.
+    pub fn new(prefix: &str, encoding: KeyEncoding) -> Result<Self, InvalidKeyPrefix> {
+        let invalid = |at| InvalidKeyPrefix { encoding, at };
+        let hex = match encoding {
+            KeyEncoding::Hex => Some(HashPrefix::hex(prefix).map_err(|e| match e {
+                hex::HexError::InvalidDigit(at) => invalid(at),
+                hex::HexError::OddLength => unreachable!("hex prefixes may have odd length"),
+            })?),
+            KeyEncoding::Base58 => {
+                if let Some(at) = prefix.find(|c| !BASE58_ALPHABET.contains(c)) {
+                    return Err(invalid(at));
+                }
+                None
+            }
+        };
+        Ok(KeyPrefix { encoding, prefix: prefix.to_string(), hex })
+    }
+
+    /// Whether `public_key` starts with this prefix in its encoding.
+    pub fn matches(&self, public_key: &[u8]) -> bool {
+        match &self.hex {
+            Some(hex) => hex.matches(public_key),
+            None => self.encoding.encode(public_key).starts_with(&self.prefix),
+        }
+    }
+
+    /// The encoding the prefix is matched in.
+    pub fn encoding(&self) -> KeyEncoding {
+        self.encoding
+    }
+}
+
+impl fmt::Display for KeyPrefix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.prefix, self.encoding)
+    }
+}
+
+/// An ed25519 keypair found by [`search_keypair`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VanityKeypair {
+    /// The 32-byte ed25519 secret key (seed). Keep it private.
+    pub secret_key: [u8; 32],
+    /// The 32-byte public key.
+    pub public_key: [u8; 32],
+    /// The nonce at which the key was found.
+    pub nonce: u64,
+}
+
+impl VanityKeypair {
+    /// The public key written in `encoding`.
+    pub fn address(&self, encoding: KeyEncoding) -> String {
+        encoding.encode(&self.public_key)
+    }
+
+    /// The keypair as an `ed25519-dalek` signing key.
+    pub fn signing_key(&self) -> SigningKey {
+        SigningKey::from_bytes(&self.secret_key)
+    }
+}
+
+/// Derives the secret key tried at `nonce`.
+fn derive_secret(base_seed: &[u8; 32], nonce: u64) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(base_seed);
+    hasher.update(nonce.to_be_bytes());
+    let mut secret = [0u8; 32];
+    secret.copy_from_slice(&hasher.finalize());
+    secret
+}
+
+/// Searches for an ed25519 keypair whose public key starts with `prefix`, on
+/// `num_threads` threads.
+///
+/// This is [`search_keypair_range`] over [`default_nonce_range`].
+pub fn search_keypair(
+    prefix: &KeyPrefix,
+    num_threads: usize,
+) -> Result<VanityKeypair, PuzzleError> {
+    search_keypair_range(prefix, default_nonce_range(), num_threads)
+}
+
+/// Searches the `nonces` window for an ed25519 keypair whose public key starts with
+/// `prefix`, splitting the window across `num_threads` threads.
+///
+/// # Returns
+///
+/// The first matching keypair found, or [`PuzzleError::Exhausted`] if no key in the window
+/// matched. A fresh random base seed is drawn on every call, so two calls never return
+/// the same key.
+pub fn search_keypair_range(
+    prefix: &KeyPrefix,
+    nonces: RangeInclusive<u64>,
+    num_threads: usize,
+) -> Result<VanityKeypair, PuzzleError> {
+    let mut base_seed = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut base_seed);
+
+    let prefix = prefix.clone();
+    let solution = search_parallel(
+        nonces,
+        num_threads,
+        move |nonce| {
+            SigningKey::from_bytes(&derive_secret(&base_seed, nonce)).verifying_key().to_bytes()
+        },
+        move |public_key| prefix.matches(public_key),
+    )?;
+
+    let secret_key = derive_secret(&base_seed, solution.nonce);
+    let public_key = SigningKey::from_bytes(&secret_key).verifying_key().to_bytes();
+    Ok(VanityKeypair { secret_key, public_key, nonce: solution.nonce })
+}
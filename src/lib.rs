@@ -0,0 +1,754 @@
+//! A simplified proof-of-work style puzzle solver that uses a multi-threaded approach
+//! to find a nonce value that meets certain difficulty criteria.
+//!
+//! The puzzle is considered solved when the SHA-256 hash of the data and the nonce, read
+//! as a big-endian 256-bit number, is at most the puzzle's [`Target`]. Targets are usually
+//! built from a [`Difficulty`]: a two-byte threshold, a number of leading zero bits, or
+//! Bitcoin's compact nBits form. The work is
+//! shared across multiple CPU cores in small chunks, and once a solution is found, all
+//! other threads stop searching.
+//!
+//! The public API consists of:
+//! - [`Puzzle`], the description of the problem to solve.
+//! - [`validate`], which checks a single candidate nonce, and [`verify`], which also
+//!   reports the hash it produces.
+//! - [`parallel_mine`], which searches for a valid nonce on every available core and
+//!   returns a [`Solution`] or a [`PuzzleError`] explaining why none was found.
+//! - [`parallel_mine_with_threads`], which does the same with an explicit thread count.
+//! - [`parallel_mine_spawn`], which runs the search in the background and returns a
+//!   [`MiningHandle`] that can pause, resume or cancel it, and that reports how the work
+//!   was shared between the threads and the energy it consumed in a [`MiningReport`];
+//!   see the [`control`] and [`energy`] modules.
+//!   The handle can also save a [`Checkpoint`] that [`parallel_mine_resume_with`]
+//!   continues from; see the [`checkpoint`] module.
+//! - [`parallel_mine_timeout`], which gives up after a wall-clock budget and reports
+//!   how many hashes it tried.
+//! - [`parallel_mine_cancellable`], which stops as soon as another thread cancels a
+//!   [`CancellationToken`].
+//! - [`parallel_mine_async`], which returns a [`MiningFuture`] for async code, such as a
+//!   tokio service, to await without blocking its runtime; a [`MiningHandle`] can be
+//!   awaited the same way. See the [`future`] module.
+//! - [`WorkEstimate`], which predicts the work a target takes and the chance of a
+//!   solution within a given time, and [`calibrate_with`], which picks the target for a
+//!   given solve time on this machine; see the [`estimate`] module. A [`Benchmark`]
+//!   measures every algorithm once and saves the rates for both; see the [`bench`]
+//!   module.
+//! - [`generate_puzzle`], which mints a puzzle over random data that takes a chosen
+//!   time to solve on this machine, [`generate_puzzles_with`], which mints many from
+//!   reproducible seeds, and [`generate_series_with`], which mints them along a
+//!   [`difficulty_curve`]; see the [`generate`] module.
+//! - [`Miner`], which combines the thread count, algorithm, nonce range, time limit,
+//!   cancellation, resuming, a shuffled or restarting [`SearchOrder`] and an
+//!   [`Observer`] of the search in one value built by [`Miner::builder`], and can keep
+//!   its worker threads alive from one puzzle to the next, each pinned to a core by a
+//!   [`CorePinning`] and, on machines with several [`NumaNode`]s, kept on its node; see
+//!   the [`numa`] module. On hybrid CPUs the threads can be kept to one [`CoreKind`] of
+//!   core; see the [`cores`] module. A lower [`WorkerPriority`] keeps those threads out
+//!   of the way of the desktop, and a [`ThermalLimit`] slows them down while the CPU
+//!   runs hot.
+//!
+//! SHA-256 is the default hash. Every entry point also has a `*_with` variant
+//! ([`validate_with`], [`parallel_mine_with`]) that is generic over any
+//! [`Digest`] implementation, so SHA-512, SHA-1 or a custom hasher can be plugged
+//! into the same solver loop. The [`digest`] crate is re-exported so callers can
+//! implement it against the exact version used here.
+//!
+//! With the `mock-hash` feature, `MockHash` is a cheap, deterministic stand-in for a
+//! real hash, so tests can run the whole parallel pipeline quickly and reproducibly.
+//! With the `profile` feature, a [`MiningReport`] also tells how long each worker spent
+//! hashing, checking the shared flags and idle, as a `ThreadProfile`; see the `profile`
+//! module.
+//!
+//! When the hash is only known at runtime (for example from the command line), the
+//! [`Algorithm`] enum dispatches to the matching solver; see the [`algo`] module.
+//!
+//! To collect every solution in a window (or the first N) rather than stopping at the
+//! first one, use [`find_solutions_with`]; see the [`solutions`] module.
+//!
+//! New kinds of puzzle can reuse the thread engine itself: implement [`ParallelSearch`]
+//! with the test for one candidate and run it with [`parallel_search`]; see the
+//! [`search`] module. With the `rayon` feature, `rayon_search` runs the same searches on
+//! a work-stealing rayon pool instead. On CPUs with AVX2 or NEON the engine hashes
+//! SHA-256 puzzles eight nonces at a time with SIMD, and on CPUs with SHA-NI or the
+//! ARMv8 crypto extensions it uses those instructions directly, chosen at runtime. With the
+//! `gpu` feature, `GpuMiner` runs the search as a compute shader on the GPU instead, and
+//! with the `opencl` feature `OpenClMiner` runs it on an OpenCL device of your choice.
+//! With the `cuda` feature, `CudaMiner` runs it as a CUDA kernel on an NVIDIA GPU. Any of
+//! them can also share the search with the CPU threads through [`parallel_mine_hybrid`],
+//! which splits the window by their measured hash rates; see the [`hybrid`] module.
+//!
+//! Work that does not split into equal pieces, such as a tree search whose branches are
+//! found as it goes, can implement [`StealSearch`] and run on a work-stealing engine
+//! with [`steal_search`] or [`steal_collect`]; see the [`steal`] module.
+//!
+//! Instead of a numeric target, [`parallel_mine_matching`] accepts any predicate over the
+//! hash, such as a vanity [`HashPrefix`]; see the [`vanity`] module.
+//!
+//! GPU- and ASIC-resistant puzzles hash with Argon2id or scrypt instead of a plain
+//! digest, with each thread's scratch memory optionally on huge pages; see
+//! [`MemoryHardPuzzle`] and the [`memory_hard`] module.
+//!
+//! [`EquihashPuzzle`] is a different kind of proof of work altogether, solved with
+//! Wagner's generalized birthday algorithm; see the [`equihash`] module. [`CuckooPuzzle`]
+//! instead asks for a cycle of fixed length in a random graph; see the [`cuckoo`] module.
+//!
+//! The same engine can run a different work function entirely: [`search_keypair`] looks
+//! for ed25519 keys with a vanity public key; see the [`keypair`] module.
+//!
+//! Many puzzles can share one set of worker threads with [`parallel_mine_batch`]; see
+//! the [`batch`] module. A [`Scheduler`] keeps a pool of workers running and serves
+//! puzzles submitted at any time by priority and deadline; see the [`scheduler`] module. On
+//! Unix, a `Daemon` serves a scheduler to other processes over a local socket; see the
+//! `daemon` module. A [`WorkServer`] instead leases the nonce windows of its puzzles to
+//! workers on other machines over HTTP, which mine them with a [`WorkClient`]; see the
+//! [`server`] and [`work_client`] modules. Its [`ClusterStatus`] shows the cluster at a
+//! glance: the workers' hash rates and how much of each window is searched. With the
+//! `grpc` feature, it serves them over gRPC as well; see the `grpc` module. It can also
+//! act as a pool for Stratum-style workers, and a [`StratumClient`] mines for such a
+//! pool; see the [`stratum`] module.
+//! With the `tls` feature, the server and its workers speak HTTPS; see the `tls` module.
+//! With the `mdns` feature, it can announce itself on the local network for workers to
+//! find; see the `discovery` module. With the `redis` feature, it can keep its puzzles
+//! and leases in Redis, to survive restarts and share them with replicas; see the
+//! `redis_queue` module. With the `gossip` feature, a `GossipNode` shares
+//! puzzles with its peers over libp2p instead, with no coordinator at all; see the
+//! `gossip` module. [`ingest`] mines the jobs of an event-driven pipeline, taken from a
+//! NATS subject, or with the `kafka` feature a Kafka topic, and publishes their results
+//! back; see the [`ingest`](mod@ingest) module. Whichever way a puzzle is solved, a
+//! [`ResultStore`] keeps an archive of the solutions, in a directory or an S3 bucket; see
+//! the [`archive`] module.
+//! A [`Chain`] mines puzzles one after another,
+//! each committing to the hash of the last, and can adjust its difficulty to hit a block
+//! time like a real chain; see the [`chain`] and [`retarget`] modules. A block's data can
+//! be the root of a [`MerkleTree`] of transactions, each provable with a
+//! [`MerkleProof`]; see the [`merkle`] module.
+//!
+//! Not every puzzle is parallel: a [`TimeLockPuzzle`] takes a fixed number of sequential
+//! squarings that extra cores cannot speed up; see the [`timelock`] module. A [`Vdf`]
+//! does the same work but also proves its output, so anyone can check it quickly; see
+//! the [`vdf`] module.
+//!
+//! [`Stamp`] mints and checks Hashcash anti-spam stamps with the same engine; see the
+//! [`hashcash`] module. For rate-limiting a server, a [`ChallengeIssuer`] hands out
+//! stateless, HMAC-bound [`Challenge`]s that clients solve in parallel; see the
+//! [`client_puzzle`] module. Two parties holding the same [`SharedSeed`] can instead
+//! derive identical puzzles from an index and never send puzzle bodies; see the
+//! [`derive`] module.
+//!
+//! A [`Puzzle`] can carry issue and expiry times that [`validate`] enforces, and a
+//! [`SeenSolutions`] set refuses a solution the second time it is submitted; see the
+//! [`replay`] module. Issuers can sign puzzles as [`SignedPuzzle`]s and verifiers can
+//! hand out signed [`Receipt`]s for accepted solutions; see the [`signing`] module.
+//!
+//! Large payloads do not need to be loaded into memory: a [`PrehashedPuzzle`] streams
+//! the data through the hasher once and only the nonce is hashed per attempt; see the
+//! [`prehash`] module.
+
+pub mod affinity;
+pub mod algo;
+pub mod archive;
+pub mod batch;
+pub mod bench;
+pub mod chain;
+pub mod checkpoint;
+pub mod client_puzzle;
+pub mod control;
+pub mod cores;
+pub mod cuckoo;
+#[cfg(feature = "cuda")]
+pub mod cuda;
+#[cfg(unix)]
+pub mod daemon;
+pub mod derive;
+pub mod difficulty;
+#[cfg(feature = "mdns")]
+pub mod discovery;
+pub mod energy;
+pub mod equihash;
+pub mod error;
+pub mod estimate;
+pub mod future;
+pub mod generate;
+#[cfg(feature = "gossip")]
+pub mod gossip;
+#[cfg(feature = "gpu")]
+pub mod gpu;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod hashcash;
+pub mod hex;
+pub mod hybrid;
+pub mod ingest;
+mod json;
+pub mod keypair;
+mod lanes;
+pub mod memory_hard;
+pub mod merkle;
+pub mod miner;
+#[cfg(feature = "mock-hash")]
+pub mod mock;
+pub mod nonce;
+pub mod numa;
+pub mod observer;
+#[cfg(feature = "opencl")]
+pub mod opencl;
+pub mod order;
+mod pool;
+pub mod prehash;
+pub mod priority;
+#[cfg(feature = "profile")]
+pub mod profile;
+#[cfg(feature = "rayon")]
+pub mod rayon_search;
+#[cfg(feature = "redis")]
+pub mod redis_queue;
+pub mod replay;
+pub mod retarget;
+mod romix;
+pub mod scheduler;
+mod scratch;
+pub mod search;
+pub mod server;
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+mod sha_ext;
+pub mod signing;
+mod prime;
+pub mod solutions;
+pub mod steal;
+pub mod stratum;
+pub mod target;
+pub mod thermal;
+pub mod timelock;
+#[cfg(feature = "tls")]
+pub mod tls;
+pub mod vanity;
+pub mod vdf;
+pub mod verify;
+pub mod work_client;
+
+pub use affinity::CorePinning;
+pub use algo::{Algorithm, Blake3, DoubleSha256};
+pub use archive::{
+    puzzle_id, ArchivedSolution, FileStore, ResultStore, S3Credentials, S3Store, StoreError,
+};
+pub use batch::{parallel_mine_batch, parallel_mine_batch_with, BatchResult};
+pub use bench::{Benchmark, BenchmarkError, HashRates};
+pub use chain::{Block, Chain};
+pub use checkpoint::{Checkpoint, CheckpointError};
+pub use client_puzzle::{Challenge, ChallengeError, ChallengeIssuer};
+pub use control::{
+    parallel_mine_cancellable, parallel_mine_cancellable_with, parallel_mine_resume_with,
+    parallel_mine_spawn, parallel_mine_spawn_with, parallel_mine_timeout,
+    parallel_mine_timeout_with, CancellationToken, MiningHandle, MiningProgress,
+    MiningReport,
+};
+pub use cores::{cpu_cores, CoreKind, CpuCore, ParseCoreKindError};
+pub use cuckoo::{CuckooParams, CuckooPuzzle, CuckooSolution, InvalidCuckooParams};
+#[cfg(feature = "cuda")]
+pub use cuda::{CudaDevice, CudaError, CudaMiner};
+#[cfg(unix)]
+pub use daemon::Daemon;
+pub use derive::SharedSeed;
+pub use difficulty::{leading_zero_bits, Difficulty};
+#[cfg(feature = "mdns")]
+pub use discovery::{discover, Advertisement, DiscoveryError};
+pub use energy::EnergyMeter;
+pub use equihash::{EquihashParams, EquihashPuzzle, EquihashSolution, InvalidEquihashParams};
+pub use error::PuzzleError;
+pub use estimate::{calibrate_with, measure_hash_rate_with, Calibration, WorkEstimate};
+pub use future::{parallel_mine_async, MiningFuture};
+pub use generate::{
+    difficulty_curve, generate_puzzle, generate_puzzle_with, generate_puzzles_with,
+    generate_series_with, GeneratedPuzzle,
+};
+#[cfg(feature = "gossip")]
+pub use gossip::{GossipClaim, GossipError, GossipNode};
+#[cfg(feature = "gpu")]
+pub use gpu::{GpuError, GpuMiner};
+pub use hashcash::{MintError, Stamp, StampError};
+pub use hex::HexError;
+pub use hybrid::{parallel_mine_hybrid, Accelerator, HybridError, HybridSolution};
+#[cfg(feature = "kafka")]
+pub use ingest::KafkaSource;
+pub use ingest::{ingest, mine_job, IngestError, JobSource, NatsSource};
+pub use keypair::{
+    search_keypair, search_keypair_range, InvalidKeyPrefix, KeyEncoding, KeyPrefix, VanityKeypair,
+};
+pub use memory_hard::{
+    available_memory, huge_pages_available, Argon2Params, InvalidParams, MemoryHardFunction,
+    MemoryHardPuzzle, ScryptParams,
+};
+pub use merkle::{MerkleProof, MerkleTree, Sibling};
+pub use miner::{InvalidMinerConfig, Miner, MinerBuilder};
+#[cfg(feature = "mock-hash")]
+pub use mock::MockHash;
+pub use nonce::{NonceEncoding, NonceFormat};
+pub use numa::{numa_nodes, NumaNode};
+pub use observer::Observer;
+#[cfg(feature = "opencl")]
+pub use opencl::{OpenClDevice, OpenClDeviceKind, OpenClError, OpenClMiner};
+pub use order::{ParseSearchOrderError, SearchOrder};
+pub use prehash::{parallel_mine_prehashed, parallel_mine_prehashed_matching, PrehashedPuzzle};
+pub use priority::{ParseWorkerPriorityError, WorkerPriority};
+#[cfg(feature = "profile")]
+pub use profile::ThreadProfile;
+#[cfg(feature = "rayon")]
+pub use rayon_search::{parallel_mine_rayon_with, rayon_search};
+#[cfg(feature = "redis")]
+pub use redis_queue::{QueueError, RedisQueue};
+pub use replay::SeenSolutions;
+pub use retarget::{retarget, Retarget};
+pub use scheduler::{ScheduledJob, Scheduler};
+pub use search::{parallel_search, ParallelSearch};
+pub use server::{ClusterStatus, PuzzleCoverage, WorkServer, WorkerShares, WorkerStatus};
+pub use signing::{Receipt, ReceiptError, SignedPuzzle};
+pub use solutions::{find_solutions_with, Solutions};
+pub use steal::{
+    parallel_mine_steal_with, steal_collect, steal_nonces, steal_search, Spawner, StealSearch,
+};
+pub use stratum::{Mined, StratumClient, StratumError, StratumJob, StratumShare};
+pub use target::{CompactTargetError, Target};
+pub use thermal::{cpu_temperature, ThermalLimit};
+pub use timelock::{TimeLockPuzzle, TimeLockTrapdoor};
+#[cfg(feature = "tls")]
+pub use tls::{ClientTls, ServerTls, TlsError};
+pub use vanity::HashPrefix;
+pub use vdf::{Vdf, VdfProof};
+pub use verify::{verify, verify_with, Verification};
+pub use work_client::{WorkClient, WorkError, WorkEvent, WorkEvents, WorkLease};
+pub use ed25519_dalek;
+pub use num_bigint;
+pub use sha2::digest;
+
+pub(crate) use search::search_parallel;
+
+use sha2::Sha256;
+use digest::Digest;
+use lanes::{LaneSearch, Sha256Lanes};
+use std::borrow::Cow;
+use std::fs;
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// A puzzle represents a proof-of-work style problem.
+///
+/// The puzzle is defined by:
+/// - A `target` which the hash must not exceed.
+/// - Arbitrary `data` whose hash, combined with a `nonce`, must be at or below the target.
+/// - A `nonce` which is the value we try to find that makes the hash valid.
+#[derive(Clone, Debug)]
+pub struct Puzzle {
+    /// The 256-bit target a valid hash must not exceed.
+    /// Lower targets make it much harder to find a valid nonce.
+    pub target: Target,
+    /// Arbitrary bytes (e.g., a binary block header, transaction data, or a message).
+    pub data: Vec<u8>,
+    /// A nonce is the variable part we adjust to find a hash meeting the target.
+    pub nonce: u64,
+    /// How the nonce is encoded and where it is placed in the hashed message.
+    pub nonce_format: NonceFormat,
+    /// An optional extra nonce that extends the search space beyond 64 bits.
+    ///
+    /// When set, it is appended to `data` as 8 big-endian bytes before the nonce is
+    /// placed, and [`parallel_mine_with`] increments it every time the nonce range is
+    /// exhausted. `None` disables the extra nonce.
+    pub extra_nonce: Option<u64>,
+    /// When the puzzle was issued, in seconds since the Unix epoch.
+    ///
+    /// [`validate`] and [`validate_with`] reject every nonce before this time. `None`
+    /// means the puzzle has no start time. It is not part of the hashed message.
+    pub issued_at: Option<u64>,
+    /// When the puzzle stops accepting solutions, in seconds since the Unix epoch.
+    ///
+    /// [`validate`] and [`validate_with`] reject every nonce from this time on. `None`
+    /// means the puzzle never expires. It is not part of the hashed message.
+    pub expires_at: Option<u64>,
+}
+
+impl Puzzle {
+    /// Creates a new puzzle over `data` with the given `difficulty`.
+    ///
+    /// The difficulty is converted with [`Difficulty::to_target`]; a bare `u64` is
+    /// accepted and interpreted as a [`Difficulty::Threshold`], and a [`Target`] is used
+    /// as is. The nonce starts at zero; it is not used by the solver, which reports the
+    /// discovered nonce separately.
+    ///
+    /// `data` can be anything convertible into bytes, such as a `&str`, a `String`, a
+    /// byte slice or a `Vec<u8>`.
+    pub fn new(data: impl Into<Vec<u8>>, difficulty: impl Into<Difficulty>) -> Self {
+        Puzzle {
+            target: difficulty.into().to_target(),
+            data: data.into(),
+            nonce: 0,
+            nonce_format: NonceFormat::default(),
+            extra_nonce: None,
+            issued_at: None,
+            expires_at: None,
+        }
+    }
+
+    /// Returns the puzzle marked as issued at `issued_at` seconds since the Unix epoch.
+    pub fn with_issued_at(mut self, issued_at: u64) -> Self {
+        self.issued_at = Some(issued_at);
+        self
+    }
+
+    /// Returns the puzzle set to expire at `expires_at` seconds since the Unix epoch.
+    pub fn with_expires_at(mut self, expires_at: u64) -> Self {
+        self.expires_at = Some(expires_at);
+        self
+    }
+
+    /// Returns the puzzle issued now and expiring `ttl` from now.
+    pub fn with_ttl(self, ttl: Duration) -> Self {
+        let now = unix_now();
+        self.with_issued_at(now).with_expires_at(now.saturating_add(ttl.as_secs()))
+    }
+
+    /// Whether the puzzle accepts solutions at `now` seconds since the Unix epoch: not
+    /// before [`issued_at`](Puzzle::issued_at) and before
+    /// [`expires_at`](Puzzle::expires_at), for whichever of them are set.
+    pub fn is_live_at(&self, now: u64) -> bool {
+        self.issued_at.is_none_or(|issued_at| now >= issued_at)
+            && self.expires_at.is_none_or(|expires_at| now < expires_at)
+    }
+
+    /// Whether the puzzle accepts solutions now; see [`is_live_at`](Puzzle::is_live_at).
+    pub fn is_live(&self) -> bool {
+        self.is_live_at(unix_now())
+    }
+
+    /// Returns the puzzle with the extra nonce enabled, starting at `start`.
+    pub fn with_extra_nonce(mut self, start: u64) -> Self {
+        self.extra_nonce = Some(start);
+        self
+    }
+
+    /// The bytes the nonce is placed into: `data`, followed by the extra nonce if set.
+    pub fn message_data(&self) -> Cow<'_, [u8]> {
+        match self.extra_nonce {
+            None => Cow::Borrowed(&self.data),
+            Some(extra_nonce) => {
+                let mut data = self.data.clone();
+                data.extend_from_slice(&extra_nonce.to_be_bytes());
+                Cow::Owned(data)
+            }
+        }
+    }
+
+    /// Returns the puzzle with a different nonce encoding and placement.
+    pub fn with_nonce_format(mut self, nonce_format: NonceFormat) -> Self {
+        self.nonce_format = nonce_format;
+        self
+    }
+
+    /// Creates a puzzle whose data is given as a hex string (optionally `0x`-prefixed).
+    ///
+    /// # Returns
+    ///
+    /// The puzzle, or a [`HexError`] if `hex` is not valid hex.
+    pub fn from_hex(hex: &str, difficulty: impl Into<Difficulty>) -> Result<Self, HexError> {
+        Ok(Puzzle::new(hex::decode(hex)?, difficulty))
+    }
+
+    /// Creates a puzzle whose data is the entire contents of the file at `path`.
+    ///
+    /// # Returns
+    ///
+    /// The puzzle, or [`PuzzleError::Io`] if the file could not be read.
+    pub fn from_file(
+        path: impl AsRef<Path>,
+        difficulty: impl Into<Difficulty>,
+    ) -> Result<Self, PuzzleError> {
+        Ok(Puzzle::new(fs::read(path)?, difficulty))
+    }
+}
+
+/// A nonce that solves a puzzle, together with the hash it produces.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Solution {
+    /// The nonce that makes the puzzle hash meet the target.
+    pub nonce: u64,
+    /// The digest of `data || nonce` under the algorithm used to mine it.
+    pub hash: Vec<u8>,
+    /// The extra nonce in effect when the solution was found, if the puzzle used one.
+    pub extra_nonce: Option<u64>,
+}
+
+/// Validates whether a given nonce produces a SHA-256 hash meeting the puzzle target.
+///
+/// This is [`validate_with`] specialised to [`Sha256`].
+///
+/// # Parameters
+///
+/// - `puzzle`: The puzzle definition containing the target and data.
+/// - `nonce`: The nonce to test against the puzzle data.
+///
+/// # Returns
+///
+/// `true` if the resulting hash is at or below the puzzle target and the puzzle has not
+/// expired; otherwise, `false`.
+pub fn validate(puzzle: &Puzzle, nonce: u64) -> bool {
+    validate_with::<Sha256>(puzzle, nonce)
+}
+
+/// Computes the puzzle hash of the data with `nonce` inserted, using the hash function `D`.
+///
+/// With the default [`NonceFormat`] and no extra nonce the message is `data || nonce` with
+/// the nonce as 8 big-endian bytes. The output length depends on `D` (32 bytes for SHA-256, 64 for
+/// SHA-512, and so on).
+pub fn hash_with<D: Digest>(puzzle: &Puzzle, nonce: u64) -> digest::Output<D> {
+    let data = puzzle.message_data();
+    let (prefix, suffix) = puzzle.nonce_format.split(&data);
+    let mut hasher = D::new();
+    hasher.update(prefix);
+    hasher.update(puzzle.nonce_format.encoding.encode(nonce));
+    hasher.update(suffix);
+    hasher.finalize()
+}
+
+/// Checks whether a hash of any length satisfies the given difficulty.
+///
+/// This is a free-function form of [`Difficulty::is_met_by`].
+///
+/// # Parameters
+///
+/// - `hash`: The raw digest bytes, of any output size.
+/// - `difficulty`: The difficulty rule to check against.
+///
+/// # Returns
+///
+/// `true` if the digest satisfies the difficulty; otherwise, `false`.
+pub fn meets_difficulty(hash: &[u8], difficulty: Difficulty) -> bool {
+    difficulty.is_met_by(hash)
+}
+
+/// Validates whether a given nonce produces a hash meeting the puzzle target,
+/// using the hash function `D`.
+///
+/// # Parameters
+///
+/// - `puzzle`: The puzzle definition containing the target and data.
+/// - `nonce`: The nonce to test against the puzzle data.
+///
+/// # Returns
+///
+/// `true` if the resulting hash is at or below the puzzle target (see
+/// [`Target::is_met_by`]) and the puzzle is live (see [`Puzzle::is_live`]); otherwise,
+/// `false`. Puzzles without an issue or expiry time are always live.
+pub fn validate_with<D: Digest>(puzzle: &Puzzle, nonce: u64) -> bool {
+    puzzle.is_live() && puzzle.target.is_met_by(&hash_with::<D>(puzzle, nonce))
+}
+
+/// Attempts to solve the given puzzle using one thread per available CPU core.
+///
+/// This is a convenience wrapper around [`parallel_mine_with_threads`] that uses
+/// `num_cpus::get()` as the thread count.
+///
+/// # Parameters
+///
+/// - `puzzle`: The puzzle containing target and data. The nonce is initially unused.
+///
+/// # Returns
+///
+/// The [`Solution`] found, or a [`PuzzleError`] if no nonce met the target.
+pub fn parallel_mine(puzzle: &Puzzle) -> Result<Solution, PuzzleError> {
+    parallel_mine_with_threads(puzzle, num_cpus::get())
+}
+
+/// Attempts to solve the given puzzle with SHA-256, splitting the search range across `num_threads` threads.
+///
+/// This is [`parallel_mine_with`] specialised to [`Sha256`].
+///
+/// # Parameters
+///
+/// - `puzzle`: The puzzle containing target and data. The nonce is initially unused.
+/// - `num_threads`: How many worker threads to spawn. A value of zero is treated as one.
+///
+/// # Returns
+///
+/// The [`Solution`] found, or a [`PuzzleError`] if no nonce met the target.
+pub fn parallel_mine_with_threads(
+    puzzle: &Puzzle,
+    num_threads: usize,
+) -> Result<Solution, PuzzleError> {
+    parallel_mine_with::<Sha256>(puzzle, num_threads)
+}
+
+/// The nonce window searched when no explicit range is given: every nonce, `0..=u64::MAX`.
+///
+/// The window is the same whatever the thread count; [`parallel_mine`] and friends hand
+/// it out to their threads in chunks, so together they cover it exactly once.
+pub fn default_nonce_range() -> RangeInclusive<u64> {
+    0..=u64::MAX
+}
+
+/// Attempts to solve the given puzzle with the hash function `D`, splitting the search
+/// range across `num_threads` threads.
+///
+/// This is [`parallel_mine_range`] over [`default_nonce_range`].
+///
+/// # Parameters
+///
+/// - `puzzle`: The puzzle containing target and data. The nonce is initially unused.
+/// - `num_threads`: How many worker threads to spawn. A value of zero is treated as one.
+///
+/// # Returns
+///
+/// The [`Solution`] (nonce and hash) found by whichever thread got there first, or
+/// [`PuzzleError::Exhausted`] if the threads covered the whole window without success and
+/// the puzzle has no extra nonce.
+pub fn parallel_mine_with<D>(puzzle: &Puzzle, num_threads: usize) -> Result<Solution, PuzzleError>
+where
+    D: Digest + Clone + Send + Sync + 'static,
+{
+    parallel_mine_range::<D>(puzzle, default_nonce_range(), num_threads)
+}
+
+/// Attempts to solve the given puzzle with the hash function `D`, searching only the
+/// nonces in `nonces` and splitting that window across `num_threads` threads.
+///
+/// An explicit window lets a caller resume a previous run from where it stopped, or
+/// coordinate disjoint ranges across several machines by hand.
+///
+/// # Parameters
+///
+/// - `puzzle`: The puzzle containing target and data. The nonce is initially unused.
+/// - `nonces`: The inclusive window `start_nonce..=end_nonce` to search.
+/// - `num_threads`: How many worker threads to spawn. A value of zero is treated as one.
+///
+/// # Returns
+///
+/// The [`Solution`] (nonce and hash) found by whichever thread got there first, or
+/// [`PuzzleError::Exhausted`] if the whole window was searched without success and the
+/// puzzle has no extra nonce. An empty window gives [`PuzzleError::EmptyRange`] and a
+/// zero target [`PuzzleError::InvalidDifficulty`], without searching.
+///
+/// # Details
+///
+/// This function:
+/// 1. Hashes `puzzle.data` once into a [`PrehashedPuzzle`] (the SHA-256 "midstate" for
+///    the default hash), so each attempt only clones that state and hashes the nonce.
+///    SHA-256 on a CPU with AVX2 or NEON instead hashes eight nonces at once from the
+///    midstate, one per SIMD lane, and on one with SHA-NI or the ARMv8 crypto extensions
+///    compresses them from the midstate with those instructions.
+/// 2. Clamps the requested thread count to at least one.
+/// 3. Hands the window out to the threads in fixed-size chunks from a shared cursor,
+///    lowest first, so together they cover it exactly once and none sits idle early.
+/// 4. Each thread searches its current chunk, validating each nonce, and takes the next
+///    chunk until it either finds a valid solution or is notified that another thread
+///    found one.
+/// 5. Lets the first thread to find a solution claim the win with a compare-exchange on
+///    a shared atomic, which also tells the other threads to stop, without any lock to
+///    contend on or poison. The winner sends its solution over a channel, and the
+///    function returns it at once rather than waiting for the others to stop.
+/// 6. If the whole window is exhausted and the puzzle has an extra nonce, increments the
+///    extra nonce and searches the window again, so the effective search space is
+///    unbounded.
+pub fn parallel_mine_range<D>(
+    puzzle: &Puzzle,
+    nonces: RangeInclusive<u64>,
+    num_threads: usize,
+) -> Result<Solution, PuzzleError>
+where
+    D: Digest + Clone + Send + Sync + 'static,
+{
+    let target = puzzle.target;
+    if target == Target::ZERO {
+        return Err(PuzzleError::InvalidDifficulty);
+    }
+    parallel_mine_matching::<D, _>(puzzle, nonces, num_threads, move |hash| target.is_met_by(hash))
+}
+
+/// Searches `nonces` for a nonce whose hash (with the hash function `D`) satisfies
+/// `predicate`, instead of comparing it against the puzzle's target.
+///
+/// This is the same parallel search as [`parallel_mine_range`], including extra-nonce
+/// rolling, with the acceptance test supplied by the caller. Vanity searches use it
+/// with a [`HashPrefix`]; see the [`vanity`] module.
+///
+/// # Parameters
+///
+/// - `puzzle`: The puzzle containing the data. Its target is ignored.
+/// - `nonces`: The inclusive window `start_nonce..=end_nonce` to search.
+/// - `num_threads`: How many worker threads to spawn. A value of zero is treated as one.
+/// - `predicate`: Returns `true` for a hash that solves the puzzle.
+///
+/// # Returns
+///
+/// The first [`Solution`] found whose hash satisfies `predicate`, or
+/// [`PuzzleError::Exhausted`] as for [`parallel_mine_range`].
+pub fn parallel_mine_matching<D, P>(
+    puzzle: &Puzzle,
+    nonces: RangeInclusive<u64>,
+    num_threads: usize,
+    predicate: P,
+) -> Result<Solution, PuzzleError>
+where
+    D: Digest + Clone + Send + Sync + 'static,
+    P: Fn(&[u8]) -> bool + Send + Sync + 'static,
+{
+    let predicate = Arc::new(predicate);
+    let mut puzzle = Cow::Borrowed(puzzle);
+    loop {
+        let predicate = Arc::clone(&predicate);
+        let accept = move |hash: &[u8]| predicate(hash);
+        // Hash several nonces at once where the CPU and the puzzle allow it.
+        let result = match Sha256Lanes::new::<D>(&puzzle) {
+            Some(lanes) => {
+                parallel_search(LaneSearch::new(lanes, accept), nonces.clone(), num_threads)
+            }
+            None => {
+                // The data prefix is identical for every nonce, so hash it only once per job.
+                let prehashed = PrehashedPuzzle::<D>::from_puzzle(&puzzle);
+                parallel_mine_prehashed_matching(&prehashed, nonces.clone(), num_threads, accept)
+            }
+        };
+        match (result, puzzle.extra_nonce) {
+            (Ok(solution), extra_nonce) => return Ok(Solution { extra_nonce, ..solution }),
+            (Err(PuzzleError::Exhausted), Some(extra_nonce)) => {
+                // Roll the extra nonce, which changes the data prefix, and search again.
+                puzzle.to_mut().extra_nonce = Some(extra_nonce.wrapping_add(1));
+            }
+            (Err(e), _) => return Err(e),
+        }
+    }
+}
+
+/// The current time in seconds since the Unix epoch, or zero if the clock is before it.
+pub(crate) fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+/// The current time in milliseconds since the Unix epoch, or zero if the clock is before
+/// it.
+pub(crate) fn unix_now_millis() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64)
+}
+
+/// Splits the `nonces` window evenly into at most `num_threads` (at least one) disjoint,
+/// non-empty sub-ranges that together cover it exactly once.
+///
+/// Fewer ranges are returned when the window holds fewer nonces than there are threads,
+/// and none when the window is empty.
+pub(crate) fn partition_nonces(
+    nonces: RangeInclusive<u64>,
+    num_threads: usize,
+) -> Vec<RangeInclusive<u64>> {
+    let num_threads = num_threads.max(1) as u128;
+    if nonces.is_empty() {
+        return Vec::new();
+    }
+    // Work in u128 so that even the full 0..=u64::MAX window (2^64 nonces) has a length.
+    let first = *nonces.start() as u128;
+    let len = *nonces.end() as u128 - first + 1;
+
+    // Consecutive ranges share boundaries, so the window is covered without gaps.
+    (0..num_threads)
+        .map(|i| (first + len * i / num_threads, first + len * (i + 1) / num_threads))
+        .filter(|(lo, hi)| lo < hi)
+        .map(|(lo, hi)| lo as u64..=(hi - 1) as u64)
+        .collect()
+}
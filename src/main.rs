@@ -1,157 +1,3195 @@
-//! This example demonstrates a simplified proof-of-work style puzzle solver that uses
-//! a multi-threaded approach to find a nonce value that meets certain difficulty criteria.
+//! Command-line front end for the parallel puzzle solver.
 //!
-//! The puzzle is considered solved when the first two bytes of the SHA-256 hash of the
-//! data and the nonce produce a value less than the specified difficulty. The work is
-//! split evenly across multiple CPU cores, and once a solution is found, all other threads
-//! stop searching.
-
-use sha2::{Sha256, Digest};
-use std::sync::{
-    Arc, Mutex,
-    atomic::{AtomicBool, Ordering}
+//! All of the solving logic lives in the library crate; this binary only parses the
+//! command line, builds a puzzle, hands it to the miner, and prints the result.
+//!
+//! ```text
+//! puzzle-solver --data "block header" --difficulty 4 --threads 8 --algo blake3
+//! puzzle-solver verify --data "block header" --difficulty 4 --algo blake3 --nonce 1234
+//! ```
+
+use std::collections::HashSet;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use clap::{Args, Parser, Subcommand};
+use parallell_puzzle_generator_and_solver::{
+    cpu_temperature, default_nonce_range, difficulty_curve, hex, huge_pages_available,
+    search_keypair, Algorithm, ArchivedSolution, Argon2Params, Benchmark, Calibration,
+    CancellationToken, Chain, Challenge, ChallengeIssuer, Checkpoint, CoreKind, CorePinning,
+    CuckooParams, CuckooPuzzle, Difficulty, EquihashParams, EquihashPuzzle, FileStore,
+    GeneratedPuzzle, HashPrefix, KeyEncoding, KeyPrefix, MemoryHardFunction, MemoryHardPuzzle,
+    Mined, Miner, MintError, MiningHandle, MiningProgress, MiningReport, NonceEncoding, NonceFormat,
+    Observer, Puzzle, PuzzleError, ResultStore, Retarget, S3Credentials, S3Store, ScryptParams,
+    SearchOrder, Solution, Stamp, StoreError, StratumClient, StratumError, Target, ThermalLimit,
+    TimeLockPuzzle, TimeLockTrapdoor, Vdf, VdfProof, Verification, WorkClient, WorkError,
+    WorkEstimate, WorkEvent, WorkServer, WorkerPriority,
 };
-use std::thread;
-
-use num_cpus;
-
-const DIFFICULTY: u64 = 1;
-
-/// A puzzle represents a proof-of-work style problem.
-/// 
-/// The puzzle is defined by:
-/// - A `difficulty` which represents the target threshold for a valid hash.
-/// - Arbitrary `data` whose hash, combined with a `nonce`, must be below the difficulty threshold.
-/// - A `nonce` which is the value we try to find that makes the hash valid.
-#[derive(Clone)]
-struct Puzzle {
-    /// Difficulty threshold for the puzzle.
-    /// Lower values make it much harder to find a valid nonce.
-    difficulty: u64,
-    /// Arbitrary data (e.g., a block's header, transaction data, or a message).
+use parallell_puzzle_generator_and_solver::ingest::{self, NatsSource};
+use parallell_puzzle_generator_and_solver::num_bigint::BigUint;
+use parallell_puzzle_generator_and_solver::server::{DEFAULT_LEASE_SIZE, DEFAULT_MAX_INVALID_RATE};
+#[cfg(feature = "cuda")]
+use parallell_puzzle_generator_and_solver::{cuda, CudaMiner};
+#[cfg(unix)]
+use parallell_puzzle_generator_and_solver::Daemon;
+#[cfg(feature = "mdns")]
+use parallell_puzzle_generator_and_solver::discovery::{self, DEFAULT_DISCOVERY_TIMEOUT};
+#[cfg(feature = "gossip")]
+use parallell_puzzle_generator_and_solver::GossipNode;
+#[cfg(feature = "gpu")]
+use parallell_puzzle_generator_and_solver::GpuMiner;
+#[cfg(feature = "kafka")]
+use parallell_puzzle_generator_and_solver::KafkaSource;
+#[cfg(any(feature = "gpu", feature = "opencl", feature = "cuda"))]
+use parallell_puzzle_generator_and_solver::{parallel_mine_hybrid, Accelerator, HybridError};
+#[cfg(feature = "opencl")]
+use parallell_puzzle_generator_and_solver::{opencl, OpenClMiner};
+#[cfg(feature = "redis")]
+use parallell_puzzle_generator_and_solver::RedisQueue;
+#[cfg(feature = "tls")]
+use parallell_puzzle_generator_and_solver::{ClientTls, ServerTls};
+
+/// Prints a line like `println!`, but through [`write_line`], so that output piped into a
+/// reader that stops early, such as `head`, ends the program instead of panicking it.
+macro_rules! outln {
+    () => {
+        write_line(format_args!(""))
+    };
+    ($($arg:tt)*) => {
+        write_line(format_args!($($arg)*))
+    };
+}
+
+/// Command-line arguments accepted by the solver.
+///
+/// Without a subcommand the solver mines the described puzzle.
+#[derive(Parser, Debug)]
+#[command(name = "puzzle-solver", version, about = "Solve proof-of-work puzzles in parallel")]
+#[command(args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    puzzle: PuzzleArgs,
+
+    #[command(flatten)]
+    mine: MineArgs,
+}
+
+/// Subcommands other than mining.
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Check a nonce produced elsewhere and print the hash it yields.
+    Verify(VerifyArgs),
+    /// Generate an ed25519 keypair whose public key starts with a chosen prefix.
+    Keygen {
+        /// The text the public key must start with.
+        #[arg(long)]
+        prefix: String,
+
+        /// How the public key is written when matching the prefix (hex or base58).
+        #[arg(long, default_value_t = KeyEncoding::Hex)]
+        encoding: KeyEncoding,
+
+        /// Number of worker threads. Defaults to the number of logical CPU cores.
+        #[arg(long)]
+        threads: Option<usize>,
+    },
+    /// Mint or check Hashcash (version 1) stamps.
+    #[command(subcommand)]
+    Hashcash(HashcashCommand),
+    /// Solve or check Equihash (n, k) puzzles.
+    #[command(subcommand)]
+    Equihash(EquihashCommand),
+    /// Solve or check Cuckoo Cycle puzzles.
+    #[command(subcommand)]
+    Cuckoo(CuckooCommand),
+    /// Generate, solve or check time-lock puzzles.
+    #[command(subcommand)]
+    Timelock(TimelockCommand),
+    /// Set up, evaluate or check verifiable delay functions.
+    #[command(subcommand)]
+    Vdf(VdfCommand),
+    /// Issue, solve or check client puzzles for DoS protection.
+    #[command(subcommand)]
+    Challenge(ChallengeCommand),
+    /// Mine a chain of blocks, each committing to the previous block's hash.
+    Chain(ChainArgs),
+    /// Generate puzzles over random data that take a given time to solve on this
+    /// machine, and print them or write them to files.
+    Generate(GenerateArgs),
+    /// Benchmark this machine and print the difficulty for a given average solve time.
+    Calibrate {
+        /// How long a solution should take on average, in seconds (fractions allowed).
+        #[arg(long, value_name = "SECONDS", value_parser = parse_seconds)]
+        time: Duration,
+
+        /// Hash algorithm to benchmark.
+        #[arg(long, default_value_t = Algorithm::Sha256)]
+        algo: Algorithm,
+
+        /// Number of worker threads. Defaults to the number of logical CPU cores.
+        #[arg(long)]
+        threads: Option<usize>,
+
+        /// Use the hash rates saved by `bench --save` instead of measuring them.
+        #[arg(long, value_name = "FILE")]
+        rates: Option<PathBuf>,
+    },
+    /// Measure the single-thread and all-core hash rates of every algorithm and print
+    /// them side by side.
+    Bench {
+        /// How long to measure each algorithm on each thread count, in seconds (fractions
+        /// allowed).
+        #[arg(long, value_name = "SECONDS", value_parser = parse_seconds, default_value = "1")]
+        time: Duration,
+
+        /// Number of threads for the all-core rates. Defaults to the number of logical
+        /// CPU cores.
+        #[arg(long)]
+        threads: Option<usize>,
+
+        /// Only measure these algorithms, comma-separated. Defaults to all of them.
+        #[arg(long, value_delimiter = ',')]
+        algo: Vec<Algorithm>,
+
+        /// Save the rates to this file, for `calibrate --rates` and `--rates` when mining.
+        #[arg(long, value_name = "FILE")]
+        save: Option<PathBuf>,
+    },
+    /// Convert a difficulty between thresholds, zero bits, nBits, targets and expected
+    /// hashes.
+    Convert(ConvertArgs),
+    /// Coordinate a cluster: lease the nonce window of the described puzzle to workers
+    /// over HTTP until one of them solves it.
+    Serve(Box<ServeArgs>),
+    /// Join a cluster: lease nonce ranges from a `serve` coordinator, or take jobs from a
+    /// Stratum pool, mine them on this machine and report back, until the coordinator goes
+    /// away.
+    Work {
+        /// URL of the coordinator, e.g. http://10.0.0.1:8080, or of a Stratum pool, e.g.
+        /// stratum+tcp://10.0.0.1:3333.
+        #[arg(long, required_unless_present = "discover")]
+        server: Option<String>,
+
+        /// Find a coordinator that announces itself on the local network with `serve
+        /// --advertise` instead of naming one (needs the `mdns` feature).
+        #[arg(long, conflicts_with = "server")]
+        discover: bool,
+
+        /// User name to mine as on a Stratum pool.
+        #[arg(long, default_value = "puzzle-solver")]
+        user: String,
+
+        /// Password of `--user` on a Stratum pool.
+        #[arg(long, default_value = "x")]
+        password: String,
+
+        /// Number of worker threads. Defaults to the number of logical CPU cores.
+        #[arg(long)]
+        threads: Option<usize>,
+
+        /// How long to wait before asking again when the coordinator has nothing to
+        /// lease, unless it pushes a new puzzle sooner, in seconds (fractions allowed).
+        #[arg(long, value_name = "SECONDS", value_parser = parse_seconds, default_value = "1")]
+        poll: Duration,
+
+        #[command(flatten)]
+        tls: WorkTlsArgs,
+    },
+    /// Join a peer-to-peer network with no coordinator: share puzzles with the other nodes
+    /// over libp2p gossip and mine whatever any of them publishes (needs the `gossip`
+    /// feature).
+    Gossip(Box<GossipArgs>),
+    /// Mine puzzle jobs taken from a NATS subject or a Kafka topic, publishing the result
+    /// of each back, until the broker closes the connection.
+    Ingest(IngestArgs),
+    /// Look up the solution of a puzzle in an archive kept with `--archive`, and check it
+    /// again.
+    Lookup {
+        /// The archive: a directory, or an S3 bucket written as s3://bucket/prefix.
+        #[arg(long, value_name = "DIR|URL")]
+        archive: String,
+
+        /// The id of the puzzle, as printed when its solution was archived.
+        puzzle: String,
+    },
+    /// Mine puzzles submitted by other processes over a Unix socket, until one of them
+    /// asks the daemon to shut down.
+    #[cfg(unix)]
+    Daemon {
+        /// Path of the socket to listen on.
+        #[arg(long, default_value = "puzzle-solver.sock")]
+        socket: PathBuf,
+
+        /// Number of worker threads. Defaults to the number of logical CPU cores.
+        #[arg(long)]
+        threads: Option<usize>,
+    },
+}
+
+/// Arguments of the `convert` subcommand: the difficulty to convert, in exactly one unit.
+#[derive(Args, Debug)]
+#[group(required = true, multiple = false)]
+struct ConvertArgs {
+    /// A two-byte threshold, as for `--difficulty` when mining.
+    #[arg(long)]
+    difficulty: Option<u64>,
+
+    /// A number of leading zero bits.
+    #[arg(long)]
+    zero_bits: Option<u32>,
+
+    /// A Bitcoin compact "nBits" target (hex, e.g. 0x1d00ffff).
+    #[arg(long, value_parser = parse_hex_u32)]
+    nbits: Option<u32>,
+
+    /// A 256-bit target (big-endian hex, up to 64 digits).
+    #[arg(long)]
+    target: Option<Target>,
+
+    /// The number of hashes a solution should take on average (e.g. 1e9).
+    #[arg(long, value_name = "HASHES")]
+    expected_hashes: Option<f64>,
+}
+
+/// Arguments shared by the `equihash` subcommands.
+#[derive(Args, Debug)]
+struct EquihashArgs {
+    /// Data hashed in front of the nonce.
+    #[arg(long, default_value = "Some data")]
+    data: String,
+
+    /// Hex-encoded binary data to hash instead of `--data`.
+    #[arg(long, conflicts_with = "data")]
+    data_hex: Option<String>,
+
+    /// String length in bits.
+    #[arg(long, default_value_t = EquihashParams::default().n())]
+    n: u32,
+
+    /// Number of collision rounds.
+    #[arg(long, default_value_t = EquihashParams::default().k())]
+    k: u32,
+}
+
+impl EquihashArgs {
+    /// Builds the puzzle, exiting on invalid parameters or hex.
+    fn puzzle(&self) -> EquihashPuzzle {
+        let params = EquihashParams::new(self.n, self.k).unwrap_or_else(|e| {
+            eprintln!("error: {}", e);
+            std::process::exit(2);
+        });
+        let data = match &self.data_hex {
+            Some(data_hex) => hex::decode(data_hex).unwrap_or_else(|e| {
+                eprintln!("error: invalid --data-hex: {}", e);
+                std::process::exit(2);
+            }),
+            None => self.data.clone().into_bytes(),
+        };
+        EquihashPuzzle::new(params, data)
+    }
+}
+
+/// The `equihash` subcommands.
+#[derive(Subcommand, Debug)]
+enum EquihashCommand {
+    /// Try nonces from `--start-nonce` upwards until one has a solution, and print it.
+    Solve {
+        #[command(flatten)]
+        puzzle: EquihashArgs,
+
+        /// First nonce to try.
+        #[arg(long, default_value_t = 0)]
+        start_nonce: u64,
+
+        /// Number of worker threads. Defaults to the number of logical CPU cores.
+        #[arg(long)]
+        threads: Option<usize>,
+    },
+    /// Check a solution.
+    Verify {
+        #[command(flatten)]
+        puzzle: EquihashArgs,
+
+        /// The nonce the solution was found for.
+        #[arg(long)]
+        nonce: u64,
+
+        /// The solution's indices, comma-separated, in order.
+        #[arg(long, value_delimiter = ',', required = true)]
+        indices: Vec<u32>,
+    },
+}
+
+/// Arguments shared by the `cuckoo` subcommands.
+#[derive(Args, Debug)]
+struct CuckooArgs {
+    /// Data hashed in front of the nonce.
+    #[arg(long, default_value = "Some data")]
+    data: String,
+
+    /// Hex-encoded binary data to hash instead of `--data`.
+    #[arg(long, conflicts_with = "data")]
+    data_hex: Option<String>,
+
+    /// Base-2 logarithm of the number of edges in the graph.
+    #[arg(long, default_value_t = CuckooParams::default().edge_bits())]
+    edge_bits: u8,
+
+    /// Number of edges in a solution cycle.
+    #[arg(long, default_value_t = CuckooParams::default().proof_size())]
+    proof_size: usize,
+}
+
+impl CuckooArgs {
+    /// Builds the puzzle, exiting on invalid parameters or hex.
+    fn puzzle(&self) -> CuckooPuzzle {
+        let params = CuckooParams::new(self.edge_bits, self.proof_size).unwrap_or_else(|e| {
+            eprintln!("error: {}", e);
+            std::process::exit(2);
+        });
+        let data = match &self.data_hex {
+            Some(data_hex) => hex::decode(data_hex).unwrap_or_else(|e| {
+                eprintln!("error: invalid --data-hex: {}", e);
+                std::process::exit(2);
+            }),
+            None => self.data.clone().into_bytes(),
+        };
+        CuckooPuzzle::new(params, data)
+    }
+}
+
+/// The `cuckoo` subcommands.
+#[derive(Subcommand, Debug)]
+enum CuckooCommand {
+    /// Try nonces from `--start-nonce` upwards until one's graph has a cycle, and print it.
+    Solve {
+        #[command(flatten)]
+        puzzle: CuckooArgs,
+
+        /// First nonce to try.
+        #[arg(long, default_value_t = 0)]
+        start_nonce: u64,
+
+        /// Number of worker threads. Defaults to the number of logical CPU cores.
+        #[arg(long)]
+        threads: Option<usize>,
+    },
+    /// Check a solution.
+    Verify {
+        #[command(flatten)]
+        puzzle: CuckooArgs,
+
+        /// The nonce the solution was found for.
+        #[arg(long)]
+        nonce: u64,
+
+        /// The cycle's edge indices, comma-separated, in ascending order.
+        #[arg(long, value_delimiter = ',', required = true)]
+        edges: Vec<u32>,
+    },
+}
+
+/// The public parts of a time-lock puzzle, as printed by `timelock generate`.
+#[derive(Args, Debug)]
+struct TimelockArgs {
+    /// The number squared repeatedly, in hex.
+    #[arg(long, value_parser = parse_hex_biguint)]
+    base: BigUint,
+
+    /// How many sequential squarings the puzzle takes.
+    #[arg(long)]
+    squarings: u64,
+}
+
+/// The `timelock` subcommands.
+#[derive(Subcommand, Debug)]
+enum TimelockCommand {
+    /// Generate a puzzle and print it with its factors and answer.
+    Generate {
+        /// Size of the RSA modulus in bits.
+        #[arg(long, default_value_t = 2048)]
+        bits: u64,
+
+        /// How many sequential squarings the puzzle takes.
+        #[arg(long)]
+        squarings: u64,
+    },
+    /// Solve a puzzle by repeated squaring and print the answer.
+    Solve {
+        /// The RSA modulus, in hex.
+        #[arg(long, value_parser = parse_hex_biguint)]
+        modulus: BigUint,
+
+        #[command(flatten)]
+        puzzle: TimelockArgs,
+    },
+    /// Check an answer using the modulus' factors.
+    Verify {
+        /// The first prime factor of the modulus, in hex.
+        #[arg(long, value_parser = parse_hex_biguint)]
+        p: BigUint,
+
+        /// The second prime factor of the modulus, in hex.
+        #[arg(long, value_parser = parse_hex_biguint)]
+        q: BigUint,
+
+        #[command(flatten)]
+        puzzle: TimelockArgs,
+
+        /// The claimed answer, in hex.
+        #[arg(long, value_parser = parse_hex_biguint)]
+        answer: BigUint,
+    },
+}
+
+/// Arguments shared by the `vdf eval` and `vdf verify` subcommands.
+#[derive(Args, Debug)]
+struct VdfArgs {
+    /// The RSA modulus printed by `vdf setup`, in hex.
+    #[arg(long, value_parser = parse_hex_biguint)]
+    modulus: BigUint,
+
+    /// How many sequential squarings an evaluation takes.
+    #[arg(long)]
+    squarings: u64,
+
+    /// The input to evaluate the function on.
+    #[arg(long, default_value = "Some data")]
     data: String,
-    /// A nonce is the variable part we adjust to find a hash meeting the difficulty.
+
+    /// Hex-encoded binary input to use instead of `--data`.
+    #[arg(long, conflicts_with = "data")]
+    data_hex: Option<String>,
+}
+
+impl VdfArgs {
+    /// Builds the function and its input, exiting on invalid hex.
+    fn vdf(&self) -> (Vdf, Vec<u8>) {
+        let data = match &self.data_hex {
+            Some(data_hex) => hex::decode(data_hex).unwrap_or_else(|e| {
+                eprintln!("error: invalid --data-hex: {}", e);
+                std::process::exit(2);
+            }),
+            None => self.data.clone().into_bytes(),
+        };
+        (Vdf::new(self.modulus.clone(), self.squarings), data)
+    }
+}
+
+/// The `vdf` subcommands.
+#[derive(Subcommand, Debug)]
+enum VdfCommand {
+    /// Generate an RSA modulus, discard its factors and print it.
+    Setup {
+        /// Size of the RSA modulus in bits.
+        #[arg(long, default_value_t = 2048)]
+        bits: u64,
+    },
+    /// Evaluate the function by repeated squaring and print the output with its proof.
+    Eval {
+        #[command(flatten)]
+        vdf: VdfArgs,
+    },
+    /// Check an output against its proof.
+    Verify {
+        #[command(flatten)]
+        vdf: VdfArgs,
+
+        /// The claimed output, in hex.
+        #[arg(long, value_parser = parse_hex_biguint)]
+        output: BigUint,
+
+        /// The proof printed with the output, in hex.
+        #[arg(long, value_parser = parse_hex_biguint)]
+        proof: BigUint,
+    },
+}
+
+/// The `challenge` subcommands.
+#[derive(Subcommand, Debug)]
+enum ChallengeCommand {
+    /// Issue a challenge to a client and print it.
+    Issue {
+        /// The server's secret key.
+        #[arg(long)]
+        key: String,
+
+        /// The client's IP address.
+        #[arg(long)]
+        client: IpAddr,
+
+        /// Number of leading zero bits a solution's hash needs.
+        #[arg(long, default_value_t = 20)]
+        bits: u32,
+    },
+    /// Find a nonce that solves a challenge.
+    Solve {
+        /// The challenge printed by `challenge issue`.
+        #[arg(long)]
+        challenge: Challenge,
+
+        /// Number of worker threads. Defaults to the number of logical CPU cores.
+        #[arg(long)]
+        threads: Option<usize>,
+    },
+    /// Check a solution on the server side.
+    Verify {
+        /// The server's secret key.
+        #[arg(long)]
+        key: String,
+
+        /// The IP address the solution came from.
+        #[arg(long)]
+        client: IpAddr,
+
+        /// Seconds a challenge stays valid after it is issued.
+        #[arg(long, default_value_t = 300)]
+        ttl: u64,
+
+        /// The challenge the solution is for.
+        #[arg(long)]
+        challenge: Challenge,
+
+        /// The nonce found by `challenge solve`.
+        #[arg(long)]
+        nonce: u64,
+    },
+}
+
+/// Arguments of the `generate` subcommand.
+#[derive(Args, Debug)]
+struct GenerateArgs {
+    /// How long a solution should take on average, in seconds (fractions allowed).
+    #[arg(long, value_name = "SECONDS", value_parser = parse_seconds)]
+    time: Duration,
+
+    /// Number of random data bytes.
+    #[arg(long, default_value_t = 32)]
+    data_len: usize,
+
+    /// Hash algorithm the puzzles are solved with.
+    #[arg(long, default_value_t = Algorithm::Sha256)]
+    algo: Algorithm,
+
+    /// Number of worker threads the solver will use. Defaults to the number of logical
+    /// CPU cores.
+    #[arg(long)]
+    threads: Option<usize>,
+
+    /// Number of puzzles to generate, from consecutive seeds. Without `--up-to` they all
+    /// share one target.
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..))]
+    count: u64,
+
+    /// Make the puzzles geometrically harder, from `--time` seconds for the first to
+    /// this many seconds for the last.
+    #[arg(long, value_name = "SECONDS", value_parser = parse_seconds)]
+    up_to: Option<Duration>,
+
+    /// Seed of the first puzzle's data, to regenerate an earlier set. Random by default.
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Write each puzzle to a numbered file in this directory, created if needed,
+    /// instead of printing it.
+    #[arg(long, value_name = "DIR")]
+    out: Option<PathBuf>,
+}
+
+/// Arguments of the `chain` subcommand.
+#[derive(Args, Debug)]
+struct ChainArgs {
+    /// Number of blocks to mine.
+    #[arg(long, default_value_t = 10)]
+    blocks: u64,
+
+    /// Payload stored in every block.
+    #[arg(long, default_value = "Some data")]
+    data: String,
+
+    /// Give every block this many generated transactions, `<data> block <height> tx <i>`,
+    /// and store their Merkle root as the block's data instead.
+    #[arg(long)]
+    transactions: Option<usize>,
+
+    /// Number of leading zero bits every block hash needs, or the first block's hash
+    /// when retargeting.
+    #[arg(long, default_value_t = 16)]
+    zero_bits: u32,
+
+    /// Adjust the target every this many blocks so blocks take `--block-time` on
+    /// average.
+    #[arg(long, requires = "block_time", value_parser = clap::value_parser!(u64).range(1..))]
+    retarget_interval: Option<u64>,
+
+    /// The average time per block, in milliseconds, that retargeting aims for.
+    #[arg(long, requires = "retarget_interval", value_parser = clap::value_parser!(u64).range(1..))]
+    block_time: Option<u64>,
+
+    /// Hash algorithm used by the chain (sha256, blake3, keccak256 or sha256d).
+    #[arg(long, default_value_t = Algorithm::Sha256)]
+    algo: Algorithm,
+
+    /// Number of worker threads. Defaults to the number of logical CPU cores.
+    #[arg(long)]
+    threads: Option<usize>,
+}
+
+/// The `hashcash` subcommands.
+#[derive(Subcommand, Debug)]
+enum HashcashCommand {
+    /// Mint a stamp for a resource and print it as an `X-Hashcash` header.
+    Mint {
+        /// What the stamp is for, typically an e-mail address.
+        #[arg(long)]
+        resource: String,
+
+        /// Number of leading zero bits the stamp's SHA-1 hash must have.
+        #[arg(long, default_value_t = 20)]
+        bits: u32,
+
+        /// Override the stamp date (`YYMMDD`); defaults to today in UTC.
+        #[arg(long, value_parser = parse_stamp_date)]
+        date: Option<String>,
+
+        /// Number of worker threads. Defaults to the number of logical CPU cores.
+        #[arg(long)]
+        threads: Option<usize>,
+    },
+    /// Check that a stamp has as many zero bits as it claims.
+    Check {
+        /// The stamp, with or without the `X-Hashcash:` prefix.
+        stamp: Stamp,
+    },
+}
+
+/// Arguments of the `verify` subcommand.
+#[derive(Args, Debug)]
+struct VerifyArgs {
+    #[command(flatten)]
+    puzzle: PuzzleArgs,
+
+    /// The nonce to check.
+    #[arg(long)]
     nonce: u64,
 }
 
-fn main() {
-    // Create a puzzle with a given difficulty and some arbitrary data.
+/// The TLS flags of `work`, for an https:// coordinator.
+#[derive(Args, Debug)]
+struct WorkTlsArgs {
+    /// Trust only the authorities in this PEM file to vouch for an https:// coordinator,
+    /// rather than the usual public ones (needs the `tls` feature).
+    #[arg(long, value_name = "PATH")]
+    tls_ca: Option<PathBuf>,
+
+    /// Present this certificate chain, in PEM, to a coordinator that demands one.
+    #[arg(long, value_name = "PATH", requires = "tls_key")]
+    tls_cert: Option<PathBuf>,
+
+    /// The private key of `--tls-cert`, in PEM.
+    #[arg(long, value_name = "PATH", requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
+}
+
+/// Arguments of the `serve` subcommand.
+#[derive(Args, Debug)]
+struct ServeArgs {
+    #[command(flatten)]
+    puzzle: PuzzleArgs,
+
+    /// Address to listen on for workers.
+    #[arg(long, default_value = "127.0.0.1:8080")]
+    listen: String,
+
+    /// Also serve the gRPC API on this address (needs the `grpc` feature).
+    #[arg(long, value_name = "ADDR")]
+    grpc_listen: Option<SocketAddr>,
+
+    /// Also act as a Stratum pool on this address, handing out each lease as a job.
+    #[arg(long, value_name = "ADDR")]
+    stratum_listen: Option<SocketAddr>,
+
+    /// Serve HTTPS, and gRPC over TLS, with this certificate chain in PEM (needs the
+    /// `tls` feature).
+    #[arg(long, value_name = "PATH", requires = "tls_key")]
+    tls_cert: Option<PathBuf>,
+
+    /// The private key of `--tls-cert`, in PEM.
+    #[arg(long, value_name = "PATH", requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
+
+    /// Demand of every worker a certificate signed by an authority in this PEM file.
+    #[arg(long, value_name = "PATH", requires = "tls_cert")]
+    tls_client_ca: Option<PathBuf>,
+
+    /// Take `POST /shutdown` only with this token, sent as `Authorization: Bearer
+    /// <TOKEN>`. Without it, only a client on this machine may shut the server down.
+    #[arg(long, value_name = "TOKEN")]
+    admin_token: Option<String>,
+
+    /// Announce the coordinator on the local network over mDNS, for `work --discover`
+    /// (needs the `mdns` feature).
+    #[arg(long)]
+    advertise: bool,
+
+    /// Keep the puzzles, workers and leases in the Redis server at this URL, such as
+    /// redis://127.0.0.1:6379, to carry on after a restart and share them with other
+    /// `serve` replicas on the same queue (needs the `redis` feature).
+    #[arg(long, value_name = "URL")]
+    redis: Option<String>,
+
+    /// The key the queue is kept under in Redis.
+    #[arg(long, value_name = "KEY", default_value = "puzzle-solver", requires = "redis")]
+    redis_key: String,
+
+    /// Archive the solution in this directory, or in an S3 bucket written as
+    /// s3://bucket/prefix; see `lookup`.
+    #[arg(long, value_name = "DIR|URL")]
+    archive: Option<String>,
+
+    /// Number of nonces leased to a worker at a time.
+    #[arg(
+        long,
+        value_name = "NONCES",
+        default_value_t = DEFAULT_LEASE_SIZE,
+        value_parser = clap::value_parser!(u64).range(1..),
+    )]
+    lease_size: u64,
+
+    /// Count hashes with this many leading zero bits as shares: proof of each worker's
+    /// effort, reported per worker. Off by default.
+    #[arg(long, value_name = "BITS")]
+    share_zero_bits: Option<u32>,
+
+    /// Report a worker as idle once it has held a lease this long without sending a
+    /// share, in seconds (fractions allowed).
+    #[arg(long, value_name = "SECONDS", value_parser = parse_seconds, default_value = "60")]
+    idle_timeout: Duration,
+
+    /// Take a lease back from its worker and lease its range to another once it has gone
+    /// this long without a report, in seconds (fractions allowed).
+    #[arg(long, value_name = "SECONDS", value_parser = parse_seconds, default_value = "300")]
+    lease_timeout: Duration,
+
+    /// Flag a worker once more than this share of the nonces it sends, and at least
+    /// three, are rejected on checking (0 to 1).
+    #[arg(
+        long,
+        value_name = "RATE",
+        default_value_t = DEFAULT_MAX_INVALID_RATE,
+        value_parser = parse_rate,
+    )]
+    max_invalid_rate: f64,
+
+    /// First nonce to lease (inclusive).
+    #[arg(long)]
+    start_nonce: Option<u64>,
+
+    /// Last nonce to lease (inclusive).
+    #[arg(long)]
+    end_nonce: Option<u64>,
+}
+
+/// Arguments of the `gossip` subcommand.
+#[derive(Args, Debug)]
+struct GossipArgs {
+    #[command(flatten)]
+    puzzle: PuzzleArgs,
+
+    /// Publish the puzzle described by the puzzle flags, and exit once the network has
+    /// solved or exhausted it. Without it, mine what other nodes publish until stopped.
+    #[arg(long)]
+    publish: bool,
+
+    /// Multiaddr to listen on for other nodes.
+    #[arg(long, value_name = "MULTIADDR", default_value = "/ip4/0.0.0.0/tcp/4001")]
+    listen: String,
+
+    /// Multiaddr of a node to join the network through, as the node prints it on
+    /// starting; may be repeated.
+    #[arg(long = "peer", value_name = "MULTIADDR")]
+    peers: Vec<String>,
+
+    /// Number of nonces claimed at a time.
+    #[arg(
+        long,
+        value_name = "NONCES",
+        default_value_t = 1 << 26,
+        value_parser = clap::value_parser!(u64).range(1..),
+    )]
+    claim_size: u64,
+
+    /// Give up on another node's claim once it has gone this long without a report, and
+    /// claim its range again, in seconds (fractions allowed).
+    #[arg(long, value_name = "SECONDS", value_parser = parse_seconds, default_value = "300")]
+    claim_timeout: Duration,
+
+    /// Number of worker threads. Defaults to the number of logical CPU cores.
+    #[arg(long)]
+    threads: Option<usize>,
+
+    /// First nonce of the published puzzle's window (inclusive).
+    #[arg(long, requires = "publish")]
+    start_nonce: Option<u64>,
+
+    /// Last nonce of the published puzzle's window (inclusive).
+    #[arg(long, requires = "publish")]
+    end_nonce: Option<u64>,
+}
+
+/// Arguments of the `ingest` subcommand.
+#[derive(Args, Debug)]
+struct IngestArgs {
+    /// Take jobs from the NATS server at this URL, such as nats://127.0.0.1:4222.
+    #[arg(long, value_name = "URL", required_unless_present = "kafka", conflicts_with = "kafka")]
+    nats: Option<String>,
+
+    /// Take jobs from the Kafka cluster with these brokers, comma-separated, such as
+    /// 127.0.0.1:9092 (needs the `kafka` feature).
+    #[arg(long, value_name = "HOST:PORT", value_delimiter = ',')]
+    kafka: Vec<String>,
+
+    /// The NATS subject or Kafka topic to take jobs from.
+    #[arg(long, value_name = "NAME", default_value = "puzzles.jobs")]
+    jobs: String,
+
+    /// The NATS subject or Kafka topic to publish results on. A NATS job sent as a
+    /// request is answered on its reply subject instead.
+    #[arg(long, value_name = "NAME", default_value = "puzzles.results")]
+    results: String,
+
+    /// The NATS queue group or Kafka consumer group to join, sharing the jobs with its
+    /// other members.
+    #[arg(long, value_name = "NAME", default_value = "puzzle-solver")]
+    group: String,
+
+    /// Number of worker threads. Defaults to the number of logical CPU cores.
+    #[arg(long)]
+    threads: Option<usize>,
+}
+
+/// Arguments describing the puzzle: its data, difficulty and hashing rules.
+#[derive(Args, Debug)]
+struct PuzzleArgs {
+    /// Arbitrary data to hash together with the nonce.
+    #[arg(long, default_value = "Some data")]
+    data: String,
+
+    /// Hex-encoded binary data to hash instead of `--data`.
+    #[arg(long, conflicts_with = "data")]
+    data_hex: Option<String>,
+
+    /// Stream the data to hash from this file (`-` for stdin) instead of `--data`.
+    /// The payload is hashed once up front and never held in memory.
+    #[arg(long, conflicts_with_all = ["data", "data_hex"])]
+    data_file: Option<PathBuf>,
+
+    /// Difficulty threshold: the first two bytes of the hash must be below this value.
+    /// Lower values make the puzzle harder.
+    #[arg(long, default_value_t = 1)]
+    difficulty: u64,
+
+    /// Require this many leading zero bits across the whole hash instead of using
+    /// the two-byte `--difficulty` threshold.
+    #[arg(long, conflicts_with = "difficulty")]
+    zero_bits: Option<u32>,
+
+    /// Use a Bitcoin compact "nBits" target (hex, e.g. 0x1d00ffff) instead of the
+    /// two-byte `--difficulty` threshold.
+    #[arg(long, value_parser = parse_hex_u32, conflicts_with_all = ["difficulty", "zero_bits"])]
+    nbits: Option<u32>,
+
+    /// Use an explicit 256-bit target (big-endian hex, up to 64 digits); the hash must
+    /// not exceed it. Allows difficulties between powers of two.
+    #[arg(long, conflicts_with_all = ["difficulty", "zero_bits", "nbits"])]
+    target: Option<Target>,
+
+    /// Hash algorithm used by the puzzle (sha256, blake3, keccak256 or sha256d).
+    #[arg(long, default_value_t = Algorithm::Sha256)]
+    algo: Algorithm,
+
+    /// How the nonce is serialised: 8 big-endian bytes (be), 8 little-endian bytes (le),
+    /// or decimal digits (ascii).
+    #[arg(long, default_value_t = NonceEncoding::BigEndian)]
+    nonce_encoding: NonceEncoding,
+
+    /// Insert the nonce at this byte offset in the data instead of appending it.
+    #[arg(long, conflicts_with = "data_file")]
+    nonce_offset: Option<usize>,
+
+    /// Append an extra nonce (starting at the given value, default 0) to the data and
+    /// roll it whenever the nonce range is exhausted.
+    #[arg(long, num_args = 0..=1, default_missing_value = "0", conflicts_with = "data_file")]
+    extra_nonce: Option<u64>,
+
+    /// Hash with the memory-hard Argon2id function instead of `--algo`.
+    #[arg(long, group = "memory_hard", conflicts_with_all = ["data_file", "algo"])]
+    argon2id: bool,
+
+    /// Argon2id memory per hash, in KiB.
+    #[arg(long, requires = "argon2id", default_value_t = Argon2Params::default().memory_kib)]
+    argon2_memory: u32,
+
+    /// Argon2id number of passes over the memory.
+    #[arg(long, requires = "argon2id", default_value_t = Argon2Params::default().iterations)]
+    argon2_iterations: u32,
+
+    /// Argon2id degree of parallelism inside one hash.
+    #[arg(long, requires = "argon2id", default_value_t = Argon2Params::default().lanes)]
+    argon2_lanes: u32,
+
+    /// Hash with the memory-hard scrypt function (Litecoin-style) instead of `--algo`.
+    #[arg(long, group = "memory_hard", conflicts_with_all = ["data_file", "algo"])]
+    scrypt: bool,
+
+    /// Scrypt cost: log2 of `N`.
+    #[arg(long, requires = "scrypt", default_value_t = ScryptParams::default().log_n)]
+    scrypt_log_n: u8,
+
+    /// Scrypt block size `r`.
+    #[arg(long, requires = "scrypt", default_value_t = ScryptParams::default().r)]
+    scrypt_r: u32,
+
+    /// Scrypt parallelism `p`.
+    #[arg(long, requires = "scrypt", default_value_t = ScryptParams::default().p)]
+    scrypt_p: u32,
+
+    /// Back each thread's memory-hard scratch memory with transparent huge pages, where
+    /// the kernel allows it.
+    #[arg(long, requires = "memory_hard")]
+    huge_pages: bool,
+}
+
+/// Arguments that only affect how the search is run.
+#[derive(Args, Debug)]
+struct MineArgs {
+    /// Number of worker threads. Defaults to the number of logical CPU cores, or with
+    /// `--pin-cores` or `--numa` to the number of cores pinned to.
+    #[arg(long)]
+    threads: Option<usize>,
+
+    /// Pin each worker thread to a core, so the system does not move it from core to
+    /// core while it mines.
+    #[arg(long, group = "pinning", conflicts_with_all = PINNING_CONFLICTS)]
+    pin_cores: bool,
+
+    /// Like `--pin-cores`, but spread the workers over the NUMA nodes, have each search
+    /// its own node's share of the nonces first, and keep memory-hard scratch memory on
+    /// the node of the thread that uses it.
+    #[arg(long, group = "pinning", conflicts_with_all = PINNING_CONFLICTS)]
+    numa: bool,
+
+    /// Cores to keep free of workers with `--pin-cores` or `--numa`, comma-separated,
+    /// e.g. `0` to leave core 0 to the operating system.
+    #[arg(long, value_name = "CORES", value_delimiter = ',', requires = "pinning")]
+    exclude_cores: Vec<usize>,
+
+    /// Pin the workers with `--pin-cores` or `--numa` only to the performance or only to
+    /// the efficiency cores of a hybrid CPU, e.g. `efficiency` to mine quietly in the
+    /// background.
+    #[arg(long, value_name = "KIND", requires = "pinning")]
+    core_type: Option<CoreKind>,
+
+    /// With `--pin-cores` or `--numa`, size each worker's chunks of nonces by the speed
+    /// of its core, so fast and slow cores spend about as long on a chunk.
+    #[arg(long, requires = "pinning")]
+    weighted_chunks: bool,
+
+    /// Operating-system priority of the worker threads: normal, low (the lowest nice
+    /// level) or idle (only run on otherwise idle cores), to keep the desktop responsive
+    /// while mining. Only lowers the priority on Linux.
+    #[arg(
+        long,
+        value_name = "PRIORITY",
+        default_value_t = WorkerPriority::Normal,
+        conflicts_with_all = BACKGROUND_CONFLICTS
+    )]
+    priority: WorkerPriority,
+
+    /// Keep each worker thread busy for only this percentage of the time, resting in
+    /// between, so the miner uses about that share of the machine; e.g. `50` on a laptop
+    /// or shared server.
+    #[arg(
+        long,
+        value_name = "PERCENT",
+        value_parser = clap::value_parser!(u8).range(1..=100),
+        conflicts_with_all = BACKGROUND_CONFLICTS
+    )]
+    max_cpu: Option<u8>,
+
+    /// Slow the workers down, halving their share of the time each second, while the CPU
+    /// is hotter than this many degrees Celsius. Needs a readable temperature sensor.
+    #[arg(long, value_name = "CELSIUS", conflicts_with_all = BACKGROUND_CONFLICTS)]
+    max_temp: Option<f64>,
+
+    /// Speed up again once the CPU has cooled to this many degrees Celsius. Defaults to
+    /// 5 degrees below `--max-temp`.
+    #[arg(long, value_name = "CELSIUS", requires = "max_temp")]
+    resume_temp: Option<f64>,
+
+    /// First nonce to try (inclusive). Useful for resuming a run or splitting work
+    /// between machines.
+    #[arg(long)]
+    start_nonce: Option<u64>,
+
+    /// Last nonce to try (inclusive).
+    #[arg(long)]
+    end_nonce: Option<u64>,
+
+    /// Keep searching after the first hit and print every solution in the nonce window.
+    #[arg(long, conflicts_with_all = ["data_file", "memory_hard"])]
+    all: bool,
+
+    /// Stop after this many solutions (implies `--all`).
+    #[arg(long, conflicts_with_all = ["data_file", "memory_hard"])]
+    limit: Option<usize>,
+
+    /// Cap the scratch memory of memory-hard hashing at this many MiB in total, running
+    /// fewer threads if needed. Defaults to half of the available memory.
+    #[arg(long, requires = "memory_hard")]
+    memory_budget: Option<u64>,
+
+    /// Vanity search: find a hash whose hex form starts with these digits, instead of
+    /// meeting a difficulty.
+    #[arg(
+        long,
+        value_parser = parse_hex_prefix,
+        conflicts_with_all = VANITY_CONFLICTS,
+    )]
+    prefix: Option<HashPrefix>,
+
+    /// Vanity search: find a hash whose raw bytes start with this ASCII text.
+    #[arg(
+        long,
+        value_parser = parse_ascii_prefix,
+        conflicts_with_all = VANITY_CONFLICTS,
+        conflicts_with = "prefix",
+    )]
+    ascii_prefix: Option<HashPrefix>,
+
+    /// Print the number of hashes tried and the live hash rate to stderr every second.
+    #[arg(long, conflicts_with_all = BACKGROUND_CONFLICTS)]
+    progress: bool,
+
+    /// Give up if no solution is found within this many seconds (fractions allowed), and
+    /// report how many hashes were tried.
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        value_parser = parse_seconds,
+        conflicts_with_all = BACKGROUND_CONFLICTS,
+    )]
+    timeout: Option<Duration>,
+
+    /// Save the search's progress to this file every few seconds, so an interrupted run
+    /// can be continued with `--resume`. The file is removed once the search ends, but
+    /// kept if it stops at `--timeout`.
+    #[arg(long, value_name = "FILE", conflicts_with_all = BACKGROUND_CONFLICTS)]
+    checkpoint: Option<PathBuf>,
+
+    /// Continue the search saved in this checkpoint file, and keep saving progress to it.
+    /// The nonce window and thread count are taken from the file.
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = BACKGROUND_CONFLICTS,
+        conflicts_with_all = ["checkpoint", "threads", "start_nonce", "end_nonce"],
+    )]
+    resume: Option<PathBuf>,
+
+    /// Have each thread try its nonces in a pseudo-random order chosen by this seed,
+    /// instead of counting up. A resumed search keeps the order saved in its checkpoint.
+    #[arg(
+        long,
+        value_name = "SEED",
+        conflicts_with_all = BACKGROUND_CONFLICTS,
+        conflicts_with = "resume",
+    )]
+    shuffle: Option<u64>,
+
+    /// Have the threads count up through runs of nonces that each start at a
+    /// pseudo-random point of the window chosen by this seed, instead of from the bottom.
+    /// Independent searches of the same puzzle should each use a seed of their own.
+    #[arg(
+        long,
+        value_name = "SEED",
+        conflicts_with_all = BACKGROUND_CONFLICTS,
+        conflicts_with_all = ["resume", "shuffle"],
+    )]
+    restarts: Option<u64>,
+
+    /// How many nonces each run of `--restarts` tries before jumping elsewhere.
+    #[arg(
+        long,
+        value_name = "NONCES",
+        default_value_t = 1 << 20,
+        value_parser = clap::value_parser!(u64).range(1..),
+        requires = "restarts",
+    )]
+    restart_every: u64,
+
+    /// Pick the difficulty by benchmarking this machine, so that a solution takes this
+    /// many seconds on average (fractions allowed).
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        value_parser = parse_seconds,
+        conflicts_with_all = ["difficulty", "zero_bits", "nbits", "target", "resume"],
+        conflicts_with_all = ["data_file", "memory_hard", "prefix", "ascii_prefix"],
+    )]
+    solve_time: Option<Duration>,
+
+    /// Use the hash rates saved by `bench --save` for `--solve-time` and the time
+    /// estimate of long searches, instead of measuring them first.
+    #[arg(long, value_name = "FILE")]
+    rates: Option<PathBuf>,
+
+    /// Seconds between checkpoint saves.
+    #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u64).range(1..))]
+    checkpoint_interval: u64,
+
+    /// Archive the solution in this directory, or in an S3 bucket written as
+    /// s3://bucket/prefix; see `lookup`.
+    #[arg(
+        long,
+        value_name = "DIR|URL",
+        conflicts_with_all = ["data_file", "memory_hard", "prefix", "ascii_prefix", "all", "limit"],
+    )]
+    archive: Option<String>,
+
+    /// Where to search: `cpu`, `gpu` for a compute shader on the graphics card, `opencl`
+    /// for an OpenCL kernel or `cuda` for a CUDA kernel on the device picked with
+    /// `--device`. The other backends only mine SHA-256 puzzles with a `be` or `le` nonce
+    /// and none of the options that need a running search; otherwise, or without a usable
+    /// device, the search falls back to the CPU.
+    #[arg(long, default_value_t = Backend::Cpu)]
+    backend: Backend,
+
+    /// The OpenCL or CUDA device to mine on, by its number in `--list-devices`. Defaults
+    /// to the first GPU or accelerator.
+    #[arg(long, value_name = "INDEX")]
+    device: Option<usize>,
+
+    /// List the OpenCL and CUDA devices `--device` can pick from, then exit.
+    #[arg(long)]
+    list_devices: bool,
+
+    /// Mine on the `--backend` device and the CPU threads at once, splitting the nonces
+    /// between them by their hash rates, measured for a moment before the search.
+    #[arg(long)]
+    hybrid: bool,
+}
+
+/// Where the `--backend` flag runs the search.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Backend {
+    Cpu,
+    Gpu,
+    OpenCl,
+    Cuda,
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Backend::Cpu => "cpu",
+            Backend::Gpu => "gpu",
+            Backend::OpenCl => "opencl",
+            Backend::Cuda => "cuda",
+        })
+    }
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "cpu" => Ok(Backend::Cpu),
+            "gpu" => Ok(Backend::Gpu),
+            "opencl" => Ok(Backend::OpenCl),
+            "cuda" => Ok(Backend::Cuda),
+            _ => Err(format!("unknown backend `{}` (expected cpu, gpu, opencl or cuda)", s)),
+        }
+    }
+}
+
+/// Flags for searches that do not run in the background, so they cannot be checkpointed,
+/// given a time limit or report progress.
+const BACKGROUND_CONFLICTS: [&str; 6] =
+    ["data_file", "all", "limit", "memory_hard", "prefix", "ascii_prefix"];
+
+/// Options that pick a search `--pin-cores` and `--numa` cannot pin: every search but
+/// the background one and memory-hard puzzles.
+const PINNING_CONFLICTS: [&str; 5] = ["data_file", "all", "limit", "prefix", "ascii_prefix"];
+
+/// Formats core numbers as a comma-separated list, e.g. `0, 2, 4`.
+fn format_cores(cores: &[usize]) -> String {
+    let cores: Vec<String> = cores.iter().map(usize::to_string).collect();
+    cores.join(", ")
+}
+
+/// Formats a hash rate with an SI prefix, e.g. `12.3 MH/s`.
+fn format_rate(rate: f64) -> String {
+    format_hashes_per(rate, "s")
+}
+
+/// Formats an energy efficiency with an SI prefix, e.g. `1.2 MH/J`.
+fn format_efficiency(hashes_per_joule: f64) -> String {
+    format_hashes_per(hashes_per_joule, "J")
+}
+
+/// Formats a number of hashes per `unit` with an SI prefix.
+fn format_hashes_per(value: f64, unit: &str) -> String {
+    const PREFIXES: [&str; 5] = ["", "k", "M", "G", "T"];
+    let mut value = value;
+    let mut prefix = 0;
+    while value >= 1000.0 && prefix < PREFIXES.len() - 1 {
+        value /= 1000.0;
+        prefix += 1;
+    }
+    format!("{:.1} {}H/{}", value, PREFIXES[prefix], unit)
+}
+
+/// Parses a `--timeout` value.
+fn parse_seconds(s: &str) -> Result<Duration, String> {
+    s.parse::<f64>()
+        .ok()
+        .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+        .filter(|duration| !duration.is_zero())
+        .ok_or_else(|| format!("invalid duration `{}`: expected a positive number of seconds", s))
+}
+
+/// Parses a Hashcash `--date`: a real calendar date written as `YYMMDD`, taking the
+/// century to be 2000.
+fn parse_stamp_date(s: &str) -> Result<String, String> {
+    let invalid = || format!("invalid date `{}`: expected YYMMDD", s);
+    if s.len() != 6 || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    let field = |i: usize| s[i..i + 2].parse::<u32>().expect("two ASCII digits");
+    let (year, month, day) = (2000 + field(0), field(2), field(4));
+    let leap = year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400));
+    let days = match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if leap => 29,
+        2 => 28,
+        _ => return Err(invalid()),
+    };
+    if (1..=days).contains(&day) {
+        Ok(s.to_string())
+    } else {
+        Err(invalid())
+    }
+}
+
+/// Parses a `--max-invalid-rate` value.
+fn parse_rate(s: &str) -> Result<f64, String> {
+    s.parse::<f64>()
+        .ok()
+        .filter(|rate| (0.0..=1.0).contains(rate))
+        .ok_or_else(|| format!("invalid rate `{}`: expected a number from 0 to 1", s))
+}
+
+/// Flags that make no sense together with a vanity prefix.
+const VANITY_CONFLICTS: [&str; 8] =
+    ["difficulty", "zero_bits", "nbits", "target", "data_file", "all", "limit", "memory_hard"];
+
+/// Parses a `--prefix` value.
+fn parse_hex_prefix(s: &str) -> Result<HashPrefix, String> {
+    HashPrefix::hex(s).map_err(|e| format!("invalid hex prefix `{}`: {}", s, e))
+}
+
+/// Parses an `--ascii-prefix` value.
+fn parse_ascii_prefix(s: &str) -> Result<HashPrefix, String> {
+    Ok(HashPrefix::ascii(s))
+}
+
+impl MineArgs {
+    /// The cores `--pin-cores` or `--numa` pins the workers to, if either was given.
+    fn pinning(&self) -> Option<CorePinning> {
+        let mut pinning = CorePinning::excluding(self.exclude_cores.iter().copied());
+        if let Some(kind) = self.core_type {
+            pinning = pinning.only(kind);
+            if pinning.cores().is_empty() {
+                eprintln!("error: no {} cores are left to pin the workers to", kind);
+                std::process::exit(2);
+            }
+        }
+        if self.weighted_chunks {
+            pinning = pinning.weighted();
+        }
+        match (self.pin_cores, self.numa) {
+            (_, true) => Some(pinning.numa()),
+            (true, false) => Some(pinning),
+            (false, false) => None,
+        }
+    }
+
+    /// The order `--shuffle` or `--restarts` searches the window in, if either was given.
+    fn order(&self) -> Option<SearchOrder> {
+        match (self.shuffle, self.restarts) {
+            (Some(seed), _) => Some(SearchOrder::Shuffled { seed }),
+            (None, Some(seed)) => Some(SearchOrder::Restarts { seed, run: self.restart_every }),
+            (None, None) => None,
+        }
+    }
+}
+
+impl PuzzleArgs {
+    /// Builds the difficulty from whichever difficulty flag was given.
+    fn difficulty(&self) -> Difficulty {
+        if let Some(target) = self.target {
+            Difficulty::Target(target)
+        } else if let Some(bits) = self.nbits {
+            match Difficulty::from_compact(bits) {
+                Ok(difficulty) => difficulty,
+                Err(e) => {
+                    eprintln!("error: invalid --nbits {:#010x}: {}", bits, e);
+                    std::process::exit(2);
+                }
+            }
+        } else if let Some(bits) = self.zero_bits {
+            Difficulty::LeadingZeroBits(bits)
+        } else {
+            Difficulty::Threshold(self.difficulty)
+        }
+    }
+
+    /// The memory-hard function selected on the command line, if any.
+    fn memory_hard(&self) -> Option<MemoryHardFunction> {
+        if self.argon2id {
+            Some(MemoryHardFunction::Argon2id(Argon2Params {
+                memory_kib: self.argon2_memory,
+                iterations: self.argon2_iterations,
+                lanes: self.argon2_lanes,
+            }))
+        } else if self.scrypt {
+            Some(MemoryHardFunction::Scrypt(ScryptParams {
+                log_n: self.scrypt_log_n,
+                r: self.scrypt_r,
+                p: self.scrypt_p,
+            }))
+        } else {
+            None
+        }
+    }
+
+    /// Builds a memory-hard puzzle from `--data` or `--data-hex` and `function`.
+    fn memory_hard_puzzle(&self, target: Target, function: MemoryHardFunction) -> MemoryHardPuzzle {
+        let puzzle = MemoryHardPuzzle::new(self.puzzle(target), function).unwrap_or_else(|e| {
+            eprintln!("error: {}", e);
+            std::process::exit(2);
+        });
+        if self.huge_pages && !huge_pages_available() {
+            eprintln!("warning: transparent huge pages are off, so --huge-pages has no effect");
+        }
+        puzzle.with_huge_pages(self.huge_pages)
+    }
+
+    /// Opens the `--data-file` payload, or stdin for `-`.
+    fn open_data_file(path: &Path) -> Box<dyn Read> {
+        if path.as_os_str() == "-" {
+            Box::new(io::stdin().lock())
+        } else {
+            Box::new(File::open(path).unwrap_or_else(|e| {
+                eprintln!("error: cannot open {}: {}", path.display(), e);
+                std::process::exit(2);
+            }))
+        }
+    }
+
+    /// Builds an in-memory puzzle from `--data` or `--data-hex`.
+    fn puzzle(&self, target: Target) -> Puzzle {
+        let puzzle = if let Some(hex) = &self.data_hex {
+            Puzzle::from_hex(hex, target).unwrap_or_else(|e| {
+                eprintln!("error: invalid --data-hex: {}", e);
+                std::process::exit(2);
+            })
+        } else {
+            Puzzle::new(self.data.clone(), target)
+        };
+        let mut puzzle = puzzle.with_nonce_format(NonceFormat {
+            encoding: self.nonce_encoding,
+            offset: self.nonce_offset,
+        });
+        puzzle.extra_nonce = self.extra_nonce;
+        puzzle
+    }
+}
+
+/// Parses a `u32` written in hex, with or without a `0x` prefix.
+fn parse_hex_u32(s: &str) -> Result<u32, String> {
+    let digits = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")).unwrap_or(s);
+    u32::from_str_radix(digits, 16).map_err(|e| format!("invalid hex value `{}`: {}", s, e))
+}
+
+/// Parses an arbitrarily large unsigned integer written in hex, with or without a `0x`
+/// prefix.
+fn parse_hex_biguint(s: &str) -> Result<BigUint, String> {
+    let digits = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")).unwrap_or(s);
+    BigUint::parse_bytes(digits.as_bytes(), 16).ok_or_else(|| format!("invalid hex value `{}`", s))
+}
+
+fn main() {
+    let cli = Cli::parse();
+    match cli.command {
+        Some(Command::Verify(args)) => verify(args),
+        Some(Command::Hashcash(command)) => hashcash(command),
+        Some(Command::Equihash(command)) => equihash(command),
+        Some(Command::Cuckoo(command)) => cuckoo(command),
+        Some(Command::Timelock(command)) => timelock(command),
+        Some(Command::Vdf(command)) => vdf(command),
+        Some(Command::Challenge(command)) => challenge(command),
+        Some(Command::Chain(args)) => chain(args),
+        Some(Command::Keygen { prefix, encoding, threads }) => keygen(&prefix, encoding, threads),
+        Some(Command::Calibrate { time, algo, threads, rates }) => {
+            calibrate(time, algo, threads, rates.as_deref())
+        }
+        Some(Command::Bench { time, threads, algo, save }) => bench(time, threads, algo, save),
+        Some(Command::Generate(args)) => generate(args),
+        Some(Command::Convert(args)) => convert(args),
+        Some(Command::Serve(args)) => serve(*args),
+        Some(Command::Work { server, discover: _, user, password, threads, poll, tls }) => {
+            let server = server.unwrap_or_else(discover_server);
+            if server.starts_with("stratum+tcp://") {
+                work_stratum(&server, &user, &password, threads)
+            } else {
+                work(&server, &tls, threads, poll)
+            }
+        }
+        Some(Command::Gossip(args)) => gossip(*args),
+        Some(Command::Ingest(args)) => ingest(args),
+        Some(Command::Lookup { archive, puzzle }) => lookup(&archive, &puzzle),
+        #[cfg(unix)]
+        Some(Command::Daemon { socket, threads }) => daemon(&socket, threads),
+        None => mine(cli.puzzle, cli.mine),
+    }
+}
+
+/// Writes `line` to a locked stdout. Once the reader has gone away there is nobody left to
+/// tell anything, so the program exits as it would have anyway, successfully; any other
+/// error ends it with one.
+fn write_line(line: fmt::Arguments<'_>) {
+    let mut stdout = io::stdout().lock();
+    if let Err(e) = writeln!(stdout, "{}", line) {
+        if e.kind() == ErrorKind::BrokenPipe {
+            std::process::exit(0);
+        }
+        eprintln!("error: cannot write the output: {}", e);
+        std::process::exit(1);
+    }
+}
+
+/// Re-hashes a single nonce and reports whether it solves the puzzle.
+fn verify(args: VerifyArgs) {
+    let target = args.puzzle.difficulty().to_target();
+    outln!("Target: {}", target);
+
+    let verification = if let Some(path) = &args.puzzle.data_file {
+        let reader = PuzzleArgs::open_data_file(path);
+        let result = args.puzzle.algo.verify_reader(
+            reader,
+            target,
+            args.puzzle.nonce_encoding,
+            args.nonce,
+        );
+        result.unwrap_or_else(|e| {
+            eprintln!("error: cannot read {}: {}", path.display(), e);
+            std::process::exit(2);
+        })
+    } else if let Some(function) = args.puzzle.memory_hard() {
+        let puzzle = args.puzzle.memory_hard_puzzle(target, function);
+        let hash = puzzle.hash(args.nonce);
+        Verification { valid: target.is_met_by(&hash), hash }
+    } else {
+        args.puzzle.algo.verify(&args.puzzle.puzzle(target), args.nonce)
+    };
+
+    outln!("Hash: {}", hex::encode(&verification.hash));
+    if verification.valid {
+        outln!("Valid: nonce {} solves the puzzle", args.nonce);
+    } else {
+        outln!("Invalid: nonce {} does not meet the target", args.nonce);
+        std::process::exit(1);
+    }
+}
+
+/// Searches for a vanity ed25519 keypair and prints it.
+fn keygen(prefix: &str, encoding: KeyEncoding, threads: Option<usize>) {
+    let prefix = KeyPrefix::new(prefix, encoding).unwrap_or_else(|e| {
+        eprintln!("error: invalid --prefix: {}", e);
+        std::process::exit(2);
+    });
+    outln!("Prefix: {}", prefix);
+
+    match search_keypair(&prefix, threads.unwrap_or_else(num_cpus::get)) {
+        Ok(keypair) => {
+            outln!("Public key: {}", keypair.address(encoding));
+            outln!("Secret key: {}", hex::encode(&keypair.secret_key));
+        }
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Solves or checks an Equihash puzzle.
+fn equihash(command: EquihashCommand) {
+    match command {
+        EquihashCommand::Solve { puzzle, start_nonce, threads } => {
+            let puzzle = puzzle.puzzle();
+            let threads = threads.unwrap_or_else(num_cpus::get);
+            match puzzle.solve_first(start_nonce..=u64::MAX, threads) {
+                Ok(solution) => {
+                    outln!("Found nonce: {}", solution.nonce);
+                    let indices: Vec<_> = solution.indices.iter().map(u32::to_string).collect();
+                    outln!("Indices: {}", indices.join(","));
+                }
+                Err(e) => {
+                    eprintln!("error: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        EquihashCommand::Verify { puzzle, nonce, indices } => {
+            if puzzle.puzzle().verify(nonce, &indices) {
+                outln!("Valid: the indices solve the puzzle for nonce {}", nonce);
+            } else {
+                outln!("Invalid: the indices do not solve the puzzle for nonce {}", nonce);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+/// Solves or checks a Cuckoo Cycle puzzle.
+fn cuckoo(command: CuckooCommand) {
+    match command {
+        CuckooCommand::Solve { puzzle, start_nonce, threads } => {
+            let puzzle = puzzle.puzzle();
+            let threads = threads.unwrap_or_else(num_cpus::get);
+            match puzzle.solve_first(start_nonce..=u64::MAX, threads) {
+                Ok(solution) => {
+                    outln!("Found nonce: {}", solution.nonce);
+                    let edges: Vec<_> = solution.edges.iter().map(u32::to_string).collect();
+                    outln!("Edges: {}", edges.join(","));
+                }
+                Err(e) => {
+                    eprintln!("error: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        CuckooCommand::Verify { puzzle, nonce, edges } => {
+            if puzzle.puzzle().verify(nonce, &edges) {
+                outln!("Valid: the edges form a cycle for nonce {}", nonce);
+            } else {
+                outln!("Invalid: the edges do not form a cycle for nonce {}", nonce);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+/// Generates, solves or checks a time-lock puzzle.
+fn timelock(command: TimelockCommand) {
+    match command {
+        TimelockCommand::Generate { bits, squarings } => {
+            if bits < 16 {
+                eprintln!("error: --bits must be at least 16");
+                std::process::exit(2);
+            }
+            let (puzzle, trapdoor) = TimeLockPuzzle::generate(bits, squarings);
+            outln!("Modulus: {:x}", puzzle.modulus);
+            outln!("Base: {:x}", puzzle.base);
+            outln!("Squarings: {}", puzzle.squarings);
+            outln!("P: {:x}", trapdoor.p);
+            outln!("Q: {:x}", trapdoor.q);
+            outln!("Answer: {:x}", trapdoor.solve(&puzzle));
+        }
+        TimelockCommand::Solve { modulus, puzzle } => {
+            let puzzle =
+                TimeLockPuzzle { modulus, base: puzzle.base, squarings: puzzle.squarings };
+            outln!("Answer: {:x}", puzzle.evaluate());
+        }
+        TimelockCommand::Verify { p, q, puzzle, answer } => {
+            let trapdoor = TimeLockTrapdoor { p, q };
+            let puzzle = TimeLockPuzzle {
+                modulus: trapdoor.modulus(),
+                base: puzzle.base,
+                squarings: puzzle.squarings,
+            };
+            if trapdoor.verify(&puzzle, &answer) {
+                outln!("Valid: the answer solves the puzzle");
+            } else {
+                outln!("Invalid: the answer does not solve the puzzle");
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+/// Sets up, evaluates or checks a verifiable delay function.
+fn vdf(command: VdfCommand) {
+    match command {
+        VdfCommand::Setup { bits } => {
+            if bits < 16 {
+                eprintln!("error: --bits must be at least 16");
+                std::process::exit(2);
+            }
+            outln!("Modulus: {:x}", Vdf::setup(bits, 0).modulus);
+        }
+        VdfCommand::Eval { vdf } => {
+            let (vdf, data) = vdf.vdf();
+            if vdf.modulus < BigUint::from(2u32) {
+                eprintln!("error: --modulus must be at least 2");
+                std::process::exit(2);
+            }
+            let proof = vdf.evaluate(&data);
+            outln!("Output: {:x}", proof.output);
+            outln!("Proof: {:x}", proof.proof);
+        }
+        VdfCommand::Verify { vdf, output, proof } => {
+            let (vdf, data) = vdf.vdf();
+            if vdf.verify(&data, &VdfProof { output, proof }) {
+                outln!("Valid: the proof shows the output is correct");
+            } else {
+                outln!("Invalid: the proof does not match the output");
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+/// Issues, solves or checks a client puzzle.
+fn challenge(command: ChallengeCommand) {
+    match command {
+        ChallengeCommand::Issue { key, client, bits } => {
+            // The time to live only matters when verifying.
+            let issuer = ChallengeIssuer::new(key, bits, Duration::ZERO);
+            outln!("Challenge: {}", issuer.issue(client));
+        }
+        ChallengeCommand::Solve { challenge, threads } => {
+            let threads = threads.unwrap_or_else(num_cpus::get);
+            match challenge.solve(threads) {
+                Ok(nonce) => outln!("Found nonce: {}", nonce),
+                Err(e) => {
+                    eprintln!("error: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        ChallengeCommand::Verify { key, client, ttl, challenge, nonce } => {
+            let issuer = ChallengeIssuer::new(key, challenge.bits, Duration::from_secs(ttl));
+            match issuer.verify(client, &challenge, nonce) {
+                Ok(()) => outln!("Valid: nonce {} solves the challenge", nonce),
+                Err(e) => {
+                    outln!("Invalid: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+    }
+}
+
+/// Mines a chain of blocks and prints each one as it is found.
+fn chain(args: ChainArgs) {
+    let threads = args.threads.unwrap_or_else(num_cpus::get);
+    let initial_target = Difficulty::LeadingZeroBits(args.zero_bits).to_target();
+    let retarget = args
+        .retarget_interval
+        .zip(args.block_time)
+        .map(|(interval, block_time)| Retarget::new(interval, Duration::from_millis(block_time)));
+    let mut chain = Chain::new(args.algo);
+    let start = Instant::now();
+    for height in 0..args.blocks {
+        let target = match &retarget {
+            Some(retarget) => {
+                let target = chain.next_target(retarget, initial_target);
+                if let Some(tip) = chain.tip().filter(|tip| tip.target != target) {
+                    outln!("Retarget: {} -> {}", tip.target, target);
+                }
+                target
+            }
+            None => initial_target,
+        };
+        let result = match args.transactions {
+            Some(count) => {
+                let transactions: Vec<_> = (0..count)
+                    .map(|i| format!("{} block {} tx {}", args.data, height, i))
+                    .collect();
+                chain.mine_transactions(&transactions, target, threads)
+            }
+            None => chain.mine_block(args.data.as_bytes(), target, threads),
+        };
+        match result {
+            Ok(block) => {
+                outln!("Block {}", block.height);
+                if args.transactions.is_some() {
+                    outln!("  Root:  {}", hex::encode(&block.data));
+                }
+                outln!("  Prev:  {}", hex::encode(&block.prev_hash));
+                outln!("  Nonce: {}", block.nonce);
+                outln!("  Hash:  {}", hex::encode(&block.hash));
+            }
+            Err(e) => {
+                eprintln!("error: block {}: {}", chain.blocks().len(), e);
+                std::process::exit(1);
+            }
+        }
+    }
+    outln!("Mined {} block(s) in {:.2?}", chain.blocks().len(), start.elapsed());
+    if !chain.verify() {
+        eprintln!("error: the mined chain does not verify");
+        std::process::exit(1);
+    }
+}
+
+/// Mints or checks a Hashcash stamp.
+fn hashcash(command: HashcashCommand) {
+    match command {
+        HashcashCommand::Mint { resource, bits, date, threads } => {
+            let mut stamp = Stamp::new(resource, bits);
+            if let Some(date) = date {
+                stamp.date = date;
+            }
+            match stamp.mint(threads.unwrap_or_else(num_cpus::get)) {
+                Ok(stamp) => outln!("{}", stamp.header()),
+                Err(e @ MintError::Stamp(_)) => {
+                    eprintln!("error: {}", e);
+                    std::process::exit(2);
+                }
+                Err(e) => {
+                    eprintln!("error: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        HashcashCommand::Check { stamp } => {
+            outln!("Hash: {}", hex::encode(&stamp.hash()));
+            if stamp.is_valid() {
+                outln!("Valid: stamp has at least {} zero bits", stamp.bits);
+            } else {
+                outln!("Invalid: stamp has fewer than {} zero bits", stamp.bits);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+/// Searches for a nonce that solves the puzzle described on the command line.
+fn mine(args: PuzzleArgs, mut mine: MineArgs) {
+    if mine.list_devices {
+        list_devices();
+        return;
+    }
+    if mine.device.is_some() && !matches!(mine.backend, Backend::OpenCl | Backend::Cuda) {
+        eprintln!("error: --device only applies to --backend opencl or cuda");
+        std::process::exit(2);
+    }
+    if mine.hybrid && mine.backend == Backend::Cpu {
+        eprintln!("error: --hybrid needs --backend gpu, opencl or cuda");
+        std::process::exit(2);
+    }
+
+    // Open the archive first, so a search is not wasted on an archive that cannot be.
+    let archive = mine.archive.as_deref().map(open_archive);
+
+    // Create a puzzle with the requested difficulty and data.
     // Initially, the nonce is zero (unused) and will be incremented by the solver.
-    let puzzle = Puzzle {
-        difficulty: DIFFICULTY,
-        data: "Some data".to_string(),
-        nonce: 0,
+    let pinning = mine.pinning();
+    let rates = mine.rates.as_deref().map(load_rates);
+    let threads = mine.threads.unwrap_or_else(|| match &pinning {
+        Some(pinning) => pinning.cores().len(),
+        None => num_cpus::get(),
+    });
+    let target = match mine.solve_time {
+        Some(time) => {
+            let puzzle = args.puzzle(Target::MAX);
+            benchmark(args.algo, &puzzle, threads, time, rates.as_ref()).target
+        }
+        None => args.difficulty().to_target(),
     };
+    let default_range = default_nonce_range();
+    let nonces = mine.start_nonce.unwrap_or(*default_range.start())
+        ..=mine.end_nonce.unwrap_or(*default_range.end());
+    let vanity = mine.prefix.take().or(mine.ascii_prefix.take());
+    match &vanity {
+        Some(prefix) => outln!(
+            "Prefix: {} (about {:.0} hashes expected)",
+            prefix,
+            prefix.expected_attempts()
+        ),
+        None => outln!("Target: {}", target),
+    }
 
-    // Attempt to solve the puzzle in parallel, using multiple CPU cores.
-    let found_nonce = parallel_mine(&puzzle);
+    // Attempt to solve the puzzle in parallel, using the requested number of threads.
+    // Only searches on a `MiningHandle` keep statistics to report.
+    let (result, report) = if let Some(result) =
+        mine_on_device(&args, &mine, vanity.is_some(), target, nonces.clone())
+    {
+        (result, None)
+    } else if let Some(prefix) = vanity {
+        let puzzle = args.puzzle(target);
+        let predicate = move |hash: &[u8]| prefix.matches(hash);
+        (args.algo.parallel_mine_matching(&puzzle, nonces, threads, predicate), None)
+    } else if let Some(function) = args.memory_hard() {
+        let puzzle = args.memory_hard_puzzle(target, function);
+        let threads = match (mine.memory_budget, mine.threads) {
+            (Some(mib), _) => {
+                puzzle.threads_within_budget(threads, mib.saturating_mul(1024 * 1024))
+            }
+            (None, Some(threads)) => threads,
+            (None, None) => puzzle.default_threads(),
+        };
+        outln!(
+            "Memory-hard: {} thread(s) x {} KiB{}",
+            threads,
+            function.memory_bytes() / 1024,
+            if puzzle.huge_pages() { " on huge pages" } else { "" }
+        );
+        let result = match &pinning {
+            Some(pinning) => {
+                outln!("Pinned to cores: {}", format_cores(&pinning.cores()));
+                puzzle.parallel_mine_pinned(nonces, threads, pinning)
+            }
+            None => puzzle.parallel_mine_range(nonces, threads),
+        };
+        (result, None)
+    } else if let Some(path) = &args.data_file {
+        // Stream the payload straight into the hasher instead of loading it.
+        let reader = PuzzleArgs::open_data_file(path);
+        let result =
+            args.algo.parallel_mine_reader(reader, target, args.nonce_encoding, nonces, threads);
+        let result = result.unwrap_or_else(|e| {
+            eprintln!("error: cannot read {}: {}", path.display(), e);
+            std::process::exit(2);
+        });
+        (result, None)
+    } else if mine.all || mine.limit.is_some() {
+        // Stream every solution as soon as a worker reports it.
+        let puzzle = args.puzzle(target);
+        let mut count = 0usize;
+        for solution in args.algo.find_solutions(&puzzle, nonces, threads, mine.limit) {
+            count += 1;
+            outln!("Found nonce: {} hash: {}", solution.nonce, hex::encode(&solution.hash));
+        }
+        outln!("{} solution(s) found", count);
+        return;
+    } else {
+        let puzzle = args.puzzle(target);
+        if target != Target::ZERO && target.expected_attempts() >= LONG_JOB_ATTEMPTS {
+            let threads = if mine.resume.is_some() { num_cpus::get() } else { threads };
+            print_estimate(args.algo, &puzzle, threads, rates.as_ref());
+        }
+        let (result, report) = mine_in_background(args.algo, &puzzle, nonces, threads, &mine);
+        (result, Some(report))
+    };
 
-    // Print out the discovered nonce that solves the puzzle.
-    println!("Found nonce (multi-thread): {}", found_nonce);
+    match &result {
+        Ok(solution) => {
+            // Print out the discovered nonce that solves the puzzle.
+            outln!("Found nonce (multi-thread): {}", solution.nonce);
+            if let Some(extra_nonce) = solution.extra_nonce {
+                outln!("Extra nonce: {}", extra_nonce);
+            }
+            outln!("Hash: {}", hex::encode(&solution.hash));
+            if let Some(archive) = &archive {
+                archive_solution(archive.as_ref(), args.puzzle(target), args.algo, solution);
+            }
+        }
+        Err(e) => eprintln!("error: {}", e),
+    }
+    if let Some(report) = report {
+        print_report(&report);
+    }
+    if result.is_err() {
+        std::process::exit(1);
+    }
 }
 
-/// Validates whether a given nonce produces a hash below the puzzle difficulty.
-///
-/// # Parameters
-///
-/// - `puzzle`: The puzzle definition containing the difficulty and data.
-/// - `nonce`: The nonce to test against the puzzle data.
-///
-/// # Returns
-///
-/// `true` if the resulting hash (first two bytes interpreted as a `u16`) is below the difficulty threshold;
-/// otherwise, `false`.
-fn validate(puzzle: &Puzzle, nonce: u64) -> bool {
-    let mut hasher = Sha256::new();
-    hasher.update(puzzle.data.as_bytes());
-    hasher.update(&nonce.to_be_bytes());
-    let result = hasher.finalize();
+/// Mines the puzzle on the GPU, an OpenCL device or a CUDA GPU if `--backend` asks for it,
+/// or returns `None` to mine on the CPU instead, after saying why if another backend was
+/// asked for.
+fn mine_on_device(
+    args: &PuzzleArgs,
+    mine: &MineArgs,
+    vanity: bool,
+    target: Target,
+    nonces: std::ops::RangeInclusive<u64>,
+) -> Option<Result<Solution, PuzzleError>> {
+    if mine.backend == Backend::Cpu {
+        return None;
+    }
+    let unsupported = if args.algo != Algorithm::Sha256 || args.memory_hard().is_some() {
+        Some("it only hashes SHA-256")
+    } else if vanity || mine.all || mine.limit.is_some() || args.data_file.is_some() {
+        Some("it only finds the first solution to a target from in-memory data")
+    } else if mine.timeout.is_some() || mine.progress || mine.order().is_some() {
+        Some("it cannot time out, report progress or reorder the nonces")
+    } else if mine.pinning().is_some() {
+        Some("it cannot pin threads to cores")
+    } else if mine.priority != WorkerPriority::Normal || mine.max_cpu.is_some() {
+        Some("it cannot lower the priority of its threads or limit their CPU use")
+    } else if mine.max_temp.is_some() {
+        Some("it cannot slow down when the CPU runs hot")
+    } else if mine.checkpoint.is_some() || mine.resume.is_some() {
+        Some("it cannot save or resume checkpoints")
+    } else {
+        None
+    };
+    if let Some(reason) = unsupported {
+        eprintln!(
+            "warning: mining on the CPU, as the {} backend cannot run this search: {}",
+            mine.backend, reason
+        );
+        return None;
+    }
+    let hybrid = mine.hybrid.then(|| mine.threads.unwrap_or_else(num_cpus::get));
+    match mine.backend {
+        Backend::Cpu => None,
+        Backend::Gpu => mine_on_gpu(&args.puzzle(target), nonces, hybrid),
+        Backend::OpenCl => mine_on_opencl(mine.device, &args.puzzle(target), nonces, hybrid),
+        Backend::Cuda => mine_on_cuda(mine.device, &args.puzzle(target), nonces, hybrid),
+    }
+}
 
-    // Convert the first two bytes of the SHA-256 hash into a u16.
-    // This drastically simplifies the puzzle complexity.
-    let result_val = u16::from_be_bytes([result[0], result[1]]);
-    result_val < puzzle.difficulty as u16
+/// Mines `puzzle` on `device`, the `name` backend, alongside `hybrid` CPU threads with
+/// `--hybrid`, or returns `None` after saying why the device failed.
+#[cfg(any(feature = "gpu", feature = "opencl", feature = "cuda"))]
+fn mine_on<A: Accelerator>(
+    name: &str,
+    device: &A,
+    puzzle: &Puzzle,
+    nonces: std::ops::RangeInclusive<u64>,
+    hybrid: Option<usize>,
+) -> Option<Result<Solution, PuzzleError>> {
+    let result = match hybrid {
+        Some(threads) => parallel_mine_hybrid(puzzle, nonces, threads, device).map(|found| {
+            outln!(
+                "Hybrid: CPU at {} ({:.1}% of the nonces), {} at {}",
+                format_rate(found.cpu_rate),
+                found.cpu_share() * 100.0,
+                name,
+                format_rate(found.device_rate)
+            );
+            outln!("Found by: {}", if found.found_by_device { name } else { "CPU" });
+            found.solution
+        }),
+        None => device.mine_cancellable(puzzle, nonces, &CancellationToken::new()).map_err(|e| {
+            A::into_search_error(e).map_or_else(HybridError::Device, HybridError::Search)
+        }),
+    };
+    match result {
+        Ok(solution) => Some(Ok(solution)),
+        Err(HybridError::Search(e)) => Some(Err(e)),
+        Err(HybridError::Device(e)) => {
+            eprintln!("warning: mining on the CPU, as the {} backend failed: {}", name, e);
+            None
+        }
+    }
 }
 
-/// Attempts to solve the given puzzle by splitting the search range across multiple CPU cores.
-///
-/// # Parameters
-///
-/// - `puzzle`: The puzzle containing difficulty and data. The nonce is initially unused.
-///
-/// # Returns
-///
-/// The nonce that solves the puzzle, or `u64::MAX` if no solution is found (which is extremely unlikely if given enough range).
-///
-/// # Details
-///
-/// This function:
-/// 1. Determines the number of CPU cores.
-/// 2. Splits a large range of possible nonces (0 to `max_nonce`) evenly among all threads.
-/// 3. Each thread searches its assigned range, validating each nonce until it either finds a valid solution or is notified that another thread found one.
-/// 4. Uses an atomic flag `found_flag` to let other threads stop working as soon as a solution is found.
-/// 5. Uses a `Mutex<Option<u64>>` to safely store the discovered solution nonce.
-fn parallel_mine(puzzle: &Puzzle) -> u64 {
-    // Clone the puzzle so it can be shared with multiple threads.
-    let puzzle = puzzle.clone();
-    let num_cores = num_cpus::get();
-    
-    // Define a maximum nonce search space.
-    // In a real-world scenario, you might want to run indefinitely or use a dynamic approach.
-    let max_nonce: u64 = u64::MAX / (num_cores as u64);
-    let range_per_thread = max_nonce / num_cores as u64;
-
-    // An atomic flag to signal that a solution has been found.
-    let found_flag = Arc::new(AtomicBool::new(false));
-    // A mutex-protected optional solution. When a thread finds a solution, it sets this.
-    let solution = Arc::new(Mutex::new(None));
-
-    let mut handles = Vec::with_capacity(num_cores);
-
-    for i in 0..num_cores {
-        let puzzle_clone = puzzle.clone();
-        let found_flag_clone = Arc::clone(&found_flag);
-        let solution_clone = Arc::clone(&solution);
-        
-        // Determine the range of nonces for this thread.
-        let start = i as u64 * range_per_thread;
-        let end = if i == num_cores - 1 {
-            max_nonce
-        } else {
-            (i as u64 + 1) * range_per_thread
+/// Mines `puzzle` on the GPU, with `hybrid` CPU threads alongside it if given, or returns
+/// `None` after saying why it cannot.
+fn mine_on_gpu(
+    puzzle: &Puzzle,
+    nonces: std::ops::RangeInclusive<u64>,
+    hybrid: Option<usize>,
+) -> Option<Result<Solution, PuzzleError>> {
+    #[cfg(feature = "gpu")]
+    {
+        match GpuMiner::new() {
+            Ok(gpu) => {
+                outln!("GPU: {}", gpu.adapter_name());
+                mine_on("GPU", &gpu, puzzle, nonces, hybrid)
+            }
+            Err(e) => {
+                eprintln!("warning: mining on the CPU, as the GPU backend failed: {}", e);
+                None
+            }
+        }
+    }
+    #[cfg(not(feature = "gpu"))]
+    {
+        let _ = (puzzle, nonces, hybrid);
+        eprintln!("warning: mining on the CPU, as this build has no GPU backend (feature `gpu`)");
+        None
+    }
+}
+
+/// Mines `puzzle` on the OpenCL device at `device`, or on the first GPU or accelerator,
+/// with `hybrid` CPU threads alongside it if given, or returns `None` after saying why it
+/// cannot.
+fn mine_on_opencl(
+    device: Option<usize>,
+    puzzle: &Puzzle,
+    nonces: std::ops::RangeInclusive<u64>,
+    hybrid: Option<usize>,
+) -> Option<Result<Solution, PuzzleError>> {
+    #[cfg(feature = "opencl")]
+    {
+        let miner = match device {
+            Some(index) => OpenClMiner::with_device(index),
+            None => OpenClMiner::new(),
+        };
+        match miner {
+            Ok(miner) => {
+                outln!("OpenCL device: {}", miner.device());
+                mine_on("OpenCL", &miner, puzzle, nonces, hybrid)
+            }
+            Err(e) => {
+                eprintln!("warning: mining on the CPU, as the OpenCL backend failed: {}", e);
+                None
+            }
+        }
+    }
+    #[cfg(not(feature = "opencl"))]
+    {
+        let _ = (device, puzzle, nonces, hybrid);
+        eprintln!(
+            "warning: mining on the CPU, as this build has no OpenCL backend (feature `opencl`)"
+        );
+        None
+    }
+}
+
+/// Mines `puzzle` on the CUDA GPU at `device`, or on the first one, with `hybrid` CPU
+/// threads alongside it if given, or returns `None` after saying why it cannot.
+fn mine_on_cuda(
+    device: Option<usize>,
+    puzzle: &Puzzle,
+    nonces: std::ops::RangeInclusive<u64>,
+    hybrid: Option<usize>,
+) -> Option<Result<Solution, PuzzleError>> {
+    #[cfg(feature = "cuda")]
+    {
+        match CudaMiner::with_device(device.unwrap_or(0)) {
+            Ok(miner) => {
+                outln!("CUDA device: {}", miner.device());
+                mine_on("CUDA", &miner, puzzle, nonces, hybrid)
+            }
+            Err(e) => {
+                eprintln!("warning: mining on the CPU, as the CUDA backend failed: {}", e);
+                None
+            }
+        }
+    }
+    #[cfg(not(feature = "cuda"))]
+    {
+        let _ = (device, puzzle, nonces, hybrid);
+        eprintln!("warning: mining on the CPU, as this build has no CUDA backend (feature `cuda`)");
+        None
+    }
+}
+
+/// Prints the OpenCL and CUDA devices `--device` can pick from, one per line under the
+/// backend they belong to.
+fn list_devices() {
+    #[cfg(not(any(feature = "opencl", feature = "cuda")))]
+    {
+        eprintln!("error: this build has no OpenCL or CUDA backend (features `opencl`, `cuda`)");
+        std::process::exit(2);
+    }
+    #[cfg(feature = "opencl")]
+    {
+        outln!("OpenCL (--backend opencl):");
+        match opencl::devices() {
+            Ok(devices) if devices.is_empty() => outln!("  no devices found"),
+            Ok(devices) => devices.iter().for_each(|device| outln!("  {}", device)),
+            Err(e) => outln!("  {}", e),
+        }
+    }
+    #[cfg(feature = "cuda")]
+    {
+        outln!("CUDA (--backend cuda):");
+        match cuda::devices() {
+            Ok(devices) if devices.is_empty() => outln!("  no devices found"),
+            Ok(devices) => devices.iter().for_each(|device| outln!("  {}", device)),
+            Err(e) => outln!("  {}", e),
+        }
+    }
+}
+
+/// Searches expected to take at least this many hashes get a time estimate first.
+const LONG_JOB_ATTEMPTS: f64 = 1e8;
+
+/// How long `calibrate` and `--solve-time` measure the hash rate for.
+const CALIBRATION_TIME: Duration = Duration::from_secs(1);
+
+/// Prints the difficulty that takes `time` on average on this machine, with the
+/// nearest `--zero-bits` and `--nbits` equivalents.
+fn calibrate(time: Duration, algo: Algorithm, threads: Option<usize>, rates: Option<&Path>) {
+    let threads = threads.unwrap_or_else(num_cpus::get);
+    let rates = rates.map(load_rates);
+    let puzzle = Puzzle::new("calibration", Difficulty::Target(Target::MAX));
+    let calibration = benchmark(algo, &puzzle, threads, time, rates.as_ref());
+    let attempts = calibration.estimate().expected_attempts();
+    outln!("Target: {}", calibration.target);
+    outln!("Expected work: {:.0} hashes", attempts);
+    let bits = attempts.log2().round().clamp(0.0, 255.0) as u32;
+    let bits_time = WorkEstimate::new(Target::from_leading_zero_bits(bits), calibration.hash_rate)
+        .expected_time();
+    outln!("Nearest --zero-bits: {} (about {} on average)", bits, format_duration(bits_time));
+    outln!("Compact form: --nbits {:#010x}", calibration.target.to_compact());
+}
+
+/// Serves puzzles on a Unix socket at `socket` until a client asks the daemon to shut down.
+#[cfg(unix)]
+fn daemon(socket: &Path, threads: Option<usize>) {
+    let threads = threads.unwrap_or_else(num_cpus::get);
+    let daemon = Daemon::bind(socket, threads).unwrap_or_else(|e| {
+        eprintln!("error: cannot listen on {}: {}", socket.display(), e);
+        std::process::exit(1);
+    });
+    outln!("Listening on {} with {} threads", socket.display(), daemon.num_threads());
+    let result = daemon.run();
+    // Dropping the daemon removes the socket file, which exiting would skip.
+    drop(daemon);
+    if let Err(e) = result {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+    outln!("Shut down");
+}
+
+/// Leases the nonce window of the puzzle to workers over HTTP until it is solved or
+/// exhausted, or a client asks the server to shut down.
+fn serve(args: ServeArgs) {
+    if args.puzzle.data_file.is_some() || args.puzzle.memory_hard().is_some() {
+        eprintln!("error: serve only leases puzzles over --data or --data-hex with --algo");
+        std::process::exit(2);
+    }
+    if args.grpc_listen.is_some() && cfg!(not(feature = "grpc")) {
+        eprintln!("error: this build has no gRPC service (feature `grpc`)");
+        std::process::exit(2);
+    }
+    if args.tls_cert.is_some() && cfg!(not(feature = "tls")) {
+        eprintln!("error: this build has no TLS (feature `tls`)");
+        std::process::exit(2);
+    }
+    if args.advertise && cfg!(not(feature = "mdns")) {
+        eprintln!("error: this build has no mDNS discovery (feature `mdns`)");
+        std::process::exit(2);
+    }
+    if args.redis.is_some() && cfg!(not(feature = "redis")) {
+        eprintln!("error: this build has no Redis queue (feature `redis`)");
+        std::process::exit(2);
+    }
+    let archive = args.archive.as_deref().map(open_archive);
+    let target = args.puzzle.difficulty().to_target();
+    let server = WorkServer::bind(&args.listen).unwrap_or_else(|e| {
+        eprintln!("error: cannot listen on {}: {}", args.listen, e);
+        std::process::exit(1);
+    });
+    let mut server = server
+        .with_lease_size(args.lease_size)
+        .with_idle_timeout(args.idle_timeout)
+        .with_lease_timeout(args.lease_timeout)
+        .with_max_invalid_rate(args.max_invalid_rate);
+    if let Some(bits) = args.share_zero_bits {
+        server = server.with_share_target(Target::from_leading_zero_bits(bits));
+    }
+    if let Some(token) = &args.admin_token {
+        server = server.with_admin_token(token);
+    }
+    #[cfg(feature = "tls")]
+    if let (Some(cert), Some(key)) = (&args.tls_cert, &args.tls_key) {
+        let client_ca = args.tls_client_ca.as_deref().map(read_pem);
+        let tls = ServerTls::new(&read_pem(cert), &read_pem(key), client_ca.as_deref())
+            .unwrap_or_else(|e| {
+                eprintln!("error: {}", e);
+                std::process::exit(1);
+            });
+        server = server.with_tls(tls);
+    }
+    #[cfg(feature = "redis")]
+    if let Some(url) = &args.redis {
+        let queue = RedisQueue::connect(url, &args.redis_key).unwrap_or_else(|e| {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        });
+        server = server.with_redis(queue);
+    }
+    let default_range = default_nonce_range();
+    let nonces = args.start_nonce.unwrap_or(*default_range.start())
+        ..=args.end_nonce.unwrap_or(*default_range.end());
+    let puzzle = args.puzzle.puzzle(target);
+    // A restarted server, or a replica on the same queue, carries on with the puzzle.
+    let id = match server.find_puzzle(&puzzle, args.puzzle.algo, &nonces) {
+        Ok(Some(id)) => {
+            outln!("Carrying on with puzzle {} from the queue", id);
+            Ok(id)
+        }
+        Ok(None) => server.add_puzzle(puzzle, args.puzzle.algo, nonces),
+        Err(e) => Err(e),
+    };
+    let id = id.unwrap_or_else(|e| {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    });
+    outln!("Target: {}", target);
+    let addr = server.local_addr().map_or(args.listen.clone(), |addr| addr.to_string());
+    let scheme = if args.tls_cert.is_some() { "https" } else { "http" };
+    outln!(
+        "Listening on {}://{}, leasing {} nonces at a time",
+        scheme,
+        addr,
+        server.lease_size()
+    );
+    if let Some(grpc_addr) = args.grpc_listen {
+        outln!("Serving gRPC on {}", grpc_addr);
+    }
+    if let Some(stratum_addr) = args.stratum_listen {
+        outln!("Serving Stratum on stratum+tcp://{}", stratum_addr);
+    }
+    #[cfg(feature = "mdns")]
+    let _advertisement = args.advertise.then(|| {
+        let advertisement = server.advertise().unwrap_or_else(|e| {
+            eprintln!("error: cannot announce the coordinator: {}", e);
+            std::process::exit(1);
+        });
+        outln!("Announcing on the local network as {}", advertisement.name());
+        advertisement
+    });
+
+    let server = &server;
+    let (result, served, grpc, stratum) = std::thread::scope(|scope| {
+        let served = scope.spawn(|| server.run());
+        let grpc = args.grpc_listen.map(|addr| scope.spawn(move || serve_grpc(server, addr)));
+        let stratum = args.stratum_listen.map(|addr| scope.spawn(move || server.run_stratum(addr)));
+        let result = server.wait(id);
+        server.shutdown();
+        (
+            result,
+            served.join().unwrap(),
+            grpc.map(|grpc| grpc.join().unwrap()),
+            stratum.map(|stratum| stratum.join().unwrap()),
+        )
+    });
+    if let Err(e) = served {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+    if let Some(Err(e)) = grpc {
+        eprintln!("error: cannot serve gRPC on {}: {}", args.grpc_listen.unwrap(), e);
+        std::process::exit(1);
+    }
+    if let Some(Err(e)) = stratum {
+        eprintln!("error: cannot serve Stratum on {}: {}", args.stratum_listen.unwrap(), e);
+        std::process::exit(1);
+    }
+    let workers = server.workers().unwrap_or_else(|e| {
+        eprintln!("warning: cannot report on the workers: {}", e);
+        Vec::new()
+    });
+    for worker in workers {
+        if args.share_zero_bits.is_some() {
+            let idle = if worker.idle { ", idle" } else { "" };
+            outln!(
+                "Worker {}: {} shares, ~{:.0} hashes{}",
+                worker.worker, worker.shares, worker.work, idle
+            );
+        }
+        if worker.flagged {
+            eprintln!(
+                "warning: worker {} is flagged: {} of the nonces it sent ({:.0}%) were invalid",
+                worker.worker,
+                worker.invalid,
+                worker.invalid_rate() * 100.0
+            );
+        }
+    }
+    match result {
+        Ok(solution) => {
+            outln!("Found nonce (cluster): {}", solution.nonce);
+            if let Some(extra_nonce) = solution.extra_nonce {
+                outln!("Extra nonce: {}", extra_nonce);
+            }
+            outln!("Hash: {}", hex::encode(&solution.hash));
+            if let Some(archive) = &archive {
+                let puzzle = args.puzzle.puzzle(target);
+                archive_solution(archive.as_ref(), puzzle, args.puzzle.algo, &solution);
+            }
+        }
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Opens the archive at `location`: an S3 bucket for an `s3://bucket/prefix` URL, and a
+/// directory otherwise. The bucket is signed for with the keys in `AWS_ACCESS_KEY_ID` and
+/// `AWS_SECRET_ACCESS_KEY`, in `AWS_REGION` (`us-east-1` by default), and reached at
+/// `AWS_ENDPOINT_URL`, such as a MinIO server, or else Amazon's endpoint for the region.
+fn open_archive(location: &str) -> Box<dyn ResultStore> {
+    let Some(path) = location.strip_prefix("s3://") else {
+        let store = FileStore::open(location).unwrap_or_else(|e| {
+            eprintln!("error: cannot open the archive {}: {}", location, e);
+            std::process::exit(1);
+        });
+        return Box::new(store);
+    };
+    let (bucket, prefix) = path.split_once('/').unwrap_or((path, ""));
+    let credentials = S3Credentials::from_env().unwrap_or_else(|| {
+        eprintln!("error: set AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY to archive in S3");
+        std::process::exit(2);
+    });
+    let region = std::env::var("AWS_REGION").unwrap_or_else(|_| "us-east-1".to_string());
+    let endpoint = std::env::var("AWS_ENDPOINT_URL")
+        .unwrap_or_else(|_| format!("https://s3.{}.amazonaws.com", region));
+    let store = S3Store::new(&endpoint, bucket, &region, credentials).unwrap_or_else(|e| {
+        eprintln!("error: cannot open the archive {}: {}", location, e);
+        std::process::exit(2);
+    });
+    Box::new(store.with_prefix(prefix))
+}
+
+/// Archives `solution` of `puzzle`, mined with `algorithm`, in `archive`.
+fn archive_solution(
+    archive: &dyn ResultStore,
+    puzzle: Puzzle,
+    algorithm: Algorithm,
+    solution: &Solution,
+) {
+    let record = ArchivedSolution::new(puzzle, algorithm, solution.clone());
+    match archive.save(&record) {
+        Ok(true) => outln!("Archived as puzzle {}", record.puzzle_id()),
+        Ok(false) => {
+            outln!("Puzzle {} is archived already; kept its solution", record.puzzle_id())
+        }
+        Err(e) => {
+            eprintln!("error: cannot archive the solution: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Prints the solution of `puzzle_id` kept in the archive at `location` and checks it
+/// again.
+fn lookup(location: &str, puzzle_id: &str) {
+    let record = match open_archive(location).get(puzzle_id) {
+        Ok(Some(record)) => record,
+        Ok(None) => {
+            eprintln!("error: puzzle {} is not in the archive", puzzle_id);
+            std::process::exit(1);
+        }
+        Err(e @ StoreError::InvalidId(_)) => {
+            eprintln!("error: {}", e);
+            std::process::exit(2);
+        }
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let (puzzle, solution) = (&record.puzzle, &record.solution);
+    outln!("Algorithm: {}", record.algorithm);
+    outln!("Data: {}", hex::encode(&puzzle.data));
+    outln!("Target: {}", puzzle.target);
+    outln!("Nonce: {}", solution.nonce);
+    if let Some(extra_nonce) = solution.extra_nonce {
+        outln!("Extra nonce: {}", extra_nonce);
+    }
+    outln!("Hash: {}", hex::encode(&solution.hash));
+    outln!("Solved at: {} (Unix time)", record.solved_at);
+    if record.verify() {
+        outln!("Valid: nonce {} solves the puzzle", solution.nonce);
+    } else {
+        outln!("Invalid: nonce {} does not solve the puzzle", solution.nonce);
+        std::process::exit(1);
+    }
+}
+
+/// Joins the gossip network, publishing the puzzle first with `--publish`, and mines what
+/// any node publishes: with `--publish` until that puzzle is solved or exhausted, and
+/// otherwise until stopped.
+fn gossip(args: GossipArgs) {
+    if args.publish && (args.puzzle.data_file.is_some() || args.puzzle.memory_hard().is_some())
+    {
+        eprintln!("error: gossip only publishes puzzles over --data or --data-hex with --algo");
+        std::process::exit(2);
+    }
+    #[cfg(feature = "gossip")]
+    {
+        gossip_node(args)
+    }
+    #[cfg(not(feature = "gossip"))]
+    {
+        eprintln!("error: this build has no gossip mode (feature `gossip`)");
+        std::process::exit(2);
+    }
+}
+
+/// Runs `gossip` on a node of this build's gossip network.
+#[cfg(feature = "gossip")]
+fn gossip_node(args: GossipArgs) {
+    let threads = args.threads.unwrap_or_else(num_cpus::get);
+    let peers: Vec<&str> = args.peers.iter().map(String::as_str).collect();
+    let node = GossipNode::start(&args.listen, &peers).unwrap_or_else(|e| {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    });
+    let node = node.with_claim_size(args.claim_size).with_claim_timeout(args.claim_timeout);
+    outln!("Listening on {} ({} threads)", node.listen_addr(), threads);
+    let published = args.publish.then(|| {
+        let target = args.puzzle.difficulty().to_target();
+        let default_range = default_nonce_range();
+        let nonces = args.start_nonce.unwrap_or(*default_range.start())
+            ..=args.end_nonce.unwrap_or(*default_range.end());
+        let id = node.publish(args.puzzle.puzzle(target), args.puzzle.algo, nonces);
+        outln!("Target: {}", target);
+        outln!("Published puzzle {:016x}", id);
+        id
+    });
+
+    loop {
+        match published.and_then(|id| node.outcome(id)) {
+            Some(Ok(solution)) => {
+                outln!("Found nonce (network): {}", solution.nonce);
+                outln!("Hash: {}", hex::encode(&solution.hash));
+                return;
+            }
+            Some(Err(e)) => {
+                eprintln!("error: {}", e);
+                std::process::exit(1);
+            }
+            None => {}
+        }
+        // Waking now and then notices the published puzzle finished elsewhere.
+        let Some(claim) = node.claim(Duration::from_secs(1)) else {
+            continue;
         };
+        outln!(
+            "Claim: puzzle {:016x}, nonces {}..={}",
+            claim.puzzle_id,
+            claim.nonces.start(),
+            claim.nonces.end()
+        );
+        match node.mine(&claim, threads) {
+            Ok(Some(solution)) => {
+                outln!("Found nonce (puzzle {:016x}): {}", claim.puzzle_id, solution.nonce);
+                outln!("Hash: {}", hex::encode(&solution.hash));
+            }
+            Ok(None) => {}
+            Err(PuzzleError::Cancelled) => outln!(
+                "Claim on puzzle {:016x} dropped: another node's won, or the puzzle is finished",
+                claim.puzzle_id
+            ),
+            Err(e) => {
+                eprintln!("error: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+/// Serves the gRPC API of `server` on `addr` until the server shuts down.
+fn serve_grpc(server: &WorkServer, addr: SocketAddr) -> io::Result<()> {
+    #[cfg(feature = "grpc")]
+    {
+        server.run_grpc(addr)
+    }
+    #[cfg(not(feature = "grpc"))]
+    {
+        let _ = addr;
+        server.shutdown();
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "this build has no gRPC service (feature `grpc`)",
+        ))
+    }
+}
+
+/// Mines the jobs taken from NATS or Kafka, printing the result of each, until the broker
+/// closes the connection.
+fn ingest(args: IngestArgs) {
+    if !args.kafka.is_empty() && cfg!(not(feature = "kafka")) {
+        eprintln!("error: this build has no Kafka source (feature `kafka`)");
+        std::process::exit(2);
+    }
+    let threads = args.threads.unwrap_or_else(num_cpus::get);
+    let report = |result: &str| outln!("{}", result);
+    let result = match &args.nats {
+        Some(url) => {
+            let mut source = NatsSource::connect(url, &args.jobs, Some(&args.group), &args.results)
+                .unwrap_or_else(|e| {
+                    eprintln!("error: {}", e);
+                    std::process::exit(1);
+                });
+            outln!("Taking jobs from NATS subject {} ({} threads)", args.jobs, threads);
+            ingest::ingest(&mut source, threads, report)
+        }
+        #[cfg(feature = "kafka")]
+        None => {
+            let mut source =
+                KafkaSource::connect(&args.kafka, &args.jobs, &args.group, &args.results)
+                    .unwrap_or_else(|e| {
+                        eprintln!("error: {}", e);
+                        std::process::exit(1);
+                    });
+            outln!("Taking jobs from Kafka topic {} ({} threads)", args.jobs, threads);
+            ingest::ingest(&mut source, threads, report)
+        }
+        #[cfg(not(feature = "kafka"))]
+        None => unreachable!("--kafka needs the `kafka` feature"),
+    };
+    if let Err(e) = result {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+/// Leases ranges from the coordinator at `server`, mines them and reports back until the
+/// coordinator stops answering.
+fn work(server: &str, tls: &WorkTlsArgs, threads: Option<usize>, poll: Duration) {
+    let threads = threads.unwrap_or_else(num_cpus::get);
+    let client = connect_work_client(server, tls).unwrap_or_else(|e| {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    });
+    outln!("Registered with {} as worker {} ({} threads)", server, client.worker_id(), threads);
 
-        // Spawn a thread to handle its portion of the search space.
-        let handle = thread::spawn(move || {
-            for nonce in start..end {
-                // If a solution is already found, stop work.
-                if found_flag_clone.load(Ordering::Relaxed) {
-                    return;
+    // The events the coordinator pushes wake the loop when there is new work, and stop
+    // mining a puzzle finished elsewhere. Without them, it only polls.
+    let pushed = Arc::new(Mutex::new(Pushed::default()));
+    let (wake, woken) = mpsc::channel();
+    match client.events() {
+        Ok(mut events) => {
+            let (pushed, wake) = (Arc::clone(&pushed), wake.clone());
+            std::thread::spawn(move || {
+                while let Ok(Some(event)) = events.recv() {
+                    pushed.lock().unwrap_or_else(PoisonError::into_inner).record(&event);
+                    let _ = wake.send(());
                 }
+            });
+        }
+        Err(e) => eprintln!("warning: polling for work, as the coordinator pushes none: {}", e),
+    }
 
-                // Validate whether the current nonce solves the puzzle.
-                if validate(&puzzle_clone, nonce) {
-                    // If we have a solution, lock and update the shared solution storage.
-                    let mut sol = solution_clone.lock().unwrap();
-                    if sol.is_none() {
-                        *sol = Some(nonce);
-                        // Signal other threads that a solution has been found.
-                        found_flag_clone.store(true, Ordering::Relaxed);
-                    }
-                    return;
+    loop {
+        let lease = match client.lease() {
+            Ok(Some(lease)) => lease,
+            Ok(None) => {
+                let _ = woken.recv_timeout(poll);
+                continue;
+            }
+            // The coordinator's queue in Redis is down for now.
+            Err(WorkError::Refused(503, message)) => {
+                eprintln!("warning: the coordinator cannot lease work for now: {}", message);
+                let _ = woken.recv_timeout(poll);
+                continue;
+            }
+            Err(e) => return work_stopped(e),
+        };
+        outln!(
+            "Lease {}: puzzle {}, nonces {}..={}",
+            lease.id,
+            lease.puzzle_id,
+            lease.nonces.start(),
+            lease.nonces.end()
+        );
+        let token = pushed.lock().unwrap_or_else(PoisonError::into_inner).start(lease.puzzle_id);
+        match client.mine_cancellable(&lease, threads, &token) {
+            Ok(Some(solution)) => {
+                outln!("Found nonce (lease {}): {}", lease.id, solution.nonce);
+                if let Some(extra_nonce) = solution.extra_nonce {
+                    outln!("Extra nonce: {}", extra_nonce);
                 }
+                outln!("Hash: {}", hex::encode(&solution.hash));
+            }
+            Ok(None) => {}
+            Err(WorkError::Mining(PuzzleError::Cancelled)) => {
+                outln!("Lease {}: puzzle {} was finished elsewhere", lease.id, lease.puzzle_id);
             }
+            // The coordinator gave up on hearing from this worker in time, or could not
+            // take its report, in which case the lease expires and is leased again.
+            Err(WorkError::Refused(410 | 503, message)) => {
+                outln!("Lease {}: {}", lease.id, message)
+            }
+            Err(e) => return work_stopped(e),
+        }
+    }
+}
+
+/// Finds a coordinator on the local network, or exits with an error.
+fn discover_server() -> String {
+    #[cfg(feature = "mdns")]
+    {
+        let url = discovery::discover(DEFAULT_DISCOVERY_TIMEOUT).unwrap_or_else(|e| {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        });
+        outln!("Found a coordinator at {}", url);
+        url
+    }
+    #[cfg(not(feature = "mdns"))]
+    {
+        eprintln!("error: this build has no mDNS discovery (feature `mdns`)");
+        std::process::exit(2);
+    }
+}
+
+/// Registers with the coordinator at `server`, trusting and presenting the certificates
+/// `tls` names if any.
+fn connect_work_client(server: &str, tls: &WorkTlsArgs) -> Result<WorkClient, WorkError> {
+    if tls.tls_ca.is_none() && tls.tls_cert.is_none() {
+        return WorkClient::connect(server);
+    }
+    #[cfg(feature = "tls")]
+    {
+        let ca = tls.tls_ca.as_deref().map(read_pem);
+        let cert = tls.tls_cert.as_deref().map(read_pem);
+        let key = tls.tls_key.as_deref().map(read_pem);
+        let identity = cert.as_deref().zip(key.as_deref());
+        let config = ClientTls::new(ca.as_deref(), identity).unwrap_or_else(|e| {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
         });
+        WorkClient::connect_tls(server, config)
+    }
+    #[cfg(not(feature = "tls"))]
+    {
+        eprintln!("error: this build has no TLS (feature `tls`)");
+        std::process::exit(2);
+    }
+}
+
+/// Reads the PEM file at `path`, or exits with an error.
+#[cfg(feature = "tls")]
+fn read_pem(path: &Path) -> Vec<u8> {
+    fs::read(path).unwrap_or_else(|e| {
+        eprintln!("error: cannot read {}: {}", path.display(), e);
+        std::process::exit(1);
+    })
+}
+
+/// Mines the jobs of the Stratum pool at `url` as `user`, submitting every share it
+/// finds, until the pool closes the connection.
+fn work_stratum(url: &str, user: &str, password: &str, threads: Option<usize>) {
+    let threads = threads.unwrap_or_else(num_cpus::get);
+    let mut client = StratumClient::connect(url, user, password).unwrap_or_else(|e| {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    });
+    outln!("Subscribed to {} as {} ({} threads)", url, user, threads);
+    if !client.extranonce1().is_empty() || client.extranonce2_size() > 0 {
+        let extranonce1 = hex::encode(client.extranonce1());
+        let size = client.extranonce2_size();
+        outln!("Extranonce1: {}, rolling {} bytes of extranonce2", extranonce1, size);
+    }
+
+    loop {
+        let mut job = match client.job() {
+            Ok(Some(job)) => job,
+            Ok(None) => return stratum_stopped(StratumError::Closed),
+            Err(e) => return stratum_stopped(e),
+        };
+        outln!("Job {}: nonces {}..={}", job.id, job.nonces.start(), job.nonces.end());
+        loop {
+            match client.mine(&mut job, threads) {
+                Ok(Mined::Accepted(share)) => {
+                    outln!("Share accepted (job {}): {}", share.job_id, share.solution.nonce);
+                    if !share.extranonce2.is_empty() {
+                        outln!("Extranonce2: {}", hex::encode(&share.extranonce2));
+                    }
+                    outln!("Hash: {}", hex::encode(&share.solution.hash));
+                }
+                Ok(Mined::Rejected { share, code, message }) => outln!(
+                    "Share rejected (job {}): {} ({}: {})",
+                    share.job_id, share.solution.nonce, code, message
+                ),
+                Ok(Mined::Exhausted) => {
+                    outln!("Job {}: exhausted", job.id);
+                    break;
+                }
+                Ok(Mined::Superseded) => break,
+                Err(e) => return stratum_stopped(e),
+            }
+        }
+    }
+}
+
+/// Ends a Stratum `work` session on `e`: quietly if the pool closed the connection, as a
+/// `serve` coordinator does once its puzzle is solved, and with an error otherwise.
+fn stratum_stopped(e: StratumError) {
+    if let StratumError::Closed = e {
+        outln!("The pool closed the connection");
+        return;
+    }
+    eprintln!("error: {}", e);
+    std::process::exit(1);
+}
+
+/// What a `work` session has heard from the coordinator's events.
+#[derive(Default)]
+struct Pushed {
+    /// The puzzles solved or exhausted.
+    finished: HashSet<u64>,
+    /// The puzzle being mined, and the token that stops mining it.
+    mining: Option<(u64, CancellationToken)>,
+}
+
+impl Pushed {
+    /// Notes `event`, cancelling the mining of its puzzle if it finished the puzzle.
+    fn record(&mut self, event: &WorkEvent) {
+        if let WorkEvent::Added { .. } = event {
+            return;
+        }
+        self.finished.insert(event.puzzle_id());
+        if let Some((_, token)) = self.mining.as_ref().filter(|(id, _)| *id == event.puzzle_id()) {
+            token.cancel();
+        }
+    }
+
+    /// Starts mining a lease of the puzzle `id`.
+    ///
+    /// # Returns
+    ///
+    /// The token that stops it, already cancelled if the puzzle has finished.
+    fn start(&mut self, id: u64) -> CancellationToken {
+        let token = CancellationToken::new();
+        if self.finished.contains(&id) {
+            token.cancel();
+        }
+        self.mining = Some((id, token.clone()));
+        token
+    }
+}
+
+/// Ends a `work` session on `e`: quietly if the coordinator has shut down, as it does
+/// once its puzzle is solved, and with an error otherwise.
+fn work_stopped(e: WorkError) {
+    if let WorkError::Io(io) = &e {
+        // A request that reached the coordinator as it shut down is reset.
+        if matches!(io.kind(), io::ErrorKind::ConnectionRefused | io::ErrorKind::ConnectionReset) {
+            outln!("The coordinator has shut down");
+            return;
+        }
+    }
+    eprintln!("error: {}", e);
+    std::process::exit(1);
+}
+
+/// Measures the hash rate of every algorithm in `algos`, or of all of them, on one
+/// thread and on `threads`, prints them as a table and saves them to `save`.
+fn bench(time: Duration, threads: Option<usize>, algos: Vec<Algorithm>, save: Option<PathBuf>) {
+    let threads = threads.unwrap_or_else(num_cpus::get);
+    let algos = if algos.is_empty() { Algorithm::ALL.to_vec() } else { algos };
+    let benchmark = Benchmark::run_algorithms(&algos, threads, time);
+    let all_threads = format!("{} thread(s)", benchmark.threads);
+    outln!("{:<10} {:>12} {:>12} {:>8}", "Algorithm", "1 thread", all_threads, "Speedup");
+    for rates in &benchmark.rates {
+        outln!(
+            "{:<10} {:>12} {:>12} {:>7.2}x",
+            rates.algorithm.name(),
+            format_rate(rates.single_thread),
+            format_rate(rates.all_threads),
+            rates.speedup()
+        );
+    }
+    if let Some(path) = save {
+        if let Err(e) = benchmark.save(&path) {
+            eprintln!("error: cannot save the rates to {}: {}", path.display(), e);
+            std::process::exit(1);
+        }
+        outln!("Saved to {}", path.display());
+    }
+}
+
+/// Loads the hash rates saved by `bench --save` to `path`, or exits with an error.
+fn load_rates(path: &Path) -> Benchmark {
+    Benchmark::load(path).unwrap_or_else(|e| {
+        eprintln!("error: cannot load the rates from {}: {}", path.display(), e);
+        std::process::exit(2);
+    })
+}
+
+/// Prints the difficulty given in `args` in every unit the miner accepts.
+fn convert(args: ConvertArgs) {
+    let target = if let Some(threshold) = args.difficulty {
+        Target::from_threshold(threshold)
+    } else if let Some(bits) = args.zero_bits {
+        Target::from_leading_zero_bits(bits)
+    } else if let Some(bits) = args.nbits {
+        Target::from_compact(bits).unwrap_or_else(|e| {
+            eprintln!("error: invalid --nbits {:#010x}: {}", bits, e);
+            std::process::exit(2);
+        })
+    } else if let Some(hashes) = args.expected_hashes {
+        Target::from_expected_attempts(hashes)
+    } else {
+        args.target.expect("clap requires one difficulty")
+    };
+    // Marks the units that cannot hold this target exactly.
+    let exact = |other: Target| if other == target { "" } else { " (nearest harder)" };
+    outln!("Target: {}", target);
+    let threshold = target.to_threshold();
+    if threshold == 0 && target != Target::ZERO {
+        outln!("Threshold: too hard for a two-byte --difficulty");
+    } else {
+        let note = exact(Target::from_threshold(threshold));
+        outln!("Threshold: --difficulty {}{}", threshold, note);
+    }
+    let bits = target.leading_zero_bits();
+    let exact_bits = Target::from_leading_zero_bits(bits) == target;
+    let note = if exact_bits { "" } else { " (rounded down)" };
+    outln!("Leading zero bits: --zero-bits {}{}", bits, note);
+    let compact = target.to_compact();
+    let decoded = Target::from_compact(compact).unwrap_or(Target::ZERO);
+    outln!("Compact form: --nbits {:#010x}{}", compact, exact(decoded));
+    outln!(
+        "Expected work: {:.0} hashes (2^{:.2})",
+        target.expected_attempts(),
+        target.work_bits()
+    );
+}
+
+/// Generates a puzzle that takes `time` on average and prints the flags that solve it.
+fn generate(args: GenerateArgs) {
+    let threads = args.threads.unwrap_or_else(num_cpus::get);
+    let first_seed = args.seed.unwrap_or_else(rand::random);
+    let count = args.count as usize;
+    let times = match args.up_to {
+        Some(hardest) => difficulty_curve(args.time, hardest, count),
+        None => vec![args.time; count],
+    };
+    let puzzles = args.algo.generate_series(first_seed, args.data_len, &times, threads);
+    let Some(dir) = &args.out else {
+        for (i, (generated, time)) in puzzles.iter().zip(&times).enumerate() {
+            if i > 0 {
+                outln!();
+            }
+            let puzzle = &generated.puzzle;
+            outln!("Seed: {}", generated.seed);
+            outln!("Data: {}", hex::encode(&puzzle.data));
+            outln!("Target: {}", puzzle.target);
+            outln!(
+                "Expected work: {:.0} hashes (about {})",
+                puzzle.target.expected_attempts(),
+                format_duration(*time)
+            );
+            outln!(
+                "Solve with: --algo {} --data-hex {} --target {} --threads {}",
+                args.algo,
+                hex::encode(&puzzle.data),
+                puzzle.target,
+                threads
+            );
+        }
+        return;
+    };
+
+    if let Err(e) = fs::create_dir_all(dir) {
+        eprintln!("error: cannot create {}: {}", dir.display(), e);
+        std::process::exit(2);
+    }
+    let created = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs());
+    let width = count.to_string().len();
+    for (i, (generated, time)) in puzzles.iter().zip(&times).enumerate() {
+        let path = dir.join(format!("puzzle-{:0width$}.txt", i + 1, width = width));
+        let contents = puzzle_file(generated, args.algo, *time, threads, created);
+        if let Err(e) = fs::write(&path, contents) {
+            eprintln!("error: cannot write {}: {}", path.display(), e);
+            std::process::exit(2);
+        }
+    }
+    outln!("Wrote {} puzzle(s) to {} (seeds {} onwards)", count, dir.display(), first_seed);
+}
+
+/// The contents of a puzzle file written by `generate --out`: a header line, then one
+/// `key value` field per line.
+fn puzzle_file(
+    generated: &GeneratedPuzzle,
+    algo: Algorithm,
+    time: Duration,
+    threads: usize,
+    created: u64,
+) -> String {
+    let puzzle = &generated.puzzle;
+    let attempts = puzzle.target.expected_attempts();
+    format!(
+        "generated-puzzle 1\n\
+         seed {}\n\
+         data {}\n\
+         algorithm {}\n\
+         target {}\n\
+         nbits {:#010x}\n\
+         zero-bits {}\n\
+         expected-hashes {:.0}\n\
+         expected-seconds {}\n\
+         threads {}\n\
+         created {}\n",
+        generated.seed,
+        hex::encode(&puzzle.data),
+        algo,
+        puzzle.target,
+        puzzle.target.to_compact(),
+        attempts.log2().round().clamp(0.0, 255.0) as u32,
+        attempts,
+        time.as_secs_f64(),
+        threads,
+        created
+    )
+}
+
+/// Measures the hash rate of `algo` on `puzzle` and prints it, returning the target
+/// that takes `time` on average at that rate.
+fn benchmark(
+    algo: Algorithm,
+    puzzle: &Puzzle,
+    threads: usize,
+    time: Duration,
+    rates: Option<&Benchmark>,
+) -> Calibration {
+    let saved = rates.and_then(|rates| rates.calibrate(algo, threads, time));
+    let calibration =
+        saved.unwrap_or_else(|| algo.calibrate(puzzle, threads, time, CALIBRATION_TIME));
+    outln!(
+        "Calibrated for {} on average: {} on {} thread(s){}",
+        format_duration(time),
+        format_rate(calibration.hash_rate),
+        threads,
+        if saved.is_some() { " (saved rate)" } else { "" }
+    );
+    calibration
+}
+
+/// Measures the hash rate for `puzzle` briefly and prints the expected work and how
+/// likely a solution is within various times.
+fn print_estimate(algo: Algorithm, puzzle: &Puzzle, threads: usize, rates: Option<&Benchmark>) {
+    let rate = rates.and_then(|rates| rates.hash_rate(algo, threads)).unwrap_or_else(|| {
+        algo.measure_hash_rate(puzzle, threads, Duration::from_millis(250))
+    });
+    let estimate = WorkEstimate::new(puzzle.target, rate);
+    let attempts = estimate.expected_attempts();
+    let attempts =
+        if attempts < 1e15 { format!("{:.0}", attempts) } else { format!("{:.3e}", attempts) };
+    outln!(
+        "Expected work: {} hashes ({} at {})",
+        attempts,
+        format_duration(estimate.expected_time()),
+        format_rate(rate)
+    );
+    let quantiles: Vec<String> = [0.5, 0.9, 0.99]
+        .iter()
+        .map(|&p| {
+            let time = format_duration(estimate.time_for_probability(p));
+            format!("{:.0}% within {}", p * 100.0, time)
+        })
+        .collect();
+    outln!("Chance of a solution: {}", quantiles.join(", "));
+}
+
+/// Formats a duration for people, e.g. `4.2s` or `3h 12m`.
+fn format_duration(duration: Duration) -> String {
+    const YEAR: u64 = 31_557_600;
+    let secs = duration.as_secs();
+    if duration == Duration::MAX {
+        return format!(">{:.3e} years", secs as f64 / YEAR as f64);
+    }
+    match secs {
+        0..60 => format!("{:.1}s", duration.as_secs_f64()),
+        60..3600 => format!("{}m {}s", secs / 60, secs % 60),
+        3600..86400 => format!("{}h {}m", secs / 3600, secs % 3600 / 60),
+        86400..YEAR => format!("{}d {}h", secs / 86400, secs % 86400 / 3600),
+        _ => format!("{:.3e} years", duration.as_secs_f64() / YEAR as f64),
+    }
+}
 
-        handles.push(handle);
+/// Prints how much work a search did and how it was shared between the threads.
+fn print_report(report: &MiningReport) {
+    outln!(
+        "Hashes: {} in {:.2?} ({})",
+        report.attempts,
+        report.elapsed,
+        format_rate(report.hash_rate())
+    );
+    if report.thread_attempts.len() > 1 {
+        let counts: Vec<String> = report.thread_attempts.iter().map(u64::to_string).collect();
+        outln!("Per thread: {}", counts.join(" "));
     }
+    if let Some(thread) = report.found_by {
+        outln!("Found by thread: {}", thread);
+    }
+    #[cfg(feature = "profile")]
+    for (thread, profile) in report.thread_profiles.iter().enumerate() {
+        outln!("Thread {} profile: {}", thread, profile);
+    }
+    if let (Some(joules), Some(efficiency)) = (report.joules(), report.hashes_per_joule()) {
+        outln!("Energy: {:.1} J ({})", joules, format_efficiency(efficiency));
+    }
+}
+
+/// Mines `puzzle` on a [`MiningHandle`], applying the checkpoint, time limit and progress
+/// flags in `mine`.
+fn mine_in_background(
+    algo: Algorithm,
+    puzzle: &Puzzle,
+    nonces: std::ops::RangeInclusive<u64>,
+    threads: usize,
+    mine: &MineArgs,
+) -> (Result<Solution, PuzzleError>, MiningReport) {
+    let checkpoint = mine.resume.as_ref().or(mine.checkpoint.as_ref());
+    let mut builder = Miner::builder().algorithm(algo);
+    let resumed = mine.resume.as_ref().map(|path| load_checkpoint(path));
+    builder = match &resumed {
+        Some(resumed) => builder.resume(resumed.clone()),
+        None => builder.threads(threads).nonces(nonces),
+    };
+    if let Some(order) = mine.order() {
+        builder = builder.order(order);
+    }
+    if let Some(timeout) = mine.timeout {
+        builder = builder.timeout(timeout);
+    }
+    if let Some(percent) = mine.max_cpu {
+        builder = builder.max_cpu(percent);
+    }
+    let thermal_limit = mine.max_temp.map(|max| match mine.resume_temp {
+        Some(resume) => ThermalLimit::new(max).resume_at(resume),
+        None => ThermalLimit::new(max),
+    });
+    if let Some(limit) = thermal_limit {
+        builder = builder.thermal_limit(limit);
+    }
+    let mut pinned = None;
+    if let Some(pinning) = mine.pinning() {
+        pinned = Some(pinning.cores());
+        builder = builder.pin_cores(pinning);
+    }
+    if mine.priority != WorkerPriority::Normal {
+        builder = builder.priority(mine.priority);
+    }
+    let progress_line = Arc::new(ProgressLine::default());
+    if mine.progress {
+        builder = builder.observer(progress_line.clone());
+    }
+    let miner = builder.build().unwrap_or_else(|e| {
+        eprintln!("error: {}", e);
+        std::process::exit(2);
+    });
+    if let Some(cores) = pinned {
+        outln!("Pinned to cores: {}", format_cores(&cores));
+    }
+    if mine.priority != WorkerPriority::Normal {
+        outln!("Priority: {}", mine.priority);
+    }
+    if let Some(percent) = mine.max_cpu {
+        outln!("CPU limit: {}% per thread", percent);
+    }
+    if let Some(limit) = thermal_limit {
+        match cpu_temperature() {
+            Some(now) => outln!(
+                "Temperature limit: {:.1} °C, full speed again at {:.1} °C (now {:.1} °C)",
+                limit.max_celsius(),
+                limit.resume_celsius(),
+                now
+            ),
+            None => {
+                eprintln!("warning: no CPU temperature sensor found, so --max-temp has no effect")
+            }
+        }
+    }
+    let handle = miner.spawn(puzzle).unwrap_or_else(|e| {
+        eprintln!("error: {}", e);
+        std::process::exit(2);
+    });
+    if let (Some(path), Some(resumed)) = (&mine.resume, &resumed) {
+        outln!(
+            "Resuming from {}: {} nonce(s) left on {} thread(s)",
+            path.display(),
+            resumed.remaining(),
+            resumed.threads
+        );
+    }
+    let outcome = match checkpoint {
+        Some(path) => {
+            mine_with_checkpoints(handle, path, Duration::from_secs(mine.checkpoint_interval))
+        }
+        None => handle.join_report(),
+    };
+    if progress_line.shown.load(Ordering::Relaxed) {
+        eprintln!();
+    }
+    outcome
+}
+
+/// Rewrites one line on stderr with the progress of the search, for `--progress`.
+#[derive(Default)]
+struct ProgressLine {
+    /// Set once the line was printed, so it can be ended after the search.
+    shown: AtomicBool,
+}
 
-    // Wait for all threads to finish (either by finding a solution or exhausting their range).
-    for handle in handles {
-        let _ = handle.join();
+impl Observer for ProgressLine {
+    fn on_progress(&self, progress: &MiningProgress) {
+        self.shown.store(true, Ordering::Relaxed);
+        eprint!(
+            "\r{} hashes in {:.0}s, {} on {} thread(s)   ",
+            progress.attempts,
+            progress.elapsed.as_secs_f64(),
+            format_rate(progress.hash_rate()),
+            progress.thread_rates.len()
+        );
     }
+}
+
+/// Reads the checkpoint at `path` for `--resume`, exiting if it cannot be read.
+fn load_checkpoint(path: &Path) -> Checkpoint {
+    Checkpoint::load(path).unwrap_or_else(|e| {
+        eprintln!("error: cannot resume from {}: {}", path.display(), e);
+        std::process::exit(2);
+    })
+}
 
-    // Retrieve the found solution, if any.
-    let sol = solution.lock().unwrap();
-    sol.unwrap_or(u64::MAX)
+/// Waits for the search behind `handle`, saving a checkpoint to `path` every `interval`.
+///
+/// Once the search ends the file is removed, as there is nothing left to resume, unless
+/// it ran out of time; then the final position is saved for `--resume`.
+fn mine_with_checkpoints(
+    handle: MiningHandle,
+    path: &Path,
+    interval: Duration,
+) -> (Result<Solution, PuzzleError>, MiningReport) {
+    // Save once up front so an unwritable path is reported before any work is done.
+    if let Err(e) = handle.checkpoint().save(path) {
+        eprintln!("error: cannot write checkpoint {}: {}", path.display(), e);
+        std::process::exit(2);
+    }
+    let mut saved = Instant::now();
+    while !handle.is_finished() {
+        std::thread::sleep(Duration::from_millis(100));
+        if saved.elapsed() >= interval {
+            if let Err(e) = handle.checkpoint().save(path) {
+                eprintln!("warning: cannot write checkpoint {}: {}", path.display(), e);
+            }
+            saved = Instant::now();
+        }
+    }
+    let checkpoint = handle.checkpoint();
+    let (result, report) = handle.join_report();
+    if let Err(PuzzleError::TimedOut { .. }) = result {
+        match checkpoint.save(path) {
+            Ok(()) => outln!("Checkpoint saved to {}", path.display()),
+            Err(e) => eprintln!("warning: cannot write checkpoint {}: {}", path.display(), e),
+        }
+    } else {
+        let _ = std::fs::remove_file(path);
+    }
+    (result, report)
 }
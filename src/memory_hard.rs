@@ -0,0 +1,421 @@
+//! Memory-hard proof of work.
+//!
+//! SHA-256 and BLAKE3 are cheap to compute on GPUs and ASICs. A memory-hard function
+//! makes every attempt fill and re-read a large buffer, so the cost of an attempt is
+//! dominated by memory rather than arithmetic. A [`MemoryHardPuzzle`] pairs an ordinary
+//! [`Puzzle`] with such a function and its cost parameters: the message `data || nonce`
+//! (honouring the puzzle's nonce format) is the password, and the 32-byte output is
+//! compared against the target. Argon2id and scrypt are supported.
+//!
+//! Each worker thread needs its own scratch buffer of
+//! [`memory_bytes`](MemoryHardFunction::memory_bytes). The buffer is allocated once per
+//! thread and reused for every attempt; use
+//! [`threads_within_budget`](MemoryHardPuzzle::threads_within_budget) to keep the total
+//! below a memory budget, or [`default_threads`](MemoryHardPuzzle::default_threads) to
+//! fit the machine's available memory. On machines with several NUMA nodes,
+//! [`parallel_mine_pinned`](MemoryHardPuzzle::parallel_mine_pinned) with
+//! [`CorePinning::numa`] keeps every buffer on the node of the thread that uses it.
+//!
+//! The buffers start on a cache line. With
+//! [`with_huge_pages`](MemoryHardPuzzle::with_huge_pages), buffers of 2 MiB or more are
+//! also backed by transparent huge pages where the kernel allows it (see
+//! [`huge_pages_available`]), which spares the TLB misses of the random accesses of a
+//! large table.
+
+use std::borrow::Cow;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+
+use argon2::{Argon2, Block, Params, Version};
+
+use crate::control::{Control, Work};
+use crate::nonce::MAX_ENCODED_LEN;
+use crate::pool::WorkerPool;
+use crate::priority::WorkerPriority;
+use crate::romix::ScryptScratch;
+use crate::scratch::ScratchBuffer;
+use crate::search::{search_parallel_controlled, HashSearch};
+use crate::{
+    default_nonce_range, parallel_search, CorePinning, NonceEncoding, Puzzle, PuzzleError,
+    SearchOrder, Solution, Target,
+};
+
+/// The salt used for every Argon2id hash.
+///
+/// The puzzle data already makes each message unique, so a fixed salt only serves to
+/// separate these hashes from other uses of the same function. Scrypt follows Litecoin
+/// and uses the message as its own salt.
+pub const SALT: &[u8] = b"parallel-puzzle-pow";
+
+/// Length of every memory-hard hash.
+const OUTPUT_LEN: usize = 32;
+
+/// Where Linux reports whether transparent huge pages are turned on.
+const THP_ENABLED: &str = "/sys/kernel/mm/transparent_hugepage/enabled";
+
+/// Argon2id cost parameters.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Argon2Params {
+    /// Memory per hash, in KiB.
+    pub memory_kib: u32,
+    /// Number of passes over the memory.
+    pub iterations: u32,
+    /// Degree of parallelism inside one hash. Lanes are computed sequentially here, since
+    /// the miner already runs one hash per thread.
+    pub lanes: u32,
+}
+
+impl Default for Argon2Params {
+    /// The `argon2` crate defaults: 19 MiB, 2 passes, 1 lane.
+    fn default() -> Self {
+        Argon2Params {
+            memory_kib: Params::DEFAULT_M_COST,
+            iterations: Params::DEFAULT_T_COST,
+            lanes: Params::DEFAULT_P_COST,
+        }
+    }
+}
+
+/// Scrypt cost parameters.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ScryptParams {
+    /// Base-2 logarithm of the CPU/memory cost `N`.
+    pub log_n: u8,
+    /// Block size `r`.
+    pub r: u32,
+    /// Parallelism `p`; the `p` mixes run one after another.
+    pub p: u32,
+}
+
+impl Default for ScryptParams {
+    /// Litecoin's parameters: `N = 1024`, `r = 1`, `p = 1` (128 KiB per hash).
+    fn default() -> Self {
+        ScryptParams { log_n: 10, r: 1, p: 1 }
+    }
+}
+
+/// A memory-hard function and its cost parameters.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MemoryHardFunction {
+    /// Argon2id, version 0x13.
+    Argon2id(Argon2Params),
+    /// Scrypt with the message as both password and salt, as in Litecoin.
+    Scrypt(ScryptParams),
+}
+
+impl MemoryHardFunction {
+    /// How much scratch memory one hash needs, in bytes.
+    pub fn memory_bytes(&self) -> u64 {
+        match self {
+            MemoryHardFunction::Argon2id(params) => u64::from(params.memory_kib) * 1024,
+            MemoryHardFunction::Scrypt(params) => {
+                // The `N`-block table dominates; `p` and two working blocks come on top.
+                let blocks = 1u64
+                    .checked_shl(u32::from(params.log_n))
+                    .unwrap_or(u64::MAX)
+                    .saturating_add(u64::from(params.p) + 2);
+                blocks.saturating_mul(128 * u64::from(params.r))
+            }
+        }
+    }
+
+    /// Checks the parameters and builds the hasher.
+    fn hasher(&self) -> Result<Hasher, InvalidParams> {
+        match *self {
+            MemoryHardFunction::Argon2id(params) => {
+                let params = Params::new(
+                    params.memory_kib,
+                    params.iterations,
+                    params.lanes,
+                    Some(OUTPUT_LEN),
+                )
+                .map_err(|e| InvalidParams(format!("argon2id: {}", e)))?;
+                let argon2 = Argon2::new(argon2::Algorithm::Argon2id, Version::V0x13, params);
+                Ok(Hasher::Argon2(argon2))
+            }
+            MemoryHardFunction::Scrypt(params) => {
+                let params = scrypt::Params::new(params.log_n, params.r, params.p, OUTPUT_LEN)
+                    .map_err(|e| InvalidParams(format!("scrypt: {}", e)))?;
+                Ok(Hasher::Scrypt(params))
+            }
+        }
+    }
+}
+
+/// The parameters of a [`MemoryHardFunction`] were rejected.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InvalidParams(String);
+
+impl fmt::Display for InvalidParams {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid memory-hard parameters: {}", self.0)
+    }
+}
+
+impl Error for InvalidParams {}
+
+/// A validated memory-hard function, ready to hash.
+#[derive(Clone)]
+enum Hasher {
+    Argon2(Argon2<'static>),
+    Scrypt(scrypt::Params),
+}
+
+/// Per-thread scratch memory for a [`Hasher`].
+enum Scratch {
+    Argon2(ScratchBuffer<Block>),
+    Scrypt(ScryptScratch),
+}
+
+impl Hasher {
+    /// Allocates the scratch memory for one thread, on huge pages if `huge_pages` is set.
+    fn scratch(&self, huge_pages: bool) -> Scratch {
+        match self {
+            Hasher::Argon2(argon2) => {
+                let blocks = argon2.params().block_count();
+                Scratch::Argon2(ScratchBuffer::new(blocks, Block::default(), huge_pages))
+            }
+            Hasher::Scrypt(params) => Scratch::Scrypt(ScryptScratch::new(params, huge_pages)),
+        }
+    }
+
+    /// Hashes `message`, reusing `scratch`.
+    fn hash(&self, scratch: &mut Scratch, message: &[u8]) -> [u8; OUTPUT_LEN] {
+        let mut out = [0u8; OUTPUT_LEN];
+        match (self, scratch) {
+            (Hasher::Argon2(argon2), Scratch::Argon2(blocks)) => argon2
+                .hash_password_into_with_memory(message, SALT, &mut out, &mut blocks[..])
+                .expect("parameters, salt and output length were validated"),
+            (Hasher::Scrypt(_), Scratch::Scrypt(scratch)) => {
+                scratch.hash(message, message, &mut out)
+            }
+            _ => unreachable!("scratch memory is always built by the same hasher"),
+        }
+        out
+    }
+}
+
+/// A puzzle whose hash is a memory-hard function instead of a plain digest.
+#[derive(Clone)]
+pub struct MemoryHardPuzzle {
+    puzzle: Puzzle,
+    function: MemoryHardFunction,
+    hasher: Hasher,
+    huge_pages: bool,
+}
+
+impl MemoryHardPuzzle {
+    /// Pairs `puzzle` with `function`.
+    ///
+    /// # Returns
+    ///
+    /// The memory-hard puzzle, or [`InvalidParams`] if the function's parameters are out
+    /// of range (for example less than 8 KiB of memory per lane for Argon2id).
+    pub fn new(puzzle: Puzzle, function: MemoryHardFunction) -> Result<Self, InvalidParams> {
+        let hasher = function.hasher()?;
+        Ok(MemoryHardPuzzle { puzzle, function, hasher, huge_pages: false })
+    }
+
+    /// Asks for each thread's scratch memory to be backed by transparent huge pages, if
+    /// `huge_pages` is set; see the [module documentation](self). Buffers under 2 MiB,
+    /// such as scrypt's with Litecoin's parameters, are too small to use them.
+    pub fn with_huge_pages(mut self, huge_pages: bool) -> Self {
+        self.huge_pages = huge_pages;
+        self
+    }
+
+    /// Whether the scratch memory is asked to be backed by huge pages.
+    pub fn huge_pages(&self) -> bool {
+        self.huge_pages
+    }
+
+    /// The underlying puzzle.
+    pub fn puzzle(&self) -> &Puzzle {
+        &self.puzzle
+    }
+
+    /// The memory-hard function and its parameters.
+    pub fn function(&self) -> MemoryHardFunction {
+        self.function
+    }
+
+    /// Computes the memory-hard hash for `nonce`, allocating fresh scratch memory.
+    pub fn hash(&self, nonce: u64) -> Vec<u8> {
+        let parts = MessageParts::new(&self.puzzle);
+        let mut message = parts.buffer();
+        parts.build(nonce, &mut message);
+        self.hasher.hash(&mut self.hasher.scratch(self.huge_pages), &message).to_vec()
+    }
+
+    /// Validates whether `nonce` produces a hash at or below the target.
+    pub fn validate(&self, nonce: u64) -> bool {
+        self.puzzle.target.is_met_by(&self.hash(nonce))
+    }
+
+    /// The largest thread count, at most `num_threads` and at least one, whose scratch
+    /// memory fits in `memory_budget` bytes.
+    pub fn threads_within_budget(&self, num_threads: usize, memory_budget: u64) -> usize {
+        let per_thread = self.function.memory_bytes().max(1);
+        let affordable = usize::try_from(memory_budget / per_thread).unwrap_or(usize::MAX);
+        num_threads.min(affordable).max(1)
+    }
+
+    /// A thread count for this machine: one per logical core, reduced so that the scratch
+    /// memory fits in half of the [`available_memory`].
+    ///
+    /// When the available memory cannot be determined, every core is used.
+    pub fn default_threads(&self) -> usize {
+        match available_memory() {
+            Some(available) => self.threads_within_budget(num_cpus::get(), available / 2),
+            None => num_cpus::get(),
+        }
+    }
+
+    /// Solves the puzzle across `num_threads` threads over [`default_nonce_range`].
+    ///
+    /// See [`parallel_mine_range`](MemoryHardPuzzle::parallel_mine_range).
+    pub fn parallel_mine(&self, num_threads: usize) -> Result<Solution, PuzzleError> {
+        self.parallel_mine_range(default_nonce_range(), num_threads)
+    }
+
+    /// Solves the puzzle by splitting `nonces` across `num_threads` threads.
+    ///
+    /// Each thread allocates [`memory_bytes`](MemoryHardFunction::memory_bytes) of
+    /// scratch memory when it starts. The search otherwise behaves like
+    /// [`parallel_mine_range`](crate::parallel_mine_range), including extra-nonce rolling.
+    pub fn parallel_mine_range(
+        &self,
+        nonces: RangeInclusive<u64>,
+        num_threads: usize,
+    ) -> Result<Solution, PuzzleError> {
+        self.mine_rounds(nonces, num_threads, None)
+    }
+
+    /// Like [`parallel_mine_range`](MemoryHardPuzzle::parallel_mine_range), with the
+    /// threads pinned to cores by `pinning`; see the [`affinity`](crate::affinity)
+    /// module. If `pinning` leaves no core, the threads run unpinned.
+    ///
+    /// Each thread allocates its scratch memory after it is pinned, so with
+    /// [`CorePinning::numa`] the memory is on the thread's own NUMA node, and each node
+    /// searches its own share of `nonces` first.
+    pub fn parallel_mine_pinned(
+        &self,
+        nonces: RangeInclusive<u64>,
+        num_threads: usize,
+        pinning: &CorePinning,
+    ) -> Result<Solution, PuzzleError> {
+        let pool = WorkerPool::new(num_threads, &pinning.placements(), WorkerPriority::Normal);
+        self.mine_rounds(nonces, num_threads, Some(&pool))
+    }
+
+    /// Searches `nonces` under each extra nonce in turn, on `pool` if there is one.
+    fn mine_rounds(
+        &self,
+        nonces: RangeInclusive<u64>,
+        num_threads: usize,
+        pool: Option<&WorkerPool>,
+    ) -> Result<Solution, PuzzleError> {
+        let target = self.puzzle.target;
+        if target == Target::ZERO {
+            return Err(PuzzleError::InvalidDifficulty);
+        }
+        let mut puzzle = Cow::Borrowed(&self.puzzle);
+        loop {
+            let parts = Arc::new(MessageParts::new(&puzzle));
+            let init_parts = Arc::clone(&parts);
+            let hasher = Arc::new(self.hasher.clone());
+            let init_hasher = Arc::clone(&hasher);
+            let huge_pages = self.huge_pages;
+            let search = HashSearch::new(
+                // Scratch memory and a message buffer, both reused for every attempt.
+                move || (init_hasher.scratch(huge_pages), init_parts.buffer()),
+                move |(scratch, message), nonce| {
+                    parts.build(nonce, message);
+                    hasher.hash(scratch, message)
+                },
+                move |hash| target.is_met_by(hash),
+            );
+            let result = match pool {
+                Some(_) if nonces.is_empty() => Err(PuzzleError::EmptyRange),
+                Some(pool) => {
+                    let order = SearchOrder::Sequential;
+                    let work = Work::on_nodes(nonces.clone(), num_threads, order, pool.nodes());
+                    let control = Arc::new(Control::default());
+                    search_parallel_controlled(Arc::new(work), search, control, Some(pool))
+                }
+                None => parallel_search(search, nonces.clone(), num_threads),
+            };
+            match (result, puzzle.extra_nonce) {
+                (Ok(solution), extra_nonce) => return Ok(Solution { extra_nonce, ..solution }),
+                (Err(PuzzleError::Exhausted), Some(extra_nonce)) => {
+                    puzzle.to_mut().extra_nonce = Some(extra_nonce.wrapping_add(1));
+                }
+                (Err(e), _) => return Err(e),
+            }
+        }
+    }
+}
+
+/// The parts of a puzzle's message around the nonce.
+struct MessageParts {
+    prefix: Vec<u8>,
+    suffix: Vec<u8>,
+    encoding: NonceEncoding,
+}
+
+impl MessageParts {
+    fn new(puzzle: &Puzzle) -> Self {
+        let data = puzzle.message_data();
+        let (prefix, suffix) = puzzle.nonce_format.split(&data);
+        MessageParts {
+            prefix: prefix.to_vec(),
+            suffix: suffix.to_vec(),
+            encoding: puzzle.nonce_format.encoding,
+        }
+    }
+
+    /// A message buffer that already holds the prefix, for [`build`](MessageParts::build).
+    fn buffer(&self) -> Vec<u8> {
+        let len = self.prefix.len() + MAX_ENCODED_LEN + self.suffix.len();
+        let mut message = Vec::with_capacity(len);
+        message.extend_from_slice(&self.prefix);
+        message
+    }
+
+    /// Turns `message`, a [`buffer`](MessageParts::buffer), into
+    /// `prefix || encoded nonce || suffix`.
+    ///
+    /// The prefix stays in the buffer from one attempt to the next and only the bytes
+    /// after it are rewritten, so a large payload is not copied again for every nonce.
+    fn build(&self, nonce: u64, message: &mut Vec<u8>) {
+        message.truncate(self.prefix.len());
+        message.extend_from_slice(self.encoding.encode(nonce).as_ref());
+        message.extend_from_slice(&self.suffix);
+    }
+}
+
+/// The memory available for new allocations, in bytes.
+///
+/// This reads `MemAvailable` from `/proc/meminfo`, so it is only known on Linux.
+pub fn available_memory() -> Option<u64> {
+    let meminfo = fs::read_to_string("/proc/meminfo").ok()?;
+    let line = meminfo.lines().find(|line| line.starts_with("MemAvailable:"))?;
+    let kib: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kib * 1024)
+}
+
+/// Whether the kernel backs memory with transparent huge pages when asked to, as
+/// [`MemoryHardPuzzle::with_huge_pages`] does.
+///
+/// On Linux this is the case unless `/sys/kernel/mm/transparent_hugepage/enabled` is set
+/// to `never`; elsewhere it is never the case.
+pub fn huge_pages_available() -> bool {
+    if !cfg!(target_os = "linux") {
+        return false;
+    }
+    fs::read_to_string(THP_ENABLED)
+        .is_ok_and(|enabled| enabled.contains("[always]") || enabled.contains("[madvise]"))
+}
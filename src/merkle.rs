@@ -0,0 +1,222 @@
+//! Merkle trees over block payloads.
+//!
+//! A [`MerkleTree`] hashes a list of "transactions" down to a single 32-byte root, which
+//! can be used as a block's data: mining the block then commits to every transaction,
+//! and a [`MerkleProof`] shows that one transaction is included using only
+//! `log2(n)` hashes instead of the whole list.
+//!
+//! Hashing follows RFC 6962 in spirit: a leaf is `SHA-256(0x00 || transaction)` and an
+//! inner node is `SHA-256(0x01 || left || right)`, so a leaf can never be passed off as
+//! a node. When a level has an odd number of nodes, the last one moves up unchanged
+//! rather than being paired with a copy of itself as in Bitcoin, which would let two
+//! different lists share a root. The root of an empty tree is `SHA-256("")`.
+
+use sha2::Sha256;
+
+use crate::digest::Digest;
+
+/// Prefix of leaf hashes.
+const LEAF_PREFIX: u8 = 0x00;
+
+/// Prefix of inner node hashes.
+const NODE_PREFIX: u8 = 0x01;
+
+/// A Merkle tree with every level kept, so proofs can be produced for any leaf.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MerkleTree {
+    /// The levels from the leaf hashes up to the root, which is the only node of the
+    /// last level. Empty for a tree without transactions.
+    levels: Vec<Vec<[u8; 32]>>,
+}
+
+/// One step of a [`MerkleProof`]: the sibling hashed with the running hash, and on
+/// which side.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Sibling {
+    /// The sibling is the left child; the running hash is the right one.
+    Left([u8; 32]),
+    /// The sibling is the right child; the running hash is the left one.
+    Right([u8; 32]),
+}
+
+/// Evidence that a transaction is a leaf of a tree with a given root.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct MerkleProof {
+    /// The siblings from the leaf level upwards. Levels where the node moved up
+    /// unpaired contribute no step.
+    pub siblings: Vec<Sibling>,
+}
+
+impl MerkleTree {
+    /// Builds the tree over `transactions`, in order.
+    pub fn new<T: AsRef<[u8]>>(transactions: &[T]) -> Self {
+        let leaves: Vec<_> = transactions.iter().map(|tx| leaf_hash(tx.as_ref())).collect();
+        if leaves.is_empty() {
+            return MerkleTree { levels: Vec::new() };
+        }
+        let mut levels = vec![leaves];
+        while let Some(level) = levels.last().filter(|level| level.len() > 1) {
+            let next = level
+                .chunks(2)
+                .map(|pair| match pair {
+                    [left, right] => node_hash(left, right),
+                    [single] => *single,
+                    _ => unreachable!("chunks of two have one or two elements"),
+                })
+                .collect();
+            levels.push(next);
+        }
+        MerkleTree { levels }
+    }
+
+    /// The root hash, which commits to every transaction and their order.
+    pub fn root(&self) -> [u8; 32] {
+        match self.levels.last() {
+            Some(level) => level[0],
+            None => Sha256::digest([]).into(),
+        }
+    }
+
+    /// The number of transactions.
+    pub fn len(&self) -> usize {
+        self.levels.first().map_or(0, Vec::len)
+    }
+
+    /// Whether the tree was built over no transactions.
+    pub fn is_empty(&self) -> bool {
+        self.levels.is_empty()
+    }
+
+    /// A proof that the transaction at `index` is in the tree.
+    ///
+    /// # Returns
+    ///
+    /// The proof, or `None` if `index` is out of range.
+    pub fn proof(&self, index: usize) -> Option<MerkleProof> {
+        if index >= self.len() {
+            return None;
+        }
+        let mut siblings = Vec::new();
+        let mut index = index;
+        for level in &self.levels[..self.levels.len() - 1] {
+            let sibling = index ^ 1;
+            if sibling < level.len() {
+                siblings.push(if sibling < index {
+                    Sibling::Left(level[sibling])
+                } else {
+                    Sibling::Right(level[sibling])
+                });
+            }
+            index /= 2;
+        }
+        Some(MerkleProof { siblings })
+    }
+}
+
+impl MerkleProof {
+    /// The root this proof leads to from `transaction`.
+    pub fn root(&self, transaction: &[u8]) -> [u8; 32] {
+        self.siblings.iter().fold(leaf_hash(transaction), |hash, sibling| match sibling {
+            Sibling::Left(left) => node_hash(left, &hash),
+            Sibling::Right(right) => node_hash(&hash, right),
+        })
+    }
+
+    /// Whether `transaction` is included in the tree with `root`.
+    pub fn verify(&self, transaction: &[u8], root: &[u8; 32]) -> bool {
+        self.root(transaction) == *root
+    }
+}
+
+/// The hash of a leaf.
+fn leaf_hash(transaction: &[u8]) -> [u8; 32] {
+    Sha256::new().chain_update([LEAF_PREFIX]).chain_update(transaction).finalize().into()
+}
+
+/// The hash of an inner node.
+fn node_hash(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    Sha256::new()
+        .chain_update([NODE_PREFIX])
+        .chain_update(left)
+        .chain_update(right)
+        .finalize()
+        .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `count` distinct transactions.
+    fn transactions(count: usize) -> Vec<Vec<u8>> {
+        (0..count).map(|i| format!("transaction {}", i).into_bytes()).collect()
+    }
+
+    #[test]
+    fn every_leaf_proves_its_inclusion() {
+        for count in [1, 2, 3, 5, 8] {
+            let txs = transactions(count);
+            let tree = MerkleTree::new(&txs);
+            assert_eq!(tree.len(), count);
+            let (root, depth) = (tree.root(), count.next_power_of_two().trailing_zeros());
+            for (index, tx) in txs.iter().enumerate() {
+                let proof = tree.proof(index).unwrap();
+                assert!(proof.verify(tx, &root), "leaf {} of {}", index, count);
+                assert!(proof.siblings.len() <= depth as usize);
+            }
+            assert_eq!(tree.proof(count), None);
+        }
+    }
+
+    #[test]
+    fn odd_nodes_move_up_unpaired() {
+        let txs = transactions(5);
+        let leaves: Vec<_> = txs.iter().map(|tx| leaf_hash(tx)).collect();
+        let first = node_hash(&leaves[0], &leaves[1]);
+        let left = node_hash(&first, &node_hash(&leaves[2], &leaves[3]));
+        let tree = MerkleTree::new(&txs);
+        assert_eq!(tree.root(), node_hash(&left, &leaves[4]));
+        // The last leaf is unpaired on the two lower levels, so its proof has one step.
+        assert_eq!(tree.proof(4).unwrap().siblings, vec![Sibling::Left(left)]);
+
+        let three = MerkleTree::new(&txs[..3]);
+        assert_eq!(three.root(), node_hash(&first, &leaves[2]));
+        // Unlike Bitcoin's duplication, repeating the odd leaf changes the root.
+        let repeated = [&txs[..3], &txs[2..3]].concat();
+        assert_ne!(MerkleTree::new(&repeated).root(), three.root());
+
+        let one = MerkleTree::new(&txs[..1]);
+        assert_eq!(one.root(), leaves[0]);
+        assert!(one.proof(0).unwrap().siblings.is_empty());
+    }
+
+    #[test]
+    fn wrong_leaves_and_indices_are_rejected() {
+        for count in [2, 3, 5, 8] {
+            let txs = transactions(count);
+            let tree = MerkleTree::new(&txs);
+            let root = tree.root();
+            for index in 0..count {
+                let proof = tree.proof(index).unwrap();
+                assert!(!proof.verify(b"not a transaction", &root));
+                for (other, tx) in txs.iter().enumerate().filter(|&(other, _)| other != index) {
+                    assert!(!proof.verify(tx, &root), "leaf {} with the proof of {}", other, index);
+                }
+            }
+            // A leaf hash is never accepted in place of a node.
+            let mut leaf_as_node = tree.proof(0).unwrap();
+            leaf_as_node.siblings.remove(0);
+            let node = node_hash(&leaf_hash(&txs[0]), &leaf_hash(&txs[1]));
+            assert!(!leaf_as_node.verify(&node, &root));
+        }
+    }
+
+    #[test]
+    fn an_empty_tree_has_the_empty_hash_as_its_root() {
+        let tree = MerkleTree::new::<&[u8]>(&[]);
+        assert!(tree.is_empty());
+        assert_eq!(tree.len(), 0);
+        assert_eq!(tree.root(), <[u8; 32]>::from(Sha256::digest([])));
+        assert_eq!(tree.proof(0), None);
+    }
+}
@@ -0,0 +1,354 @@
+//! A configurable miner, for searches that need more than the defaults.
+//!
+//! Each free function in the crate root covers one way of running a search, and every new
+//! option would otherwise need its own function or another parameter. A [`Miner`] collects
+//! the options once instead, e.g.
+//! `Miner::builder().threads(8).algorithm(Algorithm::Blake3).timeout(limit).build()`, and
+//! then mines any number of puzzles with them. [`MinerBuilder::build`] rejects options
+//! that make no sense on their own or together, so a configuration error surfaces before
+//! any thread is started.
+//!
+//! By default every search starts its worker threads and joins them when it ends. When
+//! many easy puzzles are solved in a row, that can cost more than the searches
+//! themselves. [`MinerBuilder::reuse_threads`] gives the miner a pool of worker threads
+//! that stay alive for as long as the miner does; each search sends its workers to the
+//! pool as jobs over a channel and collects their results over another.
+//! [`MinerBuilder::pin_cores`] does the same with each of those threads pinned to a
+//! core of its own, and [`MinerBuilder::priority`] with each running at a lower
+//! operating-system priority.
+
+use std::error::Error;
+use std::fmt;
+use std::future::IntoFuture;
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::pool::WorkerPool;
+use crate::{
+    default_nonce_range, Algorithm, CancellationToken, Checkpoint, CorePinning, MiningFuture,
+    MiningHandle, Observer, Puzzle, PuzzleError, SearchOrder, Solution, Solutions, ThermalLimit,
+    WorkerPriority,
+};
+
+/// Options that [`MinerBuilder::build`] rejected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InvalidMinerConfig {
+    /// The thread count is zero.
+    ZeroThreads,
+    /// The nonce range holds no nonces.
+    EmptyRange,
+    /// The timeout is zero, so the search would stop before it started.
+    ZeroTimeout,
+    /// Two options were set that cannot be used together, named as their builder
+    /// methods.
+    Conflict(&'static str, &'static str),
+    /// The threads were to be pinned to cores, but no core is left to pin them to.
+    NoCores,
+    /// The CPU limit is zero, so the search would never run.
+    ZeroCpu,
+}
+
+impl fmt::Display for InvalidMinerConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InvalidMinerConfig::ZeroThreads => f.write_str("a miner needs at least one thread"),
+            InvalidMinerConfig::EmptyRange => f.write_str("the nonce range is empty"),
+            InvalidMinerConfig::ZeroTimeout => f.write_str("the timeout must be positive"),
+            InvalidMinerConfig::Conflict(first, second) => {
+                write!(f, "`{}` cannot be combined with `{}`", first, second)
+            }
+            InvalidMinerConfig::NoCores => f.write_str("no cores are left to pin threads to"),
+            InvalidMinerConfig::ZeroCpu => f.write_str("the CPU limit must be positive"),
+        }
+    }
+}
+
+impl Error for InvalidMinerConfig {}
+
+/// Mines puzzles with one fixed set of options; see the [module documentation](self).
+#[derive(Clone, Debug)]
+pub struct Miner {
+    algorithm: Algorithm,
+    threads: usize,
+    nonces: RangeInclusive<u64>,
+    order: SearchOrder,
+    timeout: Option<Duration>,
+    max_cpu: Option<u8>,
+    thermal_limit: Option<ThermalLimit>,
+    token: Option<CancellationToken>,
+    checkpoint: Option<Checkpoint>,
+    observer: Option<Arc<dyn Observer>>,
+    /// The worker threads shared by every search, if the miner keeps any.
+    pool: Option<Arc<WorkerPool>>,
+}
+
+impl Miner {
+    /// Starts configuring a miner. Without further options it mines with SHA-256 on
+    /// every available core over [`default_nonce_range`], like
+    /// [`parallel_mine`](crate::parallel_mine).
+    pub fn builder() -> MinerBuilder {
+        MinerBuilder::default()
+    }
+
+    /// The hash algorithm the miner uses.
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    /// Starts mining `puzzle` in the background and returns a handle to the search, with
+    /// the timeout, CPU and temperature limits, cancellation token and observer, if any,
+    /// already attached.
+    ///
+    /// A miner that reuses its threads runs the workers on them, so a second search
+    /// started before the first one ends waits for the threads to come free, and a paused
+    /// search holds on to them until it is resumed or cancelled.
+    ///
+    /// # Returns
+    ///
+    /// The handle, or [`PuzzleError::Checkpoint`] if the miner resumes from a checkpoint
+    /// taken for a different puzzle or algorithm.
+    pub fn spawn(&self, puzzle: &Puzzle) -> Result<MiningHandle, PuzzleError> {
+        let observer = self.observer.clone();
+        let pool = self.pool.clone();
+        let handle = match &self.checkpoint {
+            Some(checkpoint) => {
+                self.algorithm.resume_observed(puzzle, checkpoint, observer, pool)?
+            }
+            None => self.algorithm.spawn_observed(
+                puzzle,
+                self.nonces.clone(),
+                self.threads,
+                self.order,
+                observer,
+                pool,
+            ),
+        };
+        if let Some(timeout) = self.timeout {
+            handle.cancel_after(timeout);
+        }
+        if let Some(percent) = self.max_cpu {
+            handle.set_max_cpu(percent);
+        }
+        if let Some(limit) = self.thermal_limit {
+            handle.limit_temperature(limit);
+        }
+        if let Some(token) = &self.token {
+            handle.cancel_on(token);
+        }
+        Ok(handle)
+    }
+
+    /// Streams every solution of `puzzle` as the workers find them; see the
+    /// [`solutions`](crate::solutions) module.
+    ///
+    /// The search covers the miner's nonce range with its threads and algorithm, and stops
+    /// at the timeout or when the cancellation token is cancelled, like
+    /// [`spawn`](Miner::spawn). It starts afresh even if the miner resumes from a
+    /// checkpoint, always counts up through each share whatever the search order, ignores
+    /// the CPU and temperature limits, and the observer is not told about it. It starts
+    /// threads of its own even if the miner reuses its threads. Stop early by dropping the
+    /// iterator, e.g. with [`Iterator::take`].
+    pub fn solutions(&self, puzzle: &Puzzle) -> Solutions {
+        let solutions =
+            self.algorithm.find_solutions(puzzle, self.nonces.clone(), self.threads, None);
+        if let Some(timeout) = self.timeout {
+            solutions.cancel_after(timeout);
+        }
+        if let Some(token) = &self.token {
+            solutions.cancel_on(token);
+        }
+        solutions
+    }
+
+    /// Starts mining `puzzle` like [`spawn`](Miner::spawn) and returns a future of the
+    /// result, for async code; see the [`future`](crate::future) module. A miner that
+    /// reuses its threads gives the search a dedicated pool.
+    pub fn mine_async(&self, puzzle: &Puzzle) -> MiningFuture {
+        match self.spawn(puzzle) {
+            Ok(handle) => handle.into_future(),
+            Err(e) => MiningFuture::failed(e),
+        }
+    }
+
+    /// Mines `puzzle` and waits for the result.
+    ///
+    /// # Returns
+    ///
+    /// The [`Solution`], or a [`PuzzleError`] as for [`spawn`](Miner::spawn) and
+    /// [`MiningHandle::join`].
+    pub fn mine(&self, puzzle: &Puzzle) -> Result<Solution, PuzzleError> {
+        self.spawn(puzzle)?.join()
+    }
+}
+
+/// Collects the options for a [`Miner`]; created by [`Miner::builder`].
+#[derive(Clone, Debug, Default)]
+pub struct MinerBuilder {
+    algorithm: Algorithm,
+    threads: Option<usize>,
+    nonces: Option<RangeInclusive<u64>>,
+    order: Option<SearchOrder>,
+    timeout: Option<Duration>,
+    max_cpu: Option<u8>,
+    thermal_limit: Option<ThermalLimit>,
+    token: Option<CancellationToken>,
+    checkpoint: Option<Checkpoint>,
+    observer: Option<Arc<dyn Observer>>,
+    reuse_threads: bool,
+    pinning: Option<CorePinning>,
+    priority: WorkerPriority,
+}
+
+impl MinerBuilder {
+    /// Hashes with `algorithm` instead of SHA-256.
+    pub fn algorithm(mut self, algorithm: Algorithm) -> Self {
+        self.algorithm = algorithm;
+        self
+    }
+
+    /// Splits the search across `threads` threads instead of one per core.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = Some(threads);
+        self
+    }
+
+    /// Searches only `nonces` instead of [`default_nonce_range`].
+    pub fn nonces(mut self, nonces: RangeInclusive<u64>) -> Self {
+        self.nonces = Some(nonces);
+        self
+    }
+
+    /// Searches the window in `order` instead of counting up; see the
+    /// [`order`](crate::order) module.
+    pub fn order(mut self, order: SearchOrder) -> Self {
+        self.order = Some(order);
+        self
+    }
+
+    /// Gives up once `timeout` has passed since the search started; see
+    /// [`MiningHandle::cancel_after`].
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Lets each worker hash for only about `percent` of the time; see
+    /// [`MiningHandle::set_max_cpu`].
+    pub fn max_cpu(mut self, percent: u8) -> Self {
+        self.max_cpu = Some(percent);
+        self
+    }
+
+    /// Slows every search of the miner down while the CPU is hotter than `limit` allows;
+    /// see [`MiningHandle::limit_temperature`].
+    pub fn thermal_limit(mut self, limit: ThermalLimit) -> Self {
+        self.thermal_limit = Some(limit);
+        self
+    }
+
+    /// Stops every search of the miner once `token` is cancelled.
+    pub fn cancel_on(mut self, token: CancellationToken) -> Self {
+        self.token = Some(token);
+        self
+    }
+
+    /// Reports the start, progress and end of every search of the miner to `observer`.
+    pub fn observer(mut self, observer: Arc<dyn Observer>) -> Self {
+        self.observer = Some(observer);
+        self
+    }
+
+    /// Starts the worker threads once, when the miner is built, and runs every search of
+    /// the miner and its clones on them; see the [module documentation](self). The
+    /// threads exit once the miner, its clones and their searches are all dropped.
+    pub fn reuse_threads(mut self) -> Self {
+        self.reuse_threads = true;
+        self
+    }
+
+    /// Like [`reuse_threads`](MinerBuilder::reuse_threads), with each thread pinned to a
+    /// core chosen by `pinning`; see the [`affinity`](crate::affinity) module. Without
+    /// [`threads`](MinerBuilder::threads), the miner runs one thread per pinned core.
+    pub fn pin_cores(mut self, pinning: CorePinning) -> Self {
+        self.pinning = Some(pinning);
+        self
+    }
+
+    /// Like [`reuse_threads`](MinerBuilder::reuse_threads), with each thread running at
+    /// `priority`; see the [`priority`](crate::priority) module. The normal priority
+    /// leaves the threads as they were.
+    pub fn priority(mut self, priority: WorkerPriority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Continues the search saved in `checkpoint` instead of starting afresh. The
+    /// checkpoint fixes the threads, the nonce range and the order, so none of them may be
+    /// set.
+    pub fn resume(mut self, checkpoint: Checkpoint) -> Self {
+        self.checkpoint = Some(checkpoint);
+        self
+    }
+
+    /// Checks the options and builds the miner.
+    ///
+    /// # Returns
+    ///
+    /// The [`Miner`], or an [`InvalidMinerConfig`] if the thread count, nonce range,
+    /// timeout or CPU limit is empty, if `resume` is combined with `threads`, `nonces` or
+    /// `order`, or if `pin_cores` leaves no core.
+    pub fn build(self) -> Result<Miner, InvalidMinerConfig> {
+        if self.checkpoint.is_some() {
+            if self.threads.is_some() {
+                return Err(InvalidMinerConfig::Conflict("resume", "threads"));
+            }
+            if self.nonces.is_some() {
+                return Err(InvalidMinerConfig::Conflict("resume", "nonces"));
+            }
+            if self.order.is_some() {
+                return Err(InvalidMinerConfig::Conflict("resume", "order"));
+            }
+        }
+        let cores = self.pinning.as_ref().map(CorePinning::placements);
+        if cores.as_ref().is_some_and(Vec::is_empty) {
+            return Err(InvalidMinerConfig::NoCores);
+        }
+        let threads = match &cores {
+            Some(cores) => self.threads.unwrap_or(cores.len()),
+            None => self.threads.unwrap_or_else(num_cpus::get),
+        };
+        if threads == 0 {
+            return Err(InvalidMinerConfig::ZeroThreads);
+        }
+        let nonces = self.nonces.unwrap_or_else(default_nonce_range);
+        if nonces.is_empty() {
+            return Err(InvalidMinerConfig::EmptyRange);
+        }
+        if self.timeout.is_some_and(|timeout| timeout.is_zero()) {
+            return Err(InvalidMinerConfig::ZeroTimeout);
+        }
+        if self.max_cpu == Some(0) {
+            return Err(InvalidMinerConfig::ZeroCpu);
+        }
+        let pool_size = self.checkpoint.as_ref().map_or(threads, |checkpoint| checkpoint.threads);
+        let pooled = self.reuse_threads || self.priority != WorkerPriority::Normal;
+        let pool = match cores {
+            Some(cores) => Some(WorkerPool::new(pool_size, &cores, self.priority)),
+            None => pooled.then(|| WorkerPool::new(pool_size, &[], self.priority)),
+        };
+        Ok(Miner {
+            algorithm: self.algorithm,
+            threads,
+            nonces,
+            order: self.order.unwrap_or_default(),
+            timeout: self.timeout,
+            max_cpu: self.max_cpu,
+            thermal_limit: self.thermal_limit,
+            token: self.token,
+            checkpoint: self.checkpoint,
+            observer: self.observer,
+            pool: pool.map(Arc::new),
+        })
+    }
+}
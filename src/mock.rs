@@ -0,0 +1,74 @@
+//! A fake hash function for exercising the solver in tests, behind the `mock-hash`
+//! feature.
+//!
+//! [`MockHash`] implements [`Digest`](crate::digest::Digest) like the real algorithms, so
+//! it runs through every `*_with` entry point: the prehashed midstate, the thread engine,
+//! extra-nonce rolling, checkpoints and batches. Its output is a seeded pseudorandom
+//! function of the input, which makes every run reproducible and costs a fraction of a
+//! SHA-256 compression. That lets an integration test mine at a realistic difficulty in
+//! milliseconds and assert exactly which nonces solve a puzzle. It offers no security
+//! whatsoever and must never protect anything real.
+
+use crate::digest::{consts::U32, FixedOutput, HashMarker, Output, OutputSizeUser, Reset, Update};
+
+/// The 64-bit FNV-1a offset basis, mixed with the seed to start the state.
+const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+/// The 64-bit FNV-1a prime.
+const PRIME: u64 = 0x0100_0000_01b3;
+
+/// A fast, deterministic stand-in for a 32-byte hash; see the [module
+/// documentation](self).
+///
+/// The input is folded into 64 bits with FNV-1a, and the 32-byte output is expanded from
+/// that with SplitMix64, so every output bit depends on every input byte and the digests
+/// are spread evenly enough for difficulty targets to mean the usual amount of work.
+/// Different `SEED`s give unrelated functions, for tests that need several independent
+/// runs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct MockHash<const SEED: u64 = 0> {
+    state: u64,
+}
+
+impl<const SEED: u64> Default for MockHash<SEED> {
+    fn default() -> Self {
+        MockHash { state: OFFSET_BASIS ^ SEED }
+    }
+}
+
+impl<const SEED: u64> HashMarker for MockHash<SEED> {}
+
+impl<const SEED: u64> OutputSizeUser for MockHash<SEED> {
+    type OutputSize = U32;
+}
+
+impl<const SEED: u64> Update for MockHash<SEED> {
+    fn update(&mut self, data: &[u8]) {
+        for &byte in data {
+            self.state = (self.state ^ u64::from(byte)).wrapping_mul(PRIME);
+        }
+    }
+}
+
+impl<const SEED: u64> FixedOutput for MockHash<SEED> {
+    fn finalize_into(self, out: &mut Output<Self>) {
+        let mut state = self.state;
+        for chunk in out.chunks_exact_mut(8) {
+            chunk.copy_from_slice(&split_mix(&mut state).to_be_bytes());
+        }
+    }
+}
+
+impl<const SEED: u64> Reset for MockHash<SEED> {
+    fn reset(&mut self) {
+        *self = MockHash::default();
+    }
+}
+
+/// The next output of the SplitMix64 generator with the given `state`.
+fn split_mix(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
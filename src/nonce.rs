@@ -0,0 +1,125 @@
+//! How the nonce is serialised and where it is placed in the hashed message.
+//!
+//! By default the message is `data || nonce` with the nonce as 8 big-endian bytes. Real
+//! proof-of-work formats differ: Bitcoin stores the nonce little-endian inside the
+//! header, and Hashcash-style stamps spell it out as ASCII digits. A [`NonceFormat`]
+//! combines a [`NonceEncoding`] with a byte offset so those layouts can be reproduced.
+
+use std::fmt;
+use std::str::FromStr;
+
+/// The longest encoding produced by any [`NonceEncoding`] (`u64::MAX` has 20 digits).
+pub(crate) const MAX_ENCODED_LEN: usize = 20;
+
+/// How a nonce is turned into bytes before hashing.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum NonceEncoding {
+    /// 8 bytes, most significant first. This is the original encoding.
+    #[default]
+    BigEndian,
+    /// 8 bytes, least significant first.
+    LittleEndian,
+    /// Decimal ASCII digits with no padding, e.g. `"1234"`.
+    Ascii,
+}
+
+impl NonceEncoding {
+    /// Every supported encoding, in the order they are listed in help output.
+    pub const ALL: [NonceEncoding; 3] =
+        [NonceEncoding::BigEndian, NonceEncoding::LittleEndian, NonceEncoding::Ascii];
+
+    /// The canonical lower-case name of the encoding, as accepted by [`FromStr`].
+    pub fn name(self) -> &'static str {
+        match self {
+            NonceEncoding::BigEndian => "be",
+            NonceEncoding::LittleEndian => "le",
+            NonceEncoding::Ascii => "ascii",
+        }
+    }
+
+    /// Encodes `nonce` without allocating.
+    pub fn encode(self, nonce: u64) -> EncodedNonce {
+        let mut bytes = [0u8; MAX_ENCODED_LEN];
+        let len = match self {
+            NonceEncoding::BigEndian => {
+                bytes[..8].copy_from_slice(&nonce.to_be_bytes());
+                8
+            }
+            NonceEncoding::LittleEndian => {
+                bytes[..8].copy_from_slice(&nonce.to_le_bytes());
+                8
+            }
+            NonceEncoding::Ascii => {
+                // Write digits from the end of the buffer, then shift them to the front.
+                let mut value = nonce;
+                let mut pos = MAX_ENCODED_LEN;
+                loop {
+                    pos -= 1;
+                    bytes[pos] = b'0' + (value % 10) as u8;
+                    value /= 10;
+                    if value == 0 {
+                        break;
+                    }
+                }
+                bytes.copy_within(pos.., 0);
+                MAX_ENCODED_LEN - pos
+            }
+        };
+        EncodedNonce { bytes, len }
+    }
+}
+
+impl fmt::Display for NonceEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for NonceEncoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let wanted = s.to_ascii_lowercase();
+        NonceEncoding::ALL
+            .into_iter()
+            .find(|encoding| encoding.name() == wanted)
+            .ok_or_else(|| {
+                let names: Vec<_> = NonceEncoding::ALL.iter().map(|e| e.name()).collect();
+                format!("unknown nonce encoding `{}` (expected one of: {})", s, names.join(", "))
+            })
+    }
+}
+
+/// The bytes of an encoded nonce, stored inline.
+#[derive(Clone, Copy, Debug)]
+pub struct EncodedNonce {
+    bytes: [u8; MAX_ENCODED_LEN],
+    len: usize,
+}
+
+impl AsRef<[u8]> for EncodedNonce {
+    fn as_ref(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+}
+
+/// The full description of how a nonce enters the hashed message.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct NonceFormat {
+    /// How the nonce is serialised.
+    pub encoding: NonceEncoding,
+    /// Byte offset in the data at which the encoded nonce is inserted. `None` appends it
+    /// after the data.
+    pub offset: Option<usize>,
+}
+
+impl NonceFormat {
+    /// Splits `data` around the nonce position.
+    ///
+    /// The hashed message is `prefix || encoded nonce || suffix`. An offset past the end
+    /// of the data behaves like appending.
+    pub fn split<'a>(&self, data: &'a [u8]) -> (&'a [u8], &'a [u8]) {
+        let offset = self.offset.unwrap_or(data.len()).min(data.len());
+        data.split_at(offset)
+    }
+}
@@ -0,0 +1,97 @@
+//! The NUMA nodes of the machine, for keeping each worker's work on its own node.
+//!
+//! On a machine with several sockets, each socket has memory of its own, and a core
+//! reaches another socket's memory, or a cache line another socket last wrote, only at a
+//! cost. [`CorePinning::numa`](crate::CorePinning::numa) pins the workers node by node,
+//! and the search then gives each node a share of the nonce window with a cursor of its
+//! own, so a worker takes its chunks from its own node's share while it lasts and only
+//! then helps the others. Each worker also builds its scratch memory on its own pinned
+//! thread, and Linux places memory on the node of the thread that first writes it, so
+//! the buffers of memory-hard puzzles stay local too.
+//!
+//! [`numa_nodes`] reads the topology from sysfs on Linux; anywhere else, or when it cannot
+//! be read, the machine is taken as one node.
+
+use std::cell::Cell;
+use std::fs;
+
+/// One NUMA node: a set of cores that share the same local memory.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NumaNode {
+    /// The node's number, as the operating system counts them.
+    pub id: usize,
+    /// The numbers of its cores the process may run on, in ascending order.
+    pub cores: Vec<usize>,
+}
+
+thread_local! {
+    /// The node the current thread is pinned to, as the index of its share, if any.
+    static CURRENT_NODE: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+/// The NUMA nodes with cores the process may run on, lowest first.
+///
+/// Without a topology to read, this is a single node 0 with every core the process may
+/// run on, or nothing if the platform cannot tell which cores those are.
+pub fn numa_nodes() -> Vec<NumaNode> {
+    let allowed: Vec<usize> = core_affinity::get_core_ids()
+        .unwrap_or_default()
+        .into_iter()
+        .map(|core| core.id)
+        .collect();
+    let mut nodes: Vec<NumaNode> = read_nodes()
+        .into_iter()
+        .map(|NumaNode { id, cores }| {
+            let cores = cores.into_iter().filter(|core| allowed.contains(core)).collect();
+            NumaNode { id, cores }
+        })
+        .filter(|node| !node.cores.is_empty())
+        .collect();
+    if nodes.is_empty() && !allowed.is_empty() {
+        nodes.push(NumaNode { id: 0, cores: allowed });
+    }
+    nodes
+}
+
+/// Every node sysfs lists, with all of its cores.
+fn read_nodes() -> Vec<NumaNode> {
+    let Ok(entries) = fs::read_dir("/sys/devices/system/node") else {
+        return Vec::new();
+    };
+    let mut nodes: Vec<NumaNode> = entries
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let id = entry.file_name().to_str()?.strip_prefix("node")?.parse().ok()?;
+            let cpulist = fs::read_to_string(entry.path().join("cpulist")).ok()?;
+            Some(NumaNode { id, cores: parse_cpulist(&cpulist)? })
+        })
+        .collect();
+    nodes.sort_by_key(|node| node.id);
+    nodes
+}
+
+/// Parses a kernel CPU list such as `0-3,8-11`, or returns `None` if it is malformed.
+pub(crate) fn parse_cpulist(s: &str) -> Option<Vec<usize>> {
+    let mut cores = Vec::new();
+    for part in s.trim().split(',').filter(|part| !part.is_empty()) {
+        match part.split_once('-') {
+            Some((first, last)) => {
+                cores.extend(first.parse::<usize>().ok()?..=last.parse().ok()?)
+            }
+            None => cores.push(part.parse().ok()?),
+        }
+    }
+    cores.sort_unstable();
+    Some(cores)
+}
+
+/// Records that the current thread is pinned to a core of the `index`-th node the
+/// workers are spread over, whose share of the window it searches first.
+pub(crate) fn enter_node(index: usize) {
+    CURRENT_NODE.with(|node| node.set(Some(index)));
+}
+
+/// The node the current thread was pinned to with [`enter_node`], if any.
+pub(crate) fn current_node() -> Option<usize> {
+    CURRENT_NODE.with(Cell::get)
+}
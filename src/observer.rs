@@ -0,0 +1,60 @@
+//! Hooks for following a search from the outside.
+//!
+//! An [`Observer`] is told when a search starts, how it is getting on and how it ended,
+//! so logging, metrics or a user interface can follow a search without touching the
+//! mining loop. Attach one with [`MinerBuilder::observer`](crate::MinerBuilder::observer).
+//! Every hook has an empty default, so an observer only implements the ones it needs.
+
+use std::fmt;
+use std::time::Duration;
+
+use crate::{Checkpoint, MiningProgress, MiningReport, PuzzleError, Solution};
+
+/// Receives the events of a search.
+///
+/// The hooks run on the search's own threads, so they should return quickly; an
+/// observer that does real work should hand it off, e.g. over a channel.
+pub trait Observer: Send + Sync {
+    /// Called before the first hash, with the nonces each worker is about to search.
+    fn on_start(&self, _plan: &Checkpoint) {}
+
+    /// How often [`on_progress`](Observer::on_progress) is called. One second unless
+    /// overridden.
+    fn progress_interval(&self) -> Duration {
+        Duration::from_secs(1)
+    }
+
+    /// Called every [`progress_interval`](Observer::progress_interval) while the search
+    /// runs, as for [`MiningHandle::on_progress`](crate::MiningHandle::on_progress).
+    fn on_progress(&self, _progress: &MiningProgress) {}
+
+    /// Called once a worker finds a solution.
+    fn on_solution(&self, _solution: &Solution, _report: &MiningReport) {}
+
+    /// Called when every nonce was tried and none met the target.
+    fn on_exhausted(&self, _report: &MiningReport) {}
+
+    /// Called when the search was cancelled or ran out of time.
+    fn on_cancelled(&self, _report: &MiningReport) {}
+}
+
+impl fmt::Debug for dyn Observer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Observer")
+    }
+}
+
+/// Calls the hook of `observer` that matches how the search ended. Searches that failed
+/// for another reason, such as an invalid difficulty, have no hook.
+pub(crate) fn notify_end(
+    observer: &dyn Observer,
+    result: &Result<Solution, PuzzleError>,
+    report: &MiningReport,
+) {
+    match result {
+        Ok(solution) => observer.on_solution(solution, report),
+        Err(PuzzleError::Exhausted) => observer.on_exhausted(report),
+        Err(PuzzleError::Cancelled | PuzzleError::TimedOut { .. }) => observer.on_cancelled(report),
+        Err(_) => {}
+    }
+}
@@ -0,0 +1,117 @@
+// SHA-256 of one nonce per work item, from the midstate of the puzzle; see `opencl.rs`.
+//
+// Work item `index` hashes the nonce `base + index` and, if the hash is at most the
+// target, records `index` in `results` for the CPU to verify. `results[0]` counts the
+// work items that found one; only the first `MAX_CANDIDATES` are recorded after it.
+
+#define MAX_CANDIDATES 64u
+
+__constant uint K[64] = {
+    0x428a2f98u, 0x71374491u, 0xb5c0fbcfu, 0xe9b5dba5u, 0x3956c25bu, 0x59f111f1u, 0x923f82a4u,
+    0xab1c5ed5u, 0xd807aa98u, 0x12835b01u, 0x243185beu, 0x550c7dc3u, 0x72be5d74u, 0x80deb1feu,
+    0x9bdc06a7u, 0xc19bf174u, 0xe49b69c1u, 0xefbe4786u, 0x0fc19dc6u, 0x240ca1ccu, 0x2de92c6fu,
+    0x4a7484aau, 0x5cb0a9dcu, 0x76f988dau, 0x983e5152u, 0xa831c66du, 0xb00327c8u, 0xbf597fc7u,
+    0xc6e00bf3u, 0xd5a79147u, 0x06ca6351u, 0x14292967u, 0x27b70a85u, 0x2e1b2138u, 0x4d2c6dfcu,
+    0x53380d13u, 0x650a7354u, 0x766a0abbu, 0x81c2c92eu, 0x92722c85u, 0xa2bfe8a1u, 0xa81a664bu,
+    0xc24b8b70u, 0xc76c51a3u, 0xd192e819u, 0xd6990624u, 0xf40e3585u, 0x106aa070u, 0x19a4c116u,
+    0x1e376c08u, 0x2748774cu, 0x34b0bcb5u, 0x391c0cb3u, 0x4ed8aa4au, 0x5b9cca4fu, 0x682e6ff3u,
+    0x748f82eeu, 0x78a5636fu, 0x84c87814u, 0x8cc70208u, 0x90befffau, 0xa4506cebu, 0xbef9a3f7u,
+    0xc67178f2u,
+};
+
+#define ROTR(x, n) (((x) >> (n)) | ((x) << (32u - (n))))
+
+// `state` is the state after every full block before the nonce, `target` the target as
+// eight big-endian words, most significant first, and `tail` the padded rest of the
+// message as big-endian words, `blocks` blocks long, with zeros in place of the nonce,
+// which starts `nonce_offset` bytes into it. `little_endian` is 1 for a little-endian
+// nonce and 0 for a big-endian one, and `count` how many nonces the launch tests.
+__kernel void mine(
+    __constant const uint *state,
+    __constant const uint *target,
+    __global const uint *tail,
+    const ulong base,
+    const uint nonce_offset,
+    const uint blocks,
+    const uint little_endian,
+    const uint count,
+    __global volatile uint *results
+) {
+    const uint index = get_global_id(0);
+    if (index >= count) {
+        return;
+    }
+    const ulong nonce = base + index;
+
+    // The bytes of the encoded nonce, in message order.
+    uint bytes[8];
+    for (uint i = 0; i < 8u; i++) {
+        const uint shift = little_endian ? 8u * i : 56u - 8u * i;
+        bytes[i] = (uint)(nonce >> shift) & 0xffu;
+    }
+
+    uint h[8];
+    for (uint i = 0; i < 8u; i++) {
+        h[i] = state[i];
+    }
+    uint w[64];
+    for (uint block = 0; block < blocks; block++) {
+        for (uint t = 0; t < 16u; t++) {
+            w[t] = tail[16u * block + t];
+        }
+        // Write in the nonce bytes that fall in this block.
+        for (uint i = 0; i < 8u; i++) {
+            const uint position = nonce_offset + i;
+            if (position / 64u == block) {
+                const uint byte = position % 64u;
+                w[byte / 4u] |= bytes[i] << (24u - 8u * (byte % 4u));
+            }
+        }
+        for (uint t = 16; t < 64u; t++) {
+            const uint s0 = ROTR(w[t - 15], 7u) ^ ROTR(w[t - 15], 18u) ^ (w[t - 15] >> 3);
+            const uint s1 = ROTR(w[t - 2], 17u) ^ ROTR(w[t - 2], 19u) ^ (w[t - 2] >> 10);
+            w[t] = w[t - 16] + s0 + w[t - 7] + s1;
+        }
+
+        uint a = h[0], b = h[1], c = h[2], d = h[3];
+        uint e = h[4], f = h[5], g = h[6], hh = h[7];
+        for (uint t = 0; t < 64u; t++) {
+            const uint s1 = ROTR(e, 6u) ^ ROTR(e, 11u) ^ ROTR(e, 25u);
+            const uint ch = (e & f) ^ (~e & g);
+            const uint t1 = hh + s1 + ch + K[t] + w[t];
+            const uint s0 = ROTR(a, 2u) ^ ROTR(a, 13u) ^ ROTR(a, 22u);
+            const uint maj = (a & b) ^ (a & c) ^ (b & c);
+            const uint t2 = s0 + maj;
+            hh = g;
+            g = f;
+            f = e;
+            e = d + t1;
+            d = c;
+            c = b;
+            b = a;
+            a = t1 + t2;
+        }
+        h[0] += a;
+        h[1] += b;
+        h[2] += c;
+        h[3] += d;
+        h[4] += e;
+        h[5] += f;
+        h[6] += g;
+        h[7] += hh;
+    }
+
+    // The hash read as a big-endian number must not exceed the target.
+    for (uint j = 0; j < 8u; j++) {
+        if (h[j] < target[j]) {
+            break;
+        }
+        if (h[j] > target[j]) {
+            return;
+        }
+    }
+    const uint slot = atomic_inc(&results[0]);
+    if (slot < MAX_CANDIDATES) {
+        results[1 + slot] = index;
+    }
+}
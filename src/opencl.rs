@@ -0,0 +1,434 @@
+//! An optional OpenCL backend, behind the `opencl` feature.
+//!
+//! [`devices`] lists every OpenCL device of the machine, whatever its vendor: GPUs, CPUs
+//! and accelerators such as FPGAs. [`OpenClMiner`] then runs the nonce search as an
+//! OpenCL kernel on the one picked by its index in that list, or on the first GPU or
+//! accelerator. The OpenCL library is loaded when first needed, so a build with the
+//! feature still runs on machines without it and only reports [`OpenClError::NoRuntime`].
+//!
+//! The search works like the [`gpu`](crate::gpu) backend's and takes and returns the same
+//! types as the CPU engine: a [`Puzzle`] and a nonce window in, a [`Solution`] or the
+//! [`PuzzleError`] a CPU search would give out. The message is hashed up to the nonce
+//! once on the CPU, each launch tests a few million consecutive nonces from that
+//! midstate, and the CPU hashes the candidates the kernel records again before accepting
+//! one. Only SHA-256 with a fixed-width (`be` or `le`) nonce is supported, and the search
+//! cannot be paused, cancelled, checkpointed or observed.
+
+use std::borrow::Cow;
+use std::error::Error;
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::ptr;
+
+use opencl3::command_queue::CommandQueue;
+use opencl3::context::Context;
+use opencl3::device::{
+    cl_device_id, Device, CL_DEVICE_TYPE_ACCELERATOR, CL_DEVICE_TYPE_ALL, CL_DEVICE_TYPE_CPU,
+    CL_DEVICE_TYPE_GPU,
+};
+use opencl3::error_codes::{ClError, CL_PLATFORM_NOT_FOUND_KHR, DLOPEN_RUNTIME_LOAD_FAILED};
+use opencl3::kernel::{ExecuteKernel, Kernel};
+use opencl3::memory::{Buffer, CL_MEM_COPY_HOST_PTR, CL_MEM_READ_ONLY, CL_MEM_READ_WRITE};
+use opencl3::platform;
+use opencl3::program::Program;
+use opencl3::types::{cl_uint, cl_ulong, CL_BLOCKING};
+use sha2::Sha256;
+
+use crate::hybrid::Accelerator;
+use crate::lanes::Sha256Midstate;
+use crate::{
+    hash_with, CancellationToken, NonceEncoding, Puzzle, PuzzleError, Solution, Target,
+};
+
+/// How many nonces one kernel launch tests.
+const LAUNCH_NONCES: u64 = 1 << 22;
+
+/// How many candidates the kernel records per launch, as defined in the kernel.
+const MAX_CANDIDATES: usize = 64;
+
+/// Why the OpenCL backend could not list its devices or mine a puzzle.
+#[derive(Debug)]
+pub enum OpenClError {
+    /// The OpenCL library could not be loaded.
+    NoRuntime,
+    /// No OpenCL GPU or accelerator was found.
+    NoDevice,
+    /// There is no device at `index` in the list [`devices`] returns.
+    UnknownDevice {
+        /// The index asked for.
+        index: usize,
+        /// How many devices there are.
+        count: usize,
+    },
+    /// The device could not be set up or failed while searching, with the driver's
+    /// message.
+    Device(String),
+    /// The puzzle needs something the kernel does not do: a hash other than SHA-256 or an
+    /// `ascii` nonce.
+    Unsupported,
+    /// The device reported `nonce` as a solution, but its hash on the CPU does not meet
+    /// the target.
+    WrongSolution {
+        /// The nonce the device reported.
+        nonce: u64,
+    },
+    /// The search ran but found no solution, as a CPU search would report it.
+    Search(PuzzleError),
+}
+
+impl fmt::Display for OpenClError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OpenClError::NoRuntime => f.write_str("no OpenCL runtime is installed"),
+            OpenClError::NoDevice => f.write_str("no OpenCL GPU or accelerator was found"),
+            OpenClError::UnknownDevice { index, count } => {
+                write!(f, "there is no OpenCL device {} (found {})", index, count)
+            }
+            OpenClError::Device(message) => write!(f, "the OpenCL device failed: {}", message),
+            OpenClError::Unsupported => {
+                f.write_str("OpenCL only mines SHA-256 puzzles with a `be` or `le` nonce")
+            }
+            OpenClError::WrongSolution { nonce } => write!(
+                f,
+                "the OpenCL device reported nonce {}, which does not solve the puzzle",
+                nonce
+            ),
+            OpenClError::Search(e) => e.fmt(f),
+        }
+    }
+}
+
+impl Error for OpenClError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            OpenClError::Search(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<PuzzleError> for OpenClError {
+    fn from(e: PuzzleError) -> Self {
+        OpenClError::Search(e)
+    }
+}
+
+/// The error for a call into OpenCL that failed with `e`.
+fn failed(e: impl fmt::Display) -> OpenClError {
+    OpenClError::Device(e.to_string())
+}
+
+/// What kind of processor an OpenCL device is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OpenClDeviceKind {
+    /// A graphics card.
+    Gpu,
+    /// The CPU, through a runtime such as PoCL.
+    Cpu,
+    /// Dedicated hardware such as an FPGA.
+    Accelerator,
+    /// Anything else the runtime offers.
+    Other,
+}
+
+impl fmt::Display for OpenClDeviceKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            OpenClDeviceKind::Gpu => "GPU",
+            OpenClDeviceKind::Cpu => "CPU",
+            OpenClDeviceKind::Accelerator => "accelerator",
+            OpenClDeviceKind::Other => "other",
+        })
+    }
+}
+
+/// One OpenCL device, as [`devices`] lists it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OpenClDevice {
+    /// The position of the device in the list, which [`OpenClMiner::with_device`] takes.
+    pub index: usize,
+    /// The name of the device, as its driver reports it.
+    pub name: String,
+    /// The name of the platform, i.e. the driver, the device belongs to.
+    pub platform: String,
+    /// What kind of processor the device is.
+    pub kind: OpenClDeviceKind,
+    /// How many compute units the device runs work items on in parallel.
+    pub compute_units: u32,
+}
+
+impl fmt::Display for OpenClDevice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} ({}, {} compute units) on {}",
+            self.index, self.name, self.kind, self.compute_units, self.platform
+        )
+    }
+}
+
+/// Lists the OpenCL devices of every platform, in the order the platforms report them.
+///
+/// # Returns
+///
+/// The devices, possibly none, or [`OpenClError::NoRuntime`] if the OpenCL library
+/// cannot be loaded.
+pub fn devices() -> Result<Vec<OpenClDevice>, OpenClError> {
+    Ok(device_ids()?.into_iter().map(|(device, _)| device).collect())
+}
+
+/// Lists the devices with the ids OpenCL knows them by.
+fn device_ids() -> Result<Vec<(OpenClDevice, cl_device_id)>, OpenClError> {
+    let platforms = match platform::get_platforms() {
+        Ok(platforms) => platforms,
+        Err(ClError(CL_PLATFORM_NOT_FOUND_KHR)) => Vec::new(),
+        Err(ClError(DLOPEN_RUNTIME_LOAD_FAILED)) => return Err(OpenClError::NoRuntime),
+        Err(e) => return Err(failed(e)),
+    };
+    let mut devices = Vec::new();
+    for platform in platforms {
+        let platform_name = platform.name().map_err(failed)?;
+        // A platform without devices reports that as an error.
+        for id in platform.get_devices(CL_DEVICE_TYPE_ALL).unwrap_or_default() {
+            let device = Device::new(id);
+            let kind = device.dev_type().map_err(failed)?;
+            let kind = if kind & CL_DEVICE_TYPE_GPU != 0 {
+                OpenClDeviceKind::Gpu
+            } else if kind & CL_DEVICE_TYPE_ACCELERATOR != 0 {
+                OpenClDeviceKind::Accelerator
+            } else if kind & CL_DEVICE_TYPE_CPU != 0 {
+                OpenClDeviceKind::Cpu
+            } else {
+                OpenClDeviceKind::Other
+            };
+            let info = OpenClDevice {
+                index: devices.len(),
+                name: device.name().map_err(failed)?.trim().to_owned(),
+                platform: platform_name.trim().to_owned(),
+                kind,
+                compute_units: device.max_compute_units().map_err(failed)?,
+            };
+            devices.push((info, id));
+        }
+    }
+    Ok(devices)
+}
+
+/// An OpenCL device set up to mine SHA-256 puzzles; see the [module
+/// documentation](self).
+#[derive(Debug)]
+pub struct OpenClMiner {
+    device: OpenClDevice,
+    context: Context,
+    queue: CommandQueue,
+    program: Program,
+}
+
+impl OpenClMiner {
+    /// Sets up the first GPU or accelerator of [`devices`], skipping CPU devices, which
+    /// are slower than the CPU engine.
+    ///
+    /// # Returns
+    ///
+    /// The miner, [`OpenClError::NoDevice`] if there is no GPU or accelerator, or the
+    /// errors of [`with_device`](OpenClMiner::with_device).
+    pub fn new() -> Result<Self, OpenClError> {
+        let index = devices()?
+            .into_iter()
+            .find(|device| {
+                matches!(device.kind, OpenClDeviceKind::Gpu | OpenClDeviceKind::Accelerator)
+            })
+            .ok_or(OpenClError::NoDevice)?
+            .index;
+        OpenClMiner::with_device(index)
+    }
+
+    /// Sets up the device at `index` in the list [`devices`] returns and compiles the
+    /// kernel for it.
+    ///
+    /// # Returns
+    ///
+    /// The miner, [`OpenClError::NoRuntime`] without an OpenCL library,
+    /// [`OpenClError::UnknownDevice`] if there is no such device, or
+    /// [`OpenClError::Device`] if it cannot run the kernel.
+    pub fn with_device(index: usize) -> Result<Self, OpenClError> {
+        let mut devices = device_ids()?;
+        let count = devices.len();
+        if index >= count {
+            return Err(OpenClError::UnknownDevice { index, count });
+        }
+        let (device, id) = devices.swap_remove(index);
+        let context = Context::from_device(&Device::new(id)).map_err(failed)?;
+        #[allow(deprecated)] // Its replacement needs OpenCL 2.0, which many devices lack.
+        let queue = CommandQueue::create_default(&context, 0).map_err(failed)?;
+        let program = Program::create_and_build_from_source(&context, include_str!("opencl.cl"), "")
+            .map_err(OpenClError::Device)?;
+        Ok(OpenClMiner { device, context, queue, program })
+    }
+
+    /// The device the miner runs on.
+    pub fn device(&self) -> &OpenClDevice {
+        &self.device
+    }
+
+    /// Searches `nonces` for a valid nonce of `puzzle` with SHA-256, like
+    /// [`parallel_mine_range`](crate::parallel_mine_range) but on the OpenCL device.
+    ///
+    /// # Returns
+    ///
+    /// The [`Solution`], [`OpenClError::Unsupported`] if the nonce encoding is `ascii`,
+    /// [`OpenClError::Search`] with the [`PuzzleError`] a CPU search would give if there
+    /// is no solution, or another [`OpenClError`] if the device failed.
+    pub fn mine(
+        &self,
+        puzzle: &Puzzle,
+        nonces: RangeInclusive<u64>,
+    ) -> Result<Solution, OpenClError> {
+        self.mine_cancellable(puzzle, nonces, &CancellationToken::new())
+    }
+
+    /// Like [`mine`](Self::mine), but stops before the next launch once `token` is
+    /// cancelled, with [`PuzzleError::Cancelled`].
+    pub fn mine_cancellable(
+        &self,
+        puzzle: &Puzzle,
+        nonces: RangeInclusive<u64>,
+        token: &CancellationToken,
+    ) -> Result<Solution, OpenClError> {
+        if !matches!(
+            puzzle.nonce_format.encoding,
+            NonceEncoding::BigEndian | NonceEncoding::LittleEndian
+        ) {
+            return Err(OpenClError::Unsupported);
+        }
+        if puzzle.target == Target::ZERO {
+            return Err(PuzzleError::InvalidDifficulty.into());
+        }
+        if nonces.is_empty() {
+            return Err(PuzzleError::EmptyRange.into());
+        }
+        let mut puzzle = Cow::Borrowed(puzzle);
+        loop {
+            match (self.search(&puzzle, nonces.clone(), token), puzzle.extra_nonce) {
+                (Ok(solution), extra_nonce) => return Ok(Solution { extra_nonce, ..solution }),
+                (Err(OpenClError::Search(PuzzleError::Exhausted)), Some(extra_nonce)) => {
+                    puzzle.to_mut().extra_nonce = Some(extra_nonce.wrapping_add(1));
+                }
+                (Err(e), _) => return Err(e),
+            }
+        }
+    }
+
+    /// Searches `nonces` of `puzzle` as it stands with its current extra nonce.
+    fn search(
+        &self,
+        puzzle: &Puzzle,
+        nonces: RangeInclusive<u64>,
+        token: &CancellationToken,
+    ) -> Result<Solution, OpenClError> {
+        let message = Sha256Midstate::new(puzzle);
+        let mut state = message.state;
+        let mut target: Vec<cl_uint> = puzzle
+            .target
+            .to_be_bytes()
+            .chunks_exact(4)
+            .map(|word| u32::from_be_bytes(word.try_into().expect("four bytes")))
+            .collect();
+        let mut tail: Vec<cl_uint> = message
+            .tail
+            .iter()
+            .flat_map(|block| block.chunks_exact(4))
+            .map(|word| u32::from_be_bytes(word.try_into().expect("four bytes")))
+            .collect();
+        // SAFETY: Each buffer copies exactly the elements of its host slice.
+        let input = |words: &mut [cl_uint]| unsafe {
+            let flags = CL_MEM_READ_ONLY | CL_MEM_COPY_HOST_PTR;
+            Buffer::<cl_uint>::create(&self.context, flags, words.len(), words.as_mut_ptr().cast())
+                .map_err(failed)
+        };
+        let (state, target, tail) = (input(&mut state)?, input(&mut target)?, input(&mut tail)?);
+        // The kernel's `results`, cleared before each launch.
+        // SAFETY: No host memory is passed in.
+        let mut found = unsafe {
+            Buffer::<cl_uint>::create(
+                &self.context,
+                CL_MEM_READ_WRITE,
+                1 + MAX_CANDIDATES,
+                ptr::null_mut(),
+            )
+        }
+        .map_err(failed)?;
+        let kernel = Kernel::create(&self.program, "mine").map_err(failed)?;
+        let nonce_offset = message.nonce_offset as cl_uint;
+        let blocks = message.tail.len() as cl_uint;
+        let little_endian =
+            cl_uint::from(puzzle.nonce_format.encoding == NonceEncoding::LittleEndian);
+
+        let (mut base, end) = nonces.into_inner();
+        loop {
+            if token.is_cancelled() {
+                return Err(OpenClError::Search(PuzzleError::Cancelled));
+            }
+            let count = (end - base).min(LAUNCH_NONCES - 1) + 1;
+            let mut results = [0; 1 + MAX_CANDIDATES];
+            // SAFETY: The arguments match the kernel's parameters in number, order and
+            // type, and the buffers outlive the blocking read that ends the launch.
+            unsafe {
+                self.queue
+                    .enqueue_write_buffer(&mut found, CL_BLOCKING, 0, &[0], &[])
+                    .map_err(failed)?;
+                ExecuteKernel::new(&kernel)
+                    .set_arg(&state)
+                    .set_arg(&target)
+                    .set_arg(&tail)
+                    .set_arg(&(base as cl_ulong))
+                    .set_arg(&nonce_offset)
+                    .set_arg(&blocks)
+                    .set_arg(&little_endian)
+                    .set_arg(&(count as cl_uint))
+                    .set_arg(&found)
+                    .set_global_work_size(count as usize)
+                    .enqueue_nd_range(&self.queue)
+                    .map_err(failed)?;
+                self.queue
+                    .enqueue_read_buffer(&found, CL_BLOCKING, 0, &mut results, &[])
+                    .map_err(failed)?;
+            }
+
+            let found = (results[0] as usize).min(MAX_CANDIDATES);
+            // Accept the lowest candidate, once the CPU has hashed it too.
+            if let Some(&index) = results[1..=found].iter().min() {
+                let nonce = base + u64::from(index);
+                let hash = hash_with::<Sha256>(puzzle, nonce);
+                if !puzzle.target.is_met_by(&hash) {
+                    return Err(OpenClError::WrongSolution { nonce });
+                }
+                return Ok(Solution { nonce, hash: hash.to_vec(), extra_nonce: None });
+            }
+            if end - base < LAUNCH_NONCES {
+                return Err(PuzzleError::Exhausted.into());
+            }
+            base += count;
+        }
+    }
+}
+
+impl Accelerator for OpenClMiner {
+    type Error = OpenClError;
+
+    fn mine_cancellable(
+        &self,
+        puzzle: &Puzzle,
+        nonces: RangeInclusive<u64>,
+        token: &CancellationToken,
+    ) -> Result<Solution, OpenClError> {
+        OpenClMiner::mine_cancellable(self, puzzle, nonces, token)
+    }
+
+    fn into_search_error(error: OpenClError) -> Result<PuzzleError, OpenClError> {
+        match error {
+            OpenClError::Search(e) => Ok(e),
+            e => Err(e),
+        }
+    }
+}
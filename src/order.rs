@@ -0,0 +1,172 @@
+//! The order in which the workers try the nonces of the window.
+//!
+//! By default the workers count up through the window, chunk by chunk. That is the
+//! fastest order, but it means the low nonces are always tried first, so data whose
+//! early nonces never validate costs the same wasted work on every run, and two searches
+//! of similar puzzles walk the window in lockstep. [`SearchOrder::Shuffled`] instead
+//! walks the window in a pseudo-random order fixed by a seed, the workers taking
+//! consecutive chunks of the walk. Every nonce of the window is still tried exactly once,
+//! and a paused, cancelled or checkpointed search knows exactly which nonces are left, as
+//! in the default order.
+//!
+//! [`SearchOrder::Restarts`] keeps the linear scan but restarts it at a pseudo-random
+//! point of the window every `run` nonces, so the workers sweep runs of consecutive
+//! nonces scattered over the window. When solutions are clustered in unpredictable parts
+//! of the window, that reaches one sooner on average than a single sweep from the bottom,
+//! and several independent searches of the same puzzle, each with a seed of its own,
+//! start in different places instead of all trying the same nonces first. Each nonce is
+//! still tried exactly once.
+//!
+//! The permutation only spreads the nonces out; it is not meant to be unpredictable, and
+//! the same seed always gives the same order.
+
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+/// How the workers order the nonces of the window; see the [module
+/// documentation](self).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum SearchOrder {
+    /// Count up from the first nonce of the window.
+    #[default]
+    Sequential,
+    /// Walk the window in a pseudo-random order chosen by `seed`, so consecutive chunks
+    /// of the walk land all over the window.
+    Shuffled {
+        /// Picks the permutation; the same seed always gives the same order.
+        seed: u64,
+    },
+    /// Count up through runs of `run` nonces, each starting at a pseudo-random point of
+    /// the window chosen by `seed`. The last nonces of a window that is not a whole
+    /// number of runs are tried last, in order; a run of zero is treated as one.
+    Restarts {
+        /// Picks the order of the runs; the same seed always gives the same order.
+        seed: u64,
+        /// How many consecutive nonces are tried before jumping to the next run.
+        run: u64,
+    },
+}
+
+impl SearchOrder {
+    /// The nonce of `window` tried at `position`, the `n`-th nonce of the window in
+    /// sequential order giving the `n`-th step of the walk.
+    pub(crate) fn nonce_at(self, window: &RangeInclusive<u64>, position: u64) -> u64 {
+        match self {
+            SearchOrder::Sequential => position,
+            SearchOrder::Shuffled { seed } => {
+                let first = *window.start();
+                let permutation = Permutation::new(window.end() - first, mix(seed ^ mix(first)));
+                first + permutation.apply(position - first)
+            }
+            SearchOrder::Restarts { seed, run } => {
+                let (first, index) = (*window.start(), position - *window.start());
+                let run = run.max(1);
+                // Only whole runs are permuted. A window of 2^64 nonces in runs of one
+                // has 2^64 runs, so the count only fits in a `u128`.
+                let runs = (u128::from(window.end() - first) + 1) / u128::from(run);
+                if u128::from(index / run) >= runs {
+                    return position;
+                }
+                let permutation = Permutation::new((runs - 1) as u64, mix(seed ^ mix(first)));
+                first + permutation.apply(index / run) * run + index % run
+            }
+        }
+    }
+}
+
+impl fmt::Display for SearchOrder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SearchOrder::Sequential => f.write_str("sequential"),
+            SearchOrder::Shuffled { seed } => write!(f, "shuffled {}", seed),
+            SearchOrder::Restarts { seed, run } => write!(f, "restarts {} {}", seed, run),
+        }
+    }
+}
+
+/// The error returned when a string is not a [`SearchOrder`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ParseSearchOrderError;
+
+impl fmt::Display for ParseSearchOrderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("expected `sequential`, `shuffled <seed>` or `restarts <seed> <run>`")
+    }
+}
+
+impl std::error::Error for ParseSearchOrderError {}
+
+impl FromStr for SearchOrder {
+    type Err = ParseSearchOrderError;
+
+    /// Parses the form written by `Display`: `sequential`, `shuffled <seed>` or
+    /// `restarts <seed> <run>`, with a run of at least one.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(' ') {
+            None if s == "sequential" => Ok(SearchOrder::Sequential),
+            Some(("shuffled", seed)) => seed
+                .parse()
+                .map(|seed| SearchOrder::Shuffled { seed })
+                .map_err(|_| ParseSearchOrderError),
+            Some(("restarts", fields)) => {
+                let (seed, run) = fields.split_once(' ').ok_or(ParseSearchOrderError)?;
+                match (seed.parse(), run.parse()) {
+                    (Ok(seed), Ok(run)) if run > 0 => Ok(SearchOrder::Restarts { seed, run }),
+                    _ => Err(ParseSearchOrderError),
+                }
+            }
+            _ => Err(ParseSearchOrderError),
+        }
+    }
+}
+
+/// A keyed bijection on `0..=max`.
+///
+/// A few invertible steps scramble the smallest power-of-two domain holding `max`, and
+/// values that land above `max` are scrambled again until they fall inside ("cycle
+/// walking"). The domain is less than twice as large as the range, so that takes under
+/// two rounds on average.
+struct Permutation {
+    max: u64,
+    mask: u64,
+    shift: u32,
+    key: u64,
+}
+
+impl Permutation {
+    fn new(max: u64, key: u64) -> Self {
+        let bits = u64::BITS - max.leading_zeros();
+        let mask = if bits == 0 { 0 } else { u64::MAX >> (u64::BITS - bits) };
+        Permutation { max, mask, shift: bits / 2 + 1, key }
+    }
+
+    fn apply(&self, mut value: u64) -> u64 {
+        loop {
+            value = self.round(value);
+            if value <= self.max {
+                return value;
+            }
+        }
+    }
+
+    /// One pass of the scramble, a bijection on `0..=mask`.
+    fn round(&self, mut value: u64) -> u64 {
+        for key in [self.key, self.key.rotate_left(21), self.key.rotate_left(42)] {
+            value = (value ^ key) & self.mask;
+            // Multiplying by an odd number and shifting right are both invertible on the
+            // low bits.
+            value = value.wrapping_mul(key | 1) & self.mask;
+            value ^= value >> self.shift;
+        }
+        value
+    }
+}
+
+/// The SplitMix64 finalizer, used to derive a well-spread key from the seed.
+fn mix(mut z: u64) -> u64 {
+    z = z.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
@@ -0,0 +1,113 @@
+//! Worker threads kept alive between searches.
+//!
+//! Every search normally starts its workers and joins them again when it ends, which
+//! costs more than the search itself when many easy puzzles are solved in a row. A
+//! [`WorkerPool`] starts its threads once, and each search sends its workers to them as
+//! tasks over a channel. The threads can be pinned to cores, see the
+//! [`affinity`](crate::affinity) module, and run at a lower priority, see the
+//! [`priority`](crate::priority) module.
+
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use crate::affinity::Placement;
+use crate::cores;
+use crate::numa;
+use crate::priority::WorkerPriority;
+
+/// One worker of a search, run on a pool thread.
+type Task = Box<dyn FnOnce() + Send>;
+
+/// A fixed set of threads that run the tasks sent to them, in order of arrival.
+pub(crate) struct WorkerPool {
+    /// Closed on drop, which tells the threads to exit.
+    sender: Option<Sender<Task>>,
+    threads: Vec<JoinHandle<()>>,
+    /// How many NUMA nodes the threads are spread over, or 1 if they are not.
+    nodes: usize,
+}
+
+impl WorkerPool {
+    /// Starts `num_threads` threads (at least one), placing thread `i` at
+    /// `placements[i % placements.len()]` unless `placements` is empty, each at
+    /// `priority`.
+    pub(crate) fn new(
+        num_threads: usize,
+        placements: &[Placement],
+        priority: WorkerPriority,
+    ) -> Self {
+        let (sender, receiver) = mpsc::channel::<Task>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let threads = (0..num_threads.max(1))
+            .map(|i| {
+                let receiver = Arc::clone(&receiver);
+                let placement = placements.get(i % placements.len().max(1)).copied();
+                thread::spawn(move || {
+                    // A thread the system refuses to pin simply runs anywhere, and one it
+                    // refuses to lower at normal priority.
+                    if let Some(placement) = placement {
+                        core_affinity::set_for_current(placement.core);
+                        cores::set_chunk_size(placement.chunk_size);
+                        if let Some(node) = placement.node {
+                            numa::enter_node(node);
+                        }
+                    }
+                    priority.apply();
+                    loop {
+                        // The lock is only held while waiting, never while a task runs.
+                        let task = match receiver.lock() {
+                            Ok(receiver) => receiver.recv(),
+                            Err(_) => return,
+                        };
+                        match task {
+                            Ok(task) => {
+                                let _ = panic::catch_unwind(AssertUnwindSafe(task));
+                            }
+                            Err(_) => return,
+                        }
+                    }
+                })
+            })
+            .collect();
+        let nodes = placements.iter().filter_map(|placement| placement.node).max();
+        let nodes = nodes.map_or(1, |last| last + 1);
+        WorkerPool { sender: Some(sender), threads, nodes }
+    }
+
+    /// How many NUMA nodes the threads are spread over, or 1 if they are not; each gets a
+    /// share of the window in [`Work`](crate::control::Work).
+    pub(crate) fn nodes(&self) -> usize {
+        self.nodes
+    }
+
+    /// Runs `task` on the next free thread of the pool.
+    ///
+    /// Tasks wait for a free thread in order of arrival, behind the tasks of any other
+    /// search already queued. A task that panics leaves its thread in the pool.
+    pub(crate) fn execute(&self, task: impl FnOnce() + Send + 'static) {
+        let sender = self.sender.as_ref().expect("the sender is only taken on drop");
+        sender.send(Box::new(task)).expect("pool threads only exit once the pool is dropped");
+    }
+}
+
+impl fmt::Debug for WorkerPool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WorkerPool")
+            .field("threads", &self.threads.len())
+            .field("nodes", &self.nodes)
+            .finish()
+    }
+}
+
+impl Drop for WorkerPool {
+    fn drop(&mut self) {
+        // Closing the channel ends each thread once the tasks already sent have run.
+        drop(self.sender.take());
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,146 @@
+//! Mining over a payload that is hashed once up front.
+//!
+//! Every hash the miner computes starts with the same data, so the hasher state after
+//! absorbing that data (its "prefix state") is identical for all nonces. A
+//! [`PrehashedPuzzle`] streams the data through the hasher exactly once, keeps only the
+//! resulting state, and clones it for each attempt so that only the nonce bytes (and any
+//! data placed after the nonce) are hashed per nonce. When built from a reader the
+//! payload itself is never held in memory, which makes this the right tool for
+//! multi-megabyte inputs read from a file or stdin.
+
+use std::io::{self, Read};
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+
+use crate::digest::{Digest, Output};
+use crate::{search_parallel, NonceEncoding, Puzzle, PuzzleError, Solution, Target};
+
+/// The size of the buffer used when streaming data into the prefix state.
+const READ_CHUNK: usize = 64 * 1024;
+
+/// A puzzle whose data has already been absorbed into a hasher of type `D`.
+#[derive(Clone)]
+pub struct PrehashedPuzzle<D> {
+    /// Hasher state after absorbing every byte that precedes the nonce.
+    prefix: D,
+    /// Data that follows the nonce and is hashed after it on every attempt.
+    suffix: Vec<u8>,
+    /// The 256-bit target a valid hash must not exceed.
+    pub target: Target,
+    /// How many payload bytes were absorbed into the prefix state.
+    pub data_len: u64,
+    /// How the nonce is serialised after the prefix.
+    pub nonce_encoding: NonceEncoding,
+}
+
+impl<D: Digest + Clone> PrehashedPuzzle<D> {
+    /// Builds the prefix state from an in-memory puzzle.
+    ///
+    /// Everything before the puzzle's nonce offset is absorbed once; anything after it is
+    /// kept and re-hashed after the nonce on each attempt. The puzzle's current extra
+    /// nonce, if any, is baked into the prefix.
+    pub fn from_puzzle(puzzle: &Puzzle) -> Self {
+        let data = puzzle.message_data();
+        let (before, after) = puzzle.nonce_format.split(&data);
+        let mut prefix = D::new();
+        prefix.update(before);
+        PrehashedPuzzle {
+            prefix,
+            suffix: after.to_vec(),
+            target: puzzle.target,
+            data_len: before.len() as u64,
+            nonce_encoding: puzzle.nonce_format.encoding,
+        }
+    }
+
+    /// Streams all of `reader` into the prefix state without buffering the whole payload.
+    ///
+    /// The nonce is appended after the streamed data using the default
+    /// [`NonceEncoding`]; change [`nonce_encoding`](PrehashedPuzzle::nonce_encoding)
+    /// afterwards if needed.
+    ///
+    /// # Parameters
+    ///
+    /// - `reader`: The source of the payload, e.g. a file or `io::stdin()`.
+    /// - `target`: The target a valid hash must not exceed.
+    ///
+    /// # Returns
+    ///
+    /// The prehashed puzzle, or the I/O error that interrupted reading.
+    pub fn from_reader(mut reader: impl Read, target: Target) -> io::Result<Self> {
+        let mut prefix = D::new();
+        let mut data_len = 0u64;
+        let mut buf = vec![0u8; READ_CHUNK];
+        loop {
+            let read = match reader.read(&mut buf) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            prefix.update(&buf[..read]);
+            data_len += read as u64;
+        }
+        Ok(PrehashedPuzzle {
+            prefix,
+            suffix: Vec::new(),
+            target,
+            data_len,
+            nonce_encoding: NonceEncoding::default(),
+        })
+    }
+
+    /// Computes the puzzle hash for `nonce` by cloning the prefix state.
+    ///
+    /// The result is identical to [`hash_with`](crate::hash_with) over the original data.
+    pub fn hash(&self, nonce: u64) -> Output<D> {
+        let mut hasher = self.prefix.clone();
+        hasher.update(self.nonce_encoding.encode(nonce));
+        hasher.update(&self.suffix);
+        hasher.finalize()
+    }
+
+    /// Validates whether `nonce` produces a hash at or below the target.
+    pub fn validate(&self, nonce: u64) -> bool {
+        self.target.is_met_by(&self.hash(nonce))
+    }
+}
+
+/// Solves a prehashed puzzle by splitting the `nonces` window across `num_threads` threads.
+///
+/// The threads share one copy of the prefix state; each attempt clones it and hashes only
+/// the nonce. See [`parallel_mine_range`](crate::parallel_mine_range) for how the window
+/// is split and what is returned; pass
+/// [`default_nonce_range`](crate::default_nonce_range) to search the usual window.
+pub fn parallel_mine_prehashed<D>(
+    puzzle: &PrehashedPuzzle<D>,
+    nonces: RangeInclusive<u64>,
+    num_threads: usize,
+) -> Result<Solution, PuzzleError>
+where
+    D: Digest + Clone + Send + Sync + 'static,
+{
+    let target = puzzle.target;
+    if target == Target::ZERO {
+        return Err(PuzzleError::InvalidDifficulty);
+    }
+    parallel_mine_prehashed_matching(puzzle, nonces, num_threads, move |hash| {
+        target.is_met_by(hash)
+    })
+}
+
+/// Like [`parallel_mine_prehashed`], but accepts the first hash that satisfies
+/// `predicate` instead of comparing against the puzzle's target.
+pub fn parallel_mine_prehashed_matching<D, P>(
+    puzzle: &PrehashedPuzzle<D>,
+    nonces: RangeInclusive<u64>,
+    num_threads: usize,
+    predicate: P,
+) -> Result<Solution, PuzzleError>
+where
+    D: Digest + Clone + Send + Sync + 'static,
+    P: Fn(&[u8]) -> bool + Send + Sync + 'static,
+{
+    let puzzle = Arc::new(puzzle.clone());
+    search_parallel(nonces, num_threads, move |nonce| puzzle.hash(nonce), predicate)
+}
@@ -0,0 +1,66 @@
+//! Probable primes for the number-theoretic puzzles.
+
+use num_bigint::{BigUint, RandBigInt};
+use rand::Rng;
+
+/// Miller-Rabin rounds; a composite survives all of them with probability below `4^-40`.
+const MILLER_RABIN_ROUNDS: usize = 40;
+
+/// Primes used to reject most candidates before running Miller-Rabin.
+const SMALL_PRIMES: [u32; 24] =
+    [3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97];
+
+/// Whether `n` is prime, up to a negligible Miller-Rabin error.
+pub(crate) fn is_probable_prime(n: &BigUint, rng: &mut impl Rng) -> bool {
+    let two = BigUint::from(2u32);
+    if *n < two {
+        return false;
+    }
+    if !n.bit(0) {
+        return *n == two;
+    }
+    for p in SMALL_PRIMES {
+        if *n == BigUint::from(p) {
+            return true;
+        }
+        if (n % p) == BigUint::ZERO {
+            return false;
+        }
+    }
+
+    // n - 1 = d * 2^s with d odd.
+    let one = BigUint::from(1u32);
+    let n_minus_one = n - &one;
+    let s = n_minus_one.trailing_zeros().expect("n - 1 is non-zero");
+    let d = &n_minus_one >> s;
+    'witness: for _ in 0..MILLER_RABIN_ROUNDS {
+        let a = rng.gen_biguint_range(&two, &n_minus_one);
+        let mut x = a.modpow(&d, n);
+        if x == one || x == n_minus_one {
+            continue;
+        }
+        for _ in 1..s {
+            x = x.modpow(&two, n);
+            if x == n_minus_one {
+                continue 'witness;
+            }
+        }
+        return false;
+    }
+    true
+}
+
+/// A random prime of exactly `bits` bits whose top two bits are set, so the product of
+/// two such primes has exactly `2 * bits` bits.
+pub(crate) fn random_prime(bits: u64, rng: &mut impl Rng) -> BigUint {
+    assert!(bits >= 2, "a prime needs at least two bits");
+    loop {
+        let mut candidate = rng.gen_biguint(bits);
+        candidate.set_bit(bits - 1, true);
+        candidate.set_bit(bits - 2, true);
+        candidate.set_bit(0, true);
+        if is_probable_prime(&candidate, rng) {
+            return candidate;
+        }
+    }
+}
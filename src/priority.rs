@@ -0,0 +1,100 @@
+//! Running the worker threads at a lower operating-system priority.
+//!
+//! A search keeps every core it is given busy, and at normal priority it competes with
+//! the desktop for them, so the machine turns sluggish while it mines. At
+//! [`WorkerPriority::Low`] the workers run at the lowest nice level, so anything else
+//! that wants a core gets it first; at [`WorkerPriority::Idle`] they only run when a core
+//! would otherwise sit idle. Pass it to
+//! [`MinerBuilder::priority`](crate::MinerBuilder::priority).
+//!
+//! Only Linux supports this, where each thread has a priority of its own; elsewhere the
+//! workers keep the normal priority.
+
+use std::fmt;
+use std::str::FromStr;
+
+/// The operating-system priority of the worker threads; see the [module
+/// documentation](self).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum WorkerPriority {
+    /// The priority threads start with.
+    #[default]
+    Normal,
+    /// The lowest nice level, 19: the workers yield to every normal thread.
+    Low,
+    /// The idle scheduling class: the workers only run on cores nothing else wants.
+    Idle,
+}
+
+impl WorkerPriority {
+    /// Gives the current thread this priority, if it is not the normal one.
+    ///
+    /// # Returns
+    ///
+    /// Whether the thread now runs at this priority; `false` if the system refused or
+    /// does not support it.
+    pub(crate) fn apply(self) -> bool {
+        match self {
+            WorkerPriority::Normal => true,
+            WorkerPriority::Low | WorkerPriority::Idle => lower(self),
+        }
+    }
+}
+
+/// Lowers the priority of the current thread to `priority`.
+#[cfg(target_os = "linux")]
+fn lower(priority: WorkerPriority) -> bool {
+    // On Linux, process ID 0 names the calling thread alone for both calls.
+    match priority {
+        WorkerPriority::Normal => true,
+        // SAFETY: `setpriority` only reads its arguments.
+        WorkerPriority::Low => unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, 19) == 0 },
+        WorkerPriority::Idle => {
+            let param = libc::sched_param { sched_priority: 0 };
+            // SAFETY: `param` is a valid `sched_param` that outlives the call.
+            unsafe { libc::sched_setscheduler(0, libc::SCHED_IDLE, &param) == 0 }
+        }
+    }
+}
+
+/// Lowers the priority of the current thread to `priority`.
+#[cfg(not(target_os = "linux"))]
+fn lower(priority: WorkerPriority) -> bool {
+    priority == WorkerPriority::Normal
+}
+
+impl fmt::Display for WorkerPriority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            WorkerPriority::Normal => "normal",
+            WorkerPriority::Low => "low",
+            WorkerPriority::Idle => "idle",
+        })
+    }
+}
+
+/// The error returned when a string is not a [`WorkerPriority`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ParseWorkerPriorityError;
+
+impl fmt::Display for ParseWorkerPriorityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("expected `normal`, `low` or `idle`")
+    }
+}
+
+impl std::error::Error for ParseWorkerPriorityError {}
+
+impl FromStr for WorkerPriority {
+    type Err = ParseWorkerPriorityError;
+
+    /// Parses the form written by `Display`: `normal`, `low` or `idle`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "normal" => Ok(WorkerPriority::Normal),
+            "low" => Ok(WorkerPriority::Low),
+            "idle" => Ok(WorkerPriority::Idle),
+            _ => Err(ParseWorkerPriorityError),
+        }
+    }
+}
@@ -0,0 +1,163 @@
+//! Where each worker's time goes, for diagnosing searches that scale poorly.
+//!
+//! With the `profile` feature, every worker of the thread engine keeps a
+//! [`ThreadProfile`] of its time: hashing its batches of candidates, checking the shared
+//! flags between them (which includes recording its progress and taking chunks of the
+//! window), and idle, while paused, resting under a CPU or temperature limit, or out of
+//! work while the others finish the window. A search on a
+//! [`MiningHandle`](crate::MiningHandle) adds each worker's profile to its
+//! [`MiningReport`](crate::MiningReport).
+//!
+//! A worker that spends much of its time checking has batches too small for its hash, and
+//! one that sits idle at the end of every window has too large a share of it; either way
+//! extra threads buy less than they should. Each worker reads the clock four more times
+//! per batch, and a batch takes about a tenth of a millisecond, so the profile costs
+//! little, but nothing is measured without the feature.
+
+use std::fmt;
+use std::ops::AddAssign;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+/// How one worker spent its time; see the [module documentation](self).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ThreadProfile {
+    /// The time spent hashing candidates.
+    pub hashing: Duration,
+    /// The time spent between batches checking the shared flags, recording progress and
+    /// taking chunks.
+    pub checking: Duration,
+    /// The time spent paused, resting under a limit, or out of work.
+    pub idle: Duration,
+}
+
+impl ThreadProfile {
+    /// The time the worker was profiled for.
+    pub fn total(&self) -> Duration {
+        self.hashing + self.checking + self.idle
+    }
+
+    /// The share of the time spent hashing, between 0 and 1.
+    pub fn hashing_share(&self) -> f64 {
+        self.share(self.hashing)
+    }
+
+    /// The share of the time spent checking the shared flags, between 0 and 1.
+    pub fn checking_share(&self) -> f64 {
+        self.share(self.checking)
+    }
+
+    /// The share of the time spent idle, between 0 and 1.
+    pub fn idle_share(&self) -> f64 {
+        self.share(self.idle)
+    }
+
+    fn share(&self, part: Duration) -> f64 {
+        let total = self.total();
+        if total.is_zero() {
+            0.0
+        } else {
+            part.as_secs_f64() / total.as_secs_f64()
+        }
+    }
+}
+
+impl AddAssign for ThreadProfile {
+    fn add_assign(&mut self, other: ThreadProfile) {
+        self.hashing += other.hashing;
+        self.checking += other.checking;
+        self.idle += other.idle;
+    }
+}
+
+impl fmt::Display for ThreadProfile {
+    /// Formats the profile as its shares, e.g. `97.1% hashing, 2.5% checking, 0.4% idle`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:.1}% hashing, {:.1}% checking, {:.1}% idle",
+            self.hashing_share() * 100.0,
+            self.checking_share() * 100.0,
+            self.idle_share() * 100.0
+        )
+    }
+}
+
+/// What a worker was doing since its last [`Stopwatch::lap`].
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Activity {
+    Hashing,
+    Checking,
+    Idle,
+}
+
+/// A worker's clock, splitting its time between the [`Activity`]s.
+#[derive(Debug)]
+pub(crate) struct Stopwatch {
+    /// When the last lap ended.
+    last: Instant,
+    /// The time measured since the last flush.
+    profile: ThreadProfile,
+}
+
+impl Stopwatch {
+    pub(crate) fn start() -> Self {
+        Stopwatch { last: Instant::now(), profile: ThreadProfile::default() }
+    }
+
+    /// Counts the time since the last lap as spent on `activity`.
+    pub(crate) fn lap(&mut self, activity: Activity) {
+        let now = Instant::now();
+        let elapsed = now - self.last;
+        self.last = now;
+        match activity {
+            Activity::Hashing => self.profile.hashing += elapsed,
+            Activity::Checking => self.profile.checking += elapsed,
+            Activity::Idle => self.profile.idle += elapsed,
+        }
+    }
+
+    /// Adds the time measured so far to `slot`, where the search can read it.
+    pub(crate) fn flush(&mut self, slot: &ProfileSlot) {
+        let profile = std::mem::take(&mut self.profile);
+        slot.hashing.fetch_add(nanos(profile.hashing), Ordering::Relaxed);
+        slot.checking.fetch_add(nanos(profile.checking), Ordering::Relaxed);
+        slot.idle.fetch_add(nanos(profile.idle), Ordering::Relaxed);
+    }
+}
+
+/// One worker's profile over a window, shared with the thread running the search.
+#[derive(Debug, Default)]
+pub(crate) struct ProfileSlot {
+    /// The nanoseconds flushed so far of each activity.
+    hashing: AtomicU64,
+    checking: AtomicU64,
+    idle: AtomicU64,
+    /// When the worker ran out of work or stopped.
+    exited: OnceLock<Instant>,
+}
+
+impl ProfileSlot {
+    /// Records that the worker has stopped.
+    pub(crate) fn exit(&self) {
+        let _ = self.exited.set(Instant::now());
+    }
+
+    /// The worker's profile as of `now`, counting the time since it stopped as idle.
+    pub(crate) fn profile(&self, now: Instant) -> ThreadProfile {
+        let waited = self
+            .exited
+            .get()
+            .map_or(Duration::ZERO, |&exited| now.saturating_duration_since(exited));
+        ThreadProfile {
+            hashing: Duration::from_nanos(self.hashing.load(Ordering::Relaxed)),
+            checking: Duration::from_nanos(self.checking.load(Ordering::Relaxed)),
+            idle: Duration::from_nanos(self.idle.load(Ordering::Relaxed)) + waited,
+        }
+    }
+}
+
+fn nanos(duration: Duration) -> u64 {
+    duration.as_nanos() as u64
+}
@@ -0,0 +1,105 @@
+//! An alternative engine built on rayon, behind the `rayon` feature.
+//!
+//! The hand-rolled engine in the [`search`](crate::search) module hands its threads
+//! fixed-size chunks of the window from a shared cursor. [`rayon_search`] instead hands
+//! the window to a rayon pool as a parallel iterator and stops with `find_map_any` as
+//! soon as any candidate passes. Rayon splits the window lazily and idle workers steal
+//! the unsearched halves of busy ones, so there is no chunk size to tune, but the window
+//! is not searched from the bottom up.
+//!
+//! The rayon engine only finds a solution. It cannot be paused, checkpointed or observed,
+//! and which of several solutions it returns depends on how the work was split, so the
+//! hand-rolled engine stays the default. `cargo bench --features rayon --bench engines`
+//! compares the two on this machine.
+
+use std::borrow::Cow;
+use std::ops::RangeInclusive;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+
+use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
+
+use crate::digest::Digest;
+use crate::search::HashSearch;
+use crate::{ParallelSearch, PrehashedPuzzle, Puzzle, PuzzleError, Solution, Target};
+
+/// Runs `search` over `nonces` on a rayon pool of `num_threads` threads until a candidate
+/// passes.
+///
+/// This is a drop-in replacement for [`parallel_search`](crate::parallel_search), with
+/// the same parameters and errors. Every worker builds its state with
+/// [`ParallelSearch::init`] once per piece of the window it takes on, rather than once in
+/// all.
+///
+/// # Returns
+///
+/// The output of a candidate that passed, [`PuzzleError::EmptyRange`] if `nonces` is
+/// empty, [`PuzzleError::Exhausted`] if no candidate passed, or
+/// [`PuzzleError::WorkerPanicked`] if a test panicked.
+pub fn rayon_search<S: ParallelSearch>(
+    search: S,
+    nonces: RangeInclusive<u64>,
+    num_threads: usize,
+) -> Result<S::Output, PuzzleError> {
+    if nonces.is_empty() {
+        return Err(PuzzleError::EmptyRange);
+    }
+    let pool = ThreadPoolBuilder::new()
+        .num_threads(num_threads.max(1))
+        .build()
+        .expect("failed to spawn the rayon worker threads");
+    let found = panic::catch_unwind(AssertUnwindSafe(|| {
+        pool.install(|| {
+            nonces
+                .into_par_iter()
+                .map_init(|| search.init(), |state, nonce| search.test(state, nonce))
+                .find_map_any(|output| output)
+        })
+    }));
+    match found {
+        Ok(Some(output)) => Ok(output),
+        Ok(None) => Err(PuzzleError::Exhausted),
+        Err(_) => Err(PuzzleError::WorkerPanicked),
+    }
+}
+
+/// Searches `nonces` for a valid nonce of `puzzle` with the hash function `D`, like
+/// [`parallel_mine_range`](crate::parallel_mine_range) but on the rayon engine.
+///
+/// The data is prehashed once, and a puzzle with an extra nonce rolls it and searches the
+/// window again whenever the window is exhausted, as with the default engine.
+///
+/// # Returns
+///
+/// A [`Solution`], or a [`PuzzleError`] as for
+/// [`parallel_mine_range`](crate::parallel_mine_range).
+pub fn parallel_mine_rayon_with<D>(
+    puzzle: &Puzzle,
+    nonces: RangeInclusive<u64>,
+    num_threads: usize,
+) -> Result<Solution, PuzzleError>
+where
+    D: Digest + Clone + Send + Sync + 'static,
+{
+    let target = puzzle.target;
+    if target == Target::ZERO {
+        return Err(PuzzleError::InvalidDifficulty);
+    }
+    let mut puzzle = Cow::Borrowed(puzzle);
+    loop {
+        let prehashed = Arc::new(PrehashedPuzzle::<D>::from_puzzle(&puzzle));
+        let search = HashSearch::new(
+            || (),
+            move |_, nonce| prehashed.hash(nonce),
+            move |hash| target.is_met_by(hash),
+        );
+        match (rayon_search(search, nonces.clone(), num_threads), puzzle.extra_nonce) {
+            (Ok(solution), extra_nonce) => return Ok(Solution { extra_nonce, ..solution }),
+            (Err(PuzzleError::Exhausted), Some(extra_nonce)) => {
+                puzzle.to_mut().extra_nonce = Some(extra_nonce.wrapping_add(1));
+            }
+            (Err(e), _) => return Err(e),
+        }
+    }
+}
@@ -0,0 +1,309 @@
+//! Keeping a work server's puzzles, leases and results in Redis, with the `redis` feature.
+//!
+//! A [`WorkServer`](crate::WorkServer) normally keeps everything it knows in memory, so
+//! it forgets its puzzles, and which of their ranges were searched, when it stops. Given
+//! a [`RedisQueue`] with `WorkServer::with_redis`, it keeps them in Redis instead: a
+//! restarted server carries on where it left off, and several servers, replicas behind a
+//! load balancer, say, can share one queue and lease its ranges to their workers alike.
+//!
+//! The queue lives under one key, whose name is given to [`RedisQueue::connect`]:
+//!
+//! - `<key>` holds the whole state of the queue, as newline-separated JSON records.
+//! - `<key>:lock` is held by the server carrying out a request, for the few
+//!   milliseconds it takes, so that replicas take turns. A server that dies or stalls
+//!   holding it only holds it for [`LOCK_TTL`], and a server whose lock lapsed cannot
+//!   save its state over a newer one.
+//! - `<key>:events` is the channel every server publishes its events on, and relays to
+//!   the workers subscribed to its `GET /events`.
+//!
+//! Every request reads the state and, if it changed it, writes it back whole, which
+//! suits the small clusters the work server is for rather than thousands of puzzles.
+//! Leases are timed by the servers' clocks, which should agree. A request that cannot
+//! reach Redis after a few attempts, or finds its state unreadable, is refused with
+//! `503 Service Unavailable`, and the worker can try again later.
+
+use std::error::Error;
+use std::fmt;
+use std::sync::{Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use redis::{Client, Connection, RedisError, RedisResult, Script};
+
+/// The key a queue is kept under unless told otherwise.
+pub const DEFAULT_QUEUE_KEY: &str = "puzzle-solver";
+
+/// How long a server may hold the lock of a queue before it lapses, in case the server
+/// died or stalled holding it.
+pub const LOCK_TTL: Duration = Duration::from_secs(10);
+
+/// How long a server waits between attempts to take the lock of a queue.
+const LOCK_POLL: Duration = Duration::from_millis(2);
+
+/// How long a server waits before trying Redis again after failing to reach it.
+const RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How many times a server tries a command before giving up on Redis.
+const ATTEMPTS: u32 = 3;
+
+/// How long the listener for events waits for one before checking whether to stop.
+const LISTEN_POLL: Duration = Duration::from_secs(1);
+
+/// Deletes the lock in `KEYS[1]` only if it still holds the token `ARGV[1]`, so that a
+/// server whose lock lapsed does not release another's.
+const UNLOCK: &str = r#"
+if redis.call("get", KEYS[1]) == ARGV[1] then
+    return redis.call("del", KEYS[1])
+end
+return 0
+"#;
+
+/// Sets `KEYS[2]` to `ARGV[2]` and publishes `ARGV[3]` onwards on the channel `KEYS[3]`,
+/// only if the lock in `KEYS[1]` still holds the token `ARGV[1]`, so that a server whose
+/// lock lapsed does not write its stale state over another's.
+const SAVE: &str = r#"
+if redis.call("get", KEYS[1]) ~= ARGV[1] then
+    return 0
+end
+redis.call("set", KEYS[2], ARGV[2])
+for i = 3, #ARGV do
+    redis.call("publish", KEYS[3], ARGV[i])
+end
+return 1
+"#;
+
+/// Why a queue could not be opened, or a request carried out on it.
+#[derive(Debug)]
+pub enum QueueError {
+    /// The URL is not a Redis URL.
+    Url(String),
+    /// Redis could not be reached or refused the connection.
+    Redis(String),
+    /// Redis refused a command, as it would if another program used the key.
+    Refused(String),
+    /// Another server held the lock of the queue for longer than [`LOCK_TTL`].
+    Busy,
+    /// The lock of the queue lapsed before the state was saved, so it was not.
+    Lapsed,
+}
+
+impl fmt::Display for QueueError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QueueError::Url(message) => write!(f, "invalid Redis URL: {}", message),
+            QueueError::Redis(message) => write!(f, "cannot reach Redis: {}", message),
+            QueueError::Refused(message) => write!(f, "Redis refused a command: {}", message),
+            QueueError::Busy => f.write_str("the queue stayed locked by another server"),
+            QueueError::Lapsed => {
+                f.write_str("the lock of the queue lapsed before the state was saved")
+            }
+        }
+    }
+}
+
+impl Error for QueueError {}
+
+impl From<RedisError> for QueueError {
+    fn from(e: RedisError) -> Self {
+        if is_unreachable(&e) {
+            QueueError::Redis(e.to_string())
+        } else {
+            QueueError::Refused(e.to_string())
+        }
+    }
+}
+
+/// A work server's queue kept in Redis; see the [module documentation](self).
+pub struct RedisQueue {
+    client: Client,
+    /// The connection requests go over, reopened after an error.
+    connection: Mutex<Option<Connection>>,
+    key: String,
+    unlock: Script,
+    save: Script,
+}
+
+impl RedisQueue {
+    /// Connects to the Redis server at `url`, such as `redis://127.0.0.1:6379/0`, to keep
+    /// a queue under `key`.
+    ///
+    /// # Returns
+    ///
+    /// The queue, or why Redis could not be reached.
+    pub fn connect(url: &str, key: &str) -> Result<Self, QueueError> {
+        let client = Client::open(url).map_err(|e| QueueError::Url(e.to_string()))?;
+        let mut connection =
+            client.get_connection().map_err(|e| QueueError::Redis(e.to_string()))?;
+        redis::cmd("PING")
+            .query::<()>(&mut connection)
+            .map_err(|e| QueueError::Redis(e.to_string()))?;
+        Ok(RedisQueue {
+            client,
+            connection: Mutex::new(Some(connection)),
+            key: key.to_string(),
+            unlock: Script::new(UNLOCK),
+            save: Script::new(SAVE),
+        })
+    }
+
+    /// The key the queue is kept under.
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Takes the lock of the queue, waiting for any other server to release it, for no
+    /// longer than [`LOCK_TTL`].
+    ///
+    /// # Returns
+    ///
+    /// The token to release it with, or why it could not be taken.
+    pub(crate) fn lock(&self) -> Result<String, QueueError> {
+        let token = format!("{:016x}", rand::random::<u64>());
+        let lock = self.lock_key();
+        let deadline = Instant::now() + LOCK_TTL;
+        loop {
+            let taken: Option<String> = self.retrying(|connection| {
+                redis::cmd("SET")
+                    .arg(&lock)
+                    .arg(&token)
+                    .arg("NX")
+                    .arg("PX")
+                    .arg(LOCK_TTL.as_millis() as u64)
+                    .query(connection)
+            })?;
+            if taken.is_some() {
+                return Ok(token);
+            }
+            if Instant::now() >= deadline {
+                return Err(QueueError::Busy);
+            }
+            thread::sleep(LOCK_POLL);
+        }
+    }
+
+    /// Releases the lock taken with `token`, unless it has lapsed since.
+    pub(crate) fn unlock(&self, token: &str) -> Result<(), QueueError> {
+        let lock = self.lock_key();
+        self.retrying(|connection| {
+            self.unlock.key(&lock).arg(token).invoke::<i64>(connection).map(drop)
+        })?;
+        Ok(())
+    }
+
+    /// Reads the state of the queue, which the caller must hold the lock of.
+    ///
+    /// # Returns
+    ///
+    /// The state, `None` if the queue is new, or why it could not be read.
+    pub(crate) fn load(&self) -> Result<Option<String>, QueueError> {
+        Ok(self.retrying(|connection| redis::cmd("GET").arg(&self.key).query(connection))?)
+    }
+
+    /// Writes the state of the queue and publishes the messages of the `events` that led
+    /// to it, all at once, if the caller still holds the lock taken with `token`.
+    ///
+    /// # Returns
+    ///
+    /// [`QueueError::Lapsed`] if the lock lapsed, in which case nothing is written, or
+    /// why Redis could not be reached.
+    pub(crate) fn save(
+        &self,
+        token: &str,
+        state: &str,
+        events: &[String],
+    ) -> Result<(), QueueError> {
+        let (lock, channel) = (self.lock_key(), self.events_key());
+        let saved: i64 = self.retrying(|connection| {
+            let mut invocation = self.save.key(&lock);
+            invocation.key(&self.key).key(&channel).arg(token).arg(state);
+            for event in events {
+                invocation.arg(event);
+            }
+            invocation.invoke(connection)
+        })?;
+        match saved {
+            0 => Err(QueueError::Lapsed),
+            _ => Ok(()),
+        }
+    }
+
+    /// Hands the message of every event any server publishes on the queue to `deliver`
+    /// until `stop` returns `true`, checking it every second. If Redis cannot be reached,
+    /// it keeps trying; the events published meanwhile are missed.
+    pub(crate) fn listen(&self, stop: impl Fn() -> bool, mut deliver: impl FnMut(String)) {
+        let channel = self.events_key();
+        while !stop() {
+            let subscribed = self.client.get_connection().and_then(|mut connection| {
+                connection.set_read_timeout(Some(LISTEN_POLL))?;
+                let mut pubsub = connection.as_pubsub();
+                pubsub.subscribe(&channel)?;
+                while !stop() {
+                    match pubsub.get_message() {
+                        Ok(message) => deliver(message.get_payload()?),
+                        Err(e) if e.is_timeout() => {}
+                        Err(e) => return Err(e),
+                    }
+                }
+                Ok(())
+            });
+            if subscribed.is_err() {
+                thread::sleep(RETRY_INTERVAL);
+            }
+        }
+    }
+
+    /// Runs `command` on the connection, reconnecting and trying again a second later
+    /// if Redis cannot be reached, up to [`ATTEMPTS`] times in all. The connection is not
+    /// held while waiting, so that other requests can try meanwhile.
+    ///
+    /// # Returns
+    ///
+    /// What the command returned, or the error of its last attempt.
+    fn retrying<T>(
+        &self,
+        mut command: impl FnMut(&mut Connection) -> RedisResult<T>,
+    ) -> RedisResult<T> {
+        let mut attempt = 1;
+        loop {
+            let result = {
+                let mut connection =
+                    self.connection.lock().unwrap_or_else(PoisonError::into_inner);
+                let open = match connection.take() {
+                    Some(open) => Ok(open),
+                    None => self.client.get_connection(),
+                };
+                open.and_then(|mut open| {
+                    let result = command(&mut open);
+                    if !matches!(&result, Err(e) if is_unreachable(e)) {
+                        *connection = Some(open);
+                    }
+                    result
+                })
+            };
+            match result {
+                Err(e) if is_unreachable(&e) && attempt < ATTEMPTS => attempt += 1,
+                result => return result,
+            }
+            thread::sleep(RETRY_INTERVAL);
+        }
+    }
+
+    fn lock_key(&self) -> String {
+        format!("{}:lock", self.key)
+    }
+
+    fn events_key(&self) -> String {
+        format!("{}:events", self.key)
+    }
+}
+
+/// Whether `e` is a failure to reach Redis, which may pass, rather than a refusal.
+fn is_unreachable(e: &RedisError) -> bool {
+    e.is_io_error() || e.is_connection_dropped() || e.is_connection_refusal() || e.is_timeout()
+}
+
+impl fmt::Debug for RedisQueue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RedisQueue").field("key", &self.key).finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,91 @@
+//! Replay protection for services that accept solutions.
+//!
+//! A solution is only worth its work once. [`SeenSolutions`] remembers the solutions a
+//! service has accepted so the same nonce for the same puzzle can be refused the second
+//! time. It is bounded: once full, the oldest entry is forgotten to make room. Pair it
+//! with puzzle expiry ([`Puzzle::with_ttl`]) and give it room for every solution accepted
+//! within one time to live, so an entry is only forgotten after its puzzle has expired.
+
+use std::collections::{HashSet, VecDeque};
+
+use sha2::Sha256;
+
+use crate::{hash_with, Puzzle};
+
+/// A bounded set of accepted solutions, oldest evicted first.
+///
+/// Each solution is remembered by the SHA-256 hash of its full message (data, extra
+/// nonce and nonce in the puzzle's format), so the puzzle's data is not kept.
+#[derive(Clone, Debug)]
+pub struct SeenSolutions {
+    capacity: usize,
+    order: VecDeque<[u8; 32]>,
+    seen: HashSet<[u8; 32]>,
+}
+
+impl SeenSolutions {
+    /// Creates an empty set that remembers at most `capacity` solutions.
+    ///
+    /// # Panics
+    ///
+    /// If `capacity` is zero.
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "a seen-solutions set needs room for at least one entry");
+        SeenSolutions {
+            capacity,
+            order: VecDeque::with_capacity(capacity),
+            seen: HashSet::with_capacity(capacity),
+        }
+    }
+
+    /// The most solutions remembered at once.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// How many solutions are remembered now.
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    /// Whether no solutions are remembered.
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+
+    /// Whether `nonce` has already been recorded for `puzzle`.
+    pub fn contains(&self, puzzle: &Puzzle, nonce: u64) -> bool {
+        self.seen.contains(&fingerprint(puzzle, nonce))
+    }
+
+    /// Records `nonce` as a solution of `puzzle`, forgetting the oldest entry if the set
+    /// is full.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the solution is new, or `false` if it was already recorded, in which
+    /// case it is a replay and nothing changes.
+    pub fn insert(&mut self, puzzle: &Puzzle, nonce: u64) -> bool {
+        let key = fingerprint(puzzle, nonce);
+        if !self.seen.insert(key) {
+            return false;
+        }
+        if self.order.len() == self.capacity {
+            let oldest = self.order.pop_front().expect("a full set is not empty");
+            self.seen.remove(&oldest);
+        }
+        self.order.push_back(key);
+        true
+    }
+
+    /// Forgets every recorded solution.
+    pub fn clear(&mut self) {
+        self.order.clear();
+        self.seen.clear();
+    }
+}
+
+/// The key a solution is remembered by.
+fn fingerprint(puzzle: &Puzzle, nonce: u64) -> [u8; 32] {
+    hash_with::<Sha256>(puzzle, nonce).into()
+}
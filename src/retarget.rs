@@ -0,0 +1,175 @@
+//! Difficulty retargeting, as blockchains do it.
+//!
+//! A chain wants blocks at a steady rate even though the hash rate behind it changes.
+//! Every `interval` blocks it compares how long those blocks took with how long they
+//! should have taken and scales the target by the same ratio: blocks that came too fast
+//! make the target smaller (harder), slow blocks make it larger. Like Bitcoin, which
+//! retargets every 2016 blocks aiming for ten minutes each, a single adjustment is
+//! limited to a factor of [`MAX_ADJUSTMENT`] either way, so one odd window cannot swing
+//! the difficulty wildly.
+
+use std::time::Duration;
+
+use num_bigint::BigUint;
+
+use crate::{Block, Target};
+
+/// The largest factor a single retarget can make the target easier or harder by.
+pub const MAX_ADJUSTMENT: u32 = 4;
+
+/// Scales `target` by `actual / expected`, with the ratio clamped to
+/// `[1 / MAX_ADJUSTMENT, MAX_ADJUSTMENT]` and the result capped at [`Target::MAX`].
+///
+/// # Parameters
+///
+/// - `target`: The target the last window was mined at.
+/// - `actual`: How long the window took.
+/// - `expected`: How long the window should have taken.
+///
+/// # Panics
+///
+/// If `expected` is zero.
+pub fn retarget(target: Target, actual: Duration, expected: Duration) -> Target {
+    assert!(!expected.is_zero(), "the expected timespan must be positive");
+    let expected = expected.as_nanos();
+    let actual = actual.as_nanos().clamp(
+        expected / u128::from(MAX_ADJUSTMENT),
+        expected * u128::from(MAX_ADJUSTMENT),
+    );
+    let scaled = BigUint::from_bytes_be(&target.to_be_bytes()) * actual / expected;
+    let bytes = scaled.to_bytes_be();
+    if bytes.len() > 32 {
+        return Target::MAX;
+    }
+    let mut padded = [0u8; 32];
+    padded[32 - bytes.len()..].copy_from_slice(&bytes);
+    Target::from_be_bytes(padded)
+}
+
+/// When and towards what a chain retargets.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Retarget {
+    /// Number of blocks between adjustments.
+    pub interval: u64,
+    /// The time each block should take on average.
+    pub block_time: Duration,
+}
+
+impl Retarget {
+    /// Retargets every `interval` blocks, aiming for one block per `block_time`.
+    ///
+    /// # Panics
+    ///
+    /// If `interval` or `block_time` is zero.
+    pub fn new(interval: u64, block_time: Duration) -> Self {
+        assert!(interval > 0, "the retarget interval must be at least one block");
+        assert!(!block_time.is_zero(), "the block time must be positive");
+        Retarget { interval, block_time }
+    }
+
+    /// The target for the block after `blocks`, where `now` is when mining it starts,
+    /// in milliseconds since the Unix epoch.
+    ///
+    /// # Returns
+    ///
+    /// `None` if `blocks` is empty. Otherwise the tip's target, retargeted if the
+    /// number of blocks is a multiple of the interval. The window's duration runs from
+    /// the timestamp of its first block to `now`, so it covers exactly `interval`
+    /// solves.
+    pub fn next_target(&self, blocks: &[Block], now: u64) -> Option<Target> {
+        let tip = blocks.last()?;
+        let len = blocks.len() as u64;
+        if !len.is_multiple_of(self.interval) {
+            return Some(tip.target);
+        }
+        let first = &blocks[(len - self.interval) as usize];
+        let actual = Duration::from_millis(now.saturating_sub(first.timestamp));
+        let expected =
+            self.block_time.saturating_mul(u32::try_from(self.interval).unwrap_or(u32::MAX));
+        Some(retarget(tip.target, actual, expected))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINUTE: Duration = Duration::from_secs(60);
+
+    /// `target` as a number.
+    fn value(target: Target) -> BigUint {
+        BigUint::from_bytes_be(&target.to_be_bytes())
+    }
+
+    #[test]
+    fn targets_scale_with_the_time_taken() {
+        let target = Target::from_leading_zero_bits(20);
+        assert_eq!(retarget(target, MINUTE, MINUTE), target);
+        assert_eq!(value(retarget(target, 2 * MINUTE, MINUTE)), value(target) * 2u32);
+        assert_eq!(value(retarget(target, MINUTE / 2, MINUTE)), value(target) / 2u32);
+        assert_eq!(value(retarget(target, 3 * MINUTE, 2 * MINUTE)), value(target) * 3u32 / 2u32);
+    }
+
+    #[test]
+    fn adjustments_are_clamped_to_a_factor_of_four() {
+        let target = Target::from_leading_zero_bits(20);
+        let easiest = value(target) * MAX_ADJUSTMENT;
+        let hardest = value(target) / MAX_ADJUSTMENT;
+        for actual in [4 * MINUTE, 5 * MINUTE, 1000 * MINUTE, Duration::MAX] {
+            assert_eq!(value(retarget(target, actual, MINUTE)), easiest, "{:?}", actual);
+        }
+        for actual in [MINUTE / 4, MINUTE / 5, Duration::from_nanos(1), Duration::ZERO] {
+            assert_eq!(value(retarget(target, actual, MINUTE)), hardest, "{:?}", actual);
+        }
+    }
+
+    #[test]
+    fn easier_targets_stop_at_the_maximum() {
+        assert_eq!(retarget(Target::MAX, 4 * MINUTE, MINUTE), Target::MAX);
+        assert_eq!(retarget(Target::from_leading_zero_bits(1), 4 * MINUTE, MINUTE), Target::MAX);
+        // Targets that still fit are not capped, however close they come.
+        let doubled = retarget(Target::from_leading_zero_bits(1), 2 * MINUTE, MINUTE);
+        assert_eq!(value(doubled), value(Target::MAX) - 1u32);
+        let quadrupled = retarget(Target::from_leading_zero_bits(2), 4 * MINUTE, MINUTE);
+        assert_eq!(value(quadrupled), value(Target::MAX) - 3u32);
+        assert_eq!(value(retarget(Target::MAX, MINUTE, 4 * MINUTE)), value(Target::MAX) / 4u32);
+        assert_eq!(retarget(Target::ZERO, 4 * MINUTE, MINUTE), Target::ZERO);
+    }
+
+    #[test]
+    #[should_panic(expected = "expected timespan must be positive")]
+    fn a_zero_expected_timespan_is_refused() {
+        retarget(Target::MAX, MINUTE, Duration::ZERO);
+    }
+
+    #[test]
+    fn chains_retarget_every_interval() {
+        let target = Target::from_leading_zero_bits(20);
+        let block = |height: u64| Block {
+            height,
+            prev_hash: Vec::new(),
+            timestamp: height * 30_000,
+            target,
+            data: Vec::new(),
+            nonce: 0,
+            hash: Vec::new(),
+        };
+        let retarget = Retarget::new(4, MINUTE);
+        assert_eq!(retarget.next_target(&[], 0), None);
+
+        let blocks: Vec<_> = (0..8).map(block).collect();
+        for len in [1, 2, 3, 5, 7] {
+            assert_eq!(retarget.next_target(&blocks[..len], 0), Some(target), "{} blocks", len);
+        }
+        // Four blocks from 0 to the next block's start at 120 s took half of 240 s.
+        let halved = value(target) / 2u32;
+        assert_eq!(retarget.next_target(&blocks[..4], 120_000).map(value), Some(halved));
+        // The window starts at block 4, at 120 s, and it is 16 minutes later: four times
+        // too slow, and more would still be clamped to four.
+        let quadrupled = value(target) * 4u32;
+        let late = retarget.next_target(&blocks, 120_000 + 16 * 60_000).map(value);
+        assert_eq!(late, Some(quadrupled.clone()));
+        let later = retarget.next_target(&blocks, 120_000 + 60 * 60_000).map(value);
+        assert_eq!(later, Some(quadrupled));
+    }
+}
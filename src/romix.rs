@@ -0,0 +1,143 @@
+//! Scrypt (RFC 7914) over scratch memory the caller keeps from one hash to the next.
+//!
+//! The `scrypt` crate allocates and frees its `N`-block table inside every call, so each
+//! attempt of a memory-hard search would fault the whole table in afresh and could not
+//! place it on huge pages. This is the same function with the table, and everything
+//! else it needs, in a [`ScryptScratch`] that a worker builds once.
+
+use sha2::Sha256;
+
+use crate::digest::Digest;
+use crate::scratch::ScratchBuffer;
+
+/// The 32-bit words in one 64-byte Salsa20 block.
+const BLOCK_WORDS: usize = 16;
+
+/// The working memory of one scrypt hash: the `N`-block table, the `p` blocks mixed in
+/// turn, and a block of room to mix into.
+pub(crate) struct ScryptScratch {
+    log_n: u8,
+    /// The number of 64-byte blocks in half a scrypt block.
+    r: usize,
+    table: ScratchBuffer<u32>,
+    blocks: Vec<u8>,
+    x: Vec<u32>,
+    y: Vec<u32>,
+}
+
+impl ScryptScratch {
+    /// The memory for `params`, on huge pages if `huge_pages` is set; see the
+    /// [`scratch`](crate::scratch) module.
+    pub(crate) fn new(params: &scrypt::Params, huge_pages: bool) -> Self {
+        let r = params.r() as usize;
+        // One scrypt block is `2r` Salsa20 blocks; the parameters were checked to fit.
+        let words = 2 * r * BLOCK_WORDS;
+        ScryptScratch {
+            log_n: params.log_n(),
+            r,
+            table: ScratchBuffer::new(words << params.log_n(), 0, huge_pages),
+            blocks: vec![0; params.p() as usize * words * 4],
+            x: vec![0; words],
+            y: vec![0; words],
+        }
+    }
+
+    /// Hashes `password` with `salt` into `out`, as `scrypt::scrypt` with the
+    /// parameters this memory was built for.
+    pub(crate) fn hash(&mut self, password: &[u8], salt: &[u8], out: &mut [u8]) {
+        // Taken out for the loop, as mixing each block needs the rest of the memory.
+        let mut blocks = std::mem::take(&mut self.blocks);
+        pbkdf2_sha256_once(password, salt, &mut blocks);
+        let words = self.x.len();
+        for block in blocks.chunks_exact_mut(words * 4) {
+            for (word, bytes) in self.x.iter_mut().zip(block.chunks_exact(4)) {
+                *word = u32::from_le_bytes(bytes.try_into().unwrap());
+            }
+            self.ro_mix();
+            for (bytes, word) in block.chunks_exact_mut(4).zip(&self.x) {
+                bytes.copy_from_slice(&word.to_le_bytes());
+            }
+        }
+        pbkdf2_sha256_once(password, &blocks, out);
+        self.blocks = blocks;
+    }
+
+    /// Mixes the block in `x` through the table in place: scryptROMix.
+    fn ro_mix(&mut self) {
+        let words = self.x.len();
+        let n = 1usize << self.log_n;
+        for entry in self.table.chunks_exact_mut(words) {
+            entry.copy_from_slice(&self.x);
+            block_mix(&self.x, &mut self.y, self.r);
+            std::mem::swap(&mut self.x, &mut self.y);
+        }
+        for _ in 0..n {
+            // Integerify: the first word of the last Salsa20 block, modulo `N`.
+            let j = self.x[words - BLOCK_WORDS] as usize & (n - 1);
+            let entry = &self.table[j * words..(j + 1) * words];
+            for (x, v) in self.x.iter_mut().zip(entry) {
+                *x ^= v;
+            }
+            block_mix(&self.x, &mut self.y, self.r);
+            std::mem::swap(&mut self.x, &mut self.y);
+        }
+    }
+}
+
+/// scryptBlockMix of the `2r` Salsa20 blocks of `input` into `output`: the even blocks
+/// of the chain first, then the odd ones.
+fn block_mix(input: &[u32], output: &mut [u32], r: usize) {
+    let mut x: [u32; BLOCK_WORDS] = input[input.len() - BLOCK_WORDS..].try_into().unwrap();
+    for (i, block) in input.chunks_exact(BLOCK_WORDS).enumerate() {
+        for (x, b) in x.iter_mut().zip(block) {
+            *x ^= b;
+        }
+        salsa20_8(&mut x);
+        let at = (i / 2 + (i % 2) * r) * BLOCK_WORDS;
+        output[at..at + BLOCK_WORDS].copy_from_slice(&x);
+    }
+}
+
+/// The Salsa20/8 core: four double rounds, then the input added back in.
+fn salsa20_8(block: &mut [u32; BLOCK_WORDS]) {
+    let mut x = *block;
+    let quarter = |x: &mut [u32; BLOCK_WORDS], a: usize, b: usize, c: usize, d: usize| {
+        x[b] ^= x[a].wrapping_add(x[d]).rotate_left(7);
+        x[c] ^= x[b].wrapping_add(x[a]).rotate_left(9);
+        x[d] ^= x[c].wrapping_add(x[b]).rotate_left(13);
+        x[a] ^= x[d].wrapping_add(x[c]).rotate_left(18);
+    };
+    for _ in 0..4 {
+        // Columns, then rows.
+        quarter(&mut x, 0, 4, 8, 12);
+        quarter(&mut x, 5, 9, 13, 1);
+        quarter(&mut x, 10, 14, 2, 6);
+        quarter(&mut x, 15, 3, 7, 11);
+        quarter(&mut x, 0, 1, 2, 3);
+        quarter(&mut x, 5, 6, 7, 4);
+        quarter(&mut x, 10, 11, 8, 9);
+        quarter(&mut x, 15, 12, 13, 14);
+    }
+    for (word, mixed) in block.iter_mut().zip(x) {
+        *word = word.wrapping_add(mixed);
+    }
+}
+
+/// PBKDF2-HMAC-SHA256 with a single iteration, the only count scrypt uses, filling
+/// `out`.
+fn pbkdf2_sha256_once(password: &[u8], salt: &[u8], out: &mut [u8]) {
+    let mut key = [0u8; 64];
+    if password.len() > key.len() {
+        key[..32].copy_from_slice(&Sha256::digest(password));
+    } else {
+        key[..password.len()].copy_from_slice(password);
+    }
+    // The keyed states are the same for every output block, so they are built once.
+    let inner = Sha256::new().chain_update(key.map(|b| b ^ 0x36)).chain_update(salt);
+    let outer = Sha256::new().chain_update(key.map(|b| b ^ 0x5c));
+    for (index, chunk) in (1u32..).zip(out.chunks_mut(32)) {
+        let inner = inner.clone().chain_update(index.to_be_bytes()).finalize();
+        let block = outer.clone().chain_update(inner).finalize();
+        chunk.copy_from_slice(&block[..chunk.len()]);
+    }
+}
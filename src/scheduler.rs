@@ -0,0 +1,421 @@
+//! A long-lived pool of mining threads that works on puzzles by priority.
+//!
+//! Where [`parallel_mine_batch`](crate::parallel_mine_batch) shares its threads evenly
+//! between a fixed list of puzzles, a [`Scheduler`] accepts puzzles at any time, each
+//! with a priority and an optional deadline, and always gives its threads to the most
+//! urgent work. As in the batch miner, every puzzle's nonce window is cut into chunks
+//! that workers claim one at a time; a worker claims from the highest-priority puzzle
+//! that still has work, breaking ties by the earliest deadline and then by submission
+//! order, so equal puzzles are solved one after another rather than all at once.
+//!
+//! Lower-priority work is preempted: workers check every [`PREEMPT_CHECK`] nonces
+//! whether a higher-priority puzzle is waiting, and if so hand the rest of their chunk
+//! back to its puzzle and move over. The returned nonces are claimed again before any
+//! new ones, so nothing is skipped. A puzzle whose deadline passes before it is solved
+//! is abandoned with [`PuzzleError::DeadlineExceeded`].
+
+use std::cmp::Reverse;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+use sha2::Sha256;
+
+use crate::digest::Digest;
+use crate::{default_nonce_range, BatchResult, PrehashedPuzzle, Puzzle, PuzzleError, Solution};
+
+/// How many nonces a worker claims from a puzzle at a time.
+const CHUNK_SIZE: u64 = 1 << 16;
+
+/// How many nonces a worker hashes between checks for preemption and deadlines.
+pub const PREEMPT_CHECK: u64 = 1 << 10;
+
+/// A puzzle waiting in or being worked on by a [`Scheduler`].
+struct Job<D> {
+    puzzle: PrehashedPuzzle<D>,
+    extra_nonce: Option<u64>,
+    priority: u32,
+    deadline: Option<Instant>,
+    /// Submission order, used to break ties.
+    sequence: u64,
+    submitted: Instant,
+    /// Set once the job is solved, exhausted, expired or cancelled, so workers on it stop.
+    stop: AtomicBool,
+    hashes: AtomicU64,
+    /// The outcome, filled in exactly once.
+    result: Mutex<Option<BatchResult>>,
+    finished: Condvar,
+}
+
+impl<D> Job<D> {
+    /// Records the outcome and wakes anyone waiting for it; only the first call has any
+    /// effect.
+    fn finish(&self, result: Result<Solution, PuzzleError>) {
+        self.stop.store(true, Ordering::Relaxed);
+        let mut slot = self.result.lock().unwrap_or_else(PoisonError::into_inner);
+        if slot.is_none() {
+            *slot = Some(BatchResult {
+                result,
+                hashes: self.hashes.load(Ordering::Relaxed),
+                elapsed: self.submitted.elapsed(),
+            });
+            self.finished.notify_all();
+        }
+    }
+
+    /// Whether the job's deadline has passed.
+    fn is_overdue(&self, now: Instant) -> bool {
+        self.deadline.is_some_and(|deadline| now >= deadline)
+    }
+}
+
+/// The part of a job's state that only changes under the scheduler lock.
+struct Entry<D> {
+    job: Arc<Job<D>>,
+    /// The last nonce of the window.
+    end: u64,
+    /// Start of the next unclaimed chunk, or `None` once the window is handed out.
+    next_start: Option<u64>,
+    /// Ranges handed back by preempted workers, claimed before new chunks.
+    returned: Vec<(u64, u64)>,
+    /// Chunks that have been claimed but not yet finished.
+    in_flight: usize,
+}
+
+impl<D> Entry<D> {
+    /// Whether there are nonces left to hand out.
+    fn has_work(&self) -> bool {
+        !self.returned.is_empty() || self.next_start.is_some()
+    }
+
+    /// Hands out the next range of this job's window.
+    fn claim(&mut self) -> Option<(u64, u64)> {
+        let chunk = self.returned.pop().or_else(|| {
+            let start = self.next_start?;
+            let end = start.saturating_add(CHUNK_SIZE - 1).min(self.end);
+            self.next_start = end.checked_add(1).filter(|&next| next <= self.end);
+            Some((start, end))
+        })?;
+        self.in_flight += 1;
+        Some(chunk)
+    }
+
+    /// The order in which jobs are served: higher priority first, then earlier
+    /// deadline (any deadline before none), then earlier submission.
+    fn urgency(&self) -> (Reverse<u32>, bool, Option<Instant>, u64) {
+        let job = &self.job;
+        (Reverse(job.priority), job.deadline.is_none(), job.deadline, job.sequence)
+    }
+}
+
+/// State shared by the scheduler and its workers.
+struct Shared<D> {
+    state: Mutex<State<D>>,
+    /// Signalled when work arrives or the scheduler shuts down.
+    work_ready: Condvar,
+    /// The highest priority among jobs with work left, so busy workers can tell
+    /// whether they should yield without taking the lock.
+    top_priority: AtomicU32,
+}
+
+struct State<D> {
+    entries: Vec<Entry<D>>,
+    next_sequence: u64,
+    shutdown: bool,
+}
+
+impl<D> Shared<D> {
+    /// Finishes overdue jobs, drops finished ones and refreshes `top_priority`.
+    fn tidy(&self, state: &mut MutexGuard<'_, State<D>>) {
+        let now = Instant::now();
+        state.entries.retain(|entry| {
+            if entry.job.is_overdue(now) {
+                entry.job.finish(Err(PuzzleError::DeadlineExceeded));
+            } else if !entry.has_work() && entry.in_flight == 0 {
+                entry.job.finish(Err(PuzzleError::Exhausted));
+            }
+            // Finished jobs with chunks in flight stay until those chunks come back.
+            !entry.job.stop.load(Ordering::Relaxed) || entry.in_flight > 0
+        });
+        let top = state
+            .entries
+            .iter()
+            .filter(|entry| entry.has_work() && !entry.job.stop.load(Ordering::Relaxed))
+            .map(|entry| entry.job.priority)
+            .max()
+            .unwrap_or(0);
+        self.top_priority.store(top, Ordering::Relaxed);
+    }
+}
+
+/// The handle to a puzzle submitted to a [`Scheduler`].
+pub struct ScheduledJob<D = Sha256> {
+    job: Arc<Job<D>>,
+}
+
+impl<D> ScheduledJob<D> {
+    /// Blocks until the puzzle is solved, exhausted, past its deadline or cancelled.
+    ///
+    /// # Returns
+    ///
+    /// The outcome, with the hashes spent on it and the time since it was submitted. The
+    /// outcome is [`PuzzleError::PoisonedLock`] if a thread panicked while recording it.
+    pub fn wait(self) -> BatchResult {
+        let mut result = match self.job.result.lock() {
+            Ok(result) => result,
+            Err(_) => return self.poisoned(),
+        };
+        loop {
+            if let Some(result) = result.take() {
+                return result;
+            }
+            result = match self.job.finished.wait(result) {
+                Ok(result) => result,
+                Err(_) => return self.poisoned(),
+            };
+        }
+    }
+
+    /// Whether the puzzle has finished, so [`wait`](ScheduledJob::wait) would not block.
+    pub fn is_finished(&self) -> bool {
+        self.job.result.lock().map_or(true, |result| result.is_some())
+    }
+
+    /// Blocks until the puzzle has finished, like [`wait`](ScheduledJob::wait), but only
+    /// lends its outcome to `f`, so the handle can still be queried afterwards.
+    pub fn wait_with<R>(&self, f: impl FnOnce(&BatchResult) -> R) -> R {
+        let waited = self.job.result.lock().and_then(|result| {
+            self.job.finished.wait_while(result, |result| result.is_none())
+        });
+        match waited {
+            Ok(result) => f(result
+                .as_ref()
+                .expect("the outcome is only taken by `wait`, which consumes the handle")),
+            Err(_) => f(&self.poisoned()),
+        }
+    }
+
+    /// The outcome of a puzzle whose lock was poisoned.
+    fn poisoned(&self) -> BatchResult {
+        BatchResult {
+            result: Err(PuzzleError::PoisonedLock),
+            hashes: self.job.hashes.load(Ordering::Relaxed),
+            elapsed: self.job.submitted.elapsed(),
+        }
+    }
+
+    /// The number of nonces hashed for the puzzle so far.
+    pub fn hashes(&self) -> u64 {
+        self.job.hashes.load(Ordering::Relaxed)
+    }
+
+    /// Stops work on the puzzle; it finishes with [`PuzzleError::Cancelled`] unless it
+    /// already finished.
+    pub fn cancel(&self) {
+        self.job.finish(Err(PuzzleError::Cancelled));
+    }
+}
+
+/// A pool of worker threads that mines submitted puzzles by priority.
+///
+/// Dropping the scheduler cancels every unfinished puzzle and joins the workers.
+pub struct Scheduler<D = Sha256> {
+    shared: Arc<Shared<D>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl Scheduler<Sha256> {
+    /// Starts a SHA-256 scheduler with `num_threads` workers. A value of zero is
+    /// treated as one.
+    pub fn new(num_threads: usize) -> Self {
+        Scheduler::with_hasher(num_threads)
+    }
+}
+
+impl<D> Scheduler<D>
+where
+    D: Digest + Clone + Send + Sync + 'static,
+{
+    /// Starts a scheduler with `num_threads` workers that hash with `D`. A value of zero
+    /// is treated as one.
+    pub fn with_hasher(num_threads: usize) -> Self {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State { entries: Vec::new(), next_sequence: 0, shutdown: false }),
+            work_ready: Condvar::new(),
+            top_priority: AtomicU32::new(0),
+        });
+        let workers = (0..num_threads.max(1))
+            .map(|_| {
+                let shared = Arc::clone(&shared);
+                thread::spawn(move || work(&shared))
+            })
+            .collect();
+        Scheduler { shared, workers }
+    }
+
+    /// The number of worker threads.
+    pub fn num_threads(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Queues `puzzle` with a `priority` (higher runs first) and an optional
+    /// `deadline`, and returns a handle to its outcome.
+    ///
+    /// The puzzle searches the same window as [`parallel_mine_with`](crate::parallel_mine_with)
+    /// would with the scheduler's thread count. Its current extra nonce, if any, is used
+    /// as is and is not rolled.
+    ///
+    /// # Returns
+    ///
+    /// The handle, or [`PuzzleError::PoisonedLock`] if a worker panicked while holding
+    /// the queue, which can then no longer be trusted.
+    pub fn submit(
+        &self,
+        puzzle: &Puzzle,
+        priority: u32,
+        deadline: Option<Instant>,
+    ) -> Result<ScheduledJob<D>, PuzzleError> {
+        let nonces = default_nonce_range();
+        let mut state = self.shared.state.lock()?;
+        let job = Arc::new(Job {
+            puzzle: PrehashedPuzzle::from_puzzle(puzzle),
+            extra_nonce: puzzle.extra_nonce,
+            priority,
+            deadline,
+            sequence: state.next_sequence,
+            submitted: Instant::now(),
+            stop: AtomicBool::new(false),
+            hashes: AtomicU64::new(0),
+            result: Mutex::new(None),
+            finished: Condvar::new(),
+        });
+        state.next_sequence += 1;
+        state.entries.push(Entry {
+            job: Arc::clone(&job),
+            end: *nonces.end(),
+            next_start: (!nonces.is_empty()).then_some(*nonces.start()),
+            returned: Vec::new(),
+            in_flight: 0,
+        });
+        self.shared.tidy(&mut state);
+        self.shared.work_ready.notify_all();
+        Ok(ScheduledJob { job })
+    }
+}
+
+impl<D> Drop for Scheduler<D> {
+    fn drop(&mut self) {
+        {
+            let mut state = self.shared.state.lock().unwrap_or_else(PoisonError::into_inner);
+            state.shutdown = true;
+            for entry in &state.entries {
+                entry.job.finish(Err(PuzzleError::Cancelled));
+            }
+        }
+        self.shared.work_ready.notify_all();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// A worker's main loop: claim the most urgent chunk, search it, report back. A worker
+/// stops once another panicked holding the queue, which it can no longer trust.
+fn work<D: Digest + Clone>(shared: &Shared<D>) {
+    let Ok(mut state) = shared.state.lock() else {
+        return;
+    };
+    loop {
+        shared.tidy(&mut state);
+        if state.shutdown {
+            return;
+        }
+        let claimed = state
+            .entries
+            .iter_mut()
+            .filter(|entry| entry.has_work() && !entry.job.stop.load(Ordering::Relaxed))
+            .min_by_key(|entry| entry.urgency())
+            .and_then(|entry| entry.claim().map(|chunk| (Arc::clone(&entry.job), chunk)));
+        let Some((job, (start, end))) = claimed else {
+            // Sleep until new work arrives, or until the next deadline needs enforcing.
+            let next_deadline = state
+                .entries
+                .iter()
+                .filter(|entry| !entry.job.stop.load(Ordering::Relaxed))
+                .filter_map(|entry| entry.job.deadline)
+                .min();
+            let waited = match next_deadline {
+                Some(deadline) => {
+                    let timeout = deadline.saturating_duration_since(Instant::now());
+                    shared.work_ready.wait_timeout(state, timeout).ok().map(|(state, _)| state)
+                }
+                None => shared.work_ready.wait(state).ok(),
+            };
+            let Some(waited) = waited else {
+                return;
+            };
+            state = waited;
+            continue;
+        };
+        drop(state);
+
+        let (tried, outcome) = search_chunk(shared, &job, start, end);
+        job.hashes.fetch_add(tried, Ordering::Relaxed);
+
+        let Ok(locked) = shared.state.lock() else {
+            return;
+        };
+        state = locked;
+        if let Some(entry) = state.entries.iter_mut().find(|entry| Arc::ptr_eq(&entry.job, &job)) {
+            entry.in_flight -= 1;
+            match outcome {
+                ChunkOutcome::Solved(solution) => job.finish(Ok(solution)),
+                ChunkOutcome::Yielded(rest) => entry.returned.push(rest),
+                ChunkOutcome::Done => {}
+            }
+        }
+    }
+}
+
+/// How a worker left a chunk.
+enum ChunkOutcome {
+    /// A nonce in the chunk solved the puzzle.
+    Solved(Solution),
+    /// The worker was preempted; the range is the part it did not search.
+    Yielded((u64, u64)),
+    /// The chunk was searched without success, or the job was stopped.
+    Done,
+}
+
+/// Searches `start..=end` for `job`, stopping early if the job stops, its deadline
+/// passes or a higher-priority job is waiting.
+///
+/// # Returns
+///
+/// The number of nonces hashed and how the chunk ended.
+fn search_chunk<D: Digest + Clone>(
+    shared: &Shared<D>,
+    job: &Job<D>,
+    start: u64,
+    end: u64,
+) -> (u64, ChunkOutcome) {
+    let mut tried = 0u64;
+    for nonce in start..=end {
+        if tried > 0 && tried.is_multiple_of(PREEMPT_CHECK) {
+            if job.stop.load(Ordering::Relaxed) || job.is_overdue(Instant::now()) {
+                return (tried, ChunkOutcome::Done);
+            }
+            if shared.top_priority.load(Ordering::Relaxed) > job.priority {
+                return (tried, ChunkOutcome::Yielded((nonce, end)));
+            }
+        }
+        tried += 1;
+        let hash = job.puzzle.hash(nonce);
+        if job.puzzle.target.is_met_by(&hash) {
+            let solution = Solution { nonce, hash: hash.to_vec(), extra_nonce: job.extra_nonce };
+            return (tried, ChunkOutcome::Solved(solution));
+        }
+    }
+    (tried, ChunkOutcome::Done)
+}
@@ -0,0 +1,112 @@
+//! Scratch memory for the memory-hard hashes.
+//!
+//! Argon2id and scrypt spend nearly all of their time reading and writing a table of
+//! several megabytes at pseudo-random offsets, so almost every access misses both the
+//! cache and the TLB. A [`ScratchBuffer`] starts on a cache line, so no block of the
+//! table straddles two lines. Asked for huge pages, a buffer of at least one huge page
+//! is instead aligned to and padded out to whole 2 MiB pages, and on Linux the kernel is
+//! advised to back it with transparent huge pages, each of which takes one TLB entry
+//! where 512 normal pages would take 512. Where the kernel has transparent huge pages
+//! turned off (see [`huge_pages_available`](crate::huge_pages_available)), and on other
+//! platforms, the advice is ignored and the buffer uses normal pages.
+//!
+//! Every element is written when the buffer is allocated, so the pages are faulted in
+//! before the first hash rather than during it.
+
+use std::alloc::{self, Layout};
+use std::ops::{Deref, DerefMut};
+use std::ptr::NonNull;
+use std::slice;
+
+/// The size of a cache line on the processors the miner runs on.
+const CACHE_LINE: usize = 64;
+
+/// The size of a transparent huge page on x86-64 and of the usual one on AArch64.
+const HUGE_PAGE: usize = 2 << 20;
+
+/// A fixed-size array of `T` on its own aligned allocation; see the [module
+/// documentation](self).
+pub(crate) struct ScratchBuffer<T: Copy> {
+    ptr: NonNull<T>,
+    len: usize,
+    /// The layout it was allocated with, or `None` if it holds no bytes.
+    layout: Option<Layout>,
+}
+
+// SAFETY: the buffer owns its elements like a `Vec<T>` does.
+unsafe impl<T: Copy + Send> Send for ScratchBuffer<T> {}
+// SAFETY: shared references only give shared access to the elements.
+unsafe impl<T: Copy + Sync> Sync for ScratchBuffer<T> {}
+
+impl<T: Copy> ScratchBuffer<T> {
+    /// `len` copies of `value`, on huge pages if `huge_pages` is set and the buffer
+    /// fills at least one.
+    pub(crate) fn new(len: usize, value: T, huge_pages: bool) -> Self {
+        let size = len.checked_mul(size_of::<T>()).expect("scratch buffer size overflows");
+        if size == 0 {
+            return ScratchBuffer { ptr: NonNull::dangling(), len, layout: None };
+        }
+        let huge_pages = huge_pages && size >= HUGE_PAGE;
+        let (size, align) = if huge_pages {
+            (size.next_multiple_of(HUGE_PAGE), HUGE_PAGE)
+        } else {
+            (size, CACHE_LINE)
+        };
+        let layout = Layout::from_size_align(size, align.max(align_of::<T>()))
+            .expect("scratch buffer size overflows");
+        // SAFETY: `layout` has a non-zero size.
+        let raw = unsafe { alloc::alloc(layout) };
+        let Some(ptr) = NonNull::new(raw.cast::<T>()) else {
+            alloc::handle_alloc_error(layout);
+        };
+        if huge_pages {
+            advise_huge_pages(raw, size);
+        }
+        for i in 0..len {
+            // SAFETY: `i` is within the allocation, which is aligned for `T`.
+            unsafe { ptr.as_ptr().add(i).write(value) };
+        }
+        ScratchBuffer { ptr, len, layout: Some(layout) }
+    }
+}
+
+impl<T: Copy> Deref for ScratchBuffer<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        // SAFETY: the first `len` elements were initialized in `new`.
+        unsafe { slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl<T: Copy> DerefMut for ScratchBuffer<T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        // SAFETY: the first `len` elements were initialized in `new`, and `&mut self`
+        // makes this the only reference to them.
+        unsafe { slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl<T: Copy> Drop for ScratchBuffer<T> {
+    fn drop(&mut self) {
+        if let Some(layout) = self.layout {
+            // SAFETY: the buffer was allocated in `new` with this layout, and `T: Copy`
+            // has nothing to drop.
+            unsafe { alloc::dealloc(self.ptr.as_ptr().cast(), layout) };
+        }
+    }
+}
+
+/// Asks the kernel to back the `size` bytes at `ptr` with transparent huge pages. The
+/// advice is only a hint, so a refusal is ignored.
+#[cfg(target_os = "linux")]
+fn advise_huge_pages(ptr: *mut u8, size: usize) {
+    // SAFETY: the range is a whole allocation of ours, aligned to a huge page, and
+    // `madvise` only changes how the kernel backs it.
+    unsafe { libc::madvise(ptr.cast(), size, libc::MADV_HUGEPAGE) };
+}
+
+/// Asks the kernel to back the `size` bytes at `ptr` with transparent huge pages. The
+/// advice is only a hint, so a refusal is ignored.
+#[cfg(not(target_os = "linux"))]
+fn advise_huge_pages(_ptr: *mut u8, _size: usize) {}
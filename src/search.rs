@@ -0,0 +1,399 @@
+//! The thread engine behind every miner, open to new kinds of puzzle.
+//!
+//! Every search runs the same way. Worker threads take fixed-size chunks of the nonce
+//! window from a shared cursor, lowest first, and each tests the candidates of its chunk
+//! in order before taking the next. Fast workers simply take more chunks, so no core sits
+//! idle while a slower one finishes a fixed share, and the low nonces are searched first
+//! whatever the thread count. As soon as one worker succeeds, a shared flag stops all the
+//! others, and the winner's result is returned the moment it reports, without waiting
+//! for the others to wind down. Between the flag checks, each worker tests a batch of
+//! candidates sized from its own hash rate, so a batch takes about a tenth of a
+//! millisecond: fast hashes are not slowed down by checks, and a stop, pause or rest
+//! is still noticed within moments. Only the test itself differs from one puzzle to the
+//! next. A [`ParallelSearch`] supplies that test and [`parallel_search`] runs the rest,
+//! so a new kind of puzzle reuses the engine instead of its threading code. Inside the
+//! crate the same engine also drives paused, cancelled and checkpointed searches.
+
+use std::marker::PhantomData;
+use std::ops::RangeInclusive;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::control::{Control, Work, PROGRESS_INTERVAL};
+use crate::pool::WorkerPool;
+#[cfg(feature = "profile")]
+use crate::profile::{Activity, Stopwatch};
+use crate::{PuzzleError, SearchOrder, Solution};
+
+/// The value of the winner index before any worker has found a solution.
+const NO_WINNER: usize = usize::MAX;
+
+/// The fewest candidates a worker tests between checks of the shared flags: a whole
+/// batch of SIMD lanes.
+const MIN_BATCH: u64 = 8;
+
+/// The most candidates a worker tests between checks of the shared flags, so its
+/// progress is still recorded at every [`PROGRESS_INTERVAL`].
+const MAX_BATCH: u64 = PROGRESS_INTERVAL;
+
+/// How long a worker aims to spend on one batch. Once a worker has found a solution,
+/// each of the others wastes at most about twice this finishing its batch.
+const BATCH_TIME: Duration = Duration::from_micros(100);
+
+/// Sizes a worker's batches from how long its last one took, so each takes about
+/// [`BATCH_TIME`].
+///
+/// Sizes are powers of two between [`MIN_BATCH`] and [`MAX_BATCH`]. A batch that took
+/// under half the aim doubles the next size, at the next offset it divides, so batches
+/// stay aligned to [`PROGRESS_INTERVAL`]; one that took over twice the aim halves it.
+#[derive(Debug)]
+pub(crate) struct BatchSize {
+    size: u64,
+    /// Whether the last batch was quick enough to double the size.
+    grow: bool,
+}
+
+impl BatchSize {
+    pub(crate) fn new() -> Self {
+        BatchSize { size: MIN_BATCH, grow: false }
+    }
+
+    /// The size of the batch that starts `offset` candidates into the worker's range.
+    pub(crate) fn next(&mut self, offset: u64) -> u64 {
+        if self.grow && self.size < MAX_BATCH && offset.is_multiple_of(2 * self.size) {
+            self.size *= 2;
+            self.grow = false;
+        }
+        self.size
+    }
+
+    /// Records that the last batch took `elapsed`.
+    pub(crate) fn record(&mut self, elapsed: Duration) {
+        self.grow = elapsed < BATCH_TIME / 2;
+        if elapsed > 2 * BATCH_TIME && self.size > MIN_BATCH {
+            self.size /= 2;
+        }
+    }
+}
+
+/// The puzzle-specific part of a parallel search: what a worker needs and how it tests
+/// one candidate.
+pub trait ParallelSearch: Send + Sync + 'static {
+    /// Scratch state each worker builds once and reuses for every candidate, such as the
+    /// working memory of a memory-hard hash. Use `()` when none is needed.
+    type State;
+    /// What a successful candidate produces.
+    type Output: Send + 'static;
+
+    /// Builds the state of one worker, on that worker's thread.
+    fn init(&self) -> Self::State;
+
+    /// Tests the candidate `nonce`, returning the output if it solves the puzzle.
+    fn test(&self, state: &mut Self::State, nonce: u64) -> Option<Self::Output>;
+
+    /// Tests the candidates `nonces` in order, returning the index and output of the
+    /// first one that solves the puzzle.
+    ///
+    /// The engine hands each worker its candidates in batches through this method, from a
+    /// few up to a few thousand at a time, depending on how fast the test is.
+    /// The default tests them one by one with [`test`](ParallelSearch::test); a search
+    /// that can test several candidates at once, e.g. with SIMD, overrides it.
+    fn test_batch(
+        &self,
+        state: &mut Self::State,
+        nonces: &[u64],
+    ) -> Option<(usize, Self::Output)> {
+        nonces.iter().enumerate().find_map(|(i, &nonce)| Some((i, self.test(state, nonce)?)))
+    }
+}
+
+/// Runs `search` over `nonces`, split across `num_threads` threads, until a worker finds
+/// a candidate that passes.
+///
+/// # Parameters
+///
+/// - `search`: The test to run on each candidate, shared by every worker.
+/// - `nonces`: The inclusive window `start_nonce..=end_nonce` to search.
+/// - `num_threads`: How many worker threads to spawn. A value of zero is treated as one.
+///
+/// # Returns
+///
+/// The output of the first candidate that passed, [`PuzzleError::EmptyRange`] if
+/// `nonces` is empty, or [`PuzzleError::Exhausted`] if no candidate passed.
+pub fn parallel_search<S: ParallelSearch>(
+    search: S,
+    nonces: RangeInclusive<u64>,
+    num_threads: usize,
+) -> Result<S::Output, PuzzleError> {
+    if nonces.is_empty() {
+        return Err(PuzzleError::EmptyRange);
+    }
+    let work = Work::new(nonces, num_threads, SearchOrder::Sequential);
+    search_parallel_controlled(Arc::new(work), search, Arc::new(Control::default()), None)
+}
+
+/// The search every hash-based miner runs: hash each nonce with `hash` and accept the
+/// first hash `accept` approves.
+///
+/// `hash` and `accept` are shared by reference between the workers, so any data they
+/// capture is stored once rather than copied per thread.
+pub(crate) struct HashSearch<S, O, I, H, P> {
+    init: I,
+    hash: H,
+    accept: P,
+    _types: PhantomData<fn() -> (S, O)>,
+}
+
+impl<S, O, I, H, P> HashSearch<S, O, I, H, P>
+where
+    I: Fn() -> S,
+    H: Fn(&mut S, u64) -> O,
+    P: Fn(&[u8]) -> bool,
+{
+    /// A search whose workers each build their state with `init` and pass it to every
+    /// `hash` call.
+    pub(crate) fn new(init: I, hash: H, accept: P) -> Self {
+        HashSearch { init, hash, accept, _types: PhantomData }
+    }
+}
+
+impl<S, O, I, H, P> ParallelSearch for HashSearch<S, O, I, H, P>
+where
+    S: 'static,
+    O: AsRef<[u8]> + 'static,
+    I: Fn() -> S + Send + Sync + 'static,
+    H: Fn(&mut S, u64) -> O + Send + Sync + 'static,
+    P: Fn(&[u8]) -> bool + Send + Sync + 'static,
+{
+    type State = S;
+    type Output = Solution;
+
+    fn init(&self) -> S {
+        (self.init)()
+    }
+
+    fn test(&self, state: &mut S, nonce: u64) -> Option<Solution> {
+        let hash = (self.hash)(state, nonce);
+        let hash = hash.as_ref();
+        (self.accept)(hash).then(|| Solution { nonce, hash: hash.to_vec(), extra_nonce: None })
+    }
+}
+
+/// Runs a [`HashSearch`] over `nonces`: every nonce is hashed with `hash`, and all
+/// workers stop once `accept` returns `true` for one of the hashes.
+pub(crate) fn search_parallel<H, O, P>(
+    nonces: RangeInclusive<u64>,
+    num_threads: usize,
+    hash: H,
+    accept: P,
+) -> Result<Solution, PuzzleError>
+where
+    H: Fn(u64) -> O + Send + Sync + 'static,
+    O: AsRef<[u8]> + 'static,
+    P: Fn(&[u8]) -> bool + Send + Sync + 'static,
+{
+    search_parallel_with_state(nonces, num_threads, || (), move |_, nonce| hash(nonce), accept)
+}
+
+/// Like [`search_parallel`], but every worker first builds its own state with `init`
+/// and passes it to each `hash` call.
+///
+/// Memory-hard hashes build their searches this way, to allocate their scratch memory
+/// once per thread, so the total memory in use is the per-thread size times the number
+/// of workers.
+pub(crate) fn search_parallel_with_state<S, I, H, O, P>(
+    nonces: RangeInclusive<u64>,
+    num_threads: usize,
+    init: I,
+    hash: H,
+    accept: P,
+) -> Result<Solution, PuzzleError>
+where
+    S: 'static,
+    I: Fn() -> S + Send + Sync + 'static,
+    H: Fn(&mut S, u64) -> O + Send + Sync + 'static,
+    O: AsRef<[u8]> + 'static,
+    P: Fn(&[u8]) -> bool + Send + Sync + 'static,
+{
+    parallel_search(HashSearch::new(init, hash, accept), nonces, num_threads)
+}
+
+/// Runs `search` with every worker passing through `control` before each nonce, so the
+/// search can be paused, resumed, throttled or cancelled from outside.
+///
+/// Each worker takes chunks of positions from `work` until none are left, tries them in
+/// the order of `work`, and records in its slot how far it got, so a snapshot of `work`
+/// tells which nonces are still to be tried. A cancelled search without a solution ends
+/// with [`PuzzleError::Cancelled`].
+pub(crate) fn search_parallel_controlled<S: ParallelSearch>(
+    work: Arc<Work>,
+    search: S,
+    control: Arc<Control>,
+    pool: Option<&WorkerPool>,
+) -> Result<S::Output, PuzzleError> {
+    if work.is_done() {
+        return Err(PuzzleError::Exhausted);
+    }
+
+    // The index of the worker that found the solution, or `NO_WINNER`. The first worker
+    // to swap its index in keeps its output and reports it; the others see the index set
+    // and stop. No lock is taken, so none can be contended or poisoned.
+    let winner = AtomicUsize::new(NO_WINNER);
+
+    // Each worker takes chunks of the window until it runs out.
+    let worker = {
+        let work = Arc::clone(&work);
+        let control = Arc::clone(&control);
+        move |index: usize| {
+            let slot = &work.slots()[index];
+            let mut state = search.init();
+            // Candidates tried since the last report to `control`.
+            let mut tried = 0;
+            // The output of this worker if it won.
+            let mut found = None;
+            // The nonces of the batch being tested, and how many to test next.
+            let mut nonces = vec![0; MAX_BATCH as usize];
+            let mut batch_size = BatchSize::new();
+            // When this worker last started hashing, for a CPU limit.
+            let mut busy_since = Instant::now();
+            #[cfg(feature = "profile")]
+            let mut stopwatch = Stopwatch::start();
+            let finished = 'search: loop {
+                // Stop for good once another worker has found a solution.
+                if winner.load(Ordering::Relaxed) != NO_WINNER {
+                    break 'search true;
+                }
+                let Some(chunk) = work.claim(slot) else {
+                    break 'search true;
+                };
+                let (start, end) = chunk.into_inner();
+                let mut position = start;
+                loop {
+                    // If a solution is already found, stop work.
+                    if winner.load(Ordering::Relaxed) != NO_WINNER {
+                        slot.record(position);
+                        break 'search false;
+                    }
+                    // Note how far this worker got every so often, for checkpoints.
+                    if (position - start).is_multiple_of(PROGRESS_INTERVAL) {
+                        slot.record(position);
+                        slot.add_attempts(tried);
+                        control.add_attempts(tried);
+                        tried = 0;
+                        #[cfg(feature = "profile")]
+                        stopwatch.flush(&slot.profile);
+                    }
+                    #[cfg(feature = "profile")]
+                    stopwatch.lap(Activity::Checking);
+                    // Park here while paused, and stop if cancelled. Otherwise rest now and
+                    // then under a CPU limit.
+                    let running = control.checkpoint(slot, position);
+                    if running {
+                        control.pace(&mut busy_since);
+                    }
+                    #[cfg(feature = "profile")]
+                    stopwatch.lap(Activity::Idle);
+                    if !running {
+                        break 'search false;
+                    }
+
+                    // Test whether a nonce of the next batch of positions solves the puzzle.
+                    let size = batch_size.next(position - start);
+                    let last = end.min(position.saturating_add(size - 1));
+                    let batch = &mut nonces[..(last - position) as usize + 1];
+                    for (offset, nonce) in (0..).zip(batch.iter_mut()) {
+                        *nonce = work.nonce_at(position + offset);
+                    }
+                    tried += batch.len() as u64;
+                    #[cfg(feature = "profile")]
+                    stopwatch.lap(Activity::Checking);
+                    let timer = Instant::now();
+                    let found_in_batch = search.test_batch(&mut state, batch);
+                    batch_size.record(timer.elapsed());
+                    #[cfg(feature = "profile")]
+                    stopwatch.lap(Activity::Hashing);
+                    if let Some((_, output)) = found_in_batch {
+                        // Claim the win unless another worker got there first.
+                        let claimed = winner.compare_exchange(
+                            NO_WINNER,
+                            index,
+                            Ordering::AcqRel,
+                            Ordering::Acquire,
+                        );
+                        if claimed.is_ok() {
+                            control.record_winner(index);
+                            found = Some(output);
+                        }
+                        break 'search true;
+                    }
+                    if last == end {
+                        break;
+                    }
+                    position = last + 1;
+                }
+                slot.finish();
+            };
+            slot.add_attempts(tried);
+            control.add_attempts(tried);
+            #[cfg(feature = "profile")]
+            {
+                stopwatch.lap(Activity::Checking);
+                stopwatch.flush(&slot.profile);
+                slot.profile.exit();
+            }
+            if finished {
+                slot.finish();
+            }
+            found
+        }
+    };
+
+    // Run the workers on the pool if there is one, or on threads of their own. Each one
+    // reports over `done` when it stops: its output if it won, `None` if it did not, or
+    // the payload of its panic.
+    let (done, reports) = mpsc::channel();
+    let worker = Arc::new(worker);
+    for index in 0..work.slots().len() {
+        let worker = Arc::clone(&worker);
+        let done = done.clone();
+        let task = move || {
+            let _ = done.send(panic::catch_unwind(AssertUnwindSafe(|| worker(index))));
+        };
+        match pool {
+            Some(pool) => pool.execute(task),
+            None => drop(thread::spawn(task)),
+        }
+    }
+    drop(done);
+
+    // Return as soon as the winner reports. The other workers see its index before their
+    // next nonce and exit on their own, so they are not waited for. Without a winner,
+    // wait for every worker to run out of chunks or stop. A panicked worker leaves part
+    // of its chunk unsearched, so the range is not exhausted.
+    let mut panicked = false;
+    let mut sol = None;
+    for report in reports {
+        match report {
+            Ok(Some(found)) => {
+                sol = Some(found);
+                break;
+            }
+            Ok(None) => {}
+            Err(_) => panicked = true,
+        }
+    }
+    control.tally(work.slots());
+
+    match sol {
+        Some(sol) => Ok(sol),
+        None if panicked => Err(PuzzleError::WorkerPanicked),
+        None if control.is_timed_out() => {
+            Err(PuzzleError::TimedOut { attempts: control.attempts() })
+        }
+        None if control.is_cancelled() => Err(PuzzleError::Cancelled),
+        None => Err(PuzzleError::Exhausted),
+    }
+}
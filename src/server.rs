@@ -0,0 +1,2252 @@
+//! An HTTP work server that shares the nonce windows of its puzzles among many machines.
+//!
+//! A [`WorkServer`] is the coordinator of a small cluster. It holds the puzzles added
+//! with [`WorkServer::add_puzzle`] and leases out their windows in ranges of a fixed
+//! size, lowest first, to workers on other machines, which mine each range and report
+//! back either a solution or that the range holds none. A puzzle is solved by the first
+//! valid solution, and exhausted once every range of its window has been reported
+//! without one; a puzzle with an extra nonce instead rolls it and leases the window
+//! again.
+//!
+//! Requests and responses are flat JSON objects, as in the protocol of the Unix
+//! `daemon`, and every response has `ok`, which is `false` with an `error` message (and
+//! a 4xx status) if the request could not be carried out:
+//!
+//! - `POST /workers` registers a worker and returns its `worker` id.
+//! - `POST /leases` with a `worker` id leases the next range: the `lease` id, the
+//!   `puzzle` id, the puzzle itself as `algorithm`, `data_hex`, `target` (hex),
+//!   `nonce_encoding` and, if set, `nonce_offset` and `extra_nonce`, and the range from
+//!   `start` to `end` inclusive, and the `share_target` (hex) if the server counts
+//!   shares. The response has no `lease` when there is nothing to lease.
+//! - `POST /solutions` with the `worker`, its `lease` and a `nonce` in the range submits
+//!   a solution. The server checks it before accepting it, and reports the puzzle's
+//!   `state`.
+//! - `POST /exhausted` with the `worker` and its `lease` reports that the range holds no
+//!   solution, and reports the puzzle's `state`.
+//! - `POST /progress` with the `worker`, its `lease` and the number of its nonces
+//!   `searched` so far reports how far the worker has got.
+//! - `POST /shares` with the `worker`, its `lease` and a `nonce` in the range whose hash
+//!   meets the lease's `share_target` counts a share, and reports whether it `solves`
+//!   the puzzle along with the worker's tally as below. A worker submits a share that
+//!   solves the puzzle with `POST /solutions` as well; the server does not count it
+//!   twice.
+//! - `GET /workers/<id>` reports a worker's tally: its `shares`, the `work` they stand
+//!   for (the expected number of hashes tried to find them), the seconds since its
+//!   `last_share`, if any, and whether it is `idle`, holding a lease without having
+//!   sent a share for the server's idle timeout. It also has the `invalid` nonces the
+//!   worker sent, and whether it is `flagged` for sending too many.
+//! - `GET /puzzles/<id>` reports a puzzle's `state`: `running`, `solved` (with the
+//!   `nonce` and `hash` of the solution) or `exhausted`. Every state also has the nonces
+//!   `searched` so far: the ranges reported on, plus the progress of those still leased.
+//! - `GET /status` shows the whole cluster at a glance: how many workers are
+//!   `connected`, holding a lease or heard from within the lease timeout, and their total
+//!   `hash_rate` in hashes per second. Then come the `workers`, each with its tally as
+//!   above, whether it is `connected`, the `leases` it holds, the nonces it `searched`
+//!   and its `hash_rate` over its latest lease, and the `puzzles`, each with its state
+//!   as above, the nonces in its `window` and the percentage of them searched, its
+//!   `coverage`.
+//! - `GET /events`, upgraded to a WebSocket, pushes an event as a text message whenever a
+//!   puzzle is added, `{"event":"puzzle","puzzle":2}`, or finished, `{"event":"finished"}`
+//!   with the puzzle's `state` as above, so that workers can stop mining a puzzle that is
+//!   no longer running. The server closes the socket when it shuts down.
+//! - `POST /shutdown` stops the server. A server given an admin token with
+//!   [`WorkServer::with_admin_token`] only takes it with `Authorization: Bearer <token>`;
+//!   one without takes it only from a client on the same machine.
+//!
+//! ```text
+//! > POST /workers
+//! < {"ok":true,"worker":1}
+//! > POST /leases {"worker":1}
+//! < {"ok":true,"lease":1,"puzzle":1,"algorithm":"sha256","data_hex":"...","target":"...",
+//!    "nonce_encoding":"be","start":0,"end":67108863}
+//! > POST /solutions {"worker":1,"lease":1,"nonce":1514533}
+//! < {"ok":true,"puzzle":1,"state":"solved","nonce":1514533,"hash":"00000f4b..."}
+//! ```
+//!
+//! The server trusts no worker: it hashes every nonce sent as a solution or share
+//! itself, and rejects one that is outside the worker's lease, sent twice, or whose
+//! hash misses the target. Each rejection counts against the worker, which is flagged
+//! once more than [`DEFAULT_MAX_INVALID_RATE`] of the nonces it sent, and at least
+//! three, were rejected. A lease that was taken back, or never was the worker's, is
+//! refused without counting.
+//!
+//! A lease the worker goes [`DEFAULT_LEASE_TIMEOUT`] without reporting on, by progress,
+//! share or outcome, is taken back the next time a worker asks for one, and its range
+//! is leased again before any new range, so the ranges of a worker that crashed or lost
+//! its connection are still searched. A late report on such a lease is refused with a
+//! 410 status, for as long again as the lease timeout. A Stratum worker's job is taken
+//! back as soon as its connection closes.
+//!
+//! A [`WorkClient`](crate::WorkClient) is the other end of the protocol; see the
+//! [`work_client`](crate::work_client) module.
+//!
+//! The server speaks just enough HTTP/1.1 for this: one request per connection, with at
+//! most 64 headers, no line of them over 8 KiB, and a body of at most 64 KiB. With the
+//! `grpc` feature, `WorkServer::run_grpc` serves the same puzzles over gRPC as well; see
+//! the `grpc` module. [`WorkServer::run_stratum`] hands out each lease as a job of a
+//! Stratum-style pool; see the [`stratum`](crate::stratum) module. With the `mdns`
+//! feature, `WorkServer::advertise` announces the server to workers on the local
+//! network; see the `discovery` module. With the `redis` feature, `WorkServer::with_redis`
+//! keeps the puzzles and leases in Redis, where they outlive the server and replicas can
+//! share them; see the `redis_queue` module.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::io::{self, BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::mem;
+use std::ops::{Deref, DerefMut, RangeInclusive};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread::{self, Scope};
+use std::time::{Duration, Instant};
+#[cfg(feature = "redis")]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use sha2::{Digest, Sha256};
+use tungstenite::handshake::derive_accept_key;
+use tungstenite::protocol::frame::coding::CloseCode;
+use tungstenite::protocol::{CloseFrame, Role};
+use tungstenite::{Message, WebSocket};
+
+#[cfg(feature = "redis")]
+use crate::json::nullable;
+use crate::json::{Request, Response, Value};
+#[cfg(feature = "mdns")]
+use crate::{Advertisement, DiscoveryError};
+#[cfg(feature = "redis")]
+use crate::{NonceFormat, QueueError, RedisQueue};
+#[cfg(feature = "tls")]
+use crate::ServerTls;
+use crate::{hex, Algorithm, Puzzle, PuzzleError, Solution, Target, WorkLease};
+
+/// The number of nonces leased at a time unless set with
+/// [`with_lease_size`](WorkServer::with_lease_size): a few seconds of SHA-256 on one
+/// machine.
+pub const DEFAULT_LEASE_SIZE: u64 = 1 << 26;
+
+/// How long a lease may go unreported on before it is taken back from its worker and
+/// its range leased to another, unless set with
+/// [`with_lease_timeout`](WorkServer::with_lease_timeout).
+pub const DEFAULT_LEASE_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// How long a worker may hold a lease without sending a share before it counts as idle,
+/// unless set with [`with_idle_timeout`](WorkServer::with_idle_timeout).
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The share of a worker's nonces that may be rejected before it is flagged, unless set
+/// with [`with_max_invalid_rate`](WorkServer::with_max_invalid_rate).
+pub const DEFAULT_MAX_INVALID_RATE: f64 = 0.05;
+
+/// The rejected nonces a worker is allowed whatever its rate, so that a slip or two early
+/// on does not flag it.
+const FLAG_MIN_INVALID: u64 = 3;
+
+/// The largest request body the server reads.
+const MAX_BODY: usize = 64 << 10;
+
+/// The longest request line, or header line, the server reads.
+const MAX_LINE: usize = 8 << 10;
+
+/// The most headers the server reads in a request.
+const MAX_HEADERS: usize = 64;
+
+/// How long the server waits for a client to send its request.
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often the server pings a worker subscribed to its events, to notice one that has
+/// gone away.
+const PING_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How long the server waits for a subscribed worker's own messages each time it looks
+/// for them.
+const SOCKET_POLL: Duration = Duration::from_millis(10);
+
+/// How often a worker waiting for a lease is checked for having gone away.
+const ABANDON_CHECK: Duration = Duration::from_secs(1);
+
+/// How long the owner of a server waiting for a puzzle waits before trying again to read
+/// a queue in Redis that could not be.
+const UNAVAILABLE_RETRY: Duration = Duration::from_secs(1);
+
+/// How often a request waiting on a queue kept in Redis reloads it, to see the changes
+/// made by other replicas, which do not all send events.
+#[cfg(feature = "redis")]
+const REDIS_REFRESH: Duration = Duration::from_secs(1);
+
+/// A work server listening for workers over HTTP; see the [module documentation](self).
+pub struct WorkServer {
+    listener: TcpListener,
+    coordinator: Arc<Coordinator>,
+    /// The token a client must send to shut the server down, if any.
+    admin_token: Option<String>,
+    #[cfg(feature = "tls")]
+    tls: Option<ServerTls>,
+}
+
+/// The puzzles, workers and leases of a [`WorkServer`], which every protocol it speaks
+/// works on.
+pub(crate) struct Coordinator {
+    lease_size: u64,
+    /// The target of the shares workers send as proof of their effort, if the server
+    /// counts any.
+    share_target: Option<Target>,
+    idle_timeout: Duration,
+    lease_timeout: Duration,
+    max_invalid_rate: f64,
+    state: Mutex<State>,
+    /// Signalled whenever a puzzle is added, a lease is reported on or the server shuts
+    /// down.
+    changed: Condvar,
+    shutdown: AtomicBool,
+    /// The queue in Redis the state is kept in, if it is not kept in memory.
+    #[cfg(feature = "redis")]
+    redis: Option<RedisQueue>,
+}
+
+/// The state of a [`Coordinator`], locked for one request. With Redis, it is loaded from
+/// there under the lock of the queue, and saved back when committed or dropped if it was
+/// modified.
+struct Locked<'a> {
+    coordinator: &'a Coordinator,
+    /// The state, until it is released.
+    state: Option<MutexGuard<'a, State>>,
+    /// Whether the state was borrowed mutably, and so may have been modified.
+    modified: bool,
+    /// The token of the lock of the queue in Redis, while it is held.
+    #[cfg(feature = "redis")]
+    token: Option<String>,
+}
+
+/// Everything the server knows, behind one lock.
+#[derive(Default)]
+struct State {
+    /// The registered workers, by id.
+    workers: HashMap<u64, Worker>,
+    next_worker: u64,
+    /// The puzzles, whose ids are their positions plus one.
+    jobs: Vec<Job>,
+    /// The leases not yet reported on, by id.
+    leases: HashMap<u64, Lease>,
+    next_lease: u64,
+    /// The workers of the leases taken back from them, and when they were, by lease id.
+    /// Each is forgotten once the lease timeout passes again.
+    expired: HashMap<u64, (u64, Instant)>,
+    /// The connections pushing events to workers, each sent the message of every event.
+    subscribers: Vec<Sender<String>>,
+    /// The messages of the events of the request in progress, pushed once it is done.
+    outbox: Vec<String>,
+}
+
+/// A puzzle and how far its window has been leased.
+struct Job {
+    puzzle: Puzzle,
+    algorithm: Algorithm,
+    nonces: RangeInclusive<u64>,
+    /// The first nonce not yet leased, or `None` once the whole window has been.
+    next: Option<u64>,
+    /// The ranges taken back from workers, with their extra nonces, to lease again before
+    /// any new one.
+    reclaimed: VecDeque<(RangeInclusive<u64>, Option<u64>)>,
+    /// The leases of this puzzle not yet reported on.
+    outstanding: usize,
+    /// The nonces searched in the leases of this puzzle already reported on.
+    searched: u64,
+    solution: Option<Solution>,
+}
+
+/// The shares a worker has sent, and how fast it searches.
+#[derive(Default)]
+struct Worker {
+    shares: u64,
+    /// The nonces rejected, as shares or solutions.
+    invalid: u64,
+    /// The hashes the shares prove, on average.
+    work: f64,
+    last_share: Option<Instant>,
+    /// The nonces searched in the worker's leases already reported on.
+    searched: u64,
+    /// The nonces per second the worker searched its latest lease at, by its last report
+    /// on it.
+    hash_rate: f64,
+    /// When the worker last made a request.
+    heard_at: Option<Instant>,
+}
+
+/// A range of one puzzle's window leased to a worker.
+struct Lease {
+    worker: u64,
+    /// The index of the puzzle in [`State::jobs`].
+    job: usize,
+    nonces: RangeInclusive<u64>,
+    /// The extra nonce the range was leased with.
+    extra_nonce: Option<u64>,
+    /// The nonces of the range the worker last said it had searched.
+    searched: u64,
+    /// The target of the range's shares: the server's share target, or the puzzle's
+    /// target if that is easier or the server counts no shares.
+    share_target: Target,
+    /// The nonces of the range already counted as shares.
+    shares: HashSet<u64>,
+    leased_at: Instant,
+    /// When the worker was last heard from about the lease: when it was leased, or its
+    /// last progress report or share.
+    heard_at: Instant,
+}
+
+/// The shares a [`WorkServer`] has counted for one of its workers, and the nonces it
+/// rejected.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WorkerShares {
+    /// The worker's id.
+    pub worker: u64,
+    /// The shares accepted, solutions included.
+    pub shares: u64,
+    /// The nonces rejected, as shares or solutions: outside the worker's lease, sent
+    /// twice, or whose hash the server found not to meet the target after all.
+    pub invalid: u64,
+    /// The hashes the shares prove the worker tried, on average: the expected attempts
+    /// of each share's target, summed.
+    pub work: f64,
+    /// How long ago the last share was accepted, if any was.
+    pub since_last_share: Option<Duration>,
+    /// Whether the worker has held a lease for longer than the idle timeout without
+    /// sending a share. Always `false` if the server counts no shares.
+    pub idle: bool,
+    /// Whether more of the worker's nonces were rejected than the server's maximum
+    /// invalid rate allows: a sign of a broken or cheating worker.
+    pub flagged: bool,
+}
+
+impl WorkerShares {
+    /// The share of the nonces the worker sent that were rejected, or zero if it sent
+    /// none.
+    pub fn invalid_rate(&self) -> f64 {
+        match self.shares + self.invalid {
+            0 => 0.0,
+            sent => self.invalid as f64 / sent as f64,
+        }
+    }
+}
+
+/// A [`WorkServer`]'s cluster at a glance, as `GET /status` reports it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClusterStatus {
+    /// Every registered worker, in the order they registered.
+    pub workers: Vec<WorkerStatus>,
+    /// Every puzzle, in the order they were added.
+    pub puzzles: Vec<PuzzleCoverage>,
+}
+
+impl ClusterStatus {
+    /// How many workers are connected.
+    pub fn connected(&self) -> usize {
+        self.workers.iter().filter(|worker| worker.connected).count()
+    }
+
+    /// The hash rate of the cluster: the sum of the connected workers', in hashes per
+    /// second.
+    pub fn hash_rate(&self) -> f64 {
+        let connected = self.workers.iter().filter(|worker| worker.connected);
+        connected.fold(0.0, |sum, worker| sum + worker.hash_rate)
+    }
+}
+
+/// One worker of a [`ClusterStatus`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct WorkerStatus {
+    /// The worker's id and shares, as [`WorkServer::workers`] reports them.
+    pub shares: WorkerShares,
+    /// Whether the worker holds a lease or has made a request within the server's lease
+    /// timeout.
+    pub connected: bool,
+    /// How many leases the worker holds.
+    pub leases: usize,
+    /// The nonces the worker has said it searched, in every lease.
+    pub searched: u64,
+    /// The nonces per second the worker searched its latest lease at, by its last report
+    /// on it, or zero before its first.
+    pub hash_rate: f64,
+}
+
+/// How far the search of one puzzle of a [`ClusterStatus`] has got.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PuzzleCoverage {
+    /// The puzzle's id.
+    pub puzzle: u64,
+    /// The solution, once the puzzle is solved.
+    pub solution: Option<Solution>,
+    /// Whether the puzzle is solved or its whole window was searched without a solution.
+    pub finished: bool,
+    /// The nonces workers have said they searched, over every pass of the window.
+    pub searched: u64,
+    /// The nonces in the window, short by one for the whole of `u64`.
+    pub window: u64,
+}
+
+impl PuzzleCoverage {
+    /// The percentage of the window searched. A puzzle with an extra nonce goes over 100
+    /// once its window is searched again under the next extra nonce.
+    pub fn coverage(&self) -> f64 {
+        self.searched as f64 / self.window.max(1) as f64 * 100.0
+    }
+}
+
+/// Where a puzzle stands.
+#[derive(Clone)]
+pub(crate) struct JobStatus {
+    pub(crate) id: u64,
+    pub(crate) solution: Option<Solution>,
+    /// Whether the puzzle is solved or its whole window was searched without a solution.
+    pub(crate) finished: bool,
+    /// The nonces workers have said they searched, over every pass of the window.
+    pub(crate) searched: u64,
+}
+
+/// Something the server pushes to the workers subscribed to `GET /events`.
+#[derive(Clone)]
+enum Event {
+    /// The puzzle with this id was added.
+    Added(u64),
+    /// A puzzle was solved or its whole window searched, so its leases are no longer
+    /// worth mining.
+    Finished(JobStatus),
+}
+
+/// Why the coordinator would not carry out a request.
+#[derive(Debug)]
+pub(crate) enum Refusal {
+    /// The worker, lease or puzzle the request names does not exist.
+    NotFound(String),
+    /// The request cannot be carried out as asked, such as a nonce that is no solution.
+    Rejected(String),
+    /// The lease the request names was taken back from the worker, for going unreported
+    /// on for too long.
+    Expired(String),
+    /// The state could not be locked, loaded or saved, such as while the queue in Redis
+    /// cannot be reached.
+    Unavailable(String),
+}
+
+impl Job {
+    /// Whether the puzzle is solved or its whole window was searched without a solution.
+    fn is_finished(&self) -> bool {
+        self.solution.is_some()
+            || (self.next.is_none() && self.reclaimed.is_empty() && self.outstanding == 0)
+    }
+}
+
+impl Worker {
+    /// Counts a share of `target`.
+    fn credit(&mut self, target: Target) {
+        self.shares += 1;
+        self.work += target.expected_attempts();
+        self.last_share = Some(Instant::now());
+    }
+
+    /// Takes the worker's hash rate from its report of having searched `searched` nonces
+    /// of `lease` since it was leased.
+    fn measure(&mut self, lease: &Lease, searched: u64) {
+        let elapsed = lease.leased_at.elapsed().as_secs_f64();
+        if elapsed > 0.0 {
+            self.hash_rate = searched as f64 / elapsed;
+        }
+    }
+}
+
+impl Lease {
+    /// The number of nonces in the range, short by one for the whole of `u64`.
+    fn len(&self) -> u64 {
+        (self.nonces.end() - self.nonces.start()).saturating_add(1)
+    }
+}
+
+impl State {
+    /// Where the puzzle at `index` of [`jobs`](State::jobs) stands.
+    fn status(&self, index: usize) -> JobStatus {
+        let job = &self.jobs[index];
+        let searched = self
+            .leases
+            .values()
+            .filter(|lease| lease.job == index)
+            .fold(job.searched, |sum, lease| sum.saturating_add(lease.searched));
+        JobStatus {
+            id: index as u64 + 1,
+            solution: job.solution.clone(),
+            finished: job.is_finished(),
+            searched,
+        }
+    }
+
+    /// The shares of the worker `id`, which must exist. It is idle if it has held a lease
+    /// for longer than `idle_timeout` without a share, and never if that is `None`, and
+    /// flagged if more than `max_invalid_rate` of its nonces were rejected.
+    fn worker_shares(
+        &self,
+        id: u64,
+        idle_timeout: Option<Duration>,
+        max_invalid_rate: f64,
+    ) -> WorkerShares {
+        let worker = &self.workers[&id];
+        let now = Instant::now();
+        let idle = idle_timeout.is_some_and(|timeout| {
+            self.leases.values().filter(|lease| lease.worker == id).any(|lease| {
+                let since = worker.last_share.map_or(lease.leased_at, |last| {
+                    last.max(lease.leased_at)
+                });
+                now.duration_since(since) > timeout
+            })
+        });
+        let mut shares = WorkerShares {
+            worker: id,
+            shares: worker.shares,
+            invalid: worker.invalid,
+            work: worker.work,
+            since_last_share: worker.last_share.map(|last| now.duration_since(last)),
+            idle,
+            flagged: false,
+        };
+        shares.flagged =
+            shares.invalid >= FLAG_MIN_INVALID && shares.invalid_rate() > max_invalid_rate;
+        shares
+    }
+
+    /// Where the worker `id`, which must exist, stands: its `shares`, and whether it is
+    /// connected, holding a lease or heard from within `timeout`.
+    fn worker_status(&self, shares: WorkerShares, timeout: Duration) -> WorkerStatus {
+        let worker = &self.workers[&shares.worker];
+        let leases: Vec<&Lease> =
+            self.leases.values().filter(|lease| lease.worker == shares.worker).collect();
+        let heard = worker.heard_at.is_some_and(|heard_at| heard_at.elapsed() <= timeout);
+        WorkerStatus {
+            connected: heard || !leases.is_empty(),
+            leases: leases.len(),
+            searched: leases
+                .iter()
+                .fold(worker.searched, |sum, lease| sum.saturating_add(lease.searched)),
+            hash_rate: worker.hash_rate,
+            shares,
+        }
+    }
+
+    /// Notes that the worker `id` just made a request.
+    ///
+    /// # Returns
+    ///
+    /// A refusal if the worker has not registered.
+    fn heard_from(&mut self, id: u64) -> Result<(), Refusal> {
+        match self.workers.get_mut(&id) {
+            Some(worker) => {
+                worker.heard_at = Some(Instant::now());
+                Ok(())
+            }
+            None => Err(Refusal::NotFound(format!("no worker {}", id))),
+        }
+    }
+
+    /// Counts `refusal` of a nonce `worker` sent against the worker if it is a rejection,
+    /// rather than a lease it no longer holds.
+    fn counted(&mut self, worker: u64, refusal: Refusal) -> Refusal {
+        if let Refusal::Rejected(_) = refusal {
+            self.workers.get_mut(&worker).unwrap().invalid += 1;
+        }
+        refusal
+    }
+
+    /// Takes back the lease `id` from its worker, to lease its range again unless the
+    /// puzzle is solved.
+    fn reclaim(&mut self, id: u64) {
+        let lease = self.leases.remove(&id).unwrap();
+        self.expired.insert(id, (lease.worker, Instant::now()));
+        let job = &mut self.jobs[lease.job];
+        job.outstanding -= 1;
+        if job.solution.is_none() {
+            job.reclaimed.push_back((lease.nonces, lease.extra_nonce));
+        }
+    }
+
+    /// Why `worker` cannot report on the lease `id`, which it does not hold.
+    fn missing_lease(&self, worker: u64, id: u64) -> Refusal {
+        match self.expired.get(&id) {
+            Some(&(holder, _)) if holder == worker => {
+                Refusal::Expired(format!("lease {} has expired", id))
+            }
+            _ => Refusal::NotFound(format!("worker {} holds no lease {}", worker, id)),
+        }
+    }
+
+    /// Queues `event` to be pushed once the request in progress is done.
+    fn broadcast(&mut self, event: Event) {
+        self.outbox.push(event_message(&event).to_string());
+    }
+
+    /// Pushes the message of each event in `messages` to every subscriber still
+    /// listening.
+    fn deliver(&mut self, messages: &[String]) {
+        self.subscribers.retain(|subscriber| {
+            messages.iter().all(|message| subscriber.send(message.clone()).is_ok())
+        });
+    }
+}
+
+#[cfg(feature = "redis")]
+impl State {
+    /// The state, but for its subscribers, as newline-separated JSON records to keep in
+    /// Redis.
+    fn encode(&self) -> String {
+        let mut records = vec![Response::record("state")
+            .number("next_worker", self.next_worker)
+            .number("next_lease", self.next_lease)];
+        let mut workers: Vec<_> = self.workers.iter().collect();
+        workers.sort_unstable_by_key(|&(&id, _)| id);
+        for (id, worker) in workers {
+            records.push(
+                Response::record("worker")
+                    .number("id", id)
+                    .number("shares", worker.shares)
+                    .number("invalid", worker.invalid)
+                    .number("work", worker.work)
+                    .value("last_share", &nullable(worker.last_share.map(unix_millis)))
+                    .number("searched", worker.searched)
+                    .number("hash_rate", worker.hash_rate)
+                    .value("heard_at", &nullable(worker.heard_at.map(unix_millis))),
+            );
+        }
+        for job in &self.jobs {
+            let puzzle = &job.puzzle;
+            let reclaimed = job.reclaimed.iter().map(|(nonces, extra_nonce)| {
+                let (start, end) = (Some(nonces.start()), Some(nonces.end()));
+                Value::Array(vec![nullable(start), nullable(end), nullable(*extra_nonce)])
+            });
+            let solution = job.solution.as_ref();
+            let hash = solution.map_or(Value::Null, |solution| {
+                Value::String(hex::encode(&solution.hash))
+            });
+            records.push(
+                Response::record("job")
+                    .string("algorithm", job.algorithm.name())
+                    .string("data_hex", &hex::encode(&puzzle.data))
+                    .string("target", &puzzle.target.to_string())
+                    .number("nonce", puzzle.nonce)
+                    .string("nonce_encoding", &puzzle.nonce_format.encoding.to_string())
+                    .value("nonce_offset", &nullable(puzzle.nonce_format.offset))
+                    .value("extra_nonce", &nullable(puzzle.extra_nonce))
+                    .value("issued_at", &nullable(puzzle.issued_at))
+                    .value("expires_at", &nullable(puzzle.expires_at))
+                    .number("start", job.nonces.start())
+                    .number("end", job.nonces.end())
+                    .value("next", &nullable(job.next))
+                    .value("reclaimed", &Value::Array(reclaimed.collect()))
+                    .number("outstanding", job.outstanding)
+                    .number("searched", job.searched)
+                    .value("solution", &nullable(solution.map(|solution| solution.nonce)))
+                    .value("hash", &hash)
+                    .value(
+                        "solution_extra_nonce",
+                        &nullable(solution.and_then(|solution| solution.extra_nonce)),
+                    ),
+            );
+        }
+        let mut leases: Vec<_> = self.leases.iter().collect();
+        leases.sort_unstable_by_key(|&(&id, _)| id);
+        for (id, lease) in leases {
+            let mut shares: Vec<_> = lease.shares.iter().copied().collect();
+            shares.sort_unstable();
+            let shares = shares.into_iter().map(|nonce| nullable(Some(nonce))).collect();
+            records.push(
+                Response::record("lease")
+                    .number("id", id)
+                    .number("worker", lease.worker)
+                    .number("job", lease.job)
+                    .number("start", lease.nonces.start())
+                    .number("end", lease.nonces.end())
+                    .value("extra_nonce", &nullable(lease.extra_nonce))
+                    .number("searched", lease.searched)
+                    .string("share_target", &lease.share_target.to_string())
+                    .value("shares", &Value::Array(shares))
+                    .number("leased_at", unix_millis(lease.leased_at))
+                    .number("heard_at", unix_millis(lease.heard_at)),
+            );
+        }
+        for (lease, (worker, expired_at)) in &self.expired {
+            records.push(
+                Response::record("expired")
+                    .number("lease", lease)
+                    .number("worker", worker)
+                    .number("expired_at", unix_millis(*expired_at)),
+            );
+        }
+        let records: Vec<String> = records.iter().map(Response::to_string).collect();
+        records.join("\n")
+    }
+
+    /// Reads a state written by [`encode`](State::encode), with no subscribers.
+    ///
+    /// # Returns
+    ///
+    /// The state, or what is wrong with `text`.
+    fn decode(text: &str) -> Result<State, String> {
+        let mut state = State::default();
+        for line in text.lines() {
+            let record = Request::parse(line)?;
+            let field = |key: &str| -> Result<&str, String> {
+                record.string(key)?.ok_or_else(|| format!("missing `{}`", key))
+            };
+            let nonces = || -> Result<RangeInclusive<u64>, String> {
+                Ok(record.required("start")?..=record.required("end")?)
+            };
+            match field("record")? {
+                "state" => {
+                    state.next_worker = record.required("next_worker")?;
+                    state.next_lease = record.required("next_lease")?;
+                }
+                "worker" => {
+                    let worker = Worker {
+                        shares: record.required("shares")?,
+                        invalid: record.required("invalid")?,
+                        work: record.required("work")?,
+                        last_share: record.number("last_share")?.map(instant_at),
+                        searched: record.required("searched")?,
+                        hash_rate: record.required("hash_rate")?,
+                        heard_at: record.number("heard_at")?.map(instant_at),
+                    };
+                    state.workers.insert(record.required("id")?, worker);
+                }
+                "job" => {
+                    let puzzle = Puzzle {
+                        target: field("target")?.parse()?,
+                        data: hex::decode(field("data_hex")?).map_err(|e| e.to_string())?,
+                        nonce: record.required("nonce")?,
+                        nonce_format: NonceFormat {
+                            encoding: field("nonce_encoding")?.parse()?,
+                            offset: record.number("nonce_offset")?,
+                        },
+                        extra_nonce: record.number("extra_nonce")?,
+                        issued_at: record.number("issued_at")?,
+                        expires_at: record.number("expires_at")?,
+                    };
+                    let mut reclaimed = VecDeque::new();
+                    let ranges = record.value("reclaimed").and_then(Value::as_array);
+                    for range in ranges.unwrap_or(&[]) {
+                        let range = range.as_array().unwrap_or(&[]);
+                        let number = |i: usize| range.get(i).and_then(Value::as_number);
+                        let (Some(start), Some(end)) = (number(0), number(1)) else {
+                            return Err("malformed `reclaimed` range".to_string());
+                        };
+                        reclaimed.push_back((start..=end, number(2)));
+                    }
+                    let solution = match record.number("solution")? {
+                        Some(nonce) => Some(Solution {
+                            nonce,
+                            hash: hex::decode(field("hash")?).map_err(|e| e.to_string())?,
+                            extra_nonce: record.number("solution_extra_nonce")?,
+                        }),
+                        None => None,
+                    };
+                    state.jobs.push(Job {
+                        puzzle,
+                        algorithm: field("algorithm")?.parse()?,
+                        nonces: nonces()?,
+                        next: record.number("next")?,
+                        reclaimed,
+                        outstanding: record.required("outstanding")?,
+                        searched: record.required("searched")?,
+                        solution,
+                    });
+                }
+                "lease" => {
+                    let shares = record.value("shares").and_then(Value::as_array).unwrap_or(&[]);
+                    let lease = Lease {
+                        worker: record.required("worker")?,
+                        job: record.required("job")?,
+                        nonces: nonces()?,
+                        extra_nonce: record.number("extra_nonce")?,
+                        searched: record.required("searched")?,
+                        share_target: field("share_target")?.parse()?,
+                        shares: shares.iter().filter_map(Value::as_number).collect(),
+                        leased_at: instant_at(record.required("leased_at")?),
+                        heard_at: instant_at(record.required("heard_at")?),
+                    };
+                    state.leases.insert(record.required("id")?, lease);
+                }
+                "expired" => {
+                    let expired_at = instant_at(record.required("expired_at")?);
+                    let expired = (record.required("worker")?, expired_at);
+                    state.expired.insert(record.required("lease")?, expired);
+                }
+                kind => return Err(format!("unknown record `{}`", kind)),
+            }
+        }
+        if state.leases.values().any(|lease| lease.job >= state.jobs.len()) {
+            return Err("a lease of a missing puzzle".to_string());
+        }
+        Ok(state)
+    }
+}
+
+impl Deref for Locked<'_> {
+    type Target = State;
+
+    fn deref(&self) -> &State {
+        self.state.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for Locked<'_> {
+    fn deref_mut(&mut self) -> &mut State {
+        self.modified = true;
+        self.state.as_mut().unwrap()
+    }
+}
+
+impl<'a> Locked<'a> {
+    /// Releases the state until the coordinator signals a change or, if given,
+    /// `timeout` passes, then locks it again. With Redis, where other replicas change
+    /// the state without signalling, it waits no longer than a second.
+    ///
+    /// # Returns
+    ///
+    /// The state, or why it could not be saved or locked again.
+    fn wait(mut self, timeout: Option<Duration>) -> Result<Self, PuzzleError> {
+        let coordinator = self.coordinator;
+        let (state, saved) = self.release();
+        saved?;
+        #[cfg(feature = "redis")]
+        let timeout = match coordinator.redis {
+            Some(_) => Some(timeout.map_or(REDIS_REFRESH, |timeout| timeout.min(REDIS_REFRESH))),
+            None => timeout,
+        };
+        let state = match timeout {
+            Some(timeout) => coordinator.changed.wait_timeout(state, timeout)?.0,
+            None => coordinator.changed.wait(state)?,
+        };
+        coordinator.locked(state)
+    }
+
+    /// Ends the request, as dropping the state does.
+    ///
+    /// # Returns
+    ///
+    /// An error if the state was modified but could not be saved, in which case the
+    /// modification is lost.
+    fn commit(mut self) -> Result<(), PuzzleError> {
+        self.release().1
+    }
+
+    /// Ends the request: pushes its events and, with Redis, saves the state if it changed
+    /// and releases the lock of the queue.
+    ///
+    /// # Returns
+    ///
+    /// The state, still locked within this process, and whether it could be saved.
+    fn release(&mut self) -> (MutexGuard<'a, State>, Result<(), PuzzleError>) {
+        let mut state = self.state.take().unwrap();
+        let events = mem::take(&mut state.outbox);
+        #[cfg(feature = "redis")]
+        if let (Some(queue), Some(token)) = (&self.coordinator.redis, self.token.take()) {
+            // Every replica, this one included, pushes the events it hears from Redis.
+            let saved = if self.modified {
+                queue.save(&token, &state.encode(), &events).map_err(unavailable)
+            } else {
+                Ok(())
+            };
+            // A lock that cannot be released lapses soon enough.
+            let _ = queue.unlock(&token);
+            return (state, saved);
+        }
+        state.deliver(&events);
+        (state, Ok(()))
+    }
+}
+
+impl Drop for Locked<'_> {
+    /// Ends a request refused or abandoned before it was committed. Whatever it modified
+    /// is saved if it can be.
+    fn drop(&mut self) {
+        if self.state.is_some() {
+            drop(self.release());
+        }
+    }
+}
+
+/// A request read off a connection.
+struct HttpRequest {
+    method: String,
+    path: String,
+    body: String,
+    /// The token of an `Authorization: Bearer` header.
+    bearer: Option<String>,
+    /// The key of a request to upgrade the connection to a WebSocket.
+    websocket_key: Option<String>,
+}
+
+/// Why a request could not be carried out: the HTTP status and the message.
+struct HttpError(u16, String);
+
+impl From<String> for HttpError {
+    /// A malformed request.
+    fn from(message: String) -> Self {
+        HttpError(400, message)
+    }
+}
+
+impl From<Refusal> for HttpError {
+    fn from(refusal: Refusal) -> Self {
+        match refusal {
+            Refusal::NotFound(message) => HttpError(404, message),
+            Refusal::Rejected(message) => HttpError(422, message),
+            Refusal::Expired(message) => HttpError(410, message),
+            Refusal::Unavailable(message) => HttpError(503, message),
+        }
+    }
+}
+
+impl From<PuzzleError> for Refusal {
+    /// A state that could not be locked, loaded or saved.
+    fn from(e: PuzzleError) -> Self {
+        Refusal::Unavailable(e.to_string())
+    }
+}
+
+impl From<PuzzleError> for HttpError {
+    /// A state that could not be locked, loaded or saved.
+    fn from(e: PuzzleError) -> Self {
+        Refusal::from(e).into()
+    }
+}
+
+impl WorkServer {
+    /// Listens for workers on `addr`, leasing [`DEFAULT_LEASE_SIZE`] nonces at a time.
+    ///
+    /// # Returns
+    ///
+    /// The server, or the error that stopped it from listening.
+    pub fn bind(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        Ok(WorkServer {
+            listener: TcpListener::bind(addr)?,
+            coordinator: Arc::new(Coordinator {
+                lease_size: DEFAULT_LEASE_SIZE,
+                share_target: None,
+                idle_timeout: DEFAULT_IDLE_TIMEOUT,
+                lease_timeout: DEFAULT_LEASE_TIMEOUT,
+                max_invalid_rate: DEFAULT_MAX_INVALID_RATE,
+                state: Mutex::new(State::default()),
+                changed: Condvar::new(),
+                shutdown: AtomicBool::new(false),
+                #[cfg(feature = "redis")]
+                redis: None,
+            }),
+            admin_token: None,
+            #[cfg(feature = "tls")]
+            tls: None,
+        })
+    }
+
+    /// Speaks HTTPS rather than HTTP, to workers and to [`run_grpc`](WorkServer::run_grpc)
+    /// clients alike, with the certificate of `tls`, and demands certificates of workers
+    /// if it does; see the [`tls`](crate::tls) module.
+    #[cfg(feature = "tls")]
+    pub fn with_tls(mut self, tls: ServerTls) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+
+    /// Only takes `POST /shutdown` from a client that sends `token` as
+    /// `Authorization: Bearer <token>`, rather than from any client on the same machine.
+    pub fn with_admin_token(mut self, token: impl Into<String>) -> Self {
+        self.admin_token = Some(token.into());
+        self
+    }
+
+    /// Leases `lease_size` nonces at a time. A value of zero is treated as one.
+    pub fn with_lease_size(mut self, lease_size: u64) -> Self {
+        // Only a running server shares its coordinator, and it cannot run before it is
+        // built.
+        Arc::get_mut(&mut self.coordinator).unwrap().lease_size = lease_size.max(1);
+        self
+    }
+
+    /// The number of nonces leased at a time.
+    pub fn lease_size(&self) -> u64 {
+        self.coordinator.lease_size
+    }
+
+    /// Counts the hashes workers find at or below `target` as shares: proof of the effort
+    /// they put into their leases, sent with `POST /shares` as they find them. A puzzle
+    /// whose own target is easier uses that instead, so every solution is a share.
+    pub fn with_share_target(mut self, target: Target) -> Self {
+        Arc::get_mut(&mut self.coordinator).unwrap().share_target = Some(target);
+        self
+    }
+
+    /// Counts a worker that holds a lease but has sent no share for `timeout` as idle,
+    /// rather than after [`DEFAULT_IDLE_TIMEOUT`].
+    pub fn with_idle_timeout(mut self, timeout: Duration) -> Self {
+        Arc::get_mut(&mut self.coordinator).unwrap().idle_timeout = timeout;
+        self
+    }
+
+    /// Takes back a lease that has gone `timeout` without a progress report, share or
+    /// outcome from its worker, rather than after [`DEFAULT_LEASE_TIMEOUT`], and leases its
+    /// range again before any new one. A worker that may take longer over a lease should
+    /// report its progress.
+    pub fn with_lease_timeout(mut self, timeout: Duration) -> Self {
+        Arc::get_mut(&mut self.coordinator).unwrap().lease_timeout = timeout;
+        self
+    }
+
+    /// Flags a worker once more than `rate` of the nonces it sent, as shares or solutions,
+    /// were rejected, and at least three, rather than [`DEFAULT_MAX_INVALID_RATE`].
+    pub fn with_max_invalid_rate(mut self, rate: f64) -> Self {
+        Arc::get_mut(&mut self.coordinator).unwrap().max_invalid_rate = rate;
+        self
+    }
+
+    /// Keeps the puzzles, workers and leases in `queue` rather than in memory, so that
+    /// they outlive the server and other servers on the same queue share them; see the
+    /// [`redis_queue`](crate::redis_queue) module. A queue that already holds puzzles is
+    /// carried on with. Requests made while the queue cannot be reached or read are
+    /// refused with a 503 status.
+    #[cfg(feature = "redis")]
+    pub fn with_redis(mut self, queue: RedisQueue) -> Self {
+        Arc::get_mut(&mut self.coordinator).unwrap().redis = Some(queue);
+        self
+    }
+
+    /// The shares counted for each registered worker, in the order they registered.
+    ///
+    /// # Returns
+    ///
+    /// The shares, or [`PuzzleError::Unavailable`] if the queue in Redis cannot be read.
+    pub fn workers(&self) -> Result<Vec<WorkerShares>, PuzzleError> {
+        self.coordinator.workers()
+    }
+
+    /// The workers and puzzles of the cluster, as `GET /status` reports them.
+    ///
+    /// # Returns
+    ///
+    /// The status, or [`PuzzleError::Unavailable`] if the queue in Redis cannot be read.
+    pub fn status(&self) -> Result<ClusterStatus, PuzzleError> {
+        self.coordinator.cluster_status()
+    }
+
+    /// The address the server listens on.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Announces the server on the local network over mDNS, for workers to find with
+    /// [`discover`](crate::discover), until the returned [`Advertisement`] is dropped;
+    /// see the [`discovery`](crate::discovery) module.
+    #[cfg(feature = "mdns")]
+    pub fn advertise(&self) -> Result<Advertisement, DiscoveryError> {
+        #[cfg(feature = "tls")]
+        let scheme = if self.tls.is_some() { "https" } else { "http" };
+        #[cfg(not(feature = "tls"))]
+        let scheme = "http";
+        let addr = self.local_addr().map_err(|e| DiscoveryError::Mdns(e.to_string()))?;
+        Advertisement::new(addr, scheme)
+    }
+
+    /// Adds `puzzle`, hashed with `algorithm`, whose window `nonces` is leased after the
+    /// windows of the puzzles added before it.
+    ///
+    /// # Returns
+    ///
+    /// The puzzle's id, for [`wait`](WorkServer::wait) and `GET /puzzles/<id>`, or
+    /// [`PuzzleError::Unavailable`] if the queue in Redis cannot be read or written.
+    pub fn add_puzzle(
+        &self,
+        puzzle: Puzzle,
+        algorithm: Algorithm,
+        nonces: RangeInclusive<u64>,
+    ) -> Result<u64, PuzzleError> {
+        let mut state = self.coordinator.lock()?;
+        let next = (!nonces.is_empty()).then_some(*nonces.start());
+        state.jobs.push(Job {
+            puzzle,
+            algorithm,
+            nonces,
+            next,
+            reclaimed: VecDeque::new(),
+            outstanding: 0,
+            searched: 0,
+            solution: None,
+        });
+        let id = state.jobs.len() as u64;
+        state.broadcast(Event::Added(id));
+        state.commit()?;
+        self.coordinator.changed.notify_all();
+        Ok(id)
+    }
+
+    /// Looks for a puzzle added before that is `puzzle`, hashed with `algorithm` over
+    /// the window `nonces`, such as one a server on the same queue in Redis added, or
+    /// this one before it restarted. Its extra nonce is not compared, as leasing rolls
+    /// it.
+    ///
+    /// # Returns
+    ///
+    /// The id of the first such puzzle, `None` if there is none, or
+    /// [`PuzzleError::Unavailable`] if the queue in Redis cannot be read.
+    pub fn find_puzzle(
+        &self,
+        puzzle: &Puzzle,
+        algorithm: Algorithm,
+        nonces: &RangeInclusive<u64>,
+    ) -> Result<Option<u64>, PuzzleError> {
+        let state = self.coordinator.lock()?;
+        let index = state.jobs.iter().position(|job| {
+            job.algorithm == algorithm
+                && job.nonces == *nonces
+                && job.puzzle.data == puzzle.data
+                && job.puzzle.target == puzzle.target
+                && job.puzzle.nonce_format == puzzle.nonce_format
+                && job.puzzle.issued_at == puzzle.issued_at
+                && job.puzzle.expires_at == puzzle.expires_at
+        });
+        Ok(index.map(|index| index as u64 + 1))
+    }
+
+    /// Blocks until the puzzle `id` is solved, its window is exhausted or the server
+    /// shuts down. A queue in Redis that cannot be reached meanwhile is waited out.
+    ///
+    /// # Returns
+    ///
+    /// The solution, [`PuzzleError::Exhausted`] if the window held none, or
+    /// [`PuzzleError::Cancelled`] if the server shut down first.
+    ///
+    /// # Panics
+    ///
+    /// If `id` was not returned by [`add_puzzle`](WorkServer::add_puzzle).
+    pub fn wait(&self, id: u64) -> Result<Solution, PuzzleError> {
+        let index = (id as usize).checked_sub(1).expect("no puzzle 0");
+        let mut state = self.coordinator.lock();
+        loop {
+            if let Ok(state) = &state {
+                let job = &state.jobs[index];
+                if let Some(solution) = &job.solution {
+                    return Ok(solution.clone());
+                }
+                if job.is_finished() {
+                    return Err(PuzzleError::Exhausted);
+                }
+            }
+            if self.coordinator.is_shut_down() {
+                return Err(PuzzleError::Cancelled);
+            }
+            state = match state {
+                Ok(state) => state.wait(None),
+                Err(PuzzleError::PoisonedLock) => return Err(PuzzleError::PoisonedLock),
+                Err(_) => {
+                    thread::sleep(UNAVAILABLE_RETRY);
+                    self.coordinator.lock()
+                }
+            };
+        }
+    }
+
+    /// Serves requests, each connection on a thread of its own, until a client allowed to
+    /// sends `POST /shutdown` or [`shutdown`](WorkServer::shutdown) is called.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` once shut down, or the error that stopped the server from accepting
+    /// connections.
+    pub fn run(&self) -> io::Result<()> {
+        let result = thread::scope(|scope| {
+            #[cfg(feature = "redis")]
+            if let Some(queue) = &self.coordinator.redis {
+                // Pushes the events of every replica, this one's included, to its workers.
+                let coordinator = &self.coordinator;
+                scope.spawn(|| {
+                    queue.listen(|| coordinator.is_shut_down(), |event| coordinator.relay(event))
+                });
+            }
+            self.accept(scope)
+        });
+        self.shutdown();
+        result
+    }
+
+    /// Serves the [gRPC service](crate::grpc) on `addr` until the server shuts down,
+    /// alongside [`run`](WorkServer::run) on another thread.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` once shut down, or the error that stopped the service from listening.
+    #[cfg(feature = "grpc")]
+    pub fn run_grpc(&self, addr: SocketAddr) -> io::Result<()> {
+        #[cfg(feature = "tls")]
+        let result = crate::grpc::serve(Arc::clone(&self.coordinator), addr, self.tls.as_ref());
+        #[cfg(not(feature = "tls"))]
+        let result = crate::grpc::serve(Arc::clone(&self.coordinator), addr);
+        self.shutdown();
+        result
+    }
+
+    /// Serves the [Stratum protocol](crate::stratum) on `addr` until the server shuts
+    /// down, alongside [`run`](WorkServer::run) on another thread. Each lease is a job.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` once shut down, or the error that stopped the service from listening.
+    pub fn run_stratum(&self, addr: impl ToSocketAddrs) -> io::Result<()> {
+        let result = TcpListener::bind(addr)
+            .and_then(|listener| crate::stratum::serve(&self.coordinator, listener));
+        self.shutdown();
+        result
+    }
+
+    /// Stops the server: [`run`](WorkServer::run) returns once the requests in progress
+    /// are answered, and every [`wait`](WorkServer::wait) returns.
+    pub fn shutdown(&self) {
+        if self.coordinator.shutdown.swap(true, Ordering::Relaxed) {
+            return;
+        }
+        // Taking the lock orders the flag before any waiter's next check. Dropping the
+        // subscribers closes their connections.
+        self.coordinator.state.lock().unwrap_or_else(PoisonError::into_inner).subscribers.clear();
+        self.coordinator.changed.notify_all();
+        // Wake the accept loop so it notices.
+        if let Ok(mut addr) = self.local_addr() {
+            if addr.ip().is_unspecified() {
+                addr.set_ip(match addr {
+                    SocketAddr::V4(_) => Ipv4Addr::LOCALHOST.into(),
+                    SocketAddr::V6(_) => Ipv6Addr::LOCALHOST.into(),
+                });
+            }
+            let _ = TcpStream::connect(addr);
+        }
+    }
+
+    /// Accepts connections until the server shuts down, starting a thread for each.
+    fn accept<'scope>(&'scope self, scope: &'scope Scope<'scope, '_>) -> io::Result<()> {
+        for stream in self.listener.incoming() {
+            if self.coordinator.is_shut_down() {
+                break;
+            }
+            let stream = stream?;
+            scope.spawn(move || {
+                let _ = self.serve(stream);
+            });
+        }
+        Ok(())
+    }
+
+    /// Reads the one request on a connection and answers it.
+    fn serve(&self, stream: TcpStream) -> io::Result<()> {
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+        let tcp = stream.try_clone()?;
+        #[cfg(feature = "tls")]
+        if let Some(tls) = &self.tls {
+            return self.answer(tls.accept(stream)?, &tcp);
+        }
+        self.answer(stream, &tcp)
+    }
+
+    /// Reads the one request on `stream`, a connection over `tcp`, and answers it.
+    fn answer(&self, mut stream: impl Read + Write, tcp: &TcpStream) -> io::Result<()> {
+        let (status, response) = match read_request(BufReader::new(&mut stream)) {
+            Ok(request) => match request.websocket_key {
+                Some(key) if request.method == "GET" && request.path == "/events" => {
+                    return self.push_events(stream, tcp, &key);
+                }
+                _ => match self.admit(&request, tcp).and_then(|()| {
+                    self.handle(&request.method, &request.path, &request.body)
+                }) {
+                    Ok(response) => (200, response),
+                    Err(HttpError(status, message)) => (status, Response::error(&message)),
+                },
+            },
+            Err(HttpError(status, message)) => (status, Response::error(&message)),
+        };
+        let body = format!("{}\n", response);
+        write!(
+            stream,
+            "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+             Connection: close\r\n\r\n{}",
+            status,
+            reason(status),
+            body.len(),
+            body
+        )?;
+        stream.flush()
+    }
+
+    /// Completes the WebSocket handshake of a `GET /events` request with `key` on
+    /// `stream`, a connection over `tcp`, then pushes every event to the worker until it
+    /// hangs up or the server shuts down.
+    fn push_events(
+        &self,
+        mut stream: impl Read + Write,
+        tcp: &TcpStream,
+        key: &str,
+    ) -> io::Result<()> {
+        let events = self.coordinator.subscribe();
+        write!(
+            stream,
+            "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+             Sec-WebSocket-Accept: {}\r\n\r\n",
+            derive_accept_key(key.as_bytes())
+        )?;
+        // The worker's own messages are only looked for between events, never waited on.
+        tcp.set_read_timeout(Some(SOCKET_POLL))?;
+        let mut socket = WebSocket::from_raw_socket(stream, Role::Server, None);
+        loop {
+            let message = match events.recv_timeout(PING_INTERVAL) {
+                Ok(message) => Message::text(message),
+                Err(RecvTimeoutError::Timeout) => Message::Ping(Default::default()),
+                Err(RecvTimeoutError::Disconnected) => {
+                    let reason = "the server shut down".into();
+                    let _ = socket.close(Some(CloseFrame { code: CloseCode::Away, reason }));
+                    let _ = socket.flush();
+                    return Ok(());
+                }
+            };
+            socket.send(message).map_err(io::Error::other)?;
+            // Answer whatever the worker sent meanwhile, such as pongs or a goodbye.
+            loop {
+                match socket.read() {
+                    Ok(Message::Close(_)) => {
+                        let _ = socket.flush();
+                        return Ok(());
+                    }
+                    Ok(_) => {}
+                    Err(tungstenite::Error::Io(e))
+                        if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) =>
+                    {
+                        break;
+                    }
+                    Err(e) => return Err(io::Error::other(e)),
+                }
+            }
+        }
+    }
+
+    /// Checks that the client of `request`, connected over `tcp`, may make it: only
+    /// `POST /shutdown` is guarded, by the admin token if the server has one, or else by
+    /// taking it only from the machine the server runs on.
+    fn admit(&self, request: &HttpRequest, tcp: &TcpStream) -> Result<(), HttpError> {
+        if request.path != "/shutdown" || request.method != "POST" {
+            return Ok(());
+        }
+        match (&self.admin_token, &request.bearer) {
+            // Comparing digests takes as long however much of a guess is right.
+            (Some(token), Some(bearer)) if Sha256::digest(token) == Sha256::digest(bearer) => {
+                Ok(())
+            }
+            (Some(_), _) => Err(HttpError(401, "the admin token is missing or wrong".into())),
+            (None, _) if is_local(tcp) => Ok(()),
+            (None, _) => Err(HttpError(403, "only a local client may shut down".into())),
+        }
+    }
+
+    /// Carries out one request.
+    ///
+    /// # Returns
+    ///
+    /// The response, or why the request could not be carried out.
+    fn handle(&self, method: &str, path: &str, body: &str) -> Result<Response, HttpError> {
+        let body = if body.trim().is_empty() { "{}" } else { body };
+        let post = |handler: fn(&Self, &Request) -> Result<Response, HttpError>| {
+            if method != "POST" {
+                return Err(HttpError(405, format!("{} takes POST", path)));
+            }
+            handler(self, &Request::parse(body)?)
+        };
+        match path {
+            "/workers" => post(|server, _| {
+                Ok(Response::ok().number("worker", server.coordinator.register()?))
+            }),
+            "/leases" => post(|server, request| {
+                Ok(match server.coordinator.lease(request.required("worker")?)? {
+                    Some(lease) => lease_response(&lease),
+                    None => Response::ok(),
+                })
+            }),
+            "/solutions" => post(|server, request| {
+                let (worker, lease) = (request.required("worker")?, request.required("lease")?);
+                let status = server.coordinator.submit(worker, lease, request.required("nonce")?)?;
+                Ok(with_status(Response::ok(), &status))
+            }),
+            "/exhausted" => post(|server, request| {
+                let (worker, lease) = (request.required("worker")?, request.required("lease")?);
+                Ok(with_status(Response::ok(), &server.coordinator.exhausted(worker, lease)?))
+            }),
+            "/shares" => post(|server, request| {
+                let (worker, lease) = (request.required("worker")?, request.required("lease")?);
+                let solves = server.coordinator.share(worker, lease, request.required("nonce")?)?;
+                let response = Response::ok().value("solves", &Value::Bool(solves));
+                Ok(with_shares(response, &server.coordinator.worker(worker)?))
+            }),
+            "/progress" => post(|server, request| {
+                let (worker, lease) = (request.required("worker")?, request.required("lease")?);
+                server.coordinator.progress(worker, lease, request.required("searched")?)?;
+                Ok(Response::ok())
+            }),
+            "/status" if method != "GET" => Err(HttpError(405, format!("{} takes GET", path))),
+            "/status" => Ok(status_response(&self.coordinator.cluster_status()?)),
+            "/events" if method != "GET" => Err(HttpError(405, format!("{} takes GET", path))),
+            "/events" => Err(HttpError(426, format!("{} takes a WebSocket upgrade", path))),
+            "/shutdown" => post(|server, _| {
+                server.shutdown();
+                Ok(Response::ok())
+            }),
+            _ => match (path.strip_prefix("/puzzles/"), path.strip_prefix("/workers/")) {
+                (Some(_), _) | (_, Some(_)) if method != "GET" => {
+                    Err(HttpError(405, format!("{} takes GET", path)))
+                }
+                (Some(id), _) => {
+                    let id = id.parse().map_err(|_| HttpError(404, format!("no puzzle {}", id)))?;
+                    Ok(with_status(Response::ok(), &self.coordinator.status(id)?))
+                }
+                (_, Some(id)) => {
+                    let id = id.parse().map_err(|_| HttpError(404, format!("no worker {}", id)))?;
+                    Ok(with_shares(Response::ok(), &self.coordinator.worker(id)?))
+                }
+                (None, None) => Err(HttpError(404, format!("no such endpoint {}", path))),
+            },
+        }
+    }
+}
+
+impl fmt::Debug for WorkServer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WorkServer")
+            .field("addr", &self.local_addr().ok())
+            .field("lease_size", &self.lease_size())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Coordinator {
+    /// Whether the server has shut down.
+    pub(crate) fn is_shut_down(&self) -> bool {
+        self.shutdown.load(Ordering::Relaxed)
+    }
+
+    /// Blocks until the server shuts down.
+    #[cfg(feature = "grpc")]
+    pub(crate) fn wait_for_shutdown(&self) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        while !self.is_shut_down() {
+            state = self.changed.wait(state).unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+
+    /// Subscribes to the server's events, which arrive on the channel until the server
+    /// shuts down and drops its end.
+    fn subscribe(&self) -> Receiver<String> {
+        let (sender, receiver) = mpsc::channel();
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        if !self.is_shut_down() {
+            state.subscribers.push(sender);
+        }
+        receiver
+    }
+
+    /// Locks the state for one request.
+    ///
+    /// # Returns
+    ///
+    /// The state, or why it could not be locked or loaded.
+    fn lock(&self) -> Result<Locked<'_>, PuzzleError> {
+        self.locked(self.state.lock()?)
+    }
+
+    /// Locks `state`, already locked within this process, for one request: with Redis,
+    /// takes the lock of the queue and loads the state from it.
+    ///
+    /// # Returns
+    ///
+    /// The state, or why it could not be loaded.
+    fn locked<'a>(&'a self, state: MutexGuard<'a, State>) -> Result<Locked<'a>, PuzzleError> {
+        #[cfg(feature = "redis")]
+        let (state, token) = self.load(state)?;
+        Ok(Locked {
+            coordinator: self,
+            state: Some(state),
+            modified: false,
+            #[cfg(feature = "redis")]
+            token,
+        })
+    }
+
+    /// Takes the lock of the queue in Redis, if the state is kept there, and loads
+    /// `state` from it, keeping its subscribers.
+    ///
+    /// # Returns
+    ///
+    /// The state and the token of the lock of the queue if it was taken, or why the
+    /// state could not be loaded, in which case the lock is released again.
+    #[cfg(feature = "redis")]
+    fn load<'a>(
+        &self,
+        mut state: MutexGuard<'a, State>,
+    ) -> Result<(MutexGuard<'a, State>, Option<String>), PuzzleError> {
+        let Some(queue) = &self.redis else {
+            return Ok((state, None));
+        };
+        let token = queue.lock().map_err(unavailable)?;
+        let loaded = queue.load().map_err(unavailable).and_then(|stored| {
+            stored.map(|stored| State::decode(&stored)).transpose().map_err(|e| {
+                let message = format!("the queue in Redis at `{}` is corrupt: {}", queue.key(), e);
+                eprintln!("error: {}", message);
+                PuzzleError::Unavailable(message)
+            })
+        });
+        match loaded {
+            Ok(Some(mut loaded)) => {
+                loaded.subscribers = mem::take(&mut state.subscribers);
+                *state = loaded;
+            }
+            Ok(None) => {}
+            Err(e) => {
+                let _ = queue.unlock(&token);
+                return Err(e);
+            }
+        }
+        Ok((state, Some(token)))
+    }
+
+    /// Pushes `message`, an event some server on the queue in Redis published, to the
+    /// subscribers, and wakes the requests waiting for a change.
+    #[cfg(feature = "redis")]
+    fn relay(&self, message: String) {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner).deliver(&[message]);
+        self.changed.notify_all();
+    }
+
+    /// Registers a new worker.
+    ///
+    /// # Returns
+    ///
+    /// The worker's id, or a refusal if the state could not be saved.
+    pub(crate) fn register(&self) -> Result<u64, Refusal> {
+        let mut state = self.lock()?;
+        state.next_worker += 1;
+        let id = state.next_worker;
+        let worker = Worker { heard_at: Some(Instant::now()), ..Worker::default() };
+        state.workers.insert(id, worker);
+        state.commit()?;
+        Ok(id)
+    }
+
+    /// Leases the next range to `worker`.
+    ///
+    /// # Returns
+    ///
+    /// The lease, `None` if there is nothing to lease, or a refusal if the worker has not
+    /// registered.
+    pub(crate) fn lease(&self, worker: u64) -> Result<Option<WorkLease>, Refusal> {
+        let mut state = self.lock()?;
+        state.heard_from(worker)?;
+        let lease = self.next_range(&mut state, worker);
+        state.commit()?;
+        Ok(lease)
+    }
+
+    /// Blocks until `worker` holds no lease and there is a range to lease it, then leases
+    /// it, checking now and then whether the worker has `abandoned` the wait.
+    ///
+    /// # Returns
+    ///
+    /// The lease, `None` if the server shut down or the worker abandoned the wait first,
+    /// or a refusal if the worker has not registered.
+    pub(crate) fn wait_for_lease(
+        &self,
+        worker: u64,
+        abandoned: impl Fn() -> bool,
+    ) -> Result<Option<WorkLease>, Refusal> {
+        let mut state = self.lock()?;
+        state.heard_from(worker)?;
+        loop {
+            if self.is_shut_down() || abandoned() {
+                return Ok(None);
+            }
+            if !state.leases.values().any(|lease| lease.worker == worker) {
+                if let Some(lease) = self.next_range(&mut state, worker) {
+                    state.commit()?;
+                    return Ok(Some(lease));
+                }
+            }
+            state = state.wait(Some(ABANDON_CHECK))?;
+        }
+    }
+
+    /// Leases a range of the first puzzle in the order they were added that has one to
+    /// `worker`: a range taken back from another worker if there is one, and the lowest
+    /// range not yet leased otherwise. Expired leases are taken back first.
+    fn next_range(&self, state: &mut State, worker: u64) -> Option<WorkLease> {
+        self.reclaim_expired(state);
+        let index = state.jobs.iter().position(|job| {
+            job.solution.is_none() && (!job.reclaimed.is_empty() || job.next.is_some())
+        })?;
+        let job = &mut state.jobs[index];
+        let (nonces, extra_nonce) = match job.reclaimed.pop_front() {
+            Some(reclaimed) => reclaimed,
+            None => {
+                let start = job.next.unwrap();
+                let end = start.saturating_add(self.lease_size - 1).min(*job.nonces.end());
+                let extra_nonce = job.puzzle.extra_nonce;
+                job.next = if end < *job.nonces.end() {
+                    Some(end + 1)
+                } else if let Some(extra_nonce) = extra_nonce {
+                    // Search the window again with the next extra nonce.
+                    job.puzzle.extra_nonce = Some(extra_nonce.wrapping_add(1));
+                    Some(*job.nonces.start())
+                } else {
+                    None
+                };
+                (start..=end, extra_nonce)
+            }
+        };
+        let puzzle = Puzzle { extra_nonce, ..job.puzzle.clone() };
+        let share_target = self.share_target.map_or(puzzle.target, |share_target| {
+            share_target.max(puzzle.target)
+        });
+        job.outstanding += 1;
+
+        state.next_lease += 1;
+        let now = Instant::now();
+        let lease = Lease {
+            worker,
+            job: index,
+            nonces: nonces.clone(),
+            extra_nonce,
+            searched: 0,
+            share_target,
+            shares: HashSet::new(),
+            leased_at: now,
+            heard_at: now,
+        };
+        state.leases.insert(state.next_lease, lease);
+        Some(WorkLease {
+            id: state.next_lease,
+            puzzle_id: index as u64 + 1,
+            puzzle,
+            algorithm: job.algorithm,
+            nonces,
+            share_target: self.share_target.map(|_| share_target),
+        })
+    }
+
+    /// Takes back every lease whose worker has not been heard from about it for longer
+    /// than the lease timeout, and forgets those taken back longer ago than that.
+    fn reclaim_expired(&self, state: &mut State) {
+        let now = Instant::now();
+        let timeout = self.lease_timeout;
+        state.expired.retain(|_, &mut (_, expired_at)| now.duration_since(expired_at) <= timeout);
+        let mut expired: Vec<u64> = state
+            .leases
+            .iter()
+            .filter(|(_, lease)| now.duration_since(lease.heard_at) > self.lease_timeout)
+            .map(|(&id, _)| id)
+            .collect();
+        // The oldest ranges are leased again first.
+        expired.sort_unstable();
+        for id in expired {
+            state.reclaim(id);
+        }
+    }
+
+    /// Takes back every lease of `worker`, which has gone away, so that their ranges are
+    /// leased to other workers straight away. If the state cannot be locked, the leases
+    /// expire instead.
+    pub(crate) fn release(&self, worker: u64) {
+        let Ok(mut state) = self.lock() else {
+            return;
+        };
+        let mut held: Vec<u64> = state
+            .leases
+            .iter()
+            .filter(|(_, lease)| lease.worker == worker)
+            .map(|(&id, _)| id)
+            .collect();
+        held.sort_unstable();
+        for id in held {
+            state.reclaim(id);
+        }
+        drop(state);
+        self.changed.notify_all();
+    }
+
+    /// Checks `nonce` and, if it solves the puzzle, records it as the solution found by
+    /// `worker` in `lease`, which it closes.
+    ///
+    /// # Returns
+    ///
+    /// Where the puzzle stands now, or a refusal if the lease is not the worker's or the
+    /// nonce is outside it or no solution, in which case the lease stays open.
+    pub(crate) fn submit(&self, worker: u64, lease: u64, nonce: u64) -> Result<JobStatus, Refusal> {
+        let mut locked = self.lock()?;
+        let state = &mut *locked;
+        let (id, lease) = take_lease(state, worker, lease, |lease| {
+            if lease.nonces.contains(&nonce) {
+                Ok(())
+            } else {
+                Err(Refusal::Rejected(format!("nonce {} is outside the lease", nonce)))
+            }
+        })
+        .map_err(|refusal| state.counted(worker, refusal))?;
+        let job = &mut state.jobs[lease.job];
+        let puzzle = Puzzle { extra_nonce: lease.extra_nonce, ..job.puzzle.clone() };
+        let verification = job.algorithm.verify(&puzzle, nonce);
+        if !verification.valid {
+            let message = format!("nonce {} does not solve puzzle {}", nonce, lease.job + 1);
+            // The lease stays open, so the worker can still report on it.
+            state.leases.insert(id, lease);
+            return Err(state.counted(worker, Refusal::Rejected(message)));
+        }
+        job.outstanding -= 1;
+        job.searched = job.searched.saturating_add(lease.searched);
+        let reporter = state.workers.get_mut(&worker).unwrap();
+        // Workers count up through their leases, so every nonce up to the solution was
+        // searched.
+        let searched = lease.searched.max((nonce - lease.nonces.start()).saturating_add(1));
+        reporter.measure(&lease, searched);
+        reporter.searched = reporter.searched.saturating_add(searched);
+        if !lease.shares.contains(&nonce) {
+            // A solution is a share too, unless it was already sent as one.
+            reporter.credit(lease.share_target);
+        }
+        let first = job.solution.is_none();
+        if first {
+            let hash = verification.hash;
+            job.solution = Some(Solution { nonce, hash, extra_nonce: lease.extra_nonce });
+        }
+        let status = state.status(lease.job);
+        if first {
+            state.broadcast(Event::Finished(status.clone()));
+        }
+        locked.commit()?;
+        self.changed.notify_all();
+        Ok(status)
+    }
+
+    /// Records that `lease`, leased to `worker`, holds no solution, and closes it.
+    ///
+    /// # Returns
+    ///
+    /// Where the puzzle stands now, or a refusal if the lease is not the worker's.
+    pub(crate) fn exhausted(&self, worker: u64, lease: u64) -> Result<JobStatus, Refusal> {
+        let mut state = self.lock()?;
+        let (_, lease) = take_lease(&mut state, worker, lease, |_| Ok(()))?;
+        let reporter = state.workers.get_mut(&worker).unwrap();
+        reporter.measure(&lease, lease.len());
+        reporter.searched = reporter.searched.saturating_add(lease.len());
+        let job = &mut state.jobs[lease.job];
+        job.outstanding -= 1;
+        job.searched = job.searched.saturating_add(lease.len());
+        // Only the last lease of an unsolved puzzle can finish it.
+        let exhausted = job.solution.is_none() && job.is_finished();
+        let status = state.status(lease.job);
+        if exhausted {
+            state.broadcast(Event::Finished(status.clone()));
+        }
+        state.commit()?;
+        self.changed.notify_all();
+        Ok(status)
+    }
+
+    /// Checks `nonce` against the share target of `lease` and, if it meets it, counts it
+    /// as a share of `worker`, whose lease it must be. The lease stays open.
+    ///
+    /// # Returns
+    ///
+    /// Whether the nonce also solves the puzzle, or a refusal if the lease is not the
+    /// worker's or the nonce is outside it, was sent before or misses the share target.
+    pub(crate) fn share(&self, worker: u64, lease: u64, nonce: u64) -> Result<bool, Refusal> {
+        let mut locked = self.lock()?;
+        let state = &mut *locked;
+        state.heard_from(worker)?;
+        let lease = match state.leases.get_mut(&lease) {
+            Some(held) if held.worker == worker => held,
+            _ => return Err(state.missing_lease(worker, lease)),
+        };
+        lease.heard_at = Instant::now();
+        let message = if !lease.nonces.contains(&nonce) {
+            format!("nonce {} is outside the lease", nonce)
+        } else if lease.shares.contains(&nonce) {
+            format!("nonce {} was already sent", nonce)
+        } else {
+            let job = &state.jobs[lease.job];
+            let puzzle = Puzzle { extra_nonce: lease.extra_nonce, ..job.puzzle.clone() };
+            let hash = job.algorithm.verify(&puzzle, nonce).hash;
+            if lease.share_target.is_met_by(&hash) {
+                lease.shares.insert(nonce);
+                state.workers.get_mut(&worker).unwrap().credit(lease.share_target);
+                let solves = job.puzzle.target.is_met_by(&hash);
+                locked.commit()?;
+                return Ok(solves);
+            }
+            format!("nonce {} is no share", nonce)
+        };
+        Err(state.counted(worker, Refusal::Rejected(message)))
+    }
+
+    /// Records that `worker` has searched `searched` nonces of `lease` so far.
+    ///
+    /// # Returns
+    ///
+    /// A refusal if the lease is not the worker's or is smaller than that.
+    pub(crate) fn progress(&self, worker: u64, lease: u64, searched: u64) -> Result<(), Refusal> {
+        let mut locked = self.lock()?;
+        let state = &mut *locked;
+        state.heard_from(worker)?;
+        match state.leases.get_mut(&lease) {
+            Some(lease) if lease.worker == worker => {
+                if searched > lease.len() {
+                    let message = format!("the lease holds only {} nonces", lease.len());
+                    return Err(Refusal::Rejected(message));
+                }
+                lease.searched = searched;
+                lease.heard_at = Instant::now();
+                state.workers.get_mut(&worker).unwrap().measure(lease, searched);
+                Ok(locked.commit()?)
+            }
+            _ => Err(state.missing_lease(worker, lease)),
+        }
+    }
+
+    /// Reports the shares of the worker `id`.
+    pub(crate) fn worker(&self, id: u64) -> Result<WorkerShares, Refusal> {
+        let state = self.lock()?;
+        worker_exists(&state, id)?;
+        let idle_timeout = self.share_target.map(|_| self.idle_timeout);
+        Ok(state.worker_shares(id, idle_timeout, self.max_invalid_rate))
+    }
+
+    /// Reports the shares of every worker, in the order they registered.
+    pub(crate) fn workers(&self) -> Result<Vec<WorkerShares>, PuzzleError> {
+        let state = self.lock()?;
+        let idle_timeout = self.share_target.map(|_| self.idle_timeout);
+        Ok((1..=state.next_worker)
+            .map(|id| state.worker_shares(id, idle_timeout, self.max_invalid_rate))
+            .collect())
+    }
+
+    /// Reports where the puzzle `id` stands.
+    pub(crate) fn status(&self, id: u64) -> Result<JobStatus, Refusal> {
+        let state = self.lock()?;
+        match (id as usize).checked_sub(1) {
+            Some(index) if index < state.jobs.len() => Ok(state.status(index)),
+            _ => Err(Refusal::NotFound(format!("no puzzle {}", id))),
+        }
+    }
+
+    /// Reports every worker and puzzle.
+    pub(crate) fn cluster_status(&self) -> Result<ClusterStatus, PuzzleError> {
+        let state = self.lock()?;
+        let idle_timeout = self.share_target.map(|_| self.idle_timeout);
+        let workers = (1..=state.next_worker).map(|id| {
+            let shares = state.worker_shares(id, idle_timeout, self.max_invalid_rate);
+            state.worker_status(shares, self.lease_timeout)
+        });
+        let puzzles = state.jobs.iter().enumerate().map(|(index, job)| {
+            let status = state.status(index);
+            PuzzleCoverage {
+                puzzle: status.id,
+                solution: status.solution,
+                finished: status.finished,
+                searched: status.searched,
+                window: (job.nonces.end() - job.nonces.start()).saturating_add(1),
+            }
+        });
+        Ok(ClusterStatus { workers: workers.collect(), puzzles: puzzles.collect() })
+    }
+}
+
+/// The response to `POST /leases` that hands out `lease`.
+fn lease_response(lease: &WorkLease) -> Response {
+    let format = lease.puzzle.nonce_format;
+    let mut response = Response::ok()
+        .number("lease", lease.id)
+        .number("puzzle", lease.puzzle_id)
+        .string("algorithm", lease.algorithm.name())
+        .string("data_hex", &hex::encode(&lease.puzzle.data))
+        .string("target", &lease.puzzle.target.to_string())
+        .string("nonce_encoding", &format.encoding.to_string());
+    if let Some(offset) = format.offset {
+        response = response.number("nonce_offset", offset);
+    }
+    if let Some(extra_nonce) = lease.puzzle.extra_nonce {
+        response = response.number("extra_nonce", extra_nonce);
+    }
+    if let Some(share_target) = lease.share_target {
+        response = response.string("share_target", &share_target.to_string());
+    }
+    response.number("start", lease.nonces.start()).number("end", lease.nonces.end())
+}
+
+/// Adds the `worker` id of `shares`, its `shares` and `invalid` nonces, the `work` the
+/// shares prove, how many seconds ago it sent the last one and whether it is `idle` or
+/// `flagged` to `response`.
+fn with_shares(response: Response, shares: &WorkerShares) -> Response {
+    let response = response
+        .number("worker", shares.worker)
+        .number("shares", shares.shares)
+        .number("invalid", shares.invalid)
+        .number("work", shares.work)
+        .value("idle", &Value::Bool(shares.idle))
+        .value("flagged", &Value::Bool(shares.flagged));
+    match shares.since_last_share {
+        Some(since) => response.number("last_share", since.as_secs_f64()),
+        None => response,
+    }
+}
+
+/// The response to `GET /status` that shows the cluster as `status` has it.
+fn status_response(status: &ClusterStatus) -> Response {
+    let workers = status.workers.iter().map(|worker| {
+        with_shares(Response::object(), &worker.shares)
+            .value("connected", &Value::Bool(worker.connected))
+            .number("leases", worker.leases)
+            .number("searched", worker.searched)
+            .number("hash_rate", worker.hash_rate)
+    });
+    let puzzles = status.puzzles.iter().map(|puzzle| {
+        let status = JobStatus {
+            id: puzzle.puzzle,
+            solution: puzzle.solution.clone(),
+            finished: puzzle.finished,
+            searched: puzzle.searched,
+        };
+        with_status(Response::object(), &status)
+            .number("window", puzzle.window)
+            .number("coverage", puzzle.coverage())
+    });
+    Response::ok()
+        .number("connected", status.connected())
+        .number("hash_rate", status.hash_rate())
+        .objects("workers", workers)
+        .objects("puzzles", puzzles)
+}
+
+/// The message that pushes `event` to the workers.
+fn event_message(event: &Event) -> Response {
+    match event {
+        Event::Added(id) => Response::event("puzzle").number("puzzle", id),
+        Event::Finished(status) => with_status(Response::event("finished"), status),
+    }
+}
+
+/// Adds the `puzzle` id of `status`, its `state`, its solution if it has one, and the
+/// nonces `searched` so far to `response`.
+fn with_status(response: Response, status: &JobStatus) -> Response {
+    let response = response.number("puzzle", status.id).number("searched", status.searched);
+    match &status.solution {
+        Some(solution) => {
+            let response = response
+                .string("state", "solved")
+                .number("nonce", solution.nonce)
+                .string("hash", &hex::encode(&solution.hash));
+            match solution.extra_nonce {
+                Some(extra_nonce) => response.number("extra_nonce", extra_nonce),
+                None => response,
+            }
+        }
+        None if status.finished => response.string("state", "exhausted"),
+        None => response.string("state", "running"),
+    }
+}
+
+/// The error a queue in Redis that could not be used is reported as.
+#[cfg(feature = "redis")]
+fn unavailable(e: QueueError) -> PuzzleError {
+    PuzzleError::Unavailable(e.to_string())
+}
+
+/// `instant` in milliseconds since the Unix epoch, by this machine's clock.
+#[cfg(feature = "redis")]
+fn unix_millis(instant: Instant) -> u64 {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    now.saturating_sub(instant.elapsed()).as_millis() as u64
+}
+
+/// The instant `millis` milliseconds after the Unix epoch, by this machine's clock, or
+/// now if that is still to come.
+#[cfg(feature = "redis")]
+fn instant_at(millis: u64) -> Instant {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let ago = now.saturating_sub(Duration::from_millis(millis));
+    Instant::now().checked_sub(ago).unwrap_or_else(Instant::now)
+}
+
+/// Fails unless the worker `id` has registered.
+fn worker_exists(state: &State, id: u64) -> Result<(), Refusal> {
+    if state.workers.contains_key(&id) {
+        Ok(())
+    } else {
+        Err(Refusal::NotFound(format!("no worker {}", id)))
+    }
+}
+
+/// Removes the lease `id` from `state`, once it is known to belong to `worker` and
+/// `check` accepts it.
+///
+/// # Returns
+///
+/// The lease and its id.
+fn take_lease(
+    state: &mut State,
+    worker: u64,
+    id: u64,
+    check: impl FnOnce(&Lease) -> Result<(), Refusal>,
+) -> Result<(u64, Lease), Refusal> {
+    state.heard_from(worker)?;
+    match state.leases.get(&id) {
+        Some(lease) if lease.worker == worker => check(lease)?,
+        _ => return Err(state.missing_lease(worker, id)),
+    }
+    Ok((id, state.leases.remove(&id).unwrap()))
+}
+
+/// Reads an HTTP request.
+fn read_request(mut reader: impl BufRead) -> Result<HttpRequest, HttpError> {
+    let mut line = String::new();
+    if !read_line(&mut reader, &mut line)? {
+        return Err(HttpError(414, format!("the request line is over {} bytes", MAX_LINE)));
+    }
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(path), Some(_version), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err(HttpError(400, "malformed request line".to_string()));
+    };
+    let (method, path) = (method.to_string(), path.to_string());
+
+    let (mut length, mut upgrade, mut bearer, mut websocket_key) = (0, false, None, None);
+    for count in 0.. {
+        line.clear();
+        if !read_line(&mut reader, &mut line)? {
+            return Err(HttpError(431, format!("a header is over {} bytes", MAX_LINE)));
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if count == MAX_HEADERS {
+            return Err(HttpError(431, format!("there are over {} headers", MAX_HEADERS)));
+        }
+        let Some((name, value)) = header.split_once(':') else {
+            return Err(HttpError(400, format!("malformed header `{}`", header)));
+        };
+        let (name, value) = (name.trim(), value.trim());
+        if name.eq_ignore_ascii_case("upgrade") {
+            upgrade = value.eq_ignore_ascii_case("websocket");
+        } else if name.eq_ignore_ascii_case("authorization") {
+            bearer = value.strip_prefix("Bearer ").map(|token| token.trim().to_string());
+        } else if name.eq_ignore_ascii_case("sec-websocket-key") {
+            websocket_key = Some(value.to_string());
+        } else if name.eq_ignore_ascii_case("content-length") {
+            length = value
+                .parse()
+                .map_err(|_| HttpError(400, "invalid Content-Length".to_string()))?;
+        }
+    }
+    if length > MAX_BODY {
+        return Err(HttpError(413, format!("the body is over {} bytes", MAX_BODY)));
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).map_err(read_error)?;
+    let body = String::from_utf8(body).map_err(|_| "the body is not UTF-8".to_string())?;
+    let websocket_key = websocket_key.filter(|_| upgrade);
+    Ok(HttpRequest { method, path, body, bearer, websocket_key })
+}
+
+/// Reads a line of the head of a request onto `line`, but no more than [`MAX_LINE`]
+/// bytes of it.
+///
+/// # Returns
+///
+/// Whether the line was short enough, or why it could not be read.
+fn read_line(reader: &mut impl BufRead, line: &mut String) -> Result<bool, HttpError> {
+    let limit = MAX_LINE as u64 + 1;
+    let read = reader.by_ref().take(limit).read_line(line).map_err(read_error)?;
+    Ok(read <= MAX_LINE)
+}
+
+/// Whether the client on the other end of `tcp` runs on the same machine as the server.
+fn is_local(tcp: &TcpStream) -> bool {
+    tcp.peer_addr().is_ok_and(|addr| addr.ip().to_canonical().is_loopback())
+}
+
+/// A request that could not be read.
+fn read_error(e: io::Error) -> HttpError {
+    HttpError(400, format!("cannot read the request: {}", e))
+}
+
+/// The reason phrase of an HTTP status the server sends.
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        410 => "Gone",
+        413 => "Content Too Large",
+        414 => "URI Too Long",
+        426 => "Upgrade Required",
+        431 => "Request Header Fields Too Large",
+        503 => "Service Unavailable",
+        _ => "Unprocessable Content",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Difficulty;
+
+    /// A server with a puzzle no nonce a test tries solves, leasing 1000 nonces at a time.
+    fn server() -> WorkServer {
+        let server = WorkServer::bind("127.0.0.1:0").unwrap().with_lease_size(1000);
+        let puzzle = Puzzle::new("unsolvable", Difficulty::LeadingZeroBits(64));
+        server.add_puzzle(puzzle, Algorithm::Sha256, 0..=u64::MAX).unwrap();
+        server
+    }
+
+    /// Registers a worker with `coordinator` and leases it a range.
+    fn leased(coordinator: &Coordinator) -> (u64, WorkLease) {
+        let worker = coordinator.register().unwrap();
+        let lease = coordinator.lease(worker).unwrap().expect("a range to lease");
+        (worker, lease)
+    }
+
+    #[test]
+    fn submit_refuses_a_nonce_that_is_no_solution() {
+        let server = server();
+        let coordinator = &server.coordinator;
+        let (worker, lease) = leased(coordinator);
+
+        let refusal = coordinator.submit(worker, lease.id, *lease.nonces.start());
+        assert!(matches!(refusal, Err(Refusal::Rejected(_))), "{:?}", refusal.err());
+        let refusal = coordinator.submit(worker, lease.id, lease.nonces.end() + 1);
+        assert!(matches!(refusal, Err(Refusal::Rejected(_))), "{:?}", refusal.err());
+
+        // The lease stays open, and both nonces count against the worker.
+        let state = coordinator.lock().unwrap();
+        assert!(state.leases.contains_key(&lease.id));
+        assert_eq!(state.workers[&worker].invalid, 2);
+        assert!(state.jobs[0].solution.is_none());
+    }
+
+    #[test]
+    fn submit_refuses_a_lease_of_another_worker_without_counting_it() {
+        let server = server();
+        let coordinator = &server.coordinator;
+        let (_, lease) = leased(coordinator);
+        let other = coordinator.register().unwrap();
+
+        let refusal = coordinator.submit(other, lease.id, *lease.nonces.start());
+        assert!(matches!(refusal, Err(Refusal::NotFound(_))), "{:?}", refusal.err());
+        assert_eq!(coordinator.lock().unwrap().workers[&other].invalid, 0);
+    }
+
+    #[test]
+    fn submit_accepts_a_solution_and_finishes_the_puzzle() {
+        let server = WorkServer::bind("127.0.0.1:0").unwrap().with_lease_size(1000);
+        // A threshold above `u16::MAX` accepts every hash.
+        let puzzle = Puzzle::new("trivial", Difficulty::Threshold(1 << 16));
+        server.add_puzzle(puzzle, Algorithm::Sha256, 0..=u64::MAX).unwrap();
+        let coordinator = &server.coordinator;
+        let (worker, lease) = leased(coordinator);
+
+        let nonce = lease.nonces.start() + 7;
+        let status = coordinator.submit(worker, lease.id, nonce).unwrap();
+        assert!(status.finished);
+        assert_eq!(status.solution.map(|solution| solution.nonce), Some(nonce));
+        let state = coordinator.lock().unwrap();
+        assert!(state.leases.is_empty());
+        assert_eq!(state.workers[&worker].shares, 1);
+    }
+
+    #[test]
+    fn flags_a_worker_past_the_invalid_rate() {
+        let server = server().with_share_target(Target::MAX).with_max_invalid_rate(0.25);
+        let coordinator = &server.coordinator;
+        let (worker, lease) = leased(coordinator);
+        let start = *lease.nonces.start();
+        for nonce in start..start + 20 {
+            coordinator.share(worker, lease.id, nonce).unwrap();
+        }
+
+        let flagged = || coordinator.worker(worker).unwrap().flagged;
+        for invalid in 1..=7 {
+            // Sending a share twice is as invalid as a nonce outside the lease.
+            let refusal = coordinator.share(worker, lease.id, start);
+            assert!(matches!(refusal, Err(Refusal::Rejected(_))), "{:?}", refusal.err());
+            let shares = coordinator.worker(worker).unwrap();
+            assert_eq!((shares.shares, shares.invalid), (20, invalid));
+            // 6 of 26 is under the rate, 7 of 27 over it.
+            assert_eq!(flagged(), invalid == 7, "after {} invalid nonces", invalid);
+        }
+    }
+
+    #[test]
+    fn needs_a_few_invalid_nonces_before_flagging() {
+        let server = server().with_max_invalid_rate(0.0);
+        let coordinator = &server.coordinator;
+        let (worker, lease) = leased(coordinator);
+        for invalid in 1..=3 {
+            let _ = coordinator.submit(worker, lease.id, *lease.nonces.start());
+            let shares = coordinator.worker(worker).unwrap();
+            assert_eq!(shares.invalid, invalid);
+            assert_eq!(shares.flagged, invalid >= FLAG_MIN_INVALID);
+        }
+    }
+
+    #[test]
+    fn reclaims_expired_leases_and_refuses_late_reports() {
+        let timeout = Duration::from_millis(50);
+        let server = server().with_lease_timeout(timeout);
+        let coordinator = &server.coordinator;
+        let (late, lease) = leased(coordinator);
+        thread::sleep(timeout * 2);
+
+        // The next worker to ask is leased the expired range again, under a new id.
+        let (worker, again) = leased(coordinator);
+        assert_eq!(again.nonces, lease.nonces);
+        assert_ne!(again.id, lease.id);
+        let refusal = coordinator.exhausted(late, lease.id);
+        assert!(matches!(refusal, Err(Refusal::Expired(_))), "{:?}", refusal.err());
+        let refusal = coordinator.progress(worker, lease.id, 1);
+        assert!(matches!(refusal, Err(Refusal::NotFound(_))), "{:?}", refusal.err());
+        coordinator.exhausted(worker, again.id).unwrap();
+
+        // Once the lease timeout passes again, the expired lease is forgotten.
+        thread::sleep(timeout * 2);
+        let mut state = coordinator.lock().unwrap();
+        coordinator.reclaim_expired(&mut state);
+        assert!(state.expired.is_empty());
+        drop(state);
+        let refusal = coordinator.exhausted(late, lease.id);
+        assert!(matches!(refusal, Err(Refusal::NotFound(_))), "{:?}", refusal.err());
+    }
+
+    #[test]
+    fn reclaims_only_leases_unreported_on() {
+        let timeout = Duration::from_millis(300);
+        let server = server().with_lease_timeout(timeout);
+        let coordinator = &server.coordinator;
+        let (quiet, quiet_lease) = leased(coordinator);
+        let (busy, busy_lease) = leased(coordinator);
+        thread::sleep(timeout / 2);
+        coordinator.progress(busy, busy_lease.id, 10).unwrap();
+        thread::sleep(timeout / 2 + timeout / 4);
+
+        let mut state = coordinator.lock().unwrap();
+        coordinator.reclaim_expired(&mut state);
+        assert!(!state.leases.contains_key(&quiet_lease.id));
+        assert_eq!(state.expired.get(&quiet_lease.id).map(|&(worker, _)| worker), Some(quiet));
+        assert!(state.leases.contains_key(&busy_lease.id));
+        let reclaimed = state.jobs[0].reclaimed.front().map(|(nonces, _)| nonces);
+        assert_eq!(reclaimed, Some(&quiet_lease.nonces));
+    }
+}
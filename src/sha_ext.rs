@@ -0,0 +1,209 @@
+//! SHA-256 compression on the SHA instructions of the CPU.
+//!
+//! x86-64 CPUs with the SHA extensions (SHA-NI) and AArch64 CPUs with the ARMv8 crypto
+//! extensions run two or four rounds of SHA-256 in one instruction. [`detect`] tells
+//! whether the CPU has them, and [`compress`] runs one compression of [`INTERLEAVE`]
+//! messages with them. Each round waits on the one before, so one message at a time
+//! leaves the instructions idle for most of their latency; interleaving two messages
+//! fills that gap and nearly doubles the rate, well past the SIMD lanes of the
+//! [`lanes`](crate::lanes) module.
+//!
+//! The `sha2` crate uses the same instructions when it finds them, so one-off hashes such
+//! as [`validate`](crate::validate) already get them. The miner calls [`compress`]
+//! directly instead, to hash only the blocks from the nonce on and skip the buffering
+//! and padding of a full hash for every nonce.
+
+use crate::lanes::K;
+
+/// How many messages [`compress`] hashes at once.
+pub(crate) const INTERLEAVE: usize = 2;
+
+/// Whether the CPU has the SHA-256 instructions [`compress`] needs.
+pub(crate) fn detect() -> bool {
+    #[cfg(target_arch = "x86_64")]
+    {
+        is_x86_feature_detected!("sha")
+            && is_x86_feature_detected!("sse4.1")
+            && is_x86_feature_detected!("ssse3")
+    }
+    #[cfg(target_arch = "aarch64")]
+    {
+        std::arch::is_aarch64_feature_detected!("sha2")
+    }
+}
+
+/// One SHA-256 compression of each of `blocks` into the state of the same index.
+///
+/// # Safety
+///
+/// The CPU must have the instructions, i.e. [`detect`] must have returned `true`.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sha,sse2,ssse3,sse4.1")]
+pub(crate) unsafe fn compress(
+    states: &mut [[u32; 8]; INTERLEAVE],
+    blocks: &[[u8; 64]; INTERLEAVE],
+) {
+    use std::arch::x86_64::*;
+
+    // Reads the big-endian message words into native order.
+    let swap = _mm_set_epi64x(0x0C0D_0E0F_0809_0A0B, 0x0405_0607_0001_0203);
+    // The instructions take the state as the word pairs ABEF and CDGH.
+    let mut abef = [_mm_setzero_si128(); INTERLEAVE];
+    let mut cdgh = [_mm_setzero_si128(); INTERLEAVE];
+    // The last sixteen message words, four to a register.
+    let mut w = [[_mm_setzero_si128(); 4]; INTERLEAVE];
+    for m in 0..INTERLEAVE {
+        let cdab = _mm_shuffle_epi32(_mm_loadu_si128(states[m][..4].as_ptr().cast()), 0xB1);
+        let efgh = _mm_shuffle_epi32(_mm_loadu_si128(states[m][4..].as_ptr().cast()), 0x1B);
+        abef[m] = _mm_alignr_epi8(cdab, efgh, 8);
+        cdgh[m] = _mm_blend_epi16(efgh, cdab, 0xF0);
+        for (i, w) in w[m].iter_mut().enumerate() {
+            *w = _mm_shuffle_epi8(_mm_loadu_si128(blocks[m][16 * i..].as_ptr().cast()), swap);
+        }
+    }
+    let (abef_start, cdgh_start) = (abef, cdgh);
+
+    // Four rounds at a time, for every message in turn.
+    for i in 0..16 {
+        let k = _mm_loadu_si128(K[4 * i..].as_ptr().cast());
+        for m in 0..INTERLEAVE {
+            let w = &mut w[m];
+            if i >= 4 {
+                let [w0, w1, w2, w3] = [0, 1, 2, 3].map(|j| w[(i + j) % 4]);
+                let sum = _mm_add_epi32(_mm_sha256msg1_epu32(w0, w1), _mm_alignr_epi8(w3, w2, 4));
+                w[i % 4] = _mm_sha256msg2_epu32(sum, w3);
+            }
+            let wk = _mm_add_epi32(w[i % 4], k);
+            cdgh[m] = _mm_sha256rnds2_epu32(cdgh[m], abef[m], wk);
+            abef[m] = _mm_sha256rnds2_epu32(abef[m], cdgh[m], _mm_shuffle_epi32(wk, 0x0E));
+        }
+    }
+
+    for m in 0..INTERLEAVE {
+        let feba = _mm_shuffle_epi32(_mm_add_epi32(abef[m], abef_start[m]), 0x1B);
+        let dchg = _mm_shuffle_epi32(_mm_add_epi32(cdgh[m], cdgh_start[m]), 0xB1);
+        _mm_storeu_si128(states[m][..4].as_mut_ptr().cast(), _mm_blend_epi16(feba, dchg, 0xF0));
+        _mm_storeu_si128(states[m][4..].as_mut_ptr().cast(), _mm_alignr_epi8(dchg, feba, 8));
+    }
+}
+
+/// One SHA-256 compression of each of `blocks` into the state of the same index.
+///
+/// # Safety
+///
+/// The CPU must have the instructions, i.e. [`detect`] must have returned `true`.
+#[cfg(target_arch = "aarch64")]
+#[target_feature(enable = "sha2")]
+pub(crate) unsafe fn compress(
+    states: &mut [[u32; 8]; INTERLEAVE],
+    blocks: &[[u8; 64]; INTERLEAVE],
+) {
+    use std::arch::aarch64::*;
+
+    let mut abcd = [vdupq_n_u32(0); INTERLEAVE];
+    let mut efgh = [vdupq_n_u32(0); INTERLEAVE];
+    // The last sixteen message words, four to a register.
+    let mut w = [[vdupq_n_u32(0); 4]; INTERLEAVE];
+    for m in 0..INTERLEAVE {
+        abcd[m] = vld1q_u32(states[m][..4].as_ptr());
+        efgh[m] = vld1q_u32(states[m][4..].as_ptr());
+        for (i, w) in w[m].iter_mut().enumerate() {
+            // Reads the big-endian message words into native order.
+            *w = vreinterpretq_u32_u8(vrev32q_u8(vld1q_u8(blocks[m][16 * i..].as_ptr())));
+        }
+    }
+    let (abcd_start, efgh_start) = (abcd, efgh);
+
+    // Four rounds at a time, for every message in turn.
+    for i in 0..16 {
+        let k = vld1q_u32(K[4 * i..].as_ptr());
+        for m in 0..INTERLEAVE {
+            let w = &mut w[m];
+            if i >= 4 {
+                let [w0, w1, w2, w3] = [0, 1, 2, 3].map(|j| w[(i + j) % 4]);
+                w[i % 4] = vsha256su1q_u32(vsha256su0q_u32(w0, w1), w2, w3);
+            }
+            let wk = vaddq_u32(w[i % 4], k);
+            let abcd_before = abcd[m];
+            abcd[m] = vsha256hq_u32(abcd_before, efgh[m], wk);
+            efgh[m] = vsha256h2q_u32(efgh[m], abcd_before, wk);
+        }
+    }
+
+    for m in 0..INTERLEAVE {
+        vst1q_u32(states[m][..4].as_mut_ptr(), vaddq_u32(abcd[m], abcd_start[m]));
+        vst1q_u32(states[m][4..].as_mut_ptr(), vaddq_u32(efgh[m], efgh_start[m]));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sha2::{Digest, Sha256};
+
+    use super::*;
+    use crate::lanes::H0;
+
+    /// `message` with SHA-256 padding, as whole blocks.
+    fn blocks(message: &[u8]) -> Vec<[u8; 64]> {
+        let mut padded = message.to_vec();
+        padded.push(0x80);
+        padded.resize((padded.len() + 8).div_ceil(64) * 64 - 8, 0);
+        padded.extend_from_slice(&(message.len() as u64 * 8).to_be_bytes());
+        padded.chunks_exact(64).map(|block| block.try_into().expect("a whole block")).collect()
+    }
+
+    /// The digest of a final state.
+    fn digest(state: &[u32; 8]) -> Vec<u8> {
+        state.iter().flat_map(|word| word.to_be_bytes()).collect()
+    }
+
+    #[test]
+    fn compress_hashes_like_sha256() {
+        if !detect() {
+            eprintln!("skipped: this CPU has no SHA-256 instructions");
+            return;
+        }
+        for len in 0..=200 {
+            // Messages of the same length but different bytes, so each interleaved
+            // message is checked on its own.
+            let messages: [Vec<u8>; INTERLEAVE] = std::array::from_fn(|m| {
+                (0..len).map(|i| (i * 31 + m * 101 + len) as u8).collect()
+            });
+            let blocks = messages.each_ref().map(|message| blocks(message));
+            let mut states = [H0; INTERLEAVE];
+            let rounds =
+                (0..blocks[0].len()).map(|index| std::array::from_fn(|m| blocks[m][index]));
+            for round in rounds {
+                // SAFETY: `detect` returned `true` above.
+                unsafe { compress(&mut states, &round) };
+            }
+            for (state, message) in states.iter().zip(&messages) {
+                assert_eq!(digest(state), Sha256::digest(message)[..], "{} bytes", len);
+            }
+        }
+    }
+
+    #[test]
+    fn compress_keeps_interleaved_states_apart() {
+        if !detect() {
+            eprintln!("skipped: this CPU has no SHA-256 instructions");
+            return;
+        }
+        // One message is already a block in while the other starts, and the same block
+        // goes into both, so only the states tell them apart.
+        let long = [0x5a; 100];
+        let short = [0x5a; 36];
+        let (long_blocks, short_blocks) = (blocks(&long), blocks(&short));
+        assert_eq!((long_blocks.len(), short_blocks.len()), (2, 1));
+        let mut states = [H0; INTERLEAVE];
+        let first = [long_blocks[0]; INTERLEAVE];
+        // SAFETY: `detect` returned `true` above.
+        unsafe { compress(&mut states, &first) };
+        states[1] = H0;
+        let second = [long_blocks[1], short_blocks[0]];
+        // SAFETY: as above.
+        unsafe { compress(&mut states, &second) };
+        assert_eq!(digest(&states[0]), Sha256::digest(long)[..]);
+        assert_eq!(digest(&states[1]), Sha256::digest(short)[..]);
+    }
+}
@@ -0,0 +1,213 @@
+//! Ed25519 signatures over issued puzzles and accepted solutions.
+//!
+//! When puzzles pass between parties, a solver wants to know a puzzle really came from
+//! its issuer, and a third party wants to know a solution really was accepted. An issuer
+//! wraps a [`Puzzle`] in a [`SignedPuzzle`], which signs every parameter that affects
+//! what counts as a solution. A verifier that accepts a nonce hands out a [`Receipt`],
+//! a signed statement binding the puzzle data, the nonce and the time it was checked.
+//!
+//! Both sign a domain-separated message, so a signature over one can never be passed
+//! off as the other.
+
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use sha2::Sha256;
+
+use crate::digest::Digest;
+use crate::{hex, unix_now, validate_with, NonceEncoding, Puzzle};
+
+/// Domain separation for puzzle signatures.
+const PUZZLE_DOMAIN: &[u8] = b"signed-puzzle-v1";
+
+/// Domain separation for receipt signatures.
+const RECEIPT_DOMAIN: &[u8] = b"solution-receipt-v1";
+
+/// A puzzle together with its issuer's signature.
+#[derive(Clone, Debug)]
+pub struct SignedPuzzle {
+    /// The signed puzzle.
+    pub puzzle: Puzzle,
+    /// The issuer's public key.
+    pub issuer: VerifyingKey,
+    /// The issuer's signature over the puzzle's parameters.
+    pub signature: Signature,
+}
+
+impl SignedPuzzle {
+    /// Signs `puzzle` with the issuer's `key`.
+    ///
+    /// The signature covers the target, the data, the nonce format, the extra nonce and
+    /// the issue and expiry times. The unused `nonce` field is not signed.
+    pub fn sign(puzzle: Puzzle, key: &SigningKey) -> Self {
+        let signature = key.sign(&puzzle_message(&puzzle));
+        SignedPuzzle { puzzle, issuer: key.verifying_key(), signature }
+    }
+
+    /// Whether the signature matches the puzzle and the embedded issuer key.
+    ///
+    /// This only shows the puzzle is unchanged since its key signed it; use
+    /// [`is_signed_by`](SignedPuzzle::is_signed_by) to also check whose key that was.
+    pub fn verify_signature(&self) -> bool {
+        self.issuer.verify_strict(&puzzle_message(&self.puzzle), &self.signature).is_ok()
+    }
+
+    /// Whether the puzzle was signed by `issuer` and is unchanged.
+    pub fn is_signed_by(&self, issuer: &VerifyingKey) -> bool {
+        self.issuer == *issuer && self.verify_signature()
+    }
+}
+
+/// The reasons a string cannot be parsed as a [`Receipt`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReceiptError {
+    /// The receipt does not have the five `:`-separated fields, or one of them does not
+    /// parse.
+    Malformed,
+    /// The verifier field is not a valid ed25519 public key.
+    InvalidKey,
+}
+
+impl fmt::Display for ReceiptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReceiptError::Malformed => f.write_str("receipt is malformed"),
+            ReceiptError::InvalidKey => f.write_str("receipt has an invalid verifier key"),
+        }
+    }
+}
+
+impl Error for ReceiptError {}
+
+/// A verifier's signed statement that a nonce solved a puzzle at a given time.
+///
+/// It is written as `nonce:timestamp:data_hash:verifier:signature`, with the last three
+/// in hex.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Receipt {
+    /// SHA-256 of the puzzle's data, including the extra nonce if it had one.
+    pub data_hash: [u8; 32],
+    /// The nonce that solved the puzzle.
+    pub nonce: u64,
+    /// When the solution was checked, in seconds since the Unix epoch.
+    pub timestamp: u64,
+    /// The verifier's public key.
+    pub verifier: VerifyingKey,
+    /// The verifier's signature over the hash, nonce and timestamp.
+    pub signature: Signature,
+}
+
+impl Receipt {
+    /// Checks `nonce` against `puzzle` with SHA-256 and, if it solves it, signs a receipt
+    /// dated now with the verifier's `key`.
+    ///
+    /// # Returns
+    ///
+    /// The receipt, or `None` if the nonce does not solve the puzzle or the puzzle is
+    /// not live.
+    pub fn issue(key: &SigningKey, puzzle: &Puzzle, nonce: u64) -> Option<Self> {
+        Receipt::issue_with::<Sha256>(key, puzzle, nonce)
+    }
+
+    /// Like [`issue`](Receipt::issue), checking the nonce with the hash function `D`.
+    pub fn issue_with<D: Digest>(key: &SigningKey, puzzle: &Puzzle, nonce: u64) -> Option<Self> {
+        if !validate_with::<D>(puzzle, nonce) {
+            return None;
+        }
+        let data_hash = Sha256::digest(puzzle.message_data()).into();
+        let timestamp = unix_now();
+        let signature = key.sign(&receipt_message(&data_hash, nonce, timestamp));
+        Some(Receipt { data_hash, nonce, timestamp, verifier: key.verifying_key(), signature })
+    }
+
+    /// Whether the signature matches the receipt and the embedded verifier key.
+    pub fn verify_signature(&self) -> bool {
+        let message = receipt_message(&self.data_hash, self.nonce, self.timestamp);
+        self.verifier.verify_strict(&message, &self.signature).is_ok()
+    }
+
+    /// Whether the receipt was signed by `verifier` and is unchanged.
+    pub fn is_signed_by(&self, verifier: &VerifyingKey) -> bool {
+        self.verifier == *verifier && self.verify_signature()
+    }
+
+    /// Whether the receipt is about `puzzle`'s data.
+    pub fn is_for(&self, puzzle: &Puzzle) -> bool {
+        self.data_hash == <[u8; 32]>::from(Sha256::digest(puzzle.message_data()))
+    }
+}
+
+impl fmt::Display for Receipt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}:{}:{}",
+            self.nonce,
+            self.timestamp,
+            hex::encode(&self.data_hash),
+            hex::encode(self.verifier.as_bytes()),
+            hex::encode(&self.signature.to_bytes())
+        )
+    }
+}
+
+impl FromStr for Receipt {
+    type Err = ReceiptError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = s.trim().split(':').collect();
+        let [nonce, timestamp, data_hash, verifier, signature] = fields[..] else {
+            return Err(ReceiptError::Malformed);
+        };
+        Ok(Receipt {
+            data_hash: decode_array(data_hash)?,
+            nonce: nonce.parse().map_err(|_| ReceiptError::Malformed)?,
+            timestamp: timestamp.parse().map_err(|_| ReceiptError::Malformed)?,
+            verifier: VerifyingKey::from_bytes(&decode_array(verifier)?)
+                .map_err(|_| ReceiptError::InvalidKey)?,
+            signature: Signature::from_bytes(&decode_array(signature)?),
+        })
+    }
+}
+
+/// The bytes an issuer signs for `puzzle`.
+///
+/// Variable-length and optional fields are prefixed with their length or presence, so
+/// two different puzzles never produce the same message.
+fn puzzle_message(puzzle: &Puzzle) -> Vec<u8> {
+    let mut message = PUZZLE_DOMAIN.to_vec();
+    message.extend_from_slice(&puzzle.target.to_be_bytes());
+    message.extend_from_slice(&(puzzle.data.len() as u64).to_be_bytes());
+    message.extend_from_slice(&puzzle.data);
+    message.push(match puzzle.nonce_format.encoding {
+        NonceEncoding::BigEndian => 0,
+        NonceEncoding::LittleEndian => 1,
+        NonceEncoding::Ascii => 2,
+    });
+    let offset = puzzle.nonce_format.offset.map(|offset| offset as u64);
+    for field in [offset, puzzle.extra_nonce, puzzle.issued_at, puzzle.expires_at] {
+        match field {
+            None => message.push(0),
+            Some(value) => {
+                message.push(1);
+                message.extend_from_slice(&value.to_be_bytes());
+            }
+        }
+    }
+    message
+}
+
+/// The bytes a verifier signs for a receipt.
+fn receipt_message(data_hash: &[u8; 32], nonce: u64, timestamp: u64) -> Vec<u8> {
+    [RECEIPT_DOMAIN, data_hash, &nonce.to_be_bytes(), &timestamp.to_be_bytes()].concat()
+}
+
+/// Decodes a hex field of exactly `N` bytes.
+fn decode_array<const N: usize>(s: &str) -> Result<[u8; N], ReceiptError> {
+    hex::decode(s)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or(ReceiptError::Malformed)
+}
@@ -0,0 +1,161 @@
+//! Finding every solution in a nonce window instead of just the first.
+//!
+//! [`find_solutions_with`] starts the worker threads and immediately returns a
+//! [`Solutions`] iterator. Workers keep searching after a hit and push each valid nonce
+//! into a channel, so solutions can be consumed as they are discovered. The search ends
+//! when the window is exhausted, when the requested number of solutions has been found,
+//! when it is cancelled or runs out of time, or when the iterator is dropped.
+
+use std::ops::RangeInclusive;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::control::Control;
+use crate::search::BatchSize;
+use crate::digest::Digest;
+use crate::{partition_nonces, CancellationToken, PrehashedPuzzle, Puzzle, Solution};
+
+/// A stream of solutions produced by background worker threads.
+///
+/// Solutions are yielded in the order they are found, which is not nonce order because
+/// every thread works on its own part of the window. Dropping the iterator stops the
+/// workers and waits for them to exit.
+pub struct Solutions {
+    receiver: Receiver<Solution>,
+    /// Cancelled to stop the workers: once `limit` is reached, by a token or deadline,
+    /// or when the iterator is dropped.
+    control: Arc<Control>,
+    handles: Vec<JoinHandle<()>>,
+}
+
+impl Solutions {
+    /// Stops the search when `token` is cancelled, or at once if it already is. The
+    /// iterator then yields the solutions already found and ends.
+    pub fn cancel_on(&self, token: &CancellationToken) {
+        token.register(&self.control);
+    }
+
+    /// Stops the search once `max_duration` has passed, counting from now.
+    pub fn cancel_after(&self, max_duration: Duration) {
+        if let Some(deadline) = Instant::now().checked_add(max_duration) {
+            self.control.cancel_at(deadline);
+        }
+    }
+}
+
+impl Iterator for Solutions {
+    type Item = Solution;
+
+    /// Blocks until the next solution is found, or returns `None` once every worker has
+    /// finished.
+    fn next(&mut self) -> Option<Solution> {
+        let solution = self.receiver.recv().ok();
+        if solution.is_none() {
+            // Release a deadline watchdog, which would otherwise wait for the deadline.
+            self.control.finish();
+        }
+        solution
+    }
+}
+
+impl Drop for Solutions {
+    fn drop(&mut self) {
+        self.control.cancel();
+        for handle in self.handles.drain(..) {
+            let _ = handle.join();
+        }
+        self.control.finish();
+    }
+}
+
+/// Searches `nonces` for every nonce that solves `puzzle` with the hash function `D`.
+///
+/// # Parameters
+///
+/// - `puzzle`: The puzzle to solve. Its current extra nonce, if any, is used as is and is
+///   not rolled.
+/// - `nonces`: The inclusive window to search.
+/// - `num_threads`: How many worker threads to spawn. A value of zero is treated as one.
+/// - `limit`: Stop after this many solutions; `None` searches the whole window.
+///
+/// # Returns
+///
+/// An iterator over the solutions as they are found.
+pub fn find_solutions_with<D>(
+    puzzle: &Puzzle,
+    nonces: RangeInclusive<u64>,
+    num_threads: usize,
+    limit: Option<usize>,
+) -> Solutions
+where
+    D: Digest + Clone + Send + Sync + 'static,
+{
+    let prehashed = Arc::new(PrehashedPuzzle::<D>::from_puzzle(puzzle));
+    let extra_nonce = puzzle.extra_nonce;
+    let (sender, receiver) = mpsc::channel();
+
+    let control = Arc::new(Control::default());
+    if limit == Some(0) {
+        control.cancel();
+    }
+    // How many solutions have been claimed so far, shared so the limit is global.
+    let found = Arc::new(AtomicUsize::new(0));
+
+    let ranges = partition_nonces(nonces, num_threads);
+    let mut handles = Vec::with_capacity(ranges.len());
+
+    for (start, end) in ranges.into_iter().map(RangeInclusive::into_inner) {
+        let prehashed = Arc::clone(&prehashed);
+        let control = Arc::clone(&control);
+        let found = Arc::clone(&found);
+        let sender = sender.clone();
+
+        handles.push(thread::spawn(move || {
+            // Check for a stop between batches sized to the hash rate, not every nonce.
+            let mut batch_size = BatchSize::new();
+            let mut first = start;
+            loop {
+                if control.is_interrupted() {
+                    return;
+                }
+                let last = end.min(first.saturating_add(batch_size.next(first - start) - 1));
+                let timer = Instant::now();
+                for nonce in first..=last {
+                    let hash = prehashed.hash(nonce);
+                    if !prehashed.target.is_met_by(&hash) {
+                        continue;
+                    }
+
+                    // Claim a slot before sending, so no more than `limit` solutions go
+                    // out.
+                    if let Some(limit) = limit {
+                        let index = found.fetch_add(1, Ordering::Relaxed);
+                        if index >= limit {
+                            control.cancel();
+                            return;
+                        }
+                        if index + 1 == limit {
+                            control.cancel();
+                        }
+                    }
+
+                    let solution = Solution { nonce, hash: hash.to_vec(), extra_nonce };
+                    if sender.send(solution).is_err() {
+                        // The consumer is gone; nobody wants further results.
+                        return;
+                    }
+                }
+                batch_size.record(timer.elapsed());
+                if last == end {
+                    return;
+                }
+                first = last + 1;
+            }
+        }));
+    }
+
+    Solutions { receiver, control, handles }
+}
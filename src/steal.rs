@@ -0,0 +1,313 @@
+//! A work-stealing engine on crossbeam deques, for work that does not split evenly.
+//!
+//! The thread engine in the [`search`](crate::search) module hands out equal chunks of a
+//! nonce window, and the solvers that split their work up front give each thread an
+//! equal share. Both assume every piece takes about as long as any other. That holds for
+//! hashing nonces, but not for combining Equihash buckets of very different sizes, nor
+//! for a tree search whose branches are only discovered as it goes.
+//!
+//! A [`StealSearch`] works through tasks, and running one task may spawn more. Every
+//! worker keeps the tasks it spawns on its own deque and works on the newest first, so it
+//! stays on the part of the problem that is already in its cache. A worker that runs out
+//! steals the oldest task of another, which is usually the largest piece left. The search
+//! ends when a task returns an output ([`steal_search`]) or when no task is left anywhere
+//! ([`steal_collect`], which keeps every output).
+//!
+//! [`steal_nonces`] runs a [`ParallelSearch`] this way over a nonce window, which it
+//! halves until the pieces are small; [`parallel_mine_steal_with`] mines a puzzle with it.
+//! Like the rayon engine, it cannot be paused, checkpointed or observed, and the window
+//! is not searched from the bottom up, so the thread engine stays the default for mining.
+
+use std::borrow::Cow;
+use std::iter;
+use std::ops::RangeInclusive;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Instant;
+
+use crossbeam_deque::{Injector, Stealer, Worker};
+
+use crate::control::PROGRESS_INTERVAL;
+use crate::digest::Digest;
+use crate::search::{BatchSize, HashSearch};
+use crate::{ParallelSearch, PrehashedPuzzle, Puzzle, PuzzleError, Solution, Target};
+
+/// The most nonces [`steal_nonces`] leaves in one task: pieces this small are searched
+/// rather than halved again.
+const GRAIN: u64 = PROGRESS_INTERVAL;
+
+/// Work that splits into tasks as it runs; see the [module documentation](self).
+pub trait StealSearch: Send + Sync {
+    /// One piece of the work, such as a range of nonces or a node of a search tree.
+    type Task: Send;
+    /// Scratch state each worker builds once and reuses for every task. Use `()` when
+    /// none is needed.
+    type State;
+    /// What a task produces when it succeeds.
+    type Output: Send;
+
+    /// Builds the state of one worker, on that worker's thread.
+    fn init(&self) -> Self::State;
+
+    /// Runs `task`, handing any further work to `spawner`, and returns its output if it
+    /// succeeded.
+    ///
+    /// A long task should return `None` early once
+    /// [`is_stopped`](Spawner::is_stopped) is set.
+    fn run(
+        &self,
+        state: &mut Self::State,
+        task: Self::Task,
+        spawner: &Spawner<'_, Self::Task>,
+    ) -> Option<Self::Output>;
+}
+
+/// Where a running task puts the tasks it spawns: the deque of the worker running it.
+pub struct Spawner<'a, T> {
+    local: &'a Worker<T>,
+    /// Tasks spawned but not yet finished, across every worker.
+    pending: &'a AtomicUsize,
+    stop: &'a AtomicBool,
+}
+
+impl<T> Spawner<'_, T> {
+    /// Adds `task` to the work. It runs on this worker after the tasks spawned after it,
+    /// unless another worker steals it first.
+    pub fn spawn(&self, task: T) {
+        self.pending.fetch_add(1, Ordering::Relaxed);
+        self.local.push(task);
+    }
+
+    /// Whether the search has stopped, because a task succeeded or a worker panicked.
+    pub fn is_stopped(&self) -> bool {
+        self.stop.load(Ordering::Relaxed)
+    }
+}
+
+/// Runs `search` from the tasks `roots` on `num_threads` threads until a task returns an
+/// output.
+///
+/// A thread count of zero is treated as one.
+///
+/// # Returns
+///
+/// The output of the first task to succeed, [`PuzzleError::Exhausted`] if every task
+/// ran without one, or [`PuzzleError::WorkerPanicked`] if a task panicked.
+pub fn steal_search<S: StealSearch>(
+    search: &S,
+    roots: impl IntoIterator<Item = S::Task>,
+    num_threads: usize,
+) -> Result<S::Output, PuzzleError> {
+    run(search, roots, num_threads, true)?.pop().ok_or(PuzzleError::Exhausted)
+}
+
+/// Runs `search` from the tasks `roots` on `num_threads` threads until no task is left,
+/// keeping the output of every task that returned one.
+///
+/// The outputs come in no particular order. A thread count of zero is treated as one.
+///
+/// # Returns
+///
+/// Every output, or [`PuzzleError::WorkerPanicked`] if a task panicked.
+pub fn steal_collect<S: StealSearch>(
+    search: &S,
+    roots: impl IntoIterator<Item = S::Task>,
+    num_threads: usize,
+) -> Result<Vec<S::Output>, PuzzleError> {
+    run(search, roots, num_threads, false)
+}
+
+/// Runs `search` from `roots`, stopping at the first output if `first` is set.
+fn run<S: StealSearch>(
+    search: &S,
+    roots: impl IntoIterator<Item = S::Task>,
+    num_threads: usize,
+    first: bool,
+) -> Result<Vec<S::Output>, PuzzleError> {
+    let injector = Injector::new();
+    let mut roots_len = 0;
+    for task in roots {
+        injector.push(task);
+        roots_len += 1;
+    }
+    let pending = AtomicUsize::new(roots_len);
+    // Set by the winner of a first-output search, or by a worker that panicked.
+    let stop = AtomicBool::new(false);
+    let panicked = AtomicBool::new(false);
+
+    let workers: Vec<Worker<S::Task>> =
+        (0..num_threads.max(1)).map(|_| Worker::new_lifo()).collect();
+    let stealers: Vec<Stealer<S::Task>> = workers.iter().map(Worker::stealer).collect();
+
+    let outputs = thread::scope(|scope| {
+        let handles: Vec<_> = workers
+            .into_iter()
+            .map(|local| {
+                let (injector, stealers) = (&injector, &stealers);
+                let (pending, stop, panicked) = (&pending, &stop, &panicked);
+                scope.spawn(move || {
+                    let spawner = Spawner { local: &local, pending, stop };
+                    let mut outputs = Vec::new();
+                    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                        let mut state = search.init();
+                        while !spawner.is_stopped() {
+                            let Some(task) =
+                                local.pop().or_else(|| find_task(&local, injector, stealers))
+                            else {
+                                if pending.load(Ordering::Acquire) == 0 {
+                                    break;
+                                }
+                                thread::yield_now();
+                                continue;
+                            };
+                            if let Some(output) = search.run(&mut state, task, &spawner) {
+                                // Only the first output of a first-output search is kept.
+                                if !first || !stop.swap(true, Ordering::Relaxed) {
+                                    outputs.push(output);
+                                }
+                            }
+                            // Counted down only after the task's own spawns were counted
+                            // up, so the count cannot reach zero while work remains.
+                            pending.fetch_sub(1, Ordering::Release);
+                        }
+                    }));
+                    if result.is_err() {
+                        panicked.store(true, Ordering::Relaxed);
+                        stop.store(true, Ordering::Relaxed);
+                    }
+                    outputs
+                })
+            })
+            .collect();
+        // A panic is caught inside the worker, so joining cannot fail.
+        handles.into_iter().flat_map(|handle| handle.join().unwrap()).collect()
+    });
+    if panicked.load(Ordering::Relaxed) {
+        return Err(PuzzleError::WorkerPanicked);
+    }
+    Ok(outputs)
+}
+
+/// Takes a task for a worker whose own deque is empty: a batch of the roots if any are
+/// left, else the oldest task of another worker.
+fn find_task<T>(local: &Worker<T>, injector: &Injector<T>, stealers: &[Stealer<T>]) -> Option<T> {
+    iter::repeat_with(|| {
+        injector
+            .steal_batch_and_pop(local)
+            .or_else(|| stealers.iter().map(Stealer::steal).collect())
+    })
+    .find(|steal| !steal.is_retry())
+    .and_then(|steal| steal.success())
+}
+
+/// A [`ParallelSearch`] as a [`StealSearch`] over ranges of nonces.
+struct NonceSearch<S>(S);
+
+impl<S: ParallelSearch> StealSearch for NonceSearch<S> {
+    type Task = RangeInclusive<u64>;
+    /// The search's own state, the nonces of the batch being tested, and how many to
+    /// test next.
+    type State = (S::State, Vec<u64>, BatchSize);
+    type Output = S::Output;
+
+    fn init(&self) -> Self::State {
+        (self.0.init(), Vec::new(), BatchSize::new())
+    }
+
+    fn run(
+        &self,
+        (state, nonces, batch_size): &mut Self::State,
+        task: RangeInclusive<u64>,
+        spawner: &Spawner<'_, RangeInclusive<u64>>,
+    ) -> Option<S::Output> {
+        let (start, mut end) = task.into_inner();
+        // Leave the upper halves to be stolen, and keep the lowest piece.
+        while end - start >= GRAIN {
+            let middle = start + (end - start) / 2;
+            spawner.spawn(middle + 1..=end);
+            end = middle;
+        }
+        let mut first = start;
+        loop {
+            if spawner.is_stopped() {
+                return None;
+            }
+            let last = end.min(first.saturating_add(batch_size.next(first - start) - 1));
+            nonces.clear();
+            nonces.extend(first..=last);
+            let timer = Instant::now();
+            if let Some((_, output)) = self.0.test_batch(state, nonces) {
+                return Some(output);
+            }
+            batch_size.record(timer.elapsed());
+            if last == end {
+                return None;
+            }
+            first = last + 1;
+        }
+    }
+}
+
+/// Runs `search` over `nonces` on `num_threads` work-stealing threads until a candidate
+/// passes.
+///
+/// This is a drop-in replacement for [`parallel_search`](crate::parallel_search), with
+/// the same parameters and errors.
+///
+/// # Returns
+///
+/// The output of a candidate that passed, [`PuzzleError::EmptyRange`] if `nonces` is
+/// empty, [`PuzzleError::Exhausted`] if no candidate passed, or
+/// [`PuzzleError::WorkerPanicked`] if a test panicked.
+pub fn steal_nonces<S: ParallelSearch>(
+    search: S,
+    nonces: RangeInclusive<u64>,
+    num_threads: usize,
+) -> Result<S::Output, PuzzleError> {
+    if nonces.is_empty() {
+        return Err(PuzzleError::EmptyRange);
+    }
+    steal_search(&NonceSearch(search), [nonces], num_threads)
+}
+
+/// Searches `nonces` for a valid nonce of `puzzle` with the hash function `D`, like
+/// [`parallel_mine_range`](crate::parallel_mine_range) but on the work-stealing engine.
+///
+/// The data is prehashed once, and a puzzle with an extra nonce rolls it and searches the
+/// window again whenever the window is exhausted, as with the default engine.
+///
+/// # Returns
+///
+/// A [`Solution`], or a [`PuzzleError`] as for
+/// [`parallel_mine_range`](crate::parallel_mine_range).
+pub fn parallel_mine_steal_with<D>(
+    puzzle: &Puzzle,
+    nonces: RangeInclusive<u64>,
+    num_threads: usize,
+) -> Result<Solution, PuzzleError>
+where
+    D: Digest + Clone + Send + Sync + 'static,
+{
+    let target = puzzle.target;
+    if target == Target::ZERO {
+        return Err(PuzzleError::InvalidDifficulty);
+    }
+    let mut puzzle = Cow::Borrowed(puzzle);
+    loop {
+        let prehashed = Arc::new(PrehashedPuzzle::<D>::from_puzzle(&puzzle));
+        let search = HashSearch::new(
+            || (),
+            move |_, nonce| prehashed.hash(nonce),
+            move |hash| target.is_met_by(hash),
+        );
+        match (steal_nonces(search, nonces.clone(), num_threads), puzzle.extra_nonce) {
+            (Ok(solution), extra_nonce) => return Ok(Solution { extra_nonce, ..solution }),
+            (Err(PuzzleError::Exhausted), Some(extra_nonce)) => {
+                puzzle.to_mut().extra_nonce = Some(extra_nonce.wrapping_add(1));
+            }
+            (Err(e), _) => return Err(e),
+        }
+    }
+}
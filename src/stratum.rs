@@ -0,0 +1,796 @@
+//! A Stratum-style JSON-RPC protocol, so the solver can mine for a pool.
+//!
+//! A [`StratumClient`] is a worker of a pool that speaks this protocol, and
+//! [`WorkServer::run_stratum`](crate::WorkServer::run_stratum) makes a work server one
+//! such pool. Each message is a JSON-RPC object on a line of its own, over a plain TCP
+//! connection. The worker calls:
+//!
+//! - `mining.subscribe` with the name of its software. The result is
+//!   `[[["mining.notify", <subscription>]], <extranonce1>, <extranonce2 size>]`: the
+//!   `extranonce1` the pool gives this connection, in hex, and how many bytes of
+//!   `extranonce2` the worker rolls itself.
+//! - `mining.authorize` with a user name and password. The result is `true` if the pool
+//!   accepts them.
+//! - `mining.submit` with the user name, the job id, the `extranonce2` (hex) and the
+//!   nonce (16 hex digits) of a share. The result is `true` if the pool accepts it.
+//! - `mining.exhausted` with the user name and the job id, once the worker has searched
+//!   the job's nonces under every `extranonce2` without finishing it. This is not part
+//!   of Stratum, and a pool that does not know it refuses it, which does no harm.
+//!
+//! The pool notifies:
+//!
+//! - `mining.set_target` with a target, 64 hex digits, for the jobs that follow.
+//! - `mining.set_extranonce` with a new `extranonce1` and `extranonce2` size for the
+//!   jobs that follow.
+//! - `mining.notify` with a job: its id, the `algorithm`, the data in hex, the
+//!   `nonce_encoding`, the `nonce_offset` or null, the first and last nonce to try, and
+//!   whether earlier jobs are to be dropped. A share is a nonce whose message, built from
+//!   `data || extranonce1 || extranonce2` as the nonce encoding and offset say, hashes to
+//!   at most the target.
+//!
+//! An error is `[<code>, <message>, null]`, with Stratum's codes: 20 for any other
+//! error, 21 for a job that is not (or no longer) known, 23 for a share that misses the
+//! target and 25 for a worker that has not subscribed.
+//!
+//! ```text
+//! > {"id":1,"method":"mining.subscribe","params":["puzzle-solver/0.1.0"]}
+//! < {"id":1,"result":[[["mining.notify","1"]],"",0],"error":null}
+//! > {"id":2,"method":"mining.authorize","params":["alice","x"]}
+//! < {"id":2,"result":true,"error":null}
+//! < {"id":null,"method":"mining.set_target","params":["00000fff..."]}
+//! < {"id":null,"method":"mining.notify","params":["1","sha256","...","be",null,0,67108863,true]}
+//! > {"id":3,"method":"mining.submit","params":["alice","1","","0000000000171c25"]}
+//! < {"id":3,"result":true,"error":null}
+//! ```
+//!
+//! Jobs are this crate's puzzles rather than Bitcoin block templates, so the client
+//! cannot mine for a Bitcoin pool. A work server leases ranges rather than extranonces:
+//! each job is a lease, with an empty `extranonce1` and no `extranonce2`. Its target is
+//! the server's share target, if it counts shares, and a share that also meets the
+//! puzzle's own target solves the puzzle and closes the lease. The lease of a worker
+//! whose connection closes is leased to another worker.
+
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, ErrorKind, Write};
+use std::mem;
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::json::{Request, Response, Value};
+use crate::server::{Coordinator, Refusal};
+use crate::{
+    hex, Algorithm, CancellationToken, NonceFormat, Puzzle, PuzzleError, Solution, Target,
+    WorkLease,
+};
+
+/// The port of a `stratum+tcp://` URL that does not give one.
+const DEFAULT_PORT: u16 = 3333;
+
+/// How long the client waits for the pool to answer a call.
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long a read waits for a message before the reader looks up from it, to notice a
+/// search that ended or a server that shut down.
+const POLL: Duration = Duration::from_millis(10);
+
+/// The largest `extranonce2` the client can roll.
+const MAX_EXTRANONCE2_SIZE: usize = 8;
+
+/// How long the server waits before trying again to lease a worker a job, when its queue
+/// in Redis could not be reached.
+const UNAVAILABLE_RETRY: Duration = Duration::from_secs(1);
+
+/// The reasons talking to a pool failed.
+#[derive(Debug)]
+pub enum StratumError {
+    /// The pool could not be reached, or the connection failed.
+    Io(io::Error),
+    /// The pool closed the connection.
+    Closed,
+    /// The pool answered a call with this error code and message.
+    Refused(i64, String),
+    /// The pool's address or a message could not be understood.
+    Malformed(String),
+    /// Mining a job failed for a reason other than exhausting it.
+    Mining(PuzzleError),
+}
+
+impl fmt::Display for StratumError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StratumError::Io(e) => write!(f, "cannot reach the pool: {}", e),
+            StratumError::Closed => write!(f, "the pool closed the connection"),
+            StratumError::Refused(code, message) => {
+                write!(f, "the pool refused the call ({}): {}", code, message)
+            }
+            StratumError::Malformed(message) => write!(f, "malformed exchange: {}", message),
+            StratumError::Mining(e) => write!(f, "mining the job failed: {}", e),
+        }
+    }
+}
+
+impl Error for StratumError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            StratumError::Io(e) => Some(e),
+            StratumError::Mining(e) => Some(e),
+            StratumError::Closed | StratumError::Refused(..) | StratumError::Malformed(_) => {
+                None
+            }
+        }
+    }
+}
+
+impl From<io::Error> for StratumError {
+    fn from(e: io::Error) -> Self {
+        StratumError::Io(e)
+    }
+}
+
+impl From<String> for StratumError {
+    /// A message that could not be read.
+    fn from(message: String) -> Self {
+        StratumError::Malformed(message)
+    }
+}
+
+/// A job the pool asked its workers to mine.
+#[derive(Clone, Debug)]
+pub struct StratumJob {
+    /// The job's id on the pool.
+    pub id: String,
+    /// The hash the puzzle is mined with.
+    pub algorithm: Algorithm,
+    /// The puzzle, with the target in effect when the job arrived. The extranonces are
+    /// appended to its data before mining.
+    pub puzzle: Puzzle,
+    /// The nonces to try under each `extranonce2`.
+    pub nonces: RangeInclusive<u64>,
+    /// The part of the data the pool gave this connection.
+    pub extranonce1: Vec<u8>,
+    /// The number of bytes of data the worker rolls itself, at most 8.
+    pub extranonce2_size: usize,
+    /// The `extranonce2` and nonce to resume from, or `None` once all are searched.
+    next: Option<(u64, u64)>,
+}
+
+impl StratumJob {
+    /// The data the nonce is placed into under `extranonce2`:
+    /// `data || extranonce1 || extranonce2`.
+    pub fn data(&self, extranonce2: &[u8]) -> Vec<u8> {
+        let mut data = self.puzzle.message_data().into_owned();
+        data.extend_from_slice(&self.extranonce1);
+        data.extend_from_slice(extranonce2);
+        data
+    }
+
+    /// The `extranonce2` numbered `n`, as the bytes it adds to the data.
+    fn extranonce2(&self, n: u64) -> Vec<u8> {
+        n.to_be_bytes()[MAX_EXTRANONCE2_SIZE - self.extranonce2_size..].to_vec()
+    }
+
+    /// The last `extranonce2` number.
+    fn last_extranonce2(&self) -> u64 {
+        match self.extranonce2_size {
+            MAX_EXTRANONCE2_SIZE => u64::MAX,
+            size => (1 << (8 * size)) - 1,
+        }
+    }
+}
+
+/// A nonce that meets a job's target.
+#[derive(Clone, Debug)]
+pub struct StratumShare {
+    /// The id of the job it was found in.
+    pub job_id: String,
+    /// The `extranonce2` it was found under.
+    pub extranonce2: Vec<u8>,
+    /// The nonce and the hash it produces.
+    pub solution: Solution,
+}
+
+/// How a call to [`StratumClient::mine`] ended.
+#[derive(Debug)]
+pub enum Mined {
+    /// A share was found and the pool accepted it. Mine the job again to look for more.
+    Accepted(StratumShare),
+    /// A share was found but the pool refused it, with this error code and message.
+    Rejected { share: StratumShare, code: i64, message: String },
+    /// Every nonce of the job was searched, under every `extranonce2`.
+    Exhausted,
+    /// The pool sent a new job, which [`job`](StratumClient::job) returns.
+    Superseded,
+}
+
+/// A worker subscribed to a pool; see the [module documentation](self).
+///
+/// ```text
+/// let mut client = StratumClient::connect("stratum+tcp://pool:3333", "alice", "x")?;
+/// while let Some(mut job) = client.job()? {
+///     while let Mined::Accepted(_) | Mined::Rejected { .. } = client.mine(&mut job, 8)? {}
+/// }
+/// ```
+#[derive(Debug)]
+pub struct StratumClient {
+    lines: Lines,
+    writer: TcpStream,
+    user: String,
+    next_id: u64,
+    extranonce1: Vec<u8>,
+    extranonce2_size: usize,
+    /// The target of the jobs that follow, once the pool has set one.
+    target: Option<Target>,
+    /// The newest job the pool sent that [`job`](StratumClient::job) has not returned.
+    job: Option<StratumJob>,
+}
+
+impl StratumClient {
+    /// Subscribes to the pool at `url`, written as `stratum+tcp://host:port` (the port
+    /// defaults to 3333), and authorizes as `user` with `password`.
+    ///
+    /// # Returns
+    ///
+    /// The client, or why the pool could not be reached or would not take it.
+    pub fn connect(url: &str, user: &str, password: &str) -> Result<Self, StratumError> {
+        let addr = url
+            .strip_prefix("stratum+tcp://")
+            .ok_or_else(|| format!("`{}` is not a stratum+tcp:// URL", url))?
+            .trim_end_matches('/');
+        if addr.is_empty() || addr.contains('/') {
+            return Err(StratumError::Malformed(format!("`{}` is not a pool address", url)));
+        }
+        let has_port = addr.rsplit_once(':').is_some_and(|(_, port)| !port.contains(']'));
+        let stream = if has_port {
+            TcpStream::connect(addr)?
+        } else {
+            TcpStream::connect((addr.trim_start_matches('[').trim_end_matches(']'), DEFAULT_PORT))?
+        };
+        stream.set_read_timeout(Some(POLL))?;
+        let mut client = StratumClient {
+            lines: Lines::new(stream.try_clone()?),
+            writer: stream,
+            user: user.to_string(),
+            next_id: 0,
+            extranonce1: Vec::new(),
+            extranonce2_size: 0,
+            target: None,
+            job: None,
+        };
+
+        let agent = format!("puzzle-solver/{}", env!("CARGO_PKG_VERSION"));
+        let reply = client.call("mining.subscribe", vec![Value::String(agent)])?;
+        let result = reply.value("result").and_then(Value::as_array).unwrap_or_default();
+        let (Some(extranonce1), Some(size)) = (result.get(1), result.get(2)) else {
+            return Err(StratumError::Malformed("the subscription has no extranonce".into()));
+        };
+        client.set_extranonce(extranonce1, size)?;
+
+        let params = vec![Value::String(user.to_string()), Value::String(password.to_string())];
+        let reply = client.call("mining.authorize", params)?;
+        if reply.value("result").and_then(Value::as_bool) != Some(true) {
+            let message = format!("user `{}` is not authorized", user);
+            return Err(StratumError::Refused(24, message));
+        }
+        Ok(client)
+    }
+
+    /// The part of the data the pool gave this connection.
+    pub fn extranonce1(&self) -> &[u8] {
+        &self.extranonce1
+    }
+
+    /// The number of bytes of data the worker rolls itself.
+    pub fn extranonce2_size(&self) -> usize {
+        self.extranonce2_size
+    }
+
+    /// Blocks until the pool sends a job, skipping any that a newer one replaced.
+    ///
+    /// # Returns
+    ///
+    /// The job, `None` once the pool has closed the connection, or why reading failed.
+    pub fn job(&mut self) -> Result<Option<StratumJob>, StratumError> {
+        loop {
+            if let Some(job) = self.job.take() {
+                return Ok(Some(job));
+            }
+            match self.lines.next() {
+                Ok(Some(message)) => self.notified(&message)?,
+                Ok(None) => {}
+                Err(StratumError::Closed) => return Ok(None),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Mines `job` on `num_threads` threads, from where the last call left off, until it
+    /// finds a share, searches the whole job or the pool sends a new one. A share is
+    /// submitted as soon as it is found, and an exhausted job is reported.
+    ///
+    /// # Returns
+    ///
+    /// How mining ended, or why mining or talking to the pool failed.
+    pub fn mine(
+        &mut self,
+        job: &mut StratumJob,
+        num_threads: usize,
+    ) -> Result<Mined, StratumError> {
+        while let Some((n, start)) = job.next {
+            if self.job.is_some() {
+                return Ok(Mined::Superseded);
+            }
+            let extranonce2 = job.extranonce2(n);
+            let data = job.data(&extranonce2);
+            let puzzle = Puzzle { data, extra_nonce: None, ..job.puzzle.clone() };
+            let nonces = start..=*job.nonces.end();
+            let Some(result) = self.search(job.algorithm, &puzzle, nonces, num_threads)? else {
+                return Ok(Mined::Superseded);
+            };
+            let resume = match &result {
+                Ok(solution) if solution.nonce < *job.nonces.end() => Some((n, solution.nonce + 1)),
+                Ok(_) | Err(PuzzleError::Exhausted | PuzzleError::InvalidDifficulty) => {
+                    (n < job.last_extranonce2()).then(|| (n + 1, *job.nonces.start()))
+                }
+                Err(_) => None,
+            };
+            job.next = resume;
+            match result {
+                Ok(solution) => {
+                    let share = StratumShare { job_id: job.id.clone(), extranonce2, solution };
+                    return self.submit(share);
+                }
+                Err(PuzzleError::Exhausted | PuzzleError::InvalidDifficulty) => {}
+                Err(e) => return Err(StratumError::Mining(e)),
+            }
+        }
+        let params = vec![Value::String(self.user.clone()), Value::String(job.id.clone())];
+        match self.call("mining.exhausted", params) {
+            // A pool that has dropped the job, or does not know the call, need not hear.
+            Ok(_) | Err(StratumError::Refused(..)) => Ok(Mined::Exhausted),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Submits `share` to the pool.
+    fn submit(&mut self, share: StratumShare) -> Result<Mined, StratumError> {
+        let params = vec![
+            Value::String(self.user.clone()),
+            Value::String(share.job_id.clone()),
+            Value::String(hex::encode(&share.extranonce2)),
+            Value::String(format!("{:016x}", share.solution.nonce)),
+        ];
+        match self.call("mining.submit", params) {
+            Ok(_) => Ok(Mined::Accepted(share)),
+            Err(StratumError::Refused(code, message)) => {
+                Ok(Mined::Rejected { share, code, message })
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Searches `nonces` of `puzzle` on `num_threads` threads while reading what the pool
+    /// sends, giving up as soon as it sends a new job.
+    ///
+    /// # Returns
+    ///
+    /// What the search came to, `None` if a new job cut it short, or why reading failed.
+    fn search(
+        &mut self,
+        algorithm: Algorithm,
+        puzzle: &Puzzle,
+        nonces: RangeInclusive<u64>,
+        num_threads: usize,
+    ) -> Result<Option<Result<Solution, PuzzleError>>, StratumError> {
+        let token = CancellationToken::new();
+        thread::scope(|scope| {
+            let token = &token;
+            let search = scope.spawn(move || {
+                algorithm.parallel_mine_cancellable(puzzle, nonces, num_threads, token)
+            });
+            while !search.is_finished() {
+                let read = self.lines.next().and_then(|message| match message {
+                    Some(message) => self.notified(&message),
+                    None => Ok(()),
+                });
+                if read.is_err() || self.job.is_some() {
+                    token.cancel();
+                    let _ = search.join();
+                    return read.map(|()| None);
+                }
+            }
+            Ok(Some(search.join().unwrap()))
+        })
+    }
+
+    /// Calls `method` with `params` and waits for the answer, noting whatever the pool
+    /// sends in the meantime.
+    ///
+    /// # Returns
+    ///
+    /// The answer, or the error the pool answered with.
+    fn call(&mut self, method: &str, params: Vec<Value>) -> Result<Request, StratumError> {
+        self.next_id += 1;
+        let id = self.next_id;
+        let request = Response::with_id(Some(id))
+            .string("method", method)
+            .value("params", &Value::Array(params));
+        writeln!(self.writer, "{}", request)?;
+        let deadline = Instant::now() + RESPONSE_TIMEOUT;
+        loop {
+            match self.lines.next()? {
+                Some(message) if message.value("method").is_some() => self.notified(&message)?,
+                Some(reply) if reply.value("id").and_then(Value::as_number) == Some(id) => {
+                    return match reply.value("error") {
+                        None | Some(Value::Null) => Ok(reply),
+                        Some(error) => Err(refusal(error)),
+                    };
+                }
+                // Answers to calls given up on are dropped.
+                Some(_) => {}
+                None if Instant::now() >= deadline => {
+                    let message = format!("the pool did not answer `{}`", method);
+                    return Err(io::Error::new(ErrorKind::TimedOut, message).into());
+                }
+                None => {}
+            }
+        }
+    }
+
+    /// Notes a notification from the pool. Unknown ones are skipped.
+    fn notified(&mut self, message: &Request) -> Result<(), StratumError> {
+        let params = message.value("params").and_then(Value::as_array).unwrap_or_default();
+        match message.string("method")? {
+            Some("mining.set_target") => {
+                self.target = Some(param(params, 0, Value::as_str)?.parse()?);
+            }
+            Some("mining.set_extranonce") => {
+                let (Some(extranonce1), Some(size)) = (params.first(), params.get(1)) else {
+                    return Err("`mining.set_extranonce` needs two parameters".to_string().into());
+                };
+                self.set_extranonce(extranonce1, size)?;
+            }
+            Some("mining.notify") => {
+                let target = self.target.ok_or("a job came before any target".to_string())?;
+                let data = param(params, 2, Value::as_str)?;
+                let data = hex::decode(data).map_err(|e| e.to_string())?;
+                let nonce_format = NonceFormat {
+                    encoding: parse(param(params, 3, Value::as_str)?)?,
+                    offset: match params.get(4) {
+                        None | Some(Value::Null) => None,
+                        Some(_) => Some(param(params, 4, Value::as_number)?),
+                    },
+                };
+                let start = param(params, 5, Value::as_number)?;
+                let end = param(params, 6, Value::as_number)?;
+                if start > end {
+                    return Err(format!("the job's nonces {}..={} are empty", start, end).into());
+                }
+                self.job = Some(StratumJob {
+                    id: param(params, 0, Value::as_str)?.to_string(),
+                    algorithm: parse(param(params, 1, Value::as_str)?)?,
+                    puzzle: Puzzle::new(data, target).with_nonce_format(nonce_format),
+                    nonces: start..=end,
+                    extranonce1: self.extranonce1.clone(),
+                    extranonce2_size: self.extranonce2_size,
+                    next: Some((0, start)),
+                });
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Takes the `extranonce1` and `extranonce2` size the pool sent.
+    fn set_extranonce(&mut self, extranonce1: &Value, size: &Value) -> Result<(), StratumError> {
+        let extranonce1 = extranonce1.as_str().ok_or("the extranonce1 is no string".to_string())?;
+        let size = size.as_number().filter(|&size| size <= MAX_EXTRANONCE2_SIZE);
+        self.extranonce1 = hex::decode(extranonce1).map_err(|e| e.to_string())?;
+        self.extranonce2_size = size.ok_or_else(|| {
+            format!("the extranonce2 size must be at most {} bytes", MAX_EXTRANONCE2_SIZE)
+        })?;
+        Ok(())
+    }
+}
+
+/// Reads the messages on a connection, one per line, without losing a partial line to a
+/// read that timed out.
+#[derive(Debug)]
+struct Lines {
+    reader: BufReader<TcpStream>,
+    line: Vec<u8>,
+}
+
+impl Lines {
+    fn new(stream: TcpStream) -> Self {
+        Lines { reader: BufReader::new(stream), line: Vec::new() }
+    }
+
+    /// Reads the next message, waiting no longer than the stream's read timeout.
+    ///
+    /// # Returns
+    ///
+    /// The message, `None` if none came in time, [`StratumError::Closed`] if the other
+    /// end closed the connection, or why reading failed.
+    fn next(&mut self) -> Result<Option<Request>, StratumError> {
+        match self.reader.read_until(b'\n', &mut self.line) {
+            Ok(_) if !self.line.ends_with(b"\n") => return Err(StratumError::Closed),
+            Ok(_) => {}
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                return Ok(None)
+            }
+            Err(e) => return Err(e.into()),
+        }
+        let line = String::from_utf8(mem::take(&mut self.line))
+            .map_err(|_| "a message is not UTF-8".to_string())?;
+        if line.trim().is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Request::parse(line.trim())?))
+    }
+}
+
+/// Serves the work of `coordinator` to Stratum workers connecting to `listener`, each
+/// connection on a thread of its own, until the server shuts down.
+///
+/// # Returns
+///
+/// `Ok(())` once shut down, or the error that stopped it from accepting connections.
+pub(crate) fn serve(coordinator: &Coordinator, listener: TcpListener) -> io::Result<()> {
+    // Accepting without blocking lets the loop notice that the server shut down.
+    listener.set_nonblocking(true)?;
+    thread::scope(|scope| {
+        while !coordinator.is_shut_down() {
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    scope.spawn(move || {
+                        let _ = Session::new(coordinator, stream).and_then(|session| session.run());
+                    });
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => thread::sleep(POLL),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    })
+}
+
+/// One worker's connection to the Stratum service.
+struct Session<'a> {
+    coordinator: &'a Coordinator,
+    writer: Mutex<TcpStream>,
+    /// Set once the worker's connection is closed.
+    closed: AtomicBool,
+}
+
+impl<'a> Session<'a> {
+    fn new(coordinator: &'a Coordinator, stream: TcpStream) -> io::Result<Self> {
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(POLL))?;
+        Ok(Session { coordinator, writer: Mutex::new(stream), closed: AtomicBool::new(false) })
+    }
+
+    /// Answers the worker's calls until it goes away or the server shuts down, and once it
+    /// has subscribed, sends it a job whenever it has none and there is one.
+    fn run(&self) -> io::Result<()> {
+        let mut lines = Lines::new(self.writer().try_clone()?);
+        let mut worker = None;
+        let result = thread::scope(|scope| {
+            let result = loop {
+                if self.coordinator.is_shut_down() {
+                    break Ok(());
+                }
+                let (id, answer) = match lines.next() {
+                    Ok(Some(message)) => {
+                        let id = message.value("id").and_then(Value::as_number);
+                        (id, self.answer(&message, &mut worker))
+                    }
+                    Ok(None) => continue,
+                    Err(StratumError::Io(e)) => break Err(e),
+                    Err(StratumError::Malformed(message)) => (None, Err((20, message))),
+                    Err(_) => break Ok(()),
+                };
+                let (reply, subscribed) = match answer {
+                    Ok((result, subscribed)) => {
+                        let reply = Response::with_id(id).value("result", &result);
+                        (reply.value("error", &Value::Null), subscribed)
+                    }
+                    Err((code, message)) => {
+                        let reply = Response::with_id(id).value("result", &Value::Null);
+                        (reply.value("error", &error_value(code, &message)), None)
+                    }
+                };
+                if let Err(e) = self.send(&reply) {
+                    break Err(e);
+                }
+                if let Some(worker) = subscribed {
+                    scope.spawn(move || self.push_jobs(worker));
+                }
+            };
+            // Stops pushing jobs.
+            self.closed.store(true, Ordering::Relaxed);
+            result
+        });
+        // Nobody else can report on the worker's job, so another worker may as well mine it.
+        if let Some(worker) = worker {
+            self.coordinator.release(worker);
+        }
+        let _ = self.writer().shutdown(Shutdown::Both);
+        result
+    }
+
+    /// Carries out a call of the worker, which is registered as `worker` once it has
+    /// subscribed.
+    ///
+    /// # Returns
+    ///
+    /// The result, along with the worker's id if it just subscribed, or the error code
+    /// and message.
+    fn answer(
+        &self,
+        message: &Request,
+        worker: &mut Option<u64>,
+    ) -> Result<(Value, Option<u64>), (i64, String)> {
+        let params = message.value("params").and_then(Value::as_array).unwrap_or_default();
+        let job = || -> Result<u64, (i64, String)> {
+            let id = param(params, 1, Value::as_str).map_err(|e| (20, e.to_string()))?;
+            id.parse().map_err(|_| (21, format!("no job `{}`", id)))
+        };
+        match message.string("method").map_err(|e| (20, e))? {
+            Some("mining.subscribe") if worker.is_none() => {
+                let id = self.coordinator.register().map_err(refusal_error)?;
+                *worker = Some(id);
+                let subscription = Value::Array(vec![
+                    Value::String("mining.notify".to_string()),
+                    Value::String(id.to_string()),
+                ]);
+                let result = Value::Array(vec![
+                    Value::Array(vec![subscription]),
+                    Value::String(String::new()),
+                    Value::Number("0".to_string()),
+                ]);
+                Ok((result, Some(id)))
+            }
+            Some("mining.subscribe") => Err((20, "already subscribed".to_string())),
+            // The server has no users; anyone may mine.
+            Some("mining.authorize") => Ok((Value::Bool(true), None)),
+            Some("mining.submit") => {
+                let worker = worker.ok_or((25, "not subscribed".to_string()))?;
+                let lease = job()?;
+                let extranonce2 = param(params, 2, Value::as_str).map_err(|e| (20, e.to_string()))?;
+                if !extranonce2.is_empty() {
+                    return Err((20, "this pool hands out no extranonce2".to_string()));
+                }
+                let nonce = param(params, 3, Value::as_str).map_err(|e| (20, e.to_string()))?;
+                let nonce = u64::from_str_radix(nonce, 16)
+                    .map_err(|_| (20, format!("`{}` is not a nonce in hex", nonce)))?;
+                // A share is a solution too once it meets the puzzle's own target.
+                if self.coordinator.share(worker, lease, nonce).map_err(refusal_error)? {
+                    self.coordinator.submit(worker, lease, nonce).map_err(refusal_error)?;
+                }
+                Ok((Value::Bool(true), None))
+            }
+            Some("mining.exhausted") => {
+                let worker = worker.ok_or((25, "not subscribed".to_string()))?;
+                self.coordinator.exhausted(worker, job()?).map_err(refusal_error)?;
+                Ok((Value::Bool(true), None))
+            }
+            Some(method) => Err((20, format!("unknown method `{}`", method))),
+            None => Err((20, "missing `method`".to_string())),
+        }
+    }
+
+    /// Sends `worker` a job whenever it holds no lease and there is a range to lease it,
+    /// until it goes away or the server shuts down.
+    fn push_jobs(&self, worker: u64) {
+        let abandoned = || self.closed.load(Ordering::Relaxed);
+        loop {
+            let lease = match self.coordinator.wait_for_lease(worker, abandoned) {
+                Ok(Some(lease)) => lease,
+                Err(Refusal::Unavailable(_))
+                    if !abandoned() && !self.coordinator.is_shut_down() =>
+                {
+                    thread::sleep(UNAVAILABLE_RETRY);
+                    continue;
+                }
+                _ => return,
+            };
+            let target = lease.share_target.unwrap_or(lease.puzzle.target);
+            let target = Value::String(target.to_string());
+            let set_target = Response::with_id(None)
+                .string("method", "mining.set_target")
+                .value("params", &Value::Array(vec![target]));
+            let notify = Response::with_id(None)
+                .string("method", "mining.notify")
+                .value("params", &notify_params(&lease));
+            if self.send(&set_target).and_then(|()| self.send(&notify)).is_err() {
+                return;
+            }
+        }
+    }
+
+    /// Writes `message` to the worker on a line of its own.
+    fn send(&self, message: &Response) -> io::Result<()> {
+        writeln!(self.writer(), "{}", message)
+    }
+
+    /// Locks the connection to write to it. A line that was half written when a thread
+    /// panicked is the worker's to reject, so the lock is taken regardless.
+    fn writer(&self) -> MutexGuard<'_, TcpStream> {
+        self.writer.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// The parameters of the `mining.notify` that hands out `lease`.
+fn notify_params(lease: &WorkLease) -> Value {
+    let format = lease.puzzle.nonce_format;
+    let offset = match format.offset {
+        Some(offset) => Value::Number(offset.to_string()),
+        None => Value::Null,
+    };
+    Value::Array(vec![
+        Value::String(lease.id.to_string()),
+        Value::String(lease.algorithm.name().to_string()),
+        // The extra nonce of the lease is part of the data, like an extranonce1.
+        Value::String(hex::encode(&lease.puzzle.message_data())),
+        Value::String(format.encoding.to_string()),
+        offset,
+        Value::Number(lease.nonces.start().to_string()),
+        Value::Number(lease.nonces.end().to_string()),
+        // Each lease is a job of its own, and the last is closed before the next.
+        Value::Bool(true),
+    ])
+}
+
+/// The error code and message a refusal of the coordinator is sent as.
+fn refusal_error(refusal: Refusal) -> (i64, String) {
+    match refusal {
+        Refusal::NotFound(message) => (21, message),
+        Refusal::Rejected(message) => (23, message),
+        // A stale job, to Stratum.
+        Refusal::Expired(message) => (21, message),
+        Refusal::Unavailable(message) => (20, message),
+    }
+}
+
+/// An error as Stratum sends it: `[code, message, null]`.
+fn error_value(code: i64, message: &str) -> Value {
+    Value::Array(vec![
+        Value::Number(code.to_string()),
+        Value::String(message.to_string()),
+        Value::Null,
+    ])
+}
+
+/// The error a pool answered a call with, as a [`StratumError::Refused`].
+fn refusal(error: &Value) -> StratumError {
+    match error.as_array() {
+        Some([code, message, ..]) => StratumError::Refused(
+            code.as_number().unwrap_or(20),
+            message.as_str().unwrap_or("no reason given").to_string(),
+        ),
+        _ => StratumError::Refused(20, error.as_str().unwrap_or("no reason given").to_string()),
+    }
+}
+
+/// The parameter at `index` of a message, read with `read`.
+fn param<'a, T>(
+    params: &'a [Value],
+    index: usize,
+    read: impl FnOnce(&'a Value) -> Option<T>,
+) -> Result<T, String> {
+    params.get(index).and_then(read).ok_or_else(|| format!("bad or missing parameter {}", index))
+}
+
+/// Parses a parameter written with the `Display` form of `T`.
+fn parse<T: FromStr>(s: &str) -> Result<T, StratumError>
+where
+    T::Err: fmt::Display,
+{
+    s.parse().map_err(|e: T::Err| StratumError::Malformed(e.to_string()))
+}
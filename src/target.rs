@@ -0,0 +1,450 @@
+//! 256-bit hash targets and Bitcoin's compact "nBits" encoding.
+//!
+//! A [`Target`] is an unsigned 256-bit number stored as 32 big-endian bytes. A digest
+//! solves a puzzle with that target when the digest, read as a big-endian number, is
+//! less than or equal to the target. Bitcoin block headers store the target in a
+//! compact 4-byte form, which [`Target::from_compact`] and [`Target::to_compact`]
+//! convert to and from.
+//!
+//! The same hardness can be put in several other units: the two-byte threshold of
+//! [`Difficulty::Threshold`](crate::Difficulty::Threshold), a number of leading zero bits,
+//! or the number of hashes a solution takes on average. Each has a constructor and a
+//! method here, e.g. [`Target::from_leading_zero_bits`] and [`Target::work_bits`], so a
+//! difficulty given in one unit can be read off in any other.
+
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use num_bigint::BigUint;
+
+use crate::difficulty::{leading_zero_bits, Difficulty};
+
+/// An unsigned 256-bit target, stored as big-endian bytes.
+///
+/// Targets order numerically, so a smaller target is a harder puzzle.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Target([u8; 32]);
+
+/// The reasons a compact nBits value cannot be turned into a [`Target`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompactTargetError {
+    /// The sign bit (`0x00800000`) is set, which encodes a negative target.
+    Negative,
+    /// The exponent shifts the mantissa past 256 bits.
+    Overflow,
+}
+
+impl fmt::Display for CompactTargetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompactTargetError::Negative => f.write_str("compact target has the sign bit set"),
+            CompactTargetError::Overflow => f.write_str("compact target does not fit in 256 bits"),
+        }
+    }
+}
+
+impl Error for CompactTargetError {}
+
+impl Target {
+    /// The easiest possible target: every digest satisfies it.
+    pub const MAX: Target = Target([0xff; 32]);
+    /// The hardest possible target: only the all-zero digest satisfies it.
+    pub const ZERO: Target = Target([0; 32]);
+
+    /// Creates a target from 32 big-endian bytes.
+    pub const fn from_be_bytes(bytes: [u8; 32]) -> Self {
+        Target(bytes)
+    }
+
+    /// Returns the target as 32 big-endian bytes.
+    pub const fn to_be_bytes(&self) -> [u8; 32] {
+        self.0
+    }
+
+    /// The target satisfied exactly by digests with at least `bits` leading zero bits,
+    /// i.e. `2^(256 - bits) - 1`.
+    ///
+    /// Values of 256 or more give [`Target::ZERO`].
+    pub fn from_leading_zero_bits(bits: u32) -> Self {
+        let mut bytes = [0xffu8; 32];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            let byte_start = i as u32 * 8;
+            if bits >= byte_start + 8 {
+                *byte = 0;
+            } else if bits > byte_start {
+                *byte = 0xff >> (bits - byte_start);
+            }
+        }
+        Target(bytes)
+    }
+
+    /// Decodes a Bitcoin compact "nBits" value.
+    ///
+    /// The top byte is a base-256 exponent and the low 23 bits are the mantissa, so the
+    /// target is `mantissa * 256^(exponent - 3)`.
+    ///
+    /// # Parameters
+    ///
+    /// - `bits`: The compact representation, e.g. `0x1d00ffff` for Bitcoin's genesis block.
+    ///
+    /// # Returns
+    ///
+    /// The decoded target, or a [`CompactTargetError`] if the value is negative or
+    /// does not fit in 256 bits. A zero mantissa decodes to [`Target::ZERO`].
+    pub fn from_compact(bits: u32) -> Result<Self, CompactTargetError> {
+        let exponent = (bits >> 24) as usize;
+        let mantissa = bits & 0x007f_ffff;
+
+        if mantissa == 0 {
+            return Ok(Target::ZERO);
+        }
+        if bits & 0x0080_0000 != 0 {
+            return Err(CompactTargetError::Negative);
+        }
+
+        let mantissa_bytes = mantissa.to_be_bytes();
+        let mut bytes = [0u8; 32];
+        // The mantissa occupies three bytes whose least significant byte sits at
+        // position `exponent - 3` (counting bytes from the low end of the number).
+        for (i, &byte) in mantissa_bytes[1..].iter().enumerate() {
+            let position = exponent as isize - 1 - i as isize;
+            if position < 0 {
+                // Shifted out below the least significant byte.
+                continue;
+            }
+            if position >= 32 {
+                if byte != 0 {
+                    return Err(CompactTargetError::Overflow);
+                }
+                continue;
+            }
+            bytes[31 - position as usize] = byte;
+        }
+        Ok(Target(bytes))
+    }
+
+    /// Encodes the target in Bitcoin's compact "nBits" form.
+    ///
+    /// The encoding keeps only the three most significant bytes, so converting back with
+    /// [`Target::from_compact`] may yield a slightly smaller (harder) target.
+    pub fn to_compact(&self) -> u32 {
+        let Some(first) = self.0.iter().position(|&byte| byte != 0) else {
+            return 0;
+        };
+        let mut size = (32 - first) as u32;
+        let byte = |i: usize| self.0.get(i).copied().unwrap_or(0) as u32;
+        let mut mantissa = (byte(first) << 16) | (byte(first + 1) << 8) | byte(first + 2);
+
+        // The mantissa is signed, so a set high bit would read as negative; push it
+        // down a byte and bump the exponent instead.
+        if mantissa & 0x0080_0000 != 0 {
+            mantissa >>= 8;
+            size += 1;
+        }
+        (size << 24) | mantissa
+    }
+
+    /// The probability that one uniformly random hash meets this target:
+    /// `(target + 1) / 2^256`.
+    pub fn success_probability(&self) -> f64 {
+        let fraction = self.0.iter().rev().fold(0.0, |acc, &byte| (acc + f64::from(byte)) / 256.0);
+        fraction + 2f64.powi(-256)
+    }
+
+    /// The average number of hashes needed to meet this target.
+    pub fn expected_attempts(&self) -> f64 {
+        1.0 / self.success_probability()
+    }
+
+    /// The target that takes `attempts` hashes on average to meet, i.e.
+    /// `2^256 / attempts - 1` rounded down; the inverse of
+    /// [`expected_attempts`](Target::expected_attempts).
+    ///
+    /// One attempt or fewer gives [`Target::MAX`], and `2^256` or more gives
+    /// [`Target::ZERO`].
+    pub fn from_expected_attempts(attempts: f64) -> Self {
+        if attempts.is_nan() || attempts <= 1.0 {
+            return Target::MAX;
+        }
+        if attempts >= 2f64.powi(256) {
+            return Target::ZERO;
+        }
+        // Split `attempts` exactly into `mantissa * 2^exponent`, so the division below
+        // is done in integers.
+        let bits = attempts.to_bits();
+        let exponent = ((bits >> 52) & 0x7ff) as i64 - 1075;
+        let mantissa = (bits & ((1 << 52) - 1)) | (1 << 52);
+        let quotient = (BigUint::from(1u8) << (256 - exponent) as usize) / mantissa;
+        let bytes = (quotient - 1u8).to_bytes_be();
+        let mut padded = [0u8; 32];
+        padded[32 - bytes.len()..].copy_from_slice(&bytes);
+        Target(padded)
+    }
+
+    /// The target of the two-byte `threshold`, as for [`Difficulty::Threshold`].
+    pub fn from_threshold(threshold: u64) -> Self {
+        Difficulty::Threshold(threshold).to_target()
+    }
+
+    /// The largest two-byte threshold whose target is no easier than this one, i.e.
+    /// `(target + 1) / 2^240` rounded down; the inverse of
+    /// [`from_threshold`](Target::from_threshold) for the targets it produces.
+    ///
+    /// [`Target::MAX`] gives 65536, and any target below `2^240 - 1` gives 0, which no
+    /// digest can meet, as thresholds cannot express anything that hard.
+    pub fn to_threshold(&self) -> u64 {
+        let top = u64::from(u16::from_be_bytes([self.0[0], self.0[1]]));
+        if self.0[2..].iter().all(|&byte| byte == 0xff) {
+            top + 1
+        } else {
+            top
+        }
+    }
+
+    /// The number of leading zero bits every digest meeting this target has; the inverse
+    /// of [`from_leading_zero_bits`](Target::from_leading_zero_bits) for the targets it
+    /// produces.
+    ///
+    /// For other targets the result rounds the difficulty down, so it can understate the
+    /// work by up to one bit; [`work_bits`](Target::work_bits) gives the exact figure.
+    pub fn leading_zero_bits(&self) -> u32 {
+        leading_zero_bits(&self.0)
+    }
+
+    /// The work needed to meet this target in bits, i.e. the base-2 logarithm of
+    /// [`expected_attempts`](Target::expected_attempts).
+    ///
+    /// This is `n` for a target of `n` leading zero bits, and falls between two whole
+    /// numbers for targets that are not powers of two.
+    pub fn work_bits(&self) -> f64 {
+        self.expected_attempts().log2()
+    }
+
+    /// Checks whether `hash` is less than or equal to this target.
+    ///
+    /// Digests are compared as big-endian numbers. Digests shorter than 32 bytes are
+    /// padded with zeros on the right; only the first 32 bytes of longer digests count.
+    pub fn is_met_by(&self, hash: &[u8]) -> bool {
+        let mut padded = [0u8; 32];
+        let len = hash.len().min(32);
+        padded[..len].copy_from_slice(&hash[..len]);
+        padded <= self.0
+    }
+}
+
+impl fmt::Display for Target {
+    /// Formats the target as 64 lower-case hex digits.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Target {
+    type Err = String;
+
+    /// Parses a big-endian hex target of up to 64 digits, with or without a `0x` prefix.
+    ///
+    /// Shorter inputs are treated as numbers and padded with leading zeros, so `ffff`
+    /// is the target `0x...0000ffff`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let digits = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")).unwrap_or(s);
+        if digits.is_empty() || digits.len() > 64 {
+            return Err(format!("target `{}` must have between 1 and 64 hex digits", s));
+        }
+        if !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(format!("target `{}` is not valid hex", s));
+        }
+
+        let padded = format!("{:0>64}", digits);
+        let mut bytes = [0u8; 32];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&padded[2 * i..2 * i + 2], 16)
+                .expect("digits were checked to be hex");
+        }
+        Ok(Target(bytes))
+    }
+}
+
+impl fmt::Debug for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Target({})", self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The target written as up to 64 hex digits.
+    fn target(hex: &str) -> Target {
+        hex.parse().unwrap()
+    }
+
+    /// The target `hex` followed by `zero_bytes` zero bytes.
+    fn shifted(hex: &str, zero_bytes: usize) -> Target {
+        target(&format!("{}{}", hex, "00".repeat(zero_bytes)))
+    }
+
+    #[test]
+    fn compact_targets_decode() {
+        let genesis = Target::from_compact(0x1d00_ffff).unwrap();
+        assert_eq!(genesis, shifted("ffff", 26));
+        assert_eq!(genesis.to_string(), format!("00000000ffff{}", "0".repeat(52)));
+        assert_eq!(Target::from_compact(0x1b04_04cb).unwrap(), shifted("0404cb", 24));
+        assert_eq!(Target::from_compact(0x0312_3456).unwrap(), target("123456"));
+        // Exponents below 3 shift low mantissa bytes out.
+        assert_eq!(Target::from_compact(0x0112_3456).unwrap(), target("12"));
+        assert_eq!(Target::from_compact(0x0212_3456).unwrap(), target("1234"));
+        assert_eq!(Target::from_compact(0x2000_ffff).unwrap(), shifted("ffff", 29));
+    }
+
+    #[test]
+    fn zero_mantissas_decode_to_zero_whatever_the_sign() {
+        assert_eq!(Target::from_compact(0), Ok(Target::ZERO));
+        assert_eq!(Target::from_compact(0x1d00_0000), Ok(Target::ZERO));
+        assert_eq!(Target::from_compact(0x0180_0000), Ok(Target::ZERO));
+        assert_eq!(Target::from_compact(0xff80_0000), Ok(Target::ZERO));
+    }
+
+    #[test]
+    fn negative_and_oversized_compact_targets_are_rejected() {
+        assert_eq!(Target::from_compact(0x0180_0001), Err(CompactTargetError::Negative));
+        assert_eq!(Target::from_compact(0x1d80_ffff), Err(CompactTargetError::Negative));
+        assert_eq!(Target::from_compact(0x2300_0001), Err(CompactTargetError::Overflow));
+        assert_eq!(Target::from_compact(0x2201_0000), Err(CompactTargetError::Overflow));
+        assert_eq!(Target::from_compact(0xff00_0001), Err(CompactTargetError::Overflow));
+        // Bytes that land past 256 bits are fine as long as they are zero.
+        assert_eq!(Target::from_compact(0x2100_ffff).unwrap(), shifted("ffff", 30));
+        assert_eq!(Target::from_compact(0x2200_0001).unwrap(), shifted("01", 31));
+    }
+
+    #[test]
+    fn compact_targets_round_trip() {
+        let samples = [0x1d00_ffff, 0x1b04_04cb, 0x1703_a30c, 0x0312_3456, 0x2100_ffff];
+        for bits in samples {
+            let decoded = Target::from_compact(bits).unwrap();
+            assert_eq!(decoded.to_compact(), bits, "{:#010x}", bits);
+        }
+        assert_eq!(Target::ZERO.to_compact(), 0);
+
+        // A mantissa with its top bit set is pushed down a byte instead of reading as
+        // negative.
+        let high = target("80");
+        assert_eq!(high.to_compact(), 0x0200_8000);
+        assert_eq!(Target::from_compact(high.to_compact()), Ok(high));
+
+        // Bytes beyond the top three are dropped, giving a slightly harder target.
+        let long = target("123456789a");
+        assert_eq!(long.to_compact(), 0x0512_3456);
+        assert_eq!(Target::from_compact(long.to_compact()), Ok(target("1234560000")));
+        assert_eq!(Target::MAX.to_compact(), 0x2100_ffff);
+        assert!(Target::from_compact(Target::MAX.to_compact()).unwrap() < Target::MAX);
+    }
+
+    #[test]
+    fn digests_are_compared_as_big_endian_numbers() {
+        let target = shifted("0000ffff", 28);
+        assert!(target.is_met_by(&target.to_be_bytes()));
+        let mut above = target.to_be_bytes();
+        above[31] = 1;
+        assert!(!target.is_met_by(&above));
+        let mut below = target.to_be_bytes();
+        below[3] = 0xfe;
+        below[31] = 0xff;
+        assert!(target.is_met_by(&below));
+        assert!(Target::ZERO.is_met_by(&[0; 32]));
+        assert!(!Target::ZERO.is_met_by(&[0, 0, 0, 1]));
+        assert!(Target::MAX.is_met_by(&[0xff; 32]));
+    }
+
+    #[test]
+    fn short_digests_are_padded_on_the_right() {
+        // A 160-bit digest is the top 160 bits of the number, so thresholds and zero
+        // bits read it exactly as they read a 256-bit one.
+        let mut sha1 = [0xffu8; 20];
+        sha1[..2].copy_from_slice(&[0x12, 0x34]);
+        assert!(Target::from_threshold(0x1235).is_met_by(&sha1));
+        assert!(!Target::from_threshold(0x1234).is_met_by(&sha1));
+        sha1[..3].copy_from_slice(&[0, 0, 0x0f]);
+        assert!(Target::from_leading_zero_bits(20).is_met_by(&sha1));
+        assert!(!Target::from_leading_zero_bits(21).is_met_by(&sha1));
+
+        // The zero padding only ever makes a digest smaller.
+        let target = shifted("00ff", 30);
+        assert!(target.is_met_by(&[0x00, 0xff]));
+        assert!(target.is_met_by(&[0x00]));
+        assert!(target.is_met_by(&[]));
+        assert!(!target.is_met_by(&[0x01]));
+        let digest = [0x00, 0x00, 0x00, 0x01, 0x80];
+        assert!(Target::from_leading_zero_bits(31).is_met_by(&digest));
+        assert!(!Target::from_leading_zero_bits(32).is_met_by(&digest));
+    }
+
+    #[test]
+    fn long_digests_are_cut_to_256_bits() {
+        // Only the first 32 bytes count, so the trailing 0xff bytes cannot push this
+        // digest over a target its first half equals.
+        let target = shifted("ff", 29);
+        let mut sha512 = [0xffu8; 64];
+        sha512[..32].copy_from_slice(&target.to_be_bytes());
+        assert!(target.is_met_by(&sha512));
+        assert!(!shifted("fe", 29).is_met_by(&sha512));
+        assert!(Target::from_leading_zero_bits(16).is_met_by(&sha512));
+        assert!(!Target::from_leading_zero_bits(17).is_met_by(&sha512));
+    }
+
+    #[test]
+    fn thresholds_and_zero_bits_convert_both_ways() {
+        for threshold in [1, 2, 0x100, 0x1234, 0xffff, 0x1_0000] {
+            let target = Target::from_threshold(threshold);
+            assert_eq!(target.to_threshold(), threshold, "threshold {}", threshold);
+        }
+        assert_eq!(Target::from_threshold(1), Target::from_leading_zero_bits(16));
+        assert_eq!(Target::from_threshold(0x100), Target::from_leading_zero_bits(8));
+        assert_eq!(Target::from_threshold(0x1_0000), Target::MAX);
+        assert_eq!(Target::MAX.to_threshold(), 0x1_0000);
+        assert_eq!(Target::ZERO.to_threshold(), 0);
+        // Targets harder than any threshold, or between two, round to the harder one.
+        assert_eq!(Target::from_leading_zero_bits(17).to_threshold(), 0);
+        assert_eq!(shifted("1234", 29).to_threshold(), 0x12);
+        assert_eq!(Target::from_leading_zero_bits(4).to_threshold(), 0x1000);
+
+        for bits in [0, 1, 7, 8, 20, 64, 255, 256] {
+            let target = Target::from_leading_zero_bits(bits);
+            assert_eq!(target.leading_zero_bits(), bits, "{} bits", bits);
+        }
+        assert_eq!(Target::from_leading_zero_bits(0), Target::MAX);
+        assert_eq!(Target::from_leading_zero_bits(256), Target::ZERO);
+        assert_eq!(Target::from_leading_zero_bits(300), Target::ZERO);
+        assert_eq!(Target::from_leading_zero_bits(12), target(&format!("000f{}", "f".repeat(60))));
+        // Rounded down for targets between two powers of two.
+        assert_eq!(Target::from_threshold(0x1234).leading_zero_bits(), 3);
+    }
+
+    #[test]
+    fn expected_attempts_convert_both_ways() {
+        for bits in [0, 1, 16, 40, 100, 255] {
+            let target = Target::from_leading_zero_bits(bits);
+            assert_eq!(target.expected_attempts(), 2f64.powi(bits as i32), "{} bits", bits);
+            assert_eq!(target.work_bits(), f64::from(bits));
+            assert_eq!(Target::from_expected_attempts(2f64.powi(bits as i32)), target);
+        }
+        assert_eq!(Target::ZERO.work_bits(), 256.0);
+        assert_eq!(Target::from_threshold(3 << 8).work_bits(), 8.0 - 3f64.log2());
+
+        let three = Target::from_expected_attempts(3.0);
+        assert!((three.expected_attempts() - 3.0).abs() < 1e-9);
+        assert_eq!(three.leading_zero_bits(), 1);
+        assert_eq!(Target::from_expected_attempts(1e9).leading_zero_bits(), 29);
+
+        assert_eq!(Target::from_expected_attempts(1.0), Target::MAX);
+        assert_eq!(Target::from_expected_attempts(0.5), Target::MAX);
+        assert_eq!(Target::from_expected_attempts(f64::NAN), Target::MAX);
+        assert_eq!(Target::from_expected_attempts(2f64.powi(256)), Target::ZERO);
+        assert_eq!(Target::from_expected_attempts(f64::INFINITY), Target::ZERO);
+    }
+}
@@ -0,0 +1,131 @@
+//! Slowing a search down while the CPU runs hot.
+//!
+//! A long search at full speed can heat a laptop or a poorly cooled box until the fans
+//! roar or the firmware throttles the whole machine. A [`ThermalLimit`] set with
+//! [`MiningHandle::limit_temperature`](crate::MiningHandle::limit_temperature) has a
+//! watchdog read the CPU temperature every second: above the limit it halves the share of
+//! time the workers spend hashing, each second the CPU stays hot, and the workers rest in
+//! between as with [`set_max_cpu`](crate::MiningHandle::set_max_cpu). Once the CPU has
+//! cooled to the resume temperature, the share doubles each second until the search runs
+//! at full speed again. Between the two temperatures it stays where it is, so the search
+//! does not flap around the limit.
+//!
+//! [`cpu_temperature`] reads the sensors of the CPU's hardware-monitoring driver on
+//! Linux, or the CPU's thermal zones where there is no such driver. Without a sensor the
+//! limit has no effect.
+
+use std::fs;
+use std::path::Path;
+
+/// How far below the limit the CPU must cool before the search speeds up again, by
+/// default, in degrees Celsius.
+const COOLDOWN: f64 = 5.0;
+
+/// The smallest share of the time, in percent, a hot CPU throttles the workers to.
+const MIN_SHARE: u8 = 5;
+
+/// Hardware-monitoring drivers whose sensors measure the CPU.
+const CPU_DRIVERS: [&str; 5] = ["coretemp", "k10temp", "zenpower", "cpu_thermal", "cpu-thermal"];
+
+/// The temperatures at which a search slows down and speeds up again; see the [module
+/// documentation](self).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ThermalLimit {
+    max: f64,
+    resume: f64,
+}
+
+impl ThermalLimit {
+    /// Slows the search down above `max_celsius`, and speeds it up again once the CPU has
+    /// cooled 5 °C below it.
+    pub fn new(max_celsius: f64) -> Self {
+        ThermalLimit { max: max_celsius, resume: max_celsius - COOLDOWN }
+    }
+
+    /// Speeds the search up again only at or below `celsius`, which is capped at the
+    /// limit itself.
+    pub fn resume_at(mut self, celsius: f64) -> Self {
+        self.resume = celsius.min(self.max);
+        self
+    }
+
+    /// The temperature above which the search slows down, in degrees Celsius.
+    pub fn max_celsius(&self) -> f64 {
+        self.max
+    }
+
+    /// The temperature at or below which the search speeds up again, in degrees Celsius.
+    pub fn resume_celsius(&self) -> f64 {
+        self.resume
+    }
+
+    /// The share of the time, in percent, the workers may hash for the next second, given
+    /// their `share` for the last one and the CPU's temperature, if it could be read.
+    pub(crate) fn next_share(&self, share: u8, celsius: Option<f64>) -> u8 {
+        match celsius {
+            Some(celsius) if celsius > self.max => (share / 2).max(MIN_SHARE),
+            Some(celsius) if celsius <= self.resume => share.saturating_mul(2).min(100),
+            _ => share,
+        }
+    }
+}
+
+/// The temperature of the hottest CPU sensor, in degrees Celsius, or `None` if there is
+/// no sensor to read.
+pub fn cpu_temperature() -> Option<f64> {
+    hwmon_temperature().or_else(zone_temperature)
+}
+
+/// The hottest reading of a hardware-monitoring driver in [`CPU_DRIVERS`].
+fn hwmon_temperature() -> Option<f64> {
+    let mut hottest = None;
+    for entry in fs::read_dir("/sys/class/hwmon").ok()?.flatten() {
+        let dir = entry.path();
+        let Ok(name) = fs::read_to_string(dir.join("name")) else {
+            continue;
+        };
+        if !CPU_DRIVERS.contains(&name.trim()) {
+            continue;
+        }
+        for sensor in fs::read_dir(&dir).into_iter().flatten().flatten() {
+            let file_name = sensor.file_name();
+            let Some(file_name) = file_name.to_str() else {
+                continue;
+            };
+            if file_name.starts_with("temp") && file_name.ends_with("_input") {
+                hottest = max(hottest, read_millidegrees(&sensor.path()));
+            }
+        }
+    }
+    hottest
+}
+
+/// The hottest thermal zone whose type names the CPU, such as `x86_pkg_temp`.
+fn zone_temperature() -> Option<f64> {
+    let mut hottest = None;
+    for entry in fs::read_dir("/sys/class/thermal").ok()?.flatten() {
+        let dir = entry.path();
+        let Ok(kind) = fs::read_to_string(dir.join("type")) else {
+            continue;
+        };
+        let kind = kind.trim().to_ascii_lowercase();
+        if ["cpu", "pkg", "soc"].iter().any(|name| kind.contains(name)) {
+            hottest = max(hottest, read_millidegrees(&dir.join("temp")));
+        }
+    }
+    hottest
+}
+
+/// Reads a sensor file holding thousandths of a degree Celsius.
+fn read_millidegrees(path: &Path) -> Option<f64> {
+    let millidegrees: i64 = fs::read_to_string(path).ok()?.trim().parse().ok()?;
+    Some(millidegrees as f64 / 1000.0)
+}
+
+/// The higher of two readings, either of which may be missing.
+fn max(a: Option<f64>, b: Option<f64>) -> Option<f64> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.max(b)),
+        (a, b) => a.or(b),
+    }
+}
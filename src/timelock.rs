@@ -0,0 +1,141 @@
+//! Rivest-Shamir-Wagner time-lock puzzles.
+//!
+//! A time-lock puzzle asks for `base^(2^squarings) mod modulus`, where the modulus is an
+//! RSA modulus `p * q`. Without the factors the only known way to compute it is to square
+//! `squarings` times in a row, and each squaring needs the previous result, so extra
+//! cores do not help: the puzzle takes a predictable amount of wall-clock time however
+//! much hardware is thrown at it. That is the opposite of the parallel miner.
+//!
+//! Whoever generates the puzzle keeps the factors as a [`TimeLockTrapdoor`]. Knowing
+//! `phi(modulus) = (p - 1)(q - 1)`, they can reduce the exponent `2^squarings` modulo
+//! `phi` first and compute the answer with a single short exponentiation, which makes
+//! both creating a puzzle with a known answer and checking a claimed answer fast.
+
+use num_bigint::{BigUint, RandBigInt};
+
+use crate::prime::random_prime;
+
+/// A time-lock puzzle: compute `base^(2^squarings) mod modulus`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TimeLockPuzzle {
+    /// The RSA modulus whose factors are kept secret.
+    pub modulus: BigUint,
+    /// The number that is squared repeatedly.
+    pub base: BigUint,
+    /// How many sequential squarings the puzzle takes.
+    pub squarings: u64,
+}
+
+/// The factors of a time-lock puzzle's modulus, which let its creator skip the work.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TimeLockTrapdoor {
+    /// The first prime factor.
+    pub p: BigUint,
+    /// The second prime factor.
+    pub q: BigUint,
+}
+
+impl TimeLockPuzzle {
+    /// Generates a puzzle that takes `squarings` sequential squarings modulo a fresh
+    /// RSA modulus of `modulus_bits` bits.
+    ///
+    /// # Returns
+    ///
+    /// The public puzzle, and the trapdoor its creator keeps to solve or check it quickly.
+    ///
+    /// # Panics
+    ///
+    /// If `modulus_bits` is below 16.
+    pub fn generate(modulus_bits: u64, squarings: u64) -> (TimeLockPuzzle, TimeLockTrapdoor) {
+        assert!(modulus_bits >= 16, "the modulus needs at least 16 bits");
+        let mut rng = rand::thread_rng();
+        let (p, q) = loop {
+            let p = random_prime(modulus_bits / 2, &mut rng);
+            let q = random_prime(modulus_bits - modulus_bits / 2, &mut rng);
+            if p != q {
+                break (p, q);
+            }
+        };
+        let modulus = &p * &q;
+        // A base sharing a factor with the modulus would reveal it.
+        let base = loop {
+            let base = rng.gen_biguint_range(&BigUint::from(2u32), &modulus);
+            if &base % &p != BigUint::ZERO && &base % &q != BigUint::ZERO {
+                break base;
+            }
+        };
+        (TimeLockPuzzle { modulus, base, squarings }, TimeLockTrapdoor { p, q })
+    }
+
+    /// Solves the puzzle the slow way, by squaring `squarings` times in a row.
+    ///
+    /// This runs on the calling thread; the work cannot be split across threads.
+    pub fn evaluate(&self) -> BigUint {
+        let mut value = &self.base % &self.modulus;
+        for _ in 0..self.squarings {
+            value = &value * &value % &self.modulus;
+        }
+        value
+    }
+}
+
+impl TimeLockTrapdoor {
+    /// The modulus `p * q`.
+    pub fn modulus(&self) -> BigUint {
+        &self.p * &self.q
+    }
+
+    /// Solves `puzzle` with one exponentiation, using the factors to shorten the
+    /// exponent.
+    ///
+    /// # Details
+    ///
+    /// The exponent `2^squarings` is reduced modulo `phi = (p - 1)(q - 1)`. Adding `phi`
+    /// back keeps the result correct even for a base that shares a factor with the
+    /// modulus, since `a^k = a^(k mod phi + phi) mod n` holds for every `a` once `k` is at
+    /// least the bit length of `n`; smaller exponents are used as they are.
+    pub fn solve(&self, puzzle: &TimeLockPuzzle) -> BigUint {
+        let one = BigUint::from(1u32);
+        let modulus = self.modulus();
+        let phi = (&self.p - &one) * (&self.q - &one);
+        let exponent = if puzzle.squarings < modulus.bits() {
+            one << puzzle.squarings
+        } else {
+            BigUint::from(2u32).modpow(&BigUint::from(puzzle.squarings), &phi) + &phi
+        };
+        puzzle.base.modpow(&exponent, &modulus)
+    }
+
+    /// Whether `answer` solves `puzzle`, which must have been generated with this
+    /// trapdoor.
+    pub fn verify(&self, puzzle: &TimeLockPuzzle, answer: &BigUint) -> bool {
+        puzzle.modulus == self.modulus() && self.solve(puzzle) == *answer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn smallest_puzzle_is_generated_solved_and_checked() {
+        for squarings in [0, 5, 40] {
+            let (puzzle, trapdoor) = TimeLockPuzzle::generate(16, squarings);
+            assert_eq!(puzzle.modulus.bits(), 16);
+            assert_eq!(trapdoor.modulus(), puzzle.modulus);
+            assert_ne!(trapdoor.p, trapdoor.q);
+
+            let answer = puzzle.evaluate();
+            assert_eq!(trapdoor.solve(&puzzle), answer, "with {} squarings", squarings);
+            assert!(trapdoor.verify(&puzzle, &answer));
+            let wrong = (&answer + 1u32) % &puzzle.modulus;
+            assert!(!trapdoor.verify(&puzzle, &wrong));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "at least 16 bits")]
+    fn too_small_a_modulus_is_refused() {
+        TimeLockPuzzle::generate(15, 1);
+    }
+}
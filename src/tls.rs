@@ -0,0 +1,265 @@
+//! TLS for the work server and its workers, with the `tls` feature.
+//!
+//! Across a network its operator does not trust, the plain HTTP of the
+//! [`server`](crate::server) protocol lets anyone on the path read the puzzles being
+//! leased and rewrite the solutions being reported. Given a [`ServerTls`] with
+//! [`WorkServer::with_tls`](crate::WorkServer::with_tls), the server speaks HTTPS
+//! instead, on the same port: its HTTP API, its WebSocket events (`wss://`) and, with the
+//! `grpc` feature, its gRPC service. A [`ClientTls`] given to
+//! [`WorkClient::connect_tls`](crate::WorkClient::connect_tls) is the worker's end.
+//!
+//! The server may also demand a certificate of every worker, signed by an authority of
+//! its choosing, so that only the operator's own machines can lease work at all.
+//! Certificates and keys are read as PEM, and the handshakes are rustls's, on its `ring`
+//! backend. The Stratum service stays plain TCP.
+
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::sync::Arc;
+
+use rustls::crypto::{ring, CryptoProvider};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{
+    ClientConfig, ClientConnection, RootCertStore, ServerConfig, ServerConnection, StreamOwned,
+};
+
+/// The reasons a TLS configuration could not be built.
+#[derive(Debug)]
+pub enum TlsError {
+    /// PEM could not be read.
+    Pem(io::Error),
+    /// PEM holds no certificate, or no private key, where one was expected.
+    Missing(&'static str),
+    /// rustls would not accept the certificates or key.
+    Rejected(String),
+}
+
+impl fmt::Display for TlsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TlsError::Pem(e) => write!(f, "cannot read PEM: {}", e),
+            TlsError::Missing(what) => write!(f, "the PEM holds no {}", what),
+            TlsError::Rejected(message) => write!(f, "invalid TLS configuration: {}", message),
+        }
+    }
+}
+
+impl Error for TlsError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TlsError::Pem(e) => Some(e),
+            TlsError::Missing(_) | TlsError::Rejected(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for TlsError {
+    fn from(e: io::Error) -> Self {
+        TlsError::Pem(e)
+    }
+}
+
+impl From<rustls::Error> for TlsError {
+    fn from(e: rustls::Error) -> Self {
+        TlsError::Rejected(e.to_string())
+    }
+}
+
+/// The work server's side of TLS: its certificate, and the authorities whose
+/// certificates it demands of workers, if any.
+#[derive(Clone)]
+pub struct ServerTls {
+    config: Arc<ServerConfig>,
+    requires_client_certificates: bool,
+    /// The PEM again, which tonic takes as is.
+    #[cfg(feature = "grpc")]
+    pem: (Vec<u8>, Vec<u8>, Option<Vec<u8>>),
+}
+
+impl ServerTls {
+    /// Presents the certificate chain in `cert_pem`, whose private key is in `key_pem`,
+    /// to workers. With `client_ca_pem`, also demands of every worker a certificate
+    /// signed by one of the authorities in it, and turns away workers without one.
+    ///
+    /// # Returns
+    ///
+    /// The configuration, or why the certificates or key are unusable.
+    pub fn new(
+        cert_pem: &[u8],
+        key_pem: &[u8],
+        client_ca_pem: Option<&[u8]>,
+    ) -> Result<Self, TlsError> {
+        let builder = ServerConfig::builder_with_provider(provider())
+            .with_safe_default_protocol_versions()?;
+        let builder = match client_ca_pem {
+            Some(ca_pem) => {
+                let roots = Arc::new(root_store(ca_pem)?);
+                let verifier = WebPkiClientVerifier::builder_with_provider(roots, provider())
+                    .build()
+                    .map_err(|e| TlsError::Rejected(e.to_string()))?;
+                builder.with_client_cert_verifier(verifier)
+            }
+            None => builder.with_no_client_auth(),
+        };
+        let config = builder.with_single_cert(certificates(cert_pem)?, private_key(key_pem)?)?;
+        Ok(ServerTls {
+            config: Arc::new(config),
+            requires_client_certificates: client_ca_pem.is_some(),
+            #[cfg(feature = "grpc")]
+            pem: (cert_pem.to_vec(), key_pem.to_vec(), client_ca_pem.map(<[u8]>::to_vec)),
+        })
+    }
+
+    /// Whether workers must present a certificate.
+    pub fn requires_client_certificates(&self) -> bool {
+        self.requires_client_certificates
+    }
+
+    /// Wraps a connection a worker opened. The handshake happens on the first read or
+    /// write.
+    pub(crate) fn accept(&self, stream: TcpStream) -> io::Result<TlsStream> {
+        let connection =
+            ServerConnection::new(Arc::clone(&self.config)).map_err(io::Error::other)?;
+        Ok(TlsStream::Server(StreamOwned::new(connection, stream)))
+    }
+
+    /// The same configuration for the gRPC service.
+    #[cfg(feature = "grpc")]
+    pub(crate) fn grpc_config(&self) -> tonic::transport::ServerTlsConfig {
+        use tonic::transport::{Certificate, Identity, ServerTlsConfig};
+
+        let (cert_pem, key_pem, client_ca_pem) = &self.pem;
+        let config = ServerTlsConfig::new().identity(Identity::from_pem(cert_pem, key_pem));
+        match client_ca_pem {
+            Some(ca_pem) => config.client_ca_root(Certificate::from_pem(ca_pem)),
+            None => config,
+        }
+    }
+}
+
+impl fmt::Debug for ServerTls {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ServerTls")
+            .field("requires_client_certificates", &self.requires_client_certificates())
+            .finish_non_exhaustive()
+    }
+}
+
+/// A worker's side of TLS: the authorities it trusts to vouch for the server, and the
+/// certificate it presents if the server demands one.
+#[derive(Clone, Debug)]
+pub struct ClientTls {
+    config: Arc<ClientConfig>,
+}
+
+impl ClientTls {
+    /// Trusts the authorities in `ca_pem`, such as the one that signed a coordinator's
+    /// own certificate, or the usual public authorities if it is `None`. With `identity`,
+    /// a certificate chain and its private key in PEM, presents that certificate to a
+    /// server that demands one.
+    ///
+    /// # Returns
+    ///
+    /// The configuration, or why the certificates or key are unusable.
+    pub fn new(ca_pem: Option<&[u8]>, identity: Option<(&[u8], &[u8])>) -> Result<Self, TlsError> {
+        let roots = match ca_pem {
+            Some(ca_pem) => root_store(ca_pem)?,
+            None => RootCertStore { roots: webpki_roots::TLS_SERVER_ROOTS.to_vec() },
+        };
+        let builder = ClientConfig::builder_with_provider(provider())
+            .with_safe_default_protocol_versions()?
+            .with_root_certificates(roots);
+        let config = match identity {
+            Some((cert_pem, key_pem)) => {
+                builder.with_client_auth_cert(certificates(cert_pem)?, private_key(key_pem)?)?
+            }
+            None => builder.with_no_client_auth(),
+        };
+        Ok(ClientTls { config: Arc::new(config) })
+    }
+
+    /// Wraps a connection to the server `host`, whose certificate must be for that name
+    /// or IP address. The handshake happens on the first read or write.
+    pub(crate) fn connect(&self, host: &str, stream: TcpStream) -> io::Result<TlsStream> {
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        let name = ServerName::try_from(host.to_string())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "invalid server name"))?;
+        let connection =
+            ClientConnection::new(Arc::clone(&self.config), name).map_err(io::Error::other)?;
+        Ok(TlsStream::Client(StreamOwned::new(connection, stream)))
+    }
+}
+
+/// A TLS connection over TCP, which says goodbye when dropped, so that the other end
+/// can tell the end of what was sent from a connection cut short.
+#[derive(Debug)]
+pub(crate) enum TlsStream {
+    Server(StreamOwned<ServerConnection, TcpStream>),
+    Client(StreamOwned<ClientConnection, TcpStream>),
+}
+
+impl Read for TlsStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            TlsStream::Server(stream) => stream.read(buf),
+            TlsStream::Client(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for TlsStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            TlsStream::Server(stream) => stream.write(buf),
+            TlsStream::Client(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            TlsStream::Server(stream) => stream.flush(),
+            TlsStream::Client(stream) => stream.flush(),
+        }
+    }
+}
+
+impl Drop for TlsStream {
+    fn drop(&mut self) {
+        match self {
+            TlsStream::Server(stream) => stream.conn.send_close_notify(),
+            TlsStream::Client(stream) => stream.conn.send_close_notify(),
+        }
+        let _ = self.flush();
+    }
+}
+
+/// The cryptography both sides use.
+fn provider() -> Arc<CryptoProvider> {
+    Arc::new(ring::default_provider())
+}
+
+/// Reads every certificate in `pem`, of which there must be at least one.
+fn certificates(pem: &[u8]) -> Result<Vec<CertificateDer<'static>>, TlsError> {
+    let certificates = rustls_pemfile::certs(&mut &*pem).collect::<Result<Vec<_>, _>>()?;
+    if certificates.is_empty() {
+        return Err(TlsError::Missing("certificate"));
+    }
+    Ok(certificates)
+}
+
+/// Reads the first private key in `pem`.
+fn private_key(pem: &[u8]) -> Result<PrivateKeyDer<'static>, TlsError> {
+    rustls_pemfile::private_key(&mut &*pem)?.ok_or(TlsError::Missing("private key"))
+}
+
+/// Trusts every certificate in `pem` as an authority.
+fn root_store(pem: &[u8]) -> Result<RootCertStore, TlsError> {
+    let mut roots = RootCertStore::empty();
+    for certificate in certificates(pem)? {
+        roots.add(certificate)?;
+    }
+    Ok(roots)
+}
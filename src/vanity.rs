@@ -0,0 +1,77 @@
+//! Vanity searches: looking for a hash that starts with chosen characters.
+//!
+//! A vanity prefix is not a numeric target, so it is expressed as a predicate over the
+//! hash and run through [`parallel_mine_matching`](crate::parallel_mine_matching). Each
+//! hex digit in a prefix multiplies the expected work by 16, and each ASCII byte by 256.
+
+use std::fmt;
+
+use crate::hex::HexError;
+
+/// A required beginning for a hash.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum HashPrefix {
+    /// Lower-case hex digits the hex form of the hash must start with, e.g. `"0000beef"`.
+    /// An odd number of digits is allowed.
+    Hex(String),
+    /// Raw bytes the hash itself must start with, e.g. the ASCII bytes of `"CAFE"`.
+    Bytes(Vec<u8>),
+}
+
+impl HashPrefix {
+    /// Builds a prefix from hex digits (either case, optional `0x` prefix).
+    ///
+    /// # Returns
+    ///
+    /// The prefix, or [`HexError::InvalidDigit`] if a character is not a hex digit.
+    pub fn hex(prefix: &str) -> Result<Self, HexError> {
+        let (offset, digits) = match prefix.strip_prefix("0x").or_else(|| prefix.strip_prefix("0X"))
+        {
+            Some(rest) => (2, rest),
+            None => (0, prefix),
+        };
+        if let Some(at) = digits.bytes().position(|b| !b.is_ascii_hexdigit()) {
+            return Err(HexError::InvalidDigit(offset + at));
+        }
+        Ok(HashPrefix::Hex(digits.to_ascii_lowercase()))
+    }
+
+    /// Builds a prefix that the raw hash bytes must match, from an ASCII (or any UTF-8)
+    /// string.
+    pub fn ascii(prefix: &str) -> Self {
+        HashPrefix::Bytes(prefix.as_bytes().to_vec())
+    }
+
+    /// Whether `hash` starts with this prefix.
+    pub fn matches(&self, hash: &[u8]) -> bool {
+        match self {
+            HashPrefix::Hex(digits) => {
+                // Compare nibble by nibble so odd-length prefixes work.
+                digits.len() <= hash.len() * 2
+                    && digits.bytes().enumerate().all(|(i, digit)| {
+                        let byte = hash[i / 2];
+                        let nibble = if i % 2 == 0 { byte >> 4 } else { byte & 0x0f };
+                        char::from_digit(u32::from(nibble), 16) == Some(char::from(digit))
+                    })
+            }
+            HashPrefix::Bytes(bytes) => hash.starts_with(bytes),
+        }
+    }
+
+    /// The average number of hashes needed to find a match.
+    pub fn expected_attempts(&self) -> f64 {
+        match self {
+            HashPrefix::Hex(digits) => 16f64.powi(digits.len() as i32),
+            HashPrefix::Bytes(bytes) => 256f64.powi(bytes.len() as i32),
+        }
+    }
+}
+
+impl fmt::Display for HashPrefix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HashPrefix::Hex(digits) => write!(f, "{} (hex)", digits),
+            HashPrefix::Bytes(bytes) => write!(f, "{:?} (bytes)", String::from_utf8_lossy(bytes)),
+        }
+    }
+}
@@ -0,0 +1,225 @@
+//! Wesolowski verifiable delay functions over an RSA group.
+//!
+//! A VDF maps an input to an output that takes a fixed number of sequential steps to
+//! compute but can be checked quickly by anyone, without a trapdoor. The input is hashed
+//! to a group element `x` modulo an RSA modulus, and the output is
+//! `y = x^(2^squarings) mod modulus`: the same sequential work as a
+//! [`TimeLockPuzzle`](crate::TimeLockPuzzle).
+//!
+//! Unlike a time-lock puzzle, the evaluator also produces a proof. Following Wesolowski,
+//! a prime `l` is derived by hashing `x`, `y` and the parameters, and the proof is
+//! `pi = x^floor(2^squarings / l)`. With `r = 2^squarings mod l`, a verifier checks
+//! `pi^l * x^r = y`, which takes two short exponentiations however large `squarings` is.
+//!
+//! The modulus must come from a setup whose factors nobody kept: anyone who knows them
+//! can compute outputs without doing the work, just like the holder of a
+//! [`TimeLockTrapdoor`](crate::TimeLockTrapdoor). [`Vdf::setup`] discards the factors as
+//! soon as the modulus is built.
+
+use num_bigint::BigUint;
+use sha2::{Digest, Sha256};
+
+use crate::prime::{is_probable_prime, random_prime};
+use crate::TimeLockPuzzle;
+
+/// Domain separation for hashing the input to a group element.
+const INPUT_DOMAIN: &[u8] = b"vdf-input";
+
+/// Domain separation for hashing the transcript to the challenge prime.
+const CHALLENGE_DOMAIN: &[u8] = b"vdf-challenge";
+
+/// Extra bits hashed beyond the modulus size, so reducing modulo it is close to uniform.
+const INPUT_EXTRA_BITS: u64 = 128;
+
+/// A verifiable delay function taking `squarings` sequential squarings modulo `modulus`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Vdf {
+    /// The RSA modulus whose factors nobody knows.
+    pub modulus: BigUint,
+    /// How many sequential squarings an evaluation takes.
+    pub squarings: u64,
+}
+
+/// The result of evaluating a [`Vdf`], with the proof that it is correct.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct VdfProof {
+    /// `x^(2^squarings) mod modulus`, where `x` is the hashed input.
+    pub output: BigUint,
+    /// Wesolowski's proof `x^floor(2^squarings / l) mod modulus`.
+    pub proof: BigUint,
+}
+
+impl Vdf {
+    /// A VDF over an existing modulus.
+    pub fn new(modulus: BigUint, squarings: u64) -> Self {
+        Vdf { modulus, squarings }
+    }
+
+    /// A VDF over a fresh RSA modulus of `modulus_bits` bits. The factors are dropped
+    /// before this returns, but the caller still has to trust that this process did not
+    /// leak them.
+    ///
+    /// # Panics
+    ///
+    /// If `modulus_bits` is below 16.
+    pub fn setup(modulus_bits: u64, squarings: u64) -> Self {
+        assert!(modulus_bits >= 16, "the modulus needs at least 16 bits");
+        let mut rng = rand::thread_rng();
+        let modulus = loop {
+            let p = random_prime(modulus_bits / 2, &mut rng);
+            let q = random_prime(modulus_bits - modulus_bits / 2, &mut rng);
+            if p != q {
+                break p * q;
+            }
+        };
+        Vdf { modulus, squarings }
+    }
+
+    /// Hashes `data` to the group element the VDF is evaluated on.
+    ///
+    /// SHA-256 is run in counter mode until it has produced 128 bits more than the
+    /// modulus has, and the result is reduced modulo the modulus.
+    ///
+    /// # Panics
+    ///
+    /// If the modulus is zero.
+    pub fn input(&self, data: &[u8]) -> BigUint {
+        let len = (self.modulus.bits() + INPUT_EXTRA_BITS).div_ceil(8) as usize;
+        let mut bytes = Vec::with_capacity(len + 32);
+        let mut counter = 0u32;
+        while bytes.len() < len {
+            let block = Sha256::new()
+                .chain_update(INPUT_DOMAIN)
+                .chain_update(counter.to_be_bytes())
+                .chain_update(data)
+                .finalize();
+            bytes.extend_from_slice(&block);
+            counter += 1;
+        }
+        BigUint::from_bytes_be(&bytes[..len]) % &self.modulus
+    }
+
+    /// Evaluates the VDF on `data` and proves the result.
+    ///
+    /// # Details
+    ///
+    /// Both halves run on the calling thread and take `squarings` steps each: the output
+    /// by repeated squaring, then the proof by long division of `2^squarings` by the
+    /// challenge prime, one quotient bit per squaring.
+    ///
+    /// # Panics
+    ///
+    /// If the modulus is zero.
+    pub fn evaluate(&self, data: &[u8]) -> VdfProof {
+        let x = self.input(data);
+        let output = TimeLockPuzzle {
+            modulus: self.modulus.clone(),
+            base: x.clone(),
+            squarings: self.squarings,
+        }
+        .evaluate();
+        let l = self.challenge(&x, &output);
+
+        let mut proof = BigUint::from(1u32);
+        let mut remainder = BigUint::from(1u32);
+        for _ in 0..self.squarings {
+            remainder <<= 1;
+            proof = &proof * &proof % &self.modulus;
+            if remainder >= l {
+                remainder -= &l;
+                proof = proof * &x % &self.modulus;
+            }
+        }
+        VdfProof { output, proof }
+    }
+
+    /// Whether `proof` shows that its output is the VDF of `data`.
+    pub fn verify(&self, data: &[u8], proof: &VdfProof) -> bool {
+        if self.modulus < BigUint::from(2u32)
+            || proof.output >= self.modulus
+            || proof.proof >= self.modulus
+        {
+            return false;
+        }
+        let x = self.input(data);
+        let l = self.challenge(&x, &proof.output);
+        let r = BigUint::from(2u32).modpow(&BigUint::from(self.squarings), &l);
+        let expected = proof.proof.modpow(&l, &self.modulus) * x.modpow(&r, &self.modulus)
+            % &self.modulus;
+        expected == proof.output
+    }
+
+    /// The challenge prime `l`: the first probable prime among 256-bit odd numbers
+    /// hashed from the parameters, `x`, `y` and a counter.
+    fn challenge(&self, x: &BigUint, y: &BigUint) -> BigUint {
+        let mut rng = rand::thread_rng();
+        let prefix = Sha256::new()
+            .chain_update(CHALLENGE_DOMAIN)
+            .chain_update(self.squarings.to_be_bytes())
+            .chain_update(length_prefixed(&self.modulus))
+            .chain_update(length_prefixed(x))
+            .chain_update(length_prefixed(y));
+        (0u64..)
+            .map(|counter| {
+                let hash = prefix.clone().chain_update(counter.to_be_bytes()).finalize();
+                let mut candidate = BigUint::from_bytes_be(&hash);
+                candidate.set_bit(0, true);
+                candidate
+            })
+            .find(|candidate| is_probable_prime(candidate, &mut rng))
+            .expect("primes are unbounded")
+    }
+}
+
+/// `n` as big-endian bytes behind its length, so concatenated numbers stay unambiguous.
+fn length_prefixed(n: &BigUint) -> Vec<u8> {
+    let bytes = n.to_bytes_be();
+    let mut out = (bytes.len() as u64).to_be_bytes().to_vec();
+    out.extend_from_slice(&bytes);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn smallest_vdf_round_trips() {
+        for squarings in [0, 1, 40] {
+            let vdf = Vdf::setup(16, squarings);
+            assert_eq!(vdf.modulus.bits(), 16);
+            let proof = vdf.evaluate(b"round trip");
+            assert!(vdf.verify(b"round trip", &proof), "with {} squarings", squarings);
+
+            let base = vdf.input(b"round trip");
+            let puzzle = TimeLockPuzzle { modulus: vdf.modulus.clone(), base, squarings };
+            assert_eq!(proof.output, puzzle.evaluate());
+        }
+    }
+
+    #[test]
+    fn tampered_proofs_are_rejected() {
+        // With the factors below 2^8, the 256-bit challenge prime is coprime to both
+        // `p - 1` and `q - 1`, so raising to it is one to one and no other proof passes.
+        let vdf = Vdf::setup(16, 40);
+        let proof = vdf.evaluate(b"tampered");
+        let forged = VdfProof { proof: (&proof.proof + 1u32) % &vdf.modulus, ..proof.clone() };
+        assert!(!vdf.verify(b"tampered", &forged));
+        let unreduced = VdfProof { proof: &proof.proof + &vdf.modulus, ..proof.clone() };
+        assert!(!vdf.verify(b"tampered", &unreduced));
+
+        // A different output or input changes the challenge, so a large modulus keeps
+        // an accidental match out of reach.
+        let vdf = Vdf::setup(128, 40);
+        let proof = vdf.evaluate(b"tampered");
+        let forged = VdfProof { output: (&proof.output + 1u32) % &vdf.modulus, ..proof.clone() };
+        assert!(!vdf.verify(b"tampered", &forged));
+        assert!(!vdf.verify(b"another input", &proof));
+    }
+
+    #[test]
+    #[should_panic(expected = "at least 16 bits")]
+    fn too_small_a_modulus_is_refused() {
+        Vdf::setup(15, 1);
+    }
+}
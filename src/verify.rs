@@ -0,0 +1,41 @@
+//! Checking solutions that were produced elsewhere.
+//!
+//! [`validate`](crate::validate) only answers yes or no. When a nonce comes from another
+//! machine or another implementation it is usually just as important to see the hash it
+//! produces, so the functions here return a [`Verification`] with both.
+
+use crate::digest::Digest;
+use crate::{hash_with, Algorithm, Difficulty, Puzzle};
+
+/// The result of re-hashing a candidate solution.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Verification {
+    /// Whether the hash is at or below the puzzle's target.
+    pub valid: bool,
+    /// The hash the nonce produced.
+    pub hash: Vec<u8>,
+}
+
+/// Checks whether `nonce` solves the puzzle described by `data`, `algorithm` and
+/// `difficulty` with the default nonce format.
+///
+/// # Returns
+///
+/// Whether the solution is valid, together with the hash it produces.
+pub fn verify(
+    data: impl Into<Vec<u8>>,
+    nonce: u64,
+    algorithm: Algorithm,
+    difficulty: impl Into<Difficulty>,
+) -> Verification {
+    algorithm.verify(&Puzzle::new(data, difficulty), nonce)
+}
+
+/// Checks whether `nonce` solves `puzzle` with the hash function `D`.
+///
+/// Unlike [`verify`], this honours the puzzle's nonce format, extra nonce and issue and
+/// expiry times.
+pub fn verify_with<D: Digest>(puzzle: &Puzzle, nonce: u64) -> Verification {
+    let hash = hash_with::<D>(puzzle, nonce);
+    Verification { valid: puzzle.is_live() && puzzle.target.is_met_by(&hash), hash: hash.to_vec() }
+}
@@ -0,0 +1,476 @@
+//! The worker side of the [`server`](crate::server) protocol.
+//!
+//! A [`WorkClient`] registers with a [`WorkServer`](crate::WorkServer) and then, as often
+//! as its owner asks, leases a range of nonces, mines it on this machine's threads with
+//! the usual engine, and reports back the solution or that the range holds none:
+//!
+//! ```text
+//! let client = WorkClient::connect("http://coordinator:8080")?;
+//! while let Some(lease) = client.lease()? {
+//!     client.mine(&lease, num_cpus::get())?;
+//! }
+//! ```
+//!
+//! A lease mined with [`mine`](WorkClient::mine) is mined to the end of its range, or
+//! until a solution turns up, even if another worker solves the puzzle in the meantime;
+//! the server simply stops leasing a solved puzzle. To stop sooner, subscribe to the
+//! server's [`events`](WorkClient::events), which it pushes over a WebSocket the moment a
+//! puzzle is added, solved or exhausted, and mine with
+//! [`mine_cancellable`](WorkClient::mine_cancellable), cancelling when the lease's puzzle
+//! is finished. The events also tell an idle worker when to ask for work again. If the
+//! server counts shares, `mine_cancellable` sends each one it finds with
+//! [`share`](WorkClient::share) as it goes. With the `tls` feature, `https://` servers are
+//! supported too; see `WorkClient::connect_tls`.
+
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+use std::time::Duration;
+
+use tungstenite::handshake::HandshakeError;
+use tungstenite::{Message, WebSocket};
+
+use crate::json::{Request, Value};
+#[cfg(feature = "tls")]
+use crate::ClientTls;
+use crate::{
+    hex, Algorithm, CancellationToken, NonceFormat, Puzzle, PuzzleError, Solution, Target,
+};
+
+/// How long the client waits for the server to answer a request.
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The reasons a request to the work server failed.
+#[derive(Debug)]
+pub enum WorkError {
+    /// The server could not be reached, or the connection failed.
+    Io(io::Error),
+    /// The server refused the request with this HTTP status and message.
+    Refused(u16, String),
+    /// The server's address or answer could not be understood.
+    Malformed(String),
+    /// Mining a lease failed for a reason other than exhausting it.
+    Mining(PuzzleError),
+}
+
+impl fmt::Display for WorkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WorkError::Io(e) => write!(f, "cannot reach the work server: {}", e),
+            WorkError::Refused(status, message) => {
+                write!(f, "the work server refused the request ({}): {}", status, message)
+            }
+            WorkError::Malformed(message) => write!(f, "malformed exchange: {}", message),
+            WorkError::Mining(e) => write!(f, "mining the lease failed: {}", e),
+        }
+    }
+}
+
+impl Error for WorkError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            WorkError::Io(e) => Some(e),
+            WorkError::Mining(e) => Some(e),
+            WorkError::Refused(..) | WorkError::Malformed(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for WorkError {
+    fn from(e: io::Error) -> Self {
+        WorkError::Io(e)
+    }
+}
+
+impl From<String> for WorkError {
+    /// An answer that could not be read.
+    fn from(message: String) -> Self {
+        WorkError::Malformed(message)
+    }
+}
+
+/// A range of a puzzle's window leased from the server.
+#[derive(Clone, Debug)]
+pub struct WorkLease {
+    /// The lease's id on the server.
+    pub id: u64,
+    /// The puzzle's id on the server.
+    pub puzzle_id: u64,
+    /// The puzzle, with the extra nonce of the lease, if any, in effect.
+    pub puzzle: Puzzle,
+    /// The hash the puzzle is mined with.
+    pub algorithm: Algorithm,
+    /// The leased nonces.
+    pub nonces: RangeInclusive<u64>,
+    /// The target of the shares the server counts, if it counts any: hashes at or below
+    /// it, sent as they are found, prove the effort put into the lease.
+    pub share_target: Option<Target>,
+}
+
+/// Something the server pushed to the workers subscribed to its events.
+#[derive(Clone, Debug)]
+pub enum WorkEvent {
+    /// A puzzle was added, so there may be ranges to lease again.
+    Added { puzzle_id: u64 },
+    /// A puzzle was solved, so its leases are no longer worth mining.
+    Solved { puzzle_id: u64, solution: Solution },
+    /// A puzzle's whole window was searched without a solution.
+    Exhausted { puzzle_id: u64 },
+}
+
+impl WorkEvent {
+    /// The id of the puzzle the event is about.
+    pub fn puzzle_id(&self) -> u64 {
+        match *self {
+            WorkEvent::Added { puzzle_id }
+            | WorkEvent::Solved { puzzle_id, .. }
+            | WorkEvent::Exhausted { puzzle_id } => puzzle_id,
+        }
+    }
+}
+
+/// The events a work server pushes over a WebSocket; see [`WorkClient::events`].
+#[derive(Debug)]
+pub struct WorkEvents {
+    socket: WebSocket<Box<dyn Connection>>,
+}
+
+/// A connection to the server: plain TCP, or TLS over it.
+pub(crate) trait Connection: Read + Write + Send + fmt::Debug {}
+
+impl<T: Read + Write + Send + fmt::Debug> Connection for T {}
+
+impl WorkEvents {
+    /// Blocks until the server pushes the next event.
+    ///
+    /// # Returns
+    ///
+    /// The event, `None` once the server has closed the channel, as it does when it
+    /// shuts down, or why reading failed.
+    pub fn recv(&mut self) -> Result<Option<WorkEvent>, WorkError> {
+        loop {
+            let text = match self.socket.read() {
+                Ok(Message::Text(text)) => text,
+                Ok(Message::Close(_)) => {
+                    let _ = self.socket.flush();
+                    return Ok(None);
+                }
+                // Pings are answered while reading.
+                Ok(_) => continue,
+                Err(tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed) => {
+                    return Ok(None);
+                }
+                Err(tungstenite::Error::Io(e)) => return Err(e.into()),
+                Err(e) => return Err(WorkError::Malformed(e.to_string())),
+            };
+            let fields = Request::parse(&text)?;
+            let puzzle_id = fields.required("puzzle")?;
+            let event = match (fields.string("event")?, fields.string("state")?) {
+                (Some("puzzle"), _) => WorkEvent::Added { puzzle_id },
+                (Some("finished"), Some("solved")) => {
+                    let hash = fields.string("hash")?.ok_or("missing `hash`".to_string())?;
+                    let solution = Solution {
+                        nonce: fields.required("nonce")?,
+                        hash: hex::decode(hash).map_err(|e| e.to_string())?,
+                        extra_nonce: fields.number("extra_nonce")?,
+                    };
+                    WorkEvent::Solved { puzzle_id, solution }
+                }
+                (Some("finished"), _) => WorkEvent::Exhausted { puzzle_id },
+                // Events of later versions of the server are skipped.
+                _ => continue,
+            };
+            return Ok(Some(event));
+        }
+    }
+}
+
+/// A worker registered with a work server; see the [module documentation](self).
+#[derive(Debug)]
+pub struct WorkClient {
+    /// The server's `host:port`.
+    addr: String,
+    worker: u64,
+    /// How to speak TLS to the server, and its name in its certificate, for an
+    /// `https://` server.
+    #[cfg(feature = "tls")]
+    tls: Option<(ClientTls, String)>,
+}
+
+impl WorkClient {
+    /// Registers as a new worker with the server at `url`, written as
+    /// `http://host:port` (the port defaults to 80) or, with the `tls` feature,
+    /// `https://host:port` (443), for which the server's certificate must be signed by
+    /// one of the usual public authorities.
+    ///
+    /// # Returns
+    ///
+    /// The client, or why the server could not be reached or would not register it.
+    pub fn connect(url: &str) -> Result<Self, WorkError> {
+        if url.starts_with("https://") {
+            #[cfg(feature = "tls")]
+            {
+                let tls = ClientTls::new(None, None).map_err(|e| e.to_string())?;
+                return WorkClient::connect_tls(url, tls);
+            }
+            #[cfg(not(feature = "tls"))]
+            return Err(WorkError::Malformed("this build has no TLS (feature `tls`)".to_string()));
+        }
+        let (_, addr) = server_address(url, "http://", 80)?;
+        #[cfg(feature = "tls")]
+        let client = WorkClient { addr, worker: 0, tls: None };
+        #[cfg(not(feature = "tls"))]
+        let client = WorkClient { addr, worker: 0 };
+        client.register()
+    }
+
+    /// Registers as a new worker with the server at `url`, written as
+    /// `https://host:port` (the port defaults to 443), speaking TLS as `tls` says: with
+    /// the authorities it trusts to have signed the server's certificate, and the
+    /// certificate it presents if the server demands one.
+    ///
+    /// # Returns
+    ///
+    /// The client, or why the server could not be reached or would not register it.
+    #[cfg(feature = "tls")]
+    pub fn connect_tls(url: &str, tls: ClientTls) -> Result<Self, WorkError> {
+        let (host, addr) = server_address(url, "https://", 443)?;
+        WorkClient { addr, worker: 0, tls: Some((tls, host)) }.register()
+    }
+
+    /// Registers with the server, taking the id it gives.
+    fn register(mut self) -> Result<Self, WorkError> {
+        self.worker = self.post("/workers", "{}")?.required("worker")?;
+        Ok(self)
+    }
+
+    /// The id the server gave this worker.
+    pub fn worker_id(&self) -> u64 {
+        self.worker
+    }
+
+    /// Leases the next range from the server.
+    ///
+    /// # Returns
+    ///
+    /// The lease, `None` if the server has nothing to lease right now, or why the
+    /// request failed.
+    pub fn lease(&self) -> Result<Option<WorkLease>, WorkError> {
+        let response = self.post("/leases", &format!("{{\"worker\":{}}}", self.worker))?;
+        let Some(id) = response.number("lease")? else {
+            return Ok(None);
+        };
+        let field = |key: &str| -> Result<&str, WorkError> {
+            Ok(response.string(key)?.ok_or_else(|| format!("missing `{}`", key))?)
+        };
+        let data = hex::decode(field("data_hex")?).map_err(|e| e.to_string())?;
+        let target: Target = field("target")?.parse()?;
+        let nonce_format = NonceFormat {
+            encoding: parse(field("nonce_encoding")?)?,
+            offset: response.number("nonce_offset")?,
+        };
+        let mut puzzle = Puzzle::new(data, target).with_nonce_format(nonce_format);
+        puzzle.extra_nonce = response.number("extra_nonce")?;
+        Ok(Some(WorkLease {
+            id,
+            puzzle_id: response.required("puzzle")?,
+            puzzle,
+            algorithm: parse(field("algorithm")?)?,
+            nonces: response.required("start")?..=response.required("end")?,
+            share_target: response.string("share_target")?.map(str::parse).transpose()?,
+        }))
+    }
+
+    /// Subscribes to the events the server pushes: puzzles added, solved and exhausted.
+    ///
+    /// # Returns
+    ///
+    /// The events, or why the server could not be reached or would not push them.
+    pub fn events(&self) -> Result<WorkEvents, WorkError> {
+        let stream = self.open(None)?;
+        #[cfg(feature = "tls")]
+        let scheme = if self.tls.is_some() { "wss" } else { "ws" };
+        #[cfg(not(feature = "tls"))]
+        let scheme = "ws";
+        let url = format!("{}://{}/events", scheme, self.addr);
+        match tungstenite::client(url.as_str(), stream) {
+            Ok((socket, _)) => Ok(WorkEvents { socket }),
+            Err(HandshakeError::Failure(tungstenite::Error::Io(e))) => Err(e.into()),
+            Err(HandshakeError::Failure(tungstenite::Error::Http(response))) => {
+                let status = response.status();
+                let reason = status.canonical_reason().unwrap_or("no reason given");
+                Err(WorkError::Refused(status.as_u16(), reason.to_string()))
+            }
+            Err(e) => Err(WorkError::Malformed(e.to_string())),
+        }
+    }
+
+    /// Mines `lease` on `num_threads` threads and reports the outcome to the server, along
+    /// with every share found on the way if the server counts shares.
+    ///
+    /// # Returns
+    ///
+    /// The solution if the range held one, `None` if it did not, or why mining or
+    /// reporting failed.
+    pub fn mine(
+        &self,
+        lease: &WorkLease,
+        num_threads: usize,
+    ) -> Result<Option<Solution>, WorkError> {
+        self.mine_cancellable(lease, num_threads, &CancellationToken::new())
+    }
+
+    /// Mines `lease` like [`mine`](WorkClient::mine), but gives up as soon as `token` is
+    /// cancelled, such as when the server pushes that the puzzle was solved elsewhere.
+    ///
+    /// # Returns
+    ///
+    /// As for [`mine`](WorkClient::mine), or [`WorkError::Mining`] with
+    /// [`PuzzleError::Cancelled`] if the token was cancelled first. Nothing is reported
+    /// then, and the lease stays open on the server.
+    pub fn mine_cancellable(
+        &self,
+        lease: &WorkLease,
+        num_threads: usize,
+        token: &CancellationToken,
+    ) -> Result<Option<Solution>, WorkError> {
+        // The extra nonce is baked into the data, so exhausting the range does not roll it
+        // on to another lease's. Mining for shares stops at each one, to send it.
+        let puzzle = Puzzle {
+            data: lease.puzzle.message_data().into_owned(),
+            target: lease.share_target.unwrap_or(lease.puzzle.target),
+            extra_nonce: None,
+            ..lease.puzzle.clone()
+        };
+        let mut start = *lease.nonces.start();
+        loop {
+            let nonces = start..=*lease.nonces.end();
+            match lease.algorithm.parallel_mine_cancellable(&puzzle, nonces, num_threads, token) {
+                Ok(solution) if lease.puzzle.target.is_met_by(&solution.hash) => {
+                    let solution = Solution { extra_nonce: lease.puzzle.extra_nonce, ..solution };
+                    self.submit(lease, solution.nonce)?;
+                    return Ok(Some(solution));
+                }
+                Ok(share) => {
+                    self.share(lease, share.nonce)?;
+                    match share.nonce.checked_add(1).filter(|next| lease.nonces.contains(next)) {
+                        Some(next) => start = next,
+                        None => break,
+                    }
+                }
+                Err(PuzzleError::Exhausted | PuzzleError::InvalidDifficulty) => break,
+                Err(e) => return Err(WorkError::Mining(e)),
+            }
+        }
+        self.exhausted(lease)?;
+        Ok(None)
+    }
+
+    /// Submits `nonce` as the solution found in `lease`.
+    pub fn submit(&self, lease: &WorkLease, nonce: u64) -> Result<(), WorkError> {
+        let body =
+            format!("{{\"worker\":{},\"lease\":{},\"nonce\":{}}}", self.worker, lease.id, nonce);
+        self.post("/solutions", &body).map(drop)
+    }
+
+    /// Sends `nonce`, found in `lease`, as a share.
+    ///
+    /// # Returns
+    ///
+    /// Whether the nonce also solves the puzzle, or why the request failed.
+    pub fn share(&self, lease: &WorkLease, nonce: u64) -> Result<bool, WorkError> {
+        let body =
+            format!("{{\"worker\":{},\"lease\":{},\"nonce\":{}}}", self.worker, lease.id, nonce);
+        let response = self.post("/shares", &body)?;
+        Ok(response.value("solves").and_then(Value::as_bool).unwrap_or(false))
+    }
+
+    /// Reports that `lease` holds no solution.
+    pub fn exhausted(&self, lease: &WorkLease) -> Result<(), WorkError> {
+        let body = format!("{{\"worker\":{},\"lease\":{}}}", self.worker, lease.id);
+        self.post("/exhausted", &body).map(drop)
+    }
+
+    /// Opens a connection to the server, over TLS for an `https://` server, whose reads
+    /// give up after `timeout`, if given.
+    fn open(&self, timeout: Option<Duration>) -> Result<Box<dyn Connection>, WorkError> {
+        let stream = TcpStream::connect(&self.addr)?;
+        stream.set_read_timeout(timeout)?;
+        #[cfg(feature = "tls")]
+        if let Some((tls, host)) = &self.tls {
+            return Ok(Box::new(tls.connect(host, stream)?));
+        }
+        Ok(Box::new(stream))
+    }
+
+    /// Sends `body` to `path` and reads the answer.
+    ///
+    /// # Returns
+    ///
+    /// The fields of a successful answer, or why the request failed.
+    fn post(&self, path: &str, body: &str) -> Result<Request, WorkError> {
+        let mut stream = self.open(Some(RESPONSE_TIMEOUT))?;
+        write!(
+            stream,
+            "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\n\
+             Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+            path,
+            self.addr,
+            body.len(),
+            body
+        )?;
+        let mut answer = String::new();
+        stream.read_to_string(&mut answer)?;
+
+        let (head, body) = answer
+            .split_once("\r\n\r\n")
+            .ok_or_else(|| WorkError::Malformed("the answer has no body".to_string()))?;
+        let status: u16 = head
+            .split_whitespace()
+            .nth(1)
+            .and_then(|status| status.parse().ok())
+            .ok_or_else(|| WorkError::Malformed("the answer has no status".to_string()))?;
+        // Answers are flat objects, like requests.
+        let fields = Request::parse(body.trim())?;
+        if status != 200 {
+            let message = fields.string("error")?.unwrap_or("no reason given").to_string();
+            return Err(WorkError::Refused(status, message));
+        }
+        Ok(fields)
+    }
+}
+
+/// Splits the server address off `url`, which must start with `scheme`.
+///
+/// # Returns
+///
+/// The server's host and its `host:port`, with the port `default_port` if `url` has
+/// none.
+fn server_address(
+    url: &str,
+    scheme: &str,
+    default_port: u16,
+) -> Result<(String, String), WorkError> {
+    let addr = url
+        .strip_prefix(scheme)
+        .ok_or_else(|| WorkError::Malformed(format!("`{}` is not an {} URL", url, scheme)))?
+        .trim_end_matches('/');
+    if addr.is_empty() || addr.contains('/') {
+        return Err(WorkError::Malformed(format!("`{}` is not a server address", url)));
+    }
+    match addr.rsplit_once(':').filter(|(_, port)| !port.contains(']')) {
+        Some((host, _)) => Ok((host.to_string(), addr.to_string())),
+        None => Ok((addr.to_string(), format!("{}:{}", addr, default_port))),
+    }
+}
+
+/// Parses a field of the answer written with the `Display` form of `T`.
+fn parse<T: FromStr>(s: &str) -> Result<T, WorkError>
+where
+    T::Err: fmt::Display,
+{
+    s.parse().map_err(|e: T::Err| WorkError::Malformed(e.to_string()))
+}
@@ -0,0 +1,172 @@
+//! The parallel search end to end, run with the cheap, deterministic `MockHash` so the
+//! exact solution of every puzzle is known in advance.
+//!
+//! ```text
+//! cargo test --features mock-hash --test mock_hash
+//! ```
+//!
+//! - The threads, handing the window out in chunks, find the lowest solution.
+//! - Cancelling or running out of time stops a search that cannot succeed.
+//! - A checkpoint survives a trip through a file, and resuming it finds the solution.
+//! - A batch, and the scheduler, solve every puzzle they are given.
+
+use std::ops::RangeInclusive;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use parallell_puzzle_generator_and_solver::{
+    parallel_mine_batch_with, parallel_mine_cancellable_with, parallel_mine_range,
+    parallel_mine_resume_with, parallel_mine_spawn_with, parallel_mine_timeout_with,
+    validate_with, CancellationToken, Checkpoint, CheckpointError, Difficulty, MockHash,
+    Puzzle, PuzzleError, Scheduler,
+};
+
+/// Enough leading zero bits that a solution is some 260 000 nonces in, past several of
+/// the 65 536-nonce chunks the workers take at a time.
+const SOLVABLE: Difficulty = Difficulty::LeadingZeroBits(18);
+
+/// So many leading zero bits that no search in a test comes near a solution.
+const UNSOLVABLE: Difficulty = Difficulty::LeadingZeroBits(200);
+
+/// The lowest nonce in `nonces` that solves `puzzle`, found by trying each in turn.
+fn lowest_solution(puzzle: &Puzzle, nonces: RangeInclusive<u64>) -> Option<u64> {
+    nonces.into_iter().find(|&nonce| validate_with::<MockHash>(puzzle, nonce))
+}
+
+/// A checkpoint file of its own in the temporary directory.
+fn checkpoint_path(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("mock-hash-{}-{}.checkpoint", name, std::process::id()))
+}
+
+#[test]
+fn chunked_search_finds_the_lowest_solution() {
+    let puzzle = Puzzle::new("lowest", SOLVABLE);
+    let lowest = lowest_solution(&puzzle, 0..=u64::from(u32::MAX)).expect("a solution");
+    assert!(lowest > 1 << 16, "the solution should lie past the first chunk");
+
+    // One thread takes the chunks in order, so it reaches the lowest solution first.
+    let solution = parallel_mine_range::<MockHash>(&puzzle, 0..=u64::MAX, 1).unwrap();
+    assert_eq!(solution.nonce, lowest);
+
+    // Ending the window there leaves the threads only the one solution to find.
+    for threads in [2, 4, 7] {
+        let solution = parallel_mine_range::<MockHash>(&puzzle, 0..=lowest, threads).unwrap();
+        assert_eq!(solution.nonce, lowest, "with {} threads", threads);
+        assert!(validate_with::<MockHash>(&puzzle, solution.nonce));
+    }
+
+    // Without it, the window is searched exactly once.
+    let result = parallel_mine_range::<MockHash>(&puzzle, 0..=lowest - 1, 4);
+    assert!(matches!(result, Err(PuzzleError::Exhausted)), "{:?}", result);
+}
+
+#[test]
+fn empty_windows_and_zero_targets_are_refused() {
+    let puzzle = Puzzle::new("refused", SOLVABLE);
+    let result = parallel_mine_range::<MockHash>(&puzzle, RangeInclusive::new(10, 9), 4);
+    assert!(matches!(result, Err(PuzzleError::EmptyRange)), "{:?}", result);
+
+    let puzzle = Puzzle::new("refused", Difficulty::Threshold(0));
+    let result = parallel_mine_range::<MockHash>(&puzzle, 0..=u64::MAX, 4);
+    assert!(matches!(result, Err(PuzzleError::InvalidDifficulty)), "{:?}", result);
+}
+
+#[test]
+fn cancelling_stops_the_search() {
+    let puzzle = Puzzle::new("cancel", UNSOLVABLE);
+    let token = CancellationToken::new();
+    let canceller = {
+        let token = token.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            token.cancel();
+        })
+    };
+    let result = parallel_mine_cancellable_with::<MockHash>(&puzzle, 0..=u64::MAX, 4, &token);
+    canceller.join().unwrap();
+    assert!(matches!(result, Err(PuzzleError::Cancelled)), "{:?}", result);
+
+    // A token cancelled beforehand stops the search before it starts.
+    let result = parallel_mine_cancellable_with::<MockHash>(&puzzle, 0..=u64::MAX, 4, &token);
+    assert!(matches!(result, Err(PuzzleError::Cancelled)), "{:?}", result);
+}
+
+#[test]
+fn running_out_of_time_stops_the_search() {
+    let puzzle = Puzzle::new("deadline", UNSOLVABLE);
+    let started = Instant::now();
+    let result = parallel_mine_timeout_with::<MockHash>(
+        &puzzle,
+        0..=u64::MAX,
+        4,
+        Duration::from_millis(100),
+    );
+    match result {
+        Err(PuzzleError::TimedOut { attempts }) => assert!(attempts > 0),
+        other => panic!("expected a timeout, got {:?}", other),
+    }
+    assert!(started.elapsed() < Duration::from_secs(5), "took {:?}", started.elapsed());
+}
+
+#[test]
+fn checkpoint_round_trips_and_resumes() {
+    let puzzle = Puzzle::new("resume", SOLVABLE);
+    let lowest = lowest_solution(&puzzle, 0..=u64::from(u32::MAX)).expect("a solution");
+
+    let handle = parallel_mine_spawn_with::<MockHash>(&puzzle, 0..=u64::MAX, 1);
+    handle.cancel();
+    let checkpoint = handle.checkpoint();
+    let result = handle.join();
+    assert!(matches!(result, Err(PuzzleError::Cancelled)), "{:?}", result);
+    assert!(checkpoint.matches::<MockHash>(&puzzle));
+    assert!(!checkpoint.matches::<MockHash<1>>(&puzzle));
+    assert!(checkpoint.ranges.iter().any(|range| range.contains(&lowest)));
+
+    let path = checkpoint_path("resume");
+    checkpoint.save(&path).unwrap();
+    let loaded = Checkpoint::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(loaded, checkpoint);
+
+    let solution = parallel_mine_resume_with::<MockHash>(&puzzle, &loaded).unwrap().join();
+    assert_eq!(solution.unwrap().nonce, lowest);
+
+    let other = Puzzle::new("another puzzle", SOLVABLE);
+    let result = parallel_mine_resume_with::<MockHash>(&other, &loaded);
+    assert!(matches!(result, Err(CheckpointError::Mismatch)));
+}
+
+#[test]
+fn batch_solves_every_puzzle() {
+    let puzzles: Vec<_> = (0..5).map(|i| Puzzle::new(format!("batch {}", i), SOLVABLE)).collect();
+    let results = parallel_mine_batch_with::<MockHash>(&puzzles, 3);
+    assert_eq!(results.len(), puzzles.len());
+    for (puzzle, outcome) in puzzles.iter().zip(&results) {
+        let solution = outcome.result.as_ref().unwrap();
+        assert!(validate_with::<MockHash>(puzzle, solution.nonce));
+        assert!(outcome.hashes > 0);
+    }
+    assert!(parallel_mine_batch_with::<MockHash>(&[], 3).is_empty());
+}
+
+#[test]
+fn scheduler_solves_puzzles_and_enforces_deadlines() {
+    let scheduler = Scheduler::<MockHash>::with_hasher(3);
+    let hopeless = Puzzle::new("scheduled", UNSOLVABLE);
+    let deadline = Instant::now() + Duration::from_millis(100);
+    let late = scheduler.submit(&hopeless, 0, Some(deadline)).unwrap();
+    let jobs: Vec<_> = (0..3)
+        .map(|i| {
+            let puzzle = Puzzle::new(format!("scheduled {}", i), SOLVABLE);
+            let job = scheduler.submit(&puzzle, i, None).unwrap();
+            (puzzle, job)
+        })
+        .collect();
+
+    for (puzzle, job) in jobs {
+        let solution = job.wait().result.unwrap();
+        assert!(validate_with::<MockHash>(&puzzle, solution.nonce));
+    }
+    let result = late.wait().result;
+    assert!(matches!(result, Err(PuzzleError::DeadlineExceeded)), "{:?}", result);
+}